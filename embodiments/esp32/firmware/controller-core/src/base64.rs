@@ -0,0 +1,44 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Standard padded base64 (RFC 4648), encoding only
+
+use heapless::Vec;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Append `input` to `out` as standard padded base64; false if it didn't fit
+/// (N must leave room for 4 * ceil(len / 3) more bytes)
+pub fn encode<const N: usize>(input: &[u8], out: &mut Vec<u8, N>) -> bool {
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            let c = if i <= chunk.len() { ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize] } else { b'=' };
+            if out.push(c).is_err() {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_4648_vectors() {
+        for (input, expected) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")] {
+            let mut out: Vec<u8, 8> = Vec::new();
+            assert!(encode(input.as_bytes(), &mut out));
+            assert_eq!(&out[..], expected.as_bytes());
+        }
+        let mut short: Vec<u8, 3> = Vec::new();
+        assert!(!encode(b"f", &mut short));
+    }
+}
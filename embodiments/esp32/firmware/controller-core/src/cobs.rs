@@ -0,0 +1,198 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! COBS framing with a CRC16 for the serial link (config.json
+//! `serial.framing: "cobs"`)
+//!
+//! Every message (a JSON line with its newline, or a burst of binary
+//! packets) travels as
+//!
+//! `0x00, COBS(message || CRC16), 0x00`
+//!
+//! COBS leaves no zero byte inside the frame, so a receiver that lost bytes
+//! resynchronizes at the next delimiter. The CRC is CRC-16/CCITT-FALSE
+//! (poly 0x1021, init 0xFFFF), big-endian. A frame that doesn't decode or
+//! whose CRC doesn't match is dropped whole instead of being half-parsed;
+//! empty frames (back-to-back delimiters) are skipped. The leading delimiter
+//! cuts off console output printed on the same UART before the frame.
+
+use heapless::Vec;
+
+/// Longest run of non-zero bytes one COBS block holds
+const BLOCK: usize = 254;
+
+/// CRC-16/CCITT-FALSE of `data`
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Frame `message`, handing the encoded bytes to `write` a block at a time
+///
+/// Returns false as soon as `write` does.
+pub fn encode(message: &[u8], mut write: impl FnMut(&[u8]) -> bool) -> bool {
+    if !write(&[0]) {
+        return false;
+    }
+    let crc = crc16(message).to_be_bytes();
+    // block[0] is the COBS code: the offset of the next zero
+    let mut block = [0u8; BLOCK + 1];
+    let mut len = 1;
+    for &byte in message.iter().chain(crc.iter()) {
+        if byte != 0 {
+            block[len] = byte;
+            len += 1;
+            if len < block.len() {
+                continue;
+            }
+        }
+        block[0] = len as u8;
+        if !write(&block[..len]) {
+            return false;
+        }
+        len = 1;
+    }
+    block[0] = len as u8;
+    write(&block[..len]) && write(&[0])
+}
+
+/// Reassembles frames from received bytes
+pub struct Decoder<const N: usize> {
+    /// Encoded bytes since the last delimiter
+    frame: Vec<u8, N>,
+    /// The frame outgrew the buffer; dropped at the next delimiter
+    overflow: bool,
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Add one received byte; true at a delimiter closing a verified frame,
+    /// whose message is then in `out` (cleared at a bad one)
+    pub fn push<const M: usize>(&mut self, byte: u8, out: &mut Vec<u8, M>) -> bool {
+        if byte != 0 {
+            self.overflow |= self.frame.push(byte).is_err();
+            return false;
+        }
+        let ok = !self.overflow && decode(&self.frame, out);
+        if !ok {
+            out.clear();
+        }
+        self.frame.clear();
+        self.overflow = false;
+        ok
+    }
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decode one frame (without delimiters) and check its CRC
+fn decode<const M: usize>(frame: &[u8], out: &mut Vec<u8, M>) -> bool {
+    out.clear();
+    let mut at = 0;
+    while at < frame.len() {
+        let code = frame[at] as usize;
+        let end = at + code;
+        if code == 0 || end > frame.len() || out.extend_from_slice(&frame[at + 1..end]).is_err() {
+            return false;
+        }
+        // Every block but a full one is followed by a zero, except at the end
+        if code <= BLOCK && end < frame.len() && out.push(0).is_err() {
+            return false;
+        }
+        at = end;
+    }
+    let Some(len) = out.len().checked_sub(2) else {
+        return false;
+    };
+    let crc = u16::from_be_bytes([out[len], out[len + 1]]);
+    out.truncate(len);
+    crc16(out) == crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(message: &[u8]) -> Vec<u8, 1024> {
+        let mut out = Vec::new();
+        assert!(encode(message, |bytes| out.extend_from_slice(bytes).is_ok()));
+        out
+    }
+
+    /// Every message `bytes` closes
+    fn decoded(bytes: &[u8]) -> Vec<Vec<u8, 1024>, 4> {
+        let mut decoder: Decoder<1100> = Decoder::new();
+        let mut out = Vec::new();
+        let mut messages = Vec::new();
+        for &byte in bytes {
+            if decoder.push(byte, &mut out) {
+                messages.push(out.clone()).unwrap();
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn known_frame() {
+        let crc = crc16(&[0x11, 0x00, 0x22]).to_be_bytes();
+        assert_eq!(&framed(&[0x11, 0x00, 0x22])[..], [0x00, 0x02, 0x11, 0x04, 0x22, crc[0], crc[1], 0x00]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let long: Vec<u8, 600> = (0..600).map(|i| (i % 255 + 1) as u8).collect();
+        let zeros = [0u8; 300];
+        for message in [&b"{\"b\":1}\n"[..], &long, &zeros, &long[..254], &long[..253]] {
+            let bytes = framed(message);
+            assert!(bytes[1..bytes.len() - 1].iter().all(|&b| b != 0));
+            assert_eq!(&decoded(&bytes)[..], [message]);
+        }
+    }
+
+    #[test]
+    fn damaged_frames_dropped() {
+        let mut bytes = framed(b"{\"b\":1}\n");
+        bytes[4] ^= 0x01;
+        assert!(decoded(&bytes).is_empty());
+        // Console output before the frame, and empty frames
+        let mut bytes: Vec<u8, 64> = Vec::from_slice(b"boot log\r\n\0\0").unwrap();
+        bytes.extend_from_slice(&framed(b"ok")).unwrap();
+        assert_eq!(&decoded(&bytes)[..], [b"ok"]);
+    }
+
+    #[test]
+    fn oversized_frame_dropped() {
+        let mut decoder: Decoder<8> = Decoder::new();
+        let mut out: Vec<u8, 64> = Vec::new();
+        let received = framed(b"longer than eight bytes");
+        assert!(!received.iter().any(|&b| decoder.push(b, &mut out)));
+        let short = framed(b"ok");
+        assert!(short.iter().any(|&b| decoder.push(b, &mut out)));
+        assert_eq!(&out[..], b"ok");
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Optional compression of large sensory lines (config.json `compression`)
+//!
+//! Camera frames and wide IMU/encoder frames repeat a lot of text, so a board
+//! built with a `compression` block can send them heatshrink-compressed
+//! (LZSS with an 8-bit window and 4-bit lookahead, `-w 8 -l 4`):
+//!
+//! 1. The capabilities document lists `"compression":["heatshrink"]`
+//! 2. The host opts in with `{"compression":"heatshrink"}`, answered by
+//!    `{"compression_ack":"heatshrink"}`; `{"compression":"none"}` (or an
+//!    algorithm the board doesn't know) turns it off again and is answered
+//!    by `{"compression_ack":"none"}`
+//! 3. From then on, a sensory frame or camera line of at least `min_bytes`
+//!    goes out as
+//!
+//!    `{"id":"esp32","lz":1843,"d":"<base64 of the heatshrink stream>"}`
+//!
+//!    where `"lz"` flags the line as compressed and gives the length of the
+//!    original line (without the device ID), which the host decompresses and
+//!    parses as usual
+//!
+//! A line that wouldn't get shorter is sent as it is, and so are binary
+//! packets, which are compact already. Every hello starts uncompressed.
+//! tools/feagi_esp32.py decompresses recorded traces.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::{base64, parse};
+
+/// Compression settings (from config.json `compression`)
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    /// Shorter lines are sent uncompressed
+    pub min_bytes: u32,
+}
+
+/// Name of the only algorithm, as advertised and requested
+pub const ALGORITHM: &str = "heatshrink";
+
+/// Window and lookahead sizes (log2), as heatshrink's -w and -l
+const WINDOW_BITS: u32 = 8;
+const LOOKAHEAD_BITS: u32 = 4;
+const WINDOW: usize = 1 << WINDOW_BITS;
+const LOOKAHEAD: usize = 1 << LOOKAHEAD_BITS;
+
+/// A back-reference (13 bits) only pays off from two bytes (18 bits as literals)
+const MIN_MATCH: usize = 2;
+
+/// Earlier positions tried per match
+const MAX_CHAIN: usize = 16;
+
+const HASH_SIZE: usize = 256;
+const NONE: u16 = u16::MAX;
+
+/// Parse a `{"compression":"..."}` request: Some(true) to turn compression
+/// on, Some(false) to turn it off
+pub fn parse_request(message: &str) -> Option<bool> {
+    if !message.starts_with("{\"compression\"") {
+        return None;
+    }
+    Some(parse::words(message).get(1).is_some_and(|w| *w == ALGORITHM))
+}
+
+/// `{"compression_ack":"..."}` line confirming what's in use
+pub fn ack_line(on: bool) -> String<48> {
+    let mut line: String<48> = String::new();
+    let _ = line.push_str("{\"compression_ack\":\"");
+    let _ = line.push_str(if on { ALGORITHM } else { "none" });
+    let _ = line.push_str("\"}\n");
+    line
+}
+
+/// `line` as a compressed `{"lz":...,"d":"..."}` line built in `out`
+///
+/// Lines shorter than `min_bytes`, lines that aren't JSON objects (binary
+/// packets) and lines that wouldn't get shorter are returned as they are.
+pub fn shrink<'a, const N: usize>(line: &'a [u8], min_bytes: u32, out: &'a mut Vec<u8, N>) -> &'a [u8] {
+    if !line.starts_with(b"{") || line.len() < min_bytes as usize || line.len() > u16::MAX as usize {
+        return line;
+    }
+    let mut packed: Vec<u8, N> = Vec::new();
+    if !encode(line, &mut packed) {
+        return line;
+    }
+    out.clear();
+    let mut num: String<16> = String::new();
+    let _ = write!(num, "{}", line.len());
+    let fits = out.extend_from_slice(b"{\"lz\":").is_ok()
+        && out.extend_from_slice(num.as_bytes()).is_ok()
+        && out.extend_from_slice(b",\"d\":\"").is_ok()
+        && base64::encode(&packed, out)
+        && out.extend_from_slice(b"\"}\n").is_ok();
+    if !fits || out.len() >= line.len() {
+        return line;
+    }
+    out
+}
+
+/// Heatshrink-compress `input` into `out`; false if it didn't fit
+///
+/// Greedy LZSS: each position takes the longest match among the last
+/// MAX_CHAIN earlier positions starting with the same two bytes. Input is
+/// at most 64 KiB (positions are u16).
+pub fn encode<const N: usize>(input: &[u8], out: &mut Vec<u8, N>) -> bool {
+    let mut bits = BitWriter { out, acc: 0, count: 0 };
+    // Newest position of each two-byte hash, and the one before each position
+    let mut head = [NONE; HASH_SIZE];
+    let mut prev = [NONE; WINDOW];
+    let hash = |pos: usize| (input[pos] as usize * 31 + input[pos + 1] as usize) % HASH_SIZE;
+
+    let mut pos = 0;
+    while pos < input.len() {
+        let (mut best_len, mut best_distance) = (0, 0);
+        if pos + 1 < input.len() {
+            let max_len = LOOKAHEAD.min(input.len() - pos);
+            let mut candidate = head[hash(pos)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == NONE || pos - candidate as usize > WINDOW {
+                    break;
+                }
+                let start = candidate as usize;
+                let len = input[start..].iter().zip(&input[pos..pos + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    (best_len, best_distance) = (len, pos - start);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                // Chains only run backwards; anything else is a reused slot
+                let next = prev[start % WINDOW];
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        let written = if best_len >= MIN_MATCH {
+            bits.push(0, 1)
+                && bits.push(best_distance as u32 - 1, WINDOW_BITS)
+                && bits.push(best_len as u32 - 1, LOOKAHEAD_BITS)
+        } else {
+            best_len = 1;
+            bits.push(1, 1) && bits.push(input[pos] as u32, 8)
+        };
+        if !written {
+            return false;
+        }
+        for p in (pos..pos + best_len).filter(|p| p + 1 < input.len()) {
+            prev[p % WINDOW] = head[hash(p)];
+            head[hash(p)] = p as u16;
+        }
+        pos += best_len;
+    }
+    bits.flush()
+}
+
+/// MSB-first bit packing, the last byte padded with zeros
+struct BitWriter<'a, const N: usize> {
+    out: &'a mut Vec<u8, N>,
+    acc: u32,
+    count: u32,
+}
+
+impl<const N: usize> BitWriter<'_, N> {
+    fn push(&mut self, value: u32, bits: u32) -> bool {
+        self.acc = self.acc << bits | value;
+        self.count += bits;
+        while self.count >= 8 {
+            self.count -= 8;
+            if self.out.push((self.acc >> self.count) as u8).is_err() {
+                return false;
+            }
+        }
+        self.acc &= (1 << self.count) - 1;
+        true
+    }
+
+    fn flush(&mut self) -> bool {
+        self.count == 0 || self.out.push((self.acc << (8 - self.count)) as u8).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Heatshrink -w 8 -l 4 decoder, as tools/feagi_esp32.py's
+    fn decode(data: &[u8]) -> Vec<u8, 1024> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        let mut read = |bits: usize| -> Option<usize> {
+            if pos + bits > data.len() * 8 {
+                return None;
+            }
+            let mut value = 0;
+            for _ in 0..bits {
+                value = value << 1 | (data[pos / 8] >> (7 - pos % 8) & 1) as usize;
+                pos += 1;
+            }
+            Some(value)
+        };
+        while let Some(tag) = read(1) {
+            if tag == 1 {
+                let Some(byte) = read(8) else { break };
+                out.push(byte as u8).unwrap();
+            } else {
+                let (Some(index), Some(count)) = (read(WINDOW_BITS as usize), read(LOOKAHEAD_BITS as usize)) else { break };
+                for _ in 0..=count {
+                    out.push(out[out.len() - index - 1]).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    fn encoded(input: &[u8]) -> Vec<u8, 1024> {
+        let mut out = Vec::new();
+        assert!(encode(input, &mut out));
+        out
+    }
+
+    #[test]
+    fn known_streams() {
+        // 'a', then 9 bytes from 1 back: 1 01100001, 0 00000000 1000
+        assert_eq!(&encoded(b"aaaaaaaaaa")[..], [0xB0, 0x80, 0x20]);
+        // 'a', 'b', 'c', then 6 bytes from 3 back
+        assert_eq!(&encoded(b"abcabcabc")[..], [0xB0, 0xD8, 0xAC, 0x60, 0x25]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let line = b"{\"np\":[[1,0.500],[2,0.500],[3,0.250],[4,0.500],[5,0.500],[6,0.125]],\"f\":1234}\n";
+        let packed = encoded(line);
+        assert!(packed.len() < line.len());
+        assert_eq!(decode(&packed), line);
+
+        // Pseudo-random text: matches at every distance the window holds
+        let mut seed: u32 = 1;
+        let text: Vec<u8, 600> = (0..600)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b"0123,.[]"[(seed >> 16) as usize % 8]
+            })
+            .collect();
+        assert_eq!(decode(&encoded(&text)), text);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(encoded(b"").is_empty());
+        let mut out: Vec<u8, 64> = Vec::new();
+        assert!(shrink(b"", 0, &mut out).is_empty());
+    }
+
+    #[test]
+    fn all_repeats() {
+        let input = [b'x'; 300];
+        let packed = encoded(&input);
+        // A literal, then 16-byte back-references of 13 bits each
+        assert_eq!(packed.len(), (9 + 19 * 13_usize).div_ceil(8));
+        assert_eq!(decode(&packed), input);
+    }
+
+    #[test]
+    fn incompressible() {
+        let input: Vec<u8, 256> = (0..=255).collect();
+        let packed = encoded(&input);
+        assert_eq!(packed.len(), 256 * 9 / 8);
+        assert_eq!(decode(&packed), input);
+        let mut small: Vec<u8, 64> = Vec::new();
+        assert!(!encode(&input, &mut small));
+    }
+
+    #[test]
+    fn shrinks_only_what_gets_shorter() {
+        let line = b"{\"np\":[[1,0.500],[2,0.500],[3,0.500],[4,0.500],[5,0.500],[6,0.500],[7,0.500],[8,0.500],[9,0.500]]}\n";
+        let mut out: Vec<u8, 256> = Vec::new();
+        let shrunk = shrink(line, 16, &mut out);
+        assert!(shrunk.starts_with(b"{\"lz\":99,\"d\":\"") && shrunk.len() < line.len());
+        let mut out: Vec<u8, 256> = Vec::new();
+        assert_eq!(shrink(line, 200, &mut out), line);
+        let mut out: Vec<u8, 256> = Vec::new();
+        assert_eq!(shrink(b"{\"a\":1}\n", 0, &mut out), b"{\"a\":1}\n");
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Sensory frames held back while the transport is down
+//!
+//! When WiFi drops or FEAGI closes the socket the burst loop keeps sampling;
+//! its frames go into this queue instead of being lost, and are sent oldest
+//! first once the supervisor has reconnected. The queue is bounded both in
+//! frames and in bytes: when either runs out, the oldest frames are dropped
+//! (and counted), since fresh sensor data is worth more than stale.
+//!
+//! Frames are newline-terminated lines stored back to back in one byte ring.
+
+use heapless::{Deque, Vec};
+
+pub struct FrameQueue<const N: usize> {
+    bytes: Deque<u8, N>,
+    frames: u32,
+    max_frames: u32,
+    /// Frames dropped because the queue was full
+    pub dropped: u32,
+    /// Frames sent late, after they had been queued
+    pub resent: u32,
+}
+
+impl<const N: usize> FrameQueue<N> {
+    pub const fn new(max_frames: u32) -> Self {
+        Self {
+            bytes: Deque::new(),
+            frames: 0,
+            max_frames,
+            dropped: 0,
+            resent: 0,
+        }
+    }
+
+    /// Frames waiting
+    pub fn len(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    /// Queue one line, dropping the oldest frames to make room
+    pub fn push(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        if self.max_frames == 0 || line.len() + 1 > N {
+            self.dropped = self.dropped.wrapping_add(1);
+            return;
+        }
+        while self.frames >= self.max_frames || self.bytes.capacity() - self.bytes.len() < line.len() + 1 {
+            self.pop_front();
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        for &b in line {
+            let _ = self.bytes.push_back(b);
+        }
+        let _ = self.bytes.push_back(b'\n');
+        self.frames += 1;
+    }
+
+    /// Copy the oldest frame (with its newline) into `out`; false if the
+    /// queue is empty or the frame doesn't fit
+    pub fn front<const M: usize>(&self, out: &mut Vec<u8, M>) -> bool {
+        out.clear();
+        if self.frames == 0 {
+            return false;
+        }
+        for &b in self.bytes.iter() {
+            if out.push(b).is_err() {
+                return false;
+            }
+            if b == b'\n' {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The oldest frame went out: drop it and count it as re-sent
+    pub fn sent_front(&mut self) {
+        if !self.is_empty() {
+            self.resent = self.resent.wrapping_add(1);
+        }
+        self.pop_front();
+    }
+
+    /// Drop the oldest frame
+    pub fn pop_front(&mut self) {
+        if self.frames == 0 {
+            return;
+        }
+        while let Some(b) = self.bytes.pop_front() {
+            if b == b'\n' {
+                break;
+            }
+        }
+        self.frames -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn front<const N: usize>(queue: &FrameQueue<N>) -> Vec<u8, 64> {
+        let mut out = Vec::new();
+        assert!(queue.front(&mut out));
+        out
+    }
+
+    #[test]
+    fn oldest_first() {
+        let mut queue: FrameQueue<64> = FrameQueue::new(4);
+        queue.push(b"{\"f\":1}\n");
+        queue.push(b"{\"f\":2}");
+        assert_eq!(queue.len(), 2);
+        assert_eq!(&front(&queue)[..], b"{\"f\":1}\n");
+        queue.sent_front();
+        assert_eq!(&front(&queue)[..], b"{\"f\":2}\n");
+        queue.sent_front();
+        assert!(queue.is_empty());
+        assert!(!queue.front(&mut Vec::<u8, 64>::new()));
+        assert_eq!((queue.resent, queue.dropped), (2, 0));
+    }
+
+    #[test]
+    fn full_drops_the_oldest() {
+        let mut queue: FrameQueue<64> = FrameQueue::new(2);
+        for line in [b"{\"f\":1}\n", b"{\"f\":2}\n", b"{\"f\":3}\n"] {
+            queue.push(line);
+        }
+        assert_eq!((queue.len(), queue.dropped), (2, 1));
+        assert_eq!(&front(&queue)[..], b"{\"f\":2}\n");
+
+        // Out of bytes rather than frames: 3 × 8 bytes, then one of 19
+        let mut queue: FrameQueue<32> = FrameQueue::new(8);
+        for line in [b"{\"f\":1}\n", b"{\"f\":2}\n", b"{\"f\":3}\n"] {
+            queue.push(line);
+        }
+        queue.push(b"{\"f\":4,\"np\":[1,2]}\n");
+        assert_eq!((queue.len(), queue.dropped), (2, 2));
+        assert_eq!(&front(&queue)[..], b"{\"f\":3}\n");
+    }
+
+    #[test]
+    fn too_long_or_disabled() {
+        let mut queue: FrameQueue<8> = FrameQueue::new(4);
+        queue.push(b"{\"f\":10}\n");
+        assert_eq!((queue.len(), queue.dropped), (0, 1));
+        let mut queue: FrameQueue<64> = FrameQueue::new(0);
+        queue.push(b"{\"f\":1}\n");
+        assert_eq!((queue.len(), queue.dropped), (0, 1));
+    }
+}
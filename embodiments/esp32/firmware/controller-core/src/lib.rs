@@ -0,0 +1,31 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! The parts of the ESP32 controller firmware that don't touch ESP-IDF
+//!
+//! Kept out of the firmware crate, which only builds for the board, so that
+//! `cargo test` runs them on the host:
+//!
+//! - [`parse`]: host lines taken apart without a JSON parser
+//! - [`cobs`]: COBS framing with a CRC16 for the serial link
+//! - [`protocol`]: binary FEAGI packets
+//! - [`compress`]: heatshrink compression of large sensory lines
+//! - [`base64`]: the encoding compressed lines and WebSocket keys use
+//! - [`frame_queue`]: sensory frames held back while the transport is down
+//! - [`rate_policy`]: burst-rate mismatch policies
+//! - [`profile`]: the per-burst profiler's lines
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod base64;
+pub mod cobs;
+pub mod compress;
+pub mod frame_queue;
+pub mod parse;
+pub mod profile;
+pub mod protocol;
+pub mod rate_policy;
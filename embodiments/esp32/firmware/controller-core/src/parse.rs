@@ -0,0 +1,186 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Pieces of host lines, without a JSON parser
+//!
+//! Most host requests are short, one-level objects that serde-json-core
+//! would need a struct per shape for. These take them apart in place:
+//!
+//! - [`words`] splits a line into its names and numbers, for requests like
+//!   `{"rd":4}` or `{"sysid":{"n":3,"sig":"step"}}`
+//! - [`pairs`] reads a flat object of key/value pairs, for
+//!   `{"set_settings":{...}}` and `{"store_config":{...}}`, where values may
+//!   hold any character but a quote
+//! - [`strings`] reads an array of strings, for `{"get_settings":[...]}`
+//!
+//! [`take_line`] cuts the lines out of what the transport received.
+
+use heapless::{String, Vec};
+
+/// Most words [`words`] returns; the rest of a line is left out
+pub const MAX_WORDS: usize = 32;
+
+/// Most pairs of an object [`pairs`] reads
+pub const MAX_PAIRS: usize = 32;
+
+/// The runs of letters, digits, `.`, `-` and `_` in `message`, up to
+/// MAX_WORDS of them
+pub fn words(message: &str) -> Vec<&str, MAX_WORDS> {
+    let mut words = Vec::new();
+    for word in message.split(|c: char| !c.is_alphanumeric() && c != '.' && c != '-' && c != '_') {
+        if !word.is_empty() && words.push(word).is_err() {
+            break;
+        }
+    }
+    words
+}
+
+/// Key/value pairs of a flat JSON object (`{"a":1,"b":"x"}`, strings without
+/// escapes); None if it's malformed or has more than MAX_PAIRS
+pub fn pairs(object: &str) -> Option<Vec<(&str, &str), MAX_PAIRS>> {
+    let mut pairs = Vec::new();
+    let mut rest = object.trim().strip_prefix('{')?.trim_start();
+    if let Some(end) = rest.strip_prefix('}') {
+        return end.trim().is_empty().then_some(pairs);
+    }
+    loop {
+        let (key, after) = string(rest)?;
+        rest = after.trim_start().strip_prefix(':')?.trim_start();
+        let (value, after) = if rest.starts_with('"') {
+            string(rest)?
+        } else {
+            let end = rest.find([',', '}'])?;
+            (rest[..end].trim_end(), &rest[end..])
+        };
+        if value.is_empty() {
+            return None;
+        }
+        pairs.push((key, value)).ok()?;
+        rest = after.trim_start();
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None => return rest.strip_prefix('}')?.trim().is_empty().then_some(pairs),
+        }
+    }
+}
+
+/// The strings of a JSON array (`["a","b"]`, without escapes); None if it's
+/// malformed or has more than N
+pub fn strings<const N: usize>(array: &str) -> Option<Vec<&str, N>> {
+    let mut strings = Vec::new();
+    let mut rest = array.trim().strip_prefix('[')?.trim_start();
+    if let Some(end) = rest.strip_prefix(']') {
+        return end.trim().is_empty().then_some(strings);
+    }
+    loop {
+        let (value, after) = string(rest)?;
+        strings.push(value).ok()?;
+        rest = after.trim_start();
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None => return rest.strip_prefix(']')?.trim().is_empty().then_some(strings),
+        }
+    }
+}
+
+/// The value of `{"<name>":<value>}`, the whole line being that one member
+pub fn member<'a>(message: &'a str, name: &str) -> Option<&'a str> {
+    let rest = message.trim().strip_prefix("{\"")?.strip_prefix(name)?.strip_prefix('"')?;
+    rest.trim_start().strip_prefix(':')?.trim_end().strip_suffix('}')
+}
+
+/// Take the first complete host line (up to its `\n`) off the front of
+/// `received`, keeping what follows for the next call; None while no line is
+/// complete. Non-ASCII bytes are left out
+pub fn take_line<const N: usize>(received: &mut Vec<u8, N>) -> Option<String<N>> {
+    let end = received.iter().position(|&b| b == b'\n')?;
+    let mut line = String::new();
+    for &byte in &received[..end] {
+        if byte.is_ascii() {
+            let _ = line.push(byte as char);
+        }
+    }
+    received.rotate_left(end + 1);
+    received.truncate(received.len() - end - 1);
+    Some(line)
+}
+
+/// A JSON string at the start of `s`, and what follows it
+pub fn string(s: &str) -> Option<(&str, &str)> {
+    let s = s.strip_prefix('"')?;
+    let end = s.find(['"', '\\'])?;
+    (s.as_bytes()[end] == b'"').then(|| (&s[..end], &s[end + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_of_a_request() {
+        assert_eq!(&words("{\"sysid\":{\"n\":3,\"amp\":0.5,\"sig\":\"step\"}}")[..], ["sysid", "n", "3", "amp", "0.5", "sig", "step"]);
+    }
+
+    #[test]
+    fn pairs_with_any_value_characters() {
+        let pairs = pairs("{\"rate_policy.motor\":\"interp\", \"barrier.timeout_ms\":8,\"gpio.25.cortical_mapping\":\"omot00:3, 1\"}").unwrap();
+        assert_eq!(&pairs[..], [("rate_policy.motor", "interp"), ("barrier.timeout_ms", "8"), ("gpio.25.cortical_mapping", "omot00:3, 1")]);
+        assert_eq!(super::pairs("{}").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn malformed_pairs() {
+        for object in ["", "{", "{\"a\"}", "{\"a\":}", "{\"a\":1,}", "{\"a\":1} x", "{\"a\\\"\":1}"] {
+            assert!(pairs(object).is_none(), "{}", object);
+        }
+    }
+
+    #[test]
+    fn strings_of_an_array() {
+        assert_eq!(&strings::<4>("[\"mode\", \"rate_policy.ratio\"]").unwrap()[..], ["mode", "rate_policy.ratio"]);
+        assert!(strings::<4>("[]").unwrap().is_empty());
+        assert!(strings::<1>("[\"a\",\"b\"]").is_none());
+        assert!(strings::<4>("[\"a\",2]").is_none());
+    }
+
+    #[test]
+    fn member_of_a_line() {
+        assert_eq!(member("{\"set_settings\":{\"mode\":\"raw\"}}", "set_settings"), Some("{\"mode\":\"raw\"}"));
+        assert_eq!(member("{\"get_settings\": [] }\r", "get_settings"), Some(" [] "));
+        assert_eq!(member("{\"get_settings\":[]}", "set_settings"), None);
+    }
+
+    fn received(bytes: &[u8]) -> Vec<u8, 64> {
+        Vec::from_slice(bytes).unwrap()
+    }
+
+    #[test]
+    fn two_lines_in_one_read() {
+        let mut rx = received(b"{\"neuron_id\":1,\"value\":0.5}\n{\"mc\":[[2,1.0]]}\n");
+        assert_eq!(take_line(&mut rx).as_deref(), Some("{\"neuron_id\":1,\"value\":0.5}"));
+        assert_eq!(take_line(&mut rx).as_deref(), Some("{\"mc\":[[2,1.0]]}"));
+        assert_eq!(take_line(&mut rx), None);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn partial_line_kept_for_the_next_read() {
+        let mut rx = received(b"{\"b\":7}\n{\"neuron");
+        assert_eq!(take_line(&mut rx).as_deref(), Some("{\"b\":7}"));
+        assert_eq!(take_line(&mut rx), None);
+        assert_eq!(&rx[..], b"{\"neuron");
+        rx.extend_from_slice(b"_id\":3}\n").unwrap();
+        assert_eq!(take_line(&mut rx).as_deref(), Some("{\"neuron_id\":3}"));
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn non_ascii_left_out() {
+        let mut rx = received(b"{\"b\":\xff1}\n");
+        assert_eq!(take_line(&mut rx).as_deref(), Some("{\"b\":1}"));
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Per-burst profiler: when each burst started and how long it worked
+//!
+//! With `telemetry.profile` in config.json, the burst loop's timing goes to
+//! the host in batches of PROFILE_BATCH bursts:
+//!
+//! `{"prof":[[1200345,412],[1210351,398],...]}`
+//!
+//! one `[start_us, busy_us]` pair per burst: its start on the board's clock
+//! (µs since boot) and the time it spent working, waits left out (see
+//! health.rs). `tools/feagi_esp32.py export` turns them into burst spans.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+/// Bursts per `{"prof":[...]}` line
+pub const PROFILE_BATCH: usize = 16;
+
+/// Longest `{"prof":[...]}` line: a full batch of 19- and 10-digit pairs
+pub const PROFILE_LINE_CAPACITY: usize = 12 + PROFILE_BATCH * 34;
+
+/// The bursts timed since the last line
+pub struct Profiler {
+    bursts: Vec<(i64, i64), PROFILE_BATCH>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self { bursts: Vec::new() }
+    }
+
+    /// Time a burst; left out when a full batch is still waiting to go
+    pub fn record(&mut self, start_us: i64, busy_us: i64) {
+        let _ = self.bursts.push((start_us, busy_us));
+    }
+
+    /// Is a full batch waiting to go?
+    pub fn due(&self) -> bool {
+        self.bursts.is_full()
+    }
+
+    /// `{"prof":[...]}\n` line of the bursts timed so far, which starts a new
+    /// batch
+    pub fn line(&mut self) -> String<PROFILE_LINE_CAPACITY> {
+        let mut line: String<PROFILE_LINE_CAPACITY> = String::new();
+        let _ = line.push_str("{\"prof\":[");
+        for (i, (start_us, busy_us)) in self.bursts.iter().enumerate() {
+            let _ = write!(line, "{}[{},{}]", if i == 0 { "" } else { "," }, start_us, busy_us);
+        }
+        let _ = line.push_str("]}\n");
+        self.bursts.clear();
+        line
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches() {
+        let mut profiler = Profiler::new();
+        profiler.record(1200345, 412);
+        profiler.record(1210351, 398);
+        assert!(!profiler.due());
+        assert_eq!(profiler.line().as_str(), "{\"prof\":[[1200345,412],[1210351,398]]}\n");
+        assert_eq!(profiler.line().as_str(), "{\"prof\":[]}\n");
+    }
+
+    #[test]
+    fn full_batch_fits() {
+        let mut profiler = Profiler::new();
+        for _ in 0..PROFILE_BATCH + 1 {
+            profiler.record(i64::MAX, u32::MAX as i64);
+        }
+        assert!(profiler.due());
+        let line = profiler.line();
+        assert!(line.ends_with("]]}\n"));
+        assert_eq!(line.matches('[').count(), PROFILE_BATCH + 1);
+    }
+}
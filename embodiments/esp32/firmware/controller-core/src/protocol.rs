@@ -0,0 +1,210 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Binary FEAGI packets (config.json `"wire_format": "binary"`)
+//!
+//! The same command-ID/length framing as the micro:bit firmware
+//! (embodiments/microbit/firmware/src/protocol.rs): every packet is
+//! `[id, len, payload (len bytes)]`, little-endian throughout.
+//!
+//! Host → board:
+//!
+//! | ID   | Payload                                                   |
+//! |------|-----------------------------------------------------------|
+//! | 0x10 | Motor commands: n × (neuron_id u16, value u16)            |
+//! | 0x11 | Sequenced motor commands: seq u32, then as 0x10           |
+//!
+//! Board → host, one burst as consecutive packets closed by 0x0F:
+//!
+//! | ID   | Payload                                                   |
+//! |------|-----------------------------------------------------------|
+//! | 0x01 | Sensory potentials (`np`): n × (neuron_id u16, value u16) |
+//! | 0x02 | Applied outputs (`ao`), as 0x01                           |
+//! | 0x03 | Servo feedback (`fb`), as 0x01                            |
+//! | 0x04 | Edge counts (`ec`): n × (neuron_id u16, count u16)        |
+//! | 0x05 | Odometry (`od`): x, y, th, d, dth as i32 tenths           |
+//! | 0x06 | WiFi link quality (`wl`), as 0x01                         |
+//! | 0x07 | Board health: chip °C (i16 tenths, MIN = none), load u16  |
+//! | 0x08 | Delta reporting: 1 = keyframe, 0 = changed channels (u8)  |
+//! | 0x0F | End of burst: frame u32, then Unix ms u64 once synced     |
+//!
+//! Values of 0.0-1.0 travel as 0-65535. A list longer than one packet holds
+//! (63 entries) continues in another packet of the same ID. Hello, acks and
+//! the other control messages stay JSON lines; a packet is told apart by its
+//! first byte, a control character other than CR or LF. A 0x11 packet is
+//! answered by a `motor_ack` line (see the firmware's motor_ack.rs).
+//!
+//! The firmware's protocol.rs lays a sensory frame out as these packets.
+
+use core::ops::Range;
+
+use heapless::Vec;
+
+/// Motor commands, host → board
+pub const CMD_MOTOR: u8 = 0x10;
+/// Motor commands with a sequence number, host → board
+pub const CMD_MOTOR_SEQ: u8 = 0x11;
+
+/// Burst contents, board → host
+pub const PKT_POTENTIALS: u8 = 0x01;
+pub const PKT_APPLIED: u8 = 0x02;
+pub const PKT_FEEDBACK: u8 = 0x03;
+pub const PKT_EDGES: u8 = 0x04;
+pub const PKT_ODOMETRY: u8 = 0x05;
+pub const PKT_LINK: u8 = 0x06;
+pub const PKT_HEALTH: u8 = 0x07;
+pub const PKT_KEYFRAME: u8 = 0x08;
+pub const PKT_END: u8 = 0x0F;
+
+/// Longest payload (the length is one byte)
+const MAX_PAYLOAD: usize = 255;
+
+/// Sequenced messages handled per read
+pub const MAX_SEQUENCED: usize = 8;
+
+/// (neuron_id, value) entries per packet
+const ENTRIES_PER_PACKET: usize = MAX_PAYLOAD / 4;
+
+/// A sequenced message among one read's motor commands
+#[derive(Debug, Clone)]
+pub struct Sequenced {
+    pub seq: u32,
+    /// Its commands' indices in the read's command list
+    pub commands: Range<usize>,
+}
+
+/// Whether `byte` starts a host → board packet rather than a JSON line
+pub fn is_packet_start(byte: u8) -> bool {
+    (0x10..0x20).contains(&byte)
+}
+
+/// Decode the complete packets at the front of `bytes`, adding their motor
+/// commands to `commands` and their sequence numbers to `sequenced`
+///
+/// Returns how many bytes were consumed and how many packets they held;
+/// decoding stops at the first incomplete packet or byte that doesn't start
+/// one. Unknown IDs are skipped.
+pub fn decode_packets<const N: usize>(
+    bytes: &[u8],
+    commands: &mut Vec<(u32, f32), N>,
+    sequenced: &mut Vec<Sequenced, MAX_SEQUENCED>,
+) -> (usize, u32) {
+    let mut used = 0;
+    let mut packets = 0;
+    while let [id, len, ..] = bytes[used..] {
+        let end = used + 2 + len as usize;
+        if !is_packet_start(id) || end > bytes.len() {
+            break;
+        }
+        let payload = &bytes[used + 2..end];
+        match id {
+            CMD_MOTOR => push_commands(payload, commands),
+            CMD_MOTOR_SEQ if payload.len() >= 4 => {
+                let seq = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                let start = commands.len();
+                push_commands(&payload[4..], commands);
+                let _ = sequenced.push(Sequenced { seq, commands: start..commands.len() });
+            }
+            _ => {}
+        }
+        used = end;
+        packets += 1;
+    }
+    (used, packets)
+}
+
+/// (neuron_id u16, value u16) entries as motor commands
+fn push_commands<const N: usize>(entries: &[u8], commands: &mut Vec<(u32, f32), N>) {
+    for entry in entries.chunks_exact(4) {
+        let neuron_id = u16::from_le_bytes([entry[0], entry[1]]) as u32;
+        let value = u16::from_le_bytes([entry[2], entry[3]]) as f32 / 65535.0;
+        let _ = commands.push((neuron_id, value));
+    }
+}
+
+/// (neuron_id, value) entries as packets of `id`, 63 at most each; entries
+/// whose neuron ID doesn't fit 16 bits are left out
+pub fn push_entries<const N: usize>(out: &mut Vec<u8, N>, id: u8, entries: impl Iterator<Item = (u32, u16)>) -> Option<()> {
+    let mut payload: Vec<u8, MAX_PAYLOAD> = Vec::new();
+    let mut any = false;
+    for (neuron_id, value) in entries.filter(|&(neuron_id, _)| neuron_id <= u16::MAX as u32) {
+        if payload.len() == ENTRIES_PER_PACKET * 4 {
+            push_packet(out, id, &payload)?;
+            payload.clear();
+        }
+        payload.extend_from_slice(&(neuron_id as u16).to_le_bytes()).ok()?;
+        payload.extend_from_slice(&value.to_le_bytes()).ok()?;
+        any = true;
+    }
+    if any {
+        push_packet(out, id, &payload)?;
+    }
+    Some(())
+}
+
+/// One packet of `id`; None if it doesn't fit `out`
+pub fn push_packet<const N: usize>(out: &mut Vec<u8, N>, id: u8, payload: &[u8]) -> Option<()> {
+    out.push(id).ok()?;
+    out.push(payload.len() as u8).ok()?;
+    out.extend_from_slice(payload).ok()
+}
+
+/// 0.0-1.0 as 0-65535
+pub fn unit(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16
+}
+
+/// Rounded to tenths, as an integer
+pub fn tenths(value: f32) -> i32 {
+    let scaled = value * 10.0;
+    (if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 }) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn motor_packets() {
+        let mut commands: Vec<(u32, f32), 8> = Vec::new();
+        let mut sequenced = Vec::new();
+        // A command, then the start of one still being received
+        let bytes = [CMD_MOTOR, 4, 3, 0, 0xFF, 0xFF, CMD_MOTOR, 4, 1];
+        assert_eq!(decode_packets(&bytes, &mut commands, &mut sequenced), (6, 1));
+        assert_eq!(&commands[..], [(3, 1.0)]);
+        assert!(sequenced.is_empty());
+
+        commands.clear();
+        // Unknown IDs are skipped; a JSON line isn't a packet
+        let bytes = [0x1F, 1, 9, CMD_MOTOR_SEQ, 8, 0x2A, 0, 0, 0, 1, 0, 0, 0, b'{'];
+        assert_eq!(decode_packets(&bytes, &mut commands, &mut sequenced), (13, 2));
+        assert_eq!(&commands[..], [(1, 0.0)]);
+        assert_eq!((sequenced[0].seq, sequenced[0].commands.clone()), (42, 0..1));
+        assert!(is_packet_start(CMD_MOTOR) && !is_packet_start(b'{') && !is_packet_start(b'\n'));
+    }
+
+    #[test]
+    fn entries_split_into_packets() {
+        let mut out: Vec<u8, 512> = Vec::new();
+        push_entries(&mut out, PKT_POTENTIALS, (0..64).map(|id| (id, unit(0.5)))).unwrap();
+        assert_eq!(&out[..6], [PKT_POTENTIALS, 252, 0, 0, 0x00, 0x80]);
+        assert_eq!(&out[254..], [PKT_POTENTIALS, 4, 63, 0, 0x00, 0x80]);
+
+        // Wide neuron IDs are left out, and nothing is sent for no entries
+        out.clear();
+        push_entries(&mut out, PKT_APPLIED, [(70_000, 1)].into_iter()).unwrap();
+        assert!(out.is_empty());
+        let mut small: Vec<u8, 4> = Vec::new();
+        assert!(push_packet(&mut small, PKT_END, &[1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn fixed_point_values() {
+        assert_eq!((unit(-1.0), unit(0.5), unit(2.0)), (0, 0x8000, 0xFFFF));
+        assert_eq!((tenths(-23.46), tenths(23.46), tenths(-0.04)), (-235, 235, 0));
+    }
+}
@@ -0,0 +1,508 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Burst-rate mismatch policies
+//!
+//! FEAGI's burst rate and the controller's sampling rate rarely match. These
+//! policies make the behaviour between FEAGI bursts explicit instead of
+//! implicit:
+//!
+//! **Motor** (what an output does between commands):
+//! - `hold`: keep the last commanded value (previous behaviour)
+//! - `decay`: after `decay_after_ms` without a command, ramp linearly to
+//!   `neutral` over `decay_ms` (a stalled host doesn't leave motors running);
+//!   DC motor outputs decay to their stop command (0.5) instead
+//! - `interp`: ramp from the current value to each new command over the
+//!   measured interval between commands, smoothing slow bursts
+//!
+//! **Sensory** (how samples map to frames):
+//! - `every`: one frame per sample (previous behaviour)
+//! - `subsample`: send every `ratio`-th sample
+//! - `aggregate`: send every `ratio`-th sample, each channel carrying the
+//!   maximum over the window so short pulses between frames aren't lost
+//!
+//! On top of either, `delta` reporting leaves out channels that moved by no
+//! more than `delta_epsilon` since they were last sent, with a full keyframe
+//! at least every `keyframe_ms` (and at every hello). Frames say which they
+//! are with `"kf":1` (keyframe) or `"kf":0`; the host keeps the last value of
+//! a channel a delta frame leaves out.
+//!
+//! Defaults come from config.json (`rate_policy`); the host can renegotiate
+//! after the hello with `{"policy":{"motor":"interp","sensory":"aggregate","ratio":2}}`,
+//! answered by `{"policy_ack":{...}}` with the values actually in effect.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::parse;
+
+/// Longest interpolation ramp, so a stalled host can't stretch one forever
+const MAX_INTERP_US: i64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotorPolicy {
+    HoldLast,
+    DecayToNeutral,
+    Interpolate,
+}
+
+impl MotorPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hold" => Some(MotorPolicy::HoldLast),
+            "decay" => Some(MotorPolicy::DecayToNeutral),
+            "interp" => Some(MotorPolicy::Interpolate),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MotorPolicy::HoldLast => "hold",
+            MotorPolicy::DecayToNeutral => "decay",
+            MotorPolicy::Interpolate => "interp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensoryPolicy {
+    EveryFrame,
+    Subsample,
+    Aggregate,
+}
+
+impl SensoryPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "every" => Some(SensoryPolicy::EveryFrame),
+            "subsample" => Some(SensoryPolicy::Subsample),
+            "aggregate" => Some(SensoryPolicy::Aggregate),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensoryPolicy::EveryFrame => "every",
+            SensoryPolicy::Subsample => "subsample",
+            SensoryPolicy::Aggregate => "aggregate",
+        }
+    }
+}
+
+/// Policies in effect for this session
+#[derive(Debug, Clone, Copy)]
+pub struct RatePolicy {
+    pub motor: MotorPolicy,
+    pub sensory: SensoryPolicy,
+    /// Samples per frame for `subsample`/`aggregate`
+    pub ratio: u32,
+    pub decay_after_ms: u32,
+    pub decay_ms: u32,
+    /// Value outputs decay towards (0.0-1.0)
+    pub neutral: f32,
+    /// Delta reporting: only send channels that changed
+    pub delta: bool,
+    /// Change a channel must exceed to be sent in a delta frame
+    pub delta_epsilon: f32,
+    /// Longest time between full keyframes in delta mode
+    pub keyframe_ms: u32,
+}
+
+impl RatePolicy {
+    /// Apply a `{"policy":{...}}` negotiation on top of the current policy
+    ///
+    /// Returns None if the line isn't a policy message or has unknown values.
+    pub fn negotiate(&self, message: &str) -> Option<Self> {
+        if !message.starts_with("{\"policy\"") {
+            return None;
+        }
+        let mut policy = *self;
+        let words = parse::words(message);
+        for i in 0..words.len().saturating_sub(1) {
+            let value = words[i + 1];
+            match words[i] {
+                "motor" => policy.motor = MotorPolicy::parse(value)?,
+                "sensory" => policy.sensory = SensoryPolicy::parse(value)?,
+                "ratio" => policy.ratio = value.parse::<u32>().ok()?.max(1),
+                "decay_after_ms" => policy.decay_after_ms = value.parse().ok()?,
+                "decay_ms" => policy.decay_ms = value.parse().ok()?,
+                "neutral" => policy.neutral = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+                "delta" => policy.delta = value.parse().ok()?,
+                "delta_epsilon" => policy.delta_epsilon = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+                "keyframe_ms" => policy.keyframe_ms = value.parse::<u32>().ok()?.max(1),
+                _ => {}
+            }
+        }
+        Some(policy)
+    }
+
+    /// `{"policy_ack":{...}}` line describing the policy in effect
+    pub fn ack_line(&self) -> String<128> {
+        let mut line: String<128> = String::new();
+        let _ = line.push_str("{\"policy_ack\":{\"motor\":\"");
+        let _ = line.push_str(self.motor.as_str());
+        let _ = line.push_str("\",\"sensory\":\"");
+        let _ = line.push_str(self.sensory.as_str());
+        let _ = line.push_str("\",\"ratio\":");
+        let _ = write!(line, "{}", self.ratio);
+        let _ = line.push_str(if self.delta { ",\"delta\":true}}\n" } else { ",\"delta\":false}}\n" });
+        line
+    }
+}
+
+/// Per-neuron motor state between FEAGI commands
+#[derive(Debug, Clone, Copy)]
+struct MotorTrack {
+    neuron_id: u32,
+    /// Value at the start of the current ramp
+    from: f32,
+    /// Last commanded value
+    target: f32,
+    /// Value most recently driven
+    current: f32,
+    ramp_start_us: i64,
+    ramp_us: i64,
+    last_command_us: i64,
+}
+
+/// The outputs shaped motor commands are driven on (the firmware's
+/// OutputBank)
+pub trait Outputs {
+    /// Drive the outputs mapped to `neuron_id`
+    fn apply(&mut self, neuron_id: u32, value: f32);
+
+    /// Value that stops the DC motor or stepper driven by `neuron_id`, if
+    /// that's what it drives
+    fn motor_stop_value(&self, neuron_id: u32) -> Option<f32>;
+}
+
+/// Shapes motor commands according to the motor policy
+pub struct MotorShaper<const N: usize> {
+    tracks: Vec<MotorTrack, N>,
+}
+
+impl<const N: usize> MotorShaper<N> {
+    pub fn new() -> Self {
+        Self { tracks: Vec::new() }
+    }
+
+    /// A motor command arrived from FEAGI
+    pub fn command(
+        &mut self,
+        policy: &RatePolicy,
+        outputs: &mut impl Outputs,
+        neuron_id: u32,
+        value: f32,
+        now_us: i64,
+    ) {
+        let index = match self.tracks.iter().position(|t| t.neuron_id == neuron_id) {
+            Some(index) => index,
+            None => {
+                let track = MotorTrack {
+                    neuron_id,
+                    from: value,
+                    target: value,
+                    current: value,
+                    ramp_start_us: now_us,
+                    ramp_us: 0,
+                    last_command_us: now_us,
+                };
+                if self.tracks.push(track).is_err() {
+                    // More neurons than outputs: nothing to shape, apply directly
+                    outputs.apply(neuron_id, value);
+                    return;
+                }
+                self.tracks.len() - 1
+            }
+        };
+        let track = &mut self.tracks[index];
+        if policy.motor == MotorPolicy::Interpolate {
+            // Ramp over the interval FEAGI is actually bursting at
+            track.ramp_us = (now_us - track.last_command_us).clamp(0, MAX_INTERP_US);
+            track.from = track.current;
+        } else {
+            track.ramp_us = 0;
+            track.from = value;
+        }
+        track.target = value;
+        track.ramp_start_us = now_us;
+        track.last_command_us = now_us;
+        if track.ramp_us == 0 {
+            track.current = value;
+            outputs.apply(neuron_id, value);
+        }
+    }
+
+    /// Advance ramps and decays; call once per loop iteration
+    pub fn tick(&mut self, policy: &RatePolicy, outputs: &mut impl Outputs, now_us: i64) {
+        for track in self.tracks.iter_mut() {
+            let value = match policy.motor {
+                MotorPolicy::HoldLast => continue,
+                MotorPolicy::Interpolate => {
+                    if track.current == track.target {
+                        continue;
+                    }
+                    let elapsed = now_us - track.ramp_start_us;
+                    if track.ramp_us == 0 || elapsed >= track.ramp_us {
+                        track.target
+                    } else {
+                        let progress = elapsed as f32 / track.ramp_us as f32;
+                        track.from + (track.target - track.from) * progress
+                    }
+                }
+                MotorPolicy::DecayToNeutral => {
+                    let idle_us = now_us - track.last_command_us;
+                    let after_us = policy.decay_after_ms as i64 * 1000;
+                    let neutral = outputs.motor_stop_value(track.neuron_id).unwrap_or(policy.neutral);
+                    if idle_us < after_us || track.current == neutral {
+                        continue;
+                    }
+                    let decay_us = (policy.decay_ms as i64 * 1000).max(1);
+                    let progress = ((idle_us - after_us) as f32 / decay_us as f32).min(1.0);
+                    track.target + (neutral - track.target) * progress
+                }
+            };
+            track.current = value;
+            outputs.apply(track.neuron_id, value);
+        }
+    }
+}
+
+impl<const N: usize> Default for MotorShaper<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decides which samples become frames under the sensory policy
+pub struct SensoryWindow<const N: usize> {
+    samples: u32,
+    peaks: Vec<(u32, f32), N>,
+}
+
+impl<const N: usize> SensoryWindow<N> {
+    pub fn new() -> Self {
+        Self { samples: 0, peaks: Vec::new() }
+    }
+
+    /// Feed one sample; returns true when a frame should be sent
+    ///
+    /// With `aggregate`, `data` is replaced by the per-channel maximum over
+    /// the window when the frame is due.
+    pub fn push(&mut self, policy: &RatePolicy, data: &mut Vec<(u32, f32), N>) -> bool {
+        if policy.sensory == SensoryPolicy::EveryFrame || policy.ratio <= 1 {
+            return true;
+        }
+        if policy.sensory == SensoryPolicy::Aggregate {
+            for &(id, value) in data.iter() {
+                match self.peaks.iter_mut().find(|(peak_id, _)| *peak_id == id) {
+                    Some(peak) => peak.1 = peak.1.max(value),
+                    None => {
+                        let _ = self.peaks.push((id, value));
+                    }
+                }
+            }
+        }
+        self.samples += 1;
+        if self.samples < policy.ratio {
+            return false;
+        }
+        self.samples = 0;
+        if policy.sensory == SensoryPolicy::Aggregate {
+            data.clear();
+            for peak in self.peaks.iter() {
+                let _ = data.push(*peak);
+            }
+            self.peaks.clear();
+        }
+        true
+    }
+}
+
+impl<const N: usize> Default for SensoryWindow<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Leaves out unchanged channels under delta reporting
+pub struct DeltaFilter<const N: usize> {
+    /// Each channel's value as last sent
+    sent: Vec<(u32, f32), N>,
+    /// When the last keyframe went out; None = the next frame is one
+    last_keyframe_us: Option<i64>,
+}
+
+impl<const N: usize> DeltaFilter<N> {
+    pub fn new() -> Self {
+        Self { sent: Vec::new(), last_keyframe_us: None }
+    }
+
+    /// Make the next frame a keyframe (new session)
+    pub fn force_keyframe(&mut self) {
+        self.last_keyframe_us = None;
+    }
+
+    /// Strip the channels of a due frame that didn't change, unless it's
+    /// time for a keyframe
+    ///
+    /// Returns None with delta reporting off, else whether this frame is a
+    /// keyframe.
+    pub fn filter(&mut self, policy: &RatePolicy, data: &mut Vec<(u32, f32), N>, now_us: i64) -> Option<bool> {
+        if !policy.delta {
+            return None;
+        }
+        let keyframe = self
+            .last_keyframe_us
+            .map_or(true, |last| now_us - last >= policy.keyframe_ms as i64 * 1000);
+        if keyframe {
+            self.last_keyframe_us = Some(now_us);
+        }
+        let epsilon = policy.delta_epsilon;
+        let sent = &mut self.sent;
+        data.retain(|&(id, value)| match sent.iter_mut().find(|(sent_id, _)| *sent_id == id) {
+            Some(last) => {
+                let changed = value - last.1 > epsilon || last.1 - value > epsilon;
+                if changed || keyframe {
+                    last.1 = value;
+                }
+                changed || keyframe
+            }
+            None => {
+                let _ = sent.push((id, value));
+                true
+            }
+        });
+        Some(keyframe)
+    }
+}
+
+impl<const N: usize> Default for DeltaFilter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RatePolicy = RatePolicy {
+        motor: MotorPolicy::HoldLast,
+        sensory: SensoryPolicy::EveryFrame,
+        ratio: 1,
+        decay_after_ms: 200,
+        decay_ms: 300,
+        neutral: 0.0,
+        delta: false,
+        delta_epsilon: 0.01,
+        keyframe_ms: 1000,
+    };
+
+    fn samples(values: &[(u32, f32)]) -> Vec<(u32, f32), 4> {
+        Vec::from_slice(values).unwrap()
+    }
+
+    #[test]
+    fn negotiation() {
+        let policy = POLICY.negotiate("{\"policy\":{\"motor\":\"interp\",\"sensory\":\"aggregate\",\"ratio\":0,\"neutral\":2}}").unwrap();
+        assert_eq!((policy.motor, policy.sensory, policy.ratio, policy.neutral), (MotorPolicy::Interpolate, SensoryPolicy::Aggregate, 1, 1.0));
+        assert_eq!(policy.decay_ms, 300);
+        assert!(POLICY.negotiate("{\"policy\":{\"motor\":\"coast\"}}").is_none());
+        assert!(POLICY.negotiate("{\"mc\":[[1,0.5]]}").is_none());
+    }
+
+    #[test]
+    fn ack_line() {
+        let policy = RatePolicy { sensory: SensoryPolicy::Subsample, ratio: 3, delta: true, ..POLICY };
+        assert_eq!(policy.ack_line(), "{\"policy_ack\":{\"motor\":\"hold\",\"sensory\":\"subsample\",\"ratio\":3,\"delta\":true}}\n");
+    }
+
+    /// The last value driven on each neuron; neuron 9 is a DC motor
+    struct Driven(Vec<(u32, f32), 4>);
+
+    impl Outputs for Driven {
+        fn apply(&mut self, neuron_id: u32, value: f32) {
+            self.0.retain(|(id, _)| *id != neuron_id);
+            self.0.push((neuron_id, value)).unwrap();
+        }
+
+        fn motor_stop_value(&self, neuron_id: u32) -> Option<f32> {
+            (neuron_id == 9).then_some(0.5)
+        }
+    }
+
+    #[test]
+    fn interpolate_and_decay() {
+        let mut outputs = Driven(Vec::new());
+        let mut shaper: MotorShaper<4> = MotorShaper::new();
+        let interp = RatePolicy { motor: MotorPolicy::Interpolate, ..POLICY };
+        shaper.command(&interp, &mut outputs, 1, 0.0, 0);
+        // The next command ramps over the 100 ms between the two
+        shaper.command(&interp, &mut outputs, 1, 1.0, 100_000);
+        shaper.tick(&interp, &mut outputs, 150_000);
+        assert_eq!(&outputs.0[..], [(1, 0.5)]);
+        shaper.tick(&interp, &mut outputs, 300_000);
+        assert_eq!(&outputs.0[..], [(1, 1.0)]);
+
+        // 200 ms idle, then halfway through the 300 ms ramp to neutral (or
+        // the motor's stop value)
+        let decay = RatePolicy { motor: MotorPolicy::DecayToNeutral, ..POLICY };
+        let mut outputs = Driven(Vec::new());
+        let mut shaper: MotorShaper<4> = MotorShaper::new();
+        shaper.command(&decay, &mut outputs, 1, 1.0, 0);
+        shaper.command(&decay, &mut outputs, 9, 1.0, 0);
+        shaper.tick(&decay, &mut outputs, 199_000);
+        assert_eq!(&outputs.0[..], [(1, 1.0), (9, 1.0)]);
+        shaper.tick(&decay, &mut outputs, 350_000);
+        assert_eq!(&outputs.0[..], [(1, 0.5), (9, 0.75)]);
+    }
+
+    #[test]
+    fn subsample_and_aggregate() {
+        let mut window: SensoryWindow<4> = SensoryWindow::new();
+        let subsample = RatePolicy { sensory: SensoryPolicy::Subsample, ratio: 2, ..POLICY };
+        assert!(!window.push(&subsample, &mut samples(&[(1, 0.9)])));
+        let mut data = samples(&[(1, 0.1)]);
+        assert!(window.push(&subsample, &mut data));
+        assert_eq!(&data[..], [(1, 0.1)]);
+
+        let aggregate = RatePolicy { sensory: SensoryPolicy::Aggregate, ratio: 3, ..POLICY };
+        assert!(!window.push(&aggregate, &mut samples(&[(1, 0.2), (2, 0.0)])));
+        assert!(!window.push(&aggregate, &mut samples(&[(1, 0.9), (2, 0.1)])));
+        let mut data = samples(&[(1, 0.3), (2, 0.0)]);
+        assert!(window.push(&aggregate, &mut data));
+        assert_eq!(&data[..], [(1, 0.9), (2, 0.1)]);
+    }
+
+    #[test]
+    fn delta_frames() {
+        let mut filter: DeltaFilter<4> = DeltaFilter::new();
+        let delta = RatePolicy { delta: true, ..POLICY };
+        assert_eq!(filter.filter(&POLICY, &mut samples(&[(1, 0.5)]), 0), None);
+
+        let mut data = samples(&[(1, 0.5), (2, 0.5)]);
+        assert_eq!(filter.filter(&delta, &mut data, 0), Some(true));
+        assert_eq!(data.len(), 2);
+        // Moves within epsilon are left out, and don't creep up on it
+        let mut data = samples(&[(1, 0.505), (2, 0.6)]);
+        assert_eq!(filter.filter(&delta, &mut data, 100_000), Some(false));
+        assert_eq!(&data[..], [(2, 0.6)]);
+        let mut data = samples(&[(1, 0.512), (2, 0.6)]);
+        assert_eq!(filter.filter(&delta, &mut data, 200_000), Some(false));
+        assert_eq!(&data[..], [(1, 0.512)]);
+        // Every channel in a keyframe
+        let mut data = samples(&[(1, 0.512), (2, 0.6)]);
+        assert_eq!(filter.filter(&delta, &mut data, 1_000_000), Some(true));
+        assert_eq!(data.len(), 2);
+        filter.force_keyframe();
+        assert_eq!(filter.filter(&delta, &mut samples(&[(1, 0.512)]), 1_100_000), Some(true));
+    }
+}
@@ -7,6 +7,12 @@ use std::fs;
 use std::path::PathBuf;
 
 fn main() {
+    // ESP-IDF link args and SoC capability cfgs (e.g. esp_idf_soc_temp_sensor_supported)
+    embuild::espidf::sysenv::output();
+    // Outputs are forced safe before ESP-IDF's panic handler runs (see
+    // src/failsafe.rs)
+    println!("cargo:rustc-link-arg=-Wl,--wrap=esp_panic_handler");
+    
     // Tell cargo to rerun this script if config.json changes
     println!("cargo:rerun-if-changed=config.json");
     
@@ -46,19 +52,1887 @@ fn main() {
         .and_then(|v| v.as_str())
         .unwrap_or("serial");
     
+    // Echo applied output values back to FEAGI as a proprioceptive channel
+    let output_echo = config.get("output_echo")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    
+    // Every cortical_mapping names a flat neuron ID or a voxel of a cortical
+    // area; the areas with voxels are numbered in order (see src/cortical.rs)
+    let mut cortical_areas: Vec<String> = Vec::new();
+    check_cortical_mappings(&config, &mut cortical_areas);
+    if cortical_areas.len() > 128 {
+        panic!("at most 128 cortical areas can be mapped by coordinates (got {})", cortical_areas.len());
+    }
+    
+    // Sensory frames and motor commands as JSON lines or binary packets
+    // (see src/protocol.rs)
+    let wire_format = config.get("wire_format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("json");
+    match wire_format {
+        "json" => {}
+        // Packets carry 16-bit neuron IDs
+        "binary" => check_binary_neuron_ids(&config),
+        other => panic!("wire_format must be \"json\" or \"binary\" (got \"{}\")", other),
+    }
+    
+    // Heatshrink-compressed sensory lines, when the host asks for them (see
+    // ../controller-core/src/compress.rs)
+    let compression_code = config.get("compression").map(|c| {
+        let min_bytes = c.get("min_bytes").and_then(|v| v.as_u64()).unwrap_or(256);
+        if !(16..=65535).contains(&min_bytes) {
+            panic!("compression.min_bytes must be 16-65535 (got {})", min_bytes);
+        }
+        format!("CompressionConfig {{ min_bytes: {} }}", min_bytes)
+    });
+    
+    // Message framing on the serial link, primary or fallback (see ../controller-core/src/cobs.rs)
+    let serial_framing = match config.get("serial").and_then(|s| s.get("framing")).and_then(|v| v.as_str()) {
+        None | Some("raw") => "SerialFraming::Raw",
+        Some("cobs") => "SerialFraming::Cobs",
+        Some(other) => panic!("serial.framing must be \"raw\" or \"cobs\" (got \"{}\")", other),
+    };
+    
+    // Barrier-synchronized actuation for multi-board robots
+    let barrier = config.get("barrier");
+    let barrier_enabled = barrier
+        .and_then(|b| b.get("enabled"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let barrier_timeout_ms = barrier
+        .and_then(|b| b.get("timeout_ms"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(5);
+    
+    // Chip temperature and CPU load in the sensory frame
+    let board_health = config.get("telemetry")
+        .and_then(|t| t.get("board_health"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    
+    // Start and working time of every burst (see ../controller-core/src/profile.rs)
+    let profile = config.get("telemetry")
+        .and_then(|t| t.get("profile"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    
+    // Runtime metrics sent to the host (see src/metrics.rs)
+    let metrics_code = config.get("telemetry").and_then(|t| t.get("metrics")).map(|m| {
+        let interval_ms = m.get("interval_ms").and_then(|v| v.as_u64()).unwrap_or(5000);
+        if !(100..=3600000).contains(&interval_ms) {
+            panic!("telemetry.metrics.interval_ms must be 100-3600000 (got {})", interval_ms);
+        }
+        format!("MetricsConfig {{ interval_ms: {} }}", interval_ms)
+    });
+    
+    // Transport supervision (restart a wedged transport without rebooting)
+    let supervision = config.get("transport").and_then(|t| t.get("supervision"));
+    let supervision_u64 = |key: &str, default: u64| supervision
+        .and_then(|s| s.get(key))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(default);
+    let supervision_code = format!(
+        "SupervisionConfig {{ error_limit: {}, silence_ms: {}, max_restarts: {}, backoff_ms: {}, queue_frames: {}, failover_ms: {} }}",
+        supervision_u64("error_limit", 50),
+        supervision_u64("silence_ms", 0),
+        supervision_u64("max_restarts", 5),
+        supervision_u64("backoff_ms", 500),
+        supervision_u64("queue_frames", 8),
+        supervision_u64("failover_ms", 30000),
+    );
+    
+    // Task watchdog on the burst loop, on unless "enabled": false (see
+    // src/watchdog.rs)
+    let watchdog = config.get("watchdog");
+    let watchdog_code = watchdog
+        .and_then(|w| w.get("enabled"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+        .then(|| {
+            let timeout_ms = watchdog.and_then(|w| w.get("timeout_ms")).and_then(|v| v.as_u64()).unwrap_or(5000);
+            if !(1000..=60000).contains(&timeout_ms) {
+                panic!("watchdog.timeout_ms must be 1000-60000 (got {})", timeout_ms);
+            }
+            format!("WatchdogConfig {{ timeout_ms: {} }}", timeout_ms)
+        });
+    
+    // Outputs offered back to FEAGI after a software restart, when the block
+    // is there unless "enabled": false (see src/output_restore.rs)
+    let output_restore = config.get("output_restore");
+    let output_restore_code = output_restore
+        .filter(|r| r.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .map(|r| {
+            let timeout_ms = r.get("timeout_ms").and_then(|v| v.as_u64()).unwrap_or(10000);
+            if !(1000..=600000).contains(&timeout_ms) {
+                panic!("output_restore.timeout_ms must be 1000-600000 (got {})", timeout_ms);
+            }
+            format!("RestoreConfig {{ timeout_ms: {} }}", timeout_ms)
+        });
+    
+    // Transport I/O on its own task, on unless "enabled": false (see
+    // src/transport_task.rs)
+    let tasks = config.get("tasks");
+    let transport_task_code = tasks
+        .and_then(|t| t.get("enabled"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+        .then(|| {
+            let queue_bytes = tasks.and_then(|t| t.get("queue_bytes")).and_then(|v| v.as_u64()).unwrap_or(8192);
+            if !(512..=65536).contains(&queue_bytes) {
+                panic!("tasks.queue_bytes must be 512-65536 (got {})", queue_bytes);
+            }
+            let stack_size = tasks.and_then(|t| t.get("stack_size")).and_then(|v| v.as_u64()).unwrap_or(8192);
+            if !(4096..=32768).contains(&stack_size) {
+                panic!("tasks.stack_size must be 4096-32768 (got {})", stack_size);
+            }
+            format!("TransportTaskConfig {{ queue_bytes: {}, stack_size: {} }}", queue_bytes, stack_size)
+        });
+    
+    // Console log level, and the level forwarded to the host (see src/logger.rs)
+    let log = config.get("log");
+    let level_code = |level: &str| match level {
+        "error" => Some("Level::Error"),
+        "warn" => Some("Level::Warn"),
+        "info" => Some("Level::Info"),
+        "debug" => Some("Level::Debug"),
+        _ => None,
+    };
+    let log_level = log.and_then(|l| l.get("level")).and_then(|v| v.as_str()).unwrap_or("info");
+    let log_level_code = level_code(log_level)
+        .unwrap_or_else(|| panic!("log.level must be \"error\", \"warn\", \"info\" or \"debug\" (got \"{}\")", log_level));
+    let log_forward = log.and_then(|l| l.get("forward")).and_then(|v| v.as_str()).unwrap_or("off");
+    let log_forward_code = match log_forward {
+        "off" => "None".to_string(),
+        other => match level_code(other) {
+            Some(code) => format!("Some({})", code),
+            None => panic!("log.forward must be \"off\", \"error\", \"warn\", \"info\" or \"debug\" (got \"{}\")", other),
+        },
+    };
+    
+    // Heartbeats both ways; a silent host counts as gone after timeout_ms
+    let keepalive_code = config.get("transport").and_then(|t| t.get("keepalive")).map(|k| {
+        let interval_ms = k.get("interval_ms").and_then(|v| v.as_u64()).unwrap_or(1000);
+        let timeout_ms = k.get("timeout_ms").and_then(|v| v.as_u64()).unwrap_or(interval_ms * 3);
+        if interval_ms == 0 {
+            panic!("transport.keepalive.interval_ms must be greater than 0");
+        }
+        if timeout_ms <= interval_ms {
+            panic!("transport.keepalive.timeout_ms ({}) must be longer than interval_ms ({})", timeout_ms, interval_ms);
+        }
+        format!("KeepaliveConfig {{ interval_ms: {}, timeout_ms: {} }}", interval_ms, timeout_ms)
+    });
+    
+    // Ordered fallback transports, tried when the primary stays down
+    let fallback: Vec<&str> = config.get("transport")
+        .and_then(|t| t.get("fallback"))
+        .and_then(|v| v.as_array())
+        .map(|list| list.iter().map(|v| v.as_str().expect("transport.fallback entries must be strings")).collect())
+        .unwrap_or_default();
+    for (i, kind) in fallback.iter().enumerate() {
+        if *kind != "serial" {
+            panic!("transport.fallback: only \"serial\" is supported as a fallback (got \"{}\")", kind);
+        }
+        if *kind == transport_type || fallback[..i].contains(kind) {
+            panic!("transport.fallback: \"{}\" is already the primary or an earlier fallback", kind);
+        }
+    }
+    
+    // A "bluetooth" board with a `wifi` block accepts WiFi credentials over BLE
+    // and then switches to that WiFi transport (see src/ble.rs)
+    let transport_config = config.get("transport").and_then(|t| t.get("config"));
+    let ble_wifi = if transport_type == "bluetooth" {
+        transport_config.and_then(|c| c.get("wifi"))
+    } else {
+        None
+    };
+    // Wired Ethernet (W5500 on SPI) carries the same protocols, picked by
+    // `protocol` instead of the transport type (see src/ethernet.rs)
+    let ethernet = transport_type == "ethernet";
+    let (wifi_type, prefix, wifi) = match ble_wifi {
+        Some(w) => (w.get("type").and_then(|v| v.as_str()).unwrap_or("wifi"), "transport.config.wifi", Some(w)),
+        None if ethernet => {
+            let protocol = transport_config.and_then(|c| c.get("protocol")).and_then(|v| v.as_str()).unwrap_or("tcp");
+            let wifi_type = match protocol {
+                "tcp" => "wifi",
+                "udp" | "websocket" | "mqtt" | "zmq" => protocol,
+                _ => panic!("transport.config.protocol must be tcp, udp, websocket, mqtt or zmq (got \"{}\")", protocol),
+            };
+            (wifi_type, "transport.config", transport_config)
+        }
+        None => (transport_type, "transport.config", transport_config),
+    };
+    let transport_name = if ethernet { "ethernet" } else { wifi_type };
+    if ble_wifi.is_some() && !["wifi", "udp", "websocket", "mqtt", "zmq"].contains(&wifi_type) {
+        panic!("transport.config.wifi.type must be wifi, udp, websocket, mqtt or zmq (got \"{}\")", wifi_type);
+    }
+    
+    // Names the board in every line it sends, and picks out the motor
+    // messages meant for it on a shared link (see src/device_id.rs); an MQTT
+    // board's topic ID is the default when it qualifies
+    let valid_device_id = |id: &str| {
+        !id.is_empty() && id.len() <= 32 && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    };
+    let device_id = match config.get("device_id") {
+        Some(v) => v.as_str().unwrap_or(""),
+        None => wifi.filter(|_| wifi_type == "mqtt")
+            .and_then(|w| w.get("device_id"))
+            .and_then(|v| v.as_str())
+            .filter(|id| valid_device_id(id))
+            .unwrap_or("esp32"),
+    };
+    if !valid_device_id(device_id) {
+        panic!("device_id must be 1-32 letters, digits, '.', '-' or '_' (got \"{}\")", device_id);
+    }
+    
+    // WiFi station + FEAGI endpoint (transport "wifi" = TCP, "udp", "websocket", "mqtt", "zmq", see src/wifi.rs)
+    let wifi_code = if ["wifi", "udp", "websocket", "mqtt", "zmq"].contains(&wifi_type) {
+        let wifi_str = |key: &str| wifi.and_then(|w| w.get(key)).and_then(|v| v.as_str());
+        // Captive-portal provisioning: ssid/host/port may then come from NVS instead
+        let provisioning_code = match wifi.and_then(|w| w.get("provisioning")) {
+            Some(provisioning) => {
+                let ap_ssid = provisioning.get("ap_ssid").and_then(|v| v.as_str()).unwrap_or("FEAGI-Setup");
+                let ap_password = provisioning.get("ap_password").and_then(|v| v.as_str()).unwrap_or("");
+                if ap_ssid.is_empty() || ap_ssid.len() > 32 {
+                    panic!("{}.provisioning.ap_ssid must be 1-32 bytes", prefix);
+                }
+                if !ap_password.is_empty() && !(8..=63).contains(&ap_password.len()) {
+                    panic!("{}.provisioning.ap_password must be empty (open) or 8-63 bytes", prefix);
+                }
+                let button_pin = match provisioning.get("button_pin").and_then(|v| v.as_u64()) {
+                    Some(pin) if pin > 39 => panic!("{}.provisioning.button_pin: GPIO {} does not exist", prefix, pin),
+                    Some(pin) => format!("Some({})", pin),
+                    None => "None".to_string(),
+                };
+                Some(format!(
+                    "Some(ProvisioningConfig {{ ap_ssid: {:?}, ap_password: {:?}, button_pin: {} }})",
+                    ap_ssid, ap_password, button_pin
+                ))
+            }
+            None => None,
+        };
+        if provisioning_code.is_some() && ble_wifi.is_some() {
+            panic!("transport.config.wifi is provisioned over BLE; remove its provisioning block");
+        }
+        if provisioning_code.is_some() && ethernet {
+            panic!("transport.config.provisioning is for WiFi; set host and port for Ethernet");
+        }
+        let provisioned = provisioning_code.is_some() || ble_wifi.is_some();
+        let ssid = wifi_str("ssid")
+            .or(if provisioned || ethernet { Some("") } else { None })
+            .unwrap_or_else(|| panic!("transport \"{}\" requires {}.ssid", wifi_type, prefix));
+        let password = wifi_str("password").unwrap_or("");
+        if (ssid.is_empty() && !provisioned && !ethernet) || ssid.len() > 32 {
+            panic!("{}.ssid must be 1-32 bytes", prefix);
+        }
+        if password.len() > 64 {
+            panic!("{}.password must be at most 64 bytes", prefix);
+        }
+        // Without an ssid the portal always runs first, so host/port are placeholders
+        let endpoint_required = ethernet || !ssid.is_empty();
+        let host = wifi_str("host")
+            .or(if endpoint_required { None } else { Some("0.0.0.0") })
+            .unwrap_or_else(|| panic!("transport \"{}\" requires {}.host (FEAGI IPv4 address)", transport_name, prefix));
+        let octets: Vec<u8> = host.split('.').filter_map(|o| o.parse().ok()).collect();
+        if octets.len() != 4 || host.split('.').count() != 4 {
+            panic!("{}.host must be an IPv4 address (got \"{}\")", prefix, host);
+        }
+        let port = wifi.and_then(|w| w.get("port")).and_then(|v| v.as_u64())
+            .or(if wifi_type == "mqtt" { Some(1883) } else { None })
+            .or(if endpoint_required { None } else { Some(0) })
+            .unwrap_or_else(|| panic!("transport \"{}\" requires {}.port", transport_name, prefix));
+        if (port == 0 && endpoint_required) || port > 65535 {
+            panic!("{}.port must be 1-65535", prefix);
+        }
+        let connect_timeout_ms = wifi.and_then(|w| w.get("connect_timeout_ms")).and_then(|v| v.as_u64()).unwrap_or(10000);
+        // Optional TLS for the stream protocols; the CA certificate is embedded
+        let tls_code = match wifi.and_then(|w| w.get("tls")) {
+            Some(tls) => {
+                if !["wifi", "websocket", "mqtt"].contains(&wifi_type) {
+                    panic!("{}.tls is only supported for \"wifi\", \"websocket\" and \"mqtt\"", prefix);
+                }
+                let verify = tls.get("verify").and_then(|v| v.as_bool()).unwrap_or(true);
+                let ca_cert = if verify {
+                    let ca_path = tls.get("ca_cert").and_then(|v| v.as_str())
+                        .unwrap_or_else(|| panic!("{}.tls.ca_cert (PEM file) is required when verify is true", prefix));
+                    let ca_path = PathBuf::from(&manifest_dir).join(ca_path);
+                    println!("cargo:rerun-if-changed={}", ca_path.display());
+                    let mut pem = fs::read(&ca_path)
+                        .unwrap_or_else(|e| panic!("Failed to read {}.tls.ca_cert {}: {}", prefix, ca_path.display(), e));
+                    if !pem.starts_with(b"-----BEGIN CERTIFICATE-----") {
+                        panic!("{}.tls.ca_cert must be a PEM certificate", prefix);
+                    }
+                    // mbedTLS parses PEM only with the terminating NUL counted
+                    pem.push(0);
+                    fs::write(PathBuf::from(&out_dir).join("tls_ca.pem"), &pem).expect("Failed to write tls_ca.pem");
+                    "Some(include_bytes!(concat!(env!(\"OUT_DIR\"), \"/tls_ca.pem\")))".to_string()
+                } else {
+                    // esp-tls refuses to skip verification unless built with
+                    // the opt-in sdkconfig.insecure-tls fragment
+                    println!("cargo:rerun-if-env-changed=ESP_IDF_SDKCONFIG_DEFAULTS");
+                    let defaults = env::var("ESP_IDF_SDKCONFIG_DEFAULTS").unwrap_or_default();
+                    if !defaults.split(';').any(|f| f.trim().ends_with("sdkconfig.insecure-tls")) {
+                        panic!("{}.tls.verify: false needs ESP_IDF_SDKCONFIG_DEFAULTS=\"sdkconfig.defaults;sdkconfig.insecure-tls\"", prefix);
+                    }
+                    "None".to_string()
+                };
+                let server_name = match tls.get("server_name").and_then(|v| v.as_str()) {
+                    Some(name) => format!("Some({:?})", format!("{}\0", name)),
+                    None => "None".to_string(),
+                };
+                format!("Some(TlsConfig {{ ca_cert: {}, server_name: {} }})", ca_cert, server_name)
+            }
+            None => "None".to_string(),
+        };
+        // mDNS browse for FEAGI; host/port above are the fallback
+        let discover = wifi.and_then(|w| w.get("discover")).and_then(|v| v.as_bool()).unwrap_or(false);
+        if discover && wifi_type == "mqtt" {
+            panic!("{}.discover finds FEAGI, not an MQTT broker; set host instead", prefix);
+        }
+        let discovery_timeout_ms = if discover {
+            wifi.and_then(|w| w.get("discovery_timeout_ms")).and_then(|v| v.as_u64()).unwrap_or(3000).max(1)
+        } else {
+            0
+        };
+        let protocol = if wifi_type == "udp" {
+            // Motor commands arrive on this port (default: same as FEAGI's)
+            let local_port = wifi.and_then(|w| w.get("local_port")).and_then(|v| v.as_u64()).unwrap_or(port);
+            if local_port == 0 || local_port > 65535 {
+                panic!("{}.local_port must be 1-65535 (required when port is left to provisioning)", prefix);
+            }
+            format!("NetProtocol::Udp {{ local_port: {} }}", local_port)
+        } else if wifi_type == "websocket" {
+            let path = wifi_str("path").unwrap_or("/");
+            if !path.starts_with('/') || path.len() > 128 || path.chars().any(|c| !c.is_ascii_graphic()) {
+                panic!("{}.path must start with '/', be at most 128 bytes and contain no spaces (got \"{}\")", prefix, path);
+            }
+            let ping_interval_ms = wifi.and_then(|w| w.get("ping_interval_ms")).and_then(|v| v.as_u64()).unwrap_or(10000);
+            format!("NetProtocol::WebSocket {{ path: {:?}, ping_interval_ms: {} }}", path, ping_interval_ms)
+        } else if wifi_type == "mqtt" {
+            let device_id = wifi_str("device_id").unwrap_or_else(|| panic!("transport \"mqtt\" requires {}.device_id", prefix));
+            if device_id.is_empty() || device_id.len() > 64 || device_id.contains(['/', '+', '#']) {
+                panic!("{}.device_id must be 1-64 bytes without '/', '+' or '#' (got \"{}\")", prefix, device_id);
+            }
+            let status_topic = wifi_str("status_topic").map(String::from).unwrap_or_else(|| format!("feagi/{}/status", device_id));
+            if status_topic.is_empty() || status_topic.len() > 128 || status_topic.contains(['+', '#']) {
+                panic!("{}.status_topic must be 1-128 bytes without wildcards", prefix);
+            }
+            let qos = wifi.and_then(|w| w.get("qos")).and_then(|v| v.as_u64()).unwrap_or(0);
+            if qos > 1 {
+                panic!("{}.qos must be 0 or 1 (QoS 2 is not supported)", prefix);
+            }
+            let keep_alive_s = wifi.and_then(|w| w.get("keep_alive_s")).and_then(|v| v.as_u64()).unwrap_or(30);
+            if keep_alive_s > 65535 {
+                panic!("{}.keep_alive_s must be at most 65535", prefix);
+            }
+            let mqtt_username = wifi_str("mqtt_username");
+            let mqtt_password = wifi_str("mqtt_password");
+            if mqtt_username.map_or(0, str::len) > 64 || mqtt_password.map_or(0, str::len) > 64 {
+                panic!("{}.mqtt_username and mqtt_password must be at most 64 bytes", prefix);
+            }
+            format!(
+                "NetProtocol::Mqtt(MqttConfig {{ client_id: {:?}, sensory_topic: {:?}, motor_topic: {:?}, status_topic: {:?}, qos: {}, keep_alive_s: {}, username: {:?}, password: {:?} }})",
+                device_id, format!("feagi/{}/sensory", device_id), format!("feagi/{}/motor", device_id), status_topic,
+                qos, keep_alive_s, mqtt_username, mqtt_password
+            )
+        } else if wifi_type == "zmq" {
+            // `port` is FEAGI's sensory SUB endpoint, `motor_port` its motor PUB
+            let motor_port = wifi.and_then(|w| w.get("motor_port")).and_then(|v| v.as_u64())
+                .unwrap_or_else(|| panic!("transport \"zmq\" requires {}.motor_port", prefix));
+            if motor_port == 0 || motor_port > 65535 {
+                panic!("{}.motor_port must be 1-65535", prefix);
+            }
+            let subscribe = wifi_str("subscribe").unwrap_or("");
+            if subscribe.len() > 200 {
+                panic!("{}.subscribe must be at most 200 bytes", prefix);
+            }
+            format!("NetProtocol::Zmq {{ motor_port: {}, subscribe: {:?} }}", motor_port, subscribe)
+        } else {
+            "NetProtocol::Tcp".to_string()
+        };
+        // Fixed address for networks without DHCP
+        let ipv4 = |key: &str, value: Option<&serde_json::Value>| -> String {
+            let text = value.and_then(|v| v.as_str())
+                .unwrap_or_else(|| panic!("{}.static_ip.{} is required (IPv4 address)", prefix, key));
+            let octets: Vec<u8> = text.split('.').filter_map(|o| o.parse().ok()).collect();
+            if octets.len() != 4 || text.split('.').count() != 4 {
+                panic!("{}.static_ip.{} must be an IPv4 address (got \"{}\")", prefix, key, text);
+            }
+            format!("[{}, {}, {}, {}]", octets[0], octets[1], octets[2], octets[3])
+        };
+        let static_ip_code = match wifi.and_then(|w| w.get("static_ip")) {
+            Some(net) => {
+                let dns = match net.get("dns") {
+                    Some(dns) => format!("Some({})", ipv4("dns", Some(dns))),
+                    None => "None".to_string(),
+                };
+                format!(
+                    "Some(StaticIp {{ ip: {}, netmask: {}, gateway: {}, dns: {} }})",
+                    ipv4("ip", net.get("ip")), ipv4("netmask", net.get("netmask")), ipv4("gateway", net.get("gateway")), dns
+                )
+            }
+            None => "None".to_string(),
+        };
+        let hostname_code = match wifi_str("hostname") {
+            Some(name) => {
+                // RFC 1123 label
+                if name.is_empty() || name.len() > 32 || name.starts_with('-') || name.ends_with('-')
+                    || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    panic!("{}.hostname must be 1-32 letters, digits or '-', not starting or ending with '-' (got \"{}\")", prefix, name);
+                }
+                format!("Some({:?})", format!("{}\0", name))
+            }
+            None => "None".to_string(),
+        };
+        Some(format!(
+            "WifiConfig {{ ssid: {:?}, password: {:?}, host: [{}, {}, {}, {}], port: {}, protocol: {}, connect_timeout_ms: {}, discovery_timeout_ms: {}, tls: {}, provisioning: {}, static_ip: {}, hostname: {} }}",
+            ssid, password, octets[0], octets[1], octets[2], octets[3], port, protocol, connect_timeout_ms, discovery_timeout_ms, tls_code,
+            provisioning_code.unwrap_or_else(|| "None".to_string()), static_ip_code, hostname_code
+        ))
+    } else {
+        None
+    };
+    
+    // The endpoint above goes into EthernetConfig for "ethernet", plus the
+    // W5500's SPI wiring
+    let (wifi_code, network_code) = if ethernet { (None, wifi_code) } else { (wifi_code, None) };
+    let ethernet_code = network_code.map(|network| {
+        match transport_config.and_then(|c| c.get("chip")).and_then(|v| v.as_str()).unwrap_or("w5500") {
+            "w5500" => {}
+            "enc28j60" => panic!("transport.config.chip: ESP-IDF v5.1 has no built-in ENC28J60 driver, use a W5500"),
+            chip => panic!("transport.config.chip must be \"w5500\" (got \"{}\")", chip),
+        }
+        let spi = transport_config.and_then(|c| c.get("spi"))
+            .unwrap_or_else(|| panic!("transport \"ethernet\" requires transport.config.spi (W5500 pins)"));
+        let pin = |key: &str| -> Option<u64> {
+            match spi.get(key).and_then(|v| v.as_u64()) {
+                Some(pin) if pin > 39 => panic!("transport.config.spi.{}: GPIO {} does not exist", key, pin),
+                pin => pin,
+            }
+        };
+        let mut pins = Vec::new();
+        for key in ["sclk", "mosi", "miso", "cs", "int"] {
+            let gpio = pin(key).unwrap_or_else(|| panic!("transport.config.spi.{} is required", key));
+            // GPIO 34-39 are input-only
+            if gpio >= 34 && key != "miso" && key != "int" {
+                panic!("transport.config.spi.{}: GPIO {} is input-only", key, gpio);
+            }
+            pins.push(gpio);
+        }
+        let rst = pin("rst");
+        if let Some(gpio) = rst {
+            if gpio >= 34 {
+                panic!("transport.config.spi.rst: GPIO {} is input-only", gpio);
+            }
+            pins.push(gpio);
+        }
+        if (1..pins.len()).any(|i| pins[..i].contains(&pins[i])) {
+            panic!("transport.config.spi: sclk, mosi, miso, cs, int and rst must be different pins");
+        }
+        let host = spi.get("host").and_then(|v| v.as_u64()).unwrap_or(2);
+        if host != 2 && host != 3 {
+            panic!("transport.config.spi.host must be 2 (HSPI) or 3 (VSPI)");
+        }
+        // Through the GPIO matrix the ESP32 SPI master tops out around 40 MHz
+        let clock_mhz = spi.get("clock_mhz").and_then(|v| v.as_u64()).unwrap_or(20);
+        if clock_mhz == 0 || clock_mhz > 40 {
+            panic!("transport.config.spi.clock_mhz must be 1-40");
+        }
+        format!(
+            "EthernetConfig {{ spi: EthernetSpi {{ host: {}, sclk: {}, mosi: {}, miso: {}, cs: {}, int: {}, rst: {}, clock_mhz: {} }}, network: {} }}",
+            // spi_host_device_t: SPI2_HOST = 1, SPI3_HOST = 2
+            host - 1, pins[0], pins[1], pins[2], pins[3], pins[4],
+            rst.map_or("None".to_string(), |gpio| format!("Some({})", gpio)), clock_mhz, network
+        )
+    });
+    
+    // BLE Nordic UART Service (transport "bluetooth", see src/ble.rs)
+    let ble_code = if transport_type == "bluetooth" {
+        let device_name = transport_config
+            .and_then(|c| c.get("device_name")).and_then(|v| v.as_str()).unwrap_or("FEAGI-ESP32");
+        // Flags (3 bytes) + name header (2 bytes) + name must fit 31 bytes of advertising data
+        if device_name.is_empty() || device_name.len() > 26 {
+            panic!("transport.config.device_name must be 1-26 bytes (got \"{}\")", device_name);
+        }
+        // Held at boot: stay on BLE for new credentials instead of the stored ones
+        let button_pin = match transport_config.and_then(|c| c.get("button_pin")).and_then(|v| v.as_u64()) {
+            Some(_) if ble_wifi.is_none() => panic!("transport.config.button_pin needs a transport.config.wifi block"),
+            Some(pin) if pin > 39 => panic!("transport.config.button_pin: GPIO {} does not exist", pin),
+            Some(pin) => format!("Some({})", pin),
+            None => "None".to_string(),
+        };
+        Some(format!(
+            "BleConfig {{ device_name: {:?}, wifi_provisioning: {}, button_pin: {} }}",
+            device_name, ble_wifi.is_some(), button_pin
+        ))
+    } else {
+        None
+    };
+    
+    // Deep sleep and wake sources (event-driven embodiments, see src/sleep.rs)
+    const RTC_PINS: [u64; 18] = [0, 2, 4, 12, 13, 14, 15, 25, 26, 27, 32, 33, 34, 35, 36, 37, 38, 39];
+    const TOUCH_PINS: [u64; 10] = [4, 0, 2, 15, 13, 12, 14, 27, 33, 32];
+    let sleep_code = config.get("sleep").map(|sleep| {
+        let wake = sleep.get("wake");
+        let ext0 = match wake.and_then(|w| w.get("ext0")) {
+            Some(ext0) => {
+                let pin = ext0.get("pin").and_then(|v| v.as_u64()).expect("sleep.wake.ext0 requires a \"pin\"");
+                if !RTC_PINS.contains(&pin) {
+                    panic!("sleep.wake.ext0: GPIO {} is not an RTC GPIO", pin);
+                }
+                let high = match ext0.get("level").and_then(|v| v.as_str()).unwrap_or("high") {
+                    "high" => true,
+                    "low" => false,
+                    other => panic!("sleep.wake.ext0.level must be high or low (got \"{}\")", other),
+                };
+                format!("Some(({}, {}))", pin, high)
+            }
+            None => "None".to_string(),
+        };
+        let ext1 = match wake.and_then(|w| w.get("ext1")) {
+            Some(ext1) => {
+                let mut mask: u64 = 0;
+                for pin in ext1.get("pins").and_then(|v| v.as_array()).into_iter().flatten() {
+                    let pin = pin.as_u64().expect("sleep.wake.ext1.pins must be GPIO numbers");
+                    if !RTC_PINS.contains(&pin) {
+                        panic!("sleep.wake.ext1: GPIO {} is not an RTC GPIO", pin);
+                    }
+                    mask |= 1 << pin;
+                }
+                if mask == 0 {
+                    panic!("sleep.wake.ext1 requires at least one pin");
+                }
+                let any_high = match ext1.get("mode").and_then(|v| v.as_str()).unwrap_or("any_high") {
+                    "any_high" => true,
+                    "all_low" => false,
+                    other => panic!("sleep.wake.ext1.mode must be any_high or all_low (got \"{}\")", other),
+                };
+                format!("Some((0x{:x}, {}))", mask, any_high)
+            }
+            None => "None".to_string(),
+        };
+        let mut touch = String::new();
+        for pad in wake.and_then(|w| w.get("touch")).and_then(|v| v.as_array()).into_iter().flatten() {
+            let pin = pad.get("pin").and_then(|v| v.as_u64()).expect("sleep.wake.touch entries require a \"pin\"");
+            if !TOUCH_PINS.contains(&pin) {
+                panic!("sleep.wake.touch: GPIO {} is not a touch pad", pin);
+            }
+            let threshold = pad.get("threshold").and_then(|v| v.as_u64()).unwrap_or(400).min(u16::MAX as u64);
+            touch.push_str(&format!("TouchWake {{ pin: {}, threshold: {} }}, ", pin, threshold));
+        }
+        let mode = match sleep.get("mode").and_then(|v| v.as_str()).unwrap_or("deep") {
+            "deep" => "SleepMode::Deep",
+            "light" => "SleepMode::Light",
+            other => panic!("sleep.mode must be deep or light (got \"{}\")", other),
+        };
+        let mut gpio = String::new();
+        for wake in wake.and_then(|w| w.get("gpio")).and_then(|v| v.as_array()).into_iter().flatten() {
+            let pin = wake.get("pin").and_then(|v| v.as_u64()).expect("sleep.wake.gpio entries require a \"pin\"");
+            if pin > 39 {
+                panic!("sleep.wake.gpio: GPIO {} does not exist", pin);
+            }
+            let high = match wake.get("level").and_then(|v| v.as_str()).unwrap_or("low") {
+                "high" => true,
+                "low" => false,
+                other => panic!("sleep.wake.gpio.level must be high or low (got \"{}\")", other),
+            };
+            gpio.push_str(&format!("GpioWake {{ pin: {}, high: {} }}, ", pin, high));
+        }
+        format!(
+            "Some(SleepConfig {{ mode: {}, idle_ms: {}, ext0: {}, ext1: {}, touch: &[{}], gpio: &[{}], timer_ms: {} }})",
+            mode,
+            sleep.get("idle_ms").and_then(|v| v.as_u64()).unwrap_or(0),
+            ext0,
+            ext1,
+            touch,
+            gpio,
+            wake.and_then(|w| w.get("timer_ms")).and_then(|v| v.as_u64()).unwrap_or(0),
+        )
+    });
+    
+    // Default burst-rate mismatch policies (renegotiable at handshake)
+    let rate_policy = config.get("rate_policy");
+    let policy_str = |key: &str, default: &'static str| rate_policy
+        .and_then(|p| p.get(key))
+        .and_then(|v| v.as_str())
+        .unwrap_or(default)
+        .to_string();
+    let policy_u64 = |key: &str, default: u64| rate_policy
+        .and_then(|p| p.get(key))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(default);
+    let motor_policy = match policy_str("motor", "hold").as_str() {
+        "hold" => "MotorPolicy::HoldLast",
+        "decay" => "MotorPolicy::DecayToNeutral",
+        "interp" => "MotorPolicy::Interpolate",
+        other => panic!("rate_policy.motor must be hold, decay or interp (got \"{}\")", other),
+    };
+    let sensory_policy = match policy_str("sensory", "every").as_str() {
+        "every" => "SensoryPolicy::EveryFrame",
+        "subsample" => "SensoryPolicy::Subsample",
+        "aggregate" => "SensoryPolicy::Aggregate",
+        other => panic!("rate_policy.sensory must be every, subsample or aggregate (got \"{}\")", other),
+    };
+    let policy_neutral = rate_policy
+        .and_then(|p| p.get("neutral"))
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let delta_epsilon = rate_policy
+        .and_then(|p| p.get("delta_epsilon"))
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.01);
+    if !(0.0..=1.0).contains(&delta_epsilon) {
+        panic!("rate_policy.delta_epsilon must be 0.0-1.0 (got {})", delta_epsilon);
+    }
+    let rate_policy_code = format!(
+        "RatePolicy {{ motor: {}, sensory: {}, ratio: {}, decay_after_ms: {}, decay_ms: {}, neutral: {:?}, delta: {}, delta_epsilon: {:?}, keyframe_ms: {} }}",
+        motor_policy,
+        sensory_policy,
+        policy_u64("ratio", 1).max(1),
+        policy_u64("decay_after_ms", 200),
+        policy_u64("decay_ms", 500),
+        policy_neutral as f32,
+        rate_policy.and_then(|p| p.get("delta")).and_then(|v| v.as_bool()).unwrap_or(false),
+        delta_epsilon as f32,
+        policy_u64("keyframe_ms", 1000).max(1),
+    );
+    
+    // On-device HTTP status/control endpoint (see src/status_server.rs)
+    let status_server_port = config.get("status_server")
+        .filter(|s| s.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .map(|s| {
+            if wifi_code.is_none() && ethernet_code.is_none() {
+                panic!("status_server needs a WiFi-based or ethernet transport (or a bluetooth transport with a wifi block)");
+            }
+            let port = s.get("port").and_then(|v| v.as_u64()).unwrap_or(80);
+            if port == 0 || port > 65535 {
+                panic!("status_server.port must be 1-65535 (got {})", port);
+            }
+            port
+        });
+    
+    // Signed firmware updates over the network (see src/ota.rs); the
+    // public key is embedded
+    let ota_code = config.get("ota")
+        .filter(|o| o.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .map(|o| {
+            if wifi_code.is_none() && ethernet_code.is_none() {
+                panic!("ota needs a WiFi-based or ethernet transport (or a bluetooth transport with a wifi block)");
+            }
+            let port = o.get("port").and_then(|v| v.as_u64()).unwrap_or(8080);
+            if port == 0 || port > 65535 {
+                panic!("ota.port must be 1-65535 (got {})", port);
+            }
+            if status_server_port == Some(port) {
+                panic!("ota.port must differ from status_server.port ({})", port);
+            }
+            let key_path = o.get("public_key").and_then(|v| v.as_str())
+                .unwrap_or_else(|| panic!("ota.public_key (PEM file) is required"));
+            let key_path = PathBuf::from(&manifest_dir).join(key_path);
+            println!("cargo:rerun-if-changed={}", key_path.display());
+            let mut pem = fs::read(&key_path)
+                .unwrap_or_else(|e| panic!("Failed to read ota.public_key {}: {}", key_path.display(), e));
+            if !pem.starts_with(b"-----BEGIN PUBLIC KEY-----") {
+                panic!("ota.public_key must be a PEM public key (openssl ec -pubout or rsa -pubout)");
+            }
+            // mbedTLS parses PEM only with the terminating NUL counted
+            pem.push(0);
+            fs::write(PathBuf::from(&out_dir).join("ota_key.pem"), &pem).expect("Failed to write ota_key.pem");
+            format!(
+                "OtaConfig {{ port: {}, public_key: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ota_key.pem\")) }}",
+                port
+            )
+        });
+    
+    // WiFi link quality as a sensory channel (see src/link_telemetry.rs)
+    let link_telemetry_code = config.get("telemetry").and_then(|t| t.get("link")).map(|link| {
+        if wifi_code.is_none() {
+            panic!("telemetry.link needs a WiFi-based transport (or a bluetooth transport with a wifi block)");
+        }
+        let mapping = link.get("cortical_mapping").and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("telemetry.link.cortical_mapping is required (\"cortical_area:neuron_id\")"));
+        // The RSSI neuron; re-sent share and disconnects take the next two
+        if !names_neuron(mapping) {
+            panic!("telemetry.link.cortical_mapping must end in a neuron ID or coordinates (got \"{}\")", mapping);
+        }
+        format!("LinkTelemetryConfig {{ cortical_mapping: {:?} }}", mapping)
+    });
+    
+    // SNTP time sync; frames then carry their sample time (see src/time_sync.rs)
+    let time_sync_code = config.get("time_sync")
+        .filter(|t| t.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .map(|t| {
+            if wifi_code.is_none() && ethernet_code.is_none() {
+                panic!("time_sync needs a WiFi-based or ethernet transport (or a bluetooth transport with a wifi block)");
+            }
+            let servers: Vec<String> = match t.get("servers").and_then(|v| v.as_array()) {
+                Some(list) => list.iter()
+                    .map(|v| v.as_str().expect("time_sync.servers entries must be strings").to_string())
+                    .collect(),
+                None => vec!["pool.ntp.org".to_string()],
+            };
+            // CONFIG_LWIP_SNTP_MAX_SERVERS in sdkconfig.defaults
+            if servers.is_empty() || servers.len() > 3 {
+                panic!("time_sync.servers must list 1-3 servers");
+            }
+            for server in &servers {
+                if server.is_empty() || server.len() > 64 || !server.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-') {
+                    panic!("time_sync.servers: \"{}\" is not a host name or IPv4 address", server);
+                }
+            }
+            // lwIP refuses to poll more often than every 15 s
+            let sync_interval_s = t.get("sync_interval_s").and_then(|v| v.as_u64()).unwrap_or(3600);
+            if !(15..=86400).contains(&sync_interval_s) {
+                panic!("time_sync.sync_interval_s must be 15-86400");
+            }
+            let servers: Vec<String> = servers.iter().map(|s| format!("{:?}", format!("{}\0", s))).collect();
+            format!(
+                "TimeSyncConfig {{ servers: &[{}], sync_interval_ms: {} }}",
+                servers.join(", "), sync_interval_s * 1000
+            )
+        });
+    
+    // Pre-shared key for authenticated link encryption (64 hex characters)
+    let link_psk = config.get("link_encryption")
+        .and_then(|e| e.get("psk"))
+        .and_then(|v| v.as_str())
+        .map(|hex| {
+            if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                panic!("link_encryption.psk must be 64 hex characters (32-byte key)");
+            }
+            (0..32)
+                .map(|i| format!("0x{}", &hex[i * 2..i * 2 + 2]))
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+    
+    // I2C bus and polled devices (round-robin scheduler, see src/i2c.rs)
+    let i2c = config.get("i2c");
+    let i2c_bus = i2c.map(|bus| {
+        let sda = bus.get("sda").and_then(|v| v.as_u64()).unwrap_or(21);
+        let scl = bus.get("scl").and_then(|v| v.as_u64()).unwrap_or(22);
+        let freq_hz = bus.get("freq_hz").and_then(|v| v.as_u64()).unwrap_or(400_000);
+        // Default: a quarter of the burst period
+        let burst_budget_us = bus.get("burst_budget_us")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(250_000 / burst_frequency.max(1));
+        format!(
+            "Some(I2cBusConfig {{ sda: {}, scl: {}, freq_hz: {}, burst_budget_us: {} }})",
+            sda, scl, freq_hz, burst_budget_us
+        )
+    });
+    // Register map presets: (default address, register, len, format, offset,
+    // full scale, init writes)
+    let i2c_preset = |name: &str| -> (u64, u64, u64, &'static str, u64, u64, &'static str) {
+        match name {
+            // Benewake TF-Luna lidar: distance in cm (up to 8 m)
+            "tfluna" => (0x10, 0x00, 2, "u16le", 0, 800, "&[]"),
+            // Vishay VEML7700 ambient light: ALS counts; powered on at gain 1, 100 ms
+            "veml7700" => (0x10, 0x04, 2, "u16le", 0, 65535, "&[(0, &[0, 0])]"),
+            // NXP PCF8591 ADC: AIN0-3 with auto-increment; the first byte is
+            // the previous conversion
+            "pcf8591" => (0x48, 0x04, 5, "u8", 1, 255, "&[]"),
+            other => panic!("i2c device: unknown \"preset\" \"{}\" (tfluna, veml7700, pcf8591)", other),
+        }
+    };
+    let mut i2c_devices = Vec::new();
+    let mut i2c_sensory_channels = 0;
+    for device in i2c.and_then(|b| b.get("devices")).and_then(|v| v.as_array()).into_iter().flatten() {
+        let preset = device.get("preset").and_then(|v| v.as_str()).map(|name| i2c_preset(name));
+        let address = device.get("address")
+            .and_then(|v| v.as_u64())
+            .or(preset.map(|p| p.0))
+            .expect("i2c device requires an \"address\" (or a \"preset\")");
+        if address > 0x7f {
+            panic!("i2c device address 0x{:x} is not a 7-bit address", address);
+        }
+        let register = device.get("register").and_then(|v| v.as_u64()).or(preset.map(|p| p.1)).unwrap_or(0);
+        let len = device.get("len").and_then(|v| v.as_u64()).or(preset.map(|p| p.2)).unwrap_or(1);
+        if len == 0 || len > 32 {
+            panic!("i2c device 0x{:x}: \"len\" must be 1-32", address);
+        }
+        let rate_hz = device.get("rate_hz").and_then(|v| v.as_u64()).unwrap_or(burst_frequency);
+        let budget_us = device.get("budget_us").and_then(|v| v.as_u64()).unwrap_or(500);
+        
+        // Setup writes: [[register, [byte, ...]], ...]
+        let init = match device.get("init").and_then(|v| v.as_array()) {
+            Some(writes) => {
+                let writes: Vec<String> = writes.iter().map(|write| {
+                    let (register, data) = write.as_array()
+                        .filter(|w| w.len() == 2)
+                        .and_then(|w| Some((w[0].as_u64()?, w[1].as_array()?)))
+                        .unwrap_or_else(|| panic!("i2c device 0x{:x}: \"init\" entries are [register, [bytes]]", address));
+                    let bytes: Vec<String> = data.iter()
+                        .map(|b| b.as_u64().filter(|&b| b <= 0xff).map(|b| b.to_string()))
+                        .collect::<Option<_>>()
+                        .unwrap_or_else(|| panic!("i2c device 0x{:x}: \"init\" data must be bytes", address));
+                    if register > 0xff || bytes.is_empty() || bytes.len() > 8 {
+                        panic!("i2c device 0x{:x}: \"init\" writes are one register and 1-8 bytes", address);
+                    }
+                    format!("({}, &[{}])", register, bytes.join(", "))
+                }).collect();
+                format!("&[{}]", writes.join(", "))
+            }
+            None => preset.map_or("&[]", |p| p.6).to_string(),
+        };
+        
+        // Sensory mapping: the block split into `format` values from `offset`
+        let channels = match device.get("cortical_mapping").and_then(|v| v.as_str()) {
+            Some(mapping) => {
+                if !names_neuron(mapping) {
+                    panic!("i2c device 0x{:x}: \"cortical_mapping\" must end in a neuron ID or coordinates, got \"{}\"", address, mapping);
+                }
+                let format = device.get("format").and_then(|v| v.as_str()).or(preset.map(|p| p.3)).unwrap_or("u8");
+                let (variant, size, max) = match format {
+                    "u8" => ("U8", 1, 0xff),
+                    "u16le" => ("U16Le", 2, 0xffff),
+                    "u16be" => ("U16Be", 2, 0xffff),
+                    "i16le" => ("I16Le", 2, 0x7fff),
+                    "i16be" => ("I16Be", 2, 0x7fff),
+                    other => panic!("i2c device 0x{:x}: \"format\" must be u8, u16le, u16be, i16le or i16be, got \"{}\"", address, other),
+                };
+                let offset = device.get("offset").and_then(|v| v.as_u64()).or(preset.map(|p| p.4)).unwrap_or(0);
+                if offset >= len || (len - offset) % size != 0 {
+                    panic!("i2c device 0x{:x}: \"len\" minus \"offset\" must be a whole number of {} values", address, format);
+                }
+                let full_scale = device.get("full_scale").and_then(|v| v.as_u64()).or(preset.map(|p| p.5)).unwrap_or(max);
+                if full_scale == 0 || full_scale > max {
+                    panic!("i2c device 0x{:x}: \"full_scale\" must be 1-{} for {}", address, max, format);
+                }
+                i2c_sensory_channels += ((len - offset) / size) as usize;
+                format!(
+                    "Some(I2cChannels {{ format: I2cFormat::{}, offset: {}, full_scale: {}, cortical_mapping: \"{}\" }})",
+                    variant, offset, full_scale, mapping
+                )
+            }
+            None => "None".to_string(),
+        };
+        i2c_devices.push(format!(
+            "    I2cDeviceConfig {{ address: {}, register: {}, len: {}, rate_hz: {}, budget_us: {}, init: {}, channels: {} }},\n",
+            address, register, len, rate_hz, budget_us, init, channels
+        ));
+    }
+    // Accelerometer/gyroscope on the same bus (see src/imu.rs)
+    let imu = i2c.and_then(|b| b.get("imu")).map(|imu| {
+        let chip = match imu.get("chip").and_then(|v| v.as_str()) {
+            Some("mpu6050") => "ImuChip::Mpu6050",
+            Some("icm20948") => "ImuChip::Icm20948",
+            other => panic!("i2c imu: \"chip\" must be \"mpu6050\" or \"icm20948\", got {:?}", other),
+        };
+        let default_address = if chip == "ImuChip::Mpu6050" { 0x68 } else { 0x69 };
+        let address = imu.get("address").and_then(|v| v.as_u64()).unwrap_or(default_address);
+        if address != 0x68 && address != 0x69 {
+            panic!("i2c imu: \"address\" must be 0x68 or 0x69 (104 or 105), got 0x{:x}", address);
+        }
+        let rate_hz = imu.get("rate_hz").and_then(|v| v.as_u64()).unwrap_or(burst_frequency);
+        let budget_us = imu.get("budget_us").and_then(|v| v.as_u64()).unwrap_or(600);
+        let accel_range_g = imu.get("accel_range_g").and_then(|v| v.as_u64()).unwrap_or(4);
+        if ![2, 4, 8, 16].contains(&accel_range_g) {
+            panic!("i2c imu: \"accel_range_g\" must be 2, 4, 8 or 16, got {}", accel_range_g);
+        }
+        let gyro_range_dps = imu.get("gyro_range_dps").and_then(|v| v.as_u64()).unwrap_or(500);
+        if ![250, 500, 1000, 2000].contains(&gyro_range_dps) {
+            panic!("i2c imu: \"gyro_range_dps\" must be 250, 500, 1000 or 2000, got {}", gyro_range_dps);
+        }
+        let cortical_mapping = imu.get("cortical_mapping")
+            .and_then(|v| v.as_str())
+            .expect("i2c imu requires a \"cortical_mapping\"");
+        if !names_neuron(cortical_mapping) {
+            panic!("i2c imu: \"cortical_mapping\" must end in a neuron ID or coordinates, got \"{}\"", cortical_mapping);
+        }
+        format!(
+            "Some(ImuConfig {{ chip: {}, address: {}, rate_hz: {}, budget_us: {}, accel_range_g: {}, gyro_range_dps: {}, cortical_mapping: \"{}\" }})",
+            chip, address, rate_hz, budget_us, accel_range_g, gyro_range_dps, cortical_mapping
+        )
+    });
+    // The IMU is polled by the same scheduler
+    if i2c_devices.len() + imu.is_some() as usize > 16 {
+        panic!("at most 16 i2c devices are supported (including the imu)");
+    }
+    // GPIO expanders: blocks of virtual pins from 100 up (see src/expander.rs)
+    // as (chip, address, first_pin, pin count)
+    let mut expanders: Vec<(&str, u64, u64, u64)> = Vec::new();
+    for (index, expander) in i2c.and_then(|b| b.get("expanders")).and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        let (chip, pins) = match expander.get("chip").and_then(|v| v.as_str()) {
+            Some("mcp23017") => ("ExpanderChip::Mcp23017", 16),
+            Some("pcf8574") => ("ExpanderChip::Pcf8574", 8),
+            other => panic!("i2c expander: \"chip\" must be \"mcp23017\" or \"pcf8574\", got {:?}", other),
+        };
+        let address = expander.get("address").and_then(|v| v.as_u64()).unwrap_or(0x20);
+        // PCF8574A parts answer at 0x38-0x3f
+        let valid_address = (0x20..=0x27).contains(&address)
+            || (chip == "ExpanderChip::Pcf8574" && (0x38..=0x3f).contains(&address));
+        if !valid_address {
+            panic!("i2c expander: \"address\" must be 0x20-0x27{}, got 0x{:x}",
+                if pins == 8 { " (or 0x38-0x3f)" } else { "" }, address);
+        }
+        let first_pin = expander.get("first_pin").and_then(|v| v.as_u64()).unwrap_or(100 + 16 * index as u64);
+        if first_pin < 100 {
+            panic!("i2c expander 0x{:x}: \"first_pin\" must be 100 or more (0-39 are the chip's own GPIOs)", address);
+        }
+        for &(_, other_address, other_first, other_pins) in &expanders {
+            if other_address == address {
+                panic!("i2c expander 0x{:x} is configured twice", address);
+            }
+            if first_pin < other_first + other_pins && other_first < first_pin + pins {
+                panic!("i2c expander 0x{:x}: pins {}-{} overlap expander 0x{:x}", address, first_pin, first_pin + pins - 1, other_address);
+            }
+        }
+        if i2c_devices.iter().any(|d| d.contains(&format!("address: {},", address))) {
+            panic!("i2c expander 0x{:x}: address is also an i2c device", address);
+        }
+        expanders.push((chip, address, first_pin, pins));
+    }
+    if expanders.len() > 8 {
+        panic!("at most 8 i2c expanders are supported");
+    }
+    let expander_of = |pin: u64| expanders.iter().find(|&&(_, _, first, pins)| pin >= first && pin < first + pins);
+    
     // Generate GPIO configuration (same as standalone)
+    let no_gpio = Vec::new();
     let gpio_config = config.get("gpio")
         .and_then(|v| v.as_array())
-        .unwrap_or(&vec![]);
+        .unwrap_or(&no_gpio);
+    
+    // The serial transport's UART, primary or fallback (see SerialLink in
+    // src/transport.rs): UART0 on the USB bridge's GPIO1/3 by default
+    let serial = config.get("serial");
+    let serial_u64 = |key: &str| serial.and_then(|s| s.get(key)).and_then(|v| v.as_u64());
+    let serial_port = serial_u64("port").unwrap_or(0);
+    // UART1's default pins are taken by the SPI flash
+    let (default_tx, default_rx) = match serial_port {
+        0 => (Some(1), Some(3)),
+        1 => (None, None),
+        2 => (Some(17), Some(16)),
+        port => panic!("serial.port must be 0, 1 or 2 (got {})", port),
+    };
+    let serial_pin = |key: &str, default: Option<u64>| -> Option<u64> {
+        let pin = serial_u64(key).or(default)?;
+        if pin > 39 {
+            panic!("serial.{}: GPIO {} does not exist", key, pin);
+        }
+        // GPIO 34-39 are input-only
+        if pin >= 34 && (key == "tx_pin" || key == "rts_pin") {
+            panic!("serial.{}: GPIO {} is input-only", key, pin);
+        }
+        if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(pin)) {
+            panic!("serial.{}: GPIO {} is configured as a pin of its own", key, pin);
+        }
+        Some(pin)
+    };
+    let serial_tx = serial_pin("tx_pin", default_tx)
+        .unwrap_or_else(|| panic!("serial.port 1 requires serial.tx_pin and serial.rx_pin"));
+    let serial_rx = serial_pin("rx_pin", default_rx)
+        .unwrap_or_else(|| panic!("serial.port 1 requires serial.tx_pin and serial.rx_pin"));
+    // RTS/CTS hardware flow control, each optional
+    let serial_rts = serial_pin("rts_pin", None);
+    let serial_cts = serial_pin("cts_pin", None);
+    let serial_pins: Vec<u64> = [Some(serial_tx), Some(serial_rx), serial_rts, serial_cts].into_iter().flatten().collect();
+    if (1..serial_pins.len()).any(|i| serial_pins[..i].contains(&serial_pins[i])) {
+        panic!("serial: tx_pin, rx_pin, rts_pin and cts_pin must be different pins");
+    }
+    let serial_baud_rate = serial_u64("baud_rate").unwrap_or(115200);
+    if !(1200..=5000000).contains(&serial_baud_rate) {
+        panic!("serial.baud_rate must be 1200-5000000 (got {})", serial_baud_rate);
+    }
+    // Driver ring buffer, which holds what arrives between burst loop reads
+    let serial_rx_buffer_bytes = serial_u64("rx_buffer_bytes").unwrap_or(4096);
+    if !(256..=32768).contains(&serial_rx_buffer_bytes) {
+        panic!("serial.rx_buffer_bytes must be 256-32768 (got {})", serial_rx_buffer_bytes);
+    }
+    let serial_code = format!(
+        "SerialConfig {{ port: {}, tx_pin: {}, rx_pin: {}, baud_rate: {}, rts_pin: {:?}, cts_pin: {:?}, rx_buffer_bytes: {} }}",
+        serial_port, serial_tx, serial_rx, serial_baud_rate, serial_rts, serial_cts, serial_rx_buffer_bytes
+    );
+    
+    // Hall-effect and die temperature sensors of the chip (see src/onboard.rs)
+    let onboard = config.get("onboard_sensors");
+    let onboard_mapping = |sensor: &str| onboard.and_then(|o| o.get(sensor)).map(|s| {
+        let mapping = s.get("cortical_mapping").and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("onboard_sensors.{}.cortical_mapping is required (\"cortical_area:neuron_id\")", sensor));
+        if !names_neuron(mapping) {
+            panic!("onboard_sensors.{}.cortical_mapping must end in a neuron ID or coordinates (got \"{}\")", sensor, mapping);
+        }
+        mapping.to_string()
+    });
+    let hall_mapping = onboard_mapping("hall");
+    let temperature_mapping = onboard_mapping("temperature");
+    let hall_full_scale = onboard.and_then(|o| o.get("hall")).and_then(|h| h.get("full_scale"))
+        .and_then(|v| v.as_u64()).unwrap_or(300);
+    if hall_full_scale == 0 || hall_full_scale > 4095 {
+        panic!("onboard_sensors.hall.full_scale must be 1-4095 (got {})", hall_full_scale);
+    }
+    if hall_mapping.is_some() {
+        // The sensor is read through SENSOR_VP/SENSOR_VN
+        let used = gpio_config.iter().any(|g| {
+            [g.get("pin"), g.get("feedback").and_then(|f| f.get("pin")),
+             g.get("encoder").and_then(|e| e.get("pin_b")), g.get("ultrasonic").and_then(|u| u.get("echo_pin"))]
+                .iter()
+                .any(|p| matches!(p.and_then(|v| v.as_u64()), Some(36) | Some(39)))
+        });
+        if used {
+            panic!("onboard_sensors.hall needs GPIO 36 and 39, which are configured as pins");
+        }
+    }
+    
+    // Differential-drive odometry from two wheel encoders (see src/odometry.rs)
+    let odometry = config.get("odometry").map(|od| {
+        let wheel = |key: &str| {
+            let pin = od.get(key)
+                .and_then(|v| v.as_u64())
+                .unwrap_or_else(|| panic!("odometry.{} is required (the wheel encoder's pin)", key));
+            let is_encoder = gpio_config.iter().any(|g| {
+                g.get("pin").and_then(|v| v.as_u64()) == Some(pin)
+                    && g.get("mode").and_then(|v| v.as_str()) == Some("encoder_input")
+            });
+            if !is_encoder {
+                panic!("odometry.{}: GPIO {} is not an encoder_input pin", key, pin);
+            }
+            pin
+        };
+        let (left_pin, right_pin) = (wheel("left"), wheel("right"));
+        if left_pin == right_pin {
+            panic!("odometry.left and odometry.right must be different encoders");
+        }
+        let length = |key: &str| {
+            let mm = od.get(key)
+                .and_then(|v| v.as_f64())
+                .unwrap_or_else(|| panic!("odometry.{} is required (mm)", key));
+            if mm <= 0.0 {
+                panic!("odometry.{} must be positive", key);
+            }
+            mm as f32
+        };
+        let flag = |key: &str| match od.get(key) {
+            None => false,
+            Some(v) => v.as_bool().unwrap_or_else(|| panic!("odometry.{} must be true or false", key)),
+        };
+        format!(
+            "Some(OdometryConfig {{ left_pin: {}, right_pin: {}, wheel_diameter_mm: {:?}, track_width_mm: {:?}, invert_left: {}, invert_right: {} }})",
+            left_pin, right_pin, length("wheel_diameter_mm"), length("track_width_mm"), flag("invert_left"), flag("invert_right")
+        )
+    });
+    
+    // OV2640 camera as a vision input (see src/camera.rs)
+    let camera = config.get("camera").map(|cam| {
+        // Every pin the module wires to the camera connector
+        let (board, pins) = camera_board(cam);
+        for g in gpio_config {
+            if let Some(pin) = g.get("pin").and_then(|v| v.as_u64()).filter(|p| pins.contains(p)) {
+                panic!("gpio {}: wired to the camera on this board", pin);
+            }
+        }
+        for (key, default) in [("sda", 21), ("scl", 22)] {
+            let bus_pin = i2c.map(|bus| bus.get(key).and_then(|v| v.as_u64()).unwrap_or(default));
+            if let Some(pin) = bus_pin.filter(|p| pins.contains(p)) {
+                panic!("i2c.{}: GPIO {} is wired to the camera on this board", key, pin);
+            }
+        }
+        let cortical_mapping = cam.get("cortical_mapping")
+            .and_then(|v| v.as_str())
+            .expect("camera requires a \"cortical_mapping\" (first pixel's neuron)");
+        // "vis" lines name the first pixel by its flat neuron ID
+        if let Some(Mapping::Voxel(..)) = parse_mapping(cortical_mapping) {
+            panic!("camera.cortical_mapping must be \"area:N\", not coordinates (got \"{}\")", cortical_mapping);
+        }
+        // Downscaled from 160x120; each pixel is two hex digits of the line
+        let width = cam.get("width").and_then(|v| v.as_u64()).unwrap_or(32);
+        let height = cam.get("height").and_then(|v| v.as_u64()).unwrap_or(24);
+        if !(1..=160).contains(&width) || !(1..=120).contains(&height) {
+            panic!("camera.width must be 1-160 and camera.height 1-120");
+        }
+        if width * height > 1024 {
+            panic!("camera frames are limited to 1024 pixels (e.g. 32x32), got {}x{}", width, height);
+        }
+        // Default: about 10 frames per second
+        let decimation = cam.get("decimation").and_then(|v| v.as_u64()).unwrap_or((burst_frequency / 10).max(1));
+        if decimation == 0 {
+            panic!("camera.decimation must be at least 1 (one frame every N bursts)");
+        }
+        (
+            format!(
+                "Some(CameraConfig {{ board: {}, cortical_mapping: \"{}\", width: {}, height: {}, decimation: {} }})",
+                board, cortical_mapping, width, height, decimation
+            ),
+            (width * height) as usize,
+        )
+    });
+    
+    // GPIOs raw mode may hand to the host (see src/raw_io.rs): the user
+    // GPIOs, but not the strapping pins (0, 2, 5, 12, 15; GPIO 2 is also the
+    // devkit LED) nor any pin the config wires to something
+    let mut claimed = vec![serial_tx, serial_rx];
+    claimed.extend(serial_rts.into_iter().chain(serial_cts));
+    claimed_pins(&config, &mut claimed);
+    if let Some(bus) = i2c {
+        claimed.extend([("sda", 21), ("scl", 22)].map(|(key, default)| bus.get(key).and_then(|v| v.as_u64()).unwrap_or(default)));
+    }
+    if let Some(cam) = config.get("camera") {
+        claimed.extend(camera_board(cam).1);
+    }
+    let raw_pins: Vec<u64> = [4, 13, 14, 16, 17, 18, 19, 21, 22, 23, 25, 26, 27, 32, 33]
+        .into_iter()
+        .filter(|pin| !claimed.contains(pin))
+        .collect();
+    
+    // I2S MEMS microphone as an auditory input (see src/microphone.rs)
+    let microphone = config.get("microphone").map(|mic| {
+        let pin = |key: &str| {
+            let pin = mic.get(key)
+                .and_then(|v| v.as_u64())
+                .unwrap_or_else(|| panic!("microphone.{} is required (GPIO number)", key));
+            // SCK and WS are clocked out by the board
+            if pin > 39 || (key != "sd" && pin >= 34) {
+                panic!("microphone.{}: GPIO {} can't be used (SCK and WS need an output-capable GPIO 0-33)", key, pin);
+            }
+            if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(pin)) {
+                panic!("microphone.{}: GPIO {} is configured as a pin of its own", key, pin);
+            }
+            pin
+        };
+        let (sck, ws, sd) = (pin("sck"), pin("ws"), pin("sd"));
+        if sck == ws || sck == sd || ws == sd {
+            panic!("microphone.sck, ws and sd must be three different GPIOs");
+        }
+        let right_channel = match mic.get("channel").and_then(|v| v.as_str()) {
+            None | Some("left") => false,
+            Some("right") => true,
+            Some(other) => panic!("microphone.channel must be \"left\" or \"right\", got \"{}\"", other),
+        };
+        let sample_rate_hz = mic.get("sample_rate_hz").and_then(|v| v.as_u64()).unwrap_or(16_000);
+        if !(8_000..=48_000).contains(&sample_rate_hz) {
+            panic!("microphone.sample_rate_hz must be 8000-48000");
+        }
+        let bands = mic.get("bands").and_then(|v| v.as_u64()).unwrap_or(0);
+        if bands > 8 {
+            panic!("microphone.bands must be 0-8");
+        }
+        let floor_db = mic.get("floor_db").and_then(|v| v.as_f64()).unwrap_or(-70.0);
+        if !(-120.0..=-10.0).contains(&floor_db) {
+            panic!("microphone.floor_db must be between -120 and -10 (dB below full scale)");
+        }
+        let cortical_mapping = mic.get("cortical_mapping")
+            .and_then(|v| v.as_str())
+            .expect("microphone requires a \"cortical_mapping\" (sound level neuron)");
+        (
+            format!(
+                "Some(MicrophoneConfig {{ sck: {}, ws: {}, sd: {}, right_channel: {}, sample_rate_hz: {}, bands: {}, floor_db: {:?}, cortical_mapping: \"{}\" }})",
+                sck, ws, sd, right_channel, sample_rate_hz, bands, floor_db as f32, cortical_mapping
+            ),
+            1 + bands as usize,
+        )
+    });
+    
+    // Tones played on motor commands (see src/audio.rs)
+    let audio_output = config.get("audio_output").map(|audio| {
+        let pin = |key: &str| {
+            let pin = audio.get(key)
+                .and_then(|v| v.as_u64())
+                .unwrap_or_else(|| panic!("audio_output.{} is required (GPIO number)", key));
+            if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(pin)) {
+                panic!("audio_output.{}: GPIO {} is configured as a pin of its own", key, pin);
+            }
+            let mic_pins = ["sck", "ws", "sd"].map(|k| config.get("microphone").and_then(|m| m.get(k)).and_then(|v| v.as_u64()));
+            if mic_pins.contains(&Some(pin)) {
+                panic!("audio_output.{}: GPIO {} is wired to the microphone", key, pin);
+            }
+            pin
+        };
+        // I2S0 is taken by the camera and by the DAC's DMA; the ESP32 has two
+        let driver = match audio.get("driver").and_then(|v| v.as_str()) {
+            None | Some("i2s") => {
+                if camera.is_some() && microphone.is_some() {
+                    panic!("audio_output: no I2S controller left (the camera and the microphone take both)");
+                }
+                let (bck, ws, dout) = (pin("bck"), pin("ws"), pin("dout"));
+                for (key, p) in [("bck", bck), ("ws", ws), ("dout", dout)] {
+                    if p >= 34 {
+                        panic!("audio_output.{}: GPIO {} is input-only", key, p);
+                    }
+                }
+                if bck == ws || bck == dout || ws == dout {
+                    panic!("audio_output.bck, ws and dout must be three different GPIOs");
+                }
+                format!("AudioDriver::I2s {{ bck: {}, ws: {}, dout: {} }}", bck, ws, dout)
+            }
+            Some("dac") => {
+                if camera.is_some() {
+                    panic!("audio_output.driver \"dac\" can't be used with a camera (both need I2S0)");
+                }
+                let dac_pin = pin("pin");
+                if dac_pin != 25 && dac_pin != 26 {
+                    panic!("audio_output.pin must be GPIO 25 or 26 (the DAC outputs), got {}", dac_pin);
+                }
+                format!("AudioDriver::Dac {{ pin: {} }}", dac_pin)
+            }
+            Some(other) => panic!("audio_output.driver must be \"i2s\" or \"dac\", got \"{}\"", other),
+        };
+        let sample_rate_hz = audio.get("sample_rate_hz").and_then(|v| v.as_u64()).unwrap_or(16_000);
+        if !(8_000..=48_000).contains(&sample_rate_hz) {
+            panic!("audio_output.sample_rate_hz must be 8000-48000");
+        }
+        // DMA holds two burst periods, at most 32 buffers of 256 samples
+        if 2 * sample_rate_hz / burst_frequency.max(1) > 32 * 256 {
+            panic!("audio_output: {} Hz needs more buffering than fits at {} bursts per second; lower sample_rate_hz or raise burst_frequency", sample_rate_hz, burst_frequency);
+        }
+        let volume = audio.get("volume").and_then(|v| v.as_f64()).unwrap_or(0.5);
+        if !(0.0..=1.0).contains(&volume) {
+            panic!("audio_output.volume must be 0.0-1.0");
+        }
+        let cortical_mapping = audio.get("cortical_mapping")
+            .and_then(|v| v.as_str())
+            .expect("audio_output requires a \"cortical_mapping\" (tone 0's neuron)");
+        let tones = audio.get("tones")
+            .and_then(|v| v.as_array())
+            .filter(|t| (1..=16).contains(&t.len()))
+            .expect("audio_output.tones must be a list of 1-16 tones");
+        let mut sample_bytes = 0;
+        let mut tones_code = String::from("&[");
+        for (i, tone) in tones.iter().enumerate() {
+            let repeat = match tone.get("repeat") {
+                None => false,
+                Some(v) => v.as_bool().unwrap_or_else(|| panic!("audio_output.tones[{}].repeat must be true or false", i)),
+            };
+            let (notes, sample) = match (tone.get("notes"), tone.get("sample").and_then(|v| v.as_str())) {
+                (Some(notes), None) => {
+                    let notes = notes.as_array()
+                        .filter(|n| (1..=32).contains(&n.len()))
+                        .unwrap_or_else(|| panic!("audio_output.tones[{}].notes must be a list of 1-32 [frequency_hz, ms] notes", i));
+                    let notes: Vec<String> = notes.iter().map(|note| {
+                        let pair = note.as_array().filter(|p| p.len() == 2).map(|p| (p[0].as_u64(), p[1].as_u64()));
+                        match pair {
+                            Some((Some(freq), Some(ms))) if (freq == 0 || (20..=20_000).contains(&freq)) && (1..=10_000).contains(&ms) => {
+                                format!("({}, {})", freq, ms)
+                            }
+                            _ => panic!("audio_output.tones[{}].notes: each note must be [frequency_hz, ms] with frequency 20-20000 (0 = rest) and 1-10000 ms", i),
+                        }
+                    }).collect();
+                    (format!("&[{}]", notes.join(", ")), "None".to_string())
+                }
+                (None, Some(path)) => {
+                    // Raw unsigned 8-bit mono PCM, embedded in flash
+                    let sample_path = PathBuf::from(&manifest_dir).join(path);
+                    println!("cargo:rerun-if-changed={}", sample_path.display());
+                    let pcm = fs::read(&sample_path)
+                        .unwrap_or_else(|e| panic!("Failed to read audio_output.tones[{}].sample {}: {}", i, sample_path.display(), e));
+                    if pcm.is_empty() {
+                        panic!("audio_output.tones[{}].sample is empty", i);
+                    }
+                    sample_bytes += pcm.len();
+                    let name = format!("audio_tone_{}.raw", i);
+                    fs::write(PathBuf::from(&out_dir).join(&name), &pcm).expect("Failed to write audio sample");
+                    ("&[]".to_string(), format!("Some(include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\")))", name))
+                }
+                _ => panic!("audio_output.tones[{}] needs either \"notes\" or \"sample\" (a raw 8-bit PCM file)", i),
+            };
+            tones_code.push_str(&format!("Tone {{ notes: {}, sample: {}, repeat: {} }}, ", notes, sample, repeat));
+        }
+        tones_code.push(']');
+        if sample_bytes > 256 * 1024 {
+            panic!("audio_output samples are limited to 256 KB in total, got {} bytes", sample_bytes);
+        }
+        (
+            format!(
+                "Some(AudioConfig {{ driver: {}, sample_rate_hz: {}, volume: {:?}, cortical_mapping: \"{}\", tones: {} }})",
+                driver, sample_rate_hz, volume as f32, cortical_mapping, tones_code
+            ),
+            tones.len(),
+        )
+    });
+    
+    // Battery voltage/current and the low-battery safe-stop (see src/power.rs)
+    let power_monitor = config.get("power_monitor").map(|power| {
+        let number = |key: &str, default: Option<f64>| {
+            power.get(key)
+                .map(|v| v.as_f64().unwrap_or_else(|| panic!("power_monitor.{} must be a number", key)))
+                .or(default)
+                .unwrap_or_else(|| panic!("power_monitor.{} is required", key))
+        };
+        let (source, channels) = match power.get("source").and_then(|v| v.as_str()) {
+            None | Some("adc") => {
+                let pin = power.get("pin")
+                    .and_then(|v| v.as_u64())
+                    .expect("power_monitor.pin is required (ADC1 GPIO of the divider)");
+                if !(32..=39).contains(&pin) {
+                    panic!("power_monitor.pin must be an ADC1 pin (GPIO 32-39), got {}", pin);
+                }
+                if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(pin)) {
+                    panic!("power_monitor.pin: GPIO {} is configured as a pin of its own", pin);
+                }
+                // Battery volts per volt at the pin, e.g. 2.0 for two equal resistors
+                let divider = number("divider", Some(1.0));
+                if divider < 1.0 {
+                    panic!("power_monitor.divider must be at least 1.0 (battery volts per volt at the pin)");
+                }
+                (format!("PowerSource::Adc {{ pin: {}, divider: {:?} }}", pin, divider as f32), 1)
+            }
+            Some("ina219") => {
+                if i2c_bus.is_none() {
+                    panic!("power_monitor.source \"ina219\" requires an \"i2c\" bus");
+                }
+                let address = power.get("address").and_then(|v| v.as_u64()).unwrap_or(0x40);
+                if !(0x40..=0x4F).contains(&address) {
+                    panic!("power_monitor.address must be 0x40-0x4f (64-79), got 0x{:x}", address);
+                }
+                let shunt_ohms = number("shunt_ohms", Some(0.1));
+                if shunt_ohms <= 0.0 {
+                    panic!("power_monitor.shunt_ohms must be positive");
+                }
+                (format!("PowerSource::Ina219 {{ address: {}, shunt_ohms: {:?} }}", address, shunt_ohms as f32), 2)
+            }
+            Some(other) => panic!("power_monitor.source must be \"adc\" or \"ina219\", got \"{}\"", other),
+        };
+        let (min_voltage, max_voltage) = (number("min_voltage", None), number("max_voltage", None));
+        if !(0.0..max_voltage).contains(&min_voltage) || max_voltage > 32.0 {
+            panic!("power_monitor: need 0 <= min_voltage < max_voltage <= 32 (V)");
+        }
+        let max_current = number("max_current", Some(3.2));
+        if max_current <= 0.0 {
+            panic!("power_monitor.max_current must be positive (A)");
+        }
+        let low_voltage = match power.get("low_voltage") {
+            None => "None".to_string(),
+            Some(v) => {
+                let low = v.as_f64().expect("power_monitor.low_voltage must be a number (V)");
+                if !(min_voltage..max_voltage).contains(&low) {
+                    panic!("power_monitor.low_voltage must be between min_voltage and max_voltage");
+                }
+                format!("Some({:?})", low as f32)
+            }
+        };
+        let low_hold_ms = power.get("low_hold_ms").and_then(|v| v.as_u64()).unwrap_or(2000);
+        let cortical_mapping = power.get("cortical_mapping")
+            .and_then(|v| v.as_str())
+            .expect("power_monitor requires a \"cortical_mapping\" (voltage neuron)");
+        (
+            format!(
+                "Some(PowerConfig {{ source: {}, min_voltage: {:?}, max_voltage: {:?}, max_current: {:?}, cortical_mapping: \"{}\", low_voltage: {}, low_hold_ms: {} }})",
+                source, min_voltage as f32, max_voltage as f32, max_current as f32, cortical_mapping, low_voltage, low_hold_ms
+            ),
+            channels,
+        )
+    });
+    
+    // Buffer capacities derived from the channel counts (const generics in the
+    // firmware), so bigger robots get bigger frames and small ones save RAM
+    let count_mode = |modes: &[&str]| gpio_config.iter()
+        .filter(|g| g.get("mode").and_then(|v| v.as_str()).map_or(false, |m| modes.contains(&m)))
+        .count();
+    let encoders = count_mode(&["encoder_input"]);
+    if encoders > 8 {
+        panic!("at most 8 encoder inputs are supported (one pulse counter unit each)");
+    }
+    if count_mode(&["ultrasonic_input"]) > 8 {
+        panic!("at most 8 ultrasonic inputs are supported");
+    }
+    let dht_sensors = count_mode(&["dht_input"]);
+    if dht_sensors > 4 {
+        panic!("at most 4 DHT22 inputs are supported");
+    }
+    // Encoders report position and velocity, DHT22s temperature and
+    // humidity; mapped I2C devices add a channel per value, an IMU six
+    // (accel X/Y/Z, gyro X/Y/Z), a microphone its level and bands, a power
+    // monitor its voltage (and current)
+    // Plain digital pins can be switched between input and output at runtime
+    // (gpio.<pin>.mode, see stored_config.rs), so they get a slot either way
+    let plain_digital = |mode: &str| gpio_config.iter()
+        .filter(|g| g.get("mode").and_then(|v| v.as_str()) == Some(mode) && g.get("population").is_none())
+        .count();
+    let sensory_channels = count_mode(&["digital_input", "analog_input", "touch_input", "ultrasonic_input"])
+        + plain_digital("digital_output")
+        + encoders * 2
+        + dht_sensors * 2
+        + i2c_sensory_channels
+        + if imu.is_some() { 6 } else { 0 }
+        + microphone.as_ref().map_or(0, |(_, channels)| *channels)
+        + power_monitor.as_ref().map_or(0, |(_, channels)| *channels)
+        + hall_mapping.is_some() as usize
+        + temperature_mapping.is_some() as usize;
+    let output_channels = count_mode(&["digital_output", "pwm_output", "servo_output", "dc_motor", "stepper_output"])
+        + plain_digital("digital_input");
+    if count_mode(&["stepper_output"]) > 4 {
+        panic!("at most 4 stepper outputs are supported (one step timer slot each)");
+    }
+    if count_mode(&["led_strip"]) > 4 {
+        panic!("at most 4 LED strips are supported (one RMT channel each)");
+    }
+    let feedback_channels = gpio_config.iter().filter(|g| g.get("feedback").is_some()).count();
+    // System-identification runs sample the servo feedback, encoder position
+    // and velocity and IMU axes; over serial, their lines have to fit the baud
+    // rate: "{"sy":[<t_us>" then ",0.123" for the excitation and each
+    // response, "]}\n", plus the device ID and framing
+    let sysid_responses = feedback_channels + 2 * encoders + if imu.is_some() { 6 } else { 0 };
+    let sysid_line_bytes = 20 + 6 * (1 + sysid_responses);
+    let serial_sysid_hz = serial_baud_rate / 10 / (sysid_line_bytes as u64 + 48);
+    let sysid_over_serial = transport_type == "serial" || fallback.contains(&"serial");
+    let sysid_rate_hz = match config.get("sysid").and_then(|s| s.get("rate_hz")) {
+        None if sysid_over_serial => serial_sysid_hz.clamp(1, 100),
+        None => 100,
+        Some(v) => {
+            let rate_hz = v.as_u64()
+                .filter(|r| (1..=1000).contains(r))
+                .unwrap_or_else(|| panic!("sysid.rate_hz must be 1-1000"));
+            if sysid_over_serial && rate_hz > serial_sysid_hz {
+                panic!(
+                    "sysid.rate_hz {}: {}-byte samples fit {} Hz at serial.baud_rate {}",
+                    rate_hz, sysid_line_bytes, serial_sysid_hz, serial_baud_rate
+                );
+            }
+            rate_hz
+        }
+    };
+    let edge_inputs = gpio_config.iter()
+        .filter(|g| g.get("interrupt").and_then(|v| v.as_bool()).unwrap_or(false))
+        .count();
+    if edge_inputs > 16 {
+        panic!("at most 16 interrupt-driven digital inputs are supported");
+    }
+    // Population outputs take motor commands for every neuron in their range,
+    // so staging/shaping buffers need a slot per neuron
+    let population_neurons: usize = gpio_config.iter()
+        .filter_map(|g| g.get("population"))
+        .map(|p| {
+            let first = p.get("first").and_then(|v| v.as_u64()).unwrap_or(0);
+            let last = p.get("last").and_then(|v| v.as_u64()).unwrap_or(first);
+            // Beyond the slot the output already has
+            last.saturating_sub(first) as usize
+        })
+        .sum();
+    
+    // Worst case per tuple: "[4294967295,1.000]," = 19 bytes, or
+    // "[["<area>",1023,255,63],1.000]," with cortical coordinates
+    let tuple_bytes = cortical_areas.iter().map(|area| area.len() + 26).max().unwrap_or(0).max(19);
+    // {"np":[ ... ] plus ,"kf":1,"f":<u64>}\n (the device ID is added on
+    // the way out, see LINE_CAPACITY)
+    // ,"tc":-40.0,"cpu":1.000 when board health telemetry is on
+    // ,"t":<unix ms> when time sync is on, ,"wl":[...] with link telemetry,
+    // ,"ec":[...] with interrupt-driven inputs, ,"od":{...} with odometry
+    let mut frame_bytes = 8 + sensory_channels * tuple_bytes + 36 + if board_health { 24 } else { 0 }
+        + if time_sync_code.is_some() { 20 } else { 0 }
+        + if link_telemetry_code.is_some() { 8 + 3 * tuple_bytes } else { 0 };
+    if output_echo {
+        frame_bytes += 8 + output_channels * tuple_bytes;
+    }
+    if feedback_channels > 0 {
+        frame_bytes += 8 + feedback_channels * tuple_bytes;
+    }
+    if edge_inputs > 0 {
+        // Counts run to 10 digits: "[4294967295,4294967295]," = 24 bytes
+        frame_bytes += 8 + edge_inputs * (tuple_bytes + 5);
+    }
+    if odometry.is_some() {
+        // ,"od":{"x":..,"y":..,"th":..,"d":..,"dth":..} with 12-byte values
+        frame_bytes += 8 + 5 * 18;
+    }
+    // Round up to 64 bytes, with room for console-sized lines at minimum
+    let frame_capacity = ((frame_bytes + 63) / 64 * 64).max(256);
+    
+    let buffers = config.get("buffers");
+    let max_frame_bytes = buffers
+        .and_then(|b| b.get("max_frame_bytes"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(4096) as usize;
+    if frame_capacity > max_frame_bytes {
+        panic!(
+            "{} inputs, {} outputs and {} feedback channels need {}-byte frames, \
+             more than buffers.max_frame_bytes ({})",
+            sensory_channels, output_channels, feedback_channels, frame_capacity, max_frame_bytes
+        );
+    }
+    let rx_line_capacity = buffers
+        .and_then(|b| b.get("max_rx_line_bytes"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1024) as usize;
+    if rx_line_capacity < 128 {
+        panic!("buffers.max_rx_line_bytes must be at least 128");
+    }
+    // Bytes taken from the transport per read; the rest waits for the next
+    let rx_read_capacity = buffers
+        .and_then(|b| b.get("rx_read_bytes"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(512) as usize;
+    if !(64..=rx_line_capacity).contains(&rx_read_capacity) {
+        panic!("buffers.rx_read_bytes must be 64-{} (buffers.max_rx_line_bytes)", rx_line_capacity);
+    }
+    // Frames held while the transport is down, at the full frame size each
+    let frame_queue_capacity = (supervision_u64("queue_frames", 8) as usize * frame_capacity).max(1);
+    // {"get_config":1} answer (Settings::dump), field by field at the widest
+    // each value gets: numbers at 10 digits, strings set at runtime at their
+    // longest. A field is `,"<key>":{"v":<value>,"src":"runtime"}`
+    const NUM: usize = 10;
+    const BOOL: usize = 5;
+    const UNIT: usize = 5;
+    // "-4294967.295" and "-429496729.5"
+    const MILLI: usize = 12;
+    const DECIMAL: usize = 12;
+    let text = |len: usize| len + 2;
+    let mapping = |section: Option<&serde_json::Value>| text(section
+        .and_then(|s| s.get("cortical_mapping"))
+        .and_then(|v| v.as_str())
+        .map_or(0, str::len));
+    let mut fields: Vec<(&str, usize)> = vec![
+        ("device_id", text(32)),
+        ("transport", text(transport_type.len())),
+        ("mode", text(5)),
+        ("transport.supervision.error_limit", NUM),
+        ("transport.supervision.silence_ms", NUM),
+        ("transport.supervision.max_restarts", NUM),
+        ("transport.supervision.queue_frames", NUM),
+        ("transport.supervision.failover_ms", NUM),
+    ];
+    if keepalive_code.is_some() {
+        fields.extend([("transport.keepalive.interval_ms", NUM), ("transport.keepalive.timeout_ms", NUM)]);
+    }
+    if wifi_code.is_some() || ethernet_code.is_some() {
+        fields.extend([("transport.host", text(15)), ("transport.port", NUM)]);
+    }
+    fields.extend([
+        ("burst_frequency", NUM),
+        ("wire_format", text(wire_format.len())),
+        ("serial.framing", text(4)),
+        ("serial.port", NUM),
+        ("serial.tx_pin", NUM),
+        ("serial.rx_pin", NUM),
+        ("serial.baud_rate", NUM),
+        ("serial.rts_pin", NUM),
+        ("serial.cts_pin", NUM),
+        ("serial.rx_buffer_bytes", NUM),
+        ("output_echo", BOOL),
+        ("barrier.enabled", BOOL),
+        ("barrier.timeout_ms", NUM),
+        ("log.level", text(5)),
+        ("log.forward", text(5)),
+        ("sysid.rate_hz", NUM),
+        ("telemetry.board_health", BOOL),
+        ("telemetry.profile", BOOL),
+        ("telemetry.metrics.interval_ms", NUM),
+        ("link_encryption.enabled", BOOL),
+        ("watchdog.timeout_ms", NUM),
+        ("output_restore.timeout_ms", NUM),
+        ("tasks.enabled", BOOL),
+        ("tasks.queue_bytes", NUM),
+        ("rate_policy.motor", text(6)),
+        ("rate_policy.sensory", text(9)),
+        ("rate_policy.ratio", NUM),
+        ("rate_policy.decay_after_ms", NUM),
+        ("rate_policy.decay_ms", NUM),
+        ("rate_policy.neutral", UNIT),
+        ("rate_policy.delta", BOOL),
+        ("rate_policy.delta_epsilon", UNIT),
+        ("rate_policy.keyframe_ms", NUM),
+        ("compression.min_bytes", NUM),
+        ("buffers.frame_bytes", NUM),
+        ("buffers.rx_line_bytes", NUM),
+    ]);
+    if sleep_code.is_some() {
+        fields.extend([("sleep.mode", text(5)), ("sleep.idle_ms", NUM), ("sleep.timer_ms", NUM)]);
+    }
+    if i2c_bus.is_some() {
+        fields.extend([
+            ("i2c.sda", NUM),
+            ("i2c.scl", NUM),
+            ("i2c.freq_hz", NUM),
+            ("i2c.burst_budget_us", NUM),
+            ("i2c.devices", NUM),
+            ("i2c.expanders", NUM),
+        ]);
+    }
+    if imu.is_some() {
+        fields.extend([
+            ("i2c.imu.chip", text(8)),
+            ("i2c.imu.address", NUM),
+            ("i2c.imu.rate_hz", NUM),
+            ("i2c.imu.accel_range_g", NUM),
+            ("i2c.imu.gyro_range_dps", NUM),
+            ("i2c.imu.cortical_mapping", mapping(i2c.and_then(|b| b.get("imu")))),
+        ]);
+    }
+    if let Some(ref hall) = hall_mapping {
+        fields.extend([("onboard_sensors.hall.cortical_mapping", text(hall.len())), ("onboard_sensors.hall.full_scale", NUM)]);
+    }
+    if let Some(ref temperature) = temperature_mapping {
+        fields.push(("onboard_sensors.temperature.cortical_mapping", text(temperature.len())));
+    }
+    if camera.is_some() {
+        fields.extend([
+            ("camera.board", text(10)),
+            ("camera.cortical_mapping", mapping(config.get("camera"))),
+            ("camera.width", NUM),
+            ("camera.height", NUM),
+            ("camera.decimation", NUM),
+        ]);
+    }
+    if microphone.is_some() {
+        fields.extend([
+            ("microphone.sck", NUM),
+            ("microphone.ws", NUM),
+            ("microphone.sd", NUM),
+            ("microphone.channel", text(5)),
+            ("microphone.sample_rate_hz", NUM),
+            ("microphone.bands", NUM),
+            ("microphone.floor_db", DECIMAL),
+            ("microphone.cortical_mapping", mapping(config.get("microphone"))),
+        ]);
+    }
+    if audio_output.is_some() {
+        fields.extend([
+            ("audio_output.driver", text(3)),
+            ("audio_output.bck", NUM),
+            ("audio_output.ws", NUM),
+            ("audio_output.dout", NUM),
+            ("audio_output.sample_rate_hz", NUM),
+            ("audio_output.volume", UNIT),
+            ("audio_output.cortical_mapping", mapping(config.get("audio_output"))),
+            ("audio_output.tones", NUM),
+        ]);
+    }
+    if power_monitor.is_some() {
+        fields.extend([
+            ("power_monitor.source", text(6)),
+            ("power_monitor.address", NUM),
+            ("power_monitor.shunt_ohms", MILLI),
+            ("power_monitor.min_voltage", MILLI),
+            ("power_monitor.max_voltage", MILLI),
+            ("power_monitor.max_current", MILLI),
+            ("power_monitor.cortical_mapping", mapping(config.get("power_monitor"))),
+            ("power_monitor.low_voltage", MILLI),
+            ("power_monitor.low_hold_ms", NUM),
+        ]);
+    }
+    if odometry.is_some() {
+        fields.extend([
+            ("odometry.left", NUM),
+            ("odometry.right", NUM),
+            ("odometry.wheel_diameter_mm", DECIMAL),
+            ("odometry.track_width_mm", DECIMAL),
+            ("odometry.invert_left", BOOL),
+            ("odometry.invert_right", BOOL),
+        ]);
+    }
+    let field_bytes: usize = fields.iter().map(|(key, value)| key.len() + value + 26).sum();
+    // A GPIO entry holds the sections its mode has (digital pins may be
+    // stored as the other direction, so they get the input filter either
+    // way), a stored mapping of up to 32 characters and its src
+    let gpio_bytes: usize = gpio_config.iter()
+        .filter(|g| g.get("mode").and_then(|v| v.as_str()).is_some_and(|m| m != "disabled"))
+        .map(|g| {
+            let mode = g.get("mode").and_then(|v| v.as_str()).unwrap_or("");
+            let has = |key: &str| g.get(key).is_some();
+            let calibration = g.get("analog")
+                .and_then(|a| a.get("calibration"))
+                .and_then(|v| v.as_array())
+                .map_or(0, |c| 17 + c.len() * (9 + MILLI));
+            // ,{"pin":N,"mode":"ultrasonic_input","cortical_mapping":"...",
+            // "boot_state":"float","pull":"down","drive":"open_drain",...,
+            // "src":"runtime"}
+            let mapping_len = g.get("cortical_mapping").and_then(|v| v.as_str()).map_or(0, str::len).max(32);
+            let entry = 87 + NUM + 16 + mapping_len + 5 + 4 + 10 + 7;
+            let sections: [(bool, usize); 14] = [
+                (has("safe_value"), 14 + UNIT),
+                (has("feedback"), 20 + NUM),
+                (mode == "pwm_output" || mode == "dc_motor", 43 + 2 * NUM),
+                (mode == "servo_output", 89 + 5 * NUM),
+                (mode == "dc_motor", 56 + 2 * NUM + BOOL + UNIT),
+                (mode == "stepper_output", 106 + 5 * NUM + 8 + BOOL),
+                (mode == "analog_input", 30 + 2 * MILLI + calibration),
+                (mode == "touch_input", 35 + NUM + 5),
+                (mode == "encoder_input", 50 + 3 * NUM),
+                (mode == "ultrasonic_input", 57 + 3 * NUM),
+                (mode == "led_strip", 41 + NUM + 3),
+                (mode == "dht_input", 23 + NUM),
+                (mode == "digital_input" || mode == "digital_output", 41 + 7 + NUM + BOOL),
+                (has("population"), 72 + 5 * NUM),
+            ];
+            entry + sections.iter().filter(|(on, _)| *on).map(|(_, n)| n).sum::<usize>()
+        })
+        .sum();
+    // {"effective_config":{<fields>,"gpio":[<entries>]}}\n
+    let dump_bytes = 21 + field_bytes + 9 + gpio_bytes + 4;
+    // A get_settings answer echoes the requested keys (at most a line's
+    // worth) with up to MAX_PAIRS values, the longest a device ID or mapping
+    let get_settings_bytes = 16 + rx_line_capacity + 32 * (text(32) + 2);
+    let config_dump_capacity = (dump_bytes.max(get_settings_bytes) + 63) / 64 * 64;
+    // {"vis":{"id":N,"w":W,"h":H,"px":"<2 hex digits per pixel>"}}\n
+    let camera_line_capacity = (64 + camera.as_ref().map_or(0, |(_, pixels)| pixels * 2) + 63) / 64 * 64;
+    // {"capabilities":...}: up to ~128 bytes per channel, a GPIO entry (with
+    // its feedback pin), a mapped I2C device or one of the other sensors
+    let capabilities_capacity = ((272 + (gpio_config.len() * 2 + i2c_devices.len() + 8) * 128) + 63) / 64 * 64;
+    // The longest line sent, with "id":"<device_id>", in front
+    let longest_line = frame_capacity.max(config_dump_capacity).max(camera_line_capacity).max(capabilities_capacity);
+    let line_capacity = longest_line + 40;
+    // E + hex(counter:8 || line || tag:16) + \n
+    let sealed_line_capacity = 2 + 2 * (8 + line_capacity + 16);
     
     // Generate Rust code for config
     let mut config_code = String::new();
     config_code.push_str("// Auto-generated configuration\n");
     config_code.push_str(&format!("pub const BURST_FREQUENCY_HZ: u32 = {};\n", burst_frequency));
     config_code.push_str(&format!("pub const TRANSPORT_TYPE: &str = \"{}\";\n", transport_type));
+    config_code.push_str(&format!("pub const DEVICE_ID: &str = \"{}\";\n", device_id));
+    // Reported in the boot report; compact with sorted keys, so reformatting
+    // config.json doesn't change it
+    let config_crc = crc32(serde_json::to_string(&config).unwrap().as_bytes());
+    config_code.push_str(&format!("pub const CONFIG_CRC32: u32 = 0x{:08x};\n", config_crc));
+    config_code.push_str(&format!("pub const CORTICAL_AREAS: &[&str] = &{:?};\n", cortical_areas));
+    config_code.push_str(&format!("pub const TRANSPORT_SUPERVISION: SupervisionConfig = {};\n", supervision_code));
+    match watchdog_code {
+        Some(code) => config_code.push_str(&format!("pub const WATCHDOG: Option<WatchdogConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const WATCHDOG: Option<WatchdogConfig> = None;\n"),
+    }
+    match output_restore_code {
+        Some(code) => config_code.push_str(&format!("pub const OUTPUT_RESTORE: Option<RestoreConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const OUTPUT_RESTORE: Option<RestoreConfig> = None;\n"),
+    }
+    match transport_task_code {
+        Some(code) => config_code.push_str(&format!("pub const TRANSPORT_TASK: Option<TransportTaskConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const TRANSPORT_TASK: Option<TransportTaskConfig> = None;\n"),
+    }
+    config_code.push_str(&format!("pub const LOG_CONFIG: LogConfig = LogConfig {{ level: {}, forward: {} }};\n", log_level_code, log_forward_code));
+    match keepalive_code {
+        Some(code) => config_code.push_str(&format!("pub const KEEPALIVE: Option<KeepaliveConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const KEEPALIVE: Option<KeepaliveConfig> = None;\n"),
+    }
+    config_code.push_str(&format!("pub const TRANSPORT_FALLBACK: &[&str] = &{:?};\n", fallback));
+    config_code.push_str(&format!("pub const WIRE_FORMAT: &str = \"{}\";\n", wire_format));
+    config_code.push_str(&format!("pub const SERIAL_FRAMING: SerialFraming = {};\n", serial_framing));
+    config_code.push_str(&format!("pub const SERIAL: SerialConfig = {};\n", serial_code));
+    config_code.push_str(&format!("pub const OUTPUT_ECHO_ENABLED: bool = {};\n", output_echo));
+    config_code.push_str(&format!("pub const BARRIER_ENABLED: bool = {};\n", barrier_enabled));
+    config_code.push_str(&format!("pub const BARRIER_TIMEOUT_MS: u32 = {};\n", barrier_timeout_ms));
+    config_code.push_str(&format!("pub const RAW_PINS: &[u32] = &{:?};\n", raw_pins));
+    config_code.push_str(&format!("pub const TELEMETRY_BOARD_HEALTH: bool = {};\n", board_health));
+    config_code.push_str(&format!("pub const TELEMETRY_PROFILE: bool = {};\n", profile));
+    config_code.push_str(&format!("pub const RATE_POLICY: RatePolicy = {};\n", rate_policy_code));
+    match sleep_code {
+        Some(code) => config_code.push_str(&format!("pub const SLEEP_CONFIG: Option<SleepConfig> = {};\n", code)),
+        None => config_code.push_str("pub const SLEEP_CONFIG: Option<SleepConfig> = None;\n"),
+    }
+    match wifi_code {
+        Some(code) => config_code.push_str(&format!("pub const WIFI_CONFIG: Option<WifiConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const WIFI_CONFIG: Option<WifiConfig> = None;\n"),
+    }
+    match ethernet_code {
+        Some(code) => config_code.push_str(&format!("pub const ETHERNET_CONFIG: Option<EthernetConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const ETHERNET_CONFIG: Option<EthernetConfig> = None;\n"),
+    }
+    match ble_code {
+        Some(code) => config_code.push_str(&format!("pub const BLE_CONFIG: Option<BleConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const BLE_CONFIG: Option<BleConfig> = None;\n"),
+    }
+    match ota_code {
+        Some(code) => config_code.push_str(&format!("pub const OTA: Option<OtaConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const OTA: Option<OtaConfig> = None;\n"),
+    }
+    match compression_code {
+        Some(code) => config_code.push_str(&format!("pub const COMPRESSION: Option<CompressionConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const COMPRESSION: Option<CompressionConfig> = None;\n"),
+    }
+    match link_telemetry_code {
+        Some(code) => config_code.push_str(&format!("pub const LINK_TELEMETRY: Option<LinkTelemetryConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const LINK_TELEMETRY: Option<LinkTelemetryConfig> = None;\n"),
+    }
+    match metrics_code {
+        Some(code) => config_code.push_str(&format!("pub const METRICS: Option<MetricsConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const METRICS: Option<MetricsConfig> = None;\n"),
+    }
+    match time_sync_code {
+        Some(code) => config_code.push_str(&format!("pub const TIME_SYNC: Option<TimeSyncConfig> = Some({});\n", code)),
+        None => config_code.push_str("pub const TIME_SYNC: Option<TimeSyncConfig> = None;\n"),
+    }
+    match status_server_port {
+        Some(port) => config_code.push_str(&format!("pub const STATUS_SERVER_PORT: Option<u16> = Some({});\n", port)),
+        None => config_code.push_str("pub const STATUS_SERVER_PORT: Option<u16> = None;\n"),
+    }
+    match link_psk {
+        Some(bytes) => config_code.push_str(&format!("pub const LINK_PSK: Option<[u8; 32]> = Some([{}]);\n", bytes)),
+        None => config_code.push_str("pub const LINK_PSK: Option<[u8; 32]> = None;\n"),
+    }
+    // LED strips take a motor command per LED color channel, which the
+    // staging/shaping buffers need slots for too
+    let led_strip_bytes: usize = gpio_config.iter()
+        .filter_map(|g| g.get("led_strip"))
+        .map(|l| l.get("length").and_then(|v| v.as_u64()).unwrap_or(0) as usize * 3)
+        .sum();
+    // and so does each tone of the audio output
+    let audio_tones = audio_output.as_ref().map_or(0, |(_, tones)| *tones);
+    
+    config_code.push_str("\n// Buffer capacities (derived from the channel counts above)\n");
+    config_code.push_str(&format!("pub const MAX_SENSORY_CHANNELS: usize = {};\n", sensory_channels.max(1)));
+    config_code.push_str(&format!("pub const MAX_OUTPUT_CHANNELS: usize = {};\n", (output_channels + population_neurons).max(1)));
+    config_code.push_str(&format!(
+        "pub const MAX_MOTOR_NEURONS: usize = {};\n",
+        (output_channels + population_neurons + led_strip_bytes + audio_tones).max(1)
+    ));
+    config_code.push_str(&format!("pub const LED_STRIP_BYTES: usize = {};\n", led_strip_bytes.max(1)));
+    config_code.push_str(&format!("pub const MAX_FEEDBACK_CHANNELS: usize = {};\n", feedback_channels.max(1)));
+    config_code.push_str(&format!("pub const FRAME_CAPACITY: usize = {};\n", frame_capacity));
+    config_code.push_str(&format!("pub const FRAME_QUEUE_CAPACITY: usize = {};\n", frame_queue_capacity));
+    config_code.push_str(&format!("pub const CONFIG_DUMP_CAPACITY: usize = {};\n", config_dump_capacity));
+    config_code.push_str(&format!("pub const CAMERA_LINE_CAPACITY: usize = {};\n", camera_line_capacity));
+    config_code.push_str(&format!("pub const CAPABILITIES_CAPACITY: usize = {};\n", capabilities_capacity));
+    config_code.push_str(&format!("pub const LINE_CAPACITY: usize = {};\n", line_capacity));
+    config_code.push_str(&format!("pub const SEALED_LINE_CAPACITY: usize = {};\n", sealed_line_capacity));
+    config_code.push_str(&format!("pub const RX_LINE_CAPACITY: usize = {};\n", rx_line_capacity));
+    config_code.push_str(&format!("pub const SYSID_RATE_HZ: u32 = {};\n", sysid_rate_hz));
+    config_code.push_str(&format!("pub const SYSID_RESPONSES: usize = {};\n", sysid_responses.max(1)));
+    config_code.push_str(&format!("pub const SYSID_LINE_CAPACITY: usize = {};\n", (sysid_line_bytes + 31) / 32 * 32));
+    config_code.push_str(&format!("pub const RX_READ_CAPACITY: usize = {};\n", rx_read_capacity));
+    
+    match i2c_bus {
+        Some(bus) => config_code.push_str(&format!("pub const I2C_BUS: Option<I2cBusConfig> = {};\n", bus)),
+        None => config_code.push_str("pub const I2C_BUS: Option<I2cBusConfig> = None;\n"),
+    }
+    config_code.push_str("pub const I2C_DEVICES: &[I2cDeviceConfig] = &[\n");
+    for device in &i2c_devices {
+        config_code.push_str(device);
+    }
+    config_code.push_str("];\n");
+    config_code.push_str(&format!(
+        "pub const IMU_CONFIG: Option<ImuConfig> = {};\n",
+        imu.as_deref().unwrap_or("None")
+    ));
+    config_code.push_str(&format!(
+        "pub const ODOMETRY: Option<OdometryConfig> = {};\n",
+        odometry.as_deref().unwrap_or("None")
+    ));
+    config_code.push_str(&format!(
+        "pub const CAMERA: Option<CameraConfig> = {};\n",
+        camera.as_ref().map_or("None", |(code, _)| code.as_str())
+    ));
+    config_code.push_str(&format!(
+        "pub const MICROPHONE: Option<MicrophoneConfig> = {};\n",
+        microphone.as_ref().map_or("None", |(code, _)| code.as_str())
+    ));
+    config_code.push_str(&format!(
+        "pub const AUDIO_OUTPUT: Option<AudioConfig> = {};\n",
+        audio_output.as_ref().map_or("None", |(code, _)| code.as_str())
+    ));
+    config_code.push_str(&format!(
+        "pub const POWER_MONITOR: Option<PowerConfig> = {};\n",
+        power_monitor.as_ref().map_or("None", |(code, _)| code.as_str())
+    ));
+    config_code.push_str("pub const I2C_EXPANDERS: &[ExpanderConfig] = &[\n");
+    for (chip, address, first_pin, _) in &expanders {
+        config_code.push_str(&format!(
+            "    ExpanderConfig {{ chip: {}, address: {}, first_pin: {} }},\n",
+            chip, address, first_pin
+        ));
+    }
+    config_code.push_str("];\n");
+    config_code.push_str(&format!(
+        "pub const ONBOARD_SENSORS: OnboardConfig = OnboardConfig {{ hall_mapping: {:?}, hall_full_scale: {}, temperature_mapping: {:?} }};\n",
+        hall_mapping, hall_full_scale, temperature_mapping
+    ));
     
     // Generate GPIO pin configuration
     config_code.push_str("\npub const GPIO_CONFIG: &[GpioPinConfig] = &[\n");
+    // LEDC: one channel per PWM output, one timer per distinct
+    // (frequency, resolution); the camera clock keeps timer 3 and channel 7
+    let (ledc_timers, ledc_channels) = if camera.is_some() { (3, 7) } else { (4, 8) };
+    let mut pwm_channels = 0;
+    let mut pwm_timers: Vec<(u64, u64)> = Vec::new();
     for gpio in gpio_config {
         if let Some(pin) = gpio.get("pin").and_then(|v| v.as_u64()) {
             if let Some(mode) = gpio.get("mode").and_then(|v| v.as_str()) {
@@ -72,12 +1946,598 @@ fn main() {
                         "digital_output" => "GpioMode::DigitalOutput",
                         "analog_input" => "GpioMode::AnalogInput",
                         "pwm_output" => "GpioMode::PwmOutput",
+                        "servo_output" => "GpioMode::ServoOutput",
+                        "touch_input" => "GpioMode::TouchInput",
+                        "encoder_input" => "GpioMode::EncoderInput",
+                        "ultrasonic_input" => "GpioMode::UltrasonicInput",
+                        "led_strip" => "GpioMode::LedStrip",
+                        "dht_input" => "GpioMode::DhtInput",
+                        "dc_motor" => "GpioMode::DcMotor",
+                        "stepper_output" => "GpioMode::StepperOutput",
                         _ => "GpioMode::Disabled",
                     };
                     
+                    // Virtual pins are plain digital pins of an I2C expander
+                    if pin >= 100 {
+                        if expander_of(pin).is_none() {
+                            panic!("gpio {}: pins 100 and up must belong to an i2c expander", pin);
+                        }
+                        if mode != "digital_input" && mode != "digital_output" {
+                            panic!("gpio {}: expander pins only support digital_input and digital_output", pin);
+                        }
+                    }
+                    
+                    // Optional analog position feedback (feedback servos)
+                    let feedback = match gpio.get("feedback") {
+                        Some(fb) => {
+                            let fb_pin = fb.get("pin")
+                                .and_then(|v| v.as_u64())
+                                .expect("gpio feedback requires a \"pin\"");
+                            let raw_min = fb.get("raw_min").and_then(|v| v.as_u64()).unwrap_or(0);
+                            let raw_max = fb.get("raw_max").and_then(|v| v.as_u64()).unwrap_or(4095);
+                            let fb_mapping = fb.get("cortical_mapping")
+                                .and_then(|v| v.as_str())
+                                .unwrap_or("");
+                            format!(
+                                "Some(FeedbackConfig {{ pin: {}, raw_min: {}, raw_max: {}, cortical_mapping: \"{}\" }})",
+                                fb_pin, raw_min.min(4095), raw_max.min(4095), fb_mapping
+                            )
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // Optional population-threshold decoding (digital outputs)
+                    let population = match gpio.get("population") {
+                        Some(p) => {
+                            if mode != "digital_output" {
+                                panic!("gpio {}: \"population\" is only supported on digital outputs", pin);
+                            }
+                            let first = p.get("first")
+                                .and_then(|v| v.as_u64())
+                                .expect("gpio population requires \"first\"");
+                            let last = p.get("last")
+                                .and_then(|v| v.as_u64())
+                                .expect("gpio population requires \"last\"");
+                            if last < first || last - first >= 64 {
+                                panic!("gpio {}: population range {}-{} must span 1-64 neurons", pin, first, last);
+                            }
+                            let threshold = p.get("threshold").and_then(|v| v.as_u64()).unwrap_or(0);
+                            let hysteresis = p.get("hysteresis").and_then(|v| v.as_u64()).unwrap_or(0);
+                            let window_ms = p.get("window_ms").and_then(|v| v.as_u64()).unwrap_or(100);
+                            format!(
+                                "Some(PopulationConfig {{ first: {}, last: {}, threshold: {}, hysteresis: {}, window_ms: {} }})",
+                                first, last, threshold, hysteresis, window_ms
+                            )
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // LEDC settings (PWM, servo and DC motor outputs): duty cycle =
+                    // motor command, the servo pulse for the commanded angle, or
+                    // the motor's speed
+                    let mut pwm_settings = (0, 0, 0);
+                    let pwm = match gpio.get("pwm") {
+                        Some(_) if mode != "pwm_output" && mode != "dc_motor" => {
+                            panic!("gpio {}: \"pwm\" is only supported on PWM outputs and DC motors", pin);
+                        }
+                        _ if mode == "pwm_output" || mode == "servo_output" || mode == "dc_motor" => {
+                            let p = gpio.get("pwm");
+                            // Servos: 50 Hz, 16 bits = 0.3 us pulse steps; motors:
+                            // 20 kHz, above hearing
+                            let (default_hz, default_bits) = match mode {
+                                "servo_output" => (50, 16),
+                                "dc_motor" => (20_000, 10),
+                                _ => (5000, 13),
+                            };
+                            let frequency_hz = p.and_then(|p| p.get("frequency_hz")).and_then(|v| v.as_u64()).unwrap_or(default_hz);
+                            let resolution_bits = p.and_then(|p| p.get("resolution_bits")).and_then(|v| v.as_u64()).unwrap_or(default_bits);
+                            if pin >= 34 {
+                                panic!("gpio {}: GPIO 34-39 are input-only and can't output PWM", pin);
+                            }
+                            if !(1..=20).contains(&resolution_bits) {
+                                panic!("gpio {}: pwm.resolution_bits must be 1-20", pin);
+                            }
+                            // The timer divides the 80 MHz APB clock into 2^bits steps per period
+                            if frequency_hz == 0 || frequency_hz << resolution_bits > 80_000_000 {
+                                panic!(
+                                    "gpio {}: pwm.frequency_hz {} is not reachable at {} bits (frequency x 2^bits must be at most 80 MHz)",
+                                    pin, frequency_hz, resolution_bits
+                                );
+                            }
+                            let timer = match pwm_timers.iter().position(|&t| t == (frequency_hz, resolution_bits)) {
+                                Some(timer) => timer,
+                                None => {
+                                    pwm_timers.push((frequency_hz, resolution_bits));
+                                    pwm_timers.len() - 1
+                                }
+                            };
+                            if timer >= ledc_timers {
+                                panic!("PWM, servo and DC motor outputs use at most {} different frequency/resolution settings (LEDC timers; a camera takes one)", ledc_timers);
+                            }
+                            if pwm_channels >= ledc_channels {
+                                panic!("at most {} LEDC channels are supported (one per PWM or servo output, one or two per DC motor; a camera takes one)", ledc_channels);
+                            }
+                            pwm_channels += 1;
+                            pwm_settings = (frequency_hz, resolution_bits, timer);
+                            format!(
+                                "Some(PwmConfig {{ frequency_hz: {}, resolution_bits: {}, timer: {}, channel: {} }})",
+                                frequency_hz, resolution_bits, timer, pwm_channels - 1
+                            )
+                        }
+                        _ => "None".to_string(),
+                    };
+                    
+                    // Servo pulse and angle limits (servo outputs)
+                    let servo = match gpio.get("servo") {
+                        Some(_) if mode != "servo_output" => {
+                            panic!("gpio {}: \"servo\" is only supported on servo outputs", pin);
+                        }
+                        _ if mode == "servo_output" => {
+                            let sv = gpio.get("servo");
+                            let field = |key: &str, default: u64| sv.and_then(|sv| sv.get(key)).and_then(|v| v.as_u64()).unwrap_or(default);
+                            let min_pulse_us = field("min_pulse_us", 1000);
+                            let max_pulse_us = field("max_pulse_us", 2000);
+                            let range_deg = field("range_deg", 180);
+                            let min_angle_deg = field("min_angle_deg", 0);
+                            let max_angle_deg = field("max_angle_deg", range_deg);
+                            // The pulse has to fit the 20 ms period
+                            if min_pulse_us >= max_pulse_us || max_pulse_us >= 20_000 {
+                                panic!("gpio {}: servo pulse must satisfy min_pulse_us < max_pulse_us < 20000", pin);
+                            }
+                            if range_deg == 0 || range_deg > 360 {
+                                panic!("gpio {}: servo.range_deg must be 1-360", pin);
+                            }
+                            if min_angle_deg > range_deg || max_angle_deg > range_deg || min_angle_deg == max_angle_deg {
+                                panic!("gpio {}: servo angle limits must be two different angles within 0-{}", pin, range_deg);
+                            }
+                            format!(
+                                "Some(ServoConfig {{ min_pulse_us: {}, max_pulse_us: {}, range_deg: {}, min_angle_deg: {}, max_angle_deg: {} }})",
+                                min_pulse_us, max_pulse_us, range_deg, min_angle_deg, max_angle_deg
+                            )
+                        }
+                        _ => "None".to_string(),
+                    };
+                    
+                    // H-bridge inputs, direction and deadband (DC motors)
+                    let dc_motor = match gpio.get("dc_motor") {
+                        Some(_) if mode != "dc_motor" => {
+                            panic!("gpio {}: \"dc_motor\" is only supported on DC motor outputs", pin);
+                        }
+                        Some(m) => {
+                            if gpio.get("boot_state").is_some() {
+                                panic!("gpio {}: DC motors always boot stopped (no \"boot_state\")", pin);
+                            }
+                            let check_pin = |key: &str, other: u64| {
+                                if other == pin || other >= 34 {
+                                    panic!("gpio {}: dc_motor.{} must be another output-capable GPIO (0-33)", pin, key);
+                                }
+                                if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(other)) {
+                                    panic!("gpio {}: dc_motor.{} {} is configured as a pin of its own", pin, key, other);
+                                }
+                            };
+                            let driver = match (m.get("pin_b"), m.get("direction_pins")) {
+                                // Two PWM inputs: pin_b gets a channel on the same timer
+                                (Some(pin_b), None) => {
+                                    let pin_b = pin_b.as_u64()
+                                        .unwrap_or_else(|| panic!("gpio {}: dc_motor.pin_b must be a GPIO number", pin));
+                                    check_pin("pin_b", pin_b);
+                                    if pwm_channels >= ledc_channels {
+                                        panic!("at most {} LEDC channels are supported (one per PWM or servo output, one or two per DC motor; a camera takes one)", ledc_channels);
+                                    }
+                                    pwm_channels += 1;
+                                    let (frequency_hz, resolution_bits, timer) = pwm_settings;
+                                    format!(
+                                        "MotorDriver::TwoPwm {{ pin_b: {}, pwm_b: PwmConfig {{ frequency_hz: {}, resolution_bits: {}, timer: {}, channel: {} }} }}",
+                                        pin_b, frequency_hz, resolution_bits, timer, pwm_channels - 1
+                                    )
+                                }
+                                (None, Some(pins)) => {
+                                    let pins: Vec<u64> = pins.as_array()
+                                        .and_then(|p| p.iter().map(|v| v.as_u64()).collect())
+                                        .filter(|p: &Vec<u64>| p.len() == 1 || (p.len() == 2 && p[0] != p[1]))
+                                        .unwrap_or_else(|| panic!("gpio {}: dc_motor.direction_pins must be [in1, in2] or [dir]", pin));
+                                    for &direction_pin in &pins {
+                                        check_pin("direction_pins", direction_pin);
+                                    }
+                                    let in2 = pins.get(1).map_or("None".to_string(), |p| format!("Some({})", p));
+                                    format!("MotorDriver::Direction {{ in1: {}, in2: {} }}", pins[0], in2)
+                                }
+                                _ => panic!("gpio {}: dc_motor requires either \"pin_b\" (two PWM inputs) or \"direction_pins\"", pin),
+                            };
+                            let invert = match m.get("invert") {
+                                None => false,
+                                Some(v) => v.as_bool()
+                                    .unwrap_or_else(|| panic!("gpio {}: dc_motor.invert must be true or false", pin)),
+                            };
+                            let deadband = m.get("deadband").and_then(|v| v.as_f64()).unwrap_or(0.05);
+                            if !(0.0..1.0).contains(&deadband) {
+                                panic!("gpio {}: dc_motor.deadband must be at least 0.0 and below 1.0 (a fraction of full speed)", pin);
+                            }
+                            format!("Some(DcMotorConfig {{ driver: {}, invert: {}, deadband: {:?} }})", driver, invert, deadband as f32)
+                        }
+                        None if mode == "dc_motor" => {
+                            panic!("gpio {}: dc_motor outputs require a \"dc_motor\" object (\"pin_b\" or \"direction_pins\")", pin);
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // DIR/EN pins, control mode and motion limits (stepper outputs)
+                    let stepper = match gpio.get("stepper") {
+                        Some(_) if mode != "stepper_output" => {
+                            panic!("gpio {}: \"stepper\" is only supported on stepper outputs", pin);
+                        }
+                        Some(st) => {
+                            if pin >= 34 {
+                                panic!("gpio {}: GPIO 34-39 are input-only and can't drive STEP", pin);
+                            }
+                            if gpio.get("boot_state").is_some() {
+                                panic!("gpio {}: steppers always boot stopped (no \"boot_state\")", pin);
+                            }
+                            let check_pin = |key: &str, other: u64| {
+                                if other == pin || other >= 34 {
+                                    panic!("gpio {}: stepper.{} must be another output-capable GPIO (0-33)", pin, key);
+                                }
+                                if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(other)) {
+                                    panic!("gpio {}: stepper.{} {} is configured as a pin of its own", pin, key, other);
+                                }
+                            };
+                            let dir_pin = st.get("dir_pin")
+                                .and_then(|v| v.as_u64())
+                                .unwrap_or_else(|| panic!("gpio {}: stepper requires a \"dir_pin\"", pin));
+                            check_pin("dir_pin", dir_pin);
+                            let enable_pin = match st.get("enable_pin").and_then(|v| v.as_u64()) {
+                                Some(enable_pin) => {
+                                    check_pin("enable_pin", enable_pin);
+                                    if enable_pin == dir_pin {
+                                        panic!("gpio {}: stepper.enable_pin and dir_pin must differ", pin);
+                                    }
+                                    format!("Some({})", enable_pin)
+                                }
+                                None => "None".to_string(),
+                            };
+                            let field = |key: &str, default: u64| st.get(key).and_then(|v| v.as_u64()).unwrap_or(default);
+                            // A step is a high and a low tick of the 20 kHz step timer
+                            let max_speed_sps = field("max_speed_sps", 1000);
+                            if !(1..=10_000).contains(&max_speed_sps) {
+                                panic!("gpio {}: stepper.max_speed_sps must be 1-10000", pin);
+                            }
+                            let accel_sps2 = field("accel_sps2", 2000);
+                            if !(1..=1_000_000).contains(&accel_sps2) {
+                                panic!("gpio {}: stepper.accel_sps2 must be 1-1000000", pin);
+                            }
+                            let (control, range_steps) = match st.get("control").and_then(|v| v.as_str()) {
+                                None | Some("velocity") => {
+                                    if st.get("range_steps").is_some() {
+                                        panic!("gpio {}: stepper.range_steps only applies to \"position\" control", pin);
+                                    }
+                                    ("StepperControl::Velocity", 0)
+                                }
+                                Some("position") => {
+                                    let range_steps = st.get("range_steps")
+                                        .and_then(|v| v.as_u64())
+                                        .unwrap_or_else(|| panic!("gpio {}: position-controlled steppers require \"range_steps\"", pin));
+                                    if !(1..=1_000_000).contains(&range_steps) {
+                                        panic!("gpio {}: stepper.range_steps must be 1-1000000", pin);
+                                    }
+                                    ("StepperControl::Position", range_steps)
+                                }
+                                Some(other) => panic!("gpio {}: stepper.control must be \"velocity\" or \"position\", got \"{}\"", pin, other),
+                            };
+                            let invert = match st.get("invert") {
+                                None => false,
+                                Some(v) => v.as_bool()
+                                    .unwrap_or_else(|| panic!("gpio {}: stepper.invert must be true or false", pin)),
+                            };
+                            format!(
+                                "Some(StepperConfig {{ dir_pin: {}, enable_pin: {}, control: {}, max_speed_sps: {}, accel_sps2: {}, range_steps: {}, invert: {} }})",
+                                dir_pin, enable_pin, control, max_speed_sps, accel_sps2, range_steps, invert
+                            )
+                        }
+                        None if mode == "stepper_output" => {
+                            panic!("gpio {}: stepper outputs require a \"stepper\" object with a \"dir_pin\"", pin);
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // Scale, offset and calibration table (analog inputs)
+                    let analog = match gpio.get("analog") {
+                        Some(_) if mode != "analog_input" => {
+                            panic!("gpio {}: \"analog\" is only supported on analog inputs", pin);
+                        }
+                        _ if mode == "analog_input" => {
+                            let a = gpio.get("analog");
+                            let number = |key: &str, default: f64| match a.and_then(|a| a.get(key)) {
+                                None => default,
+                                Some(v) => v.as_f64().unwrap_or_else(|| panic!("gpio {}: analog.{} must be a number", pin, key)),
+                            };
+                            let scale = number("scale", 1.0);
+                            let offset = number("offset", 0.0);
+                            // [[raw, value], ...] with raw readings rising
+                            let mut calibration = String::new();
+                            let mut last_raw = None;
+                            let points = a.and_then(|a| a.get("calibration")).map(|c| {
+                                c.as_array().unwrap_or_else(|| panic!("gpio {}: analog.calibration must be an array of [raw, value] pairs", pin))
+                            });
+                            if let Some(points) = points {
+                                if !(2..=16).contains(&points.len()) {
+                                    panic!("gpio {}: analog.calibration needs 2-16 [raw, value] points", pin);
+                                }
+                                for point in points {
+                                    let (raw, value) = match point.as_array().map(|p| p.as_slice()) {
+                                        Some([raw, value]) => (raw.as_u64(), value.as_f64()),
+                                        _ => (None, None),
+                                    };
+                                    let (Some(raw), Some(value)) = (raw, value) else {
+                                        panic!("gpio {}: analog.calibration points must be [raw, value] pairs", pin);
+                                    };
+                                    if raw > 4095 {
+                                        panic!("gpio {}: analog.calibration raw readings must be 0-4095", pin);
+                                    }
+                                    if last_raw.is_some_and(|last| raw <= last) {
+                                        panic!("gpio {}: analog.calibration raw readings must be strictly increasing", pin);
+                                    }
+                                    last_raw = Some(raw);
+                                    calibration.push_str(&format!("({}, {:?}), ", raw, value as f32));
+                                }
+                            }
+                            format!(
+                                "Some(AnalogConfig {{ scale: {:?}, offset: {:?}, calibration: &[{}] }})",
+                                scale as f32, offset as f32, calibration
+                            )
+                        }
+                        _ => "None".to_string(),
+                    };
+                    
+                    // Capacitive touch threshold and report mode (touch inputs)
+                    let touch = match gpio.get("touch") {
+                        Some(_) if mode != "touch_input" => {
+                            panic!("gpio {}: \"touch\" is only supported on touch inputs", pin);
+                        }
+                        _ if mode == "touch_input" => {
+                            if !TOUCH_PINS.contains(&pin) {
+                                panic!("gpio {}: not a touch pad (touch inputs are GPIO 0, 2, 4, 12-15, 27, 32, 33)", pin);
+                            }
+                            let t = gpio.get("touch");
+                            let threshold = match t.and_then(|t| t.get("threshold")) {
+                                Some(v) => match v.as_u64() {
+                                    Some(n) if (1..=65535).contains(&n) => format!("Some({})", n),
+                                    _ => panic!("gpio {}: touch.threshold must be 1-65535", pin),
+                                },
+                                None => "None".to_string(),
+                            };
+                            let report = match t.and_then(|t| t.get("report")).and_then(|v| v.as_str()) {
+                                None | Some("state") => "TouchReport::State",
+                                Some("level") => "TouchReport::Level",
+                                Some(other) => panic!("gpio {}: touch.report must be \"state\" or \"level\", got \"{}\"", pin, other),
+                            };
+                            format!("Some(TouchConfig {{ threshold: {}, report: {} }})", threshold, report)
+                        }
+                        _ => "None".to_string(),
+                    };
+                    
+                    // Quadrature B channel and scaling (encoder inputs)
+                    let encoder = match gpio.get("encoder") {
+                        Some(_) if mode != "encoder_input" => {
+                            panic!("gpio {}: \"encoder\" is only supported on encoder inputs", pin);
+                        }
+                        Some(e) => {
+                            let pin_b = e.get("pin_b")
+                                .and_then(|v| v.as_u64())
+                                .unwrap_or_else(|| panic!("gpio {}: encoder requires a \"pin_b\"", pin));
+                            if pin_b == pin || pin_b > 39 {
+                                panic!("gpio {}: encoder.pin_b must be another GPIO (0-39)", pin);
+                            }
+                            if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(pin_b)) {
+                                panic!("gpio {}: encoder.pin_b {} is configured as a pin of its own", pin, pin_b);
+                            }
+                            let counts_per_rev = match e.get("counts_per_rev").and_then(|v| v.as_u64()) {
+                                Some(n) if n > 0 => n,
+                                _ => panic!("gpio {}: encoder requires \"counts_per_rev\" (4x the encoder's PPR)", pin),
+                            };
+                            let max_rpm = e.get("max_rpm").and_then(|v| v.as_u64()).unwrap_or(300);
+                            if max_rpm == 0 {
+                                panic!("gpio {}: encoder.max_rpm must be at least 1", pin);
+                            }
+                            format!(
+                                "Some(EncoderConfig {{ pin_b: {}, counts_per_rev: {}, max_rpm: {} }})",
+                                pin_b, counts_per_rev, max_rpm
+                            )
+                        }
+                        None if mode == "encoder_input" => {
+                            panic!("gpio {}: encoder inputs require an \"encoder\" block with \"pin_b\"", pin);
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // HC-SR04 echo pin, range and timeout (ultrasonic inputs)
+                    let ultrasonic = match gpio.get("ultrasonic") {
+                        Some(_) if mode != "ultrasonic_input" => {
+                            panic!("gpio {}: \"ultrasonic\" is only supported on ultrasonic inputs", pin);
+                        }
+                        Some(u) => {
+                            if pin >= 34 {
+                                panic!("gpio {}: GPIO 34-39 are input-only and can't drive the trigger", pin);
+                            }
+                            let echo_pin = u.get("echo_pin")
+                                .and_then(|v| v.as_u64())
+                                .unwrap_or_else(|| panic!("gpio {}: ultrasonic requires an \"echo_pin\"", pin));
+                            if echo_pin == pin || echo_pin > 39 {
+                                panic!("gpio {}: ultrasonic.echo_pin must be another GPIO (0-39)", pin);
+                            }
+                            if gpio_config.iter().any(|g| g.get("pin").and_then(|v| v.as_u64()) == Some(echo_pin)) {
+                                panic!("gpio {}: ultrasonic.echo_pin {} is configured as a pin of its own", pin, echo_pin);
+                            }
+                            // The HC-SR04 is rated for 2-400 cm
+                            let max_range_cm = u.get("max_range_cm").and_then(|v| v.as_u64()).unwrap_or(400);
+                            if !(2..=400).contains(&max_range_cm) {
+                                panic!("gpio {}: ultrasonic.max_range_cm must be 2-400", pin);
+                            }
+                            // Default: the sensor's 60 ms measurement cycle
+                            let timeout_us = u.get("timeout_us").and_then(|v| v.as_u64()).unwrap_or(60_000);
+                            if timeout_us < max_range_cm * 58 {
+                                panic!(
+                                    "gpio {}: ultrasonic.timeout_us must be at least {} (the echo time at max_range_cm)",
+                                    pin, max_range_cm * 58
+                                );
+                            }
+                            format!(
+                                "Some(UltrasonicConfig {{ echo_pin: {}, max_range_cm: {}, timeout_us: {} }})",
+                                echo_pin, max_range_cm, timeout_us
+                            )
+                        }
+                        None if mode == "ultrasonic_input" => {
+                            panic!("gpio {}: ultrasonic inputs require an \"ultrasonic\" block with \"echo_pin\"", pin);
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // WS2812 strip length and wire color order (LED strips)
+                    let led_strip = match gpio.get("led_strip") {
+                        Some(_) if mode != "led_strip" => {
+                            panic!("gpio {}: \"led_strip\" is only supported on led_strip pins", pin);
+                        }
+                        Some(l) => {
+                            if pin >= 34 {
+                                panic!("gpio {}: GPIO 34-39 are input-only and can't drive an LED strip", pin);
+                            }
+                            let length = match l.get("length").and_then(|v| v.as_u64()) {
+                                Some(n) if (1..=512).contains(&n) => n,
+                                _ => panic!("gpio {}: led_strip requires a \"length\" of 1-512 LEDs", pin),
+                            };
+                            let color_order = match l.get("color_order").and_then(|v| v.as_str()).unwrap_or("grb") {
+                                "rgb" => "ColorOrder::Rgb",
+                                "rbg" => "ColorOrder::Rbg",
+                                "grb" => "ColorOrder::Grb",
+                                "gbr" => "ColorOrder::Gbr",
+                                "brg" => "ColorOrder::Brg",
+                                "bgr" => "ColorOrder::Bgr",
+                                other => panic!("gpio {}: led_strip.color_order must be an order of r, g and b (e.g. \"grb\"), got \"{}\"", pin, other),
+                            };
+                            format!("Some(LedStripConfig {{ length: {}, color_order: {} }})", length, color_order)
+                        }
+                        None if mode == "led_strip" => {
+                            panic!("gpio {}: led_strip pins require a \"led_strip\" block with \"length\"", pin);
+                        }
+                        None => "None".to_string(),
+                    };
+                    
+                    // DHT22 poll interval (temperature/humidity inputs)
+                    let dht = match gpio.get("dht") {
+                        Some(_) if mode != "dht_input" => {
+                            panic!("gpio {}: \"dht\" is only supported on DHT inputs", pin);
+                        }
+                        _ if mode == "dht_input" => {
+                            if pin >= 34 {
+                                panic!("gpio {}: GPIO 34-39 are input-only and can't signal the DHT22", pin);
+                            }
+                            // The DHT22 needs 2 s between reads
+                            let interval_ms = gpio.get("dht")
+                                .and_then(|d| d.get("interval_ms"))
+                                .and_then(|v| v.as_u64())
+                                .unwrap_or(2000);
+                            if interval_ms < 2000 {
+                                panic!("gpio {}: dht.interval_ms must be at least 2000", pin);
+                            }
+                            format!("Some(DhtConfig {{ interval_ms: {} }})", interval_ms)
+                        }
+                        _ => "None".to_string(),
+                    };
+                    
+                    // Trigger mode, debounce time and edge interrupt (digital inputs)
+                    let input = if mode == "digital_input" {
+                        let interrupt = match gpio.get("interrupt") {
+                            None => false,
+                            Some(v) => v.as_bool()
+                                .unwrap_or_else(|| panic!("gpio {}: \"interrupt\" must be true or false", pin)),
+                        };
+                        // Interrupt-driven inputs report edges, so default to both
+                        let trigger = match (gpio.get("trigger").and_then(|v| v.as_str()), interrupt) {
+                            (None, true) => "Trigger::Both",
+                            (None, false) | (Some("level"), false) => "Trigger::Level",
+                            (Some("level"), true) => panic!("gpio {}: interrupt-driven inputs need a \"rising\", \"falling\" or \"both\" trigger", pin),
+                            (Some("rising"), _) => "Trigger::Rising",
+                            (Some("falling"), _) => "Trigger::Falling",
+                            (Some("both"), _) => "Trigger::Both",
+                            (Some(other), _) => panic!("gpio {}: trigger must be \"level\", \"rising\", \"falling\" or \"both\", got \"{}\"", pin, other),
+                        };
+                        let debounce_ms = gpio.get("debounce_ms").and_then(|v| v.as_u64()).unwrap_or(0);
+                        if debounce_ms > 1000 {
+                            panic!("gpio {}: debounce_ms must be at most 1000", pin);
+                        }
+                        format!("Some(InputConfig {{ trigger: {}, debounce_ms: {}, interrupt: {} }})", trigger, debounce_ms, interrupt)
+                    } else {
+                        for key in ["trigger", "debounce_ms", "interrupt"] {
+                            if gpio.get(key).is_some() {
+                                panic!("gpio {}: \"{}\" is only supported on digital inputs", pin, key);
+                            }
+                        }
+                        "None".to_string()
+                    };
+                    
+                    // Output state between power-up and the first motor command
+                    let boot_state = match gpio.get("boot_state").and_then(|v| v.as_str()) {
+                        Some("float") => "BootState::Float",
+                        Some("hold") => "BootState::Hold",
+                        _ => "BootState::DriveLow",
+                    };
+                    
+                    // Internal pull resistors (digital pins; GPIO 34-39 have none)
+                    let pull = match gpio.get("pull").and_then(|v| v.as_str()) {
+                        None | Some("none") => "Pull::None",
+                        Some(_) if mode != "digital_input" && mode != "digital_output" => {
+                            panic!("gpio {}: \"pull\" is only supported on digital inputs and outputs", pin);
+                        }
+                        Some(_) if (34..100).contains(&pin) => {
+                            panic!("gpio {}: GPIO 34-39 have no pull resistors", pin);
+                        }
+                        Some("up") => "Pull::Up",
+                        Some("down") => "Pull::Down",
+                        Some(other) => panic!("gpio {}: pull must be \"none\", \"up\" or \"down\", got \"{}\"", pin, other),
+                    };
+                    
+                    // Push-pull or open-drain output stage (digital outputs)
+                    let drive = match gpio.get("drive").and_then(|v| v.as_str()) {
+                        None | Some("push_pull") => "Drive::PushPull",
+                        Some(_) if mode != "digital_output" => {
+                            panic!("gpio {}: \"drive\" is only supported on digital outputs", pin);
+                        }
+                        Some("open_drain") => "Drive::OpenDrain",
+                        Some(other) => panic!("gpio {}: drive must be \"push_pull\" or \"open_drain\", got \"{}\"", pin, other),
+                    };
+                    
+                    // Value the output falls back to when the link is lost
+                    // (see src/failsafe.rs); motors always stop
+                    let safe_value = match gpio.get("safe_value") {
+                        None => "None".to_string(),
+                        Some(_) if !matches!(mode, "digital_output" | "pwm_output" | "servo_output") => {
+                            panic!("gpio {}: \"safe_value\" is only supported on digital, PWM and servo outputs", pin);
+                        }
+                        Some(v) => match v.as_f64() {
+                            Some(value) if (0.0..=1.0).contains(&value) => format!("Some({:?})", value as f32),
+                            _ => panic!("gpio {}: safe_value must be a number from 0.0 to 1.0", pin),
+                        },
+                    };
+                    
+                    // Expander pins start low and can't interrupt
+                    if let Some(&(chip, address, _, _)) = expander_of(pin) {
+                        if input.contains("interrupt: true") {
+                            panic!("gpio {}: expander pins can't be interrupt-driven", pin);
+                        }
+                        if boot_state != "BootState::DriveLow" || drive != "Drive::PushPull" {
+                            panic!("gpio {}: expander pins don't support \"boot_state\" or \"drive\" (they start low)", pin);
+                        }
+                        match pull {
+                            "Pull::Down" => panic!("gpio {}: expanders have no pull-down resistors", pin),
+                            "Pull::Up" if chip != "ExpanderChip::Mcp23017" => {
+                                panic!("gpio {}: expander 0x{:x} is a PCF8574, whose inputs are always pulled up", pin, address);
+                            }
+                            _ => {}
+                        }
+                    }
+                    
                     config_code.push_str(&format!(
-                        "    GpioPinConfig {{ pin: {}, mode: {}, cortical_mapping: \"{}\" }},\n",
-                        pin, mode_const, cortical_mapping
+                        "    GpioPinConfig {{ pin: {}, mode: {}, cortical_mapping: \"{}\", boot_state: {}, safe_value: {}, pull: {}, drive: {}, feedback: {}, population: {}, pwm: {}, servo: {}, dc_motor: {}, stepper: {}, analog: {}, touch: {}, encoder: {}, ultrasonic: {}, led_strip: {}, dht: {}, input: {} }},\n",
+                        pin, mode_const, cortical_mapping, boot_state, safe_value, pull, drive, feedback, population, pwm, servo, dc_motor, stepper, analog, touch, encoder, ultrasonic, led_strip, dht, input
                     ));
                 }
             }
@@ -90,3 +2550,132 @@ fn main() {
         .expect("Failed to write config.rs");
 }
 
+
+// What a cortical_mapping names (see src/cortical.rs)
+enum Mapping<'a> {
+    /// "area": the neurons come from elsewhere (e.g. a population)
+    Area,
+    /// "N" or "area:N"
+    Flat(u64),
+    /// "area:x,y,z"
+    Voxel(&'a str, [u64; 3]),
+}
+
+fn parse_mapping(mapping: &str) -> Option<Mapping<'_>> {
+    let valid_area = |area: &str| !area.is_empty() && area.chars().all(|c| c.is_ascii_graphic() && !matches!(c, ':' | ',' | '"' | '\\'));
+    let (area, neuron) = match mapping.split_once(':') {
+        Some((area, neuron)) => (Some(area).filter(|a| valid_area(a))?, neuron),
+        None if mapping.parse::<u64>().is_ok() => return mapping.parse().ok().map(Mapping::Flat),
+        None => return valid_area(mapping).then_some(Mapping::Area),
+    };
+    let numbers: Vec<u64> = neuron.split(',').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [id] => Some(Mapping::Flat(id)),
+        [x, y, z] => Some(Mapping::Voxel(area, [x, y, z])),
+        _ => None,
+    }
+}
+
+// Does the mapping end in a neuron (a flat ID or coordinates)?
+fn names_neuron(mapping: &str) -> bool {
+    matches!(parse_mapping(mapping), Some(Mapping::Flat(_) | Mapping::Voxel(..)))
+}
+
+// Every cortical_mapping in the config must parse, with coordinates in the
+// ranges a packed neuron ID holds; `areas` collects the areas mapped by
+// coordinates
+fn check_cortical_mappings(value: &serde_json::Value, areas: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map {
+                if let (true, Some(mapping)) = (key == "cortical_mapping", v.as_str().filter(|m| !m.is_empty())) {
+                    match parse_mapping(mapping) {
+                        None => panic!(
+                            "cortical_mapping \"{}\" must be \"area:x,y,z\", \"area:N\", \"N\" or just the area",
+                            mapping
+                        ),
+                        Some(Mapping::Flat(id)) if id >= 1 << 31 => {
+                            panic!("cortical_mapping \"{}\": neuron IDs must be below 2147483648", mapping)
+                        }
+                        Some(Mapping::Voxel(_, [x, y, z])) if x > 1023 || y > 255 || z > 63 => {
+                            panic!("cortical_mapping \"{}\": coordinates must be within 1023,255,63", mapping)
+                        }
+                        Some(Mapping::Voxel(area, _)) if !areas.iter().any(|a| a == area) => areas.push(area.to_string()),
+                        Some(_) => {}
+                    }
+                }
+                check_cortical_mappings(v, areas);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter().for_each(|item| check_cortical_mappings(item, areas)),
+        _ => {}
+    }
+}
+
+// The CameraBoard of camera.board, and every pin the module wires to the
+// camera connector
+fn camera_board(cam: &serde_json::Value) -> (&'static str, &'static [u64]) {
+    match cam.get("board").and_then(|v| v.as_str()) {
+        None | Some("ai_thinker") => ("CameraBoard::AiThinker", &[32, 0, 26, 27, 35, 34, 39, 36, 21, 19, 18, 5, 25, 23, 22]),
+        Some("esp_eye") => ("CameraBoard::EspEye", &[4, 18, 23, 36, 37, 38, 39, 35, 14, 13, 34, 5, 27, 25]),
+        Some(other) => panic!("camera.board must be \"ai_thinker\" or \"esp_eye\", got \"{}\"", other),
+    }
+}
+
+// Pins the config wires to something: every "pin", "<name>_pin", "pin_b",
+// "pins"/"direction_pins" entry and bus pin (SPI, I2C, I2S), at any depth
+fn claimed_pins(value: &serde_json::Value, claimed: &mut Vec<u64>) {
+    const BUS_PINS: [&str; 12] = ["sda", "scl", "sclk", "sck", "mosi", "miso", "cs", "int", "rst", "bck", "ws", "dout"];
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map {
+                let names_pins = key == "pin" || key.ends_with("_pin") || key == "pin_b" || key.ends_with("pins")
+                    || BUS_PINS.contains(&key.as_str());
+                if names_pins {
+                    let pins = v.as_array().map_or_else(|| vec![v], |items| items.iter().collect());
+                    claimed.extend(pins.into_iter().filter_map(|p| p.as_u64()));
+                }
+                claimed_pins(v, claimed);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter().for_each(|item| claimed_pins(item, claimed)),
+        _ => {}
+    }
+}
+
+// CRC-32 (IEEE 802.3, as zlib.crc32) of `data`
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// Binary packets carry 16-bit neuron IDs: every cortical_mapping in the
+// config has to end in one, not in coordinates
+fn check_binary_neuron_ids(value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map {
+                if let (true, Some(mapping)) = (key == "cortical_mapping", v.as_str()) {
+                    match parse_mapping(mapping) {
+                        Some(Mapping::Flat(id)) if id > u16::MAX as u64 => {
+                            panic!("wire_format \"binary\": neuron ID of \"{}\" doesn't fit 16 bits", mapping)
+                        }
+                        Some(Mapping::Voxel(..)) => {
+                            panic!("wire_format \"binary\": \"{}\" has coordinates, which packets can't carry", mapping)
+                        }
+                        _ => {}
+                    }
+                }
+                check_binary_neuron_ids(v);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter().for_each(check_binary_neuron_ids),
+        _ => {}
+    }
+}
@@ -0,0 +1,2 @@
+// Bindings for the esp32-camera component (esp_idf_svc::sys::camera)
+#include "esp_camera.h"
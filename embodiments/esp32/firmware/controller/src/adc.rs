@@ -0,0 +1,81 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! ADC1 oneshot reads for analog pins
+//!
+//! Only ADC1 is used: ADC2 is shared with the WiFi radio on the ESP32.
+//! The unit can only be claimed once, so every `Adc1` shares one handle.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use esp_idf_svc::sys;
+
+/// Unit handle, created by the first `Adc1::new`
+static UNIT: AtomicPtr<sys::adc_oneshot_unit_ctx_t> = AtomicPtr::new(core::ptr::null_mut());
+
+/// ADC1 oneshot unit
+pub struct Adc1 {
+    handle: sys::adc_oneshot_unit_handle_t,
+}
+
+impl Adc1 {
+    /// Create the ADC1 oneshot unit, or share it if it already exists
+    pub fn new() -> Option<Self> {
+        let handle = UNIT.load(Ordering::Relaxed);
+        if !handle.is_null() {
+            return Some(Self { handle });
+        }
+        let mut handle: sys::adc_oneshot_unit_handle_t = core::ptr::null_mut();
+        let init_config = sys::adc_oneshot_unit_init_cfg_t {
+            unit_id: sys::adc_unit_t_ADC_UNIT_1,
+            ulp_mode: sys::adc_ulp_mode_t_ADC_ULP_MODE_DISABLE,
+            ..Default::default()
+        };
+        let err = unsafe { sys::adc_oneshot_new_unit(&init_config, &mut handle) };
+        if err != sys::ESP_OK {
+            return None;
+        }
+        UNIT.store(handle, Ordering::Relaxed);
+        Some(Self { handle })
+    }
+
+    /// Configure a GPIO as an ADC1 input (12-bit, full 0-3.3V range)
+    ///
+    /// Returns the ADC channel, or None if the pin is not an ADC1 pin.
+    pub fn configure_pin(&mut self, pin: u32) -> Option<sys::adc_channel_t> {
+        self.configure_pin_atten(pin, sys::adc_atten_t_ADC_ATTEN_DB_11)
+    }
+
+    /// Configure a GPIO as a 12-bit ADC1 input with the given attenuation
+    pub fn configure_pin_atten(&mut self, pin: u32, atten: sys::adc_atten_t) -> Option<sys::adc_channel_t> {
+        let mut unit: sys::adc_unit_t = 0;
+        let mut channel: sys::adc_channel_t = 0;
+        let err = unsafe { sys::adc_oneshot_io_to_channel(pin as i32, &mut unit, &mut channel) };
+        if err != sys::ESP_OK || unit != sys::adc_unit_t_ADC_UNIT_1 {
+            return None;
+        }
+        let chan_config = sys::adc_oneshot_chan_cfg_t {
+            atten,
+            bitwidth: sys::adc_bitwidth_t_ADC_BITWIDTH_12,
+        };
+        let err = unsafe { sys::adc_oneshot_config_channel(self.handle, channel, &chan_config) };
+        if err != sys::ESP_OK {
+            return None;
+        }
+        Some(channel)
+    }
+
+    /// Read a raw 12-bit sample (0-4095)
+    pub fn read_raw(&mut self, channel: sys::adc_channel_t) -> Option<u16> {
+        let mut raw: i32 = 0;
+        let err = unsafe { sys::adc_oneshot_read(self.handle, channel, &mut raw) };
+        if err != sys::ESP_OK {
+            return None;
+        }
+        Some(raw.clamp(0, 4095) as u16)
+    }
+}
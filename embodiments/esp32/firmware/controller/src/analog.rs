@@ -0,0 +1,126 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Analog inputs on ADC1 pins, scaled and calibrated per pin
+//!
+//! An `analog_input` pin is sampled every burst and its raw 12-bit reading
+//! turned into a potential in three steps:
+//!
+//! 1. `calibration`, if given: piecewise-linear interpolation over
+//!    `[raw, value]` points (raw ascending), held at the end points outside
+//!    them; otherwise the reading over full scale (raw / 4095)
+//! 2. `value * scale + offset`
+//! 3. clamped to 0.0-1.0
+//!
+//! so a voltage divider only needs `scale`/`offset` to spread its useful
+//! range over 0.0-1.0, and a non-linear sensor (e.g. a thermistor) a table
+//! of a few measured points.
+
+use heapless::Vec;
+
+use crate::adc::Adc1;
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::fault;
+use esp_idf_svc::sys;
+
+/// Full-scale 12-bit reading
+const RAW_MAX: f32 = 4095.0;
+
+/// Scaling of one analog input (from config.json `analog` block)
+#[derive(Debug, Clone, Copy)]
+pub struct AnalogConfig {
+    pub scale: f32,
+    pub offset: f32,
+    /// (raw reading, value) points, raw ascending; empty = raw / 4095
+    pub calibration: &'static [(u16, f32)],
+}
+
+impl AnalogConfig {
+    /// Potential (0.0-1.0) of a raw ADC reading
+    pub fn value(&self, raw: u16) -> f32 {
+        let value = match self.calibration {
+            [] => raw as f32 / RAW_MAX,
+            points => interpolate(points, raw),
+        };
+        (value * self.scale + self.offset).clamp(0.0, 1.0)
+    }
+}
+
+/// Piecewise-linear value of `raw` over the calibration points
+fn interpolate(points: &[(u16, f32)], raw: u16) -> f32 {
+    let (first_raw, first_value) = points[0];
+    if raw <= first_raw {
+        return first_value;
+    }
+    for pair in points.windows(2) {
+        let ((raw_a, value_a), (raw_b, value_b)) = (pair[0], pair[1]);
+        if raw <= raw_b {
+            let t = (raw - raw_a) as f32 / (raw_b - raw_a).max(1) as f32;
+            return value_a + (value_b - value_a) * t;
+        }
+    }
+    points[points.len() - 1].1
+}
+
+struct AnalogChannel {
+    config: AnalogConfig,
+    adc_channel: sys::adc_channel_t,
+    neuron_id: u32,
+}
+
+/// All configured analog inputs (capacity `N` from the build config)
+pub struct AnalogBank<const N: usize> {
+    adc: Option<Adc1>,
+    channels: Vec<AnalogChannel, N>,
+}
+
+impl<const N: usize> AnalogBank<N> {
+    /// Configure the `analog_input` pins in GPIO_CONFIG on ADC1
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut channels = Vec::new();
+        let mut adc = None;
+        for gpio_config in config {
+            let Some(analog) = gpio_config.analog else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            if adc.is_none() {
+                adc = Adc1::new();
+            }
+            let Some(ref mut unit) = adc else {
+                break;
+            };
+            match unit.configure_pin(gpio_config.pin) {
+                Some(adc_channel) => {
+                    let _ = channels.push(AnalogChannel {
+                        config: analog,
+                        adc_channel,
+                        neuron_id,
+                    });
+                }
+                None => fault!(PinInit, "GPIO {}: not an ADC1 pin, analog input disabled", gpio_config.pin),
+            }
+        }
+        Self { adc, channels }
+    }
+
+    /// Sample every analog input, returning (neuron_id, potential)
+    pub fn read_all(&mut self) -> Vec<(u32, f32), N> {
+        let mut readings = Vec::new();
+        if let Some(ref mut adc) = self.adc {
+            for channel in self.channels.iter() {
+                if let Some(raw) = adc.read_raw(channel.adc_channel) {
+                    let _ = readings.push((channel.neuron_id, channel.config.value(raw)));
+                }
+            }
+        }
+        readings
+    }
+}
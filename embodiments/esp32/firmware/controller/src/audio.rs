@@ -0,0 +1,367 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Audio output (buzzer feedback, alarms) driven by motor neurons
+//!
+//! With config.json `audio_output`, the board plays sounds from a small table
+//! defined in the config: a tone is either a sequence of notes (sine waves of
+//! a frequency and duration, frequency 0 = rest) or a raw sample file. Neuron
+//! `N + i` of the audio cortical area selects tone `i`:
+//!
+//! - A command above 0.5 starts the tone, unless it is already playing. When
+//!   several tones are commanded in the same burst, the lowest-numbered one
+//!   plays; a new tone cuts off the one playing.
+//! - A tone plays to its end, or with `"repeat": true` loops until its neuron
+//!   is commanded at 0.5 or below.
+//!
+//! Sound goes out through an I2S amplifier (MAX98357A and similar, 16-bit) or
+//! the ESP32's own 8-bit DAC on GPIO 25/26. Either way the DMA buffers hold
+//! two burst periods of samples, topped up once per burst, so the main loop
+//! never waits for the audio. Safe-stop silences it.
+
+use core::ffi::c_void;
+
+use esp_idf_svc::sys;
+
+use crate::cortical;
+use crate::{BURST_FREQUENCY_HZ, CAMERA, MICROPHONE};
+
+extern "C" {
+    // newlib's single-precision math (no libm in no_std)
+    fn sinf(x: f32) -> f32;
+}
+
+/// Most tones in the table (one bit each in the per-burst masks)
+pub const MAX_TONES: usize = 16;
+
+/// Samples rendered per DMA write
+const CHUNK: usize = 256;
+
+/// Sine table length (phase accumulator's top 8 bits)
+const SINE_SIZE: usize = 256;
+
+/// Where the samples go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioDriver {
+    /// I2S amplifier: bit clock, word select and data outputs
+    I2s { bck: u32, ws: u32, dout: u32 },
+    /// Internal DAC on GPIO 25 (channel 1) or 26 (channel 2)
+    Dac { pin: u32 },
+}
+
+impl AudioDriver {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioDriver::I2s { .. } => "i2s",
+            AudioDriver::Dac { .. } => "dac",
+        }
+    }
+}
+
+/// One entry of the tone table
+#[derive(Debug, Clone, Copy)]
+pub struct Tone {
+    /// (frequency Hz, duration ms) notes; frequency 0 = rest
+    pub notes: &'static [(u16, u16)],
+    /// Unsigned 8-bit mono PCM at the output's sample rate, instead of notes
+    pub sample: Option<&'static [u8]>,
+    /// Loop while the tone's neuron keeps firing
+    pub repeat: bool,
+}
+
+/// Audio output settings (from config.json `audio_output`)
+#[derive(Debug, Clone, Copy)]
+pub struct AudioConfig {
+    pub driver: AudioDriver,
+    pub sample_rate_hz: u32,
+    /// Output level, 0.0-1.0 of full scale
+    pub volume: f32,
+    /// "cortical_area:neuron_id" of tone 0
+    pub cortical_mapping: &'static str,
+    pub tones: &'static [Tone],
+}
+
+/// The DMA channel samples are written to
+enum Sink {
+    I2s(sys::i2s_chan_handle_t),
+    Dac(sys::dac_continuous_handle_t),
+}
+
+/// A running audio output
+pub struct AudioOutput {
+    config: AudioConfig,
+    sink: Sink,
+    first_neuron: u32,
+    sine: [i16; SINE_SIZE],
+    /// Volume as a multiplier over 256
+    gain: i32,
+    /// Tones commanded above / at or below 0.5 this burst
+    started: u32,
+    released: u32,
+    /// Tone being played, its current note (or sample offset) and the
+    /// samples left of that note
+    playing: Option<usize>,
+    position: usize,
+    note_left: u32,
+    phase: u32,
+    phase_step: u32,
+    /// Rendered samples the DMA hasn't taken yet
+    pending: [i16; CHUNK],
+    pending_at: usize,
+    pending_len: usize,
+}
+
+impl AudioOutput {
+    /// Start the output channel; None if the driver rejected the settings
+    pub fn new(config: &AudioConfig) -> Option<Self> {
+        let first_neuron = cortical::neuron_id(config.cortical_mapping)?;
+        // Two burst periods of samples, in DMA buffers of CHUNK frames
+        let frames = 2 * config.sample_rate_hz / BURST_FREQUENCY_HZ.max(1);
+        let descriptors = (frames as usize).div_ceil(CHUNK).clamp(2, 32) as u32;
+        let sink = match config.driver {
+            AudioDriver::I2s { bck, ws, dout } => Sink::I2s(start_i2s(config.sample_rate_hz, bck, ws, dout, descriptors)?),
+            AudioDriver::Dac { pin } => Sink::Dac(start_dac(config.sample_rate_hz, pin, descriptors)?),
+        };
+
+        let mut sine = [0i16; SINE_SIZE];
+        for (n, s) in sine.iter_mut().enumerate() {
+            let angle = 2.0 * core::f32::consts::PI * n as f32 / SINE_SIZE as f32;
+            *s = (unsafe { sinf(angle) } * i16::MAX as f32) as i16;
+        }
+        Some(Self {
+            config: *config,
+            sink,
+            first_neuron,
+            sine,
+            gain: (config.volume.clamp(0.0, 1.0) * 256.0) as i32,
+            started: 0,
+            released: 0,
+            playing: None,
+            position: 0,
+            note_left: 0,
+            phase: 0,
+            phase_step: 0,
+            pending: [0; CHUNK],
+            pending_at: 0,
+            pending_len: 0,
+        })
+    }
+
+    /// Record a motor command; returns true if `neuron_id` selects a tone
+    pub fn apply(&mut self, neuron_id: u32, value: f32) -> bool {
+        let Some(index) = neuron_id.checked_sub(self.first_neuron) else {
+            return false;
+        };
+        if index as usize >= self.config.tones.len().min(MAX_TONES) {
+            return false;
+        }
+        if value > 0.5 {
+            self.started |= 1 << index;
+        } else {
+            self.released |= 1 << index;
+        }
+        true
+    }
+
+    /// Silence the output until the next command
+    pub fn stop(&mut self) {
+        self.playing = None;
+        self.started = 0;
+        self.released = 0;
+        self.pending_at = 0;
+        self.pending_len = 0;
+    }
+
+    /// Act on this burst's commands and top up the DMA buffers
+    ///
+    /// Call once per burst, after the motor commands were applied.
+    pub fn refresh(&mut self) {
+        if let Some(tone) = self.playing {
+            if self.config.tones[tone].repeat && self.released & (1 << tone) != 0 {
+                self.playing = None;
+            }
+        }
+        if self.started != 0 {
+            let tone = self.started.trailing_zeros() as usize;
+            if self.playing != Some(tone) {
+                self.start(tone);
+            }
+        }
+        self.started = 0;
+        self.released = 0;
+
+        // Idle: the DMA plays out what it has, then silence
+        while self.playing.is_some() || self.pending_at < self.pending_len {
+            if self.pending_at == self.pending_len {
+                self.render();
+            }
+            let written = self.write(self.pending_at, self.pending_len);
+            self.pending_at += written;
+            if self.pending_at < self.pending_len {
+                // DMA full until the next burst
+                break;
+            }
+        }
+    }
+
+    fn start(&mut self, tone: usize) {
+        self.playing = Some(tone);
+        self.position = 0;
+        self.phase = 0;
+        self.load_note();
+    }
+
+    /// Set up the note at `position` of the playing tone
+    fn load_note(&mut self) {
+        let Some(tone) = self.playing else {
+            return;
+        };
+        if let Some(&(freq_hz, ms)) = self.config.tones[tone].notes.get(self.position) {
+            self.note_left = (self.config.sample_rate_hz as u64 * ms as u64 / 1000) as u32;
+            self.phase_step = ((freq_hz as u64) << 32).checked_div(self.config.sample_rate_hz as u64).unwrap_or(0) as u32;
+        }
+    }
+
+    /// Fill `pending` with the next CHUNK samples of the playing tone
+    fn render(&mut self) {
+        self.pending_at = 0;
+        self.pending_len = 0;
+        let tones = self.config.tones;
+        while self.pending_len < CHUNK {
+            let Some(tone) = self.playing.map(|t| &tones[t]) else {
+                break;
+            };
+            let sample = match tone.sample {
+                Some(pcm) => match pcm.get(self.position) {
+                    Some(&byte) => {
+                        self.position += 1;
+                        Some(((byte as i32 - 128) << 8) as i16)
+                    }
+                    None => None,
+                },
+                None if self.position < tone.notes.len() => {
+                    if self.note_left == 0 {
+                        self.position += 1;
+                        self.load_note();
+                        continue;
+                    }
+                    self.note_left -= 1;
+                    let value = if self.phase_step == 0 { 0 } else { self.sine[(self.phase >> 24) as usize] };
+                    self.phase = self.phase.wrapping_add(self.phase_step);
+                    Some(value)
+                }
+                None => None,
+            };
+            match sample {
+                Some(value) => {
+                    self.pending[self.pending_len] = (value as i32 * self.gain / 256) as i16;
+                    self.pending_len += 1;
+                }
+                None if tone.repeat => self.start(self.playing.unwrap_or(0)),
+                None => self.playing = None,
+            }
+        }
+    }
+
+    /// Hand `pending[from..to]` to the DMA without waiting; samples taken
+    fn write(&self, from: usize, to: usize) -> usize {
+        let mut loaded: usize = 0;
+        match self.sink {
+            Sink::I2s(channel) => {
+                unsafe {
+                    sys::i2s_channel_write(
+                        channel,
+                        self.pending[from..to].as_ptr() as *const c_void,
+                        (to - from) * core::mem::size_of::<i16>(),
+                        &mut loaded,
+                        0,
+                    );
+                }
+                loaded / core::mem::size_of::<i16>()
+            }
+            Sink::Dac(handle) => {
+                let mut bytes = [0u8; CHUNK];
+                for (byte, &sample) in bytes.iter_mut().zip(&self.pending[from..to]) {
+                    *byte = ((sample >> 8) + 128) as u8;
+                }
+                unsafe {
+                    sys::dac_continuous_write(handle, bytes.as_mut_ptr(), to - from, &mut loaded, 0);
+                }
+                loaded
+            }
+        }
+    }
+}
+
+/// Create, configure and enable the I2S TX channel (master, Philips, 16-bit)
+fn start_i2s(sample_rate_hz: u32, bck: u32, ws: u32, dout: u32, descriptors: u32) -> Option<sys::i2s_chan_handle_t> {
+    let mut channel: sys::i2s_chan_handle_t = core::ptr::null_mut();
+    unsafe {
+        let mut chan_cfg: sys::i2s_chan_config_t = core::mem::zeroed();
+        // I2S0 belongs to the camera, or the microphone if there's no camera
+        chan_cfg.id = if CAMERA.is_some() || MICROPHONE.is_some() { 1 } else { sys::i2s_port_t_I2S_NUM_0 };
+        chan_cfg.role = sys::i2s_role_t_I2S_ROLE_MASTER;
+        chan_cfg.dma_desc_num = descriptors;
+        chan_cfg.dma_frame_num = CHUNK as u32;
+        // Silence, not the last buffer over and over, when starved
+        chan_cfg.auto_clear = true;
+        if sys::i2s_new_channel(&chan_cfg, &mut channel, core::ptr::null_mut()) != sys::ESP_OK {
+            return None;
+        }
+
+        let mut std_cfg: sys::i2s_std_config_t = core::mem::zeroed();
+        std_cfg.clk_cfg.sample_rate_hz = sample_rate_hz;
+        std_cfg.clk_cfg.clk_src = sys::soc_periph_i2s_clk_src_t_I2S_CLK_SRC_DEFAULT;
+        std_cfg.clk_cfg.mclk_multiple = sys::i2s_mclk_multiple_t_I2S_MCLK_MULTIPLE_256;
+        std_cfg.slot_cfg.data_bit_width = sys::i2s_data_bit_width_t_I2S_DATA_BIT_WIDTH_16BIT;
+        std_cfg.slot_cfg.slot_bit_width = sys::i2s_slot_bit_width_t_I2S_SLOT_BIT_WIDTH_AUTO;
+        std_cfg.slot_cfg.slot_mode = sys::i2s_slot_mode_t_I2S_SLOT_MODE_MONO;
+        // Same sample in both slots, so a mono amplifier plays it either way
+        std_cfg.slot_cfg.slot_mask = sys::i2s_std_slot_mask_t_I2S_STD_SLOT_BOTH;
+        std_cfg.slot_cfg.ws_width = 16;
+        std_cfg.slot_cfg.bit_shift = true;
+        std_cfg.gpio_cfg.mclk = -1;
+        std_cfg.gpio_cfg.bclk = bck as i32;
+        std_cfg.gpio_cfg.ws = ws as i32;
+        std_cfg.gpio_cfg.dout = dout as i32;
+        std_cfg.gpio_cfg.din = -1;
+        if sys::i2s_channel_init_std_mode(channel, &std_cfg) != sys::ESP_OK
+            || sys::i2s_channel_enable(channel) != sys::ESP_OK
+        {
+            sys::i2s_del_channel(channel);
+            return None;
+        }
+    }
+    Some(channel)
+}
+
+/// Create and enable a continuous (DMA) DAC channel on GPIO 25 or 26
+fn start_dac(sample_rate_hz: u32, pin: u32, descriptors: u32) -> Option<sys::dac_continuous_handle_t> {
+    let mut handle: sys::dac_continuous_handle_t = core::ptr::null_mut();
+    unsafe {
+        let mut dac_cfg: sys::dac_continuous_config_t = core::mem::zeroed();
+        dac_cfg.chan_mask = if pin == 26 {
+            sys::dac_channel_mask_t_DAC_CHANNEL_MASK_CH1
+        } else {
+            sys::dac_channel_mask_t_DAC_CHANNEL_MASK_CH0
+        };
+        dac_cfg.desc_num = descriptors;
+        dac_cfg.buf_size = CHUNK;
+        dac_cfg.freq_hz = sample_rate_hz;
+        dac_cfg.offset = 0;
+        dac_cfg.clk_src = sys::soc_periph_dac_digi_clk_src_t_DAC_DIGI_CLK_SRC_DEFAULT;
+        dac_cfg.chan_mode = sys::dac_continuous_channel_mode_t_DAC_CHANNEL_MODE_SIMUL;
+        if sys::dac_continuous_new_channels(&dac_cfg, &mut handle) != sys::ESP_OK {
+            return None;
+        }
+        if sys::dac_continuous_enable(handle) != sys::ESP_OK {
+            sys::dac_continuous_del_channels(handle);
+            return None;
+        }
+    }
+    Some(handle)
+}
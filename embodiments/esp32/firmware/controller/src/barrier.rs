@@ -0,0 +1,140 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Barrier-synchronized actuation for multi-board robots
+//!
+//! When several boards drive halves of one robot, motor commands for a burst
+//! are staged instead of applied immediately. The gateway/host sends a barrier
+//! message (`{"b":burst_id}`) once every board has received its commands, and
+//! all boards apply their staged commands together. If the barrier doesn't
+//! arrive within the timeout, the staged commands are applied anyway so a lost
+//! barrier never freezes the robot.
+//!
+//! A barrier whose burst id isn't newer than the last one released (a
+//! duplicate, or one overtaken on the way) releases nothing; a jump back by
+//! more than `STALE_WINDOW` bursts is taken as a restarted host and accepted.
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+use serde::Deserialize;
+
+use crate::outputs::OutputBank;
+use crate::warn;
+
+/// Bursts back from the last release within which a barrier is stale
+const STALE_WINDOW: u32 = 1000;
+
+/// `{"b":burst_id}`
+#[derive(Deserialize)]
+struct BarrierMessage {
+    b: u32,
+}
+
+/// Burst id of a barrier message from the gateway/host
+pub fn parse(line: &str) -> Option<u32> {
+    serde_json_core::from_str::<BarrierMessage>(line).ok().map(|(message, _)| message.b)
+}
+
+/// Staged motor commands waiting for a barrier release
+///
+/// `N` bounds the distinct neurons staged per burst (every motor neuron).
+pub struct Barrier<const N: usize> {
+    pending: Vec<(u32, f32), N>,
+    /// esp_timer timestamp (µs) of the first command staged for this burst
+    staged_at_us: Option<i64>,
+    timeout_us: i64,
+    /// Burst id of the last barrier released
+    last_burst: Option<u32>,
+    /// Number of bursts applied because the barrier timed out
+    pub timeouts: u32,
+}
+
+impl<const N: usize> Barrier<N> {
+    pub fn new(timeout_ms: u32) -> Self {
+        Self {
+            pending: Vec::new(),
+            staged_at_us: None,
+            timeout_us: timeout_ms as i64 * 1000,
+            last_burst: None,
+            timeouts: 0,
+        }
+    }
+
+    /// Change the timeout (host `set_settings`); applies from the next burst
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_us = timeout_ms as i64 * 1000;
+    }
+
+    /// Stage a motor command until the next barrier
+    ///
+    /// A later command for the same neuron replaces the earlier one.
+    pub fn stage(&mut self, neuron_id: u32, value: f32) {
+        if self.staged_at_us.is_none() {
+            self.staged_at_us = Some(unsafe { sys::esp_timer_get_time() });
+        }
+        if let Some(entry) = self.pending.iter_mut().find(|(id, _)| *id == neuron_id) {
+            entry.1 = value;
+            return;
+        }
+        if self.pending.push((neuron_id, value)).is_err() {
+            // Staging buffer full: more distinct neurons than one burst can hold
+            warn!("Barrier staging buffer full, dropping command");
+        }
+    }
+
+    /// Barrier for `burst_id` received: apply every staged command now,
+    /// unless the barrier is stale; false if it was
+    pub fn release_burst<const M: usize>(&mut self, burst_id: u32, outputs: &mut OutputBank<M>) -> bool {
+        if self.last_burst.is_some_and(|last| last.wrapping_sub(burst_id) < STALE_WINDOW) {
+            return false;
+        }
+        self.last_burst = Some(burst_id);
+        self.release(outputs);
+        true
+    }
+
+    /// Apply every staged command now
+    fn release<const M: usize>(&mut self, outputs: &mut OutputBank<M>) {
+        for (neuron_id, value) in self.pending.iter() {
+            outputs.apply(*neuron_id, *value);
+        }
+        self.pending.clear();
+        self.staged_at_us = None;
+    }
+
+    /// Drop every staged command without applying it (safe-stop, a new
+    /// link), and start over with the burst ids
+    pub fn discard(&mut self) {
+        self.pending.clear();
+        self.staged_at_us = None;
+        self.last_burst = None;
+    }
+
+    /// Milliseconds until the staged commands time out (0 when overdue);
+    /// None when nothing is staged
+    pub fn remaining_ms(&self) -> Option<u32> {
+        let staged_at = self.staged_at_us?;
+        let elapsed = unsafe { sys::esp_timer_get_time() } - staged_at;
+        Some(((self.timeout_us - elapsed).max(0) / 1000) as u32)
+    }
+
+    /// Apply staged commands if the barrier hasn't arrived in time
+    ///
+    /// Returns true if a timeout release happened.
+    pub fn poll_timeout<const M: usize>(&mut self, outputs: &mut OutputBank<M>) -> bool {
+        let Some(staged_at) = self.staged_at_us else {
+            return false;
+        };
+        let now = unsafe { sys::esp_timer_get_time() };
+        if now - staged_at < self.timeout_us {
+            return false;
+        }
+        self.timeouts = self.timeouts.wrapping_add(1);
+        self.release(outputs);
+        true
+    }
+}
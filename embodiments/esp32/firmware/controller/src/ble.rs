@@ -0,0 +1,559 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! BLE Nordic UART Service transport (NimBLE)
+//!
+//! With `"transport": {"type": "bluetooth", ...}` the board advertises the
+//! Nordic UART Service like the micro:bit does, so the FEAGI desktop BLE
+//! bridge treats both boards the same. The lines are the serial path's JSON
+//! lines, split into notification-sized chunks on the TX characteristic and
+//! read back from writes to the RX characteristic. A central that writes the
+//! segmentation request gets segments both ways instead (the feagi-link
+//! crate, same format as the micro:bit) until the next central subscribes.
+//!
+//! NimBLE runs its host on its own FreeRTOS task: GATT writes are copied into
+//! a queue and reassembled on the burst loop's side in `read`. Lines written
+//! while no central has notifications enabled are dropped, as serial output
+//! is with no host attached; the hello is replayed to each central that
+//! subscribes later.
+//!
+//! With a `wifi` block in `transport.config`, a second service lets the
+//! FEAGI desktop app provision the board: it writes the SSID, password and
+//! FEAGI `host:port`, then 0x01 to the apply characteristic. Valid
+//! credentials are stored in NVS (provisioning.rs) and the burst loop
+//! switches to the WiFi transport; reading the apply characteristic gives
+//! the outcome.
+
+use core::cell::RefCell;
+use core::ffi::{c_char, c_void};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+use critical_section::Mutex;
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+use feagi_link::segment::{self, Mtu, Reassembler, Received, SEGMENTATION_ACK};
+use heapless::Vec;
+
+use crate::provisioning::{init_nvs, Credentials};
+use crate::transport::{FeagiTransport, Transport, TransportStatus};
+use crate::RX_LINE_CAPACITY;
+
+/// Nordic UART Service UUIDs, little-endian as NimBLE stores them
+/// (6E400001/2/3-B5A3-F393-E0A9-E50E24DCCA9E)
+const fn nus_uuid(id: u8) -> sys::ble_uuid128_t {
+    sys::ble_uuid128_t {
+        u: sys::ble_uuid_t { type_: sys::BLE_UUID_TYPE_128 as u8 },
+        value: [0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0, 0x93, 0xf3, 0xa3, 0xb5, id, 0x00, 0x40, 0x6e],
+    }
+}
+static NUS_SERVICE_UUID: sys::ble_uuid128_t = nus_uuid(0x01);
+/// Central writes here
+static NUS_RX_CHAR_UUID: sys::ble_uuid128_t = nus_uuid(0x02);
+/// Board notifies here
+static NUS_TX_CHAR_UUID: sys::ble_uuid128_t = nus_uuid(0x03);
+
+/// FEAGI WiFi provisioning UUIDs, little-endian
+/// (FEA600xx-5E1F-4C2B-9A7D-3B8E6F0D2C41)
+const fn provisioning_uuid(id: u8) -> sys::ble_uuid128_t {
+    sys::ble_uuid128_t {
+        u: sys::ble_uuid_t { type_: sys::BLE_UUID_TYPE_128 as u8 },
+        value: [0x41, 0x2c, 0x0d, 0x6f, 0x8e, 0x3b, 0x7d, 0x9a, 0x2b, 0x4c, 0x1f, 0x5e, id, 0x00, 0xa6, 0xfe],
+    }
+}
+static PROVISIONING_SERVICE_UUID: sys::ble_uuid128_t = provisioning_uuid(0x01);
+static PROVISIONING_SSID_CHAR_UUID: sys::ble_uuid128_t = provisioning_uuid(0x02);
+static PROVISIONING_PASSWORD_CHAR_UUID: sys::ble_uuid128_t = provisioning_uuid(0x03);
+/// FEAGI (or MQTT broker) address as `a.b.c.d:port`
+static PROVISIONING_ENDPOINT_CHAR_UUID: sys::ble_uuid128_t = provisioning_uuid(0x04);
+/// Write 0x01 to store and switch; read for a `ProvisioningStatus`
+static PROVISIONING_APPLY_CHAR_UUID: sys::ble_uuid128_t = provisioning_uuid(0x05);
+
+/// Outcome of the last apply, as read from the apply characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum ProvisioningStatus {
+    Waiting = 0,
+    /// Stored; the board is switching to WiFi
+    Stored = 1,
+    /// Missing SSID, or endpoint not `a.b.c.d:port`
+    Invalid = 2,
+    /// NVS write failed
+    StoreFailed = 3,
+}
+
+/// Largest notification/write payload (ATT MTU 247 - 3)
+const SEGMENT_MAX: usize = 244;
+
+/// GATT writes waiting for the burst loop
+const RX_QUEUE_DEPTH: u32 = 16;
+
+/// Before MTU exchange
+const DEFAULT_ATT_MTU: u16 = 23;
+
+/// Notifications are retried this many times (1 ms apart) while NimBLE is out
+/// of buffers
+const NOTIFY_RETRIES: u32 = 20;
+
+/// Advertised name and provisioning (from config.json `transport.config`)
+#[derive(Debug, Clone, Copy)]
+pub struct BleConfig {
+    /// At most 26 bytes, so it fits the advertising packet with the flags
+    pub device_name: &'static str,
+    /// Offer the WiFi provisioning service (`WIFI_CONFIG` holds the rest)
+    pub wifi_provisioning: bool,
+    /// Held low at boot: stay on BLE instead of using stored credentials
+    pub button_pin: Option<i32>,
+}
+
+/// Values written to the provisioning characteristics, not yet applied
+#[derive(Clone)]
+struct StagedCredentials {
+    ssid: Vec<u8, 32>,
+    password: Vec<u8, 64>,
+    endpoint: Vec<u8, 21>,
+}
+
+/// One GATT write, as queued for the burst loop
+#[repr(C)]
+struct RxSegment {
+    len: u16,
+    data: [u8; SEGMENT_MAX],
+}
+
+// State shared with the NimBLE host task
+static RX_QUEUE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+static CONN_HANDLE: AtomicU16 = AtomicU16::new(sys::BLE_HS_CONN_HANDLE_NONE as u16);
+static ATT_MTU: AtomicU16 = AtomicU16::new(DEFAULT_ATT_MTU);
+static NOTIFY_ENABLED: AtomicBool = AtomicBool::new(false);
+/// Bumped whenever a central enables notifications
+static SUBSCRIPTIONS: AtomicU32 = AtomicU32::new(0);
+/// Written by NimBLE when the service is registered
+static TX_VAL_HANDLE: AtomicU16 = AtomicU16::new(0);
+static OWN_ADDR_TYPE: AtomicU8 = AtomicU8::new(0);
+static PROVISIONING_STATUS: AtomicU8 = AtomicU8::new(ProvisioningStatus::Waiting as u8);
+
+// GATT table; NimBLE keeps pointers into it. Filled in once by `Ble::start`
+// before the host task runs and never touched again.
+static mut NUS_CHARACTERISTICS: [sys::ble_gatt_chr_def; 3] = unsafe { core::mem::zeroed() };
+static mut PROVISIONING_CHARACTERISTICS: [sys::ble_gatt_chr_def; 5] = unsafe { core::mem::zeroed() };
+static mut SERVICES: [sys::ble_gatt_svc_def; 3] = unsafe { core::mem::zeroed() };
+
+// Written from GATT callbacks; locked so nothing relies on NimBLE keeping
+// them on one task
+static STAGED: Mutex<RefCell<StagedCredentials>> = Mutex::new(RefCell::new(StagedCredentials {
+    ssid: Vec::new(),
+    password: Vec::new(),
+    endpoint: Vec::new(),
+}));
+
+/// The NimBLE host with the NUS service registered, brought up once at boot
+pub struct Ble {
+    queue: sys::QueueHandle_t,
+}
+
+impl Ble {
+    /// Initialize NimBLE, register the NUS service and start advertising
+    ///
+    /// Returns None if the stack couldn't be brought up. No central being
+    /// connected yet is not an error.
+    pub fn start(config: BleConfig) -> Option<Self> {
+        unsafe {
+            // PHY calibration data lives in NVS
+            if !init_nvs() {
+                return None;
+            }
+
+            let queue = sys::xQueueGenericCreate(RX_QUEUE_DEPTH, size_of::<RxSegment>() as u32, 0);
+            if queue.is_null() {
+                return None;
+            }
+            RX_QUEUE.store(queue, Ordering::Release);
+
+            if sys::nimble_port_init() != sys::ESP_OK {
+                return None;
+            }
+            sys::ble_hs_cfg.sync_cb = Some(on_sync);
+            sys::ble_hs_cfg.reset_cb = Some(on_reset);
+            sys::ble_svc_gap_init();
+            sys::ble_svc_gatt_init();
+
+            let characteristics = &mut *core::ptr::addr_of_mut!(NUS_CHARACTERISTICS);
+            characteristics[0].uuid = &NUS_RX_CHAR_UUID.u;
+            characteristics[0].access_cb = Some(nus_access);
+            characteristics[0].flags = (sys::BLE_GATT_CHR_F_WRITE | sys::BLE_GATT_CHR_F_WRITE_NO_RSP) as _;
+            characteristics[1].uuid = &NUS_TX_CHAR_UUID.u;
+            characteristics[1].access_cb = Some(nus_access);
+            characteristics[1].flags = sys::BLE_GATT_CHR_F_NOTIFY as _;
+            characteristics[1].val_handle = TX_VAL_HANDLE.as_ptr();
+            let services = &mut *core::ptr::addr_of_mut!(SERVICES);
+            services[0].type_ = sys::BLE_GATT_SVC_TYPE_PRIMARY as u8;
+            services[0].uuid = &NUS_SERVICE_UUID.u;
+            services[0].characteristics = characteristics.as_ptr();
+            if config.wifi_provisioning {
+                let provisioning = &mut *core::ptr::addr_of_mut!(PROVISIONING_CHARACTERISTICS);
+                let uuids = [
+                    &PROVISIONING_SSID_CHAR_UUID,
+                    &PROVISIONING_PASSWORD_CHAR_UUID,
+                    &PROVISIONING_ENDPOINT_CHAR_UUID,
+                    &PROVISIONING_APPLY_CHAR_UUID,
+                ];
+                for (characteristic, uuid) in provisioning.iter_mut().zip(uuids) {
+                    characteristic.uuid = &uuid.u;
+                    characteristic.access_cb = Some(provisioning_access);
+                    characteristic.flags = sys::BLE_GATT_CHR_F_WRITE as _;
+                }
+                provisioning[3].flags = (sys::BLE_GATT_CHR_F_WRITE | sys::BLE_GATT_CHR_F_READ) as _;
+                services[1].type_ = sys::BLE_GATT_SVC_TYPE_PRIMARY as u8;
+                services[1].uuid = &PROVISIONING_SERVICE_UUID.u;
+                services[1].characteristics = provisioning.as_ptr();
+            }
+            if sys::ble_gatts_count_cfg(services.as_ptr()) != 0 || sys::ble_gatts_add_svcs(services.as_ptr()) != 0 {
+                return None;
+            }
+
+            let mut name: Vec<u8, 32> = Vec::new();
+            name.extend_from_slice(config.device_name.as_bytes()).ok()?;
+            name.push(0).ok()?;
+            sys::ble_svc_gap_device_name_set(name.as_ptr() as *const c_char);
+            // Lets a 244-byte segment go out in one notification
+            sys::ble_att_set_preferred_mtu(SEGMENT_MAX as u16 + 3);
+
+            // Advertising starts from on_sync once the host and controller agree
+            sys::nimble_port_freertos_init(Some(host_task));
+            Some(Self { queue })
+        }
+    }
+
+    /// Credentials stored through the provisioning service, once applied
+    pub fn provisioned(&self) -> Option<Credentials> {
+        if PROVISIONING_STATUS.load(Ordering::Acquire) != ProvisioningStatus::Stored as u8 {
+            return None;
+        }
+        Credentials::load()
+    }
+
+    /// Shut NimBLE and the controller down and give their memory back (the
+    /// board has switched to WiFi)
+    pub fn stop(self) {
+        unsafe {
+            if sys::nimble_port_stop() == 0 {
+                sys::nimble_port_deinit();
+                sys::esp_bt_controller_mem_release(sys::esp_bt_mode_t_ESP_BT_MODE_BTDM);
+            }
+            RX_QUEUE.store(core::ptr::null_mut(), Ordering::Release);
+            sys::vQueueDelete(self.queue);
+        }
+    }
+
+    /// A fresh transport on the running stack (NimBLE itself is never
+    /// restarted; queued partial frames are discarded)
+    pub fn open_transport(&self) -> Option<Transport> {
+        unsafe {
+            sys::xQueueGenericReset(self.queue, 0);
+        }
+        Some(Transport::Ble(BleLink {
+            queue: self.queue,
+            reassembler: Reassembler::new(),
+            line: Vec::new(),
+            line_pos: 0,
+            greeting: Vec::new(),
+            greeted: SUBSCRIPTIONS.load(Ordering::Relaxed),
+        }))
+    }
+}
+
+/// The burst loop's end of the NUS link
+pub struct BleLink {
+    queue: sys::QueueHandle_t,
+    reassembler: Reassembler<RX_LINE_CAPACITY>,
+    /// Last write, or reassembled line (newline-terminated), handed out from
+    /// `line_pos`
+    line: Vec<u8, { RX_LINE_CAPACITY + 1 }>,
+    line_pos: usize,
+    /// First line written (the hello), replayed to later centrals
+    greeting: Vec<u8, 256>,
+    /// SUBSCRIPTIONS count already greeted
+    greeted: u32,
+}
+
+// Notifications are queued by NimBLE: nothing for `flush` to wait for
+impl FeagiTransport for BleLink {
+    /// Notify one line in chunks (or segments) of the negotiated MTU; false
+    /// only if NimBLE failed (no subscribed central is not a failure)
+    fn send_frame(&mut self, data: &[u8]) -> bool {
+        if self.greeting.is_empty() && self.greeting.extend_from_slice(data).is_err() {
+            self.greeting.clear();
+        }
+        let conn = CONN_HANDLE.load(Ordering::Acquire);
+        if conn == sys::BLE_HS_CONN_HANDLE_NONE as u16 || !NOTIFY_ENABLED.load(Ordering::Acquire) {
+            return true;
+        }
+        let mtu = Mtu::ble(ATT_MTU.load(Ordering::Relaxed));
+        let mut packet: Vec<u8, SEGMENT_MAX> = Vec::new();
+        let writes = if self.reassembler.segmented() {
+            segment::segments(data, mtu)
+        } else {
+            segment::chunks(data, mtu)
+        };
+        for seg in writes {
+            if !seg.write_to(&mut packet) || !notify(conn, &packet) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Read reassembled lines, waiting up to `timeout_ms` for a GATT write;
+    /// Ok(0) on timeout
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        let subscriptions = SUBSCRIPTIONS.load(Ordering::Relaxed);
+        if subscriptions != self.greeted {
+            // A new central starts unsegmented
+            self.greeted = subscriptions;
+            self.reassembler = Reassembler::new();
+            let greeting = self.greeting.clone();
+            self.send_frame(&greeting);
+        }
+
+        if self.line_pos == self.line.len() {
+            let mut ticks = (timeout_ms * sys::configTICK_RATE_HZ / 1000).max(1);
+            let mut segment = RxSegment { len: 0, data: [0; SEGMENT_MAX] };
+            while unsafe { sys::xQueueReceive(self.queue, &mut segment as *mut RxSegment as *mut c_void, ticks) } == 1 {
+                // Drain what's queued without waiting again
+                ticks = 0;
+                let segmented = self.reassembler.segmented();
+                match self.reassembler.push(&segment.data[..segment.len as usize]) {
+                    Received::Frame(frame) => {
+                        self.line.clear();
+                        let _ = self.line.extend_from_slice(frame);
+                        // Unsegmented writes are pieces of the line stream
+                        if segmented && self.line.last() != Some(&b'\n') {
+                            let _ = self.line.push(b'\n');
+                        }
+                        self.line_pos = 0;
+                        break;
+                    }
+                    Received::SegmentationRequested => {
+                        let conn = CONN_HANDLE.load(Ordering::Acquire);
+                        if conn != sys::BLE_HS_CONN_HANDLE_NONE as u16 {
+                            notify(conn, &SEGMENTATION_ACK);
+                        }
+                    }
+                    Received::Nothing => {}
+                }
+            }
+        }
+
+        let rest = &self.line[self.line_pos..];
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.line_pos += n;
+        Ok(n)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus {
+            kind: "bluetooth",
+            connected: CONN_HANDLE.load(Ordering::Acquire) != sys::BLE_HS_CONN_HANDLE_NONE as u16
+                && NOTIFY_ENABLED.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Send one notification, waiting briefly for NimBLE buffers if needed
+fn notify(conn: u16, data: &[u8]) -> bool {
+    for _ in 0..NOTIFY_RETRIES {
+        let rc = unsafe {
+            // The mbuf is consumed by the notify call, sent or not
+            let om = sys::ble_hs_mbuf_from_flat(data.as_ptr() as *const c_void, data.len() as u16);
+            if om.is_null() {
+                sys::BLE_HS_ENOMEM as i32
+            } else {
+                sys::ble_gatts_notify_custom(conn, TX_VAL_HANDLE.load(Ordering::Relaxed), om)
+            }
+        };
+        match rc as u32 {
+            0 => return true,
+            // The central left mid-line: nothing to deliver to
+            sys::BLE_HS_ENOTCONN => return true,
+            sys::BLE_HS_ENOMEM => FreeRtos::delay_ms(1),
+            _ => return false,
+        }
+    }
+    false
+}
+
+unsafe extern "C" fn host_task(_arg: *mut c_void) {
+    sys::nimble_port_run();
+    sys::nimble_port_freertos_deinit();
+}
+
+unsafe extern "C" fn on_sync() {
+    let mut addr_type: u8 = 0;
+    if sys::ble_hs_id_infer_auto(0, &mut addr_type) == 0 {
+        OWN_ADDR_TYPE.store(addr_type, Ordering::Relaxed);
+        advertise();
+    }
+}
+
+unsafe extern "C" fn on_reset(_reason: i32) {
+    CONN_HANDLE.store(sys::BLE_HS_CONN_HANDLE_NONE as u16, Ordering::Release);
+    NOTIFY_ENABLED.store(false, Ordering::Release);
+}
+
+/// Advertise the name, with the NUS UUID in the scan response (both don't
+/// fit one 31-byte packet)
+unsafe fn advertise() {
+    let name = sys::ble_svc_gap_device_name();
+    let mut fields: sys::ble_hs_adv_fields = core::mem::zeroed();
+    fields.flags = (sys::BLE_HS_ADV_F_DISC_GEN | sys::BLE_HS_ADV_F_BREDR_UNSUP) as u8;
+    fields.name = name as *const u8;
+    fields.name_len = core::ffi::CStr::from_ptr(name).to_bytes().len() as u8;
+    fields.set_name_is_complete(1);
+    if sys::ble_gap_adv_set_fields(&fields) != 0 {
+        return;
+    }
+
+    let mut response: sys::ble_hs_adv_fields = core::mem::zeroed();
+    response.uuids128 = &NUS_SERVICE_UUID;
+    response.num_uuids128 = 1;
+    response.set_uuids128_is_complete(1);
+    if sys::ble_gap_adv_rsp_set_fields(&response) != 0 {
+        return;
+    }
+
+    let mut params: sys::ble_gap_adv_params = core::mem::zeroed();
+    params.conn_mode = sys::BLE_GAP_CONN_MODE_UND as u8;
+    params.disc_mode = sys::BLE_GAP_DISC_MODE_GEN as u8;
+    sys::ble_gap_adv_start(
+        OWN_ADDR_TYPE.load(Ordering::Relaxed),
+        core::ptr::null(),
+        sys::BLE_HS_FOREVER as i32,
+        &params,
+        Some(gap_event),
+        core::ptr::null_mut(),
+    );
+}
+
+unsafe extern "C" fn gap_event(event: *mut sys::ble_gap_event, _arg: *mut c_void) -> i32 {
+    let event = &*event;
+    match event.type_ as u32 {
+        sys::BLE_GAP_EVENT_CONNECT => {
+            let connect = &event.__bindgen_anon_1.connect;
+            if connect.status == 0 {
+                CONN_HANDLE.store(connect.conn_handle, Ordering::Release);
+            } else {
+                advertise();
+            }
+        }
+        sys::BLE_GAP_EVENT_DISCONNECT => {
+            CONN_HANDLE.store(sys::BLE_HS_CONN_HANDLE_NONE as u16, Ordering::Release);
+            NOTIFY_ENABLED.store(false, Ordering::Release);
+            ATT_MTU.store(DEFAULT_ATT_MTU, Ordering::Relaxed);
+            advertise();
+        }
+        sys::BLE_GAP_EVENT_ADV_COMPLETE => advertise(),
+        sys::BLE_GAP_EVENT_MTU => ATT_MTU.store(event.__bindgen_anon_1.mtu.value, Ordering::Relaxed),
+        sys::BLE_GAP_EVENT_SUBSCRIBE => {
+            let subscribe = &event.__bindgen_anon_1.subscribe;
+            if subscribe.attr_handle == TX_VAL_HANDLE.load(Ordering::Relaxed) {
+                let enabled = subscribe.cur_notify() != 0;
+                NOTIFY_ENABLED.store(enabled, Ordering::Release);
+                if enabled {
+                    SUBSCRIPTIONS.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        _ => {}
+    }
+    0
+}
+
+/// GATT access to the NUS characteristics: queue writes to RX
+unsafe extern "C" fn nus_access(
+    _conn_handle: u16,
+    _attr_handle: u16,
+    ctxt: *mut sys::ble_gatt_access_ctxt,
+    _arg: *mut c_void,
+) -> i32 {
+    if (*ctxt).op as u32 != sys::BLE_GATT_ACCESS_OP_WRITE_CHR {
+        return sys::BLE_ATT_ERR_UNLIKELY as i32;
+    }
+    let mut segment = RxSegment { len: 0, data: [0; SEGMENT_MAX] };
+    if sys::ble_hs_mbuf_to_flat((*ctxt).om, segment.data.as_mut_ptr() as *mut c_void, SEGMENT_MAX as u16, &mut segment.len) != 0 {
+        return sys::BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as i32;
+    }
+    // A full queue drops the segment, and the reassembler then the frame
+    sys::xQueueGenericSend(RX_QUEUE.load(Ordering::Acquire), &segment as *const RxSegment as *const c_void, 0, 0);
+    0
+}
+
+/// GATT access to the provisioning characteristics: stage the values,
+/// validate and store them on apply, report the outcome on read
+unsafe extern "C" fn provisioning_access(
+    _conn_handle: u16,
+    _attr_handle: u16,
+    ctxt: *mut sys::ble_gatt_access_ctxt,
+    _arg: *mut c_void,
+) -> i32 {
+    let ctxt = &*ctxt;
+    let uuid = (*ctxt.__bindgen_anon_1.chr).uuid;
+    let is = |candidate: &sys::ble_uuid128_t| sys::ble_uuid_cmp(uuid, &candidate.u) == 0;
+
+    if ctxt.op as u32 == sys::BLE_GATT_ACCESS_OP_READ_CHR {
+        let status = PROVISIONING_STATUS.load(Ordering::Acquire);
+        return if sys::os_mbuf_append(ctxt.om, &status as *const u8 as *const c_void, 1) == 0 {
+            0
+        } else {
+            sys::BLE_ATT_ERR_INSUFFICIENT_RES as i32
+        };
+    }
+    if ctxt.op as u32 != sys::BLE_GATT_ACCESS_OP_WRITE_CHR {
+        return sys::BLE_ATT_ERR_UNLIKELY as i32;
+    }
+
+    let mut value = [0u8; 64];
+    let mut len: u16 = 0;
+    if sys::ble_hs_mbuf_to_flat(ctxt.om, value.as_mut_ptr() as *mut c_void, value.len() as u16, &mut len) != 0 {
+        return sys::BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as i32;
+    }
+    let value = &value[..len as usize];
+    let stored = if is(&PROVISIONING_APPLY_CHAR_UUID) {
+        if value != [0x01] {
+            return sys::BLE_ATT_ERR_UNLIKELY as i32;
+        }
+        // Copied out so the NVS write happens outside the lock
+        let staged = critical_section::with(|cs| STAGED.borrow_ref(cs).clone());
+        let status = match Credentials::from_parts(&staged.ssid, &staged.password, &staged.endpoint) {
+            Some(credentials) if credentials.store() => ProvisioningStatus::Stored,
+            Some(_) => ProvisioningStatus::StoreFailed,
+            None => ProvisioningStatus::Invalid,
+        };
+        PROVISIONING_STATUS.store(status as u8, Ordering::Release);
+        true
+    } else {
+        critical_section::with(|cs| {
+            let mut staged = STAGED.borrow_ref_mut(cs);
+            if is(&PROVISIONING_SSID_CHAR_UUID) {
+                staged.ssid.clear();
+                staged.ssid.extend_from_slice(value).is_ok()
+            } else if is(&PROVISIONING_PASSWORD_CHAR_UUID) {
+                staged.password.clear();
+                staged.password.extend_from_slice(value).is_ok()
+            } else {
+                staged.endpoint.clear();
+                staged.endpoint.extend_from_slice(value).is_ok()
+            }
+        })
+    };
+    if stored {
+        0
+    } else {
+        sys::BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as i32
+    }
+}
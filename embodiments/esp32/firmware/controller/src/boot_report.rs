@@ -0,0 +1,77 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Boot diagnostics for the host
+//!
+//! Sent after the capabilities document (right after the hello, or once the
+//! host's salt arrived with link encryption), and again on
+//! `{"get_boot":1}`, so a board that keeps restarting can be diagnosed from
+//! the host:
+//!
+//! `{"boot":{"reset":"brownout","firmware":"0.1.0","idf":"v5.1.2","config_crc":"9f3a01c2","stored_config":false,"connectome":false,"uptime_ms":1840}}`
+//!
+//! - `reset` is ESP-IDF's reset reason: `power_on`, `external` (EN pin),
+//!   `software` (esp_restart: OTA, `set_gpio`, exhausted transport restarts),
+//!   `panic`, `int_watchdog`, `task_watchdog` (a stalled burst loop, see
+//!   watchdog.rs), `watchdog` (another watchdog), `deep_sleep`, `brownout`,
+//!   `sdio` or `unknown`
+//! - `config_crc` is the CRC-32 of the config.json the firmware was built
+//!   from, as compact JSON with sorted keys (so reformatting the file doesn't
+//!   change it); `stored_config` says whether NVS overrides some of it (see
+//!   stored_config.rs)
+//! - `connectome` is always false: this firmware relays to a remote FEAGI,
+//!   the standalone firmware is the one that embeds a brain
+//! - `uptime_ms` tells a report sent on a reconnect from one sent at boot
+
+use core::ffi::CStr;
+use core::fmt::Write;
+
+use esp_idf_svc::sys;
+use heapless::String;
+
+use crate::stored_config;
+use crate::CONFIG_CRC32;
+
+/// Is this line a `{"get_boot":...}` request?
+pub fn is_request(message: &str) -> bool {
+    message.starts_with("{\"get_boot\"")
+}
+
+/// Why the board last reset, as reported in `reset`
+pub fn reset_reason() -> &'static str {
+    match unsafe { sys::esp_reset_reason() } {
+        sys::esp_reset_reason_t_ESP_RST_POWERON => "power_on",
+        sys::esp_reset_reason_t_ESP_RST_EXT => "external",
+        sys::esp_reset_reason_t_ESP_RST_SW => "software",
+        sys::esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        sys::esp_reset_reason_t_ESP_RST_INT_WDT => "int_watchdog",
+        sys::esp_reset_reason_t_ESP_RST_TASK_WDT => "task_watchdog",
+        sys::esp_reset_reason_t_ESP_RST_WDT => "watchdog",
+        sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep_sleep",
+        sys::esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        sys::esp_reset_reason_t_ESP_RST_SDIO => "sdio",
+        _ => "unknown",
+    }
+}
+
+/// The `{"boot":{...}}` line
+pub fn line() -> String<256> {
+    let idf = unsafe { CStr::from_ptr(sys::esp_get_idf_version()) }.to_str().unwrap_or("");
+    let mut line: String<256> = String::new();
+    let _ = write!(
+        line,
+        "{{\"boot\":{{\"reset\":\"{}\",\"firmware\":\"{}\",\"idf\":\"{}\",\"config_crc\":\"{:08x}\",\
+         \"stored_config\":{},\"connectome\":false,\"uptime_ms\":{}}}}}\n",
+        reset_reason(),
+        env!("CARGO_PKG_VERSION"),
+        idf,
+        CONFIG_CRC32,
+        stored_config::load().is_some(),
+        unsafe { sys::esp_timer_get_time() } / 1000,
+    );
+    line
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Burst scheduling on a hardware timer
+//!
+//! A periodic esp_timer marks the start of every burst period, so bursts
+//! start `1 / burst_frequency` apart however long sampling, sending and
+//! applying commands took. The timer callback runs in the esp_timer task and
+//! only gives a counting semaphore; the burst loop blocks on it between
+//! bursts, which leaves the rest of the period to lower-priority tasks.
+//!
+//! A burst that overruns its period starts the next one right away. Periods
+//! it overran completely are skipped rather than run back to back to catch
+//! up, and the loop yields a tick so the idle task (and its watchdog) still
+//! gets to run; the jitter shows in metrics.rs.
+
+use core::ffi::{c_char, c_void};
+
+use esp_idf_svc::sys;
+
+/// Periods counted while the burst loop is busy; more are skipped anyway
+const MAX_PENDING: u32 = 8;
+
+/// Periodic timer pacing the burst loop
+pub struct BurstTimer {
+    timer: sys::esp_timer_handle_t,
+    ticks: sys::QueueHandle_t,
+}
+
+impl BurstTimer {
+    /// Start a period every `period_us`; None if the timer or its semaphore
+    /// couldn't be created
+    pub fn start(period_us: u64) -> Option<Self> {
+        unsafe {
+            let ticks = sys::xQueueCreateCountingSemaphore(MAX_PENDING, 0);
+            if ticks.is_null() {
+                return None;
+            }
+            let args = sys::esp_timer_create_args_t {
+                callback: Some(tick),
+                arg: ticks as *mut c_void,
+                name: b"burst\0".as_ptr() as *const c_char,
+                ..Default::default()
+            };
+            let mut timer: sys::esp_timer_handle_t = core::ptr::null_mut();
+            if sys::esp_timer_create(&args, &mut timer) != sys::ESP_OK {
+                sys::vQueueDelete(ticks);
+                return None;
+            }
+            if sys::esp_timer_start_periodic(timer, period_us.max(1)) != sys::ESP_OK {
+                sys::esp_timer_delete(timer);
+                sys::vQueueDelete(ticks);
+                return None;
+            }
+            Some(Self { timer, ticks })
+        }
+    }
+
+    /// Block until the next period starts (at most a second, should the
+    /// timer stop)
+    pub fn wait(&self) {
+        self.wait_for(1000);
+    }
+
+    /// Block until the next period starts or `timeout_ms` (at least a tick)
+    /// passed; true if the period started
+    pub fn wait_for(&self, timeout_ms: u32) -> bool {
+        unsafe {
+            let ticks = (timeout_ms * sys::configTICK_RATE_HZ / 1000).max(1);
+            if sys::xQueueSemaphoreTake(self.ticks, ticks) == 0 {
+                return false;
+            }
+            // Whole periods the last burst overran are skipped
+            if sys::uxQueueMessagesWaiting(self.ticks) > 0 {
+                sys::xQueueGenericReset(self.ticks, 0);
+                // Overloaded: let the idle task run before the next burst
+                sys::vTaskDelay(1);
+            }
+            true
+        }
+    }
+}
+
+impl Drop for BurstTimer {
+    fn drop(&mut self) {
+        unsafe {
+            sys::esp_timer_stop(self.timer);
+            sys::esp_timer_delete(self.timer);
+            sys::vQueueDelete(self.ticks);
+        }
+    }
+}
+
+unsafe extern "C" fn tick(arg: *mut c_void) {
+    // xSemaphoreGive(); a full count drops the tick
+    sys::xQueueGenericSend(arg as sys::QueueHandle_t, core::ptr::null(), 0, 0);
+}
@@ -0,0 +1,209 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! OV2640 camera (ESP32-CAM and similar boards) as a vision input
+//!
+//! With config.json `camera`, the sensor captures 160x120 grayscale frames
+//! through the esp32-camera driver. Every `decimation` bursts the latest
+//! frame is box-averaged down to `width` x `height` pixels and sent as its
+//! own line, next to the sensory frame:
+//!
+//! `{"vis":{"id":N,"w":W,"h":H,"px":"<hex>"}}`
+//!
+//! `px` holds one byte per pixel (two hex digits, 00 = black, ff = white),
+//! row by row from the top left. Pixel (x, y) is neuron `N + y * W + x` of
+//! the vision cortical area, with potential byte / 255. Vision lines are not
+//! queued while the transport is down: a stale frame is no use to FEAGI.
+//!
+//! The camera clock (XCLK) takes LEDC timer 3 and channel 7, which build.rs
+//! keeps from the PWM outputs.
+
+use esp_idf_svc::sys;
+use esp_idf_svc::sys::camera;
+use heapless::String;
+
+use crate::cortical;
+use crate::{u32_to_string, CAMERA_LINE_CAPACITY};
+
+const XCLK_HZ: i32 = 20_000_000;
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+/// Camera module wiring presets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraBoard {
+    /// AI-Thinker ESP32-CAM
+    AiThinker,
+    /// Espressif ESP-EYE
+    EspEye,
+}
+
+/// Camera connector pins; -1 = not connected
+struct CameraPins {
+    pwdn: i32,
+    reset: i32,
+    xclk: i32,
+    sda: i32,
+    scl: i32,
+    /// D7 (Y9) down to D0 (Y2)
+    data: [i32; 8],
+    vsync: i32,
+    href: i32,
+    pclk: i32,
+}
+
+impl CameraBoard {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CameraBoard::AiThinker => "ai_thinker",
+            CameraBoard::EspEye => "esp_eye",
+        }
+    }
+
+    fn pins(&self) -> CameraPins {
+        match self {
+            CameraBoard::AiThinker => CameraPins {
+                pwdn: 32,
+                reset: -1,
+                xclk: 0,
+                sda: 26,
+                scl: 27,
+                data: [35, 34, 39, 36, 21, 19, 18, 5],
+                vsync: 25,
+                href: 23,
+                pclk: 22,
+            },
+            CameraBoard::EspEye => CameraPins {
+                pwdn: -1,
+                reset: -1,
+                xclk: 4,
+                sda: 18,
+                scl: 23,
+                data: [36, 37, 38, 39, 35, 14, 13, 34],
+                vsync: 5,
+                href: 27,
+                pclk: 25,
+            },
+        }
+    }
+}
+
+/// Vision input settings (from config.json `camera`)
+#[derive(Debug, Clone, Copy)]
+pub struct CameraConfig {
+    pub board: CameraBoard,
+    /// "cortical_area:neuron_id" of the top left pixel
+    pub cortical_mapping: &'static str,
+    /// Downscaled frame size sent to FEAGI
+    pub width: u32,
+    pub height: u32,
+    /// Send one frame every this many bursts
+    pub decimation: u32,
+}
+
+/// An initialized camera
+pub struct Camera {
+    config: CameraConfig,
+    first_neuron: u32,
+}
+
+impl Camera {
+    /// Power up the sensor; None if it doesn't answer
+    pub fn new(config: &CameraConfig) -> Option<Self> {
+        let first_neuron = cortical::neuron_id(config.cortical_mapping)?;
+        let pins = config.board.pins();
+        let mut driver: camera::camera_config_t = unsafe { core::mem::zeroed() };
+        driver.pin_pwdn = pins.pwdn;
+        driver.pin_reset = pins.reset;
+        driver.pin_xclk = pins.xclk;
+        driver.__bindgen_anon_1.pin_sccb_sda = pins.sda;
+        driver.__bindgen_anon_2.pin_sccb_scl = pins.scl;
+        driver.pin_d7 = pins.data[0];
+        driver.pin_d6 = pins.data[1];
+        driver.pin_d5 = pins.data[2];
+        driver.pin_d4 = pins.data[3];
+        driver.pin_d3 = pins.data[4];
+        driver.pin_d2 = pins.data[5];
+        driver.pin_d1 = pins.data[6];
+        driver.pin_d0 = pins.data[7];
+        driver.pin_vsync = pins.vsync;
+        driver.pin_href = pins.href;
+        driver.pin_pclk = pins.pclk;
+        driver.xclk_freq_hz = XCLK_HZ;
+        driver.ledc_timer = camera::ledc_timer_t_LEDC_TIMER_3;
+        driver.ledc_channel = camera::ledc_channel_t_LEDC_CHANNEL_7;
+        driver.pixel_format = camera::pixformat_t_PIXFORMAT_GRAYSCALE;
+        driver.frame_size = camera::framesize_t_FRAMESIZE_QQVGA;
+        // Two buffers in internal RAM (19.2 KB each), so the latest complete
+        // frame is ready without waiting for the sensor
+        driver.fb_count = 2;
+        driver.fb_location = camera::camera_fb_location_t_CAMERA_FB_IN_DRAM;
+        driver.grab_mode = camera::camera_grab_mode_t_CAMERA_GRAB_LATEST;
+        driver.sccb_i2c_port = -1;
+        if unsafe { camera::esp_camera_init(&driver) } != sys::ESP_OK {
+            return None;
+        }
+        Some(Self {
+            config: *config,
+            first_neuron,
+        })
+    }
+
+    /// Whether this burst sends a frame
+    pub fn due(&self, frame_number: u64) -> bool {
+        frame_number % self.config.decimation.max(1) as u64 == 0
+    }
+
+    /// The latest frame, downscaled, as a `"vis"` line
+    pub fn frame_line(&mut self) -> Option<String<CAMERA_LINE_CAPACITY>> {
+        let fb = unsafe { camera::esp_camera_fb_get() };
+        if fb.is_null() {
+            return None;
+        }
+        let (buf, len, fb_width, fb_height) = unsafe { ((*fb).buf, (*fb).len, (*fb).width as u32, (*fb).height as u32) };
+        let (width, height) = (self.config.width, self.config.height);
+        if buf.is_null() || len < (fb_width * fb_height) as usize || fb_width < width || fb_height < height {
+            unsafe { camera::esp_camera_fb_return(fb) };
+            return None;
+        }
+        let pixels = unsafe { core::slice::from_raw_parts(buf, len) };
+
+        let mut line: String<CAMERA_LINE_CAPACITY> = String::new();
+        let mut num: String<16> = String::new();
+        let _ = line.push_str("{\"vis\":{\"id\":");
+        u32_to_string(self.first_neuron, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push_str(",\"w\":");
+        u32_to_string(width, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push_str(",\"h\":");
+        u32_to_string(height, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push_str(",\"px\":\"");
+        // Box average: output pixel (x, y) covers source columns
+        // x*fw/w..(x+1)*fw/w and the same share of rows
+        for y in 0..height {
+            let (row0, row1) = (y * fb_height / height, (y + 1) * fb_height / height);
+            for x in 0..width {
+                let (col0, col1) = (x * fb_width / width, (x + 1) * fb_width / width);
+                let mut sum = 0u32;
+                for row in row0..row1 {
+                    let start = (row * fb_width) as usize;
+                    for &p in &pixels[start + col0 as usize..start + col1 as usize] {
+                        sum += p as u32;
+                    }
+                }
+                let value = sum / ((row1 - row0) * (col1 - col0)).max(1);
+                let _ = line.push(HEX[(value >> 4) as usize] as char);
+                let _ = line.push(HEX[(value & 0xf) as usize] as char);
+            }
+        }
+        unsafe { camera::esp_camera_fb_return(fb) };
+        let _ = line.push_str("\"}}\n");
+        Some(line)
+    }
+}
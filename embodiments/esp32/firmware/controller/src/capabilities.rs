@@ -0,0 +1,205 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Capabilities document, so FEAGI can set up matching cortical areas
+//!
+//! Sent once the transport is up: right after the hello, or, with link
+//! encryption, sealed once the host's salt arrived. `{"get_capabilities":1}`
+//! asks for it again at any time.
+//!
+//! `{"capabilities":{"device":"esp32","firmware":"0.1.0","burst_frequency":100,"wire":"json","channels":[...]}}`
+//!
+//! with `"compression":["heatshrink"]` before `"channels"` on boards built
+//! with a `compression` block, and `"ota":{"partition":"ota_0","pending":false}`
+//! (the running app partition, and whether it's an update not confirmed yet)
+//! on boards built with an `ota` block, and `"health":["tc","cpu"]` (the
+//! board health fields the sensory frame carries; just `["cpu"]` on the
+//! original ESP32, which has no temperature sensor) with
+//! `telemetry.board_health`.
+//!
+//! Every channel names its direction, kind, pin (GPIO channels only),
+//! cortical area, first neuron and neuron count:
+//!
+//! `{"dir":"in","kind":"encoder_input","pin":18,"area":"ienc00","first":0,"count":2}`
+//!
+//! `first` is null when the mapping doesn't end in a neuron ID, and `[x,y,z]`
+//! when it ends in cortical coordinates (see cortical.rs).
+
+use feagi_controller_core::compress;
+use heapless::String;
+
+use crate::health;
+use crate::microphone::MAX_BANDS;
+use crate::ota;
+use crate::power::PowerSource;
+use crate::settings::Settings;
+use crate::stored_config;
+use crate::*;
+
+/// Is this line a `{"get_capabilities":...}` request?
+pub fn is_request(message: &str) -> bool {
+    message.starts_with("{\"get_capabilities\"")
+}
+
+/// The `{"capabilities":{...}}` line
+pub fn document(settings: &Settings) -> String<CAPABILITIES_CAPACITY> {
+    let mut w = Writer { out: String::new(), first: true };
+    w.raw("{\"capabilities\":{\"device\":\"esp32\",\"firmware\":\"");
+    w.raw(env!("CARGO_PKG_VERSION"));
+    w.raw("\",\"burst_frequency\":");
+    w.num(settings.burst_frequency.value);
+    w.raw(",\"wire\":\"");
+    w.raw(WIRE_FORMAT);
+    w.raw("\"");
+    if COMPRESSION.is_some() {
+        // Sensory lines the host can ask to get compressed (see controller-core/src/compress.rs)
+        w.raw(",\"compression\":[\"");
+        w.raw(compress::ALGORITHM);
+        w.raw("\"]");
+    }
+    if OTA.is_some() {
+        // Where the running firmware came from (see ota.rs)
+        w.raw(",\"ota\":{\"partition\":\"");
+        w.raw(ota::running_partition());
+        w.raw("\",\"pending\":");
+        w.raw(if ota::pending_verify() { "true" } else { "false" });
+        w.raw("}");
+    }
+    if TELEMETRY_BOARD_HEALTH {
+        // Board health fields the sensory frame carries (see health.rs)
+        w.raw(if health::CHIP_TEMP_SUPPORTED { ",\"health\":[\"tc\",\"cpu\"]" } else { ",\"health\":[\"cpu\"]" });
+    }
+    w.raw(",\"channels\":[");
+
+    // With the modes and cortical mappings stored in NVS (see stored_config.rs)
+    for gpio in stored_config::gpio_config() {
+        let (dir, count) = match gpio.mode {
+            GpioMode::Disabled => continue,
+            GpioMode::DigitalInput | GpioMode::AnalogInput | GpioMode::TouchInput | GpioMode::UltrasonicInput => ("in", 1),
+            // Position and velocity; temperature and humidity
+            GpioMode::EncoderInput | GpioMode::DhtInput => ("in", 2),
+            // A neuron per color channel
+            GpioMode::LedStrip => ("out", gpio.led_strip.map_or(0, |l| l.length * 3)),
+            GpioMode::DigitalOutput
+            | GpioMode::PwmOutput
+            | GpioMode::ServoOutput
+            | GpioMode::DcMotor
+            | GpioMode::StepperOutput => ("out", 1),
+        };
+        match gpio.population {
+            Some(p) => w.channel_range(dir, gpio.mode.as_str(), Some(gpio.pin), gpio.cortical_mapping, p.first, p.last.saturating_sub(p.first) + 1),
+            None => w.channel(dir, gpio.mode.as_str(), Some(gpio.pin), gpio.cortical_mapping, count),
+        }
+        if let Some(fb) = gpio.feedback {
+            w.channel("in", "feedback", Some(fb.pin), fb.cortical_mapping, 1);
+        }
+    }
+    for device in I2C_DEVICES {
+        if let Some(channels) = device.channels {
+            let values = (device.len as usize).saturating_sub(channels.offset as usize) / channels.format.size();
+            w.channel("in", "i2c", None, channels.cortical_mapping, values as u32);
+        }
+    }
+    if let Some(imu) = IMU_CONFIG {
+        // Accel X/Y/Z, gyro X/Y/Z
+        w.channel("in", "imu", None, imu.cortical_mapping, 6);
+    }
+    if let Some(mapping) = ONBOARD_SENSORS.hall_mapping {
+        w.channel("in", "hall", None, mapping, 1);
+    }
+    if let Some(mapping) = ONBOARD_SENSORS.temperature_mapping {
+        w.channel("in", "temperature", None, mapping, 1);
+    }
+    if let Some(mic) = MICROPHONE {
+        w.channel("in", "microphone", None, mic.cortical_mapping, 1 + mic.bands.min(MAX_BANDS as u32));
+    }
+    if let Some(power) = POWER_MONITOR {
+        let count = match power.source {
+            PowerSource::Adc { .. } => 1,
+            PowerSource::Ina219 { .. } => 2,
+        };
+        w.channel("in", "power_monitor", None, power.cortical_mapping, count);
+    }
+    if let Some(link) = LINK_TELEMETRY {
+        // RSSI, re-sent share, disconnects
+        w.channel("in", "link", None, link.cortical_mapping, 3);
+    }
+    if let Some(camera) = CAMERA {
+        // Grayscale pixels, row by row ("vis" lines)
+        w.channel("in", "camera", None, camera.cortical_mapping, camera.width * camera.height);
+    }
+    if let Some(audio) = AUDIO_OUTPUT {
+        // A neuron per tone
+        w.channel("out", "audio_output", None, audio.cortical_mapping, audio.tones.len() as u32);
+    }
+    w.raw("]}}\n");
+    w.out
+}
+
+struct Writer {
+    out: String<CAPABILITIES_CAPACITY>,
+    /// No channel written yet
+    first: bool,
+}
+
+impl Writer {
+    fn raw(&mut self, s: &str) {
+        let _ = self.out.push_str(s);
+    }
+
+    fn num(&mut self, n: u32) {
+        let mut s: String<16> = String::new();
+        u32_to_string(n, &mut s);
+        self.raw(s.as_str());
+    }
+
+    /// A channel starting at the mapping's neuron ID
+    fn channel(&mut self, dir: &str, kind: &str, pin: Option<u32>, mapping: &str, count: u32) {
+        self.entry(dir, kind, pin, mapping, cortical::neuron_id(mapping), count);
+    }
+
+    /// A channel over an explicit neuron range of the mapping's area
+    fn channel_range(&mut self, dir: &str, kind: &str, pin: Option<u32>, mapping: &str, first: u32, count: u32) {
+        self.entry(dir, kind, pin, mapping, Some(first), count);
+    }
+
+    fn entry(&mut self, dir: &str, kind: &str, pin: Option<u32>, mapping: &str, first: Option<u32>, count: u32) {
+        self.raw(if self.first { "{\"dir\":\"" } else { ",{\"dir\":\"" });
+        self.first = false;
+        self.raw(dir);
+        self.raw("\",\"kind\":\"");
+        self.raw(kind);
+        self.raw("\"");
+        if let Some(pin) = pin {
+            self.raw(",\"pin\":");
+            self.num(pin);
+        }
+        // "cortical_area:neuron_id", just the area or just the neuron ID
+        let area = match mapping.rsplit_once(':') {
+            Some((area, _)) => area,
+            None if mapping.parse::<u32>().is_ok() => "",
+            None => mapping,
+        };
+        self.raw(",\"area\":\"");
+        self.raw(area);
+        self.raw("\",\"first\":");
+        match (first, first.and_then(cortical::voxel)) {
+            (_, Some(v)) => {
+                for (i, n) in [v.x, v.y, v.z].into_iter().enumerate() {
+                    self.raw(if i == 0 { "[" } else { "," });
+                    self.num(n);
+                }
+                self.raw("]");
+            }
+            (Some(first), None) => self.num(first),
+            (None, None) => self.raw("null"),
+        }
+        self.raw(",\"count\":");
+        self.num(count);
+        self.raw("}");
+    }
+}
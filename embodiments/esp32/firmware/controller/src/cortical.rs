@@ -0,0 +1,87 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Cortical addresses: which FEAGI neuron a channel reports or is driven by
+//!
+//! A `cortical_mapping` names a voxel of a cortical area, `"iimu00:0,0,0"`,
+//! or, as before, a flat neuron ID, `"iimu00:3"` or just `"3"` (the area is
+//! then only a label). Channels with several neurons (an IMU's six axes, an
+//! encoder's position and velocity, ...) continue from there along x, or
+//! count up from the flat ID. build.rs rejects a mapping that is neither.
+//!
+//! Inside the firmware every channel is keyed by a u32 neuron ID. A voxel's
+//! is its packed address:
+//!
+//! | Bits  | 31 | 30-24      | 23-18 | 17-10 | 9-0 |
+//! |-------|----|------------|-------|-------|-----|
+//! | Field | 1  | area index | z     | y     | x   |
+//!
+//! with the area's index in CORTICAL_AREAS, so flat IDs (below 2^31) and
+//! voxels never collide. On the wire a voxel is written as
+//! `["iimu00",x,y,z]` wherever a neuron ID would be (see messages.rs), and
+//! motor commands can name one the same way.
+
+use crate::CORTICAL_AREAS;
+
+/// Set in the neuron ID of a voxel
+const VOXEL: u32 = 1 << 31;
+const AREA_SHIFT: u32 = 24;
+const Z_SHIFT: u32 = 18;
+const Y_SHIFT: u32 = 10;
+
+pub const MAX_X: u32 = 1023;
+pub const MAX_Y: u32 = 255;
+pub const MAX_Z: u32 = 63;
+
+/// A voxel of a cortical area
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voxel {
+    pub area: &'static str,
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+/// The neuron ID a mapping names: a flat ID, or a voxel's packed address;
+/// None for a bare area or a malformed mapping
+pub fn neuron_id(mapping: &str) -> Option<u32> {
+    let (area, neuron) = match mapping.split_once(':') {
+        Some(parts) => parts,
+        None => ("", mapping),
+    };
+    let mut numbers = neuron.split(',').map(|n| n.parse::<u32>().ok());
+    match (numbers.next()?, numbers.next(), numbers.next(), numbers.next()) {
+        (Some(id), None, None, None) => (id < VOXEL).then_some(id),
+        (Some(x), Some(Some(y)), Some(Some(z)), None) if !area.is_empty() => pack(area, x, y, z),
+        _ => None,
+    }
+}
+
+/// Packed neuron ID of voxel (x, y, z) of `area`; None if the area isn't
+/// mapped by coordinates anywhere in config.json or a coordinate is out of
+/// range
+pub fn pack(area: &str, x: u32, y: u32, z: u32) -> Option<u32> {
+    let index = CORTICAL_AREAS.iter().position(|&a| a == area)? as u32;
+    if x > MAX_X || y > MAX_Y || z > MAX_Z {
+        return None;
+    }
+    Some(VOXEL | index << AREA_SHIFT | z << Z_SHIFT | y << Y_SHIFT | x)
+}
+
+/// The voxel a neuron ID stands for; None for a flat ID
+pub fn voxel(neuron_id: u32) -> Option<Voxel> {
+    if neuron_id & VOXEL == 0 {
+        return None;
+    }
+    let area = CORTICAL_AREAS.get(((neuron_id & !VOXEL) >> AREA_SHIFT) as usize)?;
+    Some(Voxel {
+        area,
+        x: neuron_id & MAX_X,
+        y: (neuron_id >> Y_SHIFT) & MAX_Y,
+        z: (neuron_id >> Z_SHIFT) & MAX_Z,
+    })
+}
@@ -0,0 +1,112 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Brushed DC motors on an H-bridge (`dc_motor` outputs)
+//!
+//! One motor command sets a signed speed: 0.5 stops the motor, 1.0 is full
+//! speed forward and 0.0 full speed in reverse (the same convention as the
+//! signed sensory channels). Commands within `deadband` of 0.5 stop the
+//! motor, so a neuron hovering around neutral doesn't make it hum or creep;
+//! `invert` swaps the directions of a motor wired (or mounted) backwards.
+//!
+//! Two kinds of driver are supported:
+//! - two PWM inputs per motor (DRV8833, L9110S, TB67H450): the output's pin
+//!   takes PWM forward, `pin_b` PWM in reverse, the other one stays low
+//! - a PWM enable plus direction inputs (L298N, TB6612FNG, Cytron MD10C):
+//!   the output's pin takes PWM, `direction_pins` select the direction
+//!   (IN1/IN2 in opposite levels, or a single DIR pin)
+//!
+//! A stopped motor coasts: both PWM inputs, or the enable and both
+//! direction inputs, are low.
+
+use esp_idf_svc::sys;
+
+use crate::pwm::{self, PwmConfig};
+
+/// How the H-bridge takes direction and speed
+#[derive(Debug, Clone, Copy)]
+pub enum MotorDriver {
+    /// PWM on the output's pin forward, on `pin_b` in reverse
+    TwoPwm { pin_b: u32, pwm_b: PwmConfig },
+    /// PWM on the output's pin; `in1` high forward, `in2` (if any) high in
+    /// reverse
+    Direction { in1: u32, in2: Option<u32> },
+}
+
+/// H-bridge wiring and command shaping of a `dc_motor` output (from
+/// config.json `dc_motor`)
+#[derive(Debug, Clone, Copy)]
+pub struct DcMotorConfig {
+    pub driver: MotorDriver,
+    /// Swap forward and reverse
+    pub invert: bool,
+    /// Signed speeds below this magnitude (0.0-1.0) stop the motor
+    pub deadband: f32,
+}
+
+impl DcMotorConfig {
+    /// Signed speed (-1.0-1.0) of a 0.0-1.0 command, after the deadband
+    pub fn speed(&self, value: f32) -> f32 {
+        let speed = value.clamp(0.0, 1.0) * 2.0 - 1.0;
+        if speed < self.deadband && -speed < self.deadband {
+            0.0
+        } else {
+            speed
+        }
+    }
+}
+
+/// Route a motor's pins to LEDC and the direction GPIOs, stopped
+///
+/// Returns false if LEDC rejected the PWM settings.
+pub fn attach(pin: u32, pwm: &PwmConfig, config: &DcMotorConfig) -> bool {
+    match config.driver {
+        MotorDriver::TwoPwm { pin_b, ref pwm_b } => {
+            pwm::attach(pin, pwm, 0.0).is_some() && pwm::attach(pin_b, pwm_b, 0.0).is_some()
+        }
+        MotorDriver::Direction { in1, in2 } => {
+            for gpio in [Some(in1), in2].into_iter().flatten() {
+                unsafe {
+                    sys::gpio_reset_pin(gpio as i32);
+                    sys::gpio_set_level(gpio as i32, 0);
+                    sys::gpio_set_direction(gpio as i32, sys::gpio_mode_t_GPIO_MODE_OUTPUT);
+                }
+            }
+            pwm::attach(pin, pwm, 0.0).is_some()
+        }
+    }
+}
+
+/// Drive an attached motor with a 0.0-1.0 command
+///
+/// Returns the command actually applied (0.5 inside the deadband, the duty
+/// quantized to the PWM resolution otherwise), or None if LEDC rejected it.
+pub fn set(pwm: &PwmConfig, config: &DcMotorConfig, value: f32) -> Option<f32> {
+    let speed = config.speed(value);
+    // (no f32::abs in core)
+    let magnitude = if speed < 0.0 { -speed } else { speed };
+    let forward = (speed > 0.0) != config.invert;
+    let duty = match config.driver {
+        MotorDriver::TwoPwm { ref pwm_b, .. } => {
+            // Release the idle side first, so the bridge never brakes in between
+            let (active, idle) = if forward { (pwm, pwm_b) } else { (pwm_b, pwm) };
+            pwm::set_duty(idle, 0.0)?;
+            pwm::set_duty(active, magnitude)?
+        }
+        MotorDriver::Direction { in1, in2 } => {
+            let stopped = speed == 0.0;
+            unsafe {
+                sys::gpio_set_level(in1 as i32, (!stopped && forward) as u32);
+                if let Some(in2) = in2 {
+                    sys::gpio_set_level(in2 as i32, (!stopped && !forward) as u32);
+                }
+            }
+            pwm::set_duty(pwm, magnitude)?
+        }
+    };
+    Some(if speed < 0.0 { 0.5 - duty / 2.0 } else { 0.5 + duty / 2.0 })
+}
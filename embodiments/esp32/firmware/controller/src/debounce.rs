@@ -0,0 +1,99 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Debouncing and edge detection for digital inputs
+//!
+//! Every `digital_input` pin has a trigger mode and a debounce time. A level
+//! change only counts once the pin has held the new level for the debounce
+//! time, so a bouncing switch contact changes state once instead of
+//! spamming FEAGI. The trigger decides what the pin's neuron reports:
+//!
+//! | `trigger` | Value                                                       |
+//! |-----------|-------------------------------------------------------------|
+//! | `level`   | The debounced level (1.0 high, 0.0 low)                     |
+//! | `rising`  | 1.0 in the burst the debounced level goes high, else 0.0    |
+//! | `falling` | 1.0 in the burst the debounced level goes low, else 0.0     |
+//! | `both`    | 1.0 in the burst the debounced level changes, else 0.0      |
+//!
+//! Polled pins are filtered here, once per burst; interrupt-driven pins (see
+//! edges.rs) apply the same settings in their interrupt handler.
+
+/// What a digital input reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Level,
+    Rising,
+    Falling,
+    Both,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::Level => "level",
+            Trigger::Rising => "rising",
+            Trigger::Falling => "falling",
+            Trigger::Both => "both",
+        }
+    }
+}
+
+/// Filtering of one digital input (from config.json `trigger`,
+/// `debounce_ms` and `interrupt`)
+#[derive(Debug, Clone, Copy)]
+pub struct InputConfig {
+    pub trigger: Trigger,
+    /// Time a new level must hold before it counts (0 = no debouncing)
+    pub debounce_ms: u32,
+    /// Queue edges from a GPIO interrupt instead of polling (see edges.rs)
+    pub interrupt: bool,
+}
+
+/// Debounce state of one polled input
+pub struct InputFilter {
+    trigger: Trigger,
+    debounce_us: i64,
+    /// Debounced level; None until the first sample
+    stable: Option<bool>,
+    /// When the raw level started to differ from the debounced one
+    changed_us: Option<i64>,
+}
+
+impl InputFilter {
+    pub fn new(config: &InputConfig) -> Self {
+        Self {
+            trigger: config.trigger,
+            debounce_us: config.debounce_ms as i64 * 1000,
+            stable: None,
+            changed_us: None,
+        }
+    }
+
+    /// Feed a raw sample taken at `now_us`, returning the value to report
+    pub fn update(&mut self, level: bool, now_us: i64) -> f32 {
+        let mut edge = None;
+        match self.stable {
+            None => self.stable = Some(level),
+            Some(stable) if stable == level => self.changed_us = None,
+            Some(_) => {
+                let since_us = *self.changed_us.get_or_insert(now_us);
+                if now_us - since_us >= self.debounce_us {
+                    self.stable = Some(level);
+                    self.changed_us = None;
+                    edge = Some(level);
+                }
+            }
+        }
+        let fired = match self.trigger {
+            Trigger::Level => self.stable == Some(true),
+            Trigger::Rising => edge == Some(true),
+            Trigger::Falling => edge == Some(false),
+            Trigger::Both => edge.is_some(),
+        };
+        if fired { 1.0 } else { 0.0 }
+    }
+}
@@ -0,0 +1,127 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Device identity and multi-device addressing
+//!
+//! `device_id` in config.json (default: the MQTT `device_id`, else `esp32`)
+//! names the board, and every JSON line it sends starts with it:
+//!
+//! `{"id":"left-arm","np":[[0,1],[1,0]],"f":42}`
+//!
+//! (the hello carries it as `"id"` too). The host can rename the board with
+//! `{"set_settings":{"device_id":"right-arm"}}`; the new ID is stored in NVS
+//! and outlives reboots, taking precedence over config.json.
+//!
+//! Several boards can share one serial bus or radio link: a motor message
+//! with `"to":"<id>"` is applied only by the board of that ID, one without
+//! `"to"` by every board (see messages.rs). Other host lines, and binary
+//! packets, aren't addressed.
+//!
+//! An ID is 1-32 letters, digits, `.`, `-` or `_`, so it needs no escaping.
+
+use core::ffi::c_char;
+use core::str::FromStr;
+
+use esp_idf_svc::sys;
+use heapless::{String, Vec};
+
+use crate::provisioning::init_nvs;
+
+/// Longest device ID
+pub const MAX_DEVICE_ID_LEN: usize = 32;
+
+pub type DeviceId = String<MAX_DEVICE_ID_LEN>;
+
+const NAMESPACE: &[u8] = b"feagi_dev\0";
+const KEY_ID: &[u8] = b"device_id\0";
+
+/// Is `id` usable as a device ID?
+pub fn is_valid(id: &str) -> bool {
+    (1..=MAX_DEVICE_ID_LEN).contains(&id.len())
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_'))
+}
+
+/// The ID stored by an earlier rename, if any
+pub fn load() -> Option<DeviceId> {
+    if !init_nvs() {
+        return None;
+    }
+    let mut buf = [0u8; MAX_DEVICE_ID_LEN + 1];
+    unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READONLY, &mut handle) != sys::ESP_OK {
+            return None;
+        }
+        let mut len = buf.len();
+        let ok = sys::nvs_get_str(handle, KEY_ID.as_ptr() as *const c_char, buf.as_mut_ptr() as *mut c_char, &mut len) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        if !ok {
+            return None;
+        }
+        // `len` counts the terminating NUL
+        let id = core::str::from_utf8(&buf[..len.saturating_sub(1)]).ok()?;
+        DeviceId::from_str(id).ok().filter(|id| is_valid(id))
+    }
+}
+
+/// Persist `id` to NVS; false if the write failed
+pub fn store(id: &str) -> bool {
+    if !init_nvs() {
+        return false;
+    }
+    let mut terminated: String<{ MAX_DEVICE_ID_LEN + 1 }> = String::new();
+    if terminated.push_str(id).is_err() || terminated.push('\0').is_err() {
+        return false;
+    }
+    unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READWRITE, &mut handle) != sys::ESP_OK {
+            return false;
+        }
+        let ok = sys::nvs_set_str(handle, KEY_ID.as_ptr() as *const c_char, terminated.as_ptr() as *const c_char) == sys::ESP_OK
+            && sys::nvs_commit(handle) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        ok
+    }
+}
+
+/// Forget a stored ID (config.json's applies after the next boot); false if
+/// NVS failed
+pub fn clear() -> bool {
+    if !init_nvs() {
+        return false;
+    }
+    unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READWRITE, &mut handle) != sys::ESP_OK {
+            return false;
+        }
+        let erased = sys::nvs_erase_key(handle, KEY_ID.as_ptr() as *const c_char);
+        // Nothing stored is as good as erased
+        let ok = (erased == sys::ESP_OK || erased == sys::ESP_ERR_NVS_NOT_FOUND as i32) && sys::nvs_commit(handle) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        ok
+    }
+}
+
+/// `line` with `"id":"<id>"` as its first key, built in `out`
+///
+/// Lines that aren't JSON objects (binary packets) are returned as they are,
+/// and so is a line that doesn't fit `out` (build.rs sizes LINE_CAPACITY for
+/// the longest line plus the ID).
+pub fn stamp<'a, const N: usize>(id: &str, line: &'a [u8], out: &'a mut Vec<u8, N>) -> &'a [u8] {
+    let Some(rest) = line.strip_prefix(b"{") else {
+        return line;
+    };
+    out.clear();
+    let separator: &[u8] = if rest.starts_with(b"}") { b"" } else { b"," };
+    let parts: [&[u8]; 5] = [b"{\"id\":\"", id.as_bytes(), b"\"", separator, rest];
+    if parts.iter().any(|part| out.extend_from_slice(part).is_err()) {
+        return line;
+    }
+    out
+}
@@ -0,0 +1,181 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! DHT22/AM2302 temperature and humidity sensors
+//!
+//! A `dht_input` pin is the sensor's single data wire (open drain, pulled
+//! up). The sensor can only be read every 2 s or so, so each one is polled
+//! at its own `interval_ms` and its last reading is repeated in the bursts
+//! in between. It reports two sensory channels on consecutive neurons:
+//!
+//! | Neuron | Value                                   |
+//! |--------|-----------------------------------------|
+//! | N      | Temperature, 0.0 = -40 °C, 1.0 = 80 °C  |
+//! | N + 1  | Relative humidity, 0.0-1.0 = 0-100 %    |
+//!
+//! A read bit-bangs the protocol with interrupts off for about 5 ms: the
+//! sensor encodes each bit in the length of a high pulse (~27 µs for 0,
+//! ~70 µs for 1), which an interrupt landing mid-pulse would corrupt.
+
+use esp_idf_svc::hal::interrupt;
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::fault;
+
+/// Polling settings of one sensor (from config.json `dht` block)
+#[derive(Debug, Clone, Copy)]
+pub struct DhtConfig {
+    /// Time between reads (at least 2000)
+    pub interval_ms: u32,
+}
+
+/// Sensors on one board
+pub const MAX_DHT: usize = 4;
+
+/// Host start signal: data line held low for at least 1 ms
+const START_LOW_US: u32 = 1_100;
+
+/// High pulses longer than this are 1 bits
+const ONE_THRESHOLD_US: u32 = 45;
+
+/// Longest any single phase of the protocol may last
+const PHASE_TIMEOUT_US: u32 = 120;
+
+/// Sensor range mapped to 0.0-1.0
+const MIN_TEMPERATURE_C: f32 = -40.0;
+const MAX_TEMPERATURE_C: f32 = 80.0;
+
+struct DhtSensor {
+    pin: u32,
+    neuron_id: u32,
+    interval_us: i64,
+    next_read_us: i64,
+    /// Latest (temperature, humidity) as 0.0-1.0, once a read succeeded
+    values: Option<(f32, f32)>,
+    /// Reads that timed out or failed the checksum
+    errors: u32,
+}
+
+/// All configured DHT sensors
+pub struct DhtBank {
+    sensors: Vec<DhtSensor, MAX_DHT>,
+}
+
+impl DhtBank {
+    /// Release the data line of each `dht_input` pin in GPIO_CONFIG
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut sensors = Vec::new();
+        // The sensor needs ~1 s after power-up before its first read
+        let first_read_us = unsafe { sys::esp_timer_get_time() } + 1_000_000;
+        for gpio_config in config {
+            let Some(dht) = gpio_config.dht else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            let gpio = gpio_config.pin as i32;
+            unsafe {
+                sys::gpio_reset_pin(gpio);
+                sys::gpio_set_direction(gpio, sys::gpio_mode_t_GPIO_MODE_INPUT_OUTPUT_OD);
+                sys::gpio_set_pull_mode(gpio, sys::gpio_pull_mode_t_GPIO_PULLUP_ONLY);
+                sys::gpio_set_level(gpio, 1);
+            }
+            let _ = sensors.push(DhtSensor {
+                pin: gpio_config.pin,
+                neuron_id,
+                interval_us: dht.interval_ms as i64 * 1000,
+                next_read_us: first_read_us,
+                values: None,
+                errors: 0,
+            });
+        }
+        Self { sensors }
+    }
+
+    /// Read the sensors that are due, returning (neuron_id, value) for the
+    /// temperature and humidity of every sensor with a reading
+    pub fn read_all(&mut self) -> Vec<(u32, f32), { MAX_DHT * 2 }> {
+        let mut readings = Vec::new();
+        let now_us = unsafe { sys::esp_timer_get_time() };
+        for sensor in self.sensors.iter_mut() {
+            if now_us >= sensor.next_read_us {
+                sensor.next_read_us = now_us + sensor.interval_us;
+                match read_sensor(sensor.pin) {
+                    Some((temperature_c, humidity)) => {
+                        let temperature = (temperature_c - MIN_TEMPERATURE_C) / (MAX_TEMPERATURE_C - MIN_TEMPERATURE_C);
+                        sensor.values = Some((temperature.clamp(0.0, 1.0), (humidity / 100.0).clamp(0.0, 1.0)));
+                    }
+                    None => {
+                        sensor.errors = sensor.errors.wrapping_add(1);
+                        fault!(SensorRead, "GPIO {}: DHT read failed ({} total)", sensor.pin, sensor.errors);
+                    }
+                }
+            }
+            if let Some((temperature, humidity)) = sensor.values {
+                let _ = readings.push((sensor.neuron_id, temperature));
+                let _ = readings.push((sensor.neuron_id + 1, humidity));
+            }
+        }
+        readings
+    }
+}
+
+/// One transaction: returns (°C, % RH), or None on a timeout or bad checksum
+fn read_sensor(pin: u32) -> Option<(f32, f32)> {
+    let gpio = pin as i32;
+    unsafe {
+        sys::gpio_set_level(gpio, 0);
+        sys::esp_rom_delay_us(START_LOW_US);
+    }
+    let mut data = [0u8; 5];
+    let received = interrupt::free(|| {
+        unsafe {
+            sys::gpio_set_level(gpio, 1);
+        }
+        // Response: the sensor pulls low ~80 µs, then high ~80 µs
+        wait_while(gpio, true)?;
+        wait_while(gpio, false)?;
+        wait_while(gpio, true)?;
+        // 40 bits, MSB first: ~50 µs low, then a high pulse whose length is the bit
+        for bit in 0..40 {
+            wait_while(gpio, false)?;
+            let high_us = wait_while(gpio, true)?;
+            if high_us > ONE_THRESHOLD_US {
+                data[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        Some(())
+    });
+    received?;
+
+    let checksum = data[..4].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    if checksum != data[4] {
+        return None;
+    }
+    let humidity = u16::from_be_bytes([data[0], data[1]]) as f32 / 10.0;
+    let magnitude = u16::from_be_bytes([data[2] & 0x7f, data[3]]) as f32 / 10.0;
+    let temperature = if data[2] & 0x80 != 0 { -magnitude } else { magnitude };
+    Some((temperature, humidity))
+}
+
+/// Wait until the line leaves `high`; returns how long that took in µs
+fn wait_while(gpio: i32, high: bool) -> Option<u32> {
+    let start = unsafe { sys::esp_timer_get_time() };
+    loop {
+        let elapsed = (unsafe { sys::esp_timer_get_time() } - start) as u32;
+        if (unsafe { sys::gpio_get_level(gpio) } != 0) != high {
+            return Some(elapsed);
+        }
+        if elapsed > PHASE_TIMEOUT_US {
+            return None;
+        }
+    }
+}
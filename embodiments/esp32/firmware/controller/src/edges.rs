@@ -0,0 +1,195 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Interrupt-driven digital inputs
+//!
+//! A `digital_input` pin with `"interrupt": true` is not polled: a GPIO
+//! interrupt queues every edge its trigger selects as it happens,
+//! and each burst drains the queue. Pulses far shorter than a burst are
+//! therefore never missed:
+//!
+//! - The pin's neuron is 1.0 in every burst that saw at least one edge,
+//!   else 0.0
+//! - The number of edges since the previous sensory frame is reported in the
+//!   frame's `"ec"` array as `[neuron_id, count]`
+//!
+//! Edges closer than `debounce_ms` to the previous one are dropped in the
+//! interrupt handler. If the queue overflows between bursts, the excess
+//! edges are still counted.
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::cortical;
+use crate::debounce::{InputConfig, Trigger};
+use crate::pad;
+use crate::GpioPinConfig;
+use crate::fault;
+
+/// Inputs served by the edge interrupt
+pub const MAX_EDGE_INPUTS: usize = 16;
+
+/// Events held between bursts (a power of two)
+const QUEUE_LEN: usize = 64;
+
+/// Per-input state shared with the interrupt handler
+struct EdgeSlot {
+    debounce_us: AtomicU32,
+    /// Time of the last counted edge (low 32 bits of esp_timer)
+    last_us: AtomicU32,
+    /// Edges that found the queue full
+    overflow: AtomicU32,
+}
+
+// Only used as the array initializer below
+#[allow(clippy::declare_interior_mutable_const)]
+const IDLE_SLOT: EdgeSlot = EdgeSlot {
+    debounce_us: AtomicU32::new(0),
+    last_us: AtomicU32::new(0),
+    overflow: AtomicU32::new(0),
+};
+
+static SLOTS: [EdgeSlot; MAX_EDGE_INPUTS] = [IDLE_SLOT; MAX_EDGE_INPUTS];
+
+// Single-producer (the GPIO ISR service) single-consumer (the burst loop)
+// ring of events, each the slot index of an edge
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_EVENT: AtomicU32 = AtomicU32::new(0);
+static QUEUE: [AtomicU32; QUEUE_LEN] = [EMPTY_EVENT; QUEUE_LEN];
+static HEAD: AtomicUsize = AtomicUsize::new(0);
+static TAIL: AtomicUsize = AtomicUsize::new(0);
+
+struct EdgeInput {
+    pin: u32,
+    neuron_id: u32,
+    /// Edges since the last sensory frame
+    count: u32,
+}
+
+/// All interrupt-driven digital inputs
+pub struct EdgeBank {
+    inputs: Vec<EdgeInput, MAX_EDGE_INPUTS>,
+}
+
+impl EdgeBank {
+    /// Attach the edge interrupt to each interrupt-driven `digital_input`
+    /// pin in GPIO_CONFIG
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut inputs = Vec::new();
+        for gpio_config in config {
+            let Some(input) = gpio_config.input.filter(|i| i.interrupt) else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            let index = inputs.len();
+            if index == MAX_EDGE_INPUTS {
+                break;
+            }
+            if !attach(index, gpio_config.pin, &input) {
+                fault!(PinInit, "Failed to attach interrupt to GPIO {}", gpio_config.pin);
+                continue;
+            }
+            pad::set_pull(gpio_config.pin, gpio_config.pull);
+            let _ = inputs.push(EdgeInput {
+                pin: gpio_config.pin,
+                neuron_id,
+                count: 0,
+            });
+        }
+        Self { inputs }
+    }
+
+    /// Drain the event queue, returning (pin, neuron_id, value, level) per
+    /// input: value 1.0 if it saw an edge since the previous call
+    pub fn read_all(&mut self) -> Vec<(u32, u32, f32, bool), MAX_EDGE_INPUTS> {
+        let mut fired = [0u32; MAX_EDGE_INPUTS];
+        let head = HEAD.load(Ordering::Acquire);
+        let mut tail = TAIL.load(Ordering::Relaxed);
+        while tail != head {
+            let index = QUEUE[tail % QUEUE_LEN].load(Ordering::Relaxed) as usize;
+            if let Some(count) = fired.get_mut(index) {
+                *count += 1;
+            }
+            tail = tail.wrapping_add(1);
+        }
+        TAIL.store(tail, Ordering::Release);
+
+        let mut readings = Vec::new();
+        for (index, input) in self.inputs.iter_mut().enumerate() {
+            let edges = fired[index] + SLOTS[index].overflow.swap(0, Ordering::Relaxed);
+            input.count = input.count.saturating_add(edges);
+            let level = unsafe { sys::gpio_get_level(input.pin as i32) } != 0;
+            let value = if edges > 0 { 1.0 } else { 0.0 };
+            let _ = readings.push((input.pin, input.neuron_id, value, level));
+        }
+        readings
+    }
+
+    /// (neuron_id, edges) since the previous call; call when a frame is sent
+    pub fn take_counts(&mut self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.inputs
+            .iter_mut()
+            .map(|input| (input.neuron_id, core::mem::take(&mut input.count)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+/// Configure `pin` as an input raising `SLOTS[index]` events on its edges
+fn attach(index: usize, pin: u32, config: &InputConfig) -> bool {
+    let slot = &SLOTS[index];
+    slot.debounce_us.store(config.debounce_ms.saturating_mul(1000), Ordering::Relaxed);
+    // build.rs rejects level triggers on interrupt-driven inputs
+    let intr_type = match config.trigger {
+        Trigger::Rising => sys::gpio_int_type_t_GPIO_INTR_POSEDGE,
+        Trigger::Falling => sys::gpio_int_type_t_GPIO_INTR_NEGEDGE,
+        Trigger::Level | Trigger::Both => sys::gpio_int_type_t_GPIO_INTR_ANYEDGE,
+    };
+    unsafe {
+        let gpio = pin as i32;
+        if sys::gpio_reset_pin(gpio) != sys::ESP_OK
+            || sys::gpio_set_direction(gpio, sys::gpio_mode_t_GPIO_MODE_INPUT) != sys::ESP_OK
+            || sys::gpio_set_intr_type(gpio, intr_type) != sys::ESP_OK
+        {
+            return false;
+        }
+        // Shared with other drivers (e.g. the W5500), so it may already be installed
+        let ret = sys::gpio_install_isr_service(0);
+        if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+            return false;
+        }
+        sys::gpio_isr_handler_add(gpio, Some(on_edge), index as *mut c_void) == sys::ESP_OK
+            && sys::gpio_intr_enable(gpio) == sys::ESP_OK
+    }
+}
+
+/// Edge on an input: queue it unless it falls within the debounce time
+unsafe extern "C" fn on_edge(arg: *mut c_void) {
+    let index = arg as usize;
+    let slot = &SLOTS[index];
+    let now_us = sys::esp_timer_get_time() as u32;
+    let debounce_us = slot.debounce_us.load(Ordering::Relaxed);
+    if debounce_us > 0 && now_us.wrapping_sub(slot.last_us.load(Ordering::Relaxed)) < debounce_us {
+        return;
+    }
+    slot.last_us.store(now_us, Ordering::Relaxed);
+
+    let head = HEAD.load(Ordering::Relaxed);
+    if head.wrapping_sub(TAIL.load(Ordering::Acquire)) >= QUEUE_LEN {
+        slot.overflow.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    QUEUE[head % QUEUE_LEN].store(index as u32, Ordering::Relaxed);
+    HEAD.store(head.wrapping_add(1), Ordering::Release);
+}
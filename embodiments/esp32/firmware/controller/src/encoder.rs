@@ -0,0 +1,205 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Quadrature rotary encoders on the pulse counter (PCNT) peripheral
+//!
+//! An `encoder_input` pin is the encoder's A channel; `encoder.pin_b` is its
+//! B channel. Both edges of both channels are counted (x4 decoding) in
+//! hardware, so no steps are lost between bursts. Each burst reports two
+//! sensory channels on consecutive neurons starting at the mapped ID:
+//!
+//! | Neuron | Value                                                          |
+//! |--------|----------------------------------------------------------------|
+//! | N      | Position within a revolution, 0.0-1.0                          |
+//! | N + 1  | Velocity: 0.5 = stopped, 0.0/1.0 = `max_rpm` backward/forward |
+//!
+//! The count is kept across counter overflows, so the position stays exact
+//! however far the shaft turns.
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::fault;
+
+/// Encoder settings of one pin (from config.json `encoder` block)
+#[derive(Debug, Clone, Copy)]
+pub struct EncoderConfig {
+    /// GPIO of the B channel (the pin itself is A)
+    pub pin_b: u32,
+    /// Counts per shaft revolution after x4 decoding (4x the encoder's PPR)
+    pub counts_per_rev: u32,
+    /// Speed reported as full velocity
+    pub max_rpm: u32,
+}
+
+/// Hardware counter limit; the driver accumulates the count past it
+#[cfg(esp_idf_soc_pcnt_supported)]
+const COUNT_LIMIT: i32 = 30_000;
+
+/// Pulses shorter than this are ignored as glitches
+#[cfg(esp_idf_soc_pcnt_supported)]
+const GLITCH_NS: u32 = 1_000;
+
+#[cfg(esp_idf_soc_pcnt_supported)]
+type UnitHandle = sys::pcnt_unit_handle_t;
+#[cfg(not(esp_idf_soc_pcnt_supported))]
+type UnitHandle = ();
+
+struct EncoderChannel {
+    pin: u32,
+    neuron_id: u32,
+    counts_per_rev: u32,
+    max_rpm: u32,
+    unit: UnitHandle,
+    last_count: i32,
+    last_us: i64,
+}
+
+/// All configured encoders (capacity `N` from the build config)
+pub struct EncoderBank<const N: usize> {
+    channels: Vec<EncoderChannel, N>,
+}
+
+impl<const N: usize> EncoderBank<N> {
+    /// Set up a pulse counter unit for each `encoder_input` pin in GPIO_CONFIG
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut channels = Vec::new();
+        for gpio_config in config {
+            let Some(encoder) = gpio_config.encoder else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            let Some(unit) = start_unit(gpio_config.pin, encoder.pin_b) else {
+                fault!(PinInit, "No pulse counter for encoder on GPIO {}", gpio_config.pin);
+                continue;
+            };
+            let _ = channels.push(EncoderChannel {
+                pin: gpio_config.pin,
+                neuron_id,
+                counts_per_rev: encoder.counts_per_rev.max(1),
+                max_rpm: encoder.max_rpm.max(1),
+                unit,
+                last_count: 0,
+                last_us: unsafe { sys::esp_timer_get_time() },
+            });
+        }
+        Self { channels }
+    }
+
+    /// Read every encoder, returning (neuron_id, position, velocity)
+    ///
+    /// Velocity is measured over the time since the previous call.
+    pub fn read_all(&mut self) -> Vec<(u32, f32, f32), N> {
+        let mut readings = Vec::new();
+        let now_us = unsafe { sys::esp_timer_get_time() };
+        for channel in self.channels.iter_mut() {
+            let Some(count) = read_count(&channel.unit) else {
+                continue;
+            };
+            let counts_per_rev = channel.counts_per_rev as i32;
+            let position = count.rem_euclid(counts_per_rev) as f32 / counts_per_rev as f32;
+            let elapsed_us = (now_us - channel.last_us).max(1) as f32;
+            let rpm = count.wrapping_sub(channel.last_count) as f32 / counts_per_rev as f32 * 60_000_000.0 / elapsed_us;
+            let velocity = (0.5 + rpm / channel.max_rpm as f32 / 2.0).clamp(0.0, 1.0);
+            channel.last_count = count;
+            channel.last_us = now_us;
+            let _ = readings.push((channel.neuron_id, position, velocity));
+        }
+        readings
+    }
+
+    /// Accumulated count of the encoder whose A channel is `pin`
+    pub fn count(&self, pin: u32) -> Option<i32> {
+        let channel = self.channels.iter().find(|c| c.pin == pin)?;
+        read_count(&channel.unit)
+    }
+}
+
+/// Create and start a unit counting the A/B channels in quadrature
+#[cfg(esp_idf_soc_pcnt_supported)]
+fn start_unit(pin_a: u32, pin_b: u32) -> Option<UnitHandle> {
+    unsafe {
+        let mut unit_config: sys::pcnt_unit_config_t = core::mem::zeroed();
+        unit_config.low_limit = -COUNT_LIMIT;
+        unit_config.high_limit = COUNT_LIMIT;
+        unit_config.flags.set_accum_count(1);
+        let mut unit: sys::pcnt_unit_handle_t = core::ptr::null_mut();
+        if sys::pcnt_new_unit(&unit_config, &mut unit) != sys::ESP_OK {
+            return None;
+        }
+        let filter = sys::pcnt_glitch_filter_config_t { max_glitch_ns: GLITCH_NS };
+        if sys::pcnt_unit_set_glitch_filter(unit, &filter) != sys::ESP_OK {
+            return None;
+        }
+
+        // Edges on each channel count up or down depending on the other
+        // channel's level
+        for (edge_pin, level_pin, rising, falling) in [
+            (
+                pin_a,
+                pin_b,
+                sys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_DECREASE,
+                sys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_INCREASE,
+            ),
+            (
+                pin_b,
+                pin_a,
+                sys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_INCREASE,
+                sys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_DECREASE,
+            ),
+        ] {
+            let mut chan_config: sys::pcnt_chan_config_t = core::mem::zeroed();
+            chan_config.edge_gpio_num = edge_pin as i32;
+            chan_config.level_gpio_num = level_pin as i32;
+            let mut channel: sys::pcnt_channel_handle_t = core::ptr::null_mut();
+            if sys::pcnt_new_channel(unit, &chan_config, &mut channel) != sys::ESP_OK
+                || sys::pcnt_channel_set_edge_action(channel, rising, falling) != sys::ESP_OK
+                || sys::pcnt_channel_set_level_action(
+                    channel,
+                    sys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_KEEP,
+                    sys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_INVERSE,
+                ) != sys::ESP_OK
+            {
+                return None;
+            }
+        }
+
+        // Overflow watch points let the driver accumulate past the limits
+        if sys::pcnt_unit_add_watch_point(unit, COUNT_LIMIT) != sys::ESP_OK
+            || sys::pcnt_unit_add_watch_point(unit, -COUNT_LIMIT) != sys::ESP_OK
+            || sys::pcnt_unit_enable(unit) != sys::ESP_OK
+            || sys::pcnt_unit_clear_count(unit) != sys::ESP_OK
+            || sys::pcnt_unit_start(unit) != sys::ESP_OK
+        {
+            return None;
+        }
+        Some(unit)
+    }
+}
+
+#[cfg(not(esp_idf_soc_pcnt_supported))]
+fn start_unit(_pin_a: u32, _pin_b: u32) -> Option<UnitHandle> {
+    None
+}
+
+#[cfg(esp_idf_soc_pcnt_supported)]
+fn read_count(unit: &UnitHandle) -> Option<i32> {
+    let mut count: i32 = 0;
+    if unsafe { sys::pcnt_unit_get_count(*unit, &mut count) } != sys::ESP_OK {
+        return None;
+    }
+    Some(count)
+}
+
+#[cfg(not(esp_idf_soc_pcnt_supported))]
+fn read_count(_unit: &UnitHandle) -> Option<i32> {
+    None
+}
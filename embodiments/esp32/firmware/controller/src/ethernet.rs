@@ -0,0 +1,205 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Wired Ethernet through a W5500 on SPI
+//!
+//! For labs where WiFi is not allowed, `"transport": {"type": "ethernet"}`
+//! brings up a WIZnet W5500 module on the SPI pins from config.json and
+//! carries the same protocols as the WiFi station (TCP by default, or
+//! WebSocket, UDP, MQTT, ZeroMQ) over it. As with WiFi, link and address are
+//! polled from the burst loop: a pulled cable is just a transport the
+//! supervisor keeps restarting until the link comes back.
+//!
+//! The W5500 has no factory MAC address, so the ESP32's Ethernet MAC (from
+//! eFuse) is assigned to it.
+
+use core::ffi::{c_char, c_void};
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+
+use crate::transport::Transport;
+use crate::wifi::{open_endpoint, set_static_ip, WifiConfig};
+
+/// SPI wiring of the W5500 (from config.json `transport.config.spi`)
+#[derive(Debug, Clone, Copy)]
+pub struct EthernetSpi {
+    /// SPI2 (HSPI) = 2, SPI3 (VSPI) = 3
+    pub host: u32,
+    pub sclk: i32,
+    pub mosi: i32,
+    pub miso: i32,
+    pub cs: i32,
+    /// W5500 INTn, required: frames are received on its interrupt
+    pub int: i32,
+    /// W5500 RSTn (None = not wired)
+    pub rst: Option<i32>,
+    pub clock_mhz: u32,
+}
+
+/// W5500 wiring plus the FEAGI endpoint
+#[derive(Debug, Clone, Copy)]
+pub struct EthernetConfig {
+    pub spi: EthernetSpi,
+    /// Endpoint, protocol, static IP and hostname as for WiFi; ssid,
+    /// password and provisioning are unused
+    pub network: WifiConfig,
+}
+
+/// The Ethernet interface, brought up once at boot
+pub struct Ethernet {
+    handle: sys::esp_eth_handle_t,
+    netif: *mut sys::esp_netif_t,
+    config: EthernetConfig,
+}
+
+impl Ethernet {
+    /// Initialize the SPI bus, the W5500 driver and its netif and start the
+    /// port
+    ///
+    /// Returns None if the module couldn't be brought up (wiring, or no
+    /// W5500 answering). A missing link is not an error: `open_transport`
+    /// keeps retrying.
+    pub fn start(config: EthernetConfig) -> Option<Self> {
+        let spi = config.spi;
+        unsafe {
+            if sys::esp_netif_init() != sys::ESP_OK {
+                return None;
+            }
+            let ret = sys::esp_event_loop_create_default();
+            if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+                return None;
+            }
+            // The W5500 driver hooks its INTn pin through the shared ISR service
+            let ret = sys::gpio_install_isr_service(0);
+            if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+                return None;
+            }
+
+            let mut bus: sys::spi_bus_config_t = core::mem::zeroed();
+            bus.__bindgen_anon_1.mosi_io_num = spi.mosi;
+            bus.__bindgen_anon_2.miso_io_num = spi.miso;
+            bus.sclk_io_num = spi.sclk;
+            bus.__bindgen_anon_3.quadwp_io_num = -1;
+            bus.__bindgen_anon_4.quadhd_io_num = -1;
+            bus.data4_io_num = -1;
+            bus.data5_io_num = -1;
+            bus.data6_io_num = -1;
+            bus.data7_io_num = -1;
+            if sys::spi_bus_initialize(spi.host, &bus, sys::spi_common_dma_t_SPI_DMA_CH_AUTO) != sys::ESP_OK {
+                return None;
+            }
+
+            // W5500 frame: 16-bit address phase as "command", 8-bit control as "address"
+            let mut device: sys::spi_device_interface_config_t = core::mem::zeroed();
+            device.command_bits = 16;
+            device.address_bits = 8;
+            device.mode = 0;
+            device.clock_speed_hz = (spi.clock_mhz * 1_000_000) as i32;
+            device.queue_size = 20;
+            device.spics_io_num = spi.cs;
+
+            // ETH_W5500_DEFAULT_CONFIG(), ETH_MAC_DEFAULT_CONFIG(), ETH_PHY_DEFAULT_CONFIG()
+            let mut w5500: sys::eth_w5500_config_t = core::mem::zeroed();
+            w5500.int_gpio_num = spi.int;
+            w5500.spi_host_id = spi.host;
+            w5500.spi_devcfg = &mut device;
+            let mut mac_config: sys::eth_mac_config_t = core::mem::zeroed();
+            mac_config.sw_reset_timeout_ms = 100;
+            mac_config.rx_task_stack_size = 4096;
+            mac_config.rx_task_prio = 15;
+            let mut phy_config: sys::eth_phy_config_t = core::mem::zeroed();
+            phy_config.phy_addr = -1;
+            phy_config.reset_timeout_ms = 100;
+            phy_config.autonego_timeout_ms = 4000;
+            phy_config.reset_gpio_num = spi.rst.unwrap_or(-1);
+
+            let mac = sys::esp_eth_mac_new_w5500(&w5500, &mac_config);
+            let phy = sys::esp_eth_phy_new_w5500(&phy_config);
+            if mac.is_null() || phy.is_null() {
+                return None;
+            }
+
+            // ETH_DEFAULT_CONFIG(mac, phy)
+            let mut eth_config: sys::esp_eth_config_t = core::mem::zeroed();
+            eth_config.mac = mac;
+            eth_config.phy = phy;
+            eth_config.check_link_period_ms = 2000;
+            let mut handle: sys::esp_eth_handle_t = core::ptr::null_mut();
+            if sys::esp_eth_driver_install(&eth_config, &mut handle) != sys::ESP_OK {
+                return None;
+            }
+            let mut mac_addr = [0u8; 6];
+            sys::esp_read_mac(mac_addr.as_mut_ptr(), sys::esp_mac_type_t_ESP_MAC_ETH);
+            if sys::esp_eth_ioctl(handle, sys::esp_eth_io_cmd_t_ETH_CMD_S_MAC_ADDR, mac_addr.as_mut_ptr() as *mut c_void) != sys::ESP_OK {
+                return None;
+            }
+
+            // ESP_NETIF_DEFAULT_ETH(); the glue registers the default event handlers
+            let netif_config = sys::esp_netif_config_t {
+                base: core::ptr::addr_of!(sys::_g_esp_netif_inherent_eth_config),
+                driver: core::ptr::null(),
+                stack: sys::_g_esp_netif_netstack_default_eth,
+            };
+            let netif = sys::esp_netif_new(&netif_config);
+            if netif.is_null() {
+                return None;
+            }
+            if let Some(hostname) = config.network.hostname {
+                sys::esp_netif_set_hostname(netif, hostname.as_ptr() as *const c_char);
+            }
+            if let Some(ref static_ip) = config.network.static_ip {
+                if !set_static_ip(netif, static_ip) {
+                    return None;
+                }
+            }
+            let glue = sys::esp_eth_new_netif_glue(handle);
+            if sys::esp_netif_attach(netif, glue as *mut c_void) != sys::ESP_OK || sys::esp_eth_start(handle) != sys::ESP_OK {
+                return None;
+            }
+            Some(Self { handle, netif, config })
+        }
+    }
+
+    /// Stop the port (the board failed over to another transport)
+    pub fn stop(self) {
+        unsafe {
+            sys::esp_eth_stop(self.handle);
+        }
+    }
+
+    /// Link up and holding an IP address
+    pub fn is_connected(&self) -> bool {
+        unsafe {
+            let mut ip: sys::esp_netif_ip_info_t = core::mem::zeroed();
+            sys::esp_netif_is_netif_up(self.netif)
+                && sys::esp_netif_get_ip_info(self.netif, &mut ip) == sys::ESP_OK
+                && ip.ip.addr != 0
+        }
+    }
+
+    /// Wait for link and DHCP (the driver brings the link up by itself)
+    pub fn connect(&self) -> bool {
+        let mut waited_ms = 0;
+        while !self.is_connected() {
+            if waited_ms >= self.config.network.connect_timeout_ms {
+                return false;
+            }
+            FreeRtos::delay_ms(100);
+            waited_ms += 100;
+        }
+        true
+    }
+
+    /// Wait for the link, then open the configured protocol to FEAGI
+    pub fn open_transport(&self) -> Option<Transport> {
+        if !self.connect() {
+            return None;
+        }
+        open_endpoint(&self.config.network)
+    }
+}
@@ -0,0 +1,231 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! GPIO expanders on the I2C bus (MCP23017, PCF8574)
+//!
+//! Each expander in config.json `i2c.expanders` adds a block of virtual pin
+//! numbers starting at its `first_pin` (100 and up). GPIO entries on those
+//! pins are `digital_input`s and `digital_output`s like any other: the
+//! input path reads their level from here and the output path writes it
+//! here (including triggers, debouncing, population outputs and output
+//! echo).
+//!
+//! The expanders are read once per burst before the inputs are sampled, and
+//! changed outputs are written once per burst after the motor commands were
+//! applied, so a burst costs one short transaction per expander at most in
+//! each direction.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::i2c::I2cBus;
+use crate::{fault, info};
+use crate::pad::Pull;
+use crate::{GpioMode, GpioPinConfig, I2C_EXPANDERS};
+
+/// Supported expander chips
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpanderChip {
+    /// 16 pins in two 8-bit ports, with pull-ups
+    Mcp23017,
+    /// 8 quasi-bidirectional pins (inputs are outputs written high)
+    Pcf8574,
+}
+
+impl ExpanderChip {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpanderChip::Mcp23017 => "mcp23017",
+            ExpanderChip::Pcf8574 => "pcf8574",
+        }
+    }
+
+    pub const fn pins(self) -> u32 {
+        match self {
+            ExpanderChip::Mcp23017 => 16,
+            ExpanderChip::Pcf8574 => 8,
+        }
+    }
+}
+
+/// One expander (from config.json `i2c.expanders`)
+#[derive(Debug, Clone, Copy)]
+pub struct ExpanderConfig {
+    pub chip: ExpanderChip,
+    pub address: u8,
+    /// Virtual pin number of the expander's first pin
+    pub first_pin: u32,
+}
+
+/// Expanders on one bus
+pub const MAX_EXPANDERS: usize = 8;
+
+// MCP23017 registers (IOCON.BANK = 0, the power-on default)
+const MCP_IODIRA: u8 = 0x00;
+const MCP_GPPUA: u8 = 0x0C;
+const MCP_GPIOA: u8 = 0x12;
+const MCP_OLATA: u8 = 0x14;
+
+// Output latches written by the output path, and the levels read by the
+// last `read_inputs`, one bit per expander pin; only touched from the
+// burst loop
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+static LATCHES: [AtomicU32; MAX_EXPANDERS] = [ZERO; MAX_EXPANDERS];
+static LEVELS: [AtomicU32; MAX_EXPANDERS] = [ZERO; MAX_EXPANDERS];
+/// Expanders whose latch changed since it was last written
+static DIRTY: AtomicU32 = AtomicU32::new(0);
+
+/// Expander index and bit of a virtual pin
+fn locate(pin: u32) -> Option<(usize, u32)> {
+    I2C_EXPANDERS.iter().enumerate().find_map(|(index, expander)| {
+        let bit = pin.checked_sub(expander.first_pin)?;
+        (bit < expander.chip.pins()).then_some((index, bit))
+    })
+}
+
+/// Whether `pin` is an expander pin rather than a native GPIO
+pub fn is_virtual(pin: u32) -> bool {
+    locate(pin).is_some()
+}
+
+/// Level of an expander input as of the last `read_inputs`
+pub fn read_pin(pin: u32) -> Option<bool> {
+    let (index, bit) = locate(pin)?;
+    Some(LEVELS[index].load(Ordering::Relaxed) & (1 << bit) != 0)
+}
+
+/// Set an expander output; written out by the next `write_outputs`
+pub fn write_pin(pin: u32, high: bool) {
+    let Some((index, bit)) = locate(pin) else {
+        return;
+    };
+    let latch = &LATCHES[index];
+    let previous = if high {
+        latch.fetch_or(1 << bit, Ordering::Relaxed)
+    } else {
+        latch.fetch_and(!(1 << bit), Ordering::Relaxed)
+    };
+    if (previous & (1 << bit) != 0) != high {
+        DIRTY.fetch_or(1 << index, Ordering::Relaxed);
+    }
+}
+
+struct Expander {
+    config: ExpanderConfig,
+    /// Pins configured as inputs
+    inputs: u32,
+    /// Whether the chip answered its setup
+    online: bool,
+}
+
+/// All configured expanders
+pub struct ExpanderBank {
+    expanders: Vec<Expander, MAX_EXPANDERS>,
+}
+
+impl ExpanderBank {
+    /// Set every expander's pin directions and pull-ups from GPIO_CONFIG
+    pub fn init(bus: &mut I2cBus, config: &[GpioPinConfig]) -> Self {
+        let mut expanders = Vec::new();
+        for (index, expander) in I2C_EXPANDERS.iter().enumerate() {
+            let mut outputs = 0u32;
+            let mut pull_ups = 0u32;
+            for gpio_config in config {
+                let Some((i, bit)) = locate(gpio_config.pin) else {
+                    continue;
+                };
+                if i != index {
+                    continue;
+                }
+                if let GpioMode::DigitalOutput = gpio_config.mode {
+                    outputs |= 1 << bit;
+                }
+                if gpio_config.pull == Pull::Up {
+                    pull_ups |= 1 << bit;
+                }
+            }
+            // Unused pins stay inputs, as after power-up
+            let inputs = !outputs & ((1 << expander.chip.pins()) - 1);
+
+            let latch = LATCHES[index].load(Ordering::Relaxed);
+            let online = match expander.chip {
+                ExpanderChip::Mcp23017 => {
+                    let [iodir_a, iodir_b] = (inputs as u16).to_le_bytes();
+                    let [pull_a, pull_b] = (pull_ups as u16).to_le_bytes();
+                    let [latch_a, latch_b] = (latch as u16).to_le_bytes();
+                    // Latch before direction, so outputs start at their level
+                    bus.write_registers(expander.address, MCP_OLATA, &[latch_a, latch_b])
+                        && bus.write_registers(expander.address, MCP_GPPUA, &[pull_a, pull_b])
+                        && bus.write_registers(expander.address, MCP_IODIRA, &[iodir_a, iodir_b])
+                }
+                ExpanderChip::Pcf8574 => bus.write(expander.address, &[(latch | inputs) as u8]),
+            };
+            if online {
+                info!("GPIO expander 0x{:02x}: pins {}-{}",
+                    expander.address, expander.first_pin, expander.first_pin + expander.chip.pins() - 1);
+            } else {
+                fault!(DeviceInit, "No GPIO expander answering at 0x{:02x}", expander.address);
+            }
+            let _ = expanders.push(Expander {
+                config: *expander,
+                inputs,
+                online,
+            });
+        }
+        Self { expanders }
+    }
+
+    /// Read the input levels of every expander; call before sampling inputs
+    pub fn read_inputs(&mut self, bus: &mut I2cBus) {
+        for (index, expander) in self.expanders.iter().enumerate() {
+            if !expander.online || expander.inputs == 0 {
+                continue;
+            }
+            let address = expander.config.address;
+            let levels = match expander.config.chip {
+                ExpanderChip::Mcp23017 => {
+                    let mut buf = [0u8; 2];
+                    bus.read_registers(address, MCP_GPIOA, &mut buf)
+                        .then(|| u16::from_le_bytes(buf) as u32)
+                }
+                ExpanderChip::Pcf8574 => {
+                    let mut buf = [0u8; 1];
+                    bus.read(address, &mut buf).then_some(buf[0] as u32)
+                }
+            };
+            // A failed read keeps the previous levels
+            if let Some(levels) = levels {
+                LEVELS[index].store(levels, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Write the latches changed since the last call; call once per burst
+    /// after the motor commands were applied
+    pub fn write_outputs(&mut self, bus: &mut I2cBus) {
+        let dirty = DIRTY.swap(0, Ordering::Relaxed);
+        for (index, expander) in self.expanders.iter().enumerate() {
+            if dirty & (1 << index) == 0 || !expander.online {
+                continue;
+            }
+            let latch = LATCHES[index].load(Ordering::Relaxed);
+            let address = expander.config.address;
+            let written = match expander.config.chip {
+                ExpanderChip::Mcp23017 => bus.write_registers(address, MCP_OLATA, &(latch as u16).to_le_bytes()),
+                // Inputs must stay written high
+                ExpanderChip::Pcf8574 => bus.write(address, &[(latch | expander.inputs) as u8]),
+            };
+            if !written {
+                // Retry with the next burst
+                DIRTY.fetch_or(1 << index, Ordering::Relaxed);
+            }
+        }
+    }
+}
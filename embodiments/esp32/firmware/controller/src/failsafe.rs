@@ -0,0 +1,103 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Output failsafe for link loss, stalls and panics
+//!
+//! Outputs fall back to a safe value whenever the board stops hearing
+//! FEAGI, so a motor isn't left running at its last command:
+//!
+//! - The burst loop drives every output to its `safe_value` (low when not
+//!   configured) and stops every motor when the heartbeat times out
+//!   (keepalive.rs) and when the transport is restarted or failed over
+//!   after it dropped or went quiet (supervisor.rs). The next motor command
+//!   drives the outputs again
+//! - A stalled burst loop can't do that; the watchdog interrupt forces the
+//!   pads instead (watchdog.rs)
+//! - So does a panic, from a wrapper around ESP-IDF's panic handler (build.rs
+//!   links it in with `--wrap=esp_panic_handler`), before the backtrace is
+//!   printed and the board resets
+//!
+//! Forcing a pad detaches it from LEDC, so in the last two cases digital
+//! outputs go to their `safe_value` level and PWM and servo outputs to their
+//! boot state (see `OutputBank::safe_levels`).
+
+use core::ffi::{c_char, c_void};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+
+use crate::stepper;
+
+/// One bit per GPIO: pads to drive low, high or let float
+static SAFE_LOW: PinMask = PinMask::new();
+static SAFE_HIGH: PinMask = PinMask::new();
+static SAFE_FLOAT: PinMask = PinMask::new();
+
+/// A bit per GPIO (0-63), as two 32-bit words: the RISC-V chips (C3, C6)
+/// have no 64-bit atomics
+pub struct PinMask([AtomicU32; 2]);
+
+impl PinMask {
+    pub const fn new() -> Self {
+        Self([AtomicU32::new(0), AtomicU32::new(0)])
+    }
+
+    pub fn set(&self, pin: u32, on: bool) {
+        let (word, bit) = (&self.0[(pin / 32) as usize], 1u32 << (pin % 32));
+        if on {
+            word.fetch_or(bit, Ordering::Release);
+        } else {
+            word.fetch_and(!bit, Ordering::Release);
+        }
+    }
+
+    pub fn load(&self) -> u64 {
+        (self.0[1].load(Ordering::Acquire) as u64) << 32 | self.0[0].load(Ordering::Acquire) as u64
+    }
+}
+
+/// Record the level `pin` is forced to on a stall or panic (None = float)
+pub fn set_safe_level(pin: u32, level: Option<bool>) {
+    if pin >= 64 {
+        return;
+    }
+    SAFE_LOW.set(pin, level == Some(false));
+    SAFE_HIGH.set(pin, level == Some(true));
+    SAFE_FLOAT.set(pin, level.is_none());
+}
+
+/// Halt the steppers and force every output pad to its safe level, as a
+/// plain GPIO; safe to call from an interrupt or the panic handler
+pub fn force_pads() {
+    stepper::halt();
+    let (low, high, float) = (SAFE_LOW.load(), SAFE_HIGH.load(), SAFE_FLOAT.load());
+    unsafe {
+        for pin in 0..64u32 {
+            let bit = 1u64 << pin;
+            if (low | high) & bit != 0 {
+                // Take the pad back from LEDC (or the stepper) as a plain GPIO
+                sys::esp_rom_gpio_connect_out_signal(pin, sys::SIG_GPIO_OUT_IDX, false, false);
+                sys::gpio_set_level(pin as i32, (high & bit != 0) as u32);
+            } else if float & bit != 0 {
+                sys::gpio_set_direction(pin as i32, sys::gpio_mode_t_GPIO_MODE_INPUT);
+            }
+        }
+    }
+}
+
+extern "C" {
+    fn __real_esp_panic_handler(info: *mut c_void);
+}
+
+/// Called by ESP-IDF in place of its panic handler (Rust panics end up here
+/// too, through abort)
+#[no_mangle]
+pub unsafe extern "C" fn __wrap_esp_panic_handler(info: *mut c_void) {
+    force_pads();
+    sys::esp_rom_printf(b"[FEAGI] Panic, outputs safe-stopped\r\n\0".as_ptr() as *const c_char);
+    __real_esp_panic_handler(info);
+}
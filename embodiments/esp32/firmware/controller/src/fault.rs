@@ -0,0 +1,169 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Typed reports of recoverable faults, sent to the host
+//!
+//! Besides the console warning, every fault the board recovers from is
+//! queued for the host with a code and some context, and sent by the burst
+//! loop once a transport is up (boot-time faults included):
+//!
+//! `{"fault":{"code":"pin_init","tag":"analog","ctx":"GPIO 4: not an ADC1 pin, analog input disabled","ms":812}}`
+//!
+//! plus `"dropped":N` when the queue overflowed since the last one. `tag` is
+//! the module that raised it and `ms` the uptime at the time.
+//!
+//! | Code             | Raised when                                             |
+//! |------------------|---------------------------------------------------------|
+//! | `rx_overflow`    | Received bytes dropped: sent too fast, or an overlong line |
+//! | `parse_error`    | A host line isn't JSON, or has a mistyped field         |
+//! | `auth_failed`    | A line on an encrypted link didn't authenticate         |
+//! | `pin_init`       | A GPIO couldn't be set up as configured                 |
+//! | `device_init`    | A peripheral (I2C device, camera, I2S) didn't come up   |
+//! | `frame_overflow` | A sensory frame didn't fit its buffer and was dropped   |
+//! | `sensor_read`    | A sensor read failed                                    |
+//!
+//! Like the log macros, `fault!` works from any task but not from interrupts.
+
+use core::ffi::c_void;
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::String;
+
+use crate::logger::{self, Level};
+
+/// What went wrong
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    RxOverflow = 1,
+    ParseError,
+    AuthFailed,
+    PinInit,
+    DeviceInit,
+    FrameOverflow,
+    SensorRead,
+}
+
+impl Fault {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Fault::RxOverflow => "rx_overflow",
+            Fault::ParseError => "parse_error",
+            Fault::AuthFailed => "auth_failed",
+            Fault::PinInit => "pin_init",
+            Fault::DeviceInit => "device_init",
+            Fault::FrameOverflow => "frame_overflow",
+            Fault::SensorRead => "sensor_read",
+        }
+    }
+
+    fn from_u8(n: u8) -> Option<Self> {
+        [
+            Fault::RxOverflow,
+            Fault::ParseError,
+            Fault::AuthFailed,
+            Fault::PinInit,
+            Fault::DeviceInit,
+            Fault::FrameOverflow,
+            Fault::SensorRead,
+        ]
+        .into_iter()
+        .find(|f| *f as u8 == n)
+    }
+}
+
+/// Longest context kept; longer ones are cut
+const CTX_MAX: usize = 96;
+
+/// Longest module tag kept
+const TAG_MAX: usize = 16;
+
+/// Faults waiting for the burst loop
+const QUEUE_DEPTH: u32 = 16;
+
+/// Fault lines the burst loop sends per burst
+pub const LINES_PER_BURST: usize = 2;
+
+/// One fault, as queued for the burst loop
+#[repr(C)]
+struct Record {
+    fault: u8,
+    tag_len: u8,
+    len: u16,
+    ms: u32,
+    tag: [u8; TAG_MAX],
+    ctx: [u8; CTX_MAX],
+}
+
+static QUEUE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+/// Faults lost to a full queue since the last one sent
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Create the queue; call once, early at boot
+pub fn init() {
+    unsafe {
+        let queue = sys::xQueueGenericCreate(QUEUE_DEPTH, size_of::<Record>() as u32, 0);
+        QUEUE.store(queue, Ordering::Release);
+    }
+}
+
+/// Warn on the console and queue the fault for the host; use `fault!`
+pub fn report(fault: Fault, module: &str, args: fmt::Arguments) {
+    logger::write(Level::Warn, module, args);
+    let queue = QUEUE.load(Ordering::Acquire);
+    if queue.is_null() {
+        return;
+    }
+    let mut ctx: String<CTX_MAX> = String::new();
+    // Cut rather than dropped when too long
+    let _ = ctx.write_fmt(args);
+    let tag = logger::tag(module);
+    let ms = (unsafe { sys::esp_timer_get_time() } / 1000) as u32;
+    let mut record = Record { fault: fault as u8, tag_len: 0, len: 0, ms, tag: [0; TAG_MAX], ctx: [0; CTX_MAX] };
+    record.tag[..tag.len()].copy_from_slice(tag.as_bytes());
+    record.tag_len = tag.len() as u8;
+    record.ctx[..ctx.len()].copy_from_slice(ctx.as_bytes());
+    record.len = ctx.len() as u16;
+    if unsafe { sys::xQueueGenericSend(queue, &record as *const Record as *const c_void, 0, 0) } != 1 {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Next fault line, `{"fault":{...}}\n`, if any is waiting
+pub fn next_line() -> Option<String<{ 2 * CTX_MAX + 128 }>> {
+    let queue = QUEUE.load(Ordering::Acquire);
+    if queue.is_null() {
+        return None;
+    }
+    let mut record: Record = unsafe { core::mem::zeroed() };
+    if unsafe { sys::xQueueReceive(queue, &mut record as *mut Record as *mut c_void, 0) } != 1 {
+        return None;
+    }
+    let fault = Fault::from_u8(record.fault)?;
+    let tag = core::str::from_utf8(&record.tag[..record.tag_len as usize]).unwrap_or("");
+    let ctx = core::str::from_utf8(&record.ctx[..record.len as usize]).unwrap_or("");
+    let mut line: String<{ 2 * CTX_MAX + 128 }> = String::new();
+    let _ = write!(line, "{{\"fault\":{{\"code\":\"{}\",\"tag\":\"{}\",\"ctx\":\"", fault.code(), tag);
+    logger::push_escaped(&mut line, ctx);
+    let _ = write!(line, "\",\"ms\":{}", record.ms);
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let _ = write!(line, ",\"dropped\":{}", dropped);
+    }
+    let _ = line.push_str("}}\n");
+    Some(line)
+}
+
+/// Report a fault: `fault!(PinInit, "GPIO {}: ...", pin)`
+#[macro_export]
+macro_rules! fault {
+    ($fault:ident, $($arg:tt)*) => {
+        $crate::fault::report($crate::fault::Fault::$fault, module_path!(), format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Position feedback for analog-feedback servos
+//!
+//! A PWM output can be paired with an ADC pin wired to the servo's feedback
+//! potentiometer. The raw reading is calibrated into the same 0.0-1.0 range as
+//! the commanded value and reported to FEAGI as a proprioceptive channel.
+
+use heapless::Vec;
+
+use crate::adc::Adc1;
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::fault;
+use esp_idf_svc::sys;
+
+/// Feedback calibration for one output (from config.json `feedback` block)
+#[derive(Debug, Clone, Copy)]
+pub struct FeedbackConfig {
+    /// ADC1 pin connected to the feedback wire
+    pub pin: u32,
+    /// Raw ADC reading at commanded position 0.0
+    pub raw_min: u16,
+    /// Raw ADC reading at commanded position 1.0
+    pub raw_max: u16,
+    /// Cortical mapping for the measured position
+    pub cortical_mapping: &'static str,
+}
+
+impl FeedbackConfig {
+    /// Map a raw ADC reading into the commanded 0.0-1.0 range
+    ///
+    /// `raw_min` may be greater than `raw_max` for servos whose potentiometer
+    /// runs backwards.
+    pub fn calibrate(&self, raw: u16) -> f32 {
+        let span = self.raw_max as f32 - self.raw_min as f32;
+        if span == 0.0 {
+            return 0.0;
+        }
+        let position = (raw as f32 - self.raw_min as f32) / span;
+        position.clamp(0.0, 1.0)
+    }
+}
+
+struct FeedbackChannel {
+    config: FeedbackConfig,
+    adc_channel: sys::adc_channel_t,
+    neuron_id: u32,
+}
+
+/// All configured feedback channels (capacity `N` from the build config)
+pub struct FeedbackBank<const N: usize> {
+    adc: Option<Adc1>,
+    channels: Vec<FeedbackChannel, N>,
+}
+
+impl<const N: usize> FeedbackBank<N> {
+    /// Build the feedback bank from the `feedback` blocks in GPIO_CONFIG
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut channels = Vec::new();
+        let mut adc = None;
+        for gpio_config in config {
+            let Some(feedback) = gpio_config.feedback else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(feedback.cortical_mapping) else {
+                continue;
+            };
+            if adc.is_none() {
+                adc = Adc1::new();
+            }
+            let Some(ref mut unit) = adc else {
+                break;
+            };
+            match unit.configure_pin(feedback.pin) {
+                Some(adc_channel) => {
+                    let _ = channels.push(FeedbackChannel {
+                        config: feedback,
+                        adc_channel,
+                        neuron_id,
+                    });
+                }
+                None => fault!(PinInit, "GPIO {}: feedback pin {} is not an ADC1 pin", gpio_config.pin, feedback.pin),
+            }
+        }
+        Self { adc, channels }
+    }
+
+    /// Sample every feedback pin, returning (neuron_id, measured position)
+    pub fn read_all(&mut self) -> Vec<(u32, f32), N> {
+        let mut readings = Vec::new();
+        if let Some(ref mut adc) = self.adc {
+            for channel in self.channels.iter() {
+                if let Some(raw) = adc.read_raw(channel.adc_channel) {
+                    let _ = readings.push((channel.neuron_id, channel.config.calibrate(raw)));
+                }
+            }
+        }
+        readings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+}
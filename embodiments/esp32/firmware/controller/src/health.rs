@@ -0,0 +1,120 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Board health telemetry: chip temperature and burst-loop CPU load
+//!
+//! Both are added to the sensory frame as `"tc"` (°C) and `"cpu"` (0.0-1.0)
+//! once per second when `telemetry.board_health` is enabled, so hosts can
+//! correlate degraded performance with thermal or load conditions.
+//!
+//! The load is an estimate for the burst loop itself: time spent working
+//! divided by the burst period, where time blocked in delays or waiting on
+//! the UART counts as idle.
+
+use esp_idf_svc::sys;
+
+/// Smoothing factor for the load average (per burst)
+const LOAD_EMA_ALPHA: f32 = 0.1;
+
+/// Does this SoC have an internal temperature sensor? The original ESP32
+/// doesn't, so its frames carry `"cpu"` but no `"tc"`
+pub const CHIP_TEMP_SUPPORTED: bool = cfg!(esp_idf_soc_temp_sensor_supported);
+
+/// Internal temperature sensor (not present on the original ESP32)
+pub struct ChipTemp {
+    #[cfg(esp_idf_soc_temp_sensor_supported)]
+    handle: sys::temperature_sensor_handle_t,
+}
+
+impl ChipTemp {
+    #[cfg(esp_idf_soc_temp_sensor_supported)]
+    pub fn new() -> Option<Self> {
+        let config = sys::temperature_sensor_config_t {
+            range_min: -10,
+            range_max: 80,
+            ..Default::default()
+        };
+        let mut handle: sys::temperature_sensor_handle_t = core::ptr::null_mut();
+        unsafe {
+            if sys::temperature_sensor_install(&config, &mut handle) != sys::ESP_OK {
+                return None;
+            }
+            if sys::temperature_sensor_enable(handle) != sys::ESP_OK {
+                return None;
+            }
+        }
+        Some(Self { handle })
+    }
+
+    #[cfg(not(esp_idf_soc_temp_sensor_supported))]
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    #[cfg(esp_idf_soc_temp_sensor_supported)]
+    pub fn read_celsius(&mut self) -> Option<f32> {
+        let mut celsius: f32 = 0.0;
+        let status = unsafe { sys::temperature_sensor_get_celsius(self.handle, &mut celsius) };
+        (status == sys::ESP_OK).then_some(celsius)
+    }
+
+    #[cfg(not(esp_idf_soc_temp_sensor_supported))]
+    pub fn read_celsius(&mut self) -> Option<f32> {
+        None
+    }
+}
+
+/// Busy-time accounting for the burst loop
+pub struct LoadMeter {
+    period_us: i64,
+    burst_start_us: i64,
+    idle_start_us: Option<i64>,
+    idle_us: i64,
+    /// Smoothed load, 0.0-1.0
+    pub load: f32,
+}
+
+impl LoadMeter {
+    pub fn new(period_us: i64) -> Self {
+        Self {
+            period_us: period_us.max(1),
+            burst_start_us: now_us(),
+            idle_start_us: None,
+            idle_us: 0,
+            load: 0.0,
+        }
+    }
+
+    /// Mark the start of a blocking wait
+    pub fn idle_begin(&mut self) {
+        self.idle_start_us = Some(now_us());
+    }
+
+    /// Mark the end of a blocking wait
+    pub fn idle_end(&mut self) {
+        if let Some(start) = self.idle_start_us.take() {
+            self.idle_us += now_us() - start;
+        }
+    }
+
+    /// Close the current burst (before the end-of-burst delay) and start the
+    /// next; the closed burst's start and working time (µs)
+    pub fn end_burst(&mut self) -> (i64, i64) {
+        let now = now_us();
+        let start = self.burst_start_us;
+        let busy = (now - start - self.idle_us).max(0);
+        let sample = (busy as f32 / self.period_us as f32).min(1.0);
+        self.load += LOAD_EMA_ALPHA * (sample - self.load);
+        self.burst_start_us = now;
+        self.idle_us = 0;
+        (start, busy)
+    }
+}
+
+fn now_us() -> i64 {
+    unsafe { sys::esp_timer_get_time() }
+}
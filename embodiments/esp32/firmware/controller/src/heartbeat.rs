@@ -0,0 +1,121 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Activity-driven status LED heartbeat
+//!
+//! The on-board LED encodes which way traffic is flowing, so a one-way link
+//! (frames going out but no motor commands coming back, or the reverse) is
+//! visible at a glance without a serial monitor:
+//!
+//! | Pattern (2 s cycle)          | Meaning                                 |
+//! |------------------------------|-----------------------------------------|
+//! | Short blip every 2 s         | Link idle: nothing sent or received     |
+//! | One blink per second         | Sending sensory frames, no motor input  |
+//! | Double blink per second      | Receiving motor commands, nothing sent  |
+//! | Mostly on, brief off 1/s     | Both directions active                  |
+//!
+//! A direction counts as active for [`ACTIVITY_WINDOW_MS`] after its last
+//! frame. The LED is driven from an esp_timer callback, so the pattern keeps
+//! its shape at any burst frequency.
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+
+/// On-board LED of most ESP32 dev kits
+pub const STATUS_LED_GPIO: i32 = 2;
+
+/// A direction stays "active" this long after its last frame
+pub const ACTIVITY_WINDOW_MS: u32 = 1500;
+
+/// Pattern resolution: one bit per slot, 20 slots per 2 s cycle
+const SLOT_MS: u32 = 100;
+const SLOTS: u32 = 20;
+
+/// esp_timer time (ms) of the last sensory frame sent / motor command
+/// received, 0 = never
+static LAST_SENSORY_MS: AtomicU32 = AtomicU32::new(0);
+static LAST_MOTOR_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Which directions carried traffic recently
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkActivity {
+    Idle,
+    Sensory,
+    Motor,
+    Both,
+}
+
+impl LinkActivity {
+    /// Activity as of `now_ms`
+    pub fn at(now_ms: u32) -> Self {
+        let recent = |last: &AtomicU32| {
+            let t = last.load(Ordering::Relaxed);
+            t != 0 && now_ms.wrapping_sub(t) <= ACTIVITY_WINDOW_MS
+        };
+        match (recent(&LAST_SENSORY_MS), recent(&LAST_MOTOR_MS)) {
+            (false, false) => LinkActivity::Idle,
+            (true, false) => LinkActivity::Sensory,
+            (false, true) => LinkActivity::Motor,
+            (true, true) => LinkActivity::Both,
+        }
+    }
+
+    /// LED on/off per 100 ms slot (bit 0 = first slot of the cycle)
+    fn pattern(&self) -> u32 {
+        match self {
+            // 100 ms blip at the start of the cycle
+            LinkActivity::Idle => 1,
+            // 200 ms on at 0 s and 1 s
+            LinkActivity::Sensory => 0b11 | (0b11 << 10),
+            // Two 100 ms blips at 0 s and 1 s
+            LinkActivity::Motor => 0b101 | (0b101 << 10),
+            // On except the last slot of each second
+            LinkActivity::Both => ((1 << SLOTS) - 1) & !(1 << 9) & !(1 << 19),
+        }
+    }
+}
+
+/// A sensory frame went out
+pub fn sensory_sent() {
+    LAST_SENSORY_MS.store(now_ms(), Ordering::Relaxed);
+}
+
+/// A motor command came in
+pub fn motor_received() {
+    LAST_MOTOR_MS.store(now_ms(), Ordering::Relaxed);
+}
+
+/// Start driving the status LED on `gpio` (must already be an output)
+///
+/// Returns false if the timer couldn't be created.
+pub fn start(gpio: i32) -> bool {
+    let args = sys::esp_timer_create_args_t {
+        callback: Some(tick),
+        arg: gpio as *mut c_void,
+        name: b"heartbeat\0".as_ptr() as *const core::ffi::c_char,
+        ..Default::default()
+    };
+    let mut handle: sys::esp_timer_handle_t = core::ptr::null_mut();
+    unsafe {
+        sys::esp_timer_create(&args, &mut handle) == sys::ESP_OK
+            && sys::esp_timer_start_periodic(handle, SLOT_MS as u64 * 1000) == sys::ESP_OK
+    }
+}
+
+unsafe extern "C" fn tick(arg: *mut c_void) {
+    let now = now_ms();
+    let slot = (now / SLOT_MS) % SLOTS;
+    let on = (LinkActivity::at(now).pattern() >> slot) & 1;
+    sys::gpio_set_level(arg as i32, on);
+}
+
+fn now_ms() -> u32 {
+    // Never 0, which means "never" for the timestamps
+    ((unsafe { sys::esp_timer_get_time() } / 1000) as u32).max(1)
+}
@@ -0,0 +1,326 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! I2C bus and round-robin device polling scheduler
+//!
+//! Each configured device gets a polling rate and a per-transaction time
+//! budget. Every burst the scheduler polls the devices that are due, starting
+//! where the previous burst stopped, until the burst's I2C budget is spent;
+//! devices that don't fit are deferred to the next burst instead of stretching
+//! it. A device whose reads keep exceeding its budget is reported once as
+//! `{"i2c_slow":[[address,last_us,budget_us],...]}`.
+//!
+//! A device with a cortical mapping is also a sensory source: its latest
+//! register block is split into values of the configured format, each
+//! scaled to 0.0-1.0 by `full_scale`, and fed to consecutive neurons every
+//! burst. Register writes listed in `init` set the device up at boot.
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+/// Consecutive over-budget reads before a device is reported
+const OVERRUN_STREAK: u8 = 8;
+
+/// Bus transaction timeout, in FreeRTOS ticks
+const BUS_TIMEOUT_TICKS: u32 = 2;
+
+/// Largest register block a single device read may return
+pub const MAX_READ_LEN: usize = 32;
+
+/// I2C bus pins and per-burst time budget
+#[derive(Debug, Clone, Copy)]
+pub struct I2cBusConfig {
+    pub sda: u32,
+    pub scl: u32,
+    pub freq_hz: u32,
+    /// Total I2C time allowed per burst
+    pub burst_budget_us: u32,
+}
+
+/// Longest register write in a device's `init` list
+pub const MAX_WRITE_LEN: usize = 8;
+
+/// One polled device: a register block read at a fixed rate
+#[derive(Debug, Clone, Copy)]
+pub struct I2cDeviceConfig {
+    pub address: u8,
+    pub register: u8,
+    pub len: u8,
+    pub rate_hz: u32,
+    /// Expected duration of one read
+    pub budget_us: u32,
+    /// (register, data) writes sent once at boot
+    pub init: &'static [(u8, &'static [u8])],
+    /// How the block is fed to FEAGI; None = polled only
+    pub channels: Option<I2cChannels>,
+}
+
+/// Encoding of the values in a register block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2cFormat {
+    U8,
+    U16Le,
+    U16Be,
+    I16Le,
+    I16Be,
+}
+
+impl I2cFormat {
+    pub const fn size(self) -> usize {
+        match self {
+            I2cFormat::U8 => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Sensory mapping of a device's register block (from config.json)
+#[derive(Debug, Clone, Copy)]
+pub struct I2cChannels {
+    pub format: I2cFormat,
+    /// Leading bytes of the block that aren't values
+    pub offset: u8,
+    /// Reading that maps to 1.0; signed formats map -full_scale to 0.0
+    /// and zero to 0.5
+    pub full_scale: u32,
+    /// "cortical_area:neuron_id" of the first value
+    pub cortical_mapping: &'static str,
+}
+
+impl I2cChannels {
+    /// The block's values, each scaled to 0.0-1.0
+    pub fn values<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = f32> + 'a {
+        let full_scale = self.full_scale.max(1) as f32;
+        data.get(self.offset as usize..)
+            .unwrap_or(&[])
+            .chunks_exact(self.format.size())
+            .map(move |bytes| {
+                let value = match self.format {
+                    I2cFormat::U8 => bytes[0] as f32 / full_scale,
+                    I2cFormat::U16Le => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / full_scale,
+                    I2cFormat::U16Be => u16::from_be_bytes([bytes[0], bytes[1]]) as f32 / full_scale,
+                    I2cFormat::I16Le => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / full_scale + 1.0) / 2.0,
+                    I2cFormat::I16Be => (i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / full_scale + 1.0) / 2.0,
+                };
+                value.clamp(0.0, 1.0)
+            })
+    }
+}
+
+/// Legacy-driver I2C master on port 0
+pub struct I2cBus {
+    port: sys::i2c_port_t,
+}
+
+impl I2cBus {
+    pub fn new(config: &I2cBusConfig) -> Option<Self> {
+        let port: sys::i2c_port_t = 0;
+        let mut bus_config: sys::i2c_config_t = unsafe { core::mem::zeroed() };
+        bus_config.mode = sys::i2c_mode_t_I2C_MODE_MASTER;
+        bus_config.sda_io_num = config.sda as i32;
+        bus_config.scl_io_num = config.scl as i32;
+        bus_config.sda_pullup_en = true;
+        bus_config.scl_pullup_en = true;
+        bus_config.__bindgen_anon_1.master.clk_speed = config.freq_hz;
+        unsafe {
+            if sys::i2c_param_config(port, &bus_config) != sys::ESP_OK {
+                return None;
+            }
+            if sys::i2c_driver_install(port, bus_config.mode, 0, 0, 0) != sys::ESP_OK {
+                return None;
+            }
+        }
+        Some(Self { port })
+    }
+
+    /// Read `buf.len()` bytes starting at `register`
+    pub fn read_registers(&mut self, address: u8, register: u8, buf: &mut [u8]) -> bool {
+        let status = unsafe {
+            sys::i2c_master_write_read_device(
+                self.port,
+                address,
+                &register,
+                1,
+                buf.as_mut_ptr(),
+                buf.len(),
+                BUS_TIMEOUT_TICKS,
+            )
+        };
+        status == sys::ESP_OK
+    }
+
+    /// Write one register (device setup; polled reads don't write)
+    pub fn write_register(&mut self, address: u8, register: u8, value: u8) -> bool {
+        self.write_registers(address, register, &[value])
+    }
+
+    /// Write up to MAX_WRITE_LEN bytes starting at `register`
+    pub fn write_registers(&mut self, address: u8, register: u8, values: &[u8]) -> bool {
+        let mut data: Vec<u8, { MAX_WRITE_LEN + 1 }> = Vec::new();
+        let _ = data.push(register);
+        if data.extend_from_slice(values).is_err() {
+            return false;
+        }
+        let status = unsafe {
+            sys::i2c_master_write_to_device(self.port, address, data.as_ptr(), data.len(), BUS_TIMEOUT_TICKS)
+        };
+        status == sys::ESP_OK
+    }
+
+    /// Read `buf.len()` bytes from a device without a register address
+    /// (e.g. PCF8574 port expanders)
+    pub fn read(&mut self, address: u8, buf: &mut [u8]) -> bool {
+        let status = unsafe {
+            sys::i2c_master_read_from_device(self.port, address, buf.as_mut_ptr(), buf.len(), BUS_TIMEOUT_TICKS)
+        };
+        status == sys::ESP_OK
+    }
+
+    /// Write bytes to a device without a register address
+    pub fn write(&mut self, address: u8, data: &[u8]) -> bool {
+        let status = unsafe {
+            sys::i2c_master_write_to_device(self.port, address, data.as_ptr(), data.len(), BUS_TIMEOUT_TICKS)
+        };
+        status == sys::ESP_OK
+    }
+}
+
+/// Scheduling state and latest reading of one device
+pub struct DeviceSlot {
+    pub config: I2cDeviceConfig,
+    period_us: i64,
+    next_due_us: i64,
+    /// Duration of the most recent read
+    pub last_us: u32,
+    overruns: u8,
+    /// Reported as over budget (cleared once it recovers)
+    pub slow: bool,
+    /// Most recent register block, valid for `config.len` bytes
+    pub data: [u8; MAX_READ_LEN],
+    /// Set when `data` was refreshed this burst
+    pub fresh: bool,
+    /// Set once `data` holds a reading
+    pub valid: bool,
+    pub errors: u32,
+}
+
+/// Round-robin poller sharing one burst budget across all devices
+pub struct I2cScheduler {
+    slots: Vec<DeviceSlot, 16>,
+    cursor: usize,
+    burst_budget_us: u32,
+    /// Due reads pushed to a later burst because the budget was spent
+    pub deferred: u32,
+}
+
+impl I2cScheduler {
+    pub fn new(burst_budget_us: u32) -> Self {
+        Self {
+            slots: Vec::new(),
+            cursor: 0,
+            burst_budget_us,
+            deferred: 0,
+        }
+    }
+
+    /// Register a device; returns false when the scheduler is full
+    pub fn register(&mut self, config: I2cDeviceConfig) -> bool {
+        let config = I2cDeviceConfig {
+            len: config.len.min(MAX_READ_LEN as u8),
+            ..config
+        };
+        self.slots
+            .push(DeviceSlot {
+                config,
+                period_us: 1_000_000 / config.rate_hz.max(1) as i64,
+                next_due_us: 0,
+                last_us: 0,
+                overruns: 0,
+                slow: false,
+                data: [0; MAX_READ_LEN],
+                fresh: false,
+                valid: false,
+                errors: 0,
+            })
+            .is_ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn devices(&self) -> &[DeviceSlot] {
+        &self.slots
+    }
+
+    /// Poll the devices that are due this burst
+    ///
+    /// `read` performs one transaction into the given buffer. Returns the
+    /// indices of devices that just crossed the over-budget threshold.
+    pub fn poll_burst<F>(&mut self, mut read: F) -> Vec<usize, 16>
+    where
+        F: FnMut(&I2cDeviceConfig, &mut [u8]) -> bool,
+    {
+        let mut newly_slow = Vec::new();
+        let count = self.slots.len();
+        if count == 0 {
+            return newly_slow;
+        }
+        for slot in self.slots.iter_mut() {
+            slot.fresh = false;
+        }
+
+        let burst_start = unsafe { sys::esp_timer_get_time() };
+        let mut spent_us: u32 = 0;
+        let mut next_cursor = self.cursor;
+        for step in 0..count {
+            let index = (self.cursor + step) % count;
+            let slot = &mut self.slots[index];
+            if slot.next_due_us > burst_start {
+                continue;
+            }
+            if spent_us + slot.config.budget_us > self.burst_budget_us && spent_us > 0 {
+                // Out of budget: this device goes first next burst
+                self.deferred += 1;
+                next_cursor = index;
+                break;
+            }
+
+            let start = unsafe { sys::esp_timer_get_time() };
+            let len = slot.config.len as usize;
+            if read(&slot.config, &mut slot.data[..len]) {
+                slot.fresh = true;
+                slot.valid = true;
+            } else {
+                slot.errors += 1;
+            }
+            let elapsed = (unsafe { sys::esp_timer_get_time() } - start) as u32;
+            spent_us += elapsed;
+            slot.last_us = elapsed;
+            // Keep the rate, but don't burst to catch up after a long stall
+            slot.next_due_us = if slot.next_due_us + slot.period_us < burst_start {
+                burst_start + slot.period_us
+            } else {
+                slot.next_due_us + slot.period_us
+            };
+            next_cursor = (index + 1) % count;
+
+            if elapsed > slot.config.budget_us {
+                slot.overruns = slot.overruns.saturating_add(1);
+                if slot.overruns >= OVERRUN_STREAK && !slot.slow {
+                    slot.slow = true;
+                    let _ = newly_slow.push(index);
+                }
+            } else {
+                slot.overruns = 0;
+                slot.slow = false;
+            }
+        }
+        self.cursor = next_cursor;
+        newly_slow
+    }
+}
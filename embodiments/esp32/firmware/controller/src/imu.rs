@@ -0,0 +1,191 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Accelerometer/gyroscope IMUs on the I2C bus (MPU-6050, ICM-20948)
+//!
+//! The IMU is set up once at boot (wake, full-scale ranges, low-pass filter)
+//! and then polled by the I2C scheduler like any other device: one block
+//! read of its accel and gyro registers at `rate_hz`. Each fresh block is
+//! decoded into six sensory channels on consecutive neurons starting at the
+//! ID in `cortical_mapping`:
+//!
+//! | Neuron         | Axis                  |
+//! |----------------|-----------------------|
+//! | N .. N + 2     | Acceleration X, Y, Z  |
+//! | N + 3 .. N + 5 | Rotation rate X, Y, Z |
+//!
+//! Values span the configured range: 0.0 = -range, 0.5 = at rest (gyro) or
+//! no acceleration along the axis, 1.0 = +range.
+
+use esp_idf_svc::hal::delay::FreeRtos;
+
+use crate::cortical;
+use crate::i2c::{I2cBus, I2cDeviceConfig};
+
+/// Supported IMU chips
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImuChip {
+    Mpu6050,
+    Icm20948,
+}
+
+/// IMU on the I2C bus (from config.json `i2c.imu`)
+#[derive(Debug, Clone, Copy)]
+pub struct ImuConfig {
+    pub chip: ImuChip,
+    pub address: u8,
+    pub rate_hz: u32,
+    /// Expected duration of one block read
+    pub budget_us: u32,
+    /// Accelerometer full scale: 2, 4, 8 or 16 g
+    pub accel_range_g: u32,
+    /// Gyroscope full scale: 250, 500, 1000 or 2000 degrees/s
+    pub gyro_range_dps: u32,
+    /// "cortical_area:neuron_id" of the first (accel X) neuron
+    pub cortical_mapping: &'static str,
+}
+
+impl ImuConfig {
+    /// The register block the scheduler polls
+    pub fn device(&self) -> I2cDeviceConfig {
+        let (register, len) = match self.chip {
+            // ACCEL_XOUT_H .. GYRO_ZOUT_L, with TEMP_OUT in between
+            ImuChip::Mpu6050 => (MPU6050_ACCEL_XOUT_H, 14),
+            // ACCEL_XOUT_H .. GYRO_ZOUT_L (bank 0)
+            ImuChip::Icm20948 => (ICM20948_ACCEL_XOUT_H, 12),
+        };
+        I2cDeviceConfig {
+            address: self.address,
+            register,
+            len,
+            rate_hz: self.rate_hz,
+            budget_us: self.budget_us,
+            // Set up and decoded here rather than by the scheduler
+            init: &[],
+            channels: None,
+        }
+    }
+}
+
+const MPU6050_WHO_AM_I: u8 = 0x75;
+const MPU6050_PWR_MGMT_1: u8 = 0x6B;
+const MPU6050_CONFIG: u8 = 0x1A;
+const MPU6050_GYRO_CONFIG: u8 = 0x1B;
+const MPU6050_ACCEL_CONFIG: u8 = 0x1C;
+const MPU6050_ACCEL_XOUT_H: u8 = 0x3B;
+
+const ICM20948_REG_BANK_SEL: u8 = 0x7F;
+const ICM20948_WHO_AM_I: u8 = 0x00;
+const ICM20948_PWR_MGMT_1: u8 = 0x06;
+const ICM20948_PWR_MGMT_2: u8 = 0x07;
+const ICM20948_ACCEL_XOUT_H: u8 = 0x2D;
+/// Bank 2
+const ICM20948_GYRO_CONFIG_1: u8 = 0x01;
+const ICM20948_ACCEL_CONFIG: u8 = 0x14;
+
+/// An initialized IMU and its latest reading
+pub struct Imu {
+    chip: ImuChip,
+    first_neuron: u32,
+    /// Accel X/Y/Z, gyro X/Y/Z as 0.0-1.0
+    values: [f32; 6],
+}
+
+impl Imu {
+    /// Reset and configure the IMU
+    ///
+    /// Returns None if the mapping has no neuron ID, or the chip doesn't
+    /// answer with the expected WHO_AM_I (wrong chip, address or wiring).
+    pub fn init(bus: &mut I2cBus, config: &ImuConfig) -> Option<Self> {
+        let first_neuron = cortical::neuron_id(config.cortical_mapping)?;
+        let address = config.address;
+        // Full-scale select: 2/4/8/16 g and 250/500/1000/2000 dps are 0-3
+        let accel_fs = (config.accel_range_g / 2).trailing_zeros() as u8;
+        let gyro_fs = (config.gyro_range_dps / 250).trailing_zeros() as u8;
+        let mut who = [0u8];
+        match config.chip {
+            ImuChip::Mpu6050 => {
+                // MPU-6050, or a register-compatible MPU-6500/9250 or clone
+                if !bus.read_registers(address, MPU6050_WHO_AM_I, &mut who) || !matches!(who[0], 0x68 | 0x70 | 0x71 | 0x72 | 0x98) {
+                    return None;
+                }
+                if !bus.write_register(address, MPU6050_PWR_MGMT_1, 0x80) {
+                    return None;
+                }
+                FreeRtos::delay_ms(100);
+                // Awake, clocked from the X gyro PLL; ~44 Hz low-pass
+                let setup = [
+                    (MPU6050_PWR_MGMT_1, 0x01),
+                    (MPU6050_CONFIG, 0x03),
+                    (MPU6050_GYRO_CONFIG, gyro_fs << 3),
+                    (MPU6050_ACCEL_CONFIG, accel_fs << 3),
+                ];
+                if !setup.iter().all(|&(register, value)| bus.write_register(address, register, value)) {
+                    return None;
+                }
+            }
+            ImuChip::Icm20948 => {
+                if !bus.write_register(address, ICM20948_REG_BANK_SEL, 0x00)
+                    || !bus.read_registers(address, ICM20948_WHO_AM_I, &mut who)
+                    || who[0] != 0xEA
+                {
+                    return None;
+                }
+                if !bus.write_register(address, ICM20948_PWR_MGMT_1, 0x80) {
+                    return None;
+                }
+                FreeRtos::delay_ms(10);
+                // Awake with the best clock, accel and gyro on; ~50 Hz low-pass;
+                // back to bank 0 for the data registers
+                let setup = [
+                    (ICM20948_REG_BANK_SEL, 0x00),
+                    (ICM20948_PWR_MGMT_1, 0x01),
+                    (ICM20948_PWR_MGMT_2, 0x00),
+                    (ICM20948_REG_BANK_SEL, 0x20),
+                    (ICM20948_GYRO_CONFIG_1, (3 << 3) | (gyro_fs << 1) | 1),
+                    (ICM20948_ACCEL_CONFIG, (3 << 3) | (accel_fs << 1) | 1),
+                    (ICM20948_REG_BANK_SEL, 0x00),
+                ];
+                if !setup.iter().all(|&(register, value)| bus.write_register(address, register, value)) {
+                    return None;
+                }
+            }
+        }
+        Some(Self {
+            chip: config.chip,
+            first_neuron,
+            values: [0.5; 6],
+        })
+    }
+
+    /// Decode a freshly polled register block
+    pub fn update(&mut self, data: &[u8]) {
+        // Big-endian i16 per axis; the MPU-6050 has TEMP_OUT between accel and gyro
+        let gyro_offset = match self.chip {
+            ImuChip::Mpu6050 => 8,
+            ImuChip::Icm20948 => 6,
+        };
+        if data.len() < gyro_offset + 6 {
+            return;
+        }
+        for axis in 0..3 {
+            self.values[axis] = normalize(&data[axis * 2..]);
+            self.values[axis + 3] = normalize(&data[gyro_offset + axis * 2..]);
+        }
+    }
+
+    /// (neuron_id, value) of the six channels, from the latest reading
+    pub fn channels(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.values.iter().enumerate().map(|(i, &value)| (self.first_neuron + i as u32, value))
+    }
+}
+
+/// Map a big-endian i16 reading (-full scale .. +full scale) to 0.0-1.0
+fn normalize(bytes: &[u8]) -> f32 {
+    let raw = i16::from_be_bytes([bytes[0], bytes[1]]);
+    (raw as f32 + 32768.0) / 65535.0
+}
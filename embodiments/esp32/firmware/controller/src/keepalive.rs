@@ -0,0 +1,111 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Heartbeats in both directions and link-loss detection
+//!
+//! With `transport.keepalive` configured the board sends `{"hb":N}` every
+//! `interval_ms` (N counts up from 0 at boot), and the hello tells the
+//! host the interval as `"hb":<ms>`. The host is expected to send something
+//! at least as often; `{"hb":N}` will do when it has nothing else to say.
+//!
+//! Once the host has been heard from, `timeout_ms` without receiving anything
+//! counts as a lost link: outputs go to their safe states (as for a
+//! safe-stop) and the transport is restarted, again every `timeout_ms` while
+//! it stays silent, with the usual supervision backoff (supervisor.rs). The
+//! first line received afterwards restores the link and motor commands drive
+//! the outputs again. Light sleep (sleep.rs) restarts both timers on wake, as
+//! the host's lines went unheard meanwhile.
+
+use heapless::String;
+
+use crate::u32_to_string;
+
+/// Keepalive timing (from config.json `transport.keepalive`)
+#[derive(Debug, Clone, Copy)]
+pub struct KeepaliveConfig {
+    /// Heartbeat period, board → host
+    pub interval_ms: u32,
+    /// Silence after which the link counts as lost
+    pub timeout_ms: u32,
+}
+
+/// What the keepalive wants done this burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeepaliveEvent {
+    None,
+    /// Send this `{"hb":N}` line
+    Beat(u32),
+    /// Nothing received for timeout_ms: the link just went down
+    Lost,
+    /// Another timeout_ms without anything: try reconnecting again
+    StillLost,
+}
+
+pub struct Keepalive {
+    config: KeepaliveConfig,
+    /// When something was last received; None until the host is first heard
+    last_rx_us: Option<i64>,
+    next_beat_us: i64,
+    beats: u32,
+    lost: bool,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig, now_us: i64) -> Self {
+        Self {
+            config,
+            last_rx_us: None,
+            next_beat_us: now_us,
+            beats: 0,
+            lost: false,
+        }
+    }
+
+    /// Something was received; true if that restores a lost link
+    pub fn record_rx(&mut self, now_us: i64) -> bool {
+        self.last_rx_us = Some(now_us);
+        core::mem::replace(&mut self.lost, false)
+    }
+
+    /// The board light-slept: count the silence and next heartbeat from now
+    pub fn resume(&mut self, now_us: i64) {
+        if self.last_rx_us.is_some() {
+            self.last_rx_us = Some(now_us);
+        }
+        self.next_beat_us = now_us;
+    }
+
+    /// Check the timers; at most one event per burst, a timeout first
+    pub fn poll(&mut self, now_us: i64) -> KeepaliveEvent {
+        if let Some(last) = self.last_rx_us {
+            if now_us - last >= self.config.timeout_ms as i64 * 1000 {
+                // Next timeout window
+                self.last_rx_us = Some(now_us);
+                let was_lost = core::mem::replace(&mut self.lost, true);
+                return if was_lost { KeepaliveEvent::StillLost } else { KeepaliveEvent::Lost };
+            }
+        }
+        if now_us < self.next_beat_us {
+            return KeepaliveEvent::None;
+        }
+        self.next_beat_us = now_us + self.config.interval_ms as i64 * 1000;
+        let beat = self.beats;
+        self.beats = self.beats.wrapping_add(1);
+        KeepaliveEvent::Beat(beat)
+    }
+}
+
+/// `{"hb":N}` line
+pub fn beat_line(n: u32) -> String<24> {
+    let mut line: String<24> = String::new();
+    let mut num: String<16> = String::new();
+    u32_to_string(n, &mut num);
+    let _ = line.push_str("{\"hb\":");
+    let _ = line.push_str(&num);
+    let _ = line.push_str("}\n");
+    line
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WS2812/NeoPixel LED strips on the RMT peripheral
+//!
+//! A `led_strip` pin drives the data line of an addressable strip. Its
+//! cortical mapping names the first neuron N; every LED takes three
+//! consecutive neurons for red, green and blue, so LED `i`'s channel `c`
+//! (0 = R, 1 = G, 2 = B) is neuron `N + 3 * i + c` and the value sets its
+//! brightness (0.0-1.0). The strip's wire color order is handled here.
+//!
+//! Commands only update the frame in RAM; changed frames are sent once per
+//! burst by `refresh`, from a second buffer so a frame is never changed
+//! while the RMT is still clocking it out.
+
+use core::ffi::c_void;
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+use static_cell::ConstStaticCell;
+
+use crate::cortical;
+use crate::{GpioPinConfig, LED_STRIP_BYTES};
+use crate::fault;
+
+/// Byte order a strip expects on the wire
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorOrder {
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorOrder::Rgb => "rgb",
+            ColorOrder::Rbg => "rbg",
+            ColorOrder::Grb => "grb",
+            ColorOrder::Gbr => "gbr",
+            ColorOrder::Brg => "brg",
+            ColorOrder::Bgr => "bgr",
+        }
+    }
+
+    /// Logical channel (0 = R, 1 = G, 2 = B) sent as each wire byte
+    fn channels(&self) -> [usize; 3] {
+        match self {
+            ColorOrder::Rgb => [0, 1, 2],
+            ColorOrder::Rbg => [0, 2, 1],
+            ColorOrder::Grb => [1, 0, 2],
+            ColorOrder::Gbr => [1, 2, 0],
+            ColorOrder::Brg => [2, 0, 1],
+            ColorOrder::Bgr => [2, 1, 0],
+        }
+    }
+}
+
+/// Strip length and color order (from config.json `led_strip` block)
+#[derive(Debug, Clone, Copy)]
+pub struct LedStripConfig {
+    pub length: u32,
+    pub color_order: ColorOrder,
+}
+
+/// Strips on one board (one RMT TX channel each)
+pub const MAX_LED_STRIPS: usize = 4;
+
+/// RMT tick rate: 0.1 µs per tick
+const RMT_RESOLUTION_HZ: u32 = 10_000_000;
+
+/// WS2812 bit timings in ticks: (high, low)
+const BIT0_TICKS: (u16, u16) = (4, 8);
+const BIT1_TICKS: (u16, u16) = (8, 4);
+
+/// Longest wait for the previous frame before sending the next
+const TX_WAIT_MS: i32 = 10;
+
+// Commanded frames (RGB per LED) and the wire-ordered copies being sent, for
+// all strips back to back; taken once by the bank
+static PIXELS: ConstStaticCell<[u8; LED_STRIP_BYTES]> = ConstStaticCell::new([0; LED_STRIP_BYTES]);
+static WIRE: ConstStaticCell<[u8; LED_STRIP_BYTES]> = ConstStaticCell::new([0; LED_STRIP_BYTES]);
+
+struct LedStrip {
+    first_neuron: u32,
+    color_order: ColorOrder,
+    /// Range of this strip in PIXELS and WIRE
+    start: usize,
+    len: usize,
+    channel: sys::rmt_channel_handle_t,
+    encoder: sys::rmt_encoder_handle_t,
+    /// The frame changed since it was last sent
+    dirty: bool,
+}
+
+/// All LED strips of the board
+pub struct LedStripBank {
+    strips: Vec<LedStrip, MAX_LED_STRIPS>,
+    pixels: &'static mut [u8; LED_STRIP_BYTES],
+    wire: &'static mut [u8; LED_STRIP_BYTES],
+}
+
+impl LedStripBank {
+    /// Set up an RMT channel for each `led_strip` pin in GPIO_CONFIG and
+    /// blank the strips
+    ///
+    /// Call once: the strips share one static frame buffer, and a second
+    /// call panics.
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut strips = Vec::new();
+        let mut start = 0;
+        for gpio_config in config {
+            let Some(led_strip) = gpio_config.led_strip else {
+                continue;
+            };
+            let len = led_strip.length as usize * 3;
+            if start + len > LED_STRIP_BYTES {
+                break;
+            }
+            let Some(first_neuron) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            let Some((channel, encoder)) = start_channel(gpio_config.pin) else {
+                fault!(PinInit, "No RMT channel for LED strip on GPIO {}", gpio_config.pin);
+                continue;
+            };
+            let _ = strips.push(LedStrip {
+                first_neuron,
+                color_order: led_strip.color_order,
+                start,
+                len,
+                channel,
+                encoder,
+                dirty: true,
+            });
+            start += len;
+        }
+        let mut bank = Self {
+            strips,
+            pixels: PIXELS.take(),
+            wire: WIRE.take(),
+        };
+        bank.refresh();
+        bank
+    }
+
+    /// Set the LED channel mapped to `neuron_id`; returns true if one matched
+    pub fn apply(&mut self, neuron_id: u32, value: f32) -> bool {
+        let mut matched = false;
+        for strip in self.strips.iter_mut() {
+            let Some(offset) = neuron_id.checked_sub(strip.first_neuron) else {
+                continue;
+            };
+            if offset as usize >= strip.len {
+                continue;
+            }
+            self.pixels[strip.start + offset as usize] = (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+            strip.dirty = true;
+            matched = true;
+        }
+        matched
+    }
+
+    /// Turn every LED off
+    pub fn clear(&mut self) {
+        for strip in self.strips.iter_mut() {
+            self.pixels[strip.start..strip.start + strip.len].fill(0);
+            strip.dirty = true;
+        }
+    }
+
+    /// Send the frames that changed; call once per burst
+    pub fn refresh(&mut self) {
+        for strip in self.strips.iter_mut().filter(|s| s.dirty) {
+            // The previous frame must be out before its buffer is reused
+            if unsafe { sys::rmt_tx_wait_all_done(strip.channel, TX_WAIT_MS) } != sys::ESP_OK {
+                continue;
+            }
+            let order = strip.color_order.channels();
+            for led in (strip.start..strip.start + strip.len).step_by(3) {
+                for (byte, &channel) in order.iter().enumerate() {
+                    self.wire[led + byte] = self.pixels[led + channel];
+                }
+            }
+            let sent = unsafe {
+                let tx_config: sys::rmt_transmit_config_t = core::mem::zeroed();
+                sys::rmt_transmit(
+                    strip.channel,
+                    strip.encoder,
+                    self.wire[strip.start..].as_ptr() as *const c_void,
+                    strip.len,
+                    &tx_config,
+                ) == sys::ESP_OK
+            };
+            if sent {
+                strip.dirty = false;
+            }
+        }
+    }
+}
+
+/// Create an RMT TX channel on `pin` with a WS2812 bytes encoder
+fn start_channel(pin: u32) -> Option<(sys::rmt_channel_handle_t, sys::rmt_encoder_handle_t)> {
+    unsafe {
+        let mut channel_config: sys::rmt_tx_channel_config_t = core::mem::zeroed();
+        channel_config.gpio_num = pin as i32;
+        channel_config.clk_src = sys::soc_periph_rmt_clk_src_t_RMT_CLK_SRC_DEFAULT;
+        channel_config.resolution_hz = RMT_RESOLUTION_HZ;
+        channel_config.mem_block_symbols = 64;
+        channel_config.trans_queue_depth = 1;
+        let mut channel: sys::rmt_channel_handle_t = core::ptr::null_mut();
+        if sys::rmt_new_tx_channel(&channel_config, &mut channel) != sys::ESP_OK {
+            return None;
+        }
+
+        let mut encoder_config: sys::rmt_bytes_encoder_config_t = core::mem::zeroed();
+        for (symbol, (high, low)) in [(&mut encoder_config.bit0, BIT0_TICKS), (&mut encoder_config.bit1, BIT1_TICKS)] {
+            symbol.__bindgen_anon_1.set_duration0(high);
+            symbol.__bindgen_anon_1.set_level0(1);
+            symbol.__bindgen_anon_1.set_duration1(low);
+            symbol.__bindgen_anon_1.set_level1(0);
+        }
+        encoder_config.flags.set_msb_first(1);
+        let mut encoder: sys::rmt_encoder_handle_t = core::ptr::null_mut();
+        if sys::rmt_new_bytes_encoder(&encoder_config, &mut encoder) != sys::ESP_OK
+            || sys::rmt_enable(channel) != sys::ESP_OK
+        {
+            return None;
+        }
+        Some((channel, encoder))
+    }
+}
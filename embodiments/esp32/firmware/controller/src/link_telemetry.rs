@@ -0,0 +1,82 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WiFi link quality as a sensory channel
+//!
+//! With `telemetry.link` in config.json, the sensory frame carries a `"wl"`
+//! array once per second, so the brain (and operators) can react to a
+//! degrading connection before it drops. Like feedback pins, the values are
+//! 0.0-1.0 potentials on consecutive neurons starting at the ID in
+//! `cortical_mapping`:
+//!
+//! | Neuron | Value                                                       |
+//! |--------|-------------------------------------------------------------|
+//! | N      | Signal strength: -100 dBm or worse = 0.0, -30 dBm = 1.0     |
+//! | N + 1  | Share of the last second's frames that had to be re-sent    |
+//! | N + 2  | 1.0 if the station lost the access point in the last second |
+//!
+//! Signal strength is 0.0 while the station is not associated.
+//!
+//! The re-sent share counts frames that failed to go out and were sent again
+//! from the outage queue (controller-core/src/frame_queue.rs); the 802.11
+//! retries of the WiFi driver itself are not exposed by ESP-IDF.
+
+use crate::cortical;
+
+/// Link telemetry channel (from config.json `telemetry.link`)
+#[derive(Debug, Clone, Copy)]
+pub struct LinkTelemetryConfig {
+    /// "cortical_area:neuron_id" of the RSSI neuron; the others follow it
+    pub cortical_mapping: &'static str,
+}
+
+/// Signal strength mapped to 0.0 and 1.0
+const RSSI_FLOOR_DBM: f32 = -100.0;
+const RSSI_CEILING_DBM: f32 = -30.0;
+
+/// Turns per-second counter deltas into the three link neurons
+pub struct LinkMonitor {
+    first_neuron: u32,
+    last_sent: u32,
+    last_resent: u32,
+    last_disconnects: u32,
+}
+
+impl LinkMonitor {
+    /// None if the mapping has no neuron ID (build.rs rejects that)
+    pub fn new(config: &LinkTelemetryConfig) -> Option<Self> {
+        Some(Self {
+            first_neuron: cortical::neuron_id(config.cortical_mapping)?,
+            last_sent: 0,
+            last_resent: 0,
+            last_disconnects: 0,
+        })
+    }
+
+    /// Values for this second, from the running counters: frames sent
+    /// directly, frames re-sent from the queue, and station disconnects
+    pub fn sample(&mut self, rssi_dbm: Option<i8>, sent: u32, resent: u32, disconnects: u32) -> [(u32, f32); 3] {
+        let sent_delta = sent.wrapping_sub(self.last_sent);
+        let resent_delta = resent.wrapping_sub(self.last_resent);
+        let disconnected = disconnects != self.last_disconnects;
+        self.last_sent = sent;
+        self.last_resent = resent;
+        self.last_disconnects = disconnects;
+
+        let strength = match rssi_dbm {
+            Some(dbm) => ((dbm as f32 - RSSI_FLOOR_DBM) / (RSSI_CEILING_DBM - RSSI_FLOOR_DBM)).clamp(0.0, 1.0),
+            None => 0.0,
+        };
+        let total = sent_delta.saturating_add(resent_delta);
+        let resent_share = if total == 0 { 0.0 } else { resent_delta as f32 / total as f32 };
+        [
+            (self.first_neuron, strength),
+            (self.first_neuron + 1, resent_share),
+            (self.first_neuron + 2, if disconnected { 1.0 } else { 0.0 }),
+        ]
+    }
+}
@@ -0,0 +1,249 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Leveled console logging, optionally forwarded to the host
+//!
+//! `error!`, `warn!`, `info!` and `debug!` take `format_args!` arguments and
+//! tag the line with the calling module:
+//!
+//! `[FEAGI] W ota: Firmware update rejected: bad signature`
+//!
+//! Lines at or above `log.level` go to the console. Lines at or above
+//! `log.forward` are also sent to the host by the burst loop, as
+//! `{"log":{"level":"warn","tag":"ota","msg":"..."}}` (plus `"dropped":N`
+//! when the queue overflowed since the last one). Both are runtime settings
+//! (settings.rs); forwarding is off unless config.json turns it on.
+//!
+//! The macros work from any task (the HTTP servers, the transport task) but
+//! not from interrupts: watchdog.rs's handler prints directly.
+
+use core::ffi::{c_char, c_void};
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU8, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::String;
+
+/// Log settings (from config.json `log`)
+#[derive(Debug, Clone, Copy)]
+pub struct LogConfig {
+    /// Console threshold
+    pub level: Level,
+    /// Forwarding threshold (None = off)
+    pub forward: Option<Level>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    fn letter(&self) -> char {
+        match self {
+            Level::Error => 'E',
+            Level::Warn => 'W',
+            Level::Info => 'I',
+            Level::Debug => 'D',
+        }
+    }
+
+    fn from_u8(n: u8) -> Option<Self> {
+        [Level::Error, Level::Warn, Level::Info, Level::Debug].into_iter().find(|l| *l as u8 == n)
+    }
+}
+
+/// Longest line printed or forwarded; longer ones are cut
+const LINE_MAX: usize = 160;
+
+/// Longest module tag kept for forwarding
+const TAG_MAX: usize = 16;
+
+/// Records waiting for the burst loop
+const QUEUE_DEPTH: u32 = 16;
+
+/// Forwarded lines the burst loop sends per burst, so a flurry of logging
+/// can't crowd out sensory frames
+pub const LINES_PER_BURST: usize = 4;
+
+/// One forwarded line, as queued for the burst loop
+#[repr(C)]
+struct Record {
+    level: u8,
+    tag_len: u8,
+    len: u16,
+    tag: [u8; TAG_MAX],
+    msg: [u8; LINE_MAX],
+}
+
+// Levels as u8 (0 = off), until init() the build defaults
+static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static FORWARD_LEVEL: AtomicU8 = AtomicU8::new(0);
+static QUEUE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+/// Records lost to a full queue since the last forwarded one
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Apply config.json's levels and create the forwarding queue; call once,
+/// early at boot
+pub fn init(config: &LogConfig) {
+    set_levels(config.level, config.forward);
+    unsafe {
+        let queue = sys::xQueueGenericCreate(QUEUE_DEPTH, size_of::<Record>() as u32, 0);
+        QUEUE.store(queue, Ordering::Release);
+    }
+}
+
+/// Change the console and forwarding thresholds
+pub fn set_levels(level: Level, forward: Option<Level>) {
+    CONSOLE_LEVEL.store(level as u8, Ordering::Relaxed);
+    // ESP-IDF's own components follow the console threshold; its levels
+    // are numbered like ours
+    unsafe { sys::esp_log_level_set(b"*\0".as_ptr() as *const c_char, level as sys::esp_log_level_t) };
+    FORWARD_LEVEL.store(forward.map_or(0, |l| l as u8), Ordering::Relaxed);
+}
+
+/// Print (and queue for forwarding) one line; use the macros instead
+pub fn write(level: Level, module: &str, args: fmt::Arguments) {
+    let to_console = level as u8 <= CONSOLE_LEVEL.load(Ordering::Relaxed);
+    let to_host = level as u8 <= FORWARD_LEVEL.load(Ordering::Relaxed);
+    if !to_console && !to_host {
+        return;
+    }
+    let tag = tag(module);
+    let mut msg: String<LINE_MAX> = String::new();
+    // Cut rather than dropped when too long
+    let _ = msg.write_fmt(args);
+    if to_console {
+        // Always fits: the tag and message are bounded
+        let mut line: String<{ LINE_MAX + TAG_MAX + 16 }> = String::new();
+        let _ = write!(line, "[FEAGI] {} {}: {}\r\n\0", level.letter(), tag, msg);
+        unsafe {
+            sys::esp_rom_printf(b"%s\0".as_ptr() as *const c_char, line.as_ptr());
+        }
+    }
+    if to_host {
+        forward(level, tag, &msg);
+    }
+}
+
+/// Next forwarded line, `{"log":{...}}\n`, if any is waiting
+pub fn next_line() -> Option<String<{ 2 * LINE_MAX + 96 }>> {
+    let queue = QUEUE.load(Ordering::Acquire);
+    if queue.is_null() {
+        return None;
+    }
+    let mut record: Record = unsafe { core::mem::zeroed() };
+    if unsafe { sys::xQueueReceive(queue, &mut record as *mut Record as *mut c_void, 0) } != 1 {
+        return None;
+    }
+    let level = Level::from_u8(record.level)?;
+    let tag = core::str::from_utf8(&record.tag[..record.tag_len as usize]).unwrap_or("");
+    let msg = core::str::from_utf8(&record.msg[..record.len as usize]).unwrap_or("");
+    let mut line: String<{ 2 * LINE_MAX + 96 }> = String::new();
+    let _ = write!(line, "{{\"log\":{{\"level\":\"{}\",\"tag\":\"{}\",\"msg\":\"", level.as_str(), tag);
+    push_escaped(&mut line, msg);
+    let _ = line.push('"');
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let _ = write!(line, ",\"dropped\":{}", dropped);
+    }
+    let _ = line.push_str("}}\n");
+    Some(line)
+}
+
+fn forward(level: Level, tag: &str, msg: &str) {
+    let queue = QUEUE.load(Ordering::Acquire);
+    if queue.is_null() {
+        return;
+    }
+    let mut record = Record { level: level as u8, tag_len: 0, len: 0, tag: [0; TAG_MAX], msg: [0; LINE_MAX] };
+    record.tag[..tag.len()].copy_from_slice(tag.as_bytes());
+    record.tag_len = tag.len() as u8;
+    record.msg[..msg.len()].copy_from_slice(msg.as_bytes());
+    record.len = msg.len() as u16;
+    if unsafe { sys::xQueueGenericSend(queue, &record as *const Record as *const c_void, 0, 0) } != 1 {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Append `s` as the inside of a JSON string (control characters become
+/// spaces)
+pub fn push_escaped<const N: usize>(line: &mut String<N>, s: &str) {
+    for c in s.chars() {
+        let _ = match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            c if (c as u32) < 0x20 => line.push(' '),
+            c => line.push(c),
+        };
+    }
+}
+
+/// `ota` for `feagi_esp32_controller::ota`, `main` for the crate root; cut
+/// to TAG_MAX (module names are ASCII)
+pub fn tag(module: &str) -> &str {
+    let tag = match module.rsplit_once("::") {
+        Some((_, tag)) => tag,
+        None => "main",
+    };
+    &tag[..tag.len().min(TAG_MAX)]
+}
+
+/// Log at error level: the board can't go on as configured
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::Level::Error, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Log at warning level: something failed, the board carries on
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::Level::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Log at info level: configuration and state changes
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::Level::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Log at debug level: per-command detail, off by default
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logger::write($crate::logger::Level::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
@@ -14,29 +14,208 @@
 #![no_main]
 
 use esp_idf_svc::sys;
-use core::ffi::{c_char, CStr};
 
 // ESP32-specific imports
 use esp_idf_svc::hal::{
-    gpio::{Input, Output, PinDriver, AnyIOPin},
+    gpio::{Input, Output, PinDriver},
     peripherals::Peripherals,
-    uart::{config::Config as UartConfig, UartDriver},
     delay::FreeRtos,
-    units::Hertz,
 };
 use heapless::{Vec, String, Fmt};
+use static_cell::ConstStaticCell;
+use feagi_link::secure_link::{self, Role, SecureLink, SALT_LEN};
+use feagi_controller_core::compress::{self, CompressionConfig};
+use feagi_controller_core::frame_queue::FrameQueue;
+use feagi_controller_core::parse;
+use feagi_controller_core::profile::Profiler;
+use feagi_controller_core::rate_policy::{DeltaFilter, MotorPolicy, MotorShaper, RatePolicy, SensoryPolicy, SensoryWindow};
+
+mod adc;
+mod analog;
+mod audio;
+mod barrier;
+mod ble;
+mod boot_report;
+mod burst_timer;
+mod camera;
+mod capabilities;
+mod cortical;
+mod dc_motor;
+mod debounce;
+mod device_id;
+mod dht;
+mod edges;
+mod encoder;
+mod ethernet;
+mod expander;
+mod failsafe;
+mod fault;
+mod feedback;
+mod health;
+mod heartbeat;
+mod i2c;
+mod imu;
+mod keepalive;
+mod led_strip;
+mod link_telemetry;
+mod logger;
+mod mdns;
+mod messages;
+mod metrics;
+mod microphone;
+mod motor_ack;
+mod mqtt;
+mod odometry;
+mod onboard;
+mod ota;
+mod output_restore;
+mod outputs;
+mod pad;
+mod population;
+mod power;
+mod protocol;
+mod provisioning;
+mod pwm;
+mod raw_io;
+mod settings;
+mod sleep;
+mod status_server;
+mod stepper;
+mod stored_config;
+mod supervisor;
+mod sysid;
+mod time_sync;
+mod touch;
+mod transport;
+mod transport_task;
+mod ultrasonic;
+mod version;
+mod watchdog;
+mod websocket;
+mod wifi;
+mod zmtp;
+
+use analog::{AnalogBank, AnalogConfig};
+use audio::{AudioConfig, AudioDriver, Tone};
+use barrier::Barrier;
+use ble::{Ble, BleConfig};
+use burst_timer::BurstTimer;
+use camera::{Camera, CameraBoard, CameraConfig};
+use dc_motor::{DcMotorConfig, MotorDriver};
+use debounce::{InputConfig, InputFilter, Trigger};
+use dht::{DhtBank, DhtConfig};
+use edges::EdgeBank;
+use encoder::{EncoderBank, EncoderConfig};
+use ethernet::{Ethernet, EthernetConfig, EthernetSpi};
+use expander::{ExpanderBank, ExpanderChip, ExpanderConfig};
+use feedback::{FeedbackBank, FeedbackConfig};
+use health::{ChipTemp, LoadMeter};
+use i2c::{I2cBus, I2cBusConfig, I2cChannels, I2cDeviceConfig, I2cFormat, I2cScheduler};
+use imu::{Imu, ImuChip, ImuConfig};
+use keepalive::{Keepalive, KeepaliveConfig, KeepaliveEvent};
+use led_strip::{ColorOrder, LedStripConfig};
+use link_telemetry::{LinkMonitor, LinkTelemetryConfig};
+use logger::{Level, LogConfig};
+use messages::{MotorMessage, Neuron, SensoryFrame, Tenths, Unit};
+use metrics::{Metrics, MetricsConfig};
+use microphone::{Microphone, MicrophoneConfig};
+use motor_ack::{MotorAcks, Sequenced, Verdict, MAX_SEQUENCED};
+use mqtt::MqttConfig;
+use odometry::{Odometry, OdometryConfig};
+use onboard::{HallSensor, OnboardConfig};
+use ota::OtaConfig;
+use output_restore::RestoreConfig;
+use outputs::{BootState, OutputBank};
+use pad::{Drive, Pull};
+use population::PopulationConfig;
+use power::{PowerConfig, PowerMonitor, PowerSource};
+use pwm::{PwmConfig, ServoConfig};
+use provisioning::{Credentials, ProvisioningConfig};
+use raw_io::{RawIo, SessionMode};
+use settings::{Settings, SettingsError, Source, Tracked};
+use sleep::{GpioWake, SleepConfig, SleepMode, TouchWake, WakeReason};
+use status_server::Counters;
+use stepper::{StepperConfig, StepperControl};
+use supervisor::{Action, SupervisionConfig, TransportSupervisor};
+use sysid::SysIdRequest;
+use time_sync::TimeSyncConfig;
+use touch::{TouchBank, TouchConfig, TouchReport};
+use transport::{FeagiTransport, SerialConfig, SerialFraming, SerialLink, TlsConfig, Transport};
+use transport_task::TransportTaskConfig;
+use ultrasonic::{UltrasonicBank, UltrasonicConfig};
+use version::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use watchdog::WatchdogConfig;
+use wifi::{NetProtocol, StaticIp, Wifi, WifiConfig};
 
 // Include build-time configuration
 include!(concat!(env!("OUT_DIR"), "/config.rs"));
 
+// Sensory frames held while the transport is down; static to keep it off the
+// main task's stack, and taken once by the burst loop
+static SENSORY_QUEUE: ConstStaticCell<FrameQueue<FRAME_QUEUE_CAPACITY>> =
+    ConstStaticCell::new(FrameQueue::new(TRANSPORT_SUPERVISION.queue_frames));
+
+// get_config and get_settings answers (several KiB with many pins), likewise
+static SETTINGS_REPLY: ConstStaticCell<String<CONFIG_DUMP_CAPACITY>> = ConstStaticCell::new(String::new());
+
 // GPIO pin configuration structure
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GpioMode {
     Disabled,
     DigitalInput,
     DigitalOutput,
     AnalogInput,
     PwmOutput,
+    ServoOutput,
+    TouchInput,
+    EncoderInput,
+    UltrasonicInput,
+    LedStrip,
+    DhtInput,
+    DcMotor,
+    StepperOutput,
+}
+
+impl GpioMode {
+    /// Mode as named in config.json
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GpioMode::Disabled => "disabled",
+            GpioMode::DigitalInput => "digital_input",
+            GpioMode::DigitalOutput => "digital_output",
+            GpioMode::AnalogInput => "analog_input",
+            GpioMode::PwmOutput => "pwm_output",
+            GpioMode::ServoOutput => "servo_output",
+            GpioMode::TouchInput => "touch_input",
+            GpioMode::EncoderInput => "encoder_input",
+            GpioMode::UltrasonicInput => "ultrasonic_input",
+            GpioMode::LedStrip => "led_strip",
+            GpioMode::DhtInput => "dht_input",
+            GpioMode::DcMotor => "dc_motor",
+            GpioMode::StepperOutput => "stepper_output",
+        }
+    }
+
+    /// Mode named as in config.json
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            GpioMode::Disabled,
+            GpioMode::DigitalInput,
+            GpioMode::DigitalOutput,
+            GpioMode::AnalogInput,
+            GpioMode::PwmOutput,
+            GpioMode::ServoOutput,
+            GpioMode::TouchInput,
+            GpioMode::EncoderInput,
+            GpioMode::UltrasonicInput,
+            GpioMode::LedStrip,
+            GpioMode::DhtInput,
+            GpioMode::DcMotor,
+            GpioMode::StepperOutput,
+        ]
+        .into_iter()
+        .find(|mode| mode.as_str() == s)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -44,20 +223,40 @@ pub struct GpioPinConfig {
     pub pin: u32,
     pub mode: GpioMode,
     pub cortical_mapping: &'static str,
-}
-
-// Helper function to parse neuron ID from cortical mapping
-// Format: "cortical_area:neuron_id" or just "neuron_id"
-fn parse_neuron_id(mapping: &str) -> Option<u32> {
-    if let Ok(id) = mapping.parse::<u32>() {
-        return Some(id);
-    }
-    if let Some(idx) = mapping.rfind(':') {
-        if let Ok(id) = mapping[(idx + 1)..].parse::<u32>() {
-            return Some(id);
-        }
-    }
-    None
+    /// Output state between power-up and the first motor command
+    pub boot_state: BootState,
+    /// Value applied when the link is lost (digital, PWM and servo outputs)
+    pub safe_value: Option<f32>,
+    /// Internal pull resistors (digital inputs and outputs)
+    pub pull: Pull,
+    /// Push-pull or open-drain (digital outputs)
+    pub drive: Drive,
+    /// Optional analog position feedback (PWM outputs driving feedback servos)
+    pub feedback: Option<FeedbackConfig>,
+    /// Optional population-threshold decoding (outputs driven by a neuron range)
+    pub population: Option<PopulationConfig>,
+    /// LEDC frequency, resolution and channel (PWM and servo outputs)
+    pub pwm: Option<PwmConfig>,
+    /// Pulse and angle limits (servo outputs)
+    pub servo: Option<ServoConfig>,
+    /// H-bridge wiring, direction and deadband (DC motor outputs)
+    pub dc_motor: Option<DcMotorConfig>,
+    /// DIR/EN pins, control mode and motion limits (stepper outputs)
+    pub stepper: Option<StepperConfig>,
+    /// Scale, offset and calibration table (analog inputs)
+    pub analog: Option<AnalogConfig>,
+    /// Threshold and report mode (touch inputs)
+    pub touch: Option<TouchConfig>,
+    /// B channel and scaling (encoder inputs)
+    pub encoder: Option<EncoderConfig>,
+    /// Echo pin, range and timeout (ultrasonic inputs)
+    pub ultrasonic: Option<UltrasonicConfig>,
+    /// Length and color order (LED strips)
+    pub led_strip: Option<LedStripConfig>,
+    /// Poll interval (DHT22 temperature/humidity inputs)
+    pub dht: Option<DhtConfig>,
+    /// Trigger, debounce time and interrupt use (digital inputs)
+    pub input: Option<InputConfig>,
 }
 
 // Helper function to convert u32 to string
@@ -96,143 +295,829 @@ fn u64_to_string<const N: usize>(n: u64, buf: &mut String<N>) {
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    // Initialize ESP-IDF
-    unsafe {
-        sys::esp_rom_printf(b"[FEAGI] Starting ESP32 Controller Firmware\r\n\0".as_ptr() as *const c_char);
-        sys::esp_rom_printf(b"[FEAGI] Transport: %s\r\n\0".as_ptr() as *const c_char, TRANSPORT_TYPE.as_ptr() as *const c_char);
+// Helper function to convert a 0.0-1.0 value to a string with 3 decimals
+fn unit_f32_to_string<const N: usize>(v: f32, buf: &mut String<N>) {
+    let clamped = if v < 0.0 { 0.0 } else if v > 1.0 { 1.0 } else { v };
+    let milli = (clamped * 1000.0 + 0.5) as u32;
+    let mut frac: String<8> = String::new();
+    u32_to_string(milli / 1000, buf);
+    let _ = buf.push('.');
+    u32_to_string(milli % 1000, &mut frac);
+    for _ in frac.len()..3 {
+        let _ = buf.push('0');
     }
+    let _ = buf.push_str(frac.as_str());
+}
+
+// Helper function to convert a signed value to a string with 1 decimal
+fn signed_f32_to_string<const N: usize>(v: f32, buf: &mut String<N>) {
+    let tenths = (if v < 0.0 { -v } else { v } * 10.0 + 0.5) as u32;
+    let mut part: String<16> = String::new();
+    buf.clear();
+    if v < 0.0 && tenths > 0 {
+        let _ = buf.push('-');
+    }
+    u32_to_string(tenths / 10, &mut part);
+    let _ = buf.push_str(part.as_str());
+    let _ = buf.push('.');
+    u32_to_string(tenths % 10, &mut part);
+    let _ = buf.push_str(part.as_str());
+}
+
+// Send one protocol line under the board's device ID, sealing it first when
+// link encryption is enabled
+//
+// Returns false if the transport failed to take the line.
+fn transmit(transport: &mut impl FeagiTransport, link: &mut Option<SecureLink>, device_id: &str, line: &[u8]) -> bool {
+    let mut stamped: Vec<u8, LINE_CAPACITY> = Vec::new();
+    let line = device_id::stamp(device_id, line, &mut stamped);
+    match link {
+        Some(ref mut l) => {
+            let mut sealed: Vec<u8, SEALED_LINE_CAPACITY> = Vec::new();
+            match l.seal_line(line, &mut sealed) {
+                Ok(()) => transport.send_frame(&sealed),
+                Err(_) => {
+                    warn!("Failed to seal outgoing line");
+                    true
+                }
+            }
+        }
+        None => transport.send_frame(line),
+    }
+}
+
+// Send a sensory line, compressed if the host asked for that and it's long
+// enough (see controller-core/src/compress.rs)
+fn transmit_sensory(
+    transport: &mut impl FeagiTransport,
+    link: &mut Option<SecureLink>,
+    device_id: &str,
+    compressing: bool,
+    line: &[u8],
+) -> bool {
+    let mut packed: Vec<u8, LINE_CAPACITY> = Vec::new();
+    let line = match COMPRESSION {
+        Some(config) if compressing => compress::shrink(line, config.min_bytes, &mut packed),
+        _ => line,
+    };
+    transmit(transport, link, device_id, line)
+}
+
+// Send the frames queued while the transport was down, oldest first
+//
+// Returns false if the transport failed; the unsent frames stay queued.
+fn flush_queue(
+    transport: &mut impl FeagiTransport,
+    link: &mut Option<SecureLink>,
+    device_id: &str,
+    compressing: bool,
+    queue: &mut FrameQueue<FRAME_QUEUE_CAPACITY>,
+) -> bool {
+    let mut line: Vec<u8, FRAME_CAPACITY> = Vec::new();
+    while !queue.is_empty() {
+        if !queue.front(&mut line) {
+            // Doesn't fit a frame buffer: can't be sent
+            queue.pop_front();
+            continue;
+        }
+        if !transmit_sensory(transport, link, device_id, compressing, &line) {
+            return false;
+        }
+        queue.sent_front();
+    }
+    true
+}
+
+// Tell FEAGI the transport came back: reconnects and restart attempts since
+// boot, and how many frames were queued (sent next) or dropped meanwhile
+fn send_reconnect_status(
+    transport: &mut impl FeagiTransport,
+    link: &mut Option<SecureLink>,
+    device_id: &str,
+    supervisor: &TransportSupervisor,
+    queue: &FrameQueue<FRAME_QUEUE_CAPACITY>,
+) {
+    let mut status: String<128> = String::new();
+    let mut num: String<16> = String::new();
+    for (i, (key, value)) in [
+        ("reconnected", supervisor.reconnects),
+        ("restarts", supervisor.restarts),
+        ("queued", queue.len()),
+        ("dropped", queue.dropped),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let _ = status.push_str(if i == 0 { "{\"" } else { ",\"" });
+        let _ = status.push_str(key);
+        let _ = status.push_str("\":");
+        u32_to_string(*value, &mut num);
+        let _ = status.push_str(num.as_str());
+    }
+    let _ = status.push_str("}\n");
+    transmit(transport, link, device_id, status.as_bytes());
+}
+
+// Bring up the serial transport's UART (UART0 on GPIO1/3, the USB serial of
+// most ESP32 boards, unless config.json `serial` says otherwise), framed as
+// `serial.framing` says
+fn open_serial() -> Option<SerialLink> {
+    SerialLink::open(&SERIAL, SERIAL_FRAMING)
+}
+
+// Bring up the WiFi station (`provisioned` credentials over config.json's)
+// and make the first attempt to reach FEAGI
+fn start_wifi(config: WifiConfig, provisioned: Option<&Credentials>) -> Option<(Wifi, Option<Transport>)> {
+    let (host, port) = provisioned.map_or((config.host, config.port), |c| (c.host, c.port));
+    info!("Configuring WiFi/{} transport (FEAGI at {}.{}.{}.{}:{})",
+        match config.protocol {
+            NetProtocol::Udp { .. } => "UDP",
+            NetProtocol::WebSocket { .. } => "WebSocket",
+            NetProtocol::Mqtt(_) => "MQTT",
+            NetProtocol::Zmq { .. } => "ZeroMQ",
+            NetProtocol::Tcp => "TCP",
+        },
+        host[0], host[1], host[2], host[3], port);
     
+    let wifi = Wifi::start(config, provisioned)?;
+    start_network_services();
+    // Not reachable yet: the supervisor keeps retrying from the burst loop
+    let transport = wifi.open_transport();
+    if transport.is_some() {
+        info!("WiFi transport ready");
+    } else {
+        warn!("FEAGI not reachable yet, retrying");
+    }
+    Some((wifi, transport))
+}
+
+// FEAGI endpoint stored with {"store_config":{...}} (or by the last
+// provisioning), over config.json's and the provisioned one
+fn use_stored_endpoint(settings: &Settings, config: &mut WifiConfig, mut provisioned: Option<&mut Credentials>) {
+    if settings.transport_host.source == Source::Stored {
+        config.host = settings.transport_host.value;
+        if let Some(credentials) = provisioned.as_deref_mut() {
+            credentials.host = settings.transport_host.value;
+        }
+    }
+    if settings.transport_port.source == Source::Stored {
+        config.port = settings.transport_port.value;
+        if let Some(credentials) = provisioned {
+            credentials.port = settings.transport_port.value;
+        }
+    }
+}
+
+// Services that only need the network interface: the field-debug endpoints
+// (status_server.rs) and firmware updates (ota.rs), up even while FEAGI
+// isn't, and SNTP (time_sync.rs)
+fn start_network_services() {
+    if let Some(ref config) = TIME_SYNC {
+        time_sync::start(config);
+    }
+    if let Some(port) = STATUS_SERVER_PORT {
+        if status_server::start(port) {
+            info!("Status server listening on port {}", port);
+        } else {
+            warn!("Failed to start status server");
+        }
+    }
+    if let Some(ref config) = OTA {
+        if ota::start(config) {
+            info!("Firmware updates accepted on port {}", config.port);
+        } else {
+            warn!("Failed to start the firmware update endpoint");
+        }
+    }
+}
+
+// The links a transport is reopened over; handed to the transport task for
+// the reopen when it runs (see transport_task.rs)
+pub struct Links {
+    /// WiFi station, kept for reconnecting the TCP transport
+    pub wifi: Option<Wifi>,
+    /// NimBLE host, kept for reopening the BLE transport
+    pub ble: Option<Ble>,
+    /// W5500 port, kept for reconnecting over Ethernet
+    pub ethernet: Option<Ethernet>,
+}
+
+// Why the transport is reopened
+pub enum Reopen {
+    /// The supervisor restarts a wedged transport
+    Restart,
+    /// The configured link is unreachable: fall back to serial
+    Failover,
+    /// WiFi credentials were provisioned over BLE: switch to WiFi
+    Wifi(WifiConfig, Credentials),
+}
+
+impl Links {
+    /// Open a transport as `how` says, the old one being dropped already;
+    /// slow over a network (association, DHCP, mDNS discovery, handshakes)
+    pub fn reopen(&mut self, how: &Reopen) -> Option<Transport> {
+        match how {
+            Reopen::Restart => match (&self.wifi, &self.ble, &self.ethernet) {
+                (Some(w), _, _) => w.open_transport(),
+                (None, Some(b), _) => b.open_transport(),
+                (None, None, Some(e)) => e.open_transport(),
+                (None, None, None) => open_serial().map(Transport::Serial),
+            },
+            Reopen::Failover => {
+                // Restarts reopen the fallback from now on
+                if let Some(w) = self.wifi.take() {
+                    w.stop();
+                }
+                if let Some(b) = self.ble.take() {
+                    b.stop();
+                }
+                if let Some(e) = self.ethernet.take() {
+                    e.stop();
+                }
+                // build.rs only accepts "serial" as a fallback
+                open_serial().map(Transport::Serial)
+            }
+            Reopen::Wifi(config, credentials) => {
+                if let Some(b) = self.ble.take() {
+                    b.stop();
+                }
+                match start_wifi(*config, Some(credentials)) {
+                    Some((station, first)) => {
+                        self.wifi = Some(station);
+                        first
+                    }
+                    None => unsafe {
+                        // The stored credentials take effect on the next boot
+                        error!("Failed to start WiFi, rebooting");
+                        sys::esp_restart();
+                    },
+                }
+            }
+        }
+    }
+}
+
+// Transport type as named in config.json: TCP over the Ethernet port is
+// "ethernet" rather than "wifi"
+fn transport_name(transport: &impl FeagiTransport) -> &'static str {
+    match transport.status().kind {
+        "wifi" if TRANSPORT_TYPE == "ethernet" => "ethernet",
+        kind => kind,
+    }
+}
+
+// Fresh link encryption state (new random salt), if a key is configured
+fn new_link() -> Option<SecureLink> {
+    LINK_PSK.map(|psk| {
+        // Hardware RNG; the host's salt keeps the session key fresh even if
+        // this repeats, as it can before the radio has ever run
+        let mut salt = [0u8; SALT_LEN];
+        unsafe { sys::esp_fill_random(salt.as_mut_ptr() as *mut core::ffi::c_void, SALT_LEN) };
+        SecureLink::new(&psk, salt, Role::Device)
+    })
+}
+
+// Announce the board (device ID, active transport, why it booted, whether the
+// watchdog reset it, and the link encryption salt) in plaintext
+fn send_hello(transport: &mut impl FeagiTransport, link: &Option<SecureLink>, settings: &Settings, wake: WakeReason) {
+    let device_id = &settings.device_id.value;
+    let mut hello: String<384> = String::from("{\"hello\":\"esp32\",\"id\":\"");
+    let _ = hello.push_str(device_id);
+    let _ = hello.push_str("\",\"modes\":[\"feagi\",\"raw\"],\"transport\":\"");
+    let _ = hello.push_str(transport_name(transport));
+    let _ = hello.push_str("\",\"wake\":\"");
+    let _ = hello.push_str(wake.as_str());
+    let _ = hello.push_str("\"");
+    if let Some(pin) = wake.pin(SLEEP_CONFIG.as_ref()) {
+        let mut num: String<16> = String::new();
+        u32_to_string(pin, &mut num);
+        let _ = hello.push_str(",\"wake_pin\":");
+        let _ = hello.push_str(num.as_str());
+    }
+    // The burst loop stalled before this boot (see watchdog.rs)
+    if watchdog::caused_last_reset() {
+        let _ = hello.push_str(",\"reset\":\"watchdog\"");
+    }
+    // Protocol versions spoken, oldest and newest (see version.rs)
+    let mut num: String<16> = String::new();
+    u32_to_string(MIN_PROTOCOL_VERSION, &mut num);
+    let _ = hello.push_str(",\"proto\":[");
+    let _ = hello.push_str(num.as_str());
+    u32_to_string(PROTOCOL_VERSION, &mut num);
+    let _ = hello.push_str(",");
+    let _ = hello.push_str(num.as_str());
+    let _ = hello.push_str("],\"wire\":\"");
+    let _ = hello.push_str(WIRE_FORMAT);
+    let _ = hello.push_str("\"");
+    // The host's heartbeats must come at least this often
+    if let Some(keepalive) = KEEPALIVE {
+        u32_to_string(keepalive.interval_ms, &mut num);
+        let _ = hello.push_str(",\"hb\":");
+        let _ = hello.push_str(num.as_str());
+    }
+    let _ = hello.push_str(",\"enc\":");
+    match link {
+        Some(ref l) => {
+            let _ = hello.push_str("\"chacha20poly1305\",\"salt\":\"");
+            for &c in secure_link::to_hex(&l.local_salt()).iter() {
+                let _ = hello.push(c as char);
+            }
+            let _ = hello.push_str("\",\"auth\":\"");
+            for &c in secure_link::to_hex(&l.hello_auth()).iter() {
+                let _ = hello.push(c as char);
+            }
+            let _ = hello.push_str("\"}\n");
+        }
+        None => {
+            let _ = hello.push_str("\"none\"}\n");
+        }
+    }
+    transport.send_frame(hello.as_bytes());
+    // With link encryption they follow once the host's salt arrived, sealed
+    if link.is_none() {
+        let mut stamped: Vec<u8, LINE_CAPACITY> = Vec::new();
+        transport.send_frame(device_id::stamp(device_id, capabilities::document(settings).as_bytes(), &mut stamped));
+        transport.send_frame(device_id::stamp(device_id, boot_report::line().as_bytes(), &mut stamped));
+        if let Some(offer) = OUTPUT_RESTORE.as_ref().and_then(output_restore::offer_line) {
+            transport.send_frame(device_id::stamp(device_id, offer.as_bytes(), &mut stamped));
+        }
+    }
+}
+
+// Tell the host, latch held outputs, arm the wake sources and deep sleep
+fn enter_deep_sleep<const N: usize>(
+    transport: Option<&mut Transport>,
+    link: &mut Option<SecureLink>,
+    device_id: &str,
+    outputs: &OutputBank<N>,
+    config: &SleepConfig,
+) -> ! {
+    info!("Entering deep sleep");
+    if let Some(t) = transport {
+        transmit(t, link, device_id, b"{\"sleeping\":1}\n");
+        t.flush(100);
+    }
+    outputs.hold_for_deep_sleep();
+    sleep::arm_wake_sources(config);
+    sleep::deep_sleep()
+}
+
+// Tell the host, stop the outputs and light sleep until a wake source fires,
+// then tell the host why the burst loop is back
+fn enter_light_sleep<const N: usize>(
+    mut transport: Option<&mut Transport>,
+    link: &mut Option<SecureLink>,
+    device_id: &str,
+    outputs: &mut OutputBank<N>,
+    config: &SleepConfig,
+) {
+    info!("Entering light sleep");
+    if let Some(t) = transport.as_deref_mut() {
+        transmit(t, link, device_id, b"{\"sleeping\":\"light\"}\n");
+        t.flush(100);
+    }
+    // LEDC and the stepper timers stop in light sleep, freezing whatever
+    // they were driving
+    outputs.safe_stop();
+    let wake = sleep::light_sleep(config);
+    if WATCHDOG.is_some() {
+        watchdog::feed();
+    }
+    info!("Woke from light sleep");
+    let mut line: String<48> = String::from("{\"awake\":\"");
+    let _ = line.push_str(wake.as_str());
+    let _ = line.push_str("\"");
+    if let Some(pin) = wake.pin(Some(config)) {
+        let mut num: String<16> = String::new();
+        u32_to_string(pin, &mut num);
+        let _ = line.push_str(",\"pin\":");
+        let _ = line.push_str(num.as_str());
+    }
+    let _ = line.push_str("}\n");
+    if let Some(t) = transport {
+        transmit(t, link, device_id, line.as_bytes());
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    // Initialize ESP-IDF
     sys::link_patches();
+    logger::init(&LOG_CONFIG);
+    fault::init();
+    
+    info!("Starting ESP32 Controller Firmware");
+    info!("Transport: {}", TRANSPORT_TYPE);
     
-    // Initialize logging
-    unsafe {
-        use esp_idf_svc::sys::{esp_log_level_set, esp_log_level_t_ESP_LOG_INFO};
-        esp_log_level_set(
-            CStr::from_bytes_with_nul_unchecked(b"*\0").as_ptr(),
-            esp_log_level_t_ESP_LOG_INFO,
-        );
+    // Deep-sleep wake (EXT0/EXT1/touch/timer) or a regular boot
+    let wake_reason = WakeReason::read();
+    if watchdog::caused_last_reset() {
+        warn!("Reset by the watchdog, the burst loop had stalled");
+    }
+    if let Some(ref config) = SLEEP_CONFIG {
+        sleep::release_wake_pins(config);
     }
     
     // Get peripherals
     let peripherals = Peripherals::take()
         .map_err(|_| anyhow::anyhow!("Failed to take peripherals"))?;
     
-    // Configure status LED (GPIO2 is commonly the on-board LED)
-    let mut led = PinDriver::output(peripherals.pins.gpio2)
+    // Configure status LED (GPIO2 is commonly the on-board LED); its blink
+    // pattern shows which direction traffic is flowing, see heartbeat.rs
+    let _led = PinDriver::output(peripherals.pins.gpio2)
         .map_err(|e| anyhow::anyhow!("Failed to configure LED: {:?}", e))?;
+    if !heartbeat::start(heartbeat::STATUS_LED_GPIO) {
+        warn!("Failed to start LED heartbeat");
+    }
+    
+    // Effective configuration: build-time values plus host overrides
+    // (e.g. the burst-rate policy, renegotiable by the host)
+    let mut settings = Settings::from_build();
+    // A device ID the host gave the board outlives reboots
+    if let Some(id) = device_id::load() {
+        settings.device_id = Tracked::stored(id);
+    }
+    // So does configuration stored with {"store_config":{...}}
+    if let Some(stored) = stored_config::load() {
+        let skipped = stored_config::apply(&mut settings, &stored);
+        if skipped > 0 {
+            warn!("Skipped {} stored configuration values that no longer apply", skipped);
+        }
+    }
+    logger::set_levels(settings.log_level.value, settings.log_forward.value);
+    // GPIO_CONFIG with the stored modes and cortical mappings
+    let gpio_config = stored_config::patch_gpio_config(&settings);
+    
+    // Apply output boot states before any transport is up, so actuators
+    // don't jerk while the link is being established
+    let mut outputs: OutputBank<MAX_OUTPUT_CHANNELS> = OutputBank::from_config(gpio_config);
+    // Levels the pads are forced to on a stall or panic (see failsafe.rs)
+    outputs.safe_levels(failsafe::set_safe_level);
+    // Outputs commanded before a software restart, offered to FEAGI once
+    // connected (see output_restore.rs)
+    if output_restore::init(OUTPUT_RESTORE.as_ref()) {
+        info!("Outputs from before the restart kept, waiting for FEAGI to confirm them");
+    }
     
     // Initialize transport based on configuration
-    let mut uart: Option<UartDriver<'static>> = None;
+    let mut transport: Option<Transport> = None;
+    // And the links it's reopened over
+    let mut links = Links { wifi: None, ble: None, ethernet: None };
+    
+    if TRANSPORT_TYPE != "bluetooth" {
+        // Give the BT controller's reserved DRAM back to the heap
+        unsafe {
+            sys::esp_bt_controller_mem_release(sys::esp_bt_mode_t_ESP_BT_MODE_BTDM);
+        }
+    }
     
     match TRANSPORT_TYPE {
         "serial" => {
-            unsafe {
-                sys::esp_rom_printf(b"[FEAGI] Configuring Serial/UART transport (115200 baud)\r\n\0".as_ptr() as *const c_char);
-            }
-            
-            // Initialize UART0 for serial communication (USB serial on most ESP32 boards)
-            // TX=GPIO1, RX=GPIO3 for UART0 (default USB serial)
-            let uart_config = UartConfig::default()
-                .baudrate(Hertz(115200))
-                .data_bits(esp_idf_svc::hal::uart::config::DataBits::DataBits8)
-                .parity_none()
-                .stop_bits(esp_idf_svc::hal::uart::config::StopBits::STOP1)
-                .flow_control_none();
+            info!("Configuring Serial/UART transport (UART{}, {} baud, {} framing{})",
+                SERIAL.port,
+                SERIAL.baud_rate,
+                match SERIAL_FRAMING {
+                    SerialFraming::Raw => "raw",
+                    SerialFraming::Cobs => "COBS",
+                },
+                if SERIAL.rts_pin.is_some() || SERIAL.cts_pin.is_some() { ", RTS/CTS" } else { "" });
             
-            match UartDriver::new(
-                peripherals.uart0,
-                peripherals.pins.gpio1,
-                peripherals.pins.gpio3,
-                Option::<AnyIOPin>::None,
-                Option::<AnyIOPin>::None,
-                &uart_config,
-            ) {
-                Ok(driver) => {
-                    uart = Some(driver);
-                    unsafe {
-                        sys::esp_rom_printf(b"[FEAGI] Serial/UART transport ready\r\n\0".as_ptr() as *const c_char);
-                    }
-                }
-                Err(_e) => {
-                    unsafe {
-                        sys::esp_rom_printf(b"[FEAGI] Warning: Failed to initialize UART, continuing with console only\r\n\0".as_ptr() as *const c_char);
-                    }
+            transport = open_serial().map(Transport::Serial);
+            if transport.is_some() {
+                info!("Serial/UART transport ready");
+            } else {
+                warn!("Failed to initialize UART, continuing with console only");
+            }
+        }
+        "wifi" | "udp" | "websocket" | "mqtt" | "zmq" => {
+            let mut config = WIFI_CONFIG.ok_or_else(|| anyhow::anyhow!("WiFi transport needs transport.config"))?;
+            // Credentials from an earlier provisioning, only honoured while it's enabled
+            let mut provisioned = config.provisioning.and_then(|_| Credentials::load());
+            use_stored_endpoint(&settings, &mut config, provisioned.as_mut());
+            if let Some(ref portal) = config.provisioning {
+                if (provisioned.is_none() && config.ssid.is_empty()) || portal.button_pin.map_or(false, provisioning::button_held) {
+                    info!("Starting WiFi provisioning portal");
+                    // Reboots once credentials are submitted
+                    provisioning::run_portal(portal);
+                    return Err(anyhow::anyhow!("Failed to start the provisioning portal"));
                 }
             }
+            let (station, first) = start_wifi(config, provisioned.as_ref()).ok_or_else(|| anyhow::anyhow!("Failed to start WiFi"))?;
+            links.wifi = Some(station);
+            transport = first;
         }
-        "wifi" => {
-            unsafe {
-                sys::esp_rom_printf(b"[FEAGI] WiFi transport not yet implemented\r\n\0".as_ptr() as *const c_char);
+        "ethernet" => {
+            let mut config = ETHERNET_CONFIG.ok_or_else(|| anyhow::anyhow!("Ethernet transport needs transport.config"))?;
+            use_stored_endpoint(&settings, &mut config.network, None);
+            let host = config.network.host;
+            info!("Configuring Ethernet transport (W5500, FEAGI at {}.{}.{}.{}:{})",
+                host[0], host[1], host[2], host[3], config.network.port);
+            let port = Ethernet::start(config).ok_or_else(|| anyhow::anyhow!("Failed to start Ethernet (check the W5500 wiring)"))?;
+            start_network_services();
+            // No link yet: the supervisor keeps retrying from the burst loop
+            transport = port.open_transport();
+            links.ethernet = Some(port);
+            if transport.is_some() {
+                info!("Ethernet transport ready");
+            } else {
+                warn!("FEAGI not reachable yet, retrying");
             }
-            return Err(anyhow::anyhow!("WiFi transport not yet implemented"));
         }
         "bluetooth" => {
-            unsafe {
-                sys::esp_rom_printf(b"[FEAGI] Bluetooth transport not yet implemented\r\n\0".as_ptr() as *const c_char);
+            let config = BLE_CONFIG.ok_or_else(|| anyhow::anyhow!("Bluetooth transport needs transport.config"))?;
+            // Provisioned over BLE on an earlier boot: go straight to WiFi
+            let provisioned = match WIFI_CONFIG {
+                Some(_) if !config.button_pin.map_or(false, provisioning::button_held) => Credentials::load(),
+                _ => None,
+            };
+            if let (Some(mut wifi_config), Some(mut credentials)) = (WIFI_CONFIG, provisioned) {
+                use_stored_endpoint(&settings, &mut wifi_config, Some(&mut credentials));
+                unsafe {
+                    sys::esp_bt_controller_mem_release(sys::esp_bt_mode_t_ESP_BT_MODE_BTDM);
+                }
+                let (station, first) = start_wifi(wifi_config, Some(&credentials)).ok_or_else(|| anyhow::anyhow!("Failed to start WiFi"))?;
+                links.wifi = Some(station);
+                transport = first;
+            } else {
+                info!("Configuring BLE transport (Nordic UART Service)");
+                
+                let host = Ble::start(config).ok_or_else(|| anyhow::anyhow!("Failed to start BLE"))?;
+                transport = host.open_transport();
+                links.ble = Some(host);
+                info!("BLE transport ready, advertising");
             }
-            return Err(anyhow::anyhow!("Bluetooth transport not yet implemented"));
         }
         _ => {
             return Err(anyhow::anyhow!("Unknown transport type: {}", TRANSPORT_TYPE));
         }
     }
     
-    unsafe {
-        sys::esp_rom_printf(b"[FEAGI] Configuring GPIO pins...\r\n\0".as_ptr() as *const c_char);
+    // Optional authenticated encryption of the link (pre-shared key)
+    let mut link: Option<SecureLink> = new_link();
+    
+    // Announce the board (and the link encryption salt) in plaintext
+    if let Some(ref mut u) = transport {
+        send_hello(u, &link, &settings, wake_reason);
+    }
+    if link.is_some() {
+        info!("Link encryption enabled, waiting for host salt");
     }
     
+    info!("Configuring GPIO pins...");
+    
     // Collect GPIO pin configurations
-    let mut digital_input_configs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut digital_output_configs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut analog_input_configs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut pwm_output_configs: Vec<(u32, &'static str), 32> = Vec::new();
+    let mut digital_input_configs: Vec<(u32, &'static str, Pull, InputFilter), MAX_SENSORY_CHANNELS> = Vec::new();
+    let mut digital_output_configs: Vec<(u32, &'static str), MAX_OUTPUT_CHANNELS> = Vec::new();
+    let mut pwm_output_configs: Vec<(u32, &'static str), MAX_OUTPUT_CHANNELS> = Vec::new();
     
-    for gpio_config in GPIO_CONFIG {
+    for gpio_config in gpio_config {
         match gpio_config.mode {
             GpioMode::DigitalInput => {
-                let _ = digital_input_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
-                unsafe {
-                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: Digital Input -> %s\r\n\0".as_ptr() as *const c_char,
-                        gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
+                // build.rs gives every digital input its filter settings
+                let Some(input) = gpio_config.input else {
+                    continue;
+                };
+                // Interrupt-driven inputs are served by the EdgeBank instead
+                if input.interrupt {
+                    info!("GPIO {}: Digital Input (interrupt) -> {} (debounce {} ms)",
+                        gpio_config.pin, gpio_config.cortical_mapping, input.debounce_ms);
+                    continue;
                 }
+                let _ = digital_input_configs.push((gpio_config.pin, gpio_config.cortical_mapping, gpio_config.pull, InputFilter::new(&input)));
+                info!("GPIO {}: Digital Input -> {} (debounce {} ms)",
+                    gpio_config.pin, gpio_config.cortical_mapping, input.debounce_ms);
             }
             GpioMode::DigitalOutput => {
                 let _ = digital_output_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
-                unsafe {
-                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: Digital Output -> %s\r\n\0".as_ptr() as *const c_char,
-                        gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
-                }
+                info!("GPIO {}: Digital Output -> {}", gpio_config.pin, gpio_config.cortical_mapping);
             }
             GpioMode::AnalogInput => {
-                let _ = analog_input_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
-                unsafe {
-                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: Analog Input -> %s (ADC support coming soon)\r\n\0".as_ptr() as *const c_char,
-                        gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
-                }
+                let points = gpio_config.analog.map_or(0, |analog| analog.calibration.len());
+                info!("GPIO {}: Analog Input -> {} ({} calibration points)", gpio_config.pin, gpio_config.cortical_mapping, points);
             }
             GpioMode::PwmOutput => {
                 let _ = pwm_output_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
-                unsafe {
-                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: PWM Output -> %s (PWM support coming soon)\r\n\0".as_ptr() as *const c_char,
-                        gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
+                // build.rs gives every PWM output its LEDC settings
+                if let Some(pwm) = gpio_config.pwm {
+                    info!("GPIO {}: PWM Output -> {} ({} Hz, {}-bit)",
+                        gpio_config.pin, gpio_config.cortical_mapping, pwm.frequency_hz, pwm.resolution_bits);
+                }
+            }
+            GpioMode::ServoOutput => {
+                let _ = pwm_output_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                if let Some(servo) = gpio_config.servo {
+                    info!("GPIO {}: Servo Output -> {} ({}-{} deg, {}-{} us)",
+                        gpio_config.pin, gpio_config.cortical_mapping, servo.min_angle_deg, servo.max_angle_deg, servo.min_pulse_us, servo.max_pulse_us);
+                }
+            }
+            GpioMode::DcMotor => {
+                let _ = pwm_output_configs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                if let (Some(motor), Some(pwm)) = (gpio_config.dc_motor, gpio_config.pwm) {
+                    let pin_b = match motor.driver {
+                        MotorDriver::TwoPwm { pin_b, .. } => pin_b,
+                        MotorDriver::Direction { in1, .. } => in1,
+                    };
+                    info!("GPIO {}/{}: DC Motor -> {} ({} Hz)", gpio_config.pin, pin_b, gpio_config.cortical_mapping, pwm.frequency_hz);
+                }
+            }
+            GpioMode::StepperOutput => {
+                if let Some(stepper) = gpio_config.stepper {
+                    info!("GPIO {}/{}: Stepper Output -> {} ({} steps/s, {} steps/s^2)",
+                        gpio_config.pin, stepper.dir_pin, gpio_config.cortical_mapping, stepper.max_speed_sps, stepper.accel_sps2);
+                }
+            }
+            GpioMode::TouchInput => {
+                info!("GPIO {}: Touch Input -> {}", gpio_config.pin, gpio_config.cortical_mapping);
+            }
+            GpioMode::EncoderInput => {
+                if let Some(encoder) = gpio_config.encoder {
+                    info!("GPIO {}/{}: Encoder Input -> {} ({} counts/rev)",
+                        gpio_config.pin, encoder.pin_b, gpio_config.cortical_mapping, encoder.counts_per_rev);
+                }
+            }
+            GpioMode::UltrasonicInput => {
+                if let Some(ultrasonic) = gpio_config.ultrasonic {
+                    info!("GPIO {}/{}: Ultrasonic Input -> {} ({} cm)",
+                        gpio_config.pin, ultrasonic.echo_pin, gpio_config.cortical_mapping, ultrasonic.max_range_cm);
+                }
+            }
+            GpioMode::LedStrip => {
+                if let Some(led_strip) = gpio_config.led_strip {
+                    info!("GPIO {}: LED Strip <- {} ({} LEDs)", gpio_config.pin, gpio_config.cortical_mapping, led_strip.length);
+                }
+            }
+            GpioMode::DhtInput => {
+                if let Some(dht) = gpio_config.dht {
+                    info!("GPIO {}: DHT22 Input -> {} (every {} ms)", gpio_config.pin, gpio_config.cortical_mapping, dht.interval_ms);
                 }
             }
             GpioMode::Disabled => {}
         }
     }
     
-    unsafe {
-        sys::esp_rom_printf(b"[FEAGI] GPIO configuration complete\r\n\0".as_ptr() as *const c_char);
-        sys::esp_rom_printf(b"[FEAGI] Initialization complete\r\n\0".as_ptr() as *const c_char);
-        sys::esp_rom_printf(b"[FEAGI] Burst frequency: %d Hz\r\n\0".as_ptr() as *const c_char, BURST_FREQUENCY_HZ as i32);
+    info!("GPIO configuration complete");
+
+    let mut analog: AnalogBank<MAX_SENSORY_CHANNELS> = AnalogBank::from_config(gpio_config);
+    let mut feedback: FeedbackBank<MAX_FEEDBACK_CHANNELS> = FeedbackBank::from_config(gpio_config);
+    let mut touch: TouchBank<MAX_SENSORY_CHANNELS> = TouchBank::from_config(gpio_config);
+    let mut encoders: EncoderBank<MAX_SENSORY_CHANNELS> = EncoderBank::from_config(gpio_config);
+    let mut ultrasonic = UltrasonicBank::from_config(gpio_config);
+    let mut dht = DhtBank::from_config(gpio_config);
+    let mut edges = EdgeBank::from_config(gpio_config);
+    // Pose from the wheel encoders, streamed as "od"
+    let mut odometry = ODOMETRY.and_then(|config| Odometry::new(&config, gpio_config, &encoders));
+    if ODOMETRY.is_some() && odometry.is_none() {
+        warn!("Odometry disabled, wheel encoders not counting");
+    }
+    // Sensors built into the chip (no wiring)
+    let mut hall = HallSensor::new(&ONBOARD_SENSORS);
+    if hall.is_some() {
+        info!("Onboard hall sensor enabled");
+    }
+    // Vision input, sent as "vis" lines (see camera.rs)
+    let mut camera = CAMERA.and_then(|config| Camera::new(&config));
+    if let Some(config) = CAMERA {
+        if camera.is_some() {
+            info!("Camera: {}x{} pixels every {} bursts", config.width, config.height, config.decimation);
+        } else {
+            fault!(DeviceInit, "Camera not answering, vision input disabled");
+        }
+    }
+    // Sound level and frequency bands from an I2S microphone
+    let mut microphone = MICROPHONE.and_then(|config| Microphone::new(&config));
+    if let Some(config) = MICROPHONE {
+        if microphone.is_some() {
+            info!("Microphone: {} Hz, {} bands", config.sample_rate_hz, config.bands);
+        } else {
+            fault!(DeviceInit, "I2S microphone setup failed, auditory input disabled");
+        }
+    }
+    // Tones played on motor commands (started with the outputs)
+    if let Some(config) = AUDIO_OUTPUT {
+        if outputs.has_audio() {
+            info!("Audio output: {} tones at {} Hz", config.tones.len(), config.sample_rate_hz);
+        } else {
+            fault!(DeviceInit, "Audio output setup failed, tones disabled");
+        }
+    }
+    if OUTPUT_ECHO_ENABLED {
+        info!("Output echo enabled (applied values reported as \"ao\")");
+    }
+    
+    // Barrier-synchronized actuation (multi-board robots)
+    let mut barrier: Barrier<MAX_MOTOR_NEURONS> = Barrier::new(BARRIER_TIMEOUT_MS);
+    if BARRIER_ENABLED {
+        info!("Barrier sync enabled (timeout {} ms)", BARRIER_TIMEOUT_MS);
+    }
+    
+    // I2C devices share one per-burst budget, polled round-robin
+    let mut i2c_bus: Option<I2cBus> = None;
+    let mut i2c_scheduler = I2cScheduler::new(I2C_BUS.map(|b| b.burst_budget_us).unwrap_or(0));
+    if let Some(bus_config) = I2C_BUS {
+        i2c_bus = I2cBus::new(&bus_config);
+        if i2c_bus.is_none() {
+            fault!(DeviceInit, "Failed to initialize I2C bus");
+        }
+        for device in I2C_DEVICES {
+            if let Some(ref mut bus) = i2c_bus {
+                if !device.init.iter().all(|&(register, data)| bus.write_registers(device.address, register, data)) {
+                    fault!(DeviceInit, "Failed to set up I2C device 0x{:02x}", device.address);
+                }
+            }
+            if i2c_scheduler.register(*device) {
+                info!("I2C 0x{:02x}: {} Hz, budget {} us", device.address, device.rate_hz, device.budget_us);
+            }
+        }
+    }
+    // Virtual pins behind I2C GPIO expanders
+    let mut expanders = match i2c_bus.as_mut() {
+        Some(bus) if !I2C_EXPANDERS.is_empty() => Some(ExpanderBank::init(bus, gpio_config)),
+        _ => None,
+    };
+    // The IMU is one more polled device, decoded into six channels
+    // (paired with its scheduler slot)
+    let mut imu: Option<(Imu, usize)> = None;
+    if let (Some(config), Some(bus)) = (IMU_CONFIG, i2c_bus.as_mut()) {
+        match Imu::init(bus, &config) {
+            Some(device) if i2c_scheduler.register(config.device()) => {
+                imu = Some((device, i2c_scheduler.devices().len() - 1));
+                info!("IMU 0x{:02x}: +/-{} g, +/-{} dps, {} Hz",
+                    config.address, config.accel_range_g, config.gyro_range_dps, config.rate_hz);
+            }
+            _ => fault!(DeviceInit, "No IMU answering at 0x{:02x}", config.address),
+        }
+    }
+    // Battery voltage/current, and the low-battery safe-stop
+    let mut power = POWER_MONITOR.and_then(|config| PowerMonitor::new(&config, i2c_bus.as_mut()));
+    if let Some(config) = POWER_MONITOR {
+        if power.is_some() {
+            info!("Power monitor: {}-{} mV, low battery at {} mV",
+                (config.min_voltage * 1000.0) as i32, (config.max_voltage * 1000.0) as i32, config.low_voltage.map_or(0, |v| (v * 1000.0) as i32));
+        } else {
+            fault!(DeviceInit, "Power monitor not responding, battery unmonitored");
+        }
     }
     
+    let mut shaper: MotorShaper<MAX_MOTOR_NEURONS> = MotorShaper::new();
+    // Newest acknowledged motor sequence number (reliable mode), reset
+    // with every hello
+    let mut motor_acks = MotorAcks::new();
+    // Protocol version agreed with the host, None while refused (see
+    // version.rs); also reset with every hello
+    let mut protocol_version = Some(PROTOCOL_VERSION);
+    // Whether the host asked for compressed sensory lines (see
+    // controller-core/src/compress.rs); off again with every hello
+    let mut compressing = false;
+    let mut sensory_window: SensoryWindow<MAX_SENSORY_CHANNELS> = SensoryWindow::new();
+    let mut delta_filter: DeltaFilter<MAX_SENSORY_CHANNELS> = DeltaFilter::new();
+    
+    // Sleep after this long without motor commands (event-driven robots)
+    let mut last_motor_us = unsafe { sys::esp_timer_get_time() };
+    
+    // Raw GPIO pass-through, used once the host selects {"mode":"raw"}
+    let mut raw_io = RawIo::new();
+    // Whether the host may still pick the session mode: from every hello
+    // until the first motor command or raw telegram
+    let mut mode_open = true;
+    
+    info!("Initialization complete");
+    info!("Burst frequency: {} Hz", settings.burst_frequency.value);
+    
     // Main loop: I/O communication with FEAGI
-    let sampling_period_ms = 1000 / BURST_FREQUENCY_HZ;
+    let burst_frequency = settings.burst_frequency.value.max(1);
+    let burst_period_us = 1_000_000 / burst_frequency as u64;
+    
+    // Board health telemetry (chip temperature, burst-loop load); the chip
+    // temperature may also be mapped as a sensory channel (onboard_sensors)
+    let chip_temp_neuron = ONBOARD_SENSORS.temperature_mapping.and_then(cortical::neuron_id);
+    let mut chip_temp = if TELEMETRY_BOARD_HEALTH || chip_temp_neuron.is_some() { ChipTemp::new() } else { None };
+    if chip_temp_neuron.is_some() && chip_temp.is_none() {
+        warn!("No temperature sensor on this chip");
+    }
+    let mut load_meter = LoadMeter::new(burst_period_us as i64);
+    // Start and working time of every burst (telemetry.profile)
+    let mut profiler = Profiler::new();
+    // Burst jitter, heap and receive-path counters (telemetry.metrics)
+    let mut metrics = Metrics::new(METRICS.as_ref(), burst_period_us as i64, unsafe { sys::esp_timer_get_time() });
+    // WiFi link quality as a sensory channel ("wl")
+    let mut link_monitor = LINK_TELEMETRY.as_ref().and_then(LinkMonitor::new);
     let mut frame_number: u64 = 0;
-    let mut rx_buffer: [u8; 512] = [0; 512];
-    let mut rx_accumulator: Vec<u8, 512> = Vec::new();
+    let mut rx_buffer = [0u8; RX_READ_CAPACITY];
+    let mut rx_accumulator: Vec<u8, RX_LINE_CAPACITY> = Vec::new();
+    
+    // Restarts a wedged transport without rebooting the board
+    let mut supervisor = TransportSupervisor::new(TRANSPORT_SUPERVISION, !TRANSPORT_FALLBACK.is_empty());
+    // Heartbeats both ways; a silent host is treated as gone (transport.keepalive)
+    let mut keepalive = KEEPALIVE.map(|config| Keepalive::new(config, unsafe { sys::esp_timer_get_time() }));
+    // Fallback transports used so far (transport.fallback)
+    let mut fallbacks_used = 0;
+    let sensory_queue = SENSORY_QUEUE.take();
+    let settings_reply = SETTINGS_REPLY.take();
+    
+    // Counters and safe-stop state shown by the status server
+    let mut frames_sent: u32 = 0;
+    let mut motor_commands: u32 = 0;
+    // Safe-stop (POST /stop): outputs low, motor commands ignored until resumed
+    let mut safe_stopped = false;
     
     // Helper function to get pin from peripherals by number
     // This is a simplified version - in production, use a pin mapping function
@@ -264,198 +1149,1024 @@ fn main() -> anyhow::Result<()> {
         };
     }
     
+    // From here on a stalled burst loop safe-stops the outputs and resets
+    // the board (see watchdog.rs)
+    if let Some(ref config) = WATCHDOG {
+        match watchdog::arm(config) {
+            Some(timeout_ms) => info!("Watchdog armed ({} ms)", timeout_ms),
+            None => warn!("Failed to arm the watchdog"),
+        }
+    }
+    
+    // Transport I/O moves to its own task on the other core (see transport_task.rs)
+    if let Some(ref config) = TRANSPORT_TASK {
+        if transport_task::start(config) {
+            info!("Transport task started");
+        } else {
+            warn!("Failed to start the transport task, driving the transport from the burst loop");
+        }
+    }
+    // None while the transport task is reopening the transport over them
+    let mut links = Some(links);
+    
+    // Bursts start on a hardware timer, whatever their processing took
+    // (see burst_timer.rs)
+    let burst_timer = BurstTimer::start(burst_period_us);
+    if burst_timer.is_none() {
+        warn!("Failed to start the burst timer, pacing bursts with delays");
+    }
+    
     loop {
-        // Blink LED to show activity
-        led.set_high().ok();
-        FreeRtos::delay_ms(10);
-        led.set_low().ok();
+        // A transport opened since the last burst goes to the transport task
+        transport = transport.take().map(transport_task::offload);
+        
+        // Runtime metrics, sent every telemetry.metrics.interval_ms
+        if metrics.burst(unsafe { sys::esp_timer_get_time() }) && METRICS.is_some() {
+            if let Some(ref mut u) = transport {
+                transmit(u, &mut link, &settings.device_id.value, metrics.line(frames_sent).as_bytes());
+            }
+        }
+        
+        // Burst timing, sent every PROFILE_BATCH bursts (telemetry.profile)
+        if TELEMETRY_PROFILE && profiler.due() {
+            let line = profiler.line();
+            if let Some(ref mut u) = transport {
+                transmit(u, &mut link, &settings.device_id.value, line.as_bytes());
+            }
+        }
+        
+        if WATCHDOG.is_some() {
+            watchdog::feed();
+        }
+        
+        let feagi_mode = settings.mode.value == SessionMode::Feagi;
         
         // 1. Read sensor inputs (GPIO)
-        let mut sensory_data: Vec<(u32, f32), 64> = Vec::new();  // (neuron_id, potential)
+        let mut sensory_data: Vec<(u32, f32), MAX_SENSORY_CHANNELS> = Vec::new();  // (neuron_id, potential)
         
-        // Read digital inputs dynamically
-        for (pin_num, mapping) in digital_input_configs.iter() {
-            if let Some(pin) = get_pin!(*pin_num, peripherals.pins) {
+        // Expander input levels, for the digital inputs behind them
+        if let (Some(bank), Some(bus)) = (expanders.as_mut(), i2c_bus.as_mut()) {
+            if feagi_mode {
+                bank.read_inputs(bus);
+            }
+        }
+        
+        // Read digital inputs dynamically (raw mode owns the pins instead),
+        // debounced and reported per the pin's trigger (see debounce.rs)
+        let now_us = unsafe { sys::esp_timer_get_time() };
+        for (pin_num, mapping, pull, filter) in digital_input_configs.iter_mut().filter(|_| feagi_mode) {
+            let level = if expander::is_virtual(*pin_num) {
+                expander::read_pin(*pin_num)
+            } else if let Some(pin) = get_pin!(*pin_num, peripherals.pins) {
                 // Create temporary driver to read pin state
-                if let Ok(mut driver) = PinDriver::input(pin) {
-                    if let Ok(level) = driver.get_level() {
-                        let potential = if level == esp_idf_svc::hal::gpio::Level::High { 1.0 } else { 0.0 };
-                        if let Some(neuron_id) = parse_neuron_id(mapping) {
-                            let _ = sensory_data.push((neuron_id, potential));
-                        }
+                // (it goes out of scope right away, so the pin is released)
+                PinDriver::input(pin).ok().and_then(|driver| {
+                    // The driver resets the pin, pull resistors included
+                    pad::set_pull(*pin_num, *pull);
+                    driver.get_level().ok()
+                }).map(|level| level == esp_idf_svc::hal::gpio::Level::High)
+            } else {
+                None
+            };
+            if let Some(high) = level {
+                status_server::set_level(*pin_num, high);
+                let potential = filter.update(high, now_us);
+                if let Some(neuron_id) = cortical::neuron_id(mapping) {
+                    let _ = sensory_data.push((neuron_id, potential));
+                }
+            }
+        }
+        
+        // Interrupt-driven digital inputs (1.0 if an edge arrived since the last burst)
+        if feagi_mode {
+            for (pin, neuron_id, value, level) in edges.read_all() {
+                status_server::set_level(pin, level);
+                let _ = sensory_data.push((neuron_id, value));
+            }
+        }
+        
+        // Capacitive touch pads (state or level, per pin)
+        if feagi_mode {
+            for (pin, neuron_id, value, touched) in touch.read_all() {
+                status_server::set_level(pin, touched);
+                let _ = sensory_data.push((neuron_id, value));
+            }
+        }
+        
+        // Rotary encoders (position within a revolution, then velocity)
+        if feagi_mode {
+            for (neuron_id, position, velocity) in encoders.read_all() {
+                let _ = sensory_data.push((neuron_id, position));
+                let _ = sensory_data.push((neuron_id + 1, velocity));
+            }
+        }
+        // The pose keeps integrating in raw mode too
+        if let Some(ref mut odometry) = odometry {
+            odometry.update(&encoders);
+        }
+        
+        // Ultrasonic distance (echo of the previous burst's ping)
+        if feagi_mode {
+            for reading in ultrasonic.read_all() {
+                let _ = sensory_data.push(reading);
+            }
+        }
+        
+        // Temperature and humidity (read every few seconds, repeated in between)
+        if feagi_mode {
+            for reading in dht.read_all() {
+                let _ = sensory_data.push(reading);
+            }
+        }
+        
+        // Onboard hall sensor and die temperature
+        if feagi_mode {
+            if let Some(reading) = hall.as_mut().and_then(|h| h.read()) {
+                let _ = sensory_data.push(reading);
+            }
+            if let Some(neuron_id) = chip_temp_neuron {
+                if let Some(celsius) = chip_temp.as_mut().and_then(|t| t.read_celsius()) {
+                    let _ = sensory_data.push((neuron_id, onboard::temperature_value(celsius)));
+                }
+            }
+        }
+        
+        // Battery voltage and current (measured every 100 ms, repeated in between)
+        if let Some(ref mut monitor) = power {
+            let now_ms = (unsafe { sys::esp_timer_get_time() } / 1000) as u32;
+            // Measured in raw mode too, for the low-battery safe-stop
+            let readings = monitor.read(i2c_bus.as_mut(), now_ms);
+            if feagi_mode {
+                for reading in readings {
+                    let _ = sensory_data.push(reading);
+                }
+            }
+        }
+        
+        // Sound level and bands since the previous burst
+        if feagi_mode {
+            if let Some(ref mut mic) = microphone {
+                for reading in mic.read() {
+                    let _ = sensory_data.push(reading);
+                }
+            }
+        }
+        
+        // Analog inputs (scaled and calibrated per pin)
+        if feagi_mode {
+            for reading in analog.read_all() {
+                let _ = sensory_data.push(reading);
+            }
+        }
+        
+        // Measured servo positions from analog feedback pins
+        let feedback_data = feedback.read_all();
+        
+        // Wall-clock time of this sample (Unix ms), once SNTP has synced
+        let sample_ms = time_sync::now_unix_ms();
+        
+        // Poll the I2C devices that are due, within this burst's budget
+        if let Some(ref mut bus) = i2c_bus {
+            let newly_slow = i2c_scheduler.poll_burst(|device, buf| {
+                bus.read_registers(device.address, device.register, buf)
+            });
+            if !newly_slow.is_empty() {
+                // Report devices that keep blowing their budget: {"i2c_slow":[[addr,us,budget],...]}
+                let mut report: String<256> = String::from("{\"i2c_slow\":[");
+                for (i, &index) in newly_slow.iter().enumerate() {
+                    let slot = &i2c_scheduler.devices()[index];
+                    if i > 0 {
+                        let _ = report.push_str(",");
                     }
-                    // Driver goes out of scope here, pin is released
+                    let mut num: String<16> = String::new();
+                    let _ = report.push_str("[");
+                    u32_to_string(slot.config.address as u32, &mut num);
+                    let _ = report.push_str(num.as_str());
+                    let _ = report.push_str(",");
+                    u32_to_string(slot.last_us, &mut num);
+                    let _ = report.push_str(num.as_str());
+                    let _ = report.push_str(",");
+                    u32_to_string(slot.config.budget_us, &mut num);
+                    let _ = report.push_str(num.as_str());
+                    let _ = report.push_str("]");
+                }
+                let _ = report.push_str("]}\n");
+                if let Some(ref mut u) = transport {
+                    transmit(u, &mut link, &settings.device_id.value, report.as_bytes());
                 }
             }
         }
         
-        // TODO: Read analog inputs and add to sensory_data (ADC implementation)
+        // Mapped I2C devices (the last reading is repeated between polls)
+        if feagi_mode {
+            for slot in i2c_scheduler.devices().iter().filter(|slot| slot.valid) {
+                let Some(channels) = slot.config.channels else {
+                    continue;
+                };
+                let Some(first_neuron) = cortical::neuron_id(channels.cortical_mapping) else {
+                    continue;
+                };
+                for (i, value) in channels.values(&slot.data[..slot.config.len as usize]).enumerate() {
+                    let _ = sensory_data.push((first_neuron + i as u32, value));
+                }
+            }
+        }
         
-        // 2. Format and send sensory data to FEAGI via Serial
-        if !sensory_data.is_empty() && uart.is_some() {
-            // Build JSON message: {"np":[[id,pot],...],"id":"esp32","f":N}
-            let mut json: String<512> = String::from("{\"np\":[");
-            
-            for (i, (id, pot)) in sensory_data.iter().enumerate() {
-                if i > 0 {
-                    let _ = json.push_str(",");
+        // IMU accel/gyro (the last reading is repeated between polls)
+        if let Some((ref mut device, slot)) = imu {
+            let slot = &i2c_scheduler.devices()[slot];
+            if slot.fresh {
+                device.update(&slot.data[..slot.config.len as usize]);
+            }
+            if feagi_mode {
+                for channel in device.channels() {
+                    let _ = sensory_data.push(channel);
                 }
-                
-                // Convert neuron ID to string
-                let mut id_str: String<16> = String::new();
-                u32_to_string(*id, &mut id_str);
-                
-                // Convert potential to string (binary for now: 0 or 1)
-                let pot_int = if *pot > 0.5 { 1 } else { 0 };
-                let mut pot_str: String<16> = String::new();
-                u32_to_string(pot_int as u32, &mut pot_str);
-                
-                let _ = json.push_str("[");
-                let _ = json.push_str(id_str.as_str());
-                let _ = json.push_str(",");
-                let _ = json.push_str(pot_str.as_str());
-                let _ = json.push_str("]");
             }
+        }
+        
+        // 2. Format and send sensory data to FEAGI
+        // (subsampled or aggregated per the sensory policy)
+        let frame_due = sensory_window.push(&settings.rate_policy.value, &mut sensory_data);
+        // Delta reporting: only the channels that changed, but all of them
+        // in a keyframe
+        let keyframe = if feagi_mode && protocol_version.is_some() && frame_due && transport.is_some() {
+            delta_filter.filter(&settings.rate_policy.value, &mut sensory_data, unsafe { sys::esp_timer_get_time() })
+        } else {
+            None
+        };
+        let echo_outputs = OUTPUT_ECHO_ENABLED && !outputs.is_empty();
+        // Board health rides along once per second
+        let health_due = TELEMETRY_BOARD_HEALTH && frame_number % burst_frequency as u64 == 0;
+        // So is link quality, sampled from the counters of the last second
+        let wifi_link = match link_monitor {
+            Some(ref mut monitor) if frame_number % burst_frequency as u64 == 0 => Some(monitor.sample(
+                links.as_ref().and_then(|l| l.wifi.as_ref()).and_then(|w| w.rssi()),
+                frames_sent,
+                sensory_queue.resent,
+                Wifi::disconnects(),
+            )),
+            _ => None,
+        };
+        if feagi_mode && protocol_version.is_some() && frame_due && (!sensory_data.is_empty() || echo_outputs || !feedback_data.is_empty() || health_due || wifi_link.is_some()) && transport.is_some() {
+            // Typed frame, serialized by serde-json-core (see messages.rs):
+            // "ao" (applied outputs) is only present when output echo is enabled,
+            // "fb" (measured servo positions) only when feedback pins are configured,
+            // "ec" (edge counts) only when interrupt-driven inputs are configured,
+            // "od" (wheel odometry) only when odometry is configured,
+            // "wl" (WiFi link quality) once per second when link telemetry is on,
+            // "t" (sample time, Unix ms) only once SNTP time sync has completed
+            // Hosts before protocol version 3 expect 0/1 potentials
+            let mut frame = SensoryFrame::new(&sensory_data, frame_number, protocol_version.is_some_and(|v| v >= 3));
+            // Echo the values actually applied to each output (proprioception)
+            if echo_outputs {
+                frame.ao = Some(outputs.applied_values().map(|(id, applied)| (Neuron(id), Unit(applied))).collect());
+            }
+            // Measured positions of feedback servos (proprioception)
+            if !feedback_data.is_empty() {
+                frame.fb = Some(feedback_data.iter().map(|&(id, position)| (Neuron(id), Unit(position))).collect());
+            }
+            // Edges counted on interrupt-driven inputs since the previous frame
+            if !edges.is_empty() {
+                frame.ec = Some(edges.take_counts().map(|(id, count)| (Neuron(id), count)).collect());
+            }
+            // Pose and motion since the previous frame (see odometry.rs)
+            frame.od = odometry.as_mut().map(|o| o.take_frame().into());
+            // Signal strength, re-sent share and disconnects (see link_telemetry.rs)
+            frame.wl = wifi_link.map(|values| values.map(|(id, value)| (Neuron(id), Unit(value))));
+            // Chip temperature (°C, when the SoC has a sensor) and loop load (0.0-1.0)
+            if health_due {
+                frame.tc = chip_temp.as_mut().and_then(|t| t.read_celsius()).map(Tenths);
+                frame.cpu = Some(Unit(load_meter.load));
+            }
+            // Lets FEAGI line up the streams of several boards
+            frame.t = sample_ms;
+            frame.kf = keyframe.map(u8::from);
             
-            let _ = json.push_str("],\"id\":\"esp32\",\"f\":");
-            let mut frame_str: String<16> = String::new();
-            u64_to_string(frame_number, &mut frame_str);
-            let _ = json.push_str(frame_str.as_str());
-            let _ = json.push_str("}\n");
-            
-            // Send over UART
-            if let Some(ref mut u) = uart {
-                if let Err(_e) = u.write(json.as_bytes()) {
-                    unsafe {
-                        sys::esp_rom_printf(b"[FEAGI] Failed to send sensory data\r\n\0".as_ptr() as *const c_char);
+            // Send over the transport; frames queued while the transport was down go
+            // first, and this one is queued if it can't go out
+            // Version 1 hosts only read JSON lines
+            let encoded = if WIRE_FORMAT == "binary" && protocol_version.is_some_and(|v| v >= 2) {
+                protocol::encode_frame(&frame)
+            } else {
+                frame.to_line().map(String::into_bytes)
+            };
+            match (encoded, transport.as_mut()) {
+                (Some(bytes), Some(u)) => {
+                    if flush_queue(u, &mut link, &settings.device_id.value, compressing, sensory_queue) && transmit_sensory(u, &mut link, &settings.device_id.value, compressing, &bytes) {
+                        supervisor.record_tx();
+                        heartbeat::sensory_sent();
+                        frames_sent = frames_sent.wrapping_add(1);
+                    } else {
+                        supervisor.record_error();
+                        sensory_queue.push(&bytes);
                     }
                 }
+                (Some(bytes), None) => sensory_queue.push(&bytes),
+                // build.rs sizes FRAME_CAPACITY for every channel, so only a
+                // hand-edited capacity gets here
+                (None, _) => fault!(FrameOverflow, "Sensory frame exceeds FRAME_CAPACITY, dropped"),
             }
         }
         
-        // 3. Receive motor commands from FEAGI via Serial (non-blocking)
-        if let Some(ref mut u) = uart {
-            match u.read(&mut rx_buffer, 10) {  // 10ms timeout
+        // Latest camera frame, downscaled; dropped rather than queued when
+        // the transport can't take it
+        if feagi_mode {
+            if let (Some(cam), Some(u)) = (camera.as_mut(), transport.as_mut()) {
+                if cam.due(frame_number) {
+                    if let Some(line) = cam.frame_line() {
+                        if transmit_sensory(u, &mut link, &settings.device_id.value, compressing, line.as_bytes()) {
+                            supervisor.record_tx();
+                        } else {
+                            supervisor.record_error();
+                        }
+                    }
+                }
+            }
+        }
+        
+        // Raw mode: stream the selected pins instead of a FEAGI frame
+        if !feagi_mode {
+            if let (Some(u), Some(line)) = (transport.as_mut(), raw_io.stream_line(frame_number)) {
+                if transmit(u, &mut link, &settings.device_id.value, line.as_bytes()) {
+                    heartbeat::sensory_sent();
+                }
+            }
+        }
+        
+        // 3. Receive motor commands from FEAGI (non-blocking)
+        if let Some(ref mut u) = transport {
+            load_meter.idle_begin();
+            // Returns once something arrived, or after 10 ticks (ms on
+            // network transports); sooner when staged commands time out
+            let wait = barrier.remaining_ms().filter(|_| BARRIER_ENABLED).map_or(10, |ms| ms.min(10));
+            let read = u.poll_commands(&mut rx_buffer, wait);
+            load_meter.idle_end();
+            match read {
                 Ok(count) if count > 0 => {
+                    supervisor.record_rx();
+                    if keepalive.as_mut().is_some_and(|k| k.record_rx(unsafe { sys::esp_timer_get_time() })) {
+                        info!("Link restored");
+                    }
+                    
                     // Accumulate received data
                     for i in 0..count {
                         if let Err(_) = rx_accumulator.push(rx_buffer[i]) {
                             // Buffer full, process what we have
+                            metrics.rx_dropped = metrics.rx_dropped.wrapping_add((count - i) as u32);
+                            fault!(RxOverflow, "RX buffer full, {} bytes dropped", count - i);
                             break;
                         }
                     }
                     
-                    // Check if we have a complete JSON message (ends with \n)
-                    if let Some(newline_idx) = rx_accumulator.iter().position(|&b| b == b'\n') {
-                        // Extract message (build string manually for heapless)
-                        let mut message_str: String<512> = String::new();
-                        for &byte in rx_accumulator.iter().take(newline_idx) {
-                            if byte.is_ascii() {
-                                let _ = message_str.push(byte as char);
-                            }
+                    // Motor commands of this read, from binary packets or a JSON line
+                    let mut motor: Vec<(u32, f32), MAX_MOTOR_NEURONS> = Vec::new();
+                    // Messages among them that asked for a motor_ack
+                    let mut sequenced: Vec<Sequenced, MAX_SEQUENCED> = Vec::new();
+                    
+                    // Binary packets (see protocol.rs) come ahead of any line
+                    if WIRE_FORMAT == "binary" {
+                        let (used, packets) = protocol::decode_packets(&rx_accumulator, &mut motor, &mut sequenced);
+                        metrics.frames_received = metrics.frames_received.wrapping_add(packets);
+                        if link.is_some() {
+                            // Plaintext packets on an encrypted link - drop
+                            motor.clear();
+                            sequenced.clear();
                         }
-                        rx_accumulator.clear();
-                        
-                        // Parse JSON motor command (simplified parsing)
-                        // Format: {"mc":[[neuron_id,value],...]} or {"motor_commands":[...]}
-                        // Simple parsing: look for neuron_id and value pairs
-                        // TODO: Use proper JSON parser (serde-json-core)
-                        
-                        // For now, implement simple pattern matching
-                        // Look for patterns like "neuron_id":N or "value":V
-                        let mut neuron_id: Option<u32> = None;
-                        let mut value: Option<f32> = None;
+                        rx_accumulator.rotate_left(used);
+                        rx_accumulator.truncate(rx_accumulator.len() - used);
+                    }
+                    
+                    // Every complete JSON message (ends with \n) of the read; a
+                    // trailing partial line waits for the next one
+                    while let Some(mut message_str) = parse::take_line(&mut rx_accumulator) {
+                        metrics.frames_received = metrics.frames_received.wrapping_add(1);
                         
-                        // Try to extract neuron_id and value from JSON
-                        // This is a very simple parser - in production use serde-json-core
-                        // Split by non-alphanumeric characters
-                        let mut words: Vec<&str, 64> = Vec::new();
-                        let mut word_start = 0;
-                        let message_bytes = message_str.as_bytes();
-                        for (i, &byte) in message_bytes.iter().enumerate() {
-                            let c = byte as char;
-                            if !c.is_alphanumeric() && c != '.' && c != '-' {
-                                if i > word_start {
-                                    if let Ok(word) = core::str::from_utf8(&message_bytes[word_start..i]) {
-                                        if !word.is_empty() {
-                                            let _ = words.push(word);
+                        // Encrypted link: accept an authenticated host salt, then
+                        // only sealed lines
+                        let mut handshaken = false;
+                        if let Some(ref mut l) = link {
+                            if let Some((salt, auth)) = secure_link::parse_peer_salt(&message_str) {
+                                match l.accept_host(&salt, &auth) {
+                                    Ok(()) => handshaken = true,
+                                    Err(_) => fault!(AuthFailed, "Rejected unauthenticated host salt"),
+                                }
+                                message_str.clear();
+                            } else if secure_link::is_hello_request(&message_str) {
+                                // A host that lost its session asks for the current salt
+                                send_hello(u, &link, &settings, wake_reason);
+                                message_str.clear();
+                            } else {
+                                let mut plain: Vec<u8, RX_LINE_CAPACITY> = Vec::new();
+                                let opened = l.open_line(&message_str, &mut plain);
+                                message_str.clear();
+                                match opened {
+                                    Ok(()) if WIRE_FORMAT == "binary" && plain.first().is_some_and(|&b| protocol::is_packet_start(b)) => {
+                                        protocol::decode_packets(&plain, &mut motor, &mut sequenced);
+                                    }
+                                    Ok(()) => {
+                                        for &byte in plain.iter() {
+                                            if byte.is_ascii() {
+                                                let _ = message_str.push(byte as char);
+                                            }
                                         }
                                     }
+                                    Err(_) => fault!(AuthFailed, "Dropped unauthenticated line"),
                                 }
-                                word_start = i + 1;
                             }
                         }
-                        if word_start < message_bytes.len() {
-                            if let Ok(word) = core::str::from_utf8(&message_bytes[word_start..]) {
-                                if !word.is_empty() {
-                                    let _ = words.push(word);
-                                }
+                        
+                        // Heard from the host: a fresh update has proven itself (see ota.rs)
+                        if !message_str.is_empty() || !motor.is_empty() || handshaken {
+                            ota::confirm();
+                        }
+                        
+                        // Channels for FEAGI's cortical areas: after the handshake,
+                        // or asked for with {"get_capabilities":1}
+                        if handshaken || capabilities::is_request(&message_str) {
+                            transmit(u, &mut link, &settings.device_id.value, capabilities::document(&settings).as_bytes());
+                        }
+                        // Why the board last reset, for diagnosing flaky hardware
+                        if handshaken || boot_report::is_request(&message_str) {
+                            transmit(u, &mut link, &settings.device_id.value, boot_report::line().as_bytes());
+                        }
+                        // Outputs from before a software restart, for FEAGI to confirm
+                        if handshaken {
+                            if let Some(offer) = OUTPUT_RESTORE.as_ref().and_then(output_restore::offer_line) {
+                                transmit(u, &mut link, &settings.device_id.value, offer.as_bytes());
                             }
                         }
-                            
-                            for i in 0..words.len().saturating_sub(1) {
-                                if words[i] == "neuron_id" || words[i] == "id" {
-                                    if let Some(id_str) = words.get(i + 1) {
-                                        if let Ok(id) = id_str.parse::<u32>() {
-                                            neuron_id = Some(id);
-                                        }
-                                    }
+                        if let Some(accepted) = output_restore::parse_answer(&message_str) {
+                            // Safe-stop holds them like any motor command
+                            let values = output_restore::answer(accepted && !safe_stopped);
+                            let restored = values.iter().filter(|&&(pin, value)| outputs.restore(pin, value)).count();
+                            if accepted {
+                                info!("Restored {} outputs from before the restart", restored);
+                            }
+                            transmit(u, &mut link, &settings.device_id.value, output_restore::ack_line(restored).as_bytes());
+                        }
+                        
+                        // Protocol version offered by the host: {"proto":N}
+                        if let Some(offered) = version::parse_offer(&message_str) {
+                            protocol_version = version::negotiate(offered);
+                            match protocol_version {
+                                Some(v) => {
+                                    transmit(u, &mut link, &settings.device_id.value, version::ack_line(v).as_bytes());
+                                    delta_filter.force_keyframe();
                                 }
-                                if words[i] == "value" || words[i] == "v" {
-                                    if let Some(val_str) = words.get(i + 1) {
-                                        if let Ok(val) = val_str.parse::<f32>() {
-                                            value = Some(val);
-                                        }
-                                    }
+                                None => {
+                                    warn!("Host speaks protocol {}, need {}-{}: frames and motor commands paused",
+                                        offered, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION);
+                                    transmit(u, &mut link, &settings.device_id.value, version::error_line(offered).as_bytes());
                                 }
                             }
-                            
-                            // Apply motor command to GPIO outputs
-                            if let (Some(nid), Some(val)) = (neuron_id, value) {
-                                // Find GPIO output with matching neuron ID
-                                for (pin_num, mapping) in digital_output_configs.iter() {
-                                    if let Some(neuron_id_from_map) = parse_neuron_id(mapping) {
-                                        if neuron_id_from_map == nid {
-                                            if let Some(pin) = get_pin!(*pin_num, peripherals.pins) {
-                                                if let Ok(mut driver) = PinDriver::output(pin) {
-                                                    if val > 0.5 {
-                                                        let _ = driver.set_high();
-                                                    } else {
-                                                        let _ = driver.set_low();
-                                                    }
-                                                    // Driver goes out of scope, pin released
-                                                }
+                        }
+                        
+                        // Sensory line compression: {"compression":"heatshrink"|"none"}
+                        if let Some(on) = compress::parse_request(&message_str) {
+                            compressing = on && COMPRESSION.is_some();
+                            transmit(u, &mut link, &settings.device_id.value, compress::ack_line(compressing).as_bytes());
+                        }
+                        
+                        // Barrier release from the gateway/host: {"b":burst_id}
+                        if let Some(burst_id) = barrier::parse(&message_str).filter(|_| BARRIER_ENABLED && !safe_stopped) {
+                            if !barrier.release_burst(burst_id, &mut outputs) {
+                                debug!("Stale barrier {} ignored", burst_id);
+                            }
+                        }
+                        
+                        // System-identification request: sweep an output while
+                        // streaming the response at SYSID_RATE_HZ
+                        if let Some(request) = SysIdRequest::parse(&message_str).filter(|_| !safe_stopped) {
+                            if !outputs.is_proportional(request.neuron_id) {
+                                transmit(u, &mut link, &settings.device_id.value, b"{\"sy_err\":\"not a proportional output\"}\n");
+                            } else {
+                                info!("SysId: driving neuron {} for {} ms", request.neuron_id, request.duration_ms);
+                                let started = sysid::run(
+                                    &request,
+                                    SYSID_RATE_HZ,
+                                    |value| {
+                                        outputs.apply(request.neuron_id, value);
+                                    },
+                                    |responses| {
+                                        for (_, position) in feedback.read_all().iter() {
+                                            let _ = responses.push(*position);
+                                        }
+                                        for (_, position, velocity) in encoders.read_all() {
+                                            let _ = responses.push(position);
+                                            let _ = responses.push(velocity);
+                                        }
+                                        // The I2C scheduler doesn't run meanwhile
+                                        if let (Some((device, slot)), Some(bus)) = (imu.as_mut(), i2c_bus.as_mut()) {
+                                            let config = i2c_scheduler.devices()[*slot].config;
+                                            let mut block = [0u8; i2c::MAX_READ_LEN];
+                                            let block = &mut block[..config.len as usize];
+                                            if bus.read_registers(config.address, config.register, block) {
+                                                device.update(block);
+                                            }
+                                            for (_, value) in device.channels() {
+                                                let _ = responses.push(value);
                                             }
                                         }
-                                    }
+                                    },
+                                    |line| {
+                                        transmit(u, &mut link, &settings.device_id.value, line);
+                                    },
+                                );
+                                if !started {
+                                    warn!("SysId: sample timer unavailable");
+                                    transmit(u, &mut link, &settings.device_id.value, b"{\"sy_err\":\"timer\"}\n");
+                                }
+                            }
+                        }
+                        
+                        // Burst-rate policy negotiation: {"policy":{...}}
+                        if let Some(negotiated) = settings.rate_policy.value.negotiate(&message_str) {
+                            settings.rate_policy.set_runtime(negotiated);
+                            transmit(u, &mut link, &settings.device_id.value, negotiated.ack_line().as_bytes());
+                        }
+                        
+                        // Session mode selection: {"mode":"feagi"|"raw"}, at
+                        // the handshake only
+                        if let Some(mode) = SessionMode::parse(&message_str) {
+                            if !mode_open {
+                                transmit(u, &mut link, &settings.device_id.value, settings.mode.value.refused_line().as_bytes());
+                            } else {
+                                if settings.mode.value == SessionMode::Raw && mode != SessionMode::Raw {
+                                    raw_io.leave();
+                                }
+                                settings.mode.set_runtime(mode);
+                                transmit(u, &mut link, &settings.device_id.value, mode.ack_line().as_bytes());
+                                delta_filter.force_keyframe();
+                            }
+                        }
+                        
+                        // Raw pin telegrams: {"rd":P}, {"wr":[P,V]}, {"st":[P,...]}
+                        if settings.mode.value == SessionMode::Raw {
+                            let write = message_str.starts_with("{\"wr\"");
+                            if write {
+                                heartbeat::motor_received();
+                            }
+                            if ["{\"rd\"", "{\"wr\"", "{\"st\""].iter().any(|t| message_str.starts_with(t)) {
+                                mode_open = false;
+                            }
+                            // Safe-stop holds raw pin writes too
+                            if write && safe_stopped {
+                                transmit(u, &mut link, &settings.device_id.value, b"{\"safe_stop\":true}\n");
+                            } else if let Some(reply) = raw_io.handle(&message_str) {
+                                transmit(u, &mut link, &settings.device_id.value, reply.as_bytes());
+                            }
+                        }
+                        
+                        // Bulk settings access: {"get_settings":[...]} / {"set_settings":{...}}
+                        // (a set batch is applied all-or-nothing)
+                        if Settings::is_get_request(&message_str) {
+                            let reply = match settings.get_batch(&message_str, settings_reply) {
+                                Ok(()) => settings_reply.as_bytes(),
+                                Err(overflow) => {
+                                    fault!(FrameOverflow, "get_settings answer over {} bytes", overflow.capacity);
+                                    b"{\"settings_err\":\"overflow\"}\n"
                                 }
-                                
+                            };
+                            transmit(u, &mut link, &settings.device_id.value, reply);
+                        } else if Settings::is_set_request(&message_str) {
+                            let previous_id = settings.device_id.value.clone();
+                            let result = settings.set_batch(&message_str);
+                            if result.is_ok() {
+                                barrier.set_timeout_ms(settings.barrier_timeout_ms.value);
+                                logger::set_levels(settings.log_level.value, settings.log_forward.value);
+                                if settings.device_id.value != previous_id && !device_id::store(&settings.device_id.value) {
+                                    warn!("Failed to store device ID, kept until reboot");
+                                }
+                            }
+                            transmit(u, &mut link, &settings.device_id.value, Settings::ack_line("settings_ack", &result).as_bytes());
+                        }
+                        
+                        // Configuration kept in NVS for the next boot:
+                        // {"store_config":{...}} / {"get_stored_config":1} / {"clear_config":1}
+                        if stored_config::is_store_request(&message_str) {
+                            let result = stored_config::store_request(&settings, &message_str);
+                            transmit(u, &mut link, &settings.device_id.value, Settings::ack_line("config_ack", &result).as_bytes());
+                        } else if stored_config::is_get_request(&message_str) {
+                            transmit(u, &mut link, &settings.device_id.value, stored_config::get_line().as_bytes());
+                        } else if stored_config::is_clear_request(&message_str) {
+                            let result = if stored_config::clear() {
+                                Ok(0)
+                            } else {
+                                Err(SettingsError { key: None, reason: "failed to write NVS" })
+                            };
+                            transmit(u, &mut link, &settings.device_id.value, Settings::ack_line("config_ack", &result).as_bytes());
+                        } else if stored_config::is_gpio_request(&message_str) {
+                            // {"set_gpio":{"pin":25,"mode":...,"cortical_mapping":...}}:
+                            // the pin banks are set up at boot, so reboot into it
+                            let result = stored_config::gpio_request(&settings, &message_str);
+                            transmit(u, &mut link, &settings.device_id.value, Settings::ack_line("gpio_ack", &result).as_bytes());
+                            if result.is_ok() {
+                                outputs.safe_stop();
+                                info!("Pin configuration stored, rebooting to apply it");
+                                transmit(u, &mut link, &settings.device_id.value, b"{\"restarting\":\"gpio\"}\n");
+                                u.flush(100);
                                 unsafe {
-                                    sys::esp_rom_printf(b"[FEAGI] Motor: neuron %d -> value %.2f\r\n\0".as_ptr() as *const c_char,
-                                        nid as i32, val as f64);
+                                    sys::esp_restart();
                                 }
                             }
                         }
+
+                        // Host-requested sleep: {"sleep":1}, {"sleep":"light"}
+                        // or {"sleep":"deep"}
+                        if sleep::is_sleep_request(&message_str) {
+                            if let Some(ref config) = SLEEP_CONFIG {
+                                if sleep::requested_mode(&message_str, config) == SleepMode::Deep {
+                                    enter_deep_sleep(Some(u), &mut link, &settings.device_id.value, &outputs, config);
+                                }
+                                enter_light_sleep(Some(&mut *u), &mut link, &settings.device_id.value, &mut outputs, config);
+                                let now = unsafe { sys::esp_timer_get_time() };
+                                last_motor_us = now;
+                                if let Some(k) = keepalive.as_mut() {
+                                    k.resume(now);
+                                }
+                                metrics.resume();
+                                // Forget ramps and staged commands, or they'd drive the outputs again
+                                shaper = MotorShaper::new();
+                                barrier.discard();
+                            }
+                        }
+                        
+                        // Effective configuration dump: {"get_config":1}
+                        if Settings::is_dump_request(&message_str) {
+                            let reply = match settings.dump(settings_reply) {
+                                Ok(()) => settings_reply.as_bytes(),
+                                Err(overflow) => {
+                                    fault!(FrameOverflow, "get_config answer over {} bytes", overflow.capacity);
+                                    b"{\"config_err\":\"overflow\"}\n"
+                                }
+                            };
+                            transmit(u, &mut link, &settings.device_id.value, reply);
+                        }
+                        
+                        // JSON motor commands: {"neuron_id":N,"value":V} or a
+                        // {"mc":[[N,V],...]} batch (see messages.rs), unless
+                        // addressed to another board on the link
+                        let message = match MotorMessage::parse(&message_str) {
+                            Some(message) => message,
+                            None => {
+                                // Empty once the link layer took it (salt, sealed packets)
+                                if !message_str.is_empty() {
+                                    metrics.parse_errors = metrics.parse_errors.wrapping_add(1);
+                                    fault!(ParseError, "Unparsable line: {}", &message_str[..message_str.len().min(32)]);
+                                }
+                                MotorMessage::default()
+                            }
+                        };
+                        if message.is_for(&settings.device_id.value) {
+                            let start = motor.len();
+                            for command in message.commands() {
+                                let _ = motor.push(command);
+                            }
+                            if let Some(seq) = message.seq() {
+                                let _ = sequenced.push(Sequenced { seq, commands: start..motor.len() });
+                            }
+                        }
+                    }
+                    if rx_accumulator.is_full() {
+                        // A line longer than the buffer would stall reception
+                        // for good: drop it
+                        metrics.rx_dropped = metrics.rx_dropped.wrapping_add(rx_accumulator.len() as u32);
+                        fault!(RxOverflow, "Line longer than {} bytes dropped", rx_accumulator.len());
+                        rx_accumulator.clear();
+                    }
+                    
+                    if !motor.is_empty() || !sequenced.is_empty() {
+                        mode_open = false;
+                    }
+                    
+                    // Answer sequenced messages; repeated and stale ones aren't
+                    // applied (see motor_ack.rs)
+                    let mut skipped: Vec<core::ops::Range<usize>, MAX_SEQUENCED> = Vec::new();
+                    for message in sequenced.iter() {
+                        let result = if settings.mode.value != SessionMode::Feagi {
+                            Err("mode")
+                        } else if protocol_version.is_none() {
+                            Err("protocol")
+                        } else if safe_stopped {
+                            Err("safe_stop")
+                        } else {
+                            let verdict = motor_acks.accept(message.seq);
+                            if verdict != Verdict::Apply {
+                                let _ = skipped.push(message.commands.clone());
+                            }
+                            match verdict {
+                                Verdict::Apply => Ok(message.commands.len()),
+                                Verdict::Repeat => Ok(0),
+                                Verdict::Stale => Err("stale"),
+                            }
+                        };
+                        transmit(u, &mut link, &settings.device_id.value, motor_ack::ack_line(message.seq, result).as_bytes());
+                    }
+                    
+                    if settings.mode.value == SessionMode::Feagi && protocol_version.is_some() {
+                        let applied = motor.iter().enumerate().filter(|(i, _)| !skipped.iter().any(|r| r.contains(i)));
+                        for (_, &(nid, val)) in applied {
+                            // Drive every output mapped to this neuron ID, or wait
+                            // for the barrier when actuation is synchronized
+                            last_motor_us = unsafe { sys::esp_timer_get_time() };
+                            heartbeat::motor_received();
+                            motor_commands = motor_commands.wrapping_add(1);
+                            if safe_stopped {
+                                // Ignored until resumed over the status server
+                            } else if BARRIER_ENABLED {
+                                barrier.stage(nid, val);
+                            } else {
+                                let now = unsafe { sys::esp_timer_get_time() };
+                                shaper.command(&settings.rate_policy.value, &mut outputs, nid, val, now);
+                            }
+                            
+                            debug!("Motor: neuron {} -> value {:.2}", nid, val);
+                        }
                     }
                 }
                 Ok(_) => {
                     // No data available, continue
                 }
                 Err(_) => {
-                    // Read error, continue
+                    // Read error, continue (the supervisor restarts the
+                    // transport if they keep coming)
+                    supervisor.record_error();
                 }
             }
         }
         
+        // WiFi credentials provisioned over BLE: switch to the WiFi transport
+        // without rebooting
+        let mut reopen: Option<Reopen> = None;
+        let provisioned = links.as_ref().and_then(|l| l.ble.as_ref()).and_then(|b| b.provisioned());
+        if let (Some(credentials), Some(config)) = (provisioned, WIFI_CONFIG) {
+            info!("WiFi credentials provisioned over BLE, switching to WiFi");
+            drop(transport.take());
+            rx_accumulator.clear();
+            reopen = Some(Reopen::Wifi(config, credentials));
+        }
+        
+        // Log lines at or above log.forward (see logger.rs); kept queued
+        // while there's no transport
+        if let Some(ref mut u) = transport {
+            for _ in 0..logger::LINES_PER_BURST {
+                let Some(line) = logger::next_line() else {
+                    break;
+                };
+                transmit(u, &mut link, &settings.device_id.value, line.as_bytes());
+            }
+            // Typed faults (see fault.rs), also kept until there's a transport
+            for _ in 0..fault::LINES_PER_BURST {
+                let Some(line) = fault::next_line() else {
+                    break;
+                };
+                transmit(u, &mut link, &settings.device_id.value, line.as_bytes());
+            }
+        }
+        
+        // Heartbeats, and safe outputs once FEAGI went quiet (see keepalive.rs)
+        let keepalive_event = keepalive.as_mut().map_or(KeepaliveEvent::None, |k| k.poll(unsafe { sys::esp_timer_get_time() }));
+        match keepalive_event {
+            KeepaliveEvent::None => {}
+            KeepaliveEvent::Beat(n) => {
+                if let Some(ref mut u) = transport {
+                    transmit(u, &mut link, &settings.device_id.value, keepalive::beat_line(n).as_bytes());
+                }
+            }
+            KeepaliveEvent::Lost => {
+                warn!("Link lost (nothing received for {} ms), outputs safe, reconnecting", KEEPALIVE.map_or(0, |k| k.timeout_ms));
+                if !safe_stopped {
+                    outputs.safe_stop();
+                    // Forget ramps and staged commands, or they'd drive the outputs again
+                    shaper = MotorShaper::new();
+                    barrier.discard();
+                }
+                supervisor.link_lost();
+            }
+            KeepaliveEvent::StillLost => supervisor.link_lost(),
+        }
+        
+        // Tear down and reinitialize a wedged transport; outputs, devices and
+        // the burst loop carry on meanwhile
+        // (not while a reopen is under way)
+        let action = if links.is_some() && reopen.is_none() { supervisor.check(transport.is_some()) } else { Action::None };
+        match action {
+            Action::None => {}
+            Action::Restart => {
+                warn!("Transport wedged, restarting it (restart {})", supervisor.restarts + 1);
+                // FEAGI can't reach the outputs until it's back (see failsafe.rs)
+                if !safe_stopped {
+                    outputs.safe_stop();
+                    shaper = MotorShaper::new();
+                    barrier.discard();
+                }
+                // Uninstall the old driver (close the socket) before reopening
+                drop(transport.take());
+                rx_accumulator.clear();
+                reopen = Some(Reopen::Restart);
+            }
+            Action::Failover => {
+                warn!("Transport unreachable, failing over to serial");
+                if !safe_stopped {
+                    outputs.safe_stop();
+                    shaper = MotorShaper::new();
+                    barrier.discard();
+                }
+                drop(transport.take());
+                rx_accumulator.clear();
+                fallbacks_used += 1;
+                reopen = Some(Reopen::Failover);
+            }
+            Action::Reboot => {
+                error!("Transport restarts exhausted, rebooting");
+                // Outputs with boot_state "hold" keep their level through the reset
+                outputs.hold_for_deep_sleep();
+                unsafe {
+                    sys::esp_restart();
+                }
+            }
+        }
+        
+        // Reopen the transport: on the transport task when it runs, which
+        // hands it back at a later burst, else right here
+        let mut reopened: Option<Reopen> = None;
+        if let Some(how) = reopen.take() {
+            if let Some(l) = links.take() {
+                match transport_task::reopen(l, how) {
+                    Ok(()) => {}
+                    Err((mut l, how)) => {
+                        transport = l.reopen(&how);
+                        links = Some(l);
+                        reopened = Some(how);
+                    }
+                }
+            }
+        }
+        if let Some((l, how, opened)) = transport_task::reopened() {
+            links = Some(l);
+            transport = opened;
+            reopened = Some(how);
+        }
+        if let Some(how) = reopened {
+            match how {
+                Reopen::Restart => supervisor.restarted(transport.is_some()),
+                Reopen::Failover => supervisor.failed_over(transport.is_some(), fallbacks_used < TRANSPORT_FALLBACK.len()),
+                Reopen::Wifi(..) => {}
+            }
+            if let Some(ref mut u) = transport {
+                // New session: the host re-handshakes (fresh salt, counters),
+                // and the hello names the transport, so the host can adapt
+                link = new_link();
+                motor_acks = MotorAcks::new();
+                protocol_version = Some(PROTOCOL_VERSION);
+                compressing = false;
+                delta_filter.force_keyframe();
+                mode_open = true;
+                send_hello(u, &link, &settings, wake_reason);
+                if matches!(how, Reopen::Restart) {
+                    send_reconnect_status(u, &mut link, &settings.device_id.value, &supervisor, sensory_queue);
+                }
+            }
+        }
+        
+        // Safe-stop / resume requested over the status server, or a safe-stop
+        // for a low battery
+        let low_battery = power.as_mut().map_or(false, |p| p.take_low_battery());
+        if low_battery {
+            warn!("Low battery ({} mV)", power.as_ref().map_or(0, |p| (p.voltage() * 1000.0) as i32));
+        }
+        if let Some(stop) = status_server::take_request().or(low_battery.then_some(true)) {
+            if stop && !safe_stopped {
+                outputs.safe_stop();
+                // Forget ramps and staged commands, or they'd drive the outputs again
+                shaper = MotorShaper::new();
+                barrier.discard();
+            }
+            safe_stopped = stop;
+            if stop {
+                info!("Safe-stop: outputs low, ignoring motor commands");
+            } else {
+                info!("Safe-stop released");
+            }
+            // Let the host know its commands are being ignored
+            if let Some(ref mut u) = transport {
+                let line: &[u8] = if stop { b"{\"safe_stop\":true}\n" } else { b"{\"safe_stop\":false}\n" };
+                transmit(u, &mut link, &settings.device_id.value, line);
+            }
+        }
+        
+        // A firmware update was accepted (see ota.rs): boot into it with the
+        // outputs stopped
+        if ota::take_reboot() {
+            outputs.safe_stop();
+            info!("Rebooting into the updated firmware");
+            if let Some(ref mut u) = transport {
+                transmit(u, &mut link, &settings.device_id.value, b"{\"ota\":\"rebooting\"}\n");
+                u.flush(100);
+            }
+            unsafe {
+                sys::esp_restart();
+            }
+        }
+        
         // 4. Write motor outputs (GPIO)
-        // This is handled in the receive section above
+        // This is handled in the receive section above; staged commands are
+        // applied here if the barrier didn't arrive in time
+        if BARRIER_ENABLED && !safe_stopped && barrier.poll_timeout(&mut outputs) {
+            warn!("Barrier timeout, applied staged commands ({} total)", barrier.timeouts);
+        }
+        
+        // Outputs driven by population firing switch on threshold crossings
+        if !safe_stopped {
+            outputs.update_populations();
+        }
+        
+        // Interpolate towards / decay away from the last commands
+        if !BARRIER_ENABLED && !safe_stopped && settings.rate_policy.value.motor != MotorPolicy::HoldLast {
+            shaper.tick(&settings.rate_policy.value, &mut outputs, unsafe { sys::esp_timer_get_time() });
+        }
+        
+        // LED strip frames go out once per burst, with all of its changes
+        outputs.refresh_strips();
+        // Audio output is topped up once per burst too
+        outputs.refresh_audio();
+        // So do changed expander outputs
+        if let (Some(bank), Some(bus)) = (expanders.as_mut(), i2c_bus.as_mut()) {
+            bank.write_outputs(bus);
+        }
+        
+        // Nothing happening: sleep until a wake source fires
+        if let Some(ref config) = SLEEP_CONFIG {
+            let idle_us = unsafe { sys::esp_timer_get_time() } - last_motor_us;
+            if config.idle_ms > 0 && idle_us >= config.idle_ms as i64 * 1000 {
+                if config.mode == SleepMode::Deep {
+                    enter_deep_sleep(transport.as_mut(), &mut link, &settings.device_id.value, &outputs, config);
+                }
+                enter_light_sleep(transport.as_mut(), &mut link, &settings.device_id.value, &mut outputs, config);
+                let now = unsafe { sys::esp_timer_get_time() };
+                last_motor_us = now;
+                if let Some(k) = keepalive.as_mut() {
+                    k.resume(now);
+                }
+                metrics.resume();
+                shaper = MotorShaper::new();
+                barrier.discard();
+            }
+        }
+        
+        // Snapshot for the status server's handlers
+        if STATUS_SERVER_PORT.is_some() {
+            for (pin, applied) in outputs.pin_levels() {
+                status_server::set_level(pin, applied > 0.5);
+            }
+            let counters = Counters {
+                frame: frame_number as u32,
+                frames_sent,
+                motor_commands,
+                restarts: supervisor.restarts,
+                reconnects: supervisor.reconnects,
+                queued: sensory_queue.len(),
+                dropped: sensory_queue.dropped,
+                frames_received: metrics.frames_received,
+                rx_dropped: metrics.rx_dropped,
+                parse_errors: metrics.parse_errors,
+                period_us: metrics.period_us,
+                jitter_us: metrics.jitter_us,
+            };
+            let connected = transport.as_ref().map_or(false, |t| t.status().connected);
+            status_server::publish(&counters, transport.as_ref().map(transport_name), connected, safe_stopped);
+        }
         
         frame_number = frame_number.wrapping_add(1);
         
-        // Wait for next sampling period
-        let elapsed = 10; // LED blink time + processing time estimate
-        if sampling_period_ms > elapsed {
-            FreeRtos::delay_ms(sampling_period_ms - elapsed);
+        // Wait for the next burst period; lower-priority tasks (the idle
+        // task and its watchdog feed) run meanwhile
+        let (burst_start_us, busy_us) = load_meter.end_burst();
+        if TELEMETRY_PROFILE {
+            profiler.record(burst_start_us, busy_us);
+        }
+        load_meter.idle_begin();
+        match burst_timer {
+            Some(ref timer) => {
+                // Staged commands whose barrier doesn't come are applied
+                // when they time out, not at the next burst
+                let mut started = false;
+                while let Some(ms) = barrier.remaining_ms().filter(|_| BARRIER_ENABLED && !safe_stopped) {
+                    if timer.wait_for(ms) {
+                        started = true;
+                        break;
+                    }
+                    if barrier.poll_timeout(&mut outputs) {
+                        warn!("Barrier timeout, applied staged commands ({} total)", barrier.timeouts);
+                    }
+                }
+                if !started {
+                    timer.wait();
+                }
+            }
+            None => FreeRtos::delay_ms((burst_period_us / 1000).max(1) as u32),
         }
+        load_meter.idle_end();
     }
 }
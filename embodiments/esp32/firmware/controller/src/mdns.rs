@@ -0,0 +1,201 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! mDNS discovery of the FEAGI host
+//!
+//! With `"discover": true` the WiFi transports browse for a
+//! `_feagi._tcp.local` service before connecting, instead of relying on the
+//! address in config.json. Just enough of mDNS (RFC 6762) for that: a PTR
+//! query asking for unicast replies, and the SRV and A records a responder
+//! sends back for the first instance found. The static address is used when
+//! nothing answers in time.
+
+use core::ffi::c_void;
+use core::mem::size_of;
+
+use esp_idf_svc::sys;
+use heapless::{String, Vec};
+
+use crate::transport::{ipv4_addr, received, set_rx_timeout};
+
+const MDNS_GROUP: [u8; 4] = [224, 0, 0, 251];
+const MDNS_PORT: u16 = 5353;
+
+/// The service FEAGI advertises
+const SERVICE: &str = "_feagi._tcp.local";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+/// Class IN with the "unicast response" bit
+const CLASS_IN_QU: u16 = 0x8001;
+
+/// Re-send the query this often until someone answers
+const QUERY_INTERVAL_MS: u32 = 1000;
+
+/// Largest response read; longer ones are truncated and usually still parse
+const RESPONSE_CAPACITY: usize = 1024;
+
+type Name = String<256>;
+
+/// Browse for FEAGI for up to `timeout_ms`; the first instance's IPv4
+/// address and port
+pub fn discover(timeout_ms: u32) -> Option<([u8; 4], u16)> {
+    let fd = unsafe { sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_DGRAM as i32, sys::IPPROTO_UDP as i32) };
+    if fd < 0 {
+        return None;
+    }
+    let found = browse(fd, timeout_ms);
+    unsafe {
+        sys::lwip_close(fd);
+    }
+    found
+}
+
+fn browse(fd: i32, timeout_ms: u32) -> Option<([u8; 4], u16)> {
+    let group = ipv4_addr(MDNS_GROUP, MDNS_PORT);
+    let query = query();
+    let mut rx_timeout_ms = 0;
+    // SRV target and port, kept in case the A record comes in a later packet
+    let mut service: Option<(Name, u16)> = None;
+    let mut response = [0u8; RESPONSE_CAPACITY];
+
+    let started = now_ms();
+    let mut last_query: Option<u32> = None;
+    loop {
+        let now = now_ms();
+        let elapsed = now.wrapping_sub(started);
+        if elapsed >= timeout_ms {
+            return None;
+        }
+        if last_query.map_or(true, |t| now.wrapping_sub(t) >= QUERY_INTERVAL_MS) {
+            unsafe {
+                sys::lwip_sendto(
+                    fd,
+                    query.as_ptr() as *const c_void,
+                    query.len(),
+                    0,
+                    &group as *const sys::sockaddr_in as *const sys::sockaddr,
+                    size_of::<sys::sockaddr_in>() as u32,
+                );
+            }
+            last_query = Some(now);
+        }
+
+        set_rx_timeout(fd, &mut rx_timeout_ms, (timeout_ms - elapsed).min(QUERY_INTERVAL_MS));
+        let n = unsafe { sys::lwip_recv(fd, response.as_mut_ptr() as *mut c_void, response.len(), 0) };
+        let Ok(len) = received(n) else {
+            return None;
+        };
+        if len > 0 {
+            if let Some(found) = parse(&response[..len], &mut service) {
+                return Some(found);
+            }
+        }
+    }
+}
+
+/// PTR question for the service, asking for unicast replies
+fn query() -> Vec<u8, 64> {
+    let mut packet = Vec::new();
+    // ID 0, flags 0, one question
+    let _ = packet.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in SERVICE.split('.') {
+        let _ = packet.push(label.len() as u8);
+        let _ = packet.extend_from_slice(label.as_bytes());
+    }
+    let _ = packet.push(0);
+    let _ = packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    let _ = packet.extend_from_slice(&CLASS_IN_QU.to_be_bytes());
+    packet
+}
+
+/// Look for our service's SRV record and its target's A record in one
+/// response; `service` carries a SRV seen in an earlier one
+fn parse(msg: &[u8], service: &mut Option<(Name, u16)>) -> Option<([u8; 4], u16)> {
+    // Responses only
+    if msg.len() < 12 || msg[2] & 0x80 == 0 {
+        return None;
+    }
+    let count = |i: usize| u16::from_be_bytes([msg[i], msg[i + 1]]) as usize;
+    let questions = count(4);
+    let records = count(6) + count(8) + count(10);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+    let first_record = pos;
+
+    // SRV first: the A record may come before it in the packet
+    for pass in 0..2 {
+        pos = first_record;
+        for _ in 0..records {
+            let mut name = Name::new();
+            pos = read_name(msg, pos, &mut name)?;
+            let header = msg.get(pos..pos + 10)?;
+            let kind = u16::from_be_bytes([header[0], header[1]]);
+            let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+            let rdata = pos + 10;
+            pos = rdata + rdlen;
+            if pos > msg.len() {
+                return None;
+            }
+
+            if pass == 0 && kind == TYPE_SRV && rdlen > 6 && name.ends_with(SERVICE) {
+                let port = u16::from_be_bytes([msg[rdata + 4], msg[rdata + 5]]);
+                let mut target = Name::new();
+                read_name(msg, rdata + 6, &mut target)?;
+                if service.is_none() {
+                    *service = Some((target, port));
+                }
+            }
+            if pass == 1 && kind == TYPE_A && rdlen == 4 {
+                if let Some((ref target, port)) = *service {
+                    if name == *target {
+                        return Some(([msg[rdata], msg[rdata + 1], msg[rdata + 2], msg[rdata + 3]], port));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Decode the (possibly compressed) name at `pos` into `out`, lowercased and
+/// dot-separated; the position after the name
+fn read_name(msg: &[u8], mut pos: usize, out: &mut Name) -> Option<usize> {
+    let mut end = None;
+    // Bounds compression pointer loops
+    for _ in 0..64 {
+        let len = *msg.get(pos)? as usize;
+        if len & 0xC0 == 0xC0 {
+            end = end.or(Some(pos + 2));
+            pos = ((len & 0x3F) << 8) | *msg.get(pos + 1)? as usize;
+            continue;
+        }
+        if len == 0 {
+            return Some(end.unwrap_or(pos + 1));
+        }
+        if !out.is_empty() {
+            out.push('.').ok()?;
+        }
+        for &c in msg.get(pos + 1..pos + 1 + len)? {
+            out.push(c.to_ascii_lowercase() as char).ok()?;
+        }
+        pos += 1 + len;
+    }
+    None
+}
+
+fn skip_name(msg: &[u8], pos: usize) -> Option<usize> {
+    read_name(msg, pos, &mut Name::new())
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
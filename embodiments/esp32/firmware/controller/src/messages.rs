@@ -0,0 +1,308 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Sensory frames and motor commands as typed JSON lines (serde-json-core)
+//!
+//! Board → host, one line per burst:
+//!
+//! `{"np":[[id,pot],...],"ao":[[id,val],...],"fb":[[id,pos],...],"ec":[[id,n],...],"od":{...},"wl":[[id,val],...],"tc":T,"cpu":L,"kf":K,"t":T,"f":N}`
+//!
+//! with the board's `"id"` put in front on the way out (see device_id.rs).
+//! A channel mapped to cortical coordinates is named `["area",x,y,z]` instead
+//! of by a flat neuron ID: `[["iimu00",0,0,0],0.5]` (see cortical.rs).
+//!
+//! Everything after `np` is optional and left out when there's nothing to
+//! report (see [`SensoryFrame`]). Values in 0.0-1.0 are sent with three
+//! decimals, lengths, angles and temperatures with one. Sensory potentials
+//! keep their full 0.0-1.0 resolution from protocol version 3 on; older hosts
+//! get them thresholded to 0 or 1 (see version.rs).
+//!
+//! Host → board, either one command or a batch:
+//!
+//! `{"neuron_id":N,"value":V}` (or `{"id":N,"v":V}`), `{"mc":[[N,V],...]}`,
+//! `{"cm":[["area",x,y,z,V],...]}`
+//!
+//! with an optional `"seq":S` to have it acknowledged (see motor_ack.rs) and
+//! an optional `"to":"<device_id>"` for one board of several on a shared link.
+//!
+//! Other keys are ignored, so a motor command can share a line with the other
+//! host messages; a line that isn't valid JSON of this shape commands nothing.
+//! A batch is applied in one pass, in order (a neuron listed twice ends at its
+//! last value); pairs beyond MAX_MOTOR_NEURONS are dropped rather than failing
+//! the whole batch.
+
+use core::fmt;
+
+use heapless::{String, Vec};
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::cortical;
+use crate::device_id::DeviceId;
+use crate::odometry::OdometryFrame;
+use crate::{FRAME_CAPACITY, MAX_FEEDBACK_CHANNELS, MAX_MOTOR_NEURONS, MAX_OUTPUT_CHANNELS, MAX_SENSORY_CHANNELS};
+
+/// Most interrupt-driven inputs (see edges.rs)
+const MAX_EDGE_INPUTS: usize = 16;
+
+/// A 0.0-1.0 value, sent clamped with three decimals
+#[derive(Debug, Clone, Copy)]
+pub struct Unit(pub f32);
+
+impl Serialize for Unit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f32(rounded(self.0.clamp(0.0, 1.0), 1000.0))
+    }
+}
+
+/// A signed value, sent with one decimal
+#[derive(Debug, Clone, Copy)]
+pub struct Tenths(pub f32);
+
+impl Serialize for Tenths {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f32(rounded(self.0, 10.0))
+    }
+}
+
+/// `v` rounded to 1/`scale`, so it prints as e.g. 0.333 rather than
+/// 0.33333334
+fn rounded(v: f32, scale: f32) -> f32 {
+    let magnitude = ((if v < 0.0 { -v } else { v }) * scale + 0.5) as u32 as f32 / scale;
+    if v < 0.0 && magnitude > 0.0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// A neuron ID, sent as the number or, for a voxel, as `["area",x,y,z]`
+#[derive(Debug, Clone, Copy)]
+pub struct Neuron(pub u32);
+
+impl Serialize for Neuron {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match cortical::voxel(self.0) {
+            Some(v) => (v.area, v.x, v.y, v.z).serialize(serializer),
+            None => serializer.serialize_u32(self.0),
+        }
+    }
+}
+
+/// A sensory potential
+#[derive(Debug, Clone, Copy)]
+pub enum Potential {
+    /// 0.0-1.0, sent like a [`Unit`]
+    Analog(f32),
+    /// Thresholded, sent as 0 or 1 (protocol versions before 3)
+    Binary(bool),
+}
+
+impl Potential {
+    /// The potential as 0.0-1.0
+    pub fn value(&self) -> f32 {
+        match *self {
+            Potential::Analog(v) => v.clamp(0.0, 1.0),
+            Potential::Binary(on) => on as u8 as f32,
+        }
+    }
+}
+
+impl Serialize for Potential {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Potential::Analog(v) => Unit(v).serialize(serializer),
+            Potential::Binary(on) => serializer.serialize_u8(on as u8),
+        }
+    }
+}
+
+/// Pose and motion since the previous frame (see odometry.rs)
+#[derive(Debug, Serialize)]
+pub struct OdometryFields {
+    pub x: Tenths,
+    pub y: Tenths,
+    pub th: Tenths,
+    pub d: Tenths,
+    pub dth: Tenths,
+}
+
+impl From<OdometryFrame> for OdometryFields {
+    fn from(frame: OdometryFrame) -> Self {
+        Self {
+            x: Tenths(frame.x_mm),
+            y: Tenths(frame.y_mm),
+            th: Tenths(frame.heading_deg),
+            d: Tenths(frame.distance_mm),
+            dth: Tenths(frame.turn_deg),
+        }
+    }
+}
+
+/// One burst's sensory frame
+#[derive(Debug, Serialize)]
+pub struct SensoryFrame {
+    /// (neuron_id, potential) of every sensory channel
+    pub np: Vec<(Neuron, Potential), MAX_SENSORY_CHANNELS>,
+    /// Values applied to the outputs, when output echo is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ao: Option<Vec<(Neuron, Unit), MAX_OUTPUT_CHANNELS>>,
+    /// Measured servo positions, when feedback pins are configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fb: Option<Vec<(Neuron, Unit), MAX_FEEDBACK_CHANNELS>>,
+    /// Edges counted since the previous frame on interrupt-driven inputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ec: Option<Vec<(Neuron, u32), MAX_EDGE_INPUTS>>,
+    /// Wheel odometry, when configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub od: Option<OdometryFields>,
+    /// WiFi link quality, once per second with link telemetry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wl: Option<[(Neuron, Unit); 3]>,
+    /// Chip temperature (°C) and loop load, once per second with board
+    /// health telemetry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tc: Option<Tenths>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<Unit>,
+    /// With delta reporting: 1 for a keyframe (every channel), 0 for a
+    /// frame of just the channels that changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kf: Option<u8>,
+    /// Sample time (Unix ms), once SNTP time sync has completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t: Option<u64>,
+    /// Burst number
+    pub f: u64,
+}
+
+impl SensoryFrame {
+    /// A frame with just the sensory channels, for the optional parts to
+    /// be filled in; without `full_resolution` potentials are thresholded
+    /// at 0.5
+    pub fn new(sensory_data: &[(u32, f32)], frame_number: u64, full_resolution: bool) -> Self {
+        let potential = |pot: f32| if full_resolution { Potential::Analog(pot) } else { Potential::Binary(pot > 0.5) };
+        Self {
+            np: sensory_data.iter().map(|&(id, pot)| (Neuron(id), potential(pot))).collect(),
+            ao: None,
+            fb: None,
+            ec: None,
+            od: None,
+            wl: None,
+            tc: None,
+            cpu: None,
+            kf: None,
+            t: None,
+            f: frame_number,
+        }
+    }
+
+    /// The frame as a newline-terminated line; None if it doesn't fit
+    /// FRAME_CAPACITY
+    pub fn to_line(&self) -> Option<String<FRAME_CAPACITY>> {
+        let mut line: String<FRAME_CAPACITY> = serde_json_core::to_string(self).ok()?;
+        line.push('\n').ok()?;
+        Some(line)
+    }
+}
+
+/// Motor commands of one host line
+#[derive(Debug, Default, Deserialize)]
+pub struct MotorMessage {
+    /// Batch: [[neuron_id, value], ...]
+    #[serde(default, deserialize_with = "batch")]
+    mc: Option<Vec<(u32, f32), MAX_MOTOR_NEURONS>>,
+    /// Batch by cortical coordinates: [["area", x, y, z, value], ...]
+    #[serde(default, deserialize_with = "voxel_batch")]
+    cm: Option<Vec<(u32, f32), MAX_MOTOR_NEURONS>>,
+    /// Single command
+    #[serde(alias = "id")]
+    neuron_id: Option<u32>,
+    #[serde(alias = "v")]
+    value: Option<f32>,
+    /// Sequence number, for a `motor_ack` answer
+    seq: Option<u32>,
+    /// Device ID of the board meant; every board when left out
+    to: Option<DeviceId>,
+}
+
+impl MotorMessage {
+    /// Parse a host line; a line of another shape gives no commands, one
+    /// that isn't JSON (or has a mistyped field) None
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json_core::from_str(line).ok().map(|(message, _)| message)
+    }
+
+    /// (neuron_id, value) of every command in the line
+    pub fn commands(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        let single = self.neuron_id.zip(self.value);
+        self.mc.iter().flatten().chain(self.cm.iter().flatten()).copied().chain(single)
+    }
+
+    /// The line's sequence number, if it asks to be acknowledged
+    pub fn seq(&self) -> Option<u32> {
+        self.seq
+    }
+
+    /// Is the line meant for the board with this device ID?
+    pub fn is_for(&self, device_id: &str) -> bool {
+        self.to.as_ref().map_or(true, |to| to == device_id)
+    }
+}
+
+/// A `[[neuron_id, value], ...]` batch, keeping the pairs that fit
+///
+/// FEAGI may activate more OPU neurons in a burst than this board drives
+/// (e.g. another board's), which must not cost the pairs that do fit.
+fn batch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<(u32, f32), MAX_MOTOR_NEURONS>>, D::Error> {
+    struct Batch;
+
+    impl<'de> Visitor<'de> for Batch {
+        type Value = Vec<(u32, f32), MAX_MOTOR_NEURONS>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an array of [neuron_id, value] pairs")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut pairs: A) -> Result<Self::Value, A::Error> {
+            let mut batch = Vec::new();
+            while let Some(pair) = pairs.next_element::<(u32, f32)>()? {
+                let _ = batch.push(pair);
+            }
+            Ok(batch)
+        }
+    }
+
+    deserializer.deserialize_seq(Batch).map(Some)
+}
+
+/// A `[["area", x, y, z, value], ...]` batch as (neuron_id, value) pairs,
+/// leaving out voxels of areas this board doesn't map and the pairs that
+/// don't fit
+fn voxel_batch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<(u32, f32), MAX_MOTOR_NEURONS>>, D::Error> {
+    struct VoxelBatch;
+
+    impl<'de> Visitor<'de> for VoxelBatch {
+        type Value = Vec<(u32, f32), MAX_MOTOR_NEURONS>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an array of [area, x, y, z, value] entries")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut entries: A) -> Result<Self::Value, A::Error> {
+            let mut batch = Vec::new();
+            while let Some((area, x, y, z, value)) = entries.next_element::<(&str, u32, u32, u32, f32)>()? {
+                if let Some(neuron_id) = cortical::pack(area, x, y, z) {
+                    let _ = batch.push((neuron_id, value));
+                }
+            }
+            Ok(batch)
+        }
+    }
+
+    deserializer.deserialize_seq(VoxelBatch).map(Some)
+}
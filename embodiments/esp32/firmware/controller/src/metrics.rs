@@ -0,0 +1,130 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Runtime metrics: burst timing, heap and receive-path counters
+//!
+//! Always kept, and shown by the status server's `GET /status`. With
+//! `telemetry.metrics` in config.json the board also sends them to the host
+//! every `interval_ms`:
+//!
+//! `{"metrics":{"period_us":10012,"jitter_us":850,"heap_free":112344,"heap_min":98720,
+//! "frames_sent":500,"frames_received":498,"rx_dropped":0,"parse_errors":0}}`
+//!
+//! `period_us` is the mean start-to-start time of the bursts in the last
+//! interval and `jitter_us` the largest difference between one of them and
+//! the configured burst period. The heap figures are ESP-IDF's free and
+//! lowest-ever free bytes; the counters run since boot.
+
+use core::fmt::Write;
+
+use esp_idf_svc::sys;
+use heapless::String;
+
+/// Metrics reporting (from config.json `telemetry.metrics`)
+#[derive(Debug, Clone, Copy)]
+pub struct MetricsConfig {
+    pub interval_ms: u32,
+}
+
+/// Window of the period statistics when they're only shown in /status
+const STATUS_WINDOW_MS: u32 = 1000;
+
+/// Longest `{"metrics":{...}}` line
+pub const METRICS_LINE_CAPACITY: usize = 256;
+
+/// Burst timing and receive-path counters of the burst loop
+pub struct Metrics {
+    target_us: i64,
+    window_us: i64,
+    window_start_us: i64,
+    last_burst_us: Option<i64>,
+    bursts: u32,
+    period_sum_us: i64,
+    jitter_max_us: i64,
+    /// Mean period and largest deviation of the last complete window
+    pub period_us: u32,
+    pub jitter_us: u32,
+    /// Host lines and binary packets received
+    pub frames_received: u32,
+    /// Received bytes thrown away: a line longer than the RX buffer
+    pub rx_dropped: u32,
+    /// Host lines that weren't valid JSON
+    pub parse_errors: u32,
+}
+
+impl Metrics {
+    /// `period_us` is the configured burst period
+    pub fn new(config: Option<&MetricsConfig>, period_us: i64, now_us: i64) -> Self {
+        let window_ms = config.map_or(STATUS_WINDOW_MS, |c| c.interval_ms);
+        Self {
+            target_us: period_us,
+            window_us: window_ms as i64 * 1000,
+            window_start_us: now_us,
+            last_burst_us: None,
+            bursts: 0,
+            period_sum_us: 0,
+            jitter_max_us: 0,
+            period_us: 0,
+            jitter_us: 0,
+            frames_received: 0,
+            rx_dropped: 0,
+            parse_errors: 0,
+        }
+    }
+
+    /// Mark the start of a burst; true when this closed a window
+    pub fn burst(&mut self, now_us: i64) -> bool {
+        if let Some(last) = self.last_burst_us {
+            let period = now_us - last;
+            self.bursts += 1;
+            self.period_sum_us += period;
+            self.jitter_max_us = self.jitter_max_us.max((period - self.target_us).abs());
+        }
+        self.last_burst_us = Some(now_us);
+        if now_us - self.window_start_us < self.window_us {
+            return false;
+        }
+        self.period_us = if self.bursts == 0 { 0 } else { (self.period_sum_us / self.bursts as i64) as u32 };
+        self.jitter_us = self.jitter_max_us.min(u32::MAX as i64) as u32;
+        self.window_start_us = now_us;
+        self.bursts = 0;
+        self.period_sum_us = 0;
+        self.jitter_max_us = 0;
+        true
+    }
+
+    /// Skip the next period (after a light sleep), so the time asleep
+    /// doesn't count as jitter
+    pub fn resume(&mut self) {
+        self.last_burst_us = None;
+    }
+
+    /// `{"metrics":{...}}\n` line for the host
+    pub fn line(&self, frames_sent: u32) -> String<METRICS_LINE_CAPACITY> {
+        let (heap_free, heap_min) = heap();
+        let mut line: String<METRICS_LINE_CAPACITY> = String::new();
+        let _ = write!(
+            line,
+            "{{\"metrics\":{{\"period_us\":{},\"jitter_us\":{},\"heap_free\":{},\"heap_min\":{},\
+             \"frames_sent\":{},\"frames_received\":{},\"rx_dropped\":{},\"parse_errors\":{}}}}}\n",
+            self.period_us,
+            self.jitter_us,
+            heap_free,
+            heap_min,
+            frames_sent,
+            self.frames_received,
+            self.rx_dropped,
+            self.parse_errors,
+        );
+        line
+    }
+}
+
+/// Free and lowest-ever free heap bytes
+pub fn heap() -> (u32, u32) {
+    unsafe { (sys::esp_get_free_heap_size(), sys::esp_get_minimum_free_heap_size()) }
+}
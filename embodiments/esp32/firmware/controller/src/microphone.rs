@@ -0,0 +1,293 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! I2S MEMS microphone (INMP441, SPH0645, ICS-43434) as an auditory input
+//!
+//! With config.json `microphone`, the board clocks the microphone as I2S
+//! master and collects its samples by DMA between bursts. Every burst the
+//! samples that arrived since the previous one are turned into a sound level
+//! and, optionally, the levels of a few frequency bands:
+//!
+//! | Neuron            | Value                                             |
+//! |-------------------|---------------------------------------------------|
+//! | N                 | Sound level (RMS of the burst's samples)          |
+//! | N + 1 to N + b    | Band levels, low to high (128-point FFT of the    |
+//! |                   | latest samples, log-spaced bands up to Nyquist)   |
+//!
+//! Levels are in dB relative to a full-scale sine, mapped from `floor_db`
+//! (0.0) to 0 dB (1.0). The DC offset these microphones have is removed by
+//! a high-pass filter first.
+
+use core::ffi::c_void;
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::audio::{AudioConfig, AudioDriver};
+use crate::cortical;
+use crate::{AUDIO_OUTPUT, CAMERA};
+
+extern "C" {
+    // newlib's single-precision math (no libm in no_std)
+    fn sinf(x: f32) -> f32;
+    fn cosf(x: f32) -> f32;
+    fn log10f(x: f32) -> f32;
+    fn powf(x: f32, y: f32) -> f32;
+}
+
+const PI: f32 = core::f32::consts::PI;
+
+/// FFT length; bin k covers k * sample_rate / FFT_SIZE Hz
+const FFT_SIZE: usize = 128;
+
+/// Most frequency bands
+pub const MAX_BANDS: usize = 8;
+
+/// Samples read from DMA per call
+const READ_CHUNK: usize = 256;
+
+/// High-pass filter coefficient (DC removal, ~10 Hz at 16 kHz)
+const DC_ALPHA: f32 = 0.996;
+
+/// I2S wiring and analysis settings (from config.json `microphone`)
+#[derive(Debug, Clone, Copy)]
+pub struct MicrophoneConfig {
+    /// Bit clock (SCK) and word select (WS) outputs, data (SD) input
+    pub sck: u32,
+    pub ws: u32,
+    pub sd: u32,
+    /// Microphone answering in the right slot (L/R pin tied high)
+    pub right_channel: bool,
+    pub sample_rate_hz: u32,
+    /// Frequency bands reported after the level (0 = level only)
+    pub bands: u32,
+    /// Level mapped to 0.0 (dB re full-scale sine, negative)
+    pub floor_db: f32,
+    /// "cortical_area:neuron_id" of the sound level
+    pub cortical_mapping: &'static str,
+}
+
+/// A running microphone
+pub struct Microphone {
+    config: MicrophoneConfig,
+    channel: sys::i2s_chan_handle_t,
+    first_neuron: u32,
+    /// High-pass filter state (previous input and output)
+    dc_in: f32,
+    dc_out: f32,
+    /// The latest FFT_SIZE samples, oldest at `history_at`
+    history: [f32; FFT_SIZE],
+    history_at: usize,
+    window: [f32; FFT_SIZE],
+    /// e^(-2πik/N), k < N/2
+    twiddles: [(f32, f32); FFT_SIZE / 2],
+    /// First FFT bin of each band, then one past the last band's
+    band_edges: [usize; MAX_BANDS + 1],
+    /// Last reported values, repeated when a burst brought no samples
+    values: [f32; MAX_BANDS + 1],
+}
+
+impl Microphone {
+    /// Start the I2S channel; None if the driver rejected the settings
+    pub fn new(config: &MicrophoneConfig) -> Option<Self> {
+        let first_neuron = cortical::neuron_id(config.cortical_mapping)?;
+        let channel = start_channel(config)?;
+
+        let mut window = [0.0; FFT_SIZE];
+        for (n, w) in window.iter_mut().enumerate() {
+            // Hann
+            *w = 0.5 - 0.5 * unsafe { cosf(2.0 * PI * n as f32 / (FFT_SIZE - 1) as f32) };
+        }
+        let mut twiddles = [(0.0, 0.0); FFT_SIZE / 2];
+        for (k, t) in twiddles.iter_mut().enumerate() {
+            let angle = 2.0 * PI * k as f32 / FFT_SIZE as f32;
+            *t = unsafe { (cosf(angle), -sinf(angle)) };
+        }
+        // Bins 1..=N/2 split into bands of equal ratio, at least a bin each
+        let bands = (config.bands as usize).min(MAX_BANDS);
+        let mut band_edges = [0; MAX_BANDS + 1];
+        band_edges[0] = 1;
+        for i in 1..=bands {
+            let edge = unsafe { powf((FFT_SIZE / 2) as f32, i as f32 / bands as f32) } as usize;
+            band_edges[i] = edge.max(band_edges[i - 1] + 1).min(FFT_SIZE / 2 + 1);
+        }
+        band_edges[bands] = FFT_SIZE / 2 + 1;
+
+        Some(Self {
+            config: *config,
+            channel,
+            first_neuron,
+            dc_in: 0.0,
+            dc_out: 0.0,
+            history: [0.0; FFT_SIZE],
+            history_at: 0,
+            window,
+            twiddles,
+            band_edges,
+            values: [0.0; MAX_BANDS + 1],
+        })
+    }
+
+    /// Analyse the samples since the previous call, returning
+    /// (neuron_id, value) for the level and each band
+    pub fn read(&mut self) -> Vec<(u32, f32), { MAX_BANDS + 1 }> {
+        let mut buf = [0i32; READ_CHUNK];
+        let mut count = 0u32;
+        let mut power = 0.0f32;
+        loop {
+            let mut bytes_read: usize = 0;
+            let err = unsafe {
+                sys::i2s_channel_read(
+                    self.channel,
+                    buf.as_mut_ptr() as *mut c_void,
+                    core::mem::size_of_val(&buf),
+                    &mut bytes_read,
+                    0,
+                )
+            };
+            let samples = bytes_read / core::mem::size_of::<i32>();
+            for &raw in &buf[..samples] {
+                // 24-bit samples, left-aligned in 32-bit slots
+                let x = (raw >> 8) as f32 / 8_388_608.0;
+                let y = x - self.dc_in + DC_ALPHA * self.dc_out;
+                self.dc_in = x;
+                self.dc_out = y;
+                power += y * y;
+                self.history[self.history_at] = y;
+                self.history_at = (self.history_at + 1) % FFT_SIZE;
+            }
+            count += samples as u32;
+            if err != sys::ESP_OK || samples < READ_CHUNK {
+                break;
+            }
+        }
+
+        let bands = (self.config.bands as usize).min(MAX_BANDS);
+        if count > 0 {
+            // A full-scale sine has mean power 1/2
+            self.values[0] = self.scale_db(power / count as f32 * 2.0);
+            if bands > 0 {
+                self.analyse_bands(bands);
+            }
+        }
+        let mut readings = Vec::new();
+        for (i, value) in self.values[..=bands].iter().enumerate() {
+            let _ = readings.push((self.first_neuron + i as u32, *value));
+        }
+        readings
+    }
+
+    /// Band levels from an FFT of the latest FFT_SIZE samples
+    fn analyse_bands(&mut self, bands: usize) {
+        let mut re = [0.0f32; FFT_SIZE];
+        let mut im = [0.0f32; FFT_SIZE];
+        for (n, value) in re.iter_mut().enumerate() {
+            *value = self.history[(self.history_at + n) % FFT_SIZE] * self.window[n];
+        }
+        fft(&mut re, &mut im, &self.twiddles);
+        // A full-scale sine peaks at N/4 with the Hann window
+        let full_scale = (FFT_SIZE / 4) as f32 * (FFT_SIZE / 4) as f32;
+        for band in 0..bands {
+            let power: f32 = (self.band_edges[band]..self.band_edges[band + 1])
+                .filter(|&k| k < FFT_SIZE / 2)
+                .map(|k| re[k] * re[k] + im[k] * im[k])
+                .sum();
+            self.values[band + 1] = self.scale_db(power / full_scale);
+        }
+    }
+
+    /// Power relative to full scale, as 0.0 (`floor_db`) to 1.0 (0 dB)
+    fn scale_db(&self, power: f32) -> f32 {
+        if power <= 0.0 {
+            return 0.0;
+        }
+        let db = 10.0 * unsafe { log10f(power) };
+        (1.0 - db / self.config.floor_db).clamp(0.0, 1.0)
+    }
+}
+
+/// Create, configure and enable the I2S RX channel (master, Philips)
+fn start_channel(config: &MicrophoneConfig) -> Option<sys::i2s_chan_handle_t> {
+    let mut channel: sys::i2s_chan_handle_t = core::ptr::null_mut();
+    unsafe {
+        let mut chan_cfg: sys::i2s_chan_config_t = core::mem::zeroed();
+        // The camera driver drives I2S0 directly on the ESP32, and so does
+        // the DAC audio output
+        let i2s0_taken = CAMERA.is_some()
+            || matches!(AUDIO_OUTPUT, Some(AudioConfig { driver: AudioDriver::Dac { .. }, .. }));
+        chan_cfg.id = if i2s0_taken { 1 } else { sys::i2s_port_t_I2S_NUM_0 };
+        chan_cfg.role = sys::i2s_role_t_I2S_ROLE_MASTER;
+        // 4 x 256 frames: 64 ms at 16 kHz between reads before samples drop
+        chan_cfg.dma_desc_num = 4;
+        chan_cfg.dma_frame_num = 256;
+        if sys::i2s_new_channel(&chan_cfg, core::ptr::null_mut(), &mut channel) != sys::ESP_OK {
+            return None;
+        }
+
+        let mut std_cfg: sys::i2s_std_config_t = core::mem::zeroed();
+        std_cfg.clk_cfg.sample_rate_hz = config.sample_rate_hz;
+        std_cfg.clk_cfg.clk_src = sys::soc_periph_i2s_clk_src_t_I2S_CLK_SRC_DEFAULT;
+        std_cfg.clk_cfg.mclk_multiple = sys::i2s_mclk_multiple_t_I2S_MCLK_MULTIPLE_256;
+        std_cfg.slot_cfg.data_bit_width = sys::i2s_data_bit_width_t_I2S_DATA_BIT_WIDTH_32BIT;
+        std_cfg.slot_cfg.slot_bit_width = sys::i2s_slot_bit_width_t_I2S_SLOT_BIT_WIDTH_AUTO;
+        std_cfg.slot_cfg.slot_mode = sys::i2s_slot_mode_t_I2S_SLOT_MODE_MONO;
+        std_cfg.slot_cfg.slot_mask = if config.right_channel {
+            sys::i2s_std_slot_mask_t_I2S_STD_SLOT_RIGHT
+        } else {
+            sys::i2s_std_slot_mask_t_I2S_STD_SLOT_LEFT
+        };
+        std_cfg.slot_cfg.ws_width = 32;
+        std_cfg.slot_cfg.bit_shift = true;
+        std_cfg.gpio_cfg.mclk = -1;
+        std_cfg.gpio_cfg.bclk = config.sck as i32;
+        std_cfg.gpio_cfg.ws = config.ws as i32;
+        std_cfg.gpio_cfg.dout = -1;
+        std_cfg.gpio_cfg.din = config.sd as i32;
+        if sys::i2s_channel_init_std_mode(channel, &std_cfg) != sys::ESP_OK
+            || sys::i2s_channel_enable(channel) != sys::ESP_OK
+        {
+            sys::i2s_del_channel(channel);
+            return None;
+        }
+    }
+    Some(channel)
+}
+
+/// In-place radix-2 FFT
+fn fft(re: &mut [f32; FFT_SIZE], im: &mut [f32; FFT_SIZE], twiddles: &[(f32, f32); FFT_SIZE / 2]) {
+    // Bit-reversed order
+    let mut j = 0;
+    for i in 1..FFT_SIZE {
+        let mut bit = FFT_SIZE >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= FFT_SIZE {
+        let step = FFT_SIZE / len;
+        for start in (0..FFT_SIZE).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = twiddles[k * step];
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * wr - im[b] * wi;
+                let ti = re[b] * wi + im[b] * wr;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
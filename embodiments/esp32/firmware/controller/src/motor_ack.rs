@@ -0,0 +1,96 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Acknowledged motor commands (reliable mode)
+//!
+//! A motor message may carry a sequence number: `"seq"` on a JSON line
+//! (`{"seq":7,"mc":[[1,0.5],[2,0.0]]}`) or a 0x11 packet with the binary
+//! wire format (see protocol.rs). Every sequenced message is answered by
+//!
+//! `{"motor_ack":{"seq":7,"ok":true,"n":2}}` or
+//! `{"motor_ack":{"seq":7,"ok":false,"error":"safe_stop"}}`
+//!
+//! so host tooling can tell a lost message (no answer) from a rejected one.
+//! `n` is the number of commands accepted. Errors:
+//!
+//! - `safe_stop`: motor commands are held until resumed
+//! - `mode`: the session is in raw mode
+//! - `protocol`: the host's protocol version was refused (see version.rs)
+//! - `stale`: older than the newest sequence number applied, so it isn't
+//!   applied over newer commands
+//!
+//! Repeating the newest sequence number (a retry after a lost ack) is
+//! acknowledged again with `n` 0, without applying the commands twice.
+//! Sequence numbers are u32 and compared with wrap-around; every hello
+//! starts them over. Messages without one are applied unanswered, as before.
+
+use heapless::String;
+
+use crate::u32_to_string;
+
+// Shared with the binary wire format's decoder
+pub use feagi_controller_core::protocol::{Sequenced, MAX_SEQUENCED};
+
+/// What to do with a sequenced message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// Newer than anything applied so far
+    Apply,
+    /// The newest one again: acknowledge, don't apply
+    Repeat,
+    /// Older than the newest one: reject
+    Stale,
+}
+
+/// Newest sequence number applied
+pub struct MotorAcks {
+    newest: Option<u32>,
+}
+
+impl MotorAcks {
+    pub const fn new() -> Self {
+        Self { newest: None }
+    }
+
+    /// Check `seq` against the newest applied one, taking it as the newest
+    /// when it is to be applied
+    pub fn accept(&mut self, seq: u32) -> Verdict {
+        let verdict = match self.newest {
+            Some(newest) if seq == newest => Verdict::Repeat,
+            Some(newest) if (seq.wrapping_sub(newest) as i32) < 0 => Verdict::Stale,
+            _ => Verdict::Apply,
+        };
+        if verdict == Verdict::Apply {
+            self.newest = Some(seq);
+        }
+        verdict
+    }
+}
+
+/// `{"motor_ack":{...}}` line answering message `seq`, with the number of
+/// commands accepted or why the message was rejected
+pub fn ack_line(seq: u32, result: Result<usize, &'static str>) -> String<80> {
+    let mut line: String<80> = String::new();
+    let _ = line.push_str("{\"motor_ack\":{\"seq\":");
+    let mut number: String<16> = String::new();
+    u32_to_string(seq, &mut number);
+    let _ = line.push_str(&number);
+    match result {
+        Ok(n) => {
+            u32_to_string(n as u32, &mut number);
+            let _ = line.push_str(",\"ok\":true,\"n\":");
+            let _ = line.push_str(&number);
+        }
+        Err(reason) => {
+            let _ = line.push_str(",\"ok\":false,\"error\":\"");
+            let _ = line.push_str(reason);
+            let _ = line.push_str("\"");
+        }
+    }
+    let _ = line.push_str("}}\n");
+    line
+}
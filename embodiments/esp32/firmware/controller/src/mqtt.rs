@@ -0,0 +1,367 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! MQTT client transport (MQTT 3.1.1) for fleet deployments
+//!
+//! With `"transport": {"type": "mqtt", ...}` the board connects to a broker
+//! instead of to FEAGI directly. Every line it sends (hello, sensory frames,
+//! replies) is published to `feagi/<device_id>/sensory`, and every message on
+//! `feagi/<device_id>/motor` is read as one line, so the protocol on top is
+//! the same as over serial or TCP.
+//!
+//! The status topic holds a retained `online` while the board is connected;
+//! its last will replaces that with `offline` whenever the connection drops
+//! without a DISCONNECT, which is every way this client goes away. PINGREQs
+//! go out every half keep-alive interval, and silence for 1.5 intervals makes
+//! `read` fail so the transport supervisor reconnects (clean session,
+//! resubscribing).
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::transport::{FeagiTransport, TcpStream, TlsConfig, TransportStatus};
+use crate::RX_LINE_CAPACITY;
+
+const PACKET_CONNECT: u8 = 0x10;
+const PACKET_CONNACK: u8 = 0x20;
+const PACKET_PUBLISH: u8 = 0x30;
+const PACKET_PUBACK: u8 = 0x40;
+/// SUBSCRIBE carries mandatory flags 0b0010
+const PACKET_SUBSCRIBE: u8 = 0x82;
+const PACKET_SUBACK: u8 = 0x90;
+const PACKET_PINGREQ: u8 = 0xC0;
+
+/// CONNECT flags
+const CONNECT_CLEAN_SESSION: u8 = 0x02;
+const CONNECT_WILL: u8 = 0x04;
+const CONNECT_WILL_RETAIN: u8 = 0x20;
+const CONNECT_PASSWORD: u8 = 0x40;
+const CONNECT_USERNAME: u8 = 0x80;
+
+/// SUBACK return code for a refused subscription
+const SUBACK_FAILURE: u8 = 0x80;
+
+/// Longest fixed header: type byte + 4-byte remaining length
+const MAX_HEADER_LEN: usize = 5;
+
+/// Topic length limit (enforced by build.rs)
+const MAX_TOPIC_LEN: usize = 128;
+
+/// Raw bytes buffered until a whole packet has arrived: a full line behind
+/// the fixed header, topic and packet id
+const RX_CAPACITY: usize = RX_LINE_CAPACITY + MAX_HEADER_LEN + 2 + MAX_TOPIC_LEN + 2;
+
+/// CONNECT and topic headers (build.rs caps ids, topics and credentials)
+const CONTROL_CAPACITY: usize = 512;
+
+/// Broker session settings (from config.json `transport.config`)
+#[derive(Debug, Clone, Copy)]
+pub struct MqttConfig {
+    pub client_id: &'static str,
+    /// `feagi/<device_id>/sensory`: everything the board sends
+    pub sensory_topic: &'static str,
+    /// `feagi/<device_id>/motor`: everything the board reads
+    pub motor_topic: &'static str,
+    /// Retained `online`, last will `offline`
+    pub status_topic: &'static str,
+    /// 0 or 1, for publishes, the subscription and the will
+    pub qos: u8,
+    /// Seconds; 0 disables keep-alive
+    pub keep_alive_s: u16,
+    pub username: Option<&'static str>,
+    pub password: Option<&'static str>,
+}
+
+/// A connected, subscribed MQTT session
+pub struct MqttClient {
+    stream: TcpStream,
+    config: MqttConfig,
+    /// Received bytes; the first `ready` are message payload not yet read
+    rx: Vec<u8, RX_CAPACITY>,
+    ready: usize,
+    /// The message ends after the ready bytes: terminate the line
+    end_of_message: bool,
+    next_packet_id: u16,
+    last_ping_ms: u32,
+    last_rx_ms: u32,
+}
+
+impl MqttClient {
+    /// Connect to the broker at `host:port` (over TLS if `tls` is set),
+    /// waiting up to `timeout_ms` for CONNACK, then subscribe to the motor
+    /// topic and announce `online`
+    pub fn connect(host: [u8; 4], port: u16, tls: Option<&TlsConfig>, config: MqttConfig, timeout_ms: u32) -> Option<Self> {
+        let stream = TcpStream::open(host, port, tls, timeout_ms)?;
+        let now = now_ms();
+        let mut client = Self {
+            stream,
+            config,
+            rx: Vec::new(),
+            ready: 0,
+            end_of_message: false,
+            next_packet_id: 1,
+            last_ping_ms: now,
+            last_rx_ms: now,
+        };
+
+        let mut flags = CONNECT_CLEAN_SESSION | CONNECT_WILL | CONNECT_WILL_RETAIN | (config.qos << 3);
+        if config.username.is_some() {
+            flags |= CONNECT_USERNAME;
+        }
+        if config.password.is_some() {
+            flags |= CONNECT_PASSWORD;
+        }
+        let mut connect: Vec<u8, CONTROL_CAPACITY> = Vec::new();
+        push_string(&mut connect, "MQTT")?;
+        connect.push(4).ok()?; // protocol level 3.1.1
+        connect.push(flags).ok()?;
+        connect.extend_from_slice(&config.keep_alive_s.to_be_bytes()).ok()?;
+        push_string(&mut connect, config.client_id)?;
+        push_string(&mut connect, config.status_topic)?;
+        push_string(&mut connect, "offline")?;
+        if let Some(username) = config.username {
+            push_string(&mut connect, username)?;
+        }
+        if let Some(password) = config.password {
+            push_string(&mut connect, password)?;
+        }
+        if !client.send_packet(PACKET_CONNECT, &[&connect]) {
+            return None;
+        }
+
+        // CONNACK is always the broker's first packet: 0x20, 2, flags, return code
+        while client.rx.len() < 4 {
+            if now_ms().wrapping_sub(now) > timeout_ms {
+                return None;
+            }
+            let mut chunk = [0u8; 4];
+            let n = client.stream.read(&mut chunk[..4 - client.rx.len()], 100).ok()?;
+            client.rx.extend_from_slice(&chunk[..n]).ok()?;
+        }
+        if client.rx[..2] != [PACKET_CONNACK, 2] || client.rx[3] != 0 {
+            return None;
+        }
+        client.rx.clear();
+
+        // The SUBACK is checked in drain(): retained motor messages may come first
+        let mut subscribe: Vec<u8, CONTROL_CAPACITY> = Vec::new();
+        subscribe.extend_from_slice(&client.packet_id().to_be_bytes()).ok()?;
+        push_string(&mut subscribe, config.motor_topic)?;
+        subscribe.push(config.qos).ok()?;
+        if !client.send_packet(PACKET_SUBSCRIBE, &[&subscribe]) || !client.publish(config.status_topic, b"online", true) {
+            return None;
+        }
+        Some(client)
+    }
+}
+
+impl FeagiTransport for MqttClient {
+    /// Publish one line to the sensory topic
+    fn send_frame(&mut self, data: &[u8]) -> bool {
+        let line = data.strip_suffix(b"\n").unwrap_or(data);
+        self.publish(self.config.sensory_topic, line, false)
+    }
+
+    /// Read motor topic messages, newline-terminated per message; Ok(0) on
+    /// timeout, Err once the broker closed the connection or went silent
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        let now = now_ms();
+        let keep_alive_ms = self.config.keep_alive_s as u32 * 1000;
+        if keep_alive_ms > 0 {
+            if now.wrapping_sub(self.last_rx_ms) > keep_alive_ms + keep_alive_ms / 2 {
+                return Err(());
+            }
+            if now.wrapping_sub(self.last_ping_ms) >= keep_alive_ms / 2 {
+                if !self.send_packet(PACKET_PINGREQ, &[]) {
+                    return Err(());
+                }
+                self.last_ping_ms = now;
+            }
+        }
+
+        // Hand out what's already buffered before waiting on the socket
+        let n = self.drain(buf)?;
+        if n > 0 {
+            return Ok(n);
+        }
+        let mut chunk = [0u8; 256];
+        let spare = (RX_CAPACITY - self.rx.len()).min(chunk.len());
+        let received = self.stream.read(&mut chunk[..spare], timeout_ms)?;
+        if received > 0 {
+            self.last_rx_ms = now_ms();
+            let _ = self.rx.extend_from_slice(&chunk[..received]);
+        }
+        self.drain(buf)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("mqtt")
+    }
+}
+
+impl MqttClient {
+    /// Move message payloads into `buf`, acknowledging packets on the way
+    fn drain(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut n = 0;
+        loop {
+            if self.ready > 0 {
+                let take = self.ready.min(buf.len() - n);
+                buf[n..n + take].copy_from_slice(&self.rx[..take]);
+                self.consume(take);
+                self.ready -= take;
+                n += take;
+                if self.ready > 0 {
+                    return Ok(n);
+                }
+            }
+            if self.end_of_message {
+                if n == buf.len() {
+                    return Ok(n);
+                }
+                buf[n] = b'\n';
+                n += 1;
+                self.end_of_message = false;
+            }
+
+            let Some((first, header_len, body_len)) = fixed_header(&self.rx)? else {
+                return Ok(n);
+            };
+            let packet_len = header_len + body_len;
+            if packet_len > RX_CAPACITY {
+                // Longer than any line the burst loop accepts
+                return Err(());
+            }
+            if packet_len > self.rx.len() {
+                return Ok(n);
+            }
+            let body = &self.rx[header_len..packet_len];
+
+            match first & 0xF0 {
+                PACKET_PUBLISH => {
+                    let qos = (first >> 1) & 0x03;
+                    let topic_len = u16::from_be_bytes([*body.first().ok_or(())?, *body.get(1).ok_or(())?]) as usize;
+                    let id_len = if qos > 0 { 2 } else { 0 };
+                    let payload_start = 2 + topic_len + id_len;
+                    if payload_start > body.len() {
+                        return Err(());
+                    }
+                    if qos > 0 {
+                        let id = [body[2 + topic_len], body[3 + topic_len]];
+                        if !self.send_packet(PACKET_PUBACK, &[&id]) {
+                            return Err(());
+                        }
+                    }
+                    let payload_len = body_len - payload_start;
+                    // Don't double the newline if the publisher already sent one
+                    self.end_of_message = self.rx[header_len + payload_start..packet_len].last() != Some(&b'\n');
+                    self.consume(header_len + payload_start);
+                    self.ready = payload_len;
+                }
+                PACKET_SUBACK => {
+                    // Packet id, then one return code for the one topic
+                    if body.get(2) == Some(&SUBACK_FAILURE) {
+                        return Err(());
+                    }
+                    self.consume(packet_len);
+                }
+                // PINGRESP, PUBACKs for our QoS 1 publishes: only last_rx_ms matters
+                _ => self.consume(packet_len),
+            }
+        }
+    }
+
+    /// Publish at the configured QoS; QoS 1 publishes are sent once and not
+    /// retried (a newer sensory frame supersedes a lost one)
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> bool {
+        let qos = self.config.qos;
+        let mut head: Vec<u8, CONTROL_CAPACITY> = Vec::new();
+        if push_string(&mut head, topic).is_none() {
+            return false;
+        }
+        if qos > 0 {
+            let id = self.packet_id();
+            let _ = head.extend_from_slice(&id.to_be_bytes());
+        }
+        self.send_packet(PACKET_PUBLISH | (qos << 1) | retain as u8, &[&head, payload])
+    }
+
+    /// Send a packet whose body is `parts` back to back
+    fn send_packet(&mut self, first: u8, parts: &[&[u8]]) -> bool {
+        let mut out: Vec<u8, 256> = Vec::new();
+        let _ = out.push(first);
+        // Remaining length: 7 bits per byte, least significant first
+        let mut len = parts.iter().map(|p| p.len()).sum::<usize>();
+        loop {
+            let byte = (len % 128) as u8;
+            len /= 128;
+            let _ = out.push(if len > 0 { byte | 0x80 } else { byte });
+            if len == 0 {
+                break;
+            }
+        }
+        // Copy through a small buffer so short packets go out in one segment
+        for part in parts {
+            let mut rest = *part;
+            while !rest.is_empty() {
+                if out.is_full() {
+                    if !self.stream.write(&out) {
+                        return false;
+                    }
+                    out.clear();
+                }
+                let n = rest.len().min(out.capacity() - out.len());
+                let _ = out.extend_from_slice(&rest[..n]);
+                rest = &rest[n..];
+            }
+        }
+        self.stream.write(&out)
+    }
+
+    /// Next non-zero packet identifier
+    fn packet_id(&mut self) -> u16 {
+        let id = self.next_packet_id;
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    /// Drop the first `n` buffered bytes
+    fn consume(&mut self, n: usize) {
+        let len = self.rx.len();
+        self.rx.copy_within(n..len, 0);
+        self.rx.truncate(len - n);
+    }
+}
+
+/// First byte, header length and body length of the packet at the start of
+/// `bytes`; Ok(None) until the whole fixed header arrived, Err if malformed
+fn fixed_header(bytes: &[u8]) -> Result<Option<(u8, usize, usize)>, ()> {
+    let Some(&first) = bytes.first() else {
+        return Ok(None);
+    };
+    let mut body_len = 0;
+    for (i, &byte) in bytes[1..].iter().take(4).enumerate() {
+        body_len |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((first, i + 2, body_len)));
+        }
+    }
+    if bytes.len() >= MAX_HEADER_LEN {
+        Err(())
+    } else {
+        Ok(None)
+    }
+}
+
+/// Append a length-prefixed UTF-8 string
+fn push_string<const N: usize>(out: &mut Vec<u8, N>, s: &str) -> Option<()> {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes()).ok()?;
+    out.extend_from_slice(s.as_bytes()).ok()
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Differential-drive wheel odometry from two encoders
+//!
+//! With config.json `odometry`, the counts of the left and right wheel
+//! encoders (`encoder_input` pins) are turned into travelled distance and
+//! heading change every burst, using the wheel diameter and the track width
+//! (distance between the wheels' contact points). The pose is integrated
+//! from boot, and each sensory frame carries
+//!
+//! `"od":{"x":X,"y":Y,"th":TH,"d":D,"dth":DTH}`
+//!
+//! - `x`, `y`: position in mm, starting at 0,0 facing along +x
+//! - `th`: heading in degrees, -180-180, counter-clockwise positive
+//! - `d`, `dth`: distance (mm) and heading change (degrees) since the
+//!   previous frame, so none is lost when frames are subsampled
+
+use crate::encoder::EncoderBank;
+use crate::GpioPinConfig;
+
+extern "C" {
+    // newlib's single-precision trigonometry (no libm in no_std)
+    fn sinf(x: f32) -> f32;
+    fn cosf(x: f32) -> f32;
+}
+
+const PI: f32 = core::f32::consts::PI;
+
+/// Wheel encoders and geometry (from config.json `odometry`)
+#[derive(Debug, Clone, Copy)]
+pub struct OdometryConfig {
+    /// `encoder_input` pins (A channels) of the left and right wheel
+    pub left_pin: u32,
+    pub right_pin: u32,
+    pub wheel_diameter_mm: f32,
+    pub track_width_mm: f32,
+    /// Negate a wheel's count, so forward motion counts up
+    pub invert_left: bool,
+    pub invert_right: bool,
+}
+
+/// One frame's odometry: pose plus motion since the previous frame
+#[derive(Debug, Clone, Copy)]
+pub struct OdometryFrame {
+    pub x_mm: f32,
+    pub y_mm: f32,
+    pub heading_deg: f32,
+    pub distance_mm: f32,
+    pub turn_deg: f32,
+}
+
+/// Pose integrated from the wheel encoders
+pub struct Odometry {
+    config: OdometryConfig,
+    left_mm_per_count: f32,
+    right_mm_per_count: f32,
+    last_left: i32,
+    last_right: i32,
+    x_mm: f32,
+    y_mm: f32,
+    /// Radians, kept within -π..π
+    heading: f32,
+    /// Motion since the last frame
+    distance_mm: f32,
+    turn: f32,
+}
+
+impl Odometry {
+    /// Start at the origin; None if either wheel's encoder isn't counting
+    pub fn new<const N: usize>(config: &OdometryConfig, gpio: &[GpioPinConfig], encoders: &EncoderBank<N>) -> Option<Self> {
+        let mm_per_count = |pin: u32| {
+            let encoder = gpio.iter().find(|g| g.pin == pin)?.encoder?;
+            Some(PI * config.wheel_diameter_mm / encoder.counts_per_rev.max(1) as f32)
+        };
+        Some(Self {
+            config: *config,
+            left_mm_per_count: mm_per_count(config.left_pin)?,
+            right_mm_per_count: mm_per_count(config.right_pin)?,
+            last_left: encoders.count(config.left_pin)?,
+            last_right: encoders.count(config.right_pin)?,
+            x_mm: 0.0,
+            y_mm: 0.0,
+            heading: 0.0,
+            distance_mm: 0.0,
+            turn: 0.0,
+        })
+    }
+
+    /// Integrate the wheel motion since the previous call; call every burst
+    pub fn update<const N: usize>(&mut self, encoders: &EncoderBank<N>) {
+        let (Some(left), Some(right)) = (encoders.count(self.config.left_pin), encoders.count(self.config.right_pin)) else {
+            return;
+        };
+        let mut left_counts = left.wrapping_sub(self.last_left) as f32;
+        let mut right_counts = right.wrapping_sub(self.last_right) as f32;
+        self.last_left = left;
+        self.last_right = right;
+        if self.config.invert_left {
+            left_counts = -left_counts;
+        }
+        if self.config.invert_right {
+            right_counts = -right_counts;
+        }
+
+        let left_mm = left_counts * self.left_mm_per_count;
+        let right_mm = right_counts * self.right_mm_per_count;
+        let distance = (left_mm + right_mm) / 2.0;
+        let turn = (right_mm - left_mm) / self.config.track_width_mm;
+        // Move along the mean heading of the interval
+        let mid = self.heading + turn / 2.0;
+        unsafe {
+            self.x_mm += distance * cosf(mid);
+            self.y_mm += distance * sinf(mid);
+        }
+        self.heading = wrap(self.heading + turn);
+        self.distance_mm += distance;
+        self.turn += turn;
+    }
+
+    /// The pose and the motion since the previous frame, which restarts
+    pub fn take_frame(&mut self) -> OdometryFrame {
+        let frame = OdometryFrame {
+            x_mm: self.x_mm,
+            y_mm: self.y_mm,
+            heading_deg: self.heading.to_degrees(),
+            distance_mm: self.distance_mm,
+            turn_deg: self.turn.to_degrees(),
+        };
+        self.distance_mm = 0.0;
+        self.turn = 0.0;
+        frame
+    }
+}
+
+/// Wrap an angle into -π..π
+fn wrap(mut angle: f32) -> f32 {
+    while angle > PI {
+        angle -= 2.0 * PI;
+    }
+    while angle < -PI {
+        angle += 2.0 * PI;
+    }
+    angle
+}
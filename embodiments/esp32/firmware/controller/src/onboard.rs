@@ -0,0 +1,149 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Sensors built into the chip, as sensory channels needing no wiring
+//!
+//! - Hall-effect sensor (original ESP32 only): magnetic field through the
+//!   chip, 0.5 = the field at boot, towards 0.0/1.0 as a magnet's south or
+//!   north pole approaches, reaching them at `hall_full_scale`.
+//! - Temperature sensor (ESP32-S2/S3/C3): the die temperature, 0.0 = -10 °C,
+//!   1.0 = 80 °C. Shares the sensor with board health telemetry.
+//!
+//! Each is enabled by giving it a cortical mapping in config.json
+//! `onboard_sensors`; on a chip without it, a warning is logged at boot.
+
+use crate::adc::Adc1;
+use crate::cortical;
+use crate::warn;
+use esp_idf_svc::sys;
+
+/// Onboard sensors and their mappings (from config.json `onboard_sensors`)
+#[derive(Debug, Clone, Copy)]
+pub struct OnboardConfig {
+    pub hall_mapping: Option<&'static str>,
+    /// Raw hall reading (relative to boot) mapped to 0.0/1.0
+    pub hall_full_scale: u32,
+    pub temperature_mapping: Option<&'static str>,
+}
+
+/// Temperature range mapped to 0.0-1.0 (the range health.rs installs the
+/// sensor for)
+const MIN_TEMPERATURE_C: f32 = -10.0;
+const MAX_TEMPERATURE_C: f32 = 80.0;
+
+/// Map a die temperature to 0.0-1.0
+pub fn temperature_value(celsius: f32) -> f32 {
+    ((celsius - MIN_TEMPERATURE_C) / (MAX_TEMPERATURE_C - MIN_TEMPERATURE_C)).clamp(0.0, 1.0)
+}
+
+/// Conversions averaged per hall reading
+const HALL_SAMPLES: i32 = 4;
+
+/// The hall-effect sensor, read through ADC1 on GPIO 36 (SENSOR_VP) and
+/// GPIO 39 (SENSOR_VN)
+pub struct HallSensor {
+    adc: Adc1,
+    vp: sys::adc_channel_t,
+    vn: sys::adc_channel_t,
+    neuron_id: u32,
+    full_scale: f32,
+    /// Reading at boot, taken as zero field
+    baseline: i32,
+}
+
+impl HallSensor {
+    /// Power up the sensor and take the zero-field baseline
+    pub fn new(config: &OnboardConfig) -> Option<Self> {
+        let neuron_id = cortical::neuron_id(config.hall_mapping?)?;
+        if !hall_supported() {
+            warn!("No hall sensor on this chip");
+            return None;
+        }
+        let mut adc = Adc1::new()?;
+        // The sensor's output is a few mV: no attenuation
+        let vp = adc.configure_pin_atten(36, sys::adc_atten_t_ADC_ATTEN_DB_0)?;
+        let vn = adc.configure_pin_atten(39, sys::adc_atten_t_ADC_ATTEN_DB_0)?;
+        let mut sensor = Self {
+            adc,
+            vp,
+            vn,
+            neuron_id,
+            full_scale: config.hall_full_scale.max(1) as f32,
+            baseline: 0,
+        };
+        sensor.baseline = sensor.read_raw()?;
+        Some(sensor)
+    }
+
+    /// (neuron_id, value) of the current field
+    pub fn read(&mut self) -> Option<(u32, f32)> {
+        let field = (self.read_raw()? - self.baseline) as f32;
+        Some((self.neuron_id, (0.5 + field / self.full_scale / 2.0).clamp(0.0, 1.0)))
+    }
+
+    /// Differential reading: both outputs sampled in both bias phases, which
+    /// cancels the ADC offset
+    fn read_raw(&mut self) -> Option<i32> {
+        let mut total = 0;
+        for _ in 0..HALL_SAMPLES {
+            set_hall_phase(false);
+            let vp0 = self.adc.read_raw(self.vp)? as i32;
+            let vn0 = self.adc.read_raw(self.vn)? as i32;
+            set_hall_phase(true);
+            let vp1 = self.adc.read_raw(self.vp)? as i32;
+            let vn1 = self.adc.read_raw(self.vn)? as i32;
+            total += (vp1 - vp0) - (vn1 - vn0);
+        }
+        set_hall_phase(false);
+        Some(total / HALL_SAMPLES)
+    }
+}
+
+// IDF 5 dropped the hall sensor API, so it is driven through the RTC
+// registers it used (ESP32 TRM, "RTC_IO_HALL_SENS_REG" and
+// "SENS_SAR_TOUCH_CTRL1_REG")
+#[cfg(esp32)]
+const RTC_IO_HALL_SENS_REG: *mut u32 = 0x3ff4_8474 as *mut u32;
+#[cfg(esp32)]
+const RTC_IO_XPD_HALL: u32 = 1 << 31;
+#[cfg(esp32)]
+const RTC_IO_HALL_PHASE: u32 = 1 << 30;
+#[cfg(esp32)]
+const SENS_SAR_TOUCH_CTRL1_REG: *mut u32 = 0x3ff4_8858 as *mut u32;
+#[cfg(esp32)]
+const SENS_XPD_HALL_FORCE: u32 = 1 << 27;
+#[cfg(esp32)]
+const SENS_HALL_PHASE_FORCE: u32 = 1 << 26;
+
+/// Power the sensor up under software control; false if the chip has none
+#[cfg(esp32)]
+fn hall_supported() -> bool {
+    unsafe {
+        let ctrl = core::ptr::read_volatile(SENS_SAR_TOUCH_CTRL1_REG);
+        core::ptr::write_volatile(SENS_SAR_TOUCH_CTRL1_REG, ctrl | SENS_XPD_HALL_FORCE | SENS_HALL_PHASE_FORCE);
+        let sens = core::ptr::read_volatile(RTC_IO_HALL_SENS_REG);
+        core::ptr::write_volatile(RTC_IO_HALL_SENS_REG, sens | RTC_IO_XPD_HALL);
+    }
+    true
+}
+
+#[cfg(not(esp32))]
+fn hall_supported() -> bool {
+    false
+}
+
+#[cfg(esp32)]
+fn set_hall_phase(inverted: bool) {
+    unsafe {
+        let sens = core::ptr::read_volatile(RTC_IO_HALL_SENS_REG);
+        let sens = if inverted { sens | RTC_IO_HALL_PHASE } else { sens & !RTC_IO_HALL_PHASE };
+        core::ptr::write_volatile(RTC_IO_HALL_SENS_REG, sens);
+    }
+}
+
+#[cfg(not(esp32))]
+fn set_hall_phase(_inverted: bool) {}
@@ -0,0 +1,287 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Signed firmware updates over WiFi or Ethernet (config.json `ota`)
+//!
+//! The flash holds two app partitions (partitions.csv). An update is written
+//! to the one not running, and the board boots it only if it verifies:
+//!
+//! 1. A host POSTs the image (the `.bin` from `espflash save-image`) to
+//!    `http://<board>:<port>/ota`, with an `X-Signature` header holding the
+//!    hex of its signature (`openssl dgst -sha256 -sign key.pem`, ECDSA or
+//!    RSA) made with the key whose public half is `ota.public_key`
+//! 2. The board streams it into the idle partition while hashing it, checks
+//!    the signature and that it's an image of this firmware, answers
+//!    `{"ota":"ok","version":"0.2.0"}` (or
+//!    `{"ota":"error","reason":"..."}` and keeps the running image), and
+//!    reboots into it from the burst loop, outputs safe-stopped first
+//!    An image whose version (`[package.metadata.esp-idf] version`) isn't
+//!    newer than the running one is refused, so a captured update can't be
+//!    replayed, neither to downgrade the board nor to reinstall the same
+//!    image (rebooting it and restarting its probation)
+//! 3. The new image boots on probation: once a line from the host arrives,
+//!    it's marked valid. If it resets before that (crash, watchdog, no
+//!    link), the bootloader rolls back to the previous image
+//!
+//! The capabilities document reports the running version (`"firmware"`)
+//! and partition. tools/feagi_esp32.py's `ota` command uploads an image.
+//!
+//! The upload runs in esp_http_server's own task, which leaves the reboot to
+//! the burst loop, like status_server.rs leaves safe-stop to it.
+
+use core::ffi::{c_char, c_void, CStr};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use esp_idf_svc::sys;
+use feagi_link::secure_link::decode_hex;
+use heapless::{String, Vec};
+
+use crate::{info, warn};
+use crate::provisioning::http_config;
+
+/// Update settings (from config.json `ota`)
+#[derive(Debug, Clone, Copy)]
+pub struct OtaConfig {
+    /// Port of the update endpoint
+    pub port: u16,
+    /// PEM public key the images are signed for, NUL-terminated for mbedTLS
+    pub public_key: &'static [u8],
+}
+
+/// Longest signature accepted (RSA-4096)
+const MAX_SIGNATURE: usize = 512;
+
+/// Bytes read from the request per esp_ota_write
+const CHUNK: usize = 1024;
+
+/// Receive timeouts in a row (of `recv_wait_timeout`, 5 s each) after which
+/// an upload that stopped arriving is abandoned, freeing the endpoint
+const MAX_RECV_TIMEOUTS: u32 = 3;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// An upload is being received, or one was accepted and awaits the reboot
+static BUSY: AtomicBool = AtomicBool::new(false);
+static REBOOT: AtomicBool = AtomicBool::new(false);
+static CONFIRMED: AtomicBool = AtomicBool::new(false);
+
+/// Start the update endpoint once the network interface is up
+///
+/// Later calls are no-ops. Returns false if it couldn't be started.
+pub fn start(config: &OtaConfig) -> bool {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return true;
+    }
+    unsafe {
+        let mut http = http_config(config.port);
+        // Next to status_server.rs's instance, and with room for mbedTLS
+        http.ctrl_port += 1;
+        http.stack_size = 8192;
+        let mut server: sys::httpd_handle_t = core::ptr::null_mut();
+        if sys::httpd_start(&mut server, &http) != sys::ESP_OK {
+            STARTED.store(false, Ordering::Release);
+            return false;
+        }
+        let mut route: sys::httpd_uri_t = core::mem::zeroed();
+        route.uri = b"/ota\0".as_ptr() as *const c_char;
+        route.method = sys::http_method_HTTP_POST;
+        route.handler = Some(handle_update);
+        sys::httpd_register_uri_handler(server, &route) == sys::ESP_OK
+    }
+}
+
+/// Was an update accepted since the last call? The burst loop then reboots
+/// into it
+pub fn take_reboot() -> bool {
+    REBOOT.swap(false, Ordering::AcqRel)
+}
+
+/// Mark the running image valid, cancelling the rollback armed for a fresh
+/// update; call once the host was heard from
+pub fn confirm() {
+    if CONFIRMED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    unsafe {
+        if pending_verify() && sys::esp_ota_mark_app_valid_cancel_rollback() == sys::ESP_OK {
+            info!("Updated firmware confirmed");
+        }
+    }
+}
+
+/// Label of the running app partition (`ota_0` or `ota_1`)
+pub fn running_partition() -> &'static str {
+    unsafe {
+        let partition = sys::esp_ota_get_running_partition();
+        if partition.is_null() {
+            return "";
+        }
+        CStr::from_ptr((*partition).label.as_ptr()).to_str().unwrap_or("")
+    }
+}
+
+/// Is the running image a fresh update, not confirmed yet?
+pub fn pending_verify() -> bool {
+    unsafe {
+        let mut state: sys::esp_ota_img_states_t = 0;
+        sys::esp_ota_get_state_partition(sys::esp_ota_get_running_partition(), &mut state) == sys::ESP_OK
+            && state == sys::esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+    }
+}
+
+unsafe extern "C" fn handle_update(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    if BUSY.swap(true, Ordering::AcqRel) {
+        return reply(req, b"409 Conflict\0", "{\"ota\":\"error\",\"reason\":\"update in progress\"}");
+    }
+    match receive(req) {
+        Ok(version) => {
+            info!("Firmware {} received, rebooting into it", CStr::from_ptr(version.as_ptr()).to_str().unwrap_or(""));
+            let mut json: String<80> = String::new();
+            let _ = json.push_str("{\"ota\":\"ok\",\"version\":\"");
+            let _ = json.push_str(CStr::from_ptr(version.as_ptr()).to_str().unwrap_or(""));
+            let _ = json.push_str("\"}");
+            let sent = reply(req, b"200 OK\0", &json);
+            // BUSY stays set: nothing else is accepted before the reboot
+            REBOOT.store(true, Ordering::Release);
+            sent
+        }
+        Err(reason) => {
+            warn!("Firmware update rejected: {}", reason.trim_end_matches('\0'));
+            let mut json: String<96> = String::new();
+            let _ = json.push_str("{\"ota\":\"error\",\"reason\":\"");
+            let _ = json.push_str(reason.trim_end_matches('\0'));
+            let _ = json.push_str("\"}");
+            BUSY.store(false, Ordering::Release);
+            reply(req, b"400 Bad Request\0", &json)
+        }
+    }
+}
+
+/// Write the uploaded image to the idle partition and make it the boot
+/// partition if it verifies; the new image's version (NUL-terminated), or
+/// why it was refused (NUL-terminated too, for the console)
+unsafe fn receive(req: *mut sys::httpd_req_t) -> Result<[c_char; 32], &'static str> {
+    let mut signature: Vec<u8, MAX_SIGNATURE> = Vec::new();
+    let mut hex = [0u8; 2 * MAX_SIGNATURE + 1];
+    let header = b"X-Signature\0".as_ptr() as *const c_char;
+    let len = sys::httpd_req_get_hdr_value_len(req, header);
+    if len == 0 || len >= hex.len()
+        || sys::httpd_req_get_hdr_value_str(req, header, hex.as_mut_ptr() as *mut c_char, hex.len()) != sys::ESP_OK
+        || decode_hex(&hex[..len], &mut signature).is_err()
+    {
+        return Err("missing or malformed X-Signature\0");
+    }
+
+    let size = (*req).content_len;
+    let partition = sys::esp_ota_get_next_update_partition(core::ptr::null());
+    if partition.is_null() {
+        return Err("no OTA partition\0");
+    }
+    if size == 0 || size > (*partition).size as usize {
+        return Err("image empty or larger than the partition\0");
+    }
+    let mut handle: sys::esp_ota_handle_t = 0;
+    if sys::esp_ota_begin(partition, size, &mut handle) != sys::ESP_OK {
+        return Err("esp_ota_begin failed\0");
+    }
+
+    // Hash while writing, so the image is only read once
+    let mut sha: sys::mbedtls_sha256_context = core::mem::zeroed();
+    sys::mbedtls_sha256_init(&mut sha);
+    sys::mbedtls_sha256_starts(&mut sha, 0);
+    let mut chunk = [0u8; CHUNK];
+    let mut received = 0;
+    let mut timeouts = 0;
+    while received < size {
+        let n = sys::httpd_req_recv(req, chunk.as_mut_ptr() as *mut c_char, CHUNK.min(size - received));
+        if n == sys::HTTPD_SOCK_ERR_TIMEOUT {
+            timeouts += 1;
+            if timeouts < MAX_RECV_TIMEOUTS {
+                continue;
+            }
+            sys::mbedtls_sha256_free(&mut sha);
+            sys::esp_ota_abort(handle);
+            return Err("upload stalled\0");
+        }
+        timeouts = 0;
+        if n <= 0 || sys::esp_ota_write(handle, chunk.as_ptr() as *const c_void, n as usize) != sys::ESP_OK {
+            sys::mbedtls_sha256_free(&mut sha);
+            sys::esp_ota_abort(handle);
+            return Err("upload interrupted or flash write failed\0");
+        }
+        sys::mbedtls_sha256_update(&mut sha, chunk.as_ptr(), n as usize);
+        received += n as usize;
+    }
+    let mut digest = [0u8; 32];
+    sys::mbedtls_sha256_finish(&mut sha, digest.as_mut_ptr());
+    sys::mbedtls_sha256_free(&mut sha);
+
+    if !verify(&digest, &signature) {
+        sys::esp_ota_abort(handle);
+        return Err("bad signature\0");
+    }
+    // Checks the image structure and checksums
+    if sys::esp_ota_end(handle) != sys::ESP_OK {
+        return Err("not a valid app image\0");
+    }
+    let mut new: sys::esp_app_desc_t = core::mem::zeroed();
+    let running = sys::esp_app_get_description();
+    if sys::esp_ota_get_partition_description(partition, &mut new) != sys::ESP_OK
+        || CStr::from_ptr(new.project_name.as_ptr()) != CStr::from_ptr((*running).project_name.as_ptr())
+    {
+        return Err("not an image of this firmware\0");
+    }
+    match (parse_version(&new.version), parse_version(&(*running).version)) {
+        (Some(new), Some(running)) if new > running => {}
+        (Some(_), Some(_)) => return Err("not newer than the running firmware\0"),
+        _ => return Err("unreadable firmware version\0"),
+    }
+    if sys::esp_ota_set_boot_partition(partition) != sys::ESP_OK {
+        return Err("esp_ota_set_boot_partition failed\0");
+    }
+    Ok(new.version)
+}
+
+/// `major.minor.patch` of an app description's version; anything after the
+/// patch number (`-rc1`, `+dirty`) is ignored
+fn parse_version(version: &[c_char; 32]) -> Option<(u32, u32, u32)> {
+    let text = unsafe { CStr::from_ptr(version.as_ptr()) }.to_str().ok()?;
+    let mut parts = text.trim_start_matches('v').splitn(3, '.');
+    let number = |part: Option<&str>| -> Option<u32> {
+        let part = part?;
+        let end = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+        part[..end].parse().ok()
+    };
+    Some((number(parts.next())?, number(parts.next())?, number(parts.next())?))
+}
+
+/// Does `signature` sign `digest` (SHA-256) for the configured public key?
+unsafe fn verify(digest: &[u8; 32], signature: &[u8]) -> bool {
+    // start() only runs with `ota` configured
+    let Some(config) = crate::OTA else {
+        return false;
+    };
+    let key = config.public_key;
+    let mut pk: sys::mbedtls_pk_context = core::mem::zeroed();
+    sys::mbedtls_pk_init(&mut pk);
+    let ok = sys::mbedtls_pk_parse_public_key(&mut pk, key.as_ptr(), key.len()) == 0
+        && sys::mbedtls_pk_verify(
+            &mut pk,
+            sys::mbedtls_md_type_t_MBEDTLS_MD_SHA256,
+            digest.as_ptr(),
+            digest.len(),
+            signature.as_ptr(),
+            signature.len(),
+        ) == 0;
+    sys::mbedtls_pk_free(&mut pk);
+    ok
+}
+
+unsafe fn reply(req: *mut sys::httpd_req_t, status: &[u8], json: &str) -> sys::esp_err_t {
+    sys::httpd_resp_set_status(req, status.as_ptr() as *const c_char);
+    sys::httpd_resp_set_type(req, b"application/json\0".as_ptr() as *const c_char);
+    sys::httpd_resp_send(req, json.as_ptr() as *const c_char, json.len() as _)
+}
@@ -0,0 +1,217 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Commanded outputs restored after a software restart (config.json
+//! `output_restore`)
+//!
+//! Every motor command applied to an output is also recorded in RTC memory,
+//! which keeps its contents through a software reset (esp_restart: OTA,
+//! `set_gpio`, exhausted transport restarts) but not a power cycle. After
+//! such a reset the outputs still come up in their boot states; the board
+//! offers the recorded values to the host after the boot report, and only
+//! applies them once FEAGI confirms:
+//!
+//! ```text
+//! board: {"restore_offer":{"outputs":[[25,0.750],[26,1.000]]}}
+//! host:  {"restore":true}
+//! board: {"restore_ack":{"n":2}}
+//! ```
+//!
+//! Outputs are listed by pin, with their value on the motor command's
+//! 0.0-1.0 scale. `{"restore":false}`, the first motor command or no answer
+//! within `timeout_ms` of the offer drop it. Panics, watchdog resets and
+//! brownouts never lead to an offer: the outputs' state before those isn't
+//! trusted. Neither does a firmware built from a different config.json.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use critical_section::Mutex;
+use esp_idf_svc::sys;
+use heapless::{String, Vec};
+
+use crate::{unit_f32_to_string, u32_to_string, CONFIG_CRC32, MAX_OUTPUT_CHANNELS};
+
+/// Output restore settings (from config.json `output_restore`)
+#[derive(Debug, Clone, Copy)]
+pub struct RestoreConfig {
+    /// How long an offer waits for the host's answer
+    pub timeout_ms: u32,
+}
+
+/// Marks a snapshot written by this layout (RTC memory is random after a
+/// power cycle)
+const MAGIC: u32 = 0x4F52_5301;
+
+/// Pin of a slot nothing was recorded in
+const EMPTY: u16 = 0xFFFF;
+
+/// Longest `{"restore_offer":{...}}` line: "[65534,1.000]," per output
+pub const OFFER_LINE_CAPACITY: usize = 40 + 14 * MAX_OUTPUT_CHANNELS;
+
+/// Last commanded value of each output channel, by its index in the bank
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Snapshot {
+    magic: u32,
+    config_crc: u32,
+    pins: [u16; MAX_OUTPUT_CHANNELS],
+    values: [f32; MAX_OUTPUT_CHANNELS],
+}
+
+// Written by the burst loop, read by the status server task. A Cell rather
+// than a RefCell: a borrow flag in RTC memory would be random after a power
+// cycle.
+#[link_section = ".rtc_noinit"]
+static SNAPSHOT: Mutex<Cell<Snapshot>> = Mutex::new(Cell::new(Snapshot {
+    magic: 0,
+    config_crc: 0,
+    pins: [EMPTY; MAX_OUTPUT_CHANNELS],
+    values: [0.0; MAX_OUTPUT_CHANNELS],
+}));
+
+/// An offer is waiting for the host's answer
+static PENDING: AtomicBool = AtomicBool::new(false);
+/// When an unanswered offer lapses, in milliseconds since boot (0 = not sent
+/// yet); 32 bits, as the RISC-V chips have no 64-bit atomics
+static DEADLINE_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Decide at boot whether there's anything to offer; otherwise start a
+/// fresh snapshot
+pub fn init(config: Option<&RestoreConfig>) -> bool {
+    let benign = unsafe { sys::esp_reset_reason() } == sys::esp_reset_reason_t_ESP_RST_SW;
+    let pending = with_snapshot(|snapshot| {
+        let valid = snapshot.magic == MAGIC
+            && snapshot.config_crc == CONFIG_CRC32
+            && snapshot.values.iter().all(|v| (0.0..=1.0).contains(v))
+            && snapshot.pins.iter().any(|&p| p != EMPTY);
+        let pending = config.is_some() && valid && benign;
+        if !pending {
+            clear(snapshot);
+        }
+        pending
+    });
+    PENDING.store(pending, Ordering::Release);
+    pending
+}
+
+/// Record the value just applied to output channel `index`
+///
+/// The first command after a reset drops a pending offer, and what it
+/// recorded with it.
+pub fn record(index: usize, pin: u32, value: f32) {
+    let drop_offer = PENDING.swap(false, Ordering::AcqRel);
+    with_snapshot(|snapshot| {
+        if drop_offer {
+            clear(snapshot);
+        }
+        if index < MAX_OUTPUT_CHANNELS {
+            snapshot.pins[index] = pin as u16;
+            snapshot.values[index] = value.clamp(0.0, 1.0);
+        }
+    });
+}
+
+/// `{"restore_offer":{...}}\n` while an offer is pending; starts its timeout
+pub fn offer_line(config: &RestoreConfig) -> Option<String<OFFER_LINE_CAPACITY>> {
+    if !PENDING.load(Ordering::Acquire) {
+        return None;
+    }
+    let deadline = now_ms().wrapping_add(config.timeout_ms).max(1);
+    let _ = DEADLINE_MS.compare_exchange(0, deadline, Ordering::AcqRel, Ordering::Acquire);
+    let snapshot = critical_section::with(|cs| SNAPSHOT.borrow(cs).get());
+    let mut line: String<OFFER_LINE_CAPACITY> = String::from("{\"restore_offer\":{\"outputs\":[");
+    let mut num: String<16> = String::new();
+    for (i, (&pin, &value)) in snapshot.pins.iter().zip(snapshot.values.iter()).filter(|(p, _)| **p != EMPTY).enumerate() {
+        let _ = line.push_str(if i == 0 { "[" } else { ",[" });
+        u32_to_string(pin as u32, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push(',');
+        unit_f32_to_string(value, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push(']');
+    }
+    let _ = line.push_str("]}}\n");
+    Some(line)
+}
+
+/// Drop an offer the host didn't answer in time; true if it just lapsed
+pub fn poll() -> bool {
+    let deadline = DEADLINE_MS.load(Ordering::Acquire);
+    // Wrapping difference: still right when the millisecond count rolls over
+    if deadline == 0 || (now_ms().wrapping_sub(deadline) as i32) < 0 {
+        return false;
+    }
+    DEADLINE_MS.store(0, Ordering::Release);
+    if !PENDING.swap(false, Ordering::AcqRel) {
+        return false;
+    }
+    with_snapshot(clear);
+    true
+}
+
+/// The host's answer, `{"restore":true}` or `{"restore":false}`
+pub fn parse_answer(message: &str) -> Option<bool> {
+    match message.trim() {
+        "{\"restore\":true}" => Some(true),
+        "{\"restore\":false}" => Some(false),
+        _ => None,
+    }
+}
+
+/// Close the offer: the (pin, value) pairs to apply if the host accepted
+/// it, none otherwise (or if there was none)
+pub fn answer(accepted: bool) -> Vec<(u32, f32), MAX_OUTPUT_CHANNELS> {
+    let mut values = Vec::new();
+    if !PENDING.swap(false, Ordering::AcqRel) {
+        return values;
+    }
+    with_snapshot(|snapshot| {
+        if accepted {
+            for (&pin, &value) in snapshot.pins.iter().zip(snapshot.values.iter()) {
+                if pin != EMPTY {
+                    let _ = values.push((pin as u32, value));
+                }
+            }
+        }
+        // Restored values are recorded again as they're applied
+        clear(snapshot);
+    });
+    values
+}
+
+/// `{"restore_ack":{"n":N}}\n`, N outputs restored
+pub fn ack_line(restored: usize) -> String<40> {
+    let mut line: String<40> = String::from("{\"restore_ack\":{\"n\":");
+    let mut num: String<16> = String::new();
+    u32_to_string(restored as u32, &mut num);
+    let _ = line.push_str(num.as_str());
+    let _ = line.push_str("}}\n");
+    line
+}
+
+/// Update the snapshot under the critical section
+fn with_snapshot<R>(f: impl FnOnce(&mut Snapshot) -> R) -> R {
+    critical_section::with(|cs| {
+        let cell = SNAPSHOT.borrow(cs);
+        let mut snapshot = cell.get();
+        let result = f(&mut snapshot);
+        cell.set(snapshot);
+        result
+    })
+}
+
+fn clear(snapshot: &mut Snapshot) {
+    snapshot.magic = MAGIC;
+    snapshot.config_crc = CONFIG_CRC32;
+    snapshot.pins = [EMPTY; MAX_OUTPUT_CHANNELS];
+    snapshot.values = [0.0; MAX_OUTPUT_CHANNELS];
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
@@ -0,0 +1,477 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Motor output application for the ESP32 controller
+//!
+//! Owns every configured output pin and remembers the value that was actually
+//! driven onto it, so the main loop can echo applied values back to FEAGI as a
+//! proprioceptive channel.
+//!
+//! An output is driven either by a single neuron (the neuron ID in its
+//! cortical mapping) or by a population of neurons crossing a threshold.
+//! Digital outputs switch at 0.5; PWM outputs take the value as their duty
+//! cycle and servo outputs as their angle (pwm.rs); DC motor outputs take it
+//! as a signed speed around 0.5 (dc_motor.rs) and stepper outputs as their
+//! axis' target speed or position (stepper.rs). LED strips take a neuron per
+//! LED color channel (led_strip.rs), and the audio output a neuron per tone
+//! (audio.rs).
+//!
+//! Each output also has a boot state that is applied before any transport is
+//! brought up, so actuators don't twitch while the board is still booting.
+//! Its pull resistors and (digital outputs) open-drain stage are set along
+//! with it (pad.rs).
+//!
+//! Digital outputs on expander pins (expander.rs) only update the expander's
+//! output latch here; they start low once the expander is set up.
+
+use esp_idf_svc::sys;
+use feagi_controller_core::rate_policy;
+use heapless::Vec;
+
+use crate::audio::AudioOutput;
+use crate::cortical;
+use crate::dc_motor::{self, DcMotorConfig, MotorDriver};
+use crate::expander;
+use crate::led_strip::LedStripBank;
+use crate::output_restore;
+use crate::pad::{self, Drive};
+use crate::population::Population;
+use crate::pwm::{self, PwmConfig, ServoConfig};
+use crate::stepper::{self, StepperConfig, StepperControl};
+use crate::{GpioMode, GpioPinConfig, AUDIO_OUTPUT};
+use crate::fault;
+
+/// Output pin state between power-up and the first motor command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BootState {
+    /// Drive the pin low immediately (default)
+    DriveLow,
+    /// Leave the pin as a high-impedance input until FEAGI commands it
+    Float,
+    /// Keep the pad latched at its current level (e.g. across deep sleep)
+    Hold,
+}
+
+/// A configured output pin and the value most recently applied to it
+#[derive(Debug, Clone, Copy)]
+pub struct OutputChannel {
+    pub pin: u32,
+    pub mode: GpioMode,
+    pub neuron_id: Option<u32>,
+    /// Population decoder, for outputs driven by a neuron range instead
+    pub population: Option<Population>,
+    /// LEDC channel, for PWM and servo outputs
+    pub pwm: Option<PwmConfig>,
+    /// Pulse and angle limits, for servo outputs
+    pub servo: Option<ServoConfig>,
+    /// H-bridge wiring, for DC motor outputs
+    pub dc_motor: Option<DcMotorConfig>,
+    /// Driver wiring and motion limits, for stepper outputs
+    pub stepper: Option<StepperConfig>,
+    pub boot_state: BootState,
+    /// Value applied on link loss instead of the stop value (digital, PWM
+    /// and servo outputs)
+    pub safe_value: Option<f32>,
+    /// Push-pull or open-drain (digital outputs)
+    pub drive: Drive,
+    /// Value actually driven onto the pin (after clamping/thresholding), 0.0-1.0
+    pub applied: f32,
+    /// Whether the output driver has been enabled (false while floating/held)
+    driving: bool,
+}
+
+impl OutputChannel {
+    /// Value to echo: the applied value, or where a stepper axis actually is
+    fn echo(&self) -> f32 {
+        self.stepper
+            .as_ref()
+            .and_then(|config| stepper::feedback(self.pin, config))
+            .unwrap_or(self.applied)
+    }
+}
+
+/// All output channels of the board (capacity `N` from the build config)
+pub struct OutputBank<const N: usize> {
+    channels: Vec<OutputChannel, N>,
+    strips: LedStripBank,
+    audio: Option<AudioOutput>,
+}
+
+impl<const N: usize> OutputBank<N> {
+    /// Build the output bank from GPIO_CONFIG and apply each pin's boot state
+    ///
+    /// Must run before any transport is initialized.
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut channels = Vec::new();
+        for gpio_config in config {
+            if let GpioMode::DigitalOutput
+            | GpioMode::PwmOutput
+            | GpioMode::ServoOutput
+            | GpioMode::DcMotor
+            | GpioMode::StepperOutput = gpio_config.mode
+            {
+                let mut driving = match gpio_config.stepper {
+                    Some(ref stepper) => stepper::attach(gpio_config.pin, stepper),
+                    // Expander pins have no pad to set up here
+                    None => expander::is_virtual(gpio_config.pin) || apply_boot_state(gpio_config),
+                };
+                if let (true, Some(ref pwm)) = (driving, gpio_config.pwm) {
+                    // Low from the first period; if LEDC refuses, the pin
+                    // stays a low GPIO and the first command retries
+                    driving = match gpio_config.dc_motor {
+                        Some(ref motor) => dc_motor::attach(gpio_config.pin, pwm, motor),
+                        None => pwm::attach(gpio_config.pin, pwm, 0.0).is_some(),
+                    };
+                    if !driving {
+                        fault!(PinInit, "GPIO {}: LEDC rejected {} Hz at {} bits", gpio_config.pin, pwm.frequency_hz, pwm.resolution_bits);
+                    }
+                }
+                let population = gpio_config.population.map(Population::new);
+                let mut channel = OutputChannel {
+                    pin: gpio_config.pin,
+                    mode: gpio_config.mode,
+                    neuron_id: match population {
+                        Some(_) => None,
+                        None => cortical::neuron_id(gpio_config.cortical_mapping),
+                    },
+                    population,
+                    pwm: gpio_config.pwm,
+                    servo: gpio_config.servo,
+                    dc_motor: gpio_config.dc_motor,
+                    stepper: gpio_config.stepper,
+                    boot_state: gpio_config.boot_state,
+                    safe_value: gpio_config.safe_value,
+                    drive: gpio_config.drive,
+                    applied: 0.0,
+                    driving,
+                };
+                channel.applied = stop_value(&channel);
+                let _ = channels.push(channel);
+            }
+        }
+        Self {
+            channels,
+            strips: LedStripBank::from_config(config),
+            audio: AUDIO_OUTPUT.and_then(|audio| AudioOutput::new(&audio)),
+        }
+    }
+
+    /// Apply a motor command to every output mapped to `neuron_id`
+    ///
+    /// Population outputs only record the firing here; they switch in
+    /// `update_populations`. Returns true if at least one output matched.
+    pub fn apply(&mut self, neuron_id: u32, value: f32) -> bool {
+        let mut matched = self.strips.apply(neuron_id, value);
+        if let Some(ref mut audio) = self.audio {
+            matched |= audio.apply(neuron_id, value);
+        }
+        let now_ms = now_ms();
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            if let Some(ref mut population) = channel.population {
+                matched |= population.record(neuron_id, value, now_ms);
+                continue;
+            }
+            if channel.neuron_id != Some(neuron_id) {
+                continue;
+            }
+            matched = true;
+            drive(channel, value);
+            output_restore::record(index, channel.pin, value);
+        }
+        matched
+    }
+
+    /// Whether `neuron_id` drives an output that takes the value in steps,
+    /// not just on/off (PWM, servo, DC motor or stepper), directly rather
+    /// than through a population
+    pub fn is_proportional(&self, neuron_id: u32) -> bool {
+        self.channels.iter().any(|channel| {
+            channel.neuron_id == Some(neuron_id)
+                && matches!(channel.mode, GpioMode::PwmOutput | GpioMode::ServoOutput | GpioMode::DcMotor | GpioMode::StepperOutput)
+        })
+    }
+
+    /// Drive the output on `pin` with a value restored after a reset (see
+    /// output_restore.rs); false if there's no such output anymore
+    pub fn restore(&mut self, pin: u32, value: f32) -> bool {
+        let Some((index, channel)) = self.channels.iter_mut().enumerate().find(|(_, c)| c.pin == pin) else {
+            return false;
+        };
+        drive(channel, value);
+        output_restore::record(index, pin, value);
+        true
+    }
+
+    /// Switch population outputs whose firing count crossed their threshold
+    ///
+    /// Call once per burst, after the motor commands were applied.
+    pub fn update_populations(&mut self) {
+        let now_ms = now_ms();
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            let Some(ref mut population) = channel.population else {
+                continue;
+            };
+            if let Some(active) = population.update(now_ms) {
+                let value = if active { 1.0 } else { 0.0 };
+                drive(channel, value);
+                output_restore::record(index, channel.pin, value);
+            }
+        }
+    }
+
+    /// Send LED strip frames changed by this burst's commands
+    ///
+    /// Call once per burst, after the motor commands were applied.
+    pub fn refresh_strips(&mut self) {
+        self.strips.refresh();
+    }
+
+    /// Start or stop tones commanded by this burst and feed the audio output
+    ///
+    /// Call once per burst, after the motor commands were applied.
+    pub fn refresh_audio(&mut self) {
+        if let Some(ref mut audio) = self.audio {
+            audio.refresh();
+        }
+    }
+
+    /// Whether the configured audio output is running
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    /// Iterate over (neuron_id, applied value) for every mapped output
+    pub fn applied_values(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.channels
+            .iter()
+            .filter_map(|c| c.neuron_id.map(|id| (id, c.echo())))
+    }
+
+    /// Iterate over (pin, applied value) for every output
+    pub fn pin_levels(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.channels.iter().map(|c| (c.pin, c.echo()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Resting command of a motor driven by `neuron_id`, if there is one
+    ///
+    /// DC motors and stepper axes decay to this instead of the policy's
+    /// neutral value, so a stalled host can't leave them running in reverse
+    /// (or send a position axis to the neutral position).
+    pub fn motor_stop_value(&self, neuron_id: u32) -> Option<f32> {
+        self.channels
+            .iter()
+            .find(|c| (c.dc_motor.is_some() || c.stepper.is_some()) && c.neuron_id == Some(neuron_id))
+            .map(stop_value)
+    }
+
+    /// Drive every output to its safe value (low unless configured) and stop
+    /// every motor (safe-stop, and the failsafe on link loss)
+    ///
+    /// Floating and held pins are taken over too, so nothing is left at a
+    /// level FEAGI commanded.
+    pub fn safe_stop(&mut self) {
+        for channel in self.channels.iter_mut() {
+            if channel.stepper.is_some() {
+                // Steppers decelerate, rather than lose steps
+                stepper::stop(channel.pin);
+                continue;
+            }
+            drive(channel, channel.safe_value.unwrap_or_else(|| stop_value(channel)));
+        }
+        self.strips.clear();
+        if let Some(ref mut audio) = self.audio {
+            audio.stop();
+        }
+    }
+
+    /// Every pad the outputs drive, with its level when the burst loop can't
+    /// safe-stop them (failsafe.rs): Some(level), or None to let it float
+    ///
+    /// That's the `safe_value` level of digital outputs, otherwise the boot
+    /// state (pins that hold theirs are left alone), with H-bridge and
+    /// stepper DIR pins low and stepper drivers disabled. Expander pins are
+    /// out of reach.
+    pub fn safe_levels(&self, mut each: impl FnMut(u32, Option<bool>)) {
+        for channel in self.channels.iter() {
+            if expander::is_virtual(channel.pin) {
+                continue;
+            }
+            match (channel.mode, channel.safe_value, channel.boot_state) {
+                (GpioMode::DigitalOutput, Some(value), _) => each(channel.pin, Some(value > 0.5)),
+                (_, _, BootState::DriveLow) => each(channel.pin, Some(false)),
+                (_, _, BootState::Float) => each(channel.pin, None),
+                (_, _, BootState::Hold) => {}
+            }
+            match channel.dc_motor.map(|motor| motor.driver) {
+                Some(MotorDriver::TwoPwm { pin_b, .. }) => each(pin_b, Some(false)),
+                Some(MotorDriver::Direction { in1, in2 }) => {
+                    each(in1, Some(false));
+                    if let Some(in2) = in2 {
+                        each(in2, Some(false));
+                    }
+                }
+                None => {}
+            }
+            if let Some(ref stepper) = channel.stepper {
+                each(stepper.dir_pin, Some(false));
+                if let Some(enable) = stepper.enable_pin {
+                    // Active low
+                    each(enable, Some(true));
+                }
+            }
+        }
+    }
+
+    /// Latch outputs configured with `"boot_state": "hold"` before deep sleep
+    ///
+    /// The pads keep their level through deep sleep and the next boot, until
+    /// the first motor command releases them.
+    pub fn hold_for_deep_sleep(&self) {
+        let mut any_held = false;
+        for channel in self.channels.iter() {
+            if channel.boot_state == BootState::Hold {
+                unsafe {
+                    sys::gpio_hold_en(channel.pin as i32);
+                }
+                any_held = true;
+            }
+        }
+        if any_held {
+            unsafe {
+                sys::gpio_deep_sleep_hold_en();
+            }
+        }
+    }
+}
+
+// Motor commands shaped by the rate policy (rate_policy::MotorShaper)
+impl<const N: usize> rate_policy::Outputs for OutputBank<N> {
+    fn apply(&mut self, neuron_id: u32, value: f32) {
+        OutputBank::apply(self, neuron_id, value);
+    }
+
+    fn motor_stop_value(&self, neuron_id: u32) -> Option<f32> {
+        OutputBank::motor_stop_value(self, neuron_id)
+    }
+}
+
+/// Drive one output pin with a 0.0-1.0 value
+fn drive(channel: &mut OutputChannel, value: f32) {
+    if let Some(ref config) = channel.stepper {
+        if let Some(applied) = stepper::command(channel.pin, config, value) {
+            channel.applied = applied;
+        }
+        return;
+    }
+    if let (Some(ref motor), Some(ref pwm)) = (channel.dc_motor, channel.pwm) {
+        if !channel.driving {
+            // LEDC rejected the settings at boot; retry
+            channel.driving = dc_motor::attach(channel.pin, pwm, motor);
+        }
+        if channel.driving {
+            if let Some(applied) = dc_motor::set(pwm, motor, value) {
+                channel.applied = applied;
+            }
+        }
+        return;
+    }
+    if let Some(ref pwm) = channel.pwm {
+        let target = match channel.servo {
+            Some(ref servo) => servo.duty(value),
+            None => value,
+        };
+        let duty = if channel.driving {
+            pwm::set_duty(pwm, target)
+        } else {
+            // First command for a floating/held pin: LEDC takes over the pad
+            unsafe {
+                sys::gpio_hold_dis(channel.pin as i32);
+            }
+            pwm::attach(channel.pin, pwm, target)
+        };
+        if let Some(duty) = duty {
+            // A servo's applied value is the command, not the pulse's duty
+            channel.applied = if channel.servo.is_some() { value.clamp(0.0, 1.0) } else { duty };
+            channel.driving = true;
+        }
+        return;
+    }
+    if !channel.driving {
+        // First command for a floating/held pin: take over the pad
+        unsafe {
+            sys::gpio_hold_dis(channel.pin as i32);
+            sys::gpio_set_direction(channel.pin as i32, channel.drive.output_mode());
+        }
+        channel.driving = true;
+    }
+    match channel.mode {
+        GpioMode::DigitalOutput => {
+            let high = value > 0.5;
+            if expander::is_virtual(channel.pin) {
+                expander::write_pin(channel.pin, high);
+            } else {
+                unsafe {
+                    sys::gpio_set_level(channel.pin as i32, high as u32);
+                }
+            }
+            channel.applied = if high { 1.0 } else { 0.0 };
+        }
+        _ => {}
+    }
+}
+
+/// Command that stops an output: low, 0.5 (zero speed) for DC motors and
+/// velocity-controlled steppers, or the last command for position-controlled
+/// steppers
+fn stop_value(channel: &OutputChannel) -> f32 {
+    match channel.stepper {
+        Some(ref stepper) if stepper.control == StepperControl::Position => channel.applied,
+        Some(_) => 0.5,
+        None if channel.dc_motor.is_some() => 0.5,
+        None => 0.0,
+    }
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
+
+/// Put an output pin into its boot state, with its pull resistors
+///
+/// Returns true if the pin is actively driven afterwards.
+fn apply_boot_state(config: &GpioPinConfig) -> bool {
+    let gpio = config.pin as i32;
+    unsafe {
+        match config.boot_state {
+            BootState::DriveLow => {
+                sys::gpio_hold_dis(gpio);
+                sys::gpio_reset_pin(gpio);
+                pad::set_pull(config.pin, config.pull);
+                sys::gpio_set_level(gpio, 0);
+                sys::gpio_set_direction(gpio, config.drive.output_mode());
+                true
+            }
+            BootState::Float => {
+                // A pull resistor gives the floating pin a defined level
+                sys::gpio_hold_dis(gpio);
+                sys::gpio_reset_pin(gpio);
+                pad::set_pull(config.pin, config.pull);
+                sys::gpio_set_direction(gpio, sys::gpio_mode_t_GPIO_MODE_DISABLE);
+                false
+            }
+            BootState::Hold => {
+                // Don't reset the pin: that would glitch the latched level
+                sys::gpio_hold_en(gpio);
+                false
+            }
+        }
+    }
+}
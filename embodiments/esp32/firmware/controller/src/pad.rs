@@ -0,0 +1,74 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Electrical pad settings of GPIO pins
+//!
+//! `pull` enables the chip's internal pull resistors (~45 kΩ), so a button
+//! to ground needs no external resistor. `drive` selects push-pull or
+//! open-drain outputs; an open-drain output only ever pulls low and lets the
+//! line float high otherwise, for wired-OR buses and peripherals powered
+//! from another supply.
+//!
+//! GPIO 34-39 have no pull resistors (build.rs rejects `pull` on them).
+
+use esp_idf_svc::sys;
+
+/// Internal pull resistors of a pin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+impl Pull {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pull::None => "none",
+            Pull::Up => "up",
+            Pull::Down => "down",
+        }
+    }
+}
+
+/// Output stage of a pin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Drive {
+    PushPull,
+    OpenDrain,
+}
+
+impl Drive {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Drive::PushPull => "push_pull",
+            Drive::OpenDrain => "open_drain",
+        }
+    }
+
+    /// GPIO direction that drives the pin with this output stage
+    pub fn output_mode(&self) -> sys::gpio_mode_t {
+        match self {
+            Drive::PushPull => sys::gpio_mode_t_GPIO_MODE_OUTPUT,
+            Drive::OpenDrain => sys::gpio_mode_t_GPIO_MODE_OUTPUT_OD,
+        }
+    }
+}
+
+/// Apply `pull` to a pin
+///
+/// Call after `gpio_reset_pin`, which turns the pull-up on.
+pub fn set_pull(pin: u32, pull: Pull) {
+    let mode = match pull {
+        Pull::None => sys::gpio_pull_mode_t_GPIO_FLOATING,
+        Pull::Up => sys::gpio_pull_mode_t_GPIO_PULLUP_ONLY,
+        Pull::Down => sys::gpio_pull_mode_t_GPIO_PULLDOWN_ONLY,
+    };
+    unsafe {
+        sys::gpio_set_pull_mode(pin as i32, mode);
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Population-threshold decoding for outputs
+//!
+//! Instead of one neuron driving a pin, an output can watch a range of
+//! neurons (the voxels of its mapped area) and switch on when more than
+//! `threshold` of them fired within the last `window_ms`. It switches off
+//! again only once the count drops to `threshold - hysteresis` or below, so a
+//! noisy genome hovering around the threshold doesn't make the pin chatter.
+
+/// Largest neuron range one output can decode
+pub const MAX_POPULATION: usize = 64;
+
+/// Population decoder settings (from config.json `population` block)
+#[derive(Debug, Clone, Copy)]
+pub struct PopulationConfig {
+    /// First and last neuron ID of the mapped area (inclusive)
+    pub first: u32,
+    pub last: u32,
+    /// Switch on when more than this many neurons fired
+    pub threshold: u32,
+    /// Switch off at `threshold - hysteresis` firing neurons or fewer
+    pub hysteresis: u32,
+    /// How long a firing counts towards the population
+    pub window_ms: u32,
+}
+
+/// Recent firings of one output's population and its current state
+#[derive(Debug, Clone, Copy)]
+pub struct Population {
+    config: PopulationConfig,
+    /// esp_timer time (ms) each neuron last fired, 0 = never
+    last_fired_ms: [u32; MAX_POPULATION],
+    pub active: bool,
+}
+
+impl Population {
+    pub fn new(config: PopulationConfig) -> Self {
+        Self {
+            config,
+            last_fired_ms: [0; MAX_POPULATION],
+            active: false,
+        }
+    }
+
+    /// Record a motor command; returns false if the neuron isn't in the range
+    pub fn record(&mut self, neuron_id: u32, value: f32, now_ms: u32) -> bool {
+        if neuron_id < self.config.first || neuron_id > self.config.last {
+            return false;
+        }
+        let index = (neuron_id - self.config.first) as usize;
+        if index < MAX_POPULATION && value > 0.5 {
+            self.last_fired_ms[index] = now_ms.max(1);
+        }
+        true
+    }
+
+    /// Re-evaluate the population; returns the new state when it changed
+    pub fn update(&mut self, now_ms: u32) -> Option<bool> {
+        let window = self.config.window_ms;
+        let firing = self
+            .last_fired_ms
+            .iter()
+            .filter(|&&t| t != 0 && now_ms.wrapping_sub(t) <= window)
+            .count() as u32;
+        let active = if self.active {
+            firing > self.config.threshold.saturating_sub(self.config.hysteresis)
+        } else {
+            firing > self.config.threshold
+        };
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        Some(active)
+    }
+}
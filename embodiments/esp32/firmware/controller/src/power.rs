@@ -0,0 +1,242 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Battery voltage and current monitoring, with a low-battery safe-stop
+//!
+//! With config.json `power_monitor`, the battery is measured either through
+//! a resistor divider on an ADC1 pin (voltage only) or by an INA219 on the
+//! I2C bus (voltage and current through its shunt), at most every
+//! [`READ_INTERVAL_MS`] and repeated in between:
+//!
+//! | Neuron | Value                                                       |
+//! |--------|-------------------------------------------------------------|
+//! | N      | Voltage, 0.0 = `min_voltage`, 1.0 = `max_voltage`           |
+//! | N + 1  | Current (INA219), 0.0 = none or charging, 1.0 = `max_current` |
+//!
+//! With `low_voltage` set, a battery below it for `low_hold_ms` (so a motor
+//! start's sag doesn't count) safe-stops every output, as the status server
+//! does. It stays stopped until released there or the board restarts; the
+//! voltage has to recover above `low_voltage` before it can trip again.
+
+use esp_idf_svc::sys;
+
+use crate::adc::Adc1;
+use crate::cortical;
+use crate::i2c::I2cBus;
+
+/// Shortest time between two measurements
+pub const READ_INTERVAL_MS: u32 = 100;
+
+/// ADC conversions averaged per divider reading
+const ADC_SAMPLES: u32 = 8;
+
+/// INA219 registers
+const INA219_CONFIG: u8 = 0x00;
+const INA219_SHUNT_VOLTAGE: u8 = 0x01;
+const INA219_BUS_VOLTAGE: u8 = 0x02;
+/// 32 V bus range, ±320 mV shunt range, 12-bit, continuous (reset default)
+const INA219_CONFIG_DEFAULT: u16 = 0x399F;
+
+/// What measures the battery
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerSource {
+    /// Divider on an ADC1 pin: battery volts per volt at the pin
+    Adc { pin: u32, divider: f32 },
+    /// INA219 at `address`, with its shunt resistance
+    Ina219 { address: u8, shunt_ohms: f32 },
+}
+
+impl PowerSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowerSource::Adc { .. } => "adc",
+            PowerSource::Ina219 { .. } => "ina219",
+        }
+    }
+}
+
+/// Battery monitor settings (from config.json `power_monitor`)
+#[derive(Debug, Clone, Copy)]
+pub struct PowerConfig {
+    pub source: PowerSource,
+    /// Voltage range mapped to 0.0-1.0 (empty to full)
+    pub min_voltage: f32,
+    pub max_voltage: f32,
+    /// Current mapped to 1.0 (INA219)
+    pub max_current: f32,
+    /// "cortical_area:neuron_id" of the voltage neuron
+    pub cortical_mapping: &'static str,
+    /// Safe-stop threshold, if any
+    pub low_voltage: Option<f32>,
+    /// Time below `low_voltage` before the safe-stop
+    pub low_hold_ms: u32,
+}
+
+/// The ADC side of a divider measurement
+struct DividerAdc {
+    adc: Adc1,
+    channel: sys::adc_channel_t,
+    /// Line-fitting calibration, if the chip's eFuses allow it
+    cali: Option<sys::adc_cali_handle_t>,
+}
+
+/// A running battery monitor
+pub struct PowerMonitor {
+    config: PowerConfig,
+    first_neuron: u32,
+    adc: Option<DividerAdc>,
+    /// Latest measurement (volts, amps)
+    voltage: f32,
+    current: f32,
+    last_read_ms: Option<u32>,
+    /// When the battery dropped below `low_voltage`, while it stays there
+    low_since_ms: Option<u32>,
+    /// Tripped and not yet recovered
+    tripped: bool,
+    /// Trip not yet taken by the main loop
+    trip_pending: bool,
+}
+
+impl PowerMonitor {
+    /// Set up the measurement; None if the pin or chip doesn't respond
+    pub fn new(config: &PowerConfig, bus: Option<&mut I2cBus>) -> Option<Self> {
+        let first_neuron = cortical::neuron_id(config.cortical_mapping)?;
+        let adc = match config.source {
+            PowerSource::Adc { pin, .. } => {
+                let mut adc = Adc1::new()?;
+                let channel = adc.configure_pin(pin)?;
+                Some(DividerAdc {
+                    adc,
+                    channel,
+                    cali: line_fitting(),
+                })
+            }
+            PowerSource::Ina219 { address, .. } => {
+                if !bus?.write_registers(address, INA219_CONFIG, &INA219_CONFIG_DEFAULT.to_be_bytes()) {
+                    return None;
+                }
+                None
+            }
+        };
+        Some(Self {
+            config: *config,
+            first_neuron,
+            adc,
+            voltage: 0.0,
+            current: 0.0,
+            last_read_ms: None,
+            low_since_ms: None,
+            tripped: false,
+            trip_pending: false,
+        })
+    }
+
+    /// Measure if due, returning (neuron_id, value) of voltage and current
+    pub fn read(&mut self, bus: Option<&mut I2cBus>, now_ms: u32) -> impl Iterator<Item = (u32, f32)> {
+        let due = self.last_read_ms.map_or(true, |last| now_ms.wrapping_sub(last) >= READ_INTERVAL_MS);
+        if due && self.measure(bus) {
+            self.last_read_ms = Some(now_ms);
+            self.check_low(now_ms);
+        }
+
+        let span = (self.config.max_voltage - self.config.min_voltage).max(0.001);
+        let voltage = ((self.voltage - self.config.min_voltage) / span).clamp(0.0, 1.0);
+        let current = (self.current / self.config.max_current.max(0.001)).clamp(0.0, 1.0);
+        let channels = match self.config.source {
+            PowerSource::Adc { .. } => 1,
+            PowerSource::Ina219 { .. } => 2,
+        };
+        [(self.first_neuron, voltage), (self.first_neuron + 1, current)]
+            .into_iter()
+            .take(channels)
+    }
+
+    /// Latest battery voltage (V)
+    pub fn voltage(&self) -> f32 {
+        self.voltage
+    }
+
+    /// Whether the battery just went low (once per trip)
+    pub fn take_low_battery(&mut self) -> bool {
+        core::mem::take(&mut self.trip_pending)
+    }
+
+    /// Update `voltage`/`current`; false if the measurement failed
+    fn measure(&mut self, bus: Option<&mut I2cBus>) -> bool {
+        match self.config.source {
+            PowerSource::Adc { divider, .. } => {
+                let Some(ref mut divider_adc) = self.adc else {
+                    return false;
+                };
+                let mut sum = 0u32;
+                for _ in 0..ADC_SAMPLES {
+                    let Some(raw) = divider_adc.adc.read_raw(divider_adc.channel) else {
+                        return false;
+                    };
+                    sum += raw as u32;
+                }
+                let raw = (sum / ADC_SAMPLES) as i32;
+                let mut mv = raw * 3300 / 4095;
+                if let Some(cali) = divider_adc.cali {
+                    unsafe {
+                        sys::adc_cali_raw_to_voltage(cali, raw, &mut mv);
+                    }
+                }
+                self.voltage = mv as f32 / 1000.0 * divider;
+                true
+            }
+            PowerSource::Ina219 { address, shunt_ohms } => {
+                let Some(bus) = bus else {
+                    return false;
+                };
+                let (mut shunt, mut bus_voltage) = ([0u8; 2], [0u8; 2]);
+                if !bus.read_registers(address, INA219_SHUNT_VOLTAGE, &mut shunt)
+                    || !bus.read_registers(address, INA219_BUS_VOLTAGE, &mut bus_voltage)
+                {
+                    return false;
+                }
+                // Bus voltage in bits 15..3, 4 mV each; shunt voltage 10 µV each
+                self.voltage = (u16::from_be_bytes(bus_voltage) >> 3) as f32 * 0.004;
+                self.current = i16::from_be_bytes(shunt) as f32 * 0.000_01 / shunt_ohms;
+                true
+            }
+        }
+    }
+
+    /// Trip once the voltage has stayed below `low_voltage` for `low_hold_ms`
+    fn check_low(&mut self, now_ms: u32) {
+        let Some(low_voltage) = self.config.low_voltage else {
+            return;
+        };
+        if self.voltage >= low_voltage {
+            self.low_since_ms = None;
+            self.tripped = false;
+            return;
+        }
+        let since = *self.low_since_ms.get_or_insert(now_ms);
+        if !self.tripped && now_ms.wrapping_sub(since) >= self.config.low_hold_ms {
+            self.tripped = true;
+            self.trip_pending = true;
+        }
+    }
+}
+
+/// ADC1 line-fitting calibration at 11 dB, as `Adc1::configure_pin` sets up
+fn line_fitting() -> Option<sys::adc_cali_handle_t> {
+    let config = sys::adc_cali_line_fitting_config_t {
+        unit_id: sys::adc_unit_t_ADC_UNIT_1,
+        atten: sys::adc_atten_t_ADC_ATTEN_DB_11,
+        bitwidth: sys::adc_bitwidth_t_ADC_BITWIDTH_12,
+        // Used when the eFuses have no measured reference
+        default_vref: 1100,
+    };
+    let mut handle: sys::adc_cali_handle_t = core::ptr::null_mut();
+    if unsafe { sys::adc_cali_create_scheme_line_fitting(&config, &mut handle) } != sys::ESP_OK {
+        return None;
+    }
+    Some(handle)
+}
@@ -0,0 +1,65 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Sensory frames as binary FEAGI packets (config.json `"wire_format": "binary"`)
+//!
+//! The packet format, and decoding the host's motor packets, live in the
+//! feagi-controller-core crate (`protocol`); this lays out one burst's
+//! sensory frame as packets.
+
+use feagi_controller_core::protocol::{
+    push_entries, push_packet, tenths, unit, PKT_APPLIED, PKT_EDGES, PKT_END, PKT_FEEDBACK, PKT_HEALTH,
+    PKT_KEYFRAME, PKT_LINK, PKT_ODOMETRY, PKT_POTENTIALS,
+};
+use heapless::Vec;
+
+use crate::messages::SensoryFrame;
+use crate::FRAME_CAPACITY;
+
+pub use feagi_controller_core::protocol::{decode_packets, is_packet_start};
+
+/// One burst's sensory frame as packets; None if it doesn't fit FRAME_CAPACITY
+pub fn encode_frame(frame: &SensoryFrame) -> Option<Vec<u8, FRAME_CAPACITY>> {
+    let mut out = Vec::new();
+    let potentials = frame.np.iter().map(|&(id, pot)| (id.0, unit(pot.value())));
+    push_entries(&mut out, PKT_POTENTIALS, potentials)?;
+    if let Some(ref applied) = frame.ao {
+        push_entries(&mut out, PKT_APPLIED, applied.iter().map(|&(id, v)| (id.0, unit(v.0))))?;
+    }
+    if let Some(ref feedback) = frame.fb {
+        push_entries(&mut out, PKT_FEEDBACK, feedback.iter().map(|&(id, v)| (id.0, unit(v.0))))?;
+    }
+    if let Some(ref edges) = frame.ec {
+        push_entries(&mut out, PKT_EDGES, edges.iter().map(|&(id, n)| (id.0, n.min(u16::MAX as u32) as u16)))?;
+    }
+    if let Some(ref od) = frame.od {
+        let mut payload: Vec<u8, 20> = Vec::new();
+        for value in [od.x, od.y, od.th, od.d, od.dth] {
+            payload.extend_from_slice(&tenths(value.0).to_le_bytes()).ok()?;
+        }
+        push_packet(&mut out, PKT_ODOMETRY, &payload)?;
+    }
+    if let Some(ref link) = frame.wl {
+        push_entries(&mut out, PKT_LINK, link.iter().map(|&(id, v)| (id.0, unit(v.0))))?;
+    }
+    if let Some(load) = frame.cpu {
+        let celsius = frame.tc.map_or(i16::MIN, |t| tenths(t.0).clamp(i16::MIN as i32 + 1, i16::MAX as i32) as i16);
+        let [c0, c1] = celsius.to_le_bytes();
+        let [l0, l1] = unit(load.0).to_le_bytes();
+        push_packet(&mut out, PKT_HEALTH, &[c0, c1, l0, l1])?;
+    }
+    if let Some(kf) = frame.kf {
+        push_packet(&mut out, PKT_KEYFRAME, &[kf])?;
+    }
+    let mut end: Vec<u8, 12> = Vec::new();
+    end.extend_from_slice(&(frame.f as u32).to_le_bytes()).ok()?;
+    if let Some(t) = frame.t {
+        end.extend_from_slice(&t.to_le_bytes()).ok()?;
+    }
+    push_packet(&mut out, PKT_END, &end)?;
+    Some(out)
+}
@@ -0,0 +1,541 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WiFi provisioning over a SoftAP captive portal
+//!
+//! With `transport.config.provisioning` in config.json, the WiFi credentials
+//! and FEAGI address no longer have to be baked into the firmware. A board
+//! without stored credentials (or whose provisioning button is held at boot)
+//! starts an open or WPA2 access point instead of the controller, answers
+//! every DNS query with its own address so phones pop up the sign-in page,
+//! and serves a form for SSID, password and FEAGI host/port. Submitted
+//! values go to NVS and the board reboots into controller mode, where they
+//! take precedence over config.json. A BLE board can receive the same
+//! credentials over GATT instead (see ble.rs).
+
+use core::ffi::{c_char, c_void};
+use core::mem::size_of;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+use heapless::String;
+
+use crate::info;
+use crate::stored_config;
+use crate::transport::{ipv4_addr, received, set_rx_timeout};
+use crate::u32_to_string;
+use crate::wifi::{copy_truncated, init_config};
+
+/// Provisioning access point (from config.json `transport.config.provisioning`)
+#[derive(Debug, Clone, Copy)]
+pub struct ProvisioningConfig {
+    pub ap_ssid: &'static str,
+    /// Empty = open network
+    pub ap_password: &'static str,
+    /// Held low at boot: enter the portal even with stored credentials
+    pub button_pin: Option<i32>,
+}
+
+/// WiFi network and FEAGI endpoint entered through the portal
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub ssid: String<32>,
+    pub password: String<64>,
+    pub host: [u8; 4],
+    pub port: u16,
+}
+
+const NAMESPACE: &[u8] = b"feagi_prov\0";
+const KEY_SSID: &[u8] = b"ssid\0";
+const KEY_PASSWORD: &[u8] = b"password\0";
+const KEY_HOST: &[u8] = b"host\0";
+const KEY_PORT: &[u8] = b"port\0";
+
+/// Watch the provisioning button this long after boot
+const BUTTON_WINDOW_MS: u32 = 1000;
+
+/// Largest form submission accepted
+const FORM_CAPACITY: usize = 512;
+
+/// Set by the form handler once credentials are stored
+static SAVED: AtomicBool = AtomicBool::new(false);
+
+impl Credentials {
+    /// Credentials stored by an earlier provisioning, if any
+    pub fn load() -> Option<Self> {
+        if !init_nvs() {
+            return None;
+        }
+        unsafe {
+            let mut handle: sys::nvs_handle_t = 0;
+            if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READONLY, &mut handle) != sys::ESP_OK {
+                return None;
+            }
+            let credentials = read_credentials(handle);
+            sys::nvs_close(handle);
+            credentials
+        }
+    }
+
+    /// Persist to NVS; false if the write failed
+    pub fn store(&self) -> bool {
+        if !init_nvs() {
+            return false;
+        }
+        let mut ssid: String<33> = String::new();
+        let mut password: String<65> = String::new();
+        if ssid.push_str(&self.ssid).is_err()
+            || ssid.push('\0').is_err()
+            || password.push_str(&self.password).is_err()
+            || password.push('\0').is_err()
+        {
+            return false;
+        }
+        unsafe {
+            let mut handle: sys::nvs_handle_t = 0;
+            if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READWRITE, &mut handle) != sys::ESP_OK {
+                return false;
+            }
+            let ok = sys::nvs_set_str(handle, KEY_SSID.as_ptr() as *const c_char, ssid.as_ptr() as *const c_char) == sys::ESP_OK
+                && sys::nvs_set_str(handle, KEY_PASSWORD.as_ptr() as *const c_char, password.as_ptr() as *const c_char) == sys::ESP_OK
+                && sys::nvs_set_u32(handle, KEY_HOST.as_ptr() as *const c_char, u32::from_be_bytes(self.host)) == sys::ESP_OK
+                && sys::nvs_set_u16(handle, KEY_PORT.as_ptr() as *const c_char, self.port) == sys::ESP_OK
+                && sys::nvs_commit(handle) == sys::ESP_OK;
+            sys::nvs_close(handle);
+            ok && self.store_endpoint()
+        }
+    }
+
+    /// Store the FEAGI endpoint with the rest of the stored configuration
+    /// (stored_config.rs), so a later `store_config` can change it
+    fn store_endpoint(&self) -> bool {
+        let mut host: String<16> = String::new();
+        let mut num: String<16> = String::new();
+        for (i, octet) in self.host.iter().enumerate() {
+            if i > 0 {
+                let _ = host.push('.');
+            }
+            u32_to_string(*octet as u32, &mut num);
+            let _ = host.push_str(&num);
+        }
+        u32_to_string(self.port as u32, &mut num);
+        stored_config::store(&[("transport.host", &host), ("transport.port", &num)])
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` submission with `ssid`,
+    /// `password`, `host` (dotted IPv4) and `port`
+    pub fn from_form(body: &[u8]) -> Option<Self> {
+        let mut ssid: Option<String<32>> = None;
+        let mut password: String<64> = String::new();
+        let mut host: Option<[u8; 4]> = None;
+        let mut port: Option<u16> = None;
+        for pair in body.split(|&b| b == b'&') {
+            let mut parts = pair.splitn(2, |&b| b == b'=');
+            let key = parts.next()?;
+            let value = parts.next().unwrap_or(b"");
+            match key {
+                b"ssid" => ssid = Some(url_decode(value)?),
+                b"password" => password = url_decode(value)?,
+                b"host" => host = parse_ipv4(&url_decode::<15>(value)?),
+                b"port" => port = url_decode::<5>(value)?.parse().ok().filter(|&p| p != 0),
+                _ => {}
+            }
+        }
+        let ssid = ssid.filter(|s| !s.is_empty())?;
+        Some(Self { ssid, password, host: host?, port: port? })
+    }
+
+    /// From raw SSID and password values and a `host:port` endpoint (as
+    /// written to the BLE provisioning characteristics)
+    pub fn from_parts(ssid: &[u8], password: &[u8], endpoint: &[u8]) -> Option<Self> {
+        let endpoint = core::str::from_utf8(endpoint).ok()?;
+        let (host, port) = endpoint.split_once(':')?;
+        let port = port.parse().ok().filter(|&p| p != 0)?;
+        if ssid.is_empty() {
+            return None;
+        }
+        Some(Self {
+            ssid: String::from_str(core::str::from_utf8(ssid).ok()?).ok()?,
+            password: String::from_str(core::str::from_utf8(password).ok()?).ok()?,
+            host: parse_ipv4(host)?,
+            port,
+        })
+    }
+}
+
+/// Bring up NVS (WiFi calibration data and stored credentials live there)
+pub fn init_nvs() -> bool {
+    unsafe {
+        let mut ret = sys::nvs_flash_init();
+        if ret == sys::ESP_ERR_NVS_NO_FREE_PAGES as i32 || ret == sys::ESP_ERR_NVS_NEW_VERSION_FOUND as i32 {
+            sys::nvs_flash_erase();
+            ret = sys::nvs_flash_init();
+        }
+        ret == sys::ESP_OK
+    }
+}
+
+/// A provisioning button on `pin` is held (low) during the first second
+/// after boot
+pub fn button_held(pin: i32) -> bool {
+    unsafe {
+        sys::gpio_reset_pin(pin);
+        sys::gpio_set_direction(pin, sys::gpio_mode_t_GPIO_MODE_INPUT);
+        sys::gpio_set_pull_mode(pin, sys::gpio_pull_mode_t_GPIO_PULLUP_ONLY);
+    }
+    let mut waited_ms = 0;
+    while waited_ms < BUTTON_WINDOW_MS {
+        FreeRtos::delay_ms(10);
+        waited_ms += 10;
+        if unsafe { sys::gpio_get_level(pin) } == 0 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Run the captive portal until credentials are submitted, then reboot
+///
+/// Only returns (false) if the access point or HTTP server couldn't be
+/// started.
+pub fn run_portal(config: &ProvisioningConfig) -> bool {
+    let Some(ap_ip) = start_access_point(config) else {
+        return false;
+    };
+    if !start_http_server() {
+        return false;
+    }
+    info!("Provisioning: join the setup access point and open http://{}.{}.{}.{}/", ap_ip[0], ap_ip[1], ap_ip[2], ap_ip[3]);
+
+    let dns = DnsResponder::bind(ap_ip);
+    while !SAVED.load(Ordering::Acquire) {
+        match dns {
+            Some(ref dns) => dns.answer_one(500),
+            None => FreeRtos::delay_ms(500),
+        }
+    }
+    // Let the confirmation page reach the browser
+    FreeRtos::delay_ms(1000);
+    unsafe {
+        info!("Provisioning: credentials stored, rebooting");
+        sys::esp_restart()
+    }
+}
+
+/// Start the SoftAP; its IPv4 address
+fn start_access_point(config: &ProvisioningConfig) -> Option<[u8; 4]> {
+    if !init_nvs() {
+        return None;
+    }
+    unsafe {
+        if sys::esp_netif_init() != sys::ESP_OK {
+            return None;
+        }
+        let ret = sys::esp_event_loop_create_default();
+        if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+            return None;
+        }
+        let netif = sys::esp_netif_create_default_wifi_ap();
+        if netif.is_null() {
+            return None;
+        }
+        let init = init_config();
+        if sys::esp_wifi_init(&init) != sys::ESP_OK {
+            return None;
+        }
+        sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM);
+        sys::esp_wifi_set_mode(sys::wifi_mode_t_WIFI_MODE_AP);
+
+        let mut wifi_config: sys::wifi_config_t = core::mem::zeroed();
+        copy_truncated(&mut wifi_config.ap.ssid, config.ap_ssid);
+        wifi_config.ap.ssid_len = config.ap_ssid.len() as u8;
+        copy_truncated(&mut wifi_config.ap.password, config.ap_password);
+        wifi_config.ap.authmode = if config.ap_password.is_empty() {
+            sys::wifi_auth_mode_t_WIFI_AUTH_OPEN
+        } else {
+            sys::wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK
+        };
+        wifi_config.ap.channel = 1;
+        wifi_config.ap.max_connection = 4;
+        if sys::esp_wifi_set_config(sys::wifi_interface_t_WIFI_IF_AP, &mut wifi_config) != sys::ESP_OK
+            || sys::esp_wifi_start() != sys::ESP_OK
+        {
+            return None;
+        }
+
+        let mut ip: sys::esp_netif_ip_info_t = core::mem::zeroed();
+        if sys::esp_netif_get_ip_info(netif, &mut ip) != sys::ESP_OK {
+            return None;
+        }
+        Some(ip.ip.addr.to_ne_bytes())
+    }
+}
+
+/// HTTPD_DEFAULT_CONFIG() on `port`, plus wildcard URI matching
+///
+/// Shared with the status server (status_server.rs).
+pub fn http_config(port: u16) -> sys::httpd_config_t {
+    let mut config: sys::httpd_config_t = unsafe { core::mem::zeroed() };
+    config.task_priority = 5;
+    config.stack_size = 6144;
+    config.core_id = sys::tskNO_AFFINITY as _;
+    config.server_port = port;
+    config.ctrl_port = sys::ESP_HTTPD_DEF_CTRL_PORT as _;
+    config.max_open_sockets = 7;
+    config.max_uri_handlers = 8;
+    config.max_resp_headers = 8;
+    config.backlog_conn = 5;
+    config.recv_wait_timeout = 5;
+    config.send_wait_timeout = 5;
+    config.uri_match_fn = Some(sys::httpd_uri_match_wildcard);
+    config
+}
+
+/// esp_http_server with the form on every GET and the submission on POST /save
+fn start_http_server() -> bool {
+    unsafe {
+        // Wildcard matching so captive-portal probes (/generate_204,
+        // /hotspot-detect.html, ...) get the form
+        let config = http_config(80);
+        let mut server: sys::httpd_handle_t = core::ptr::null_mut();
+        if sys::httpd_start(&mut server, &config) != sys::ESP_OK {
+            return false;
+        }
+
+        let mut save: sys::httpd_uri_t = core::mem::zeroed();
+        save.uri = b"/save\0".as_ptr() as *const c_char;
+        save.method = sys::http_method_HTTP_POST;
+        save.handler = Some(handle_save);
+        let mut form: sys::httpd_uri_t = core::mem::zeroed();
+        form.uri = b"/*\0".as_ptr() as *const c_char;
+        form.method = sys::http_method_HTTP_GET;
+        form.handler = Some(handle_form);
+        sys::httpd_register_uri_handler(server, &save) == sys::ESP_OK
+            && sys::httpd_register_uri_handler(server, &form) == sys::ESP_OK
+    }
+}
+
+const FORM_PAGE: &str = "<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\">\
+<title>FEAGI controller setup</title></head><body><h3>FEAGI controller setup</h3>\
+<form method=\"post\" action=\"/save\">\
+<p>WiFi network<br><input name=\"ssid\" maxlength=\"32\" required></p>\
+<p>WiFi password<br><input name=\"password\" type=\"password\" maxlength=\"64\"></p>\
+<p>FEAGI host (IPv4)<br><input name=\"host\" maxlength=\"15\" required></p>\
+<p>FEAGI port<br><input name=\"port\" type=\"number\" min=\"1\" max=\"65535\" required></p>\
+<p><button type=\"submit\">Save and reboot</button></p></form></body></html>";
+
+const SAVED_PAGE: &str = "<!DOCTYPE html><html><body><h3>Saved</h3>\
+<p>The controller reboots and joins the network.</p></body></html>";
+
+const INVALID_PAGE: &str = "<!DOCTYPE html><html><body><h3>Invalid settings</h3>\
+<p>Check the network name, FEAGI host and port, then <a href=\"/\">try again</a>.</p></body></html>";
+
+unsafe extern "C" fn handle_form(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    send_page(req, FORM_PAGE)
+}
+
+unsafe extern "C" fn handle_save(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    let mut body = [0u8; FORM_CAPACITY];
+    let len = (*req).content_len;
+    if len > body.len() {
+        sys::httpd_resp_set_status(req, b"413 Payload Too Large\0".as_ptr() as *const c_char);
+        return send_page(req, INVALID_PAGE);
+    }
+    let mut filled = 0;
+    while filled < len {
+        let n = sys::httpd_req_recv(req, body[filled..].as_mut_ptr() as *mut c_char, len - filled);
+        if n <= 0 {
+            // Connection dropped or timed out; the server closes it
+            return sys::ESP_FAIL;
+        }
+        filled += n as usize;
+    }
+
+    match Credentials::from_form(&body[..len]) {
+        Some(credentials) if credentials.store() => {
+            SAVED.store(true, Ordering::Release);
+            send_page(req, SAVED_PAGE)
+        }
+        _ => {
+            sys::httpd_resp_set_status(req, b"400 Bad Request\0".as_ptr() as *const c_char);
+            send_page(req, INVALID_PAGE)
+        }
+    }
+}
+
+unsafe fn send_page(req: *mut sys::httpd_req_t, page: &str) -> sys::esp_err_t {
+    sys::httpd_resp_set_type(req, b"text/html\0".as_ptr() as *const c_char);
+    sys::httpd_resp_send(req, page.as_ptr() as *const c_char, page.len() as _)
+}
+
+/// Answers every DNS A query with the access point's address
+struct DnsResponder {
+    fd: i32,
+    ap_ip: [u8; 4],
+}
+
+impl DnsResponder {
+    fn bind(ap_ip: [u8; 4]) -> Option<Self> {
+        unsafe {
+            let fd = sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_DGRAM as i32, sys::IPPROTO_UDP as i32);
+            if fd < 0 {
+                return None;
+            }
+            // Closes the socket if binding fails
+            let dns = Self { fd, ap_ip };
+            let local = ipv4_addr([0, 0, 0, 0], 53);
+            if sys::lwip_bind(fd, &local as *const sys::sockaddr_in as *const sys::sockaddr, size_of::<sys::sockaddr_in>() as u32) != 0 {
+                return None;
+            }
+            let mut rx_timeout_ms = 0;
+            set_rx_timeout(fd, &mut rx_timeout_ms, 500);
+            Some(dns)
+        }
+    }
+
+    /// Wait up to the socket timeout for one query and answer it
+    fn answer_one(&self, timeout_ms: u32) {
+        let mut packet = [0u8; 512];
+        let mut from: sys::sockaddr_in = unsafe { core::mem::zeroed() };
+        let mut from_len = size_of::<sys::sockaddr_in>() as u32;
+        let n = unsafe {
+            sys::lwip_recvfrom(
+                self.fd,
+                packet.as_mut_ptr() as *mut c_void,
+                packet.len(),
+                0,
+                &mut from as *mut sys::sockaddr_in as *mut sys::sockaddr,
+                &mut from_len,
+            )
+        };
+        let len = match received(n) {
+            Ok(len) if len > 0 => len,
+            Ok(_) => return,
+            // Don't spin on a broken socket
+            Err(()) => return FreeRtos::delay_ms(timeout_ms),
+        };
+        if let Some(reply_len) = dns_reply(&mut packet, len, self.ap_ip) {
+            unsafe {
+                sys::lwip_sendto(
+                    self.fd,
+                    packet.as_ptr() as *const c_void,
+                    reply_len,
+                    0,
+                    &from as *const sys::sockaddr_in as *const sys::sockaddr,
+                    from_len,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for DnsResponder {
+    fn drop(&mut self) {
+        unsafe {
+            sys::lwip_close(self.fd);
+        }
+    }
+}
+
+/// Turn the single-question query in `packet[..len]` into a reply, with an
+/// answer pointing at `ip` for A queries; the reply's length
+fn dns_reply(packet: &mut [u8], len: usize, ip: [u8; 4]) -> Option<usize> {
+    // Standard queries with exactly one question
+    if len < 12 || packet[2] & 0xF8 != 0 || packet[4..6] != [0, 1] {
+        return None;
+    }
+    let mut pos = 12;
+    loop {
+        let label = *packet[..len].get(pos)? as usize;
+        if label == 0 {
+            break;
+        }
+        if label & 0xC0 != 0 {
+            return None;
+        }
+        pos += 1 + label;
+    }
+    let question_end = pos + 5;
+    if question_end > len {
+        return None;
+    }
+    // Response, recursion desired/available, no records yet
+    packet[2] = 0x81;
+    packet[3] = 0x80;
+    packet[6..12].copy_from_slice(&[0, 0, 0, 0, 0, 0]);
+    // AAAA and friends get an empty answer, so clients fall back to A quickly
+    if packet[pos + 1..pos + 3] != [0, 1] {
+        return Some(question_end);
+    }
+    packet[7] = 1;
+    // Name pointer to the question, type A, class IN, TTL 60 s, 4 bytes
+    let answer: [u8; 16] = [0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, ip[0], ip[1], ip[2], ip[3]];
+    packet.get_mut(question_end..question_end + answer.len())?.copy_from_slice(&answer);
+    Some(question_end + answer.len())
+}
+
+/// Read the stored strings, host and port from an open handle
+unsafe fn read_credentials(handle: sys::nvs_handle_t) -> Option<Credentials> {
+    let mut ssid = [0u8; 33];
+    let mut password = [0u8; 65];
+    let mut host: u32 = 0;
+    let mut port: u16 = 0;
+    let ssid = read_str(handle, KEY_SSID, &mut ssid)?;
+    let password = read_str(handle, KEY_PASSWORD, &mut password)?;
+    if sys::nvs_get_u32(handle, KEY_HOST.as_ptr() as *const c_char, &mut host) != sys::ESP_OK
+        || sys::nvs_get_u16(handle, KEY_PORT.as_ptr() as *const c_char, &mut port) != sys::ESP_OK
+    {
+        return None;
+    }
+    Some(Credentials {
+        ssid: String::from_str(ssid).ok()?,
+        password: String::from_str(password).ok()?,
+        host: host.to_be_bytes(),
+        port,
+    })
+}
+
+unsafe fn read_str<'a>(handle: sys::nvs_handle_t, key: &[u8], buf: &'a mut [u8]) -> Option<&'a str> {
+    let mut len = buf.len();
+    if sys::nvs_get_str(handle, key.as_ptr() as *const c_char, buf.as_mut_ptr() as *mut c_char, &mut len) != sys::ESP_OK {
+        return None;
+    }
+    // `len` counts the terminating NUL
+    core::str::from_utf8(&buf[..len.saturating_sub(1)]).ok()
+}
+
+/// Decode a form value (`+` is a space, `%XX` a byte)
+fn url_decode<const N: usize>(value: &[u8]) -> Option<String<N>> {
+    let mut bytes: heapless::Vec<u8, N> = heapless::Vec::new();
+    let mut i = 0;
+    while i < value.len() {
+        let byte = match value[i] {
+            b'+' => b' ',
+            b'%' => {
+                let hex = core::str::from_utf8(value.get(i + 1..i + 3)?).ok()?;
+                i += 2;
+                u8::from_str_radix(hex, 16).ok()?
+            }
+            b => b,
+        };
+        bytes.push(byte).ok()?;
+        i += 1;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Octets of a dotted IPv4 address
+pub fn parse_ipv4(host: &str) -> Option<[u8; 4]> {
+    let mut octets = [0u8; 4];
+    let mut parts = host.split('.');
+    for octet in &mut octets {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(octets)
+}
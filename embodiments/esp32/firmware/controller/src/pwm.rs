@@ -0,0 +1,120 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! LEDC PWM for `pwm_output` and `servo_output` pins
+//!
+//! On a PWM output, a motor command of 0.0-1.0 sets the duty cycle.
+//! Frequency and duty resolution are per pin (config.json `pwm`); build.rs
+//! hands out the LEDC channels and lets pins with the same settings share a
+//! timer, so a board has at most 8 PWM and servo outputs with at most 4
+//! different settings.
+//!
+//! A servo output is a 50 Hz, 16-bit PWM output whose command is an angle
+//! instead: 0.0-1.0 sweeps the configured angle limits, and the angle sets
+//! the pulse width between the servo's min and max pulse ([`ServoConfig`]).
+//!
+//! Low-speed mode is used throughout: it is clocked from the 80 MHz APB
+//! clock like high-speed mode, and is the only mode on later ESP32 chips.
+
+use esp_idf_svc::sys;
+
+/// LEDC setup of one PWM output (from config.json `pwm`; timer and channel
+/// assigned by build.rs)
+#[derive(Debug, Clone, Copy)]
+pub struct PwmConfig {
+    pub frequency_hz: u32,
+    /// Duty resolution in bits (1-20)
+    pub resolution_bits: u32,
+    pub timer: u32,
+    pub channel: u32,
+}
+
+const SPEED_MODE: sys::ledc_mode_t = sys::ledc_mode_t_LEDC_LOW_SPEED_MODE;
+
+/// Pulse period of a hobby servo (50 Hz)
+pub const SERVO_PERIOD_US: u32 = 20_000;
+
+/// Pulse and angle limits of a hobby servo (from config.json `servo`)
+#[derive(Debug, Clone, Copy)]
+pub struct ServoConfig {
+    /// Pulse widths at the ends of the servo's travel (0 and `range_deg`)
+    pub min_pulse_us: u32,
+    pub max_pulse_us: u32,
+    /// Full travel of the servo, in degrees
+    pub range_deg: u32,
+    /// Angles commanded by 0.0 and 1.0, within 0..=`range_deg`
+    pub min_angle_deg: u32,
+    pub max_angle_deg: u32,
+}
+
+impl ServoConfig {
+    /// Duty cycle (0.0-1.0 of the 20 ms period) for a 0.0-1.0 command
+    pub fn duty(&self, value: f32) -> f32 {
+        let angle = self.min_angle_deg as f32 + value.clamp(0.0, 1.0) * (self.max_angle_deg as f32 - self.min_angle_deg as f32);
+        let pulse_us = self.min_pulse_us as f32
+            + angle / self.range_deg as f32 * (self.max_pulse_us as f32 - self.min_pulse_us as f32);
+        pulse_us / SERVO_PERIOD_US as f32
+    }
+}
+
+/// Route `pin` to its LEDC channel, starting at duty `value` (0.0-1.0)
+///
+/// Configures the channel's timer first; pins sharing a timer configure it
+/// with the same settings. Returns the duty actually set (quantized to the
+/// resolution), or None if LEDC rejected the settings.
+pub fn attach(pin: u32, config: &PwmConfig, value: f32) -> Option<f32> {
+    let (steps, duty) = quantize(config, value);
+    unsafe {
+        let mut timer: sys::ledc_timer_config_t = core::mem::zeroed();
+        timer.speed_mode = SPEED_MODE;
+        timer.duty_resolution = config.resolution_bits;
+        timer.timer_num = config.timer;
+        timer.freq_hz = config.frequency_hz;
+        timer.clk_cfg = sys::ledc_clk_cfg_t_LEDC_AUTO_CLK;
+        if sys::ledc_timer_config(&timer) != sys::ESP_OK {
+            return None;
+        }
+
+        let mut channel: sys::ledc_channel_config_t = core::mem::zeroed();
+        channel.gpio_num = pin as i32;
+        channel.speed_mode = SPEED_MODE;
+        channel.channel = config.channel;
+        channel.intr_type = sys::ledc_intr_type_t_LEDC_INTR_DISABLE;
+        channel.timer_sel = config.timer;
+        channel.duty = steps;
+        channel.hpoint = 0;
+        if sys::ledc_channel_config(&channel) != sys::ESP_OK {
+            return None;
+        }
+    }
+    Some(duty)
+}
+
+/// Set the duty cycle of an attached channel to `value` (0.0-1.0)
+///
+/// Returns the duty actually set, or None if LEDC rejected it.
+pub fn set_duty(config: &PwmConfig, value: f32) -> Option<f32> {
+    let (steps, duty) = quantize(config, value);
+    unsafe {
+        if sys::ledc_set_duty(SPEED_MODE, config.channel, steps) != sys::ESP_OK
+            || sys::ledc_update_duty(SPEED_MODE, config.channel) != sys::ESP_OK
+        {
+            return None;
+        }
+    }
+    Some(duty)
+}
+
+/// Duty in LEDC steps and the 0.0-1.0 value it stands for
+///
+/// A full period is 2^resolution steps, so 1.0 keeps the pin high.
+fn quantize(config: &PwmConfig, value: f32) -> (u32, f32) {
+    let period = 1u32 << config.resolution_bits;
+    let steps = (value.clamp(0.0, 1.0) * period as f32 + 0.5) as u32;
+    let steps = steps.min(period);
+    (steps, steps as f32 / period as f32)
+}
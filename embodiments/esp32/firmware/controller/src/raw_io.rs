@@ -0,0 +1,222 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Raw GPIO pass-through ("dumb mode")
+//!
+//! For users who just want remote GPIO without cortical semantics. The host
+//! picks the session mode right after the hello with `{"mode":"raw"}` (or
+//! `{"mode":"feagi"}`), answered by `{"mode_ack":"raw"}`. Once the session
+//! has started (the first motor command or raw telegram) the mode is fixed
+//! until the next hello, and a selection is answered by
+//! `{"mode_err":"session started","mode":"raw"}`. In raw mode no FEAGI
+//! frames are sent and motor commands are ignored; instead:
+//!
+//! - `{"rd":4}` reads pin 4 once, answered by `{"pv":[[4,1]]}`
+//! - `{"wr":[4,1]}` drives pin 4 high (`0` drives it low)
+//! - `{"st":[4,5]}` streams pins 4 and 5 every burst as
+//!   `{"pv":[[4,1],[5,0]],"f":N}`; `{"st":[]}` stops streaming
+//!
+//! Only the GPIOs in `RAW_PINS` are accessible: build.rs leaves out the
+//! UART, flash and strapping pins and every pin config.json wires to
+//! something. Anything else is answered by `{"raw_err":<pin>}`. Leaving raw
+//! mode resets the pins it touched to their power-on state.
+
+use esp_idf_svc::sys;
+use feagi_controller_core::parse;
+use heapless::{String, Vec};
+
+use crate::u32_to_string;
+use crate::RAW_PINS;
+
+/// Pins streamed at once
+const MAX_STREAM_PINS: usize = 20;
+
+/// What the session is speaking, chosen by the host at handshake
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionMode {
+    /// Cortical mapping: neuron frames and motor commands (default)
+    Feagi,
+    /// Raw pin telegrams
+    Raw,
+}
+
+impl SessionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionMode::Feagi => "feagi",
+            SessionMode::Raw => "raw",
+        }
+    }
+
+    /// Parse a `{"mode":"raw"|"feagi"}` selection
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.starts_with("{\"mode\"") {
+            return None;
+        }
+        match parse::words(message).get(1).copied() {
+            Some("feagi") => Some(SessionMode::Feagi),
+            Some("raw") => Some(SessionMode::Raw),
+            _ => None,
+        }
+    }
+
+    /// `{"mode_ack":"..."}` line confirming the mode in effect
+    pub fn ack_line(&self) -> String<64> {
+        let mut line: String<64> = String::from("{\"mode_ack\":\"");
+        let _ = line.push_str(self.as_str());
+        let _ = line.push_str("\"}\n");
+        line
+    }
+
+    /// `{"mode_err":...}` line refusing a selection after the handshake, with
+    /// the mode still in effect
+    pub fn refused_line(&self) -> String<64> {
+        let mut line: String<64> = String::from("{\"mode_err\":\"session started\",\"mode\":\"");
+        let _ = line.push_str(self.as_str());
+        let _ = line.push_str("\"}\n");
+        line
+    }
+}
+
+/// Raw pin access and the stream set
+pub struct RawIo {
+    stream: Vec<u32, MAX_STREAM_PINS>,
+    /// Pins switched to output by a `wr` telegram (bit per GPIO)
+    driven: u64,
+    /// Pins whose direction raw mode set, by a read or a write
+    touched: u64,
+}
+
+impl RawIo {
+    pub fn new() -> Self {
+        Self { stream: Vec::new(), driven: 0, touched: 0 }
+    }
+
+    /// Leave raw mode: stop streaming and reset every pin raw mode touched
+    /// (input and output off, pull-up on, as at power-on), so none is left
+    /// driving when the cortical mapping takes over
+    pub fn leave(&mut self) {
+        self.stream.clear();
+        for &pin in RAW_PINS {
+            if self.touched & (1 << pin) != 0 {
+                unsafe {
+                    sys::gpio_reset_pin(pin as i32);
+                }
+            }
+        }
+        self.driven = 0;
+        self.touched = 0;
+    }
+
+    /// Handle one raw telegram
+    ///
+    /// Returns the reply line, if the telegram has one.
+    pub fn handle(&mut self, message: &str) -> Option<String<256>> {
+        let words = parse::words(message);
+        let (&command, args) = words.split_first()?;
+        match command {
+            "rd" => {
+                let pin: u32 = args.first()?.parse().ok()?;
+                if !RAW_PINS.contains(&pin) {
+                    return Some(error_line(pin));
+                }
+                let mut line: String<256> = String::from("{\"pv\":[");
+                push_pin_value(&mut line, pin, self.read(pin));
+                let _ = line.push_str("]}\n");
+                Some(line)
+            }
+            "wr" => {
+                let pin: u32 = args.first()?.parse().ok()?;
+                let level: f32 = args.get(1)?.parse().ok()?;
+                if !RAW_PINS.contains(&pin) {
+                    return Some(error_line(pin));
+                }
+                self.write(pin, level > 0.5);
+                None
+            }
+            "st" => {
+                self.stream.clear();
+                for arg in args {
+                    let pin: u32 = arg.parse().ok()?;
+                    if !RAW_PINS.contains(&pin) {
+                        self.stream.clear();
+                        return Some(error_line(pin));
+                    }
+                    if !self.stream.contains(&pin) {
+                        let _ = self.stream.push(pin);
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// `{"pv":[...],"f":N}` line for the streamed pins, if any
+    pub fn stream_line(&mut self, frame_number: u64) -> Option<String<256>> {
+        if self.stream.is_empty() {
+            return None;
+        }
+        let mut line: String<256> = String::from("{\"pv\":[");
+        for i in 0..self.stream.len() {
+            if i > 0 {
+                let _ = line.push(',');
+            }
+            let pin = self.stream[i];
+            let level = self.read(pin);
+            push_pin_value(&mut line, pin, level);
+        }
+        let _ = line.push_str("],\"f\":");
+        let mut num: String<24> = String::new();
+        crate::u64_to_string(frame_number, &mut num);
+        let _ = line.push_str(num.as_str());
+        let _ = line.push_str("}\n");
+        Some(line)
+    }
+
+    fn read(&mut self, pin: u32) -> bool {
+        let gpio = pin as i32;
+        unsafe {
+            if self.touched & (1 << pin) == 0 {
+                sys::gpio_set_direction(gpio, sys::gpio_mode_t_GPIO_MODE_INPUT);
+                self.touched |= 1 << pin;
+            }
+            sys::gpio_get_level(gpio) != 0
+        }
+    }
+
+    fn write(&mut self, pin: u32, high: bool) {
+        let gpio = pin as i32;
+        unsafe {
+            if self.driven & (1 << pin) == 0 {
+                // Input-output so reads of a driven pin return its level
+                sys::gpio_hold_dis(gpio);
+                sys::gpio_set_direction(gpio, sys::gpio_mode_t_GPIO_MODE_INPUT_OUTPUT);
+                self.driven |= 1 << pin;
+                self.touched |= 1 << pin;
+            }
+            sys::gpio_set_level(gpio, high as u32);
+        }
+    }
+}
+
+fn push_pin_value(line: &mut String<256>, pin: u32, high: bool) {
+    let mut num: String<16> = String::new();
+    u32_to_string(pin, &mut num);
+    let _ = line.push('[');
+    let _ = line.push_str(num.as_str());
+    let _ = line.push_str(if high { ",1]" } else { ",0]" });
+}
+
+fn error_line(pin: u32) -> String<256> {
+    let mut line: String<256> = String::from("{\"raw_err\":");
+    let mut num: String<16> = String::new();
+    u32_to_string(pin, &mut num);
+    let _ = line.push_str(num.as_str());
+    let _ = line.push_str("}\n");
+    line
+}
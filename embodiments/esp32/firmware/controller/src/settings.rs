@@ -0,0 +1,1040 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Effective configuration and where each value came from
+//!
+//! Values start as the build-time constants generated from config.json and
+//! may be overridden at runtime by the host (e.g. burst-rate policy
+//! negotiation). `{"get_config":1}` returns everything the board is actually
+//! using, one `{"v":<value>,"src":"build|nvs|runtime"}` object per field:
+//!
+//! `{"effective_config":{"burst_frequency":{"v":100,"src":"build"},...,"gpio":[...]}}`
+//!
+//! The link encryption key is never reported, only whether it is set.
+//!
+//! The runtime-settable subset can also be read and written by name in one
+//! round trip. `{"set_settings":{"rate_policy.motor":"interp","barrier.timeout_ms":8}}`
+//! validates every pair first and applies them all or none, answered by
+//! `{"settings_ack":{"ok":true,"n":2}}` or
+//! `{"settings_ack":{"ok":false,"key":"...","error":"..."}}`.
+//! `{"get_settings":["mode","rate_policy.ratio"]}` (or `[]` for all) is
+//! answered by `{"settings":{"mode":"feagi","rate_policy.ratio":1}}`.
+//!
+//! Settings changed this way last until the next reboot (the device ID
+//! excepted); `{"store_config":{...}}` stores them in NVS instead, along with
+//! the ones only read at boot, `mode`, `burst_frequency`, `transport.host`,
+//! `transport.port`, `gpio.<pin>.mode` and `gpio.<pin>.cortical_mapping` (see
+//! stored_config.rs).
+//! Those can be read with `get_settings` too.
+
+use core::str::FromStr;
+
+use feagi_controller_core::parse;
+use feagi_controller_core::rate_policy::{MotorPolicy, RatePolicy, SensoryPolicy};
+use heapless::{String, Vec};
+
+use crate::device_id::{self, DeviceId};
+use crate::provisioning::parse_ipv4;
+use crate::raw_io::SessionMode;
+use crate::stored_config;
+use crate::*;
+
+/// Keys accepted by `get_settings`/`set_settings`
+pub const SETTABLE_KEYS: [&str; 13] = [
+    "device_id",
+    "barrier.timeout_ms",
+    "log.level",
+    "log.forward",
+    "rate_policy.motor",
+    "rate_policy.sensory",
+    "rate_policy.ratio",
+    "rate_policy.decay_after_ms",
+    "rate_policy.decay_ms",
+    "rate_policy.neutral",
+    "rate_policy.delta",
+    "rate_policy.delta_epsilon",
+    "rate_policy.keyframe_ms",
+];
+
+/// Settings only read at boot, so only changed through `store_config`
+/// (besides `gpio.<pin>.mode` and `gpio.<pin>.cortical_mapping`); the
+/// session mode otherwise changes only at the handshake (see raw_io.rs)
+pub const BOOT_KEYS: [&str; 4] = ["mode", "burst_frequency", "transport.host", "transport.port"];
+
+/// GPIO entries in config.json
+pub const GPIO_PINS: usize = GPIO_CONFIG.len();
+
+/// Longest stored `cortical_mapping`
+pub const MAX_MAPPING_LEN: usize = 32;
+
+pub type Mapping = String<MAX_MAPPING_LEN>;
+
+/// Why a `set_settings` batch was rejected
+#[derive(Debug, Clone)]
+pub struct SettingsError {
+    /// The key at fault, as the host sent it
+    pub key: Option<KeyName>,
+    pub reason: &'static str,
+}
+
+/// A setting's name in an error, cut to fit
+pub type KeyName = String<48>;
+
+/// A `get_config` or `get_settings` answer that didn't fit
+/// CONFIG_DUMP_CAPACITY; build.rs sizes that for the longest answer, so this
+/// means the two disagree
+#[derive(Debug, Clone, Copy)]
+pub struct ReplyOverflow {
+    pub capacity: usize,
+}
+
+impl SettingsError {
+    /// Error about `key`
+    pub fn at(key: &str, reason: &'static str) -> Self {
+        let mut name = KeyName::new();
+        for c in key.chars() {
+            if name.push(c).is_err() {
+                break;
+            }
+        }
+        Self { key: Some(name), reason }
+    }
+}
+
+/// Where an effective value came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// config.json at build time
+    Build,
+    /// Set by the host before a reboot, kept in NVS
+    Stored,
+    /// Changed by the host since boot
+    Runtime,
+}
+
+impl Source {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Source::Build => "build",
+            Source::Stored => "nvs",
+            Source::Runtime => "runtime",
+        }
+    }
+}
+
+/// A value together with its source
+#[derive(Debug, Clone, Copy)]
+pub struct Tracked<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Tracked<T> {
+    pub const fn build(value: T) -> Self {
+        Self { value, source: Source::Build }
+    }
+
+    pub const fn stored(value: T) -> Self {
+        Self { value, source: Source::Stored }
+    }
+
+    pub fn set_runtime(&mut self, value: T) {
+        self.set(value, Source::Runtime);
+    }
+
+    pub fn set(&mut self, value: T, source: Source) {
+        self.value = value;
+        self.source = source;
+    }
+}
+
+/// Settings the host can change at runtime or store in NVS
+#[derive(Clone)]
+pub struct Settings {
+    pub device_id: Tracked<DeviceId>,
+    pub rate_policy: Tracked<RatePolicy>,
+    pub mode: Tracked<SessionMode>,
+    pub barrier_timeout_ms: Tracked<u32>,
+    pub log_level: Tracked<Level>,
+    /// Lines forwarded to the host (None = off)
+    pub log_forward: Tracked<Option<Level>>,
+    /// The rest are read at boot (see stored_config.rs)
+    pub burst_frequency: Tracked<u32>,
+    /// FEAGI endpoint of the WiFi-based and Ethernet transports
+    pub transport_host: Tracked<[u8; 4]>,
+    pub transport_port: Tracked<u16>,
+    /// Stored cortical mappings of GPIO pins, replacing config.json's
+    pub cortical_mappings: Vec<(u32, Mapping), GPIO_PINS>,
+    /// Stored modes of plain digital pins, replacing config.json's
+    pub gpio_modes: Vec<(u32, GpioMode), GPIO_PINS>,
+}
+
+impl Settings {
+    pub fn from_build() -> Self {
+        Self {
+            device_id: Tracked::build(DeviceId::from_str(DEVICE_ID).unwrap_or_default()),
+            rate_policy: Tracked::build(RATE_POLICY),
+            mode: Tracked::build(SessionMode::Feagi),
+            barrier_timeout_ms: Tracked::build(BARRIER_TIMEOUT_MS),
+            log_level: Tracked::build(LOG_CONFIG.level),
+            log_forward: Tracked::build(LOG_CONFIG.forward),
+            burst_frequency: Tracked::build(BURST_FREQUENCY_HZ),
+            transport_host: Tracked::build(network_endpoint().map_or([0; 4], |(host, _)| host)),
+            transport_port: Tracked::build(network_endpoint().map_or(0, |(_, port)| port)),
+            cortical_mappings: Vec::new(),
+            gpio_modes: Vec::new(),
+        }
+    }
+
+    /// Effective mode of a GPIO entry
+    pub fn gpio_mode(&self, gpio: &GpioPinConfig) -> GpioMode {
+        match self.gpio_modes.iter().find(|(pin, _)| *pin == gpio.pin) {
+            Some((_, mode)) => *mode,
+            None => gpio.mode,
+        }
+    }
+
+    /// Effective `cortical_mapping` of a GPIO entry
+    pub fn cortical_mapping(&self, gpio: &GpioPinConfig) -> &str {
+        match self.cortical_mappings.iter().find(|(pin, _)| *pin == gpio.pin) {
+            Some((_, mapping)) => mapping,
+            None => gpio.cortical_mapping,
+        }
+    }
+
+    /// Is this line a `{"get_settings":[...]}` request?
+    pub fn is_get_request(message: &str) -> bool {
+        message.starts_with("{\"get_settings\"")
+    }
+
+    /// Is this line a `{"set_settings":{...}}` request?
+    pub fn is_set_request(message: &str) -> bool {
+        message.starts_with("{\"set_settings\"")
+    }
+
+    /// Write the `{"settings":{...}}` answer to a `get_settings` request
+    ///
+    /// Unknown keys are answered with `null`. On overflow `out` is left
+    /// empty.
+    pub fn get_batch(&self, message: &str, out: &mut String<CONFIG_DUMP_CAPACITY>) -> Result<(), ReplyOverflow> {
+        let mut w = JsonWriter::new(out);
+        w.raw("{\"settings\":{");
+        // Unknown or malformed requests are answered like `[]`
+        let keys = parse::member(message, "get_settings")
+            .and_then(parse::strings::<{ parse::MAX_PAIRS }>)
+            .unwrap_or_default();
+        if keys.is_empty() {
+            for key in SETTABLE_KEYS.iter().chain(BOOT_KEYS.iter()) {
+                w.key(key);
+                self.write_value(key, &mut w);
+            }
+        } else {
+            for key in keys.iter() {
+                w.key(key);
+                if !self.write_value(key, &mut w) {
+                    w.raw("null");
+                }
+            }
+        }
+        w.raw("}}\n");
+        w.finish()
+    }
+
+    /// Apply a `set_settings` batch: every pair is validated before any is applied
+    ///
+    /// Returns the number of settings changed.
+    pub fn set_batch(&mut self, message: &str) -> Result<usize, SettingsError> {
+        let pairs = parse::member(message, "set_settings")
+            .and_then(parse::pairs)
+            .filter(|pairs| !pairs.is_empty())
+            .ok_or(SettingsError { key: None, reason: "expected an object of key/value pairs" })?;
+        let mut staged = self.clone();
+        for &(key, value) in pairs.iter() {
+            if is_boot_key(key) {
+                return Err(SettingsError::at(key, "read at boot: use store_config"));
+            }
+            staged.set(key, value, Source::Runtime)?;
+        }
+        *self = staged;
+        Ok(pairs.len())
+    }
+
+    /// Validate and set one value read from NVS (see stored_config.rs)
+    pub fn set_stored(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        if key == "device_id" {
+            return Err(SettingsError::at(key, "stored by set_settings"));
+        }
+        self.set(key, value, Source::Stored)
+    }
+
+    /// `{"<reply>":{...}}` line for a `set_batch` (`settings_ack`) or
+    /// `store_config` (`config_ack`) outcome
+    pub fn ack_line(reply: &str, result: &Result<usize, SettingsError>) -> String<160> {
+        let mut line: String<160> = String::from("{\"");
+        let _ = line.push_str(reply);
+        let _ = line.push_str("\":{\"ok\":");
+        match result {
+            Ok(n) => {
+                let mut num: String<16> = String::new();
+                u32_to_string(*n as u32, &mut num);
+                let _ = line.push_str("true,\"n\":");
+                let _ = line.push_str(num.as_str());
+            }
+            Err(e) => {
+                let _ = line.push_str("false");
+                if let Some(ref key) = e.key {
+                    let _ = line.push_str(",\"key\":\"");
+                    let _ = line.push_str(key);
+                    let _ = line.push_str("\"");
+                }
+                let _ = line.push_str(",\"error\":\"");
+                let _ = line.push_str(e.reason);
+                let _ = line.push_str("\"");
+            }
+        }
+        let _ = line.push_str("}}\n");
+        line
+    }
+
+    /// Validate and set one value
+    fn set(&mut self, key: &str, value: &str, source: Source) -> Result<(), SettingsError> {
+        if let Some(pin) = gpio_mapping_pin(key) {
+            return self.set_cortical_mapping(key, pin, value);
+        }
+        if let Some(pin) = gpio_mode_pin(key) {
+            return self.set_gpio_mode(key, pin, value);
+        }
+        let Some(&key) = SETTABLE_KEYS.iter().chain(BOOT_KEYS.iter()).find(|&&k| k == key) else {
+            return Err(SettingsError::at(key, "unknown key"));
+        };
+        let invalid = |reason| SettingsError::at(key, reason);
+        let number = || value.parse::<u32>().map_err(|_| invalid("expected a non-negative integer"));
+        let mut policy = self.rate_policy.value;
+        match key {
+            "device_id" => {
+                if !device_id::is_valid(value) {
+                    return Err(invalid("expected 1-32 letters, digits, '.', '-' or '_'"));
+                }
+                self.device_id.set(DeviceId::from_str(value).unwrap_or_default(), source);
+                return Ok(());
+            }
+            "mode" => {
+                let mode = match value {
+                    "feagi" => SessionMode::Feagi,
+                    "raw" => SessionMode::Raw,
+                    _ => return Err(invalid("expected feagi or raw")),
+                };
+                self.mode.set(mode, source);
+                return Ok(());
+            }
+            "barrier.timeout_ms" => {
+                let timeout_ms = number()?;
+                if timeout_ms == 0 {
+                    return Err(invalid("must be at least 1"));
+                }
+                self.barrier_timeout_ms.set(timeout_ms, source);
+                return Ok(());
+            }
+            "log.level" => {
+                self.log_level.set(Level::parse(value).ok_or(invalid("expected error, warn, info or debug"))?, source);
+                return Ok(());
+            }
+            "log.forward" => {
+                let forward = match value {
+                    "off" => None,
+                    _ => Some(Level::parse(value).ok_or(invalid("expected off, error, warn, info or debug"))?),
+                };
+                self.log_forward.set(forward, source);
+                return Ok(());
+            }
+            "burst_frequency" => {
+                let hz = number()?;
+                if !(1..=1000).contains(&hz) {
+                    return Err(invalid("expected 1-1000"));
+                }
+                // Audio output is buffered for config.json's rate or faster
+                if AUDIO_OUTPUT.is_some() && hz < BURST_FREQUENCY_HZ {
+                    return Err(invalid("audio output needs config.json's burst_frequency or faster"));
+                }
+                self.burst_frequency.set(hz, source);
+                return Ok(());
+            }
+            "transport.host" | "transport.port" if network_endpoint().is_none() => {
+                return Err(invalid("needs a WiFi-based or ethernet transport"));
+            }
+            "transport.host" => {
+                self.transport_host.set(parse_ipv4(value).ok_or(invalid("expected an IPv4 address"))?, source);
+                return Ok(());
+            }
+            "transport.port" => {
+                let port = number()?;
+                if !(1..=65535).contains(&port) {
+                    return Err(invalid("expected 1-65535"));
+                }
+                self.transport_port.set(port as u16, source);
+                return Ok(());
+            }
+            "rate_policy.motor" => {
+                policy.motor = MotorPolicy::parse(value).ok_or(invalid("expected hold, decay or interp"))?;
+            }
+            "rate_policy.sensory" => {
+                policy.sensory = SensoryPolicy::parse(value).ok_or(invalid("expected every, subsample or aggregate"))?;
+            }
+            "rate_policy.ratio" => {
+                policy.ratio = number()?;
+                if policy.ratio == 0 {
+                    return Err(invalid("must be at least 1"));
+                }
+            }
+            "rate_policy.decay_after_ms" => policy.decay_after_ms = number()?,
+            "rate_policy.decay_ms" => policy.decay_ms = number()?,
+            "rate_policy.neutral" => {
+                policy.neutral = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|v| (0.0..=1.0).contains(v))
+                    .ok_or(invalid("expected 0.0-1.0"))?;
+            }
+            "rate_policy.delta" => policy.delta = value.parse().map_err(|_| invalid("expected true or false"))?,
+            "rate_policy.delta_epsilon" => {
+                policy.delta_epsilon = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|v| (0.0..=1.0).contains(v))
+                    .ok_or(invalid("expected 0.0-1.0"))?;
+            }
+            "rate_policy.keyframe_ms" => {
+                policy.keyframe_ms = number()?;
+                if policy.keyframe_ms == 0 {
+                    return Err(invalid("must be at least 1"));
+                }
+            }
+            _ => return Err(invalid("not settable")),
+        }
+        self.rate_policy.set(policy, source);
+        Ok(())
+    }
+
+    /// Validate and set the cortical mapping of GPIO `pin`
+    fn set_cortical_mapping(&mut self, key: &str, pin: u32, value: &str) -> Result<(), SettingsError> {
+        let invalid = |reason| SettingsError::at(key, reason);
+        let gpio = GPIO_CONFIG
+            .iter()
+            .find(|g| g.pin == pin && g.mode != GpioMode::Disabled)
+            .ok_or(invalid("no such GPIO entry in config.json"))?;
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b':' | b',' | b'_' | b'-')) {
+            return Err(invalid("expected area, N, area:N or area:x,y,z"));
+        }
+        let mapping = Mapping::from_str(value).map_err(|_| invalid("too long"))?;
+        // Channels that need a neuron keep one; voxels only of the areas
+        // config.json maps by coordinates (CORTICAL_AREAS)
+        match cortical::neuron_id(value) {
+            None if value.contains(':') || cortical::neuron_id(gpio.cortical_mapping).is_some() => {
+                return Err(invalid("expected N, area:N or area:x,y,z of an area config.json maps by coordinates"));
+            }
+            Some(id) if WIRE_FORMAT == "binary" && id > u16::MAX as u32 => {
+                return Err(invalid("binary wire format needs a 16-bit neuron ID"));
+            }
+            _ => {}
+        }
+        match self.cortical_mappings.iter_mut().find(|(p, _)| *p == pin) {
+            Some((_, m)) => *m = mapping,
+            None => {
+                let _ = self.cortical_mappings.push((pin, mapping));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate and set the mode of GPIO `pin`: plain digital pins can
+    /// switch between input and output, or be turned off
+    fn set_gpio_mode(&mut self, key: &str, pin: u32, value: &str) -> Result<(), SettingsError> {
+        let invalid = |reason| SettingsError::at(key, reason);
+        GPIO_CONFIG
+            .iter()
+            .find(|g| g.pin == pin && matches!(g.mode, GpioMode::DigitalInput | GpioMode::DigitalOutput) && g.population.is_none())
+            .ok_or(invalid("no digital_input or digital_output entry in config.json"))?;
+        let mode = match GpioMode::parse(value) {
+            Some(mode @ (GpioMode::DigitalInput | GpioMode::DigitalOutput | GpioMode::Disabled)) => mode,
+            _ => return Err(invalid("expected digital_input, digital_output or disabled")),
+        };
+        if mode == GpioMode::DigitalOutput && (34..=39).contains(&pin) {
+            return Err(invalid("GPIO 34-39 are input-only"));
+        }
+        match self.gpio_modes.iter_mut().find(|(p, _)| *p == pin) {
+            Some((_, m)) => *m = mode,
+            None => {
+                let _ = self.gpio_modes.push((pin, mode));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the JSON value of a settable key; false if the key is unknown
+    fn write_value(&self, key: &str, w: &mut JsonWriter<'_>) -> bool {
+        if let Some(pin) = gpio_mapping_pin(key) {
+            let Some(gpio) = GPIO_CONFIG.iter().find(|g| g.pin == pin) else {
+                return false;
+            };
+            w.string(self.cortical_mapping(gpio));
+            return true;
+        }
+        if let Some(pin) = gpio_mode_pin(key) {
+            let Some(gpio) = GPIO_CONFIG.iter().find(|g| g.pin == pin) else {
+                return false;
+            };
+            w.string(self.gpio_mode(gpio).as_str());
+            return true;
+        }
+        let policy = &self.rate_policy.value;
+        match key {
+            "device_id" => w.string(&self.device_id.value),
+            "mode" => w.string(self.mode.value.as_str()),
+            "barrier.timeout_ms" => w.num(self.barrier_timeout_ms.value),
+            "log.level" => w.string(self.log_level.value.as_str()),
+            "log.forward" => w.string(self.log_forward.value.map_or("off", |l| l.as_str())),
+            "rate_policy.motor" => w.string(policy.motor.as_str()),
+            "rate_policy.sensory" => w.string(policy.sensory.as_str()),
+            "rate_policy.ratio" => w.num(policy.ratio),
+            "rate_policy.decay_after_ms" => w.num(policy.decay_after_ms),
+            "rate_policy.decay_ms" => w.num(policy.decay_ms),
+            "rate_policy.neutral" => w.unit(policy.neutral),
+            "rate_policy.delta" => w.raw(if policy.delta { "true" } else { "false" }),
+            "rate_policy.delta_epsilon" => w.unit(policy.delta_epsilon),
+            "rate_policy.keyframe_ms" => w.num(policy.keyframe_ms),
+            "burst_frequency" => w.num(self.burst_frequency.value),
+            "transport.host" if network_endpoint().is_some() => w.ipv4(self.transport_host.value),
+            "transport.port" if network_endpoint().is_some() => w.num(self.transport_port.value as u32),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Is this line a `{"get_config":...}` request?
+    pub fn is_dump_request(message: &str) -> bool {
+        message.starts_with("{\"get_config\"")
+    }
+
+    /// Write the `{"effective_config":{...}}` line; on overflow `out` is
+    /// left empty
+    pub fn dump(&self, out: &mut String<CONFIG_DUMP_CAPACITY>) -> Result<(), ReplyOverflow> {
+        let mut w = JsonWriter::new(out);
+        w.raw("{\"effective_config\":{");
+
+        w.field_str("device_id", &self.device_id.value, self.device_id.source);
+        w.field_str("transport", TRANSPORT_TYPE, Source::Build);
+        w.field_str("mode", self.mode.value.as_str(), self.mode.source);
+        w.field_u32("transport.supervision.error_limit", TRANSPORT_SUPERVISION.error_limit, Source::Build);
+        w.field_u32("transport.supervision.silence_ms", TRANSPORT_SUPERVISION.silence_ms, Source::Build);
+        w.field_u32("transport.supervision.max_restarts", TRANSPORT_SUPERVISION.max_restarts, Source::Build);
+        w.field_u32("transport.supervision.queue_frames", TRANSPORT_SUPERVISION.queue_frames, Source::Build);
+        w.field_u32("transport.supervision.failover_ms", TRANSPORT_SUPERVISION.failover_ms, Source::Build);
+        if let Some(keepalive) = KEEPALIVE {
+            w.field_u32("transport.keepalive.interval_ms", keepalive.interval_ms, Source::Build);
+            w.field_u32("transport.keepalive.timeout_ms", keepalive.timeout_ms, Source::Build);
+        }
+        if network_endpoint().is_some() {
+            w.open("transport.host");
+            w.ipv4(self.transport_host.value);
+            w.close(self.transport_host.source);
+            w.field_u32("transport.port", self.transport_port.value as u32, self.transport_port.source);
+        }
+        w.field_u32("burst_frequency", self.burst_frequency.value, self.burst_frequency.source);
+        w.field_str("wire_format", WIRE_FORMAT, Source::Build);
+        w.field_str("serial.framing", SERIAL_FRAMING.as_str(), Source::Build);
+        w.field_u32("serial.port", SERIAL.port as u32, Source::Build);
+        w.field_u32("serial.tx_pin", SERIAL.tx_pin as u32, Source::Build);
+        w.field_u32("serial.rx_pin", SERIAL.rx_pin as u32, Source::Build);
+        w.field_u32("serial.baud_rate", SERIAL.baud_rate, Source::Build);
+        if let Some(pin) = SERIAL.rts_pin {
+            w.field_u32("serial.rts_pin", pin as u32, Source::Build);
+        }
+        if let Some(pin) = SERIAL.cts_pin {
+            w.field_u32("serial.cts_pin", pin as u32, Source::Build);
+        }
+        w.field_u32("serial.rx_buffer_bytes", SERIAL.rx_buffer_bytes, Source::Build);
+        w.field_bool("output_echo", OUTPUT_ECHO_ENABLED, Source::Build);
+        w.field_bool("barrier.enabled", BARRIER_ENABLED, Source::Build);
+        w.field_u32("barrier.timeout_ms", self.barrier_timeout_ms.value, self.barrier_timeout_ms.source);
+        w.field_str("log.level", self.log_level.value.as_str(), self.log_level.source);
+        w.field_str("log.forward", self.log_forward.value.map_or("off", |l| l.as_str()), self.log_forward.source);
+        w.field_u32("sysid.rate_hz", SYSID_RATE_HZ, Source::Build);
+        w.field_bool("telemetry.board_health", TELEMETRY_BOARD_HEALTH, Source::Build);
+        w.field_bool("telemetry.profile", TELEMETRY_PROFILE, Source::Build);
+        if let Some(metrics) = METRICS {
+            w.field_u32("telemetry.metrics.interval_ms", metrics.interval_ms, Source::Build);
+        }
+        w.field_bool("link_encryption.enabled", LINK_PSK.is_some(), Source::Build);
+        if let Some(watchdog) = WATCHDOG {
+            w.field_u32("watchdog.timeout_ms", watchdog.timeout_ms, Source::Build);
+        }
+        if let Some(restore) = OUTPUT_RESTORE {
+            w.field_u32("output_restore.timeout_ms", restore.timeout_ms, Source::Build);
+        }
+        w.field_bool("tasks.enabled", TRANSPORT_TASK.is_some(), Source::Build);
+        if let Some(tasks) = TRANSPORT_TASK {
+            w.field_u32("tasks.queue_bytes", tasks.queue_bytes, Source::Build);
+        }
+
+        let policy = &self.rate_policy.value;
+        let src = self.rate_policy.source;
+        w.field_str("rate_policy.motor", policy.motor.as_str(), src);
+        w.field_str("rate_policy.sensory", policy.sensory.as_str(), src);
+        w.field_u32("rate_policy.ratio", policy.ratio, src);
+        w.field_u32("rate_policy.decay_after_ms", policy.decay_after_ms, src);
+        w.field_u32("rate_policy.decay_ms", policy.decay_ms, src);
+        w.field_unit("rate_policy.neutral", policy.neutral, src);
+        w.field_bool("rate_policy.delta", policy.delta, src);
+        w.field_unit("rate_policy.delta_epsilon", policy.delta_epsilon, src);
+        w.field_u32("rate_policy.keyframe_ms", policy.keyframe_ms, src);
+
+        if let Some(compression) = COMPRESSION {
+            w.field_u32("compression.min_bytes", compression.min_bytes, Source::Build);
+        }
+
+        w.field_u32("buffers.frame_bytes", FRAME_CAPACITY as u32, Source::Build);
+        w.field_u32("buffers.rx_line_bytes", RX_LINE_CAPACITY as u32, Source::Build);
+
+        if let Some(sleep) = SLEEP_CONFIG {
+            w.field_str("sleep.mode", sleep.mode.as_str(), Source::Build);
+            w.field_u32("sleep.idle_ms", sleep.idle_ms, Source::Build);
+            w.field_u32("sleep.timer_ms", sleep.timer_ms, Source::Build);
+        }
+
+        if let Some(bus) = I2C_BUS {
+            w.field_u32("i2c.sda", bus.sda, Source::Build);
+            w.field_u32("i2c.scl", bus.scl, Source::Build);
+            w.field_u32("i2c.freq_hz", bus.freq_hz, Source::Build);
+            w.field_u32("i2c.burst_budget_us", bus.burst_budget_us, Source::Build);
+            w.field_u32("i2c.devices", I2C_DEVICES.len() as u32, Source::Build);
+            w.field_u32("i2c.expanders", I2C_EXPANDERS.len() as u32, Source::Build);
+        }
+        if let Some(imu) = IMU_CONFIG {
+            let chip = match imu.chip {
+                ImuChip::Mpu6050 => "mpu6050",
+                ImuChip::Icm20948 => "icm20948",
+            };
+            w.field_str("i2c.imu.chip", chip, Source::Build);
+            w.field_u32("i2c.imu.address", imu.address as u32, Source::Build);
+            w.field_u32("i2c.imu.rate_hz", imu.rate_hz, Source::Build);
+            w.field_u32("i2c.imu.accel_range_g", imu.accel_range_g, Source::Build);
+            w.field_u32("i2c.imu.gyro_range_dps", imu.gyro_range_dps, Source::Build);
+            w.field_str("i2c.imu.cortical_mapping", imu.cortical_mapping, Source::Build);
+        }
+        if let Some(mapping) = ONBOARD_SENSORS.hall_mapping {
+            w.field_str("onboard_sensors.hall.cortical_mapping", mapping, Source::Build);
+            w.field_u32("onboard_sensors.hall.full_scale", ONBOARD_SENSORS.hall_full_scale, Source::Build);
+        }
+        if let Some(mapping) = ONBOARD_SENSORS.temperature_mapping {
+            w.field_str("onboard_sensors.temperature.cortical_mapping", mapping, Source::Build);
+        }
+        if let Some(camera) = CAMERA {
+            w.field_str("camera.board", camera.board.as_str(), Source::Build);
+            w.field_str("camera.cortical_mapping", camera.cortical_mapping, Source::Build);
+            w.field_u32("camera.width", camera.width, Source::Build);
+            w.field_u32("camera.height", camera.height, Source::Build);
+            w.field_u32("camera.decimation", camera.decimation, Source::Build);
+        }
+        if let Some(mic) = MICROPHONE {
+            w.field_u32("microphone.sck", mic.sck, Source::Build);
+            w.field_u32("microphone.ws", mic.ws, Source::Build);
+            w.field_u32("microphone.sd", mic.sd, Source::Build);
+            w.field_str("microphone.channel", if mic.right_channel { "right" } else { "left" }, Source::Build);
+            w.field_u32("microphone.sample_rate_hz", mic.sample_rate_hz, Source::Build);
+            w.field_u32("microphone.bands", mic.bands, Source::Build);
+            w.field_decimal("microphone.floor_db", mic.floor_db, Source::Build);
+            w.field_str("microphone.cortical_mapping", mic.cortical_mapping, Source::Build);
+        }
+        if let Some(audio) = AUDIO_OUTPUT {
+            w.field_str("audio_output.driver", audio.driver.as_str(), Source::Build);
+            match audio.driver {
+                AudioDriver::I2s { bck, ws, dout } => {
+                    w.field_u32("audio_output.bck", bck, Source::Build);
+                    w.field_u32("audio_output.ws", ws, Source::Build);
+                    w.field_u32("audio_output.dout", dout, Source::Build);
+                }
+                AudioDriver::Dac { pin } => w.field_u32("audio_output.pin", pin, Source::Build),
+            }
+            w.field_u32("audio_output.sample_rate_hz", audio.sample_rate_hz, Source::Build);
+            w.field_unit("audio_output.volume", audio.volume, Source::Build);
+            w.field_str("audio_output.cortical_mapping", audio.cortical_mapping, Source::Build);
+            w.field_u32("audio_output.tones", audio.tones.len() as u32, Source::Build);
+        }
+        if let Some(power) = POWER_MONITOR {
+            w.field_str("power_monitor.source", power.source.as_str(), Source::Build);
+            match power.source {
+                PowerSource::Adc { pin, divider } => {
+                    w.field_u32("power_monitor.pin", pin, Source::Build);
+                    w.field_milli("power_monitor.divider", divider, Source::Build);
+                }
+                PowerSource::Ina219 { address, shunt_ohms } => {
+                    w.field_u32("power_monitor.address", address as u32, Source::Build);
+                    w.field_milli("power_monitor.shunt_ohms", shunt_ohms, Source::Build);
+                }
+            }
+            w.field_milli("power_monitor.min_voltage", power.min_voltage, Source::Build);
+            w.field_milli("power_monitor.max_voltage", power.max_voltage, Source::Build);
+            w.field_milli("power_monitor.max_current", power.max_current, Source::Build);
+            w.field_str("power_monitor.cortical_mapping", power.cortical_mapping, Source::Build);
+            if let Some(low_voltage) = power.low_voltage {
+                w.field_milli("power_monitor.low_voltage", low_voltage, Source::Build);
+                w.field_u32("power_monitor.low_hold_ms", power.low_hold_ms, Source::Build);
+            }
+        }
+        if let Some(odometry) = ODOMETRY {
+            w.field_u32("odometry.left", odometry.left_pin, Source::Build);
+            w.field_u32("odometry.right", odometry.right_pin, Source::Build);
+            w.field_decimal("odometry.wheel_diameter_mm", odometry.wheel_diameter_mm, Source::Build);
+            w.field_decimal("odometry.track_width_mm", odometry.track_width_mm, Source::Build);
+            w.field_bool("odometry.invert_left", odometry.invert_left, Source::Build);
+            w.field_bool("odometry.invert_right", odometry.invert_right, Source::Build);
+        }
+
+        // GPIO entries as configured (build-time, but for stored cortical
+        // mappings)
+        w.raw(",\"gpio\":[");
+        for (i, gpio) in stored_config::gpio_config().iter().enumerate() {
+            if i > 0 {
+                w.raw(",");
+            }
+            w.raw("{\"pin\":");
+            w.num(gpio.pin);
+            w.raw(",\"mode\":\"");
+            w.raw(gpio.mode.as_str());
+            w.raw("\",\"cortical_mapping\":\"");
+            w.raw(gpio.cortical_mapping);
+            w.raw("\",\"boot_state\":\"");
+            w.raw(match gpio.boot_state {
+                BootState::DriveLow => "low",
+                BootState::Float => "float",
+                BootState::Hold => "hold",
+            });
+            w.raw("\"");
+            if let Some(value) = gpio.safe_value {
+                w.raw(",\"safe_value\":");
+                w.unit(value);
+            }
+            w.raw(",\"pull\":\"");
+            w.raw(gpio.pull.as_str());
+            w.raw("\",\"drive\":\"");
+            w.raw(gpio.drive.as_str());
+            w.raw("\"");
+            if let Some(fb) = gpio.feedback {
+                w.raw(",\"feedback\":{\"pin\":");
+                w.num(fb.pin);
+                w.raw("}");
+            }
+            if let (GpioMode::PwmOutput | GpioMode::DcMotor, Some(pwm)) = (gpio.mode, gpio.pwm) {
+                w.raw(",\"pwm\":{\"frequency_hz\":");
+                w.num(pwm.frequency_hz);
+                w.raw(",\"resolution_bits\":");
+                w.num(pwm.resolution_bits);
+                w.raw("}");
+            }
+            if let Some(servo) = gpio.servo {
+                w.raw(",\"servo\":{\"min_pulse_us\":");
+                w.num(servo.min_pulse_us);
+                w.raw(",\"max_pulse_us\":");
+                w.num(servo.max_pulse_us);
+                w.raw(",\"range_deg\":");
+                w.num(servo.range_deg);
+                w.raw(",\"min_angle_deg\":");
+                w.num(servo.min_angle_deg);
+                w.raw(",\"max_angle_deg\":");
+                w.num(servo.max_angle_deg);
+                w.raw("}");
+            }
+            if let Some(motor) = gpio.dc_motor {
+                match motor.driver {
+                    MotorDriver::TwoPwm { pin_b, .. } => {
+                        w.raw(",\"dc_motor\":{\"pin_b\":");
+                        w.num(pin_b);
+                    }
+                    MotorDriver::Direction { in1, in2 } => {
+                        w.raw(",\"dc_motor\":{\"direction_pins\":[");
+                        w.num(in1);
+                        if let Some(in2) = in2 {
+                            w.raw(",");
+                            w.num(in2);
+                        }
+                        w.raw("]");
+                    }
+                }
+                w.raw(",\"invert\":");
+                w.raw(if motor.invert { "true" } else { "false" });
+                w.raw(",\"deadband\":");
+                w.unit(motor.deadband);
+                w.raw("}");
+            }
+            if let Some(stepper) = gpio.stepper {
+                w.raw(",\"stepper\":{\"dir_pin\":");
+                w.num(stepper.dir_pin);
+                if let Some(enable_pin) = stepper.enable_pin {
+                    w.raw(",\"enable_pin\":");
+                    w.num(enable_pin);
+                }
+                w.raw(",\"control\":\"");
+                w.raw(stepper.control.as_str());
+                w.raw("\",\"max_speed_sps\":");
+                w.num(stepper.max_speed_sps);
+                w.raw(",\"accel_sps2\":");
+                w.num(stepper.accel_sps2);
+                w.raw(",\"range_steps\":");
+                w.num(stepper.range_steps);
+                w.raw(",\"invert\":");
+                w.raw(if stepper.invert { "true" } else { "false" });
+                w.raw("}");
+            }
+            if let Some(analog) = gpio.analog {
+                w.raw(",\"analog\":{\"scale\":");
+                w.milli(analog.scale);
+                w.raw(",\"offset\":");
+                w.milli(analog.offset);
+                if !analog.calibration.is_empty() {
+                    w.raw(",\"calibration\":[");
+                    for (i, (raw, value)) in analog.calibration.iter().enumerate() {
+                        w.raw(if i == 0 { "[" } else { ",[" });
+                        w.num(*raw as u32);
+                        w.raw(",");
+                        w.milli(*value);
+                        w.raw("]");
+                    }
+                    w.raw("]");
+                }
+                w.raw("}");
+            }
+            if let Some(touch) = gpio.touch {
+                w.raw(",\"touch\":{");
+                if let Some(threshold) = touch.threshold {
+                    w.raw("\"threshold\":");
+                    w.num(threshold as u32);
+                    w.raw(",");
+                }
+                w.raw("\"report\":\"");
+                w.raw(match touch.report {
+                    TouchReport::State => "state",
+                    TouchReport::Level => "level",
+                });
+                w.raw("\"}");
+            }
+            if let Some(encoder) = gpio.encoder {
+                w.raw(",\"encoder\":{\"pin_b\":");
+                w.num(encoder.pin_b);
+                w.raw(",\"counts_per_rev\":");
+                w.num(encoder.counts_per_rev);
+                w.raw(",\"max_rpm\":");
+                w.num(encoder.max_rpm);
+                w.raw("}");
+            }
+            if let Some(ultrasonic) = gpio.ultrasonic {
+                w.raw(",\"ultrasonic\":{\"echo_pin\":");
+                w.num(ultrasonic.echo_pin);
+                w.raw(",\"max_range_cm\":");
+                w.num(ultrasonic.max_range_cm);
+                w.raw(",\"timeout_us\":");
+                w.num(ultrasonic.timeout_us);
+                w.raw("}");
+            }
+            if let Some(led_strip) = gpio.led_strip {
+                w.raw(",\"led_strip\":{\"length\":");
+                w.num(led_strip.length);
+                w.raw(",\"color_order\":\"");
+                w.raw(led_strip.color_order.as_str());
+                w.raw("\"}");
+            }
+            if let Some(dht) = gpio.dht {
+                w.raw(",\"dht\":{\"interval_ms\":");
+                w.num(dht.interval_ms);
+                w.raw("}");
+            }
+            if let Some(input) = gpio.input {
+                w.raw(",\"trigger\":\"");
+                w.raw(input.trigger.as_str());
+                w.raw("\",\"debounce_ms\":");
+                w.num(input.debounce_ms);
+                w.raw(",\"interrupt\":");
+                w.raw(if input.interrupt { "true" } else { "false" });
+            }
+            if let Some(p) = gpio.population {
+                w.raw(",\"population\":{\"first\":");
+                w.num(p.first);
+                w.raw(",\"last\":");
+                w.num(p.last);
+                w.raw(",\"threshold\":");
+                w.num(p.threshold);
+                w.raw(",\"hysteresis\":");
+                w.num(p.hysteresis);
+                w.raw(",\"window_ms\":");
+                w.num(p.window_ms);
+                w.raw("}");
+            }
+            let stored = self.cortical_mappings.iter().any(|(pin, _)| *pin == gpio.pin)
+                || self.gpio_modes.iter().any(|(pin, _)| *pin == gpio.pin);
+            w.close(if stored { Source::Stored } else { Source::Build });
+        }
+        w.raw("]}}\n");
+        w.finish()
+    }
+}
+
+/// Is `key` a BOOT_KEYS entry, `gpio.<pin>.mode` or `gpio.<pin>.cortical_mapping`?
+fn is_boot_key(key: &str) -> bool {
+    gpio_mapping_pin(key).is_some() || gpio_mode_pin(key).is_some() || BOOT_KEYS.contains(&key)
+}
+
+/// The pin of a `gpio.<pin>.cortical_mapping` key
+fn gpio_mapping_pin(key: &str) -> Option<u32> {
+    key.strip_prefix("gpio.")?.strip_suffix(".cortical_mapping")?.parse().ok()
+}
+
+/// The pin of a `gpio.<pin>.mode` key
+fn gpio_mode_pin(key: &str) -> Option<u32> {
+    key.strip_prefix("gpio.")?.strip_suffix(".mode")?.parse().ok()
+}
+
+/// config.json's FEAGI endpoint; None without a WiFi-based or Ethernet transport
+fn network_endpoint() -> Option<([u8; 4], u16)> {
+    match (WIFI_CONFIG, ETHERNET_CONFIG) {
+        (Some(wifi), _) => Some((wifi.host, wifi.port)),
+        (None, Some(ethernet)) => Some((ethernet.network.host, ethernet.network.port)),
+        (None, None) => None,
+    }
+}
+
+/// Minimal JSON emitter (dump fields are `"key":{"v":...,"src":"..."}`)
+struct JsonWriter<'a> {
+    out: &'a mut String<CONFIG_DUMP_CAPACITY>,
+    first: bool,
+    /// Something didn't fit; the rest is dropped
+    overflow: bool,
+}
+
+impl<'a> JsonWriter<'a> {
+    fn new(out: &'a mut String<CONFIG_DUMP_CAPACITY>) -> Self {
+        out.clear();
+        Self { out, first: true, overflow: false }
+    }
+
+    /// The line is complete; an error (and nothing written) if it didn't fit
+    fn finish(self) -> Result<(), ReplyOverflow> {
+        if self.overflow {
+            self.out.clear();
+            return Err(ReplyOverflow { capacity: CONFIG_DUMP_CAPACITY });
+        }
+        Ok(())
+    }
+
+    fn raw(&mut self, s: &str) {
+        if self.overflow || self.out.push_str(s).is_err() {
+            self.overflow = true;
+        }
+    }
+
+    fn num(&mut self, n: u32) {
+        let mut s: String<16> = String::new();
+        u32_to_string(n, &mut s);
+        self.raw(s.as_str());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.raw("\"");
+        self.raw(s);
+        self.raw("\"");
+    }
+
+    /// A dotted IPv4 address, as a string
+    fn ipv4(&mut self, octets: [u8; 4]) {
+        self.raw("\"");
+        for (i, octet) in octets.iter().enumerate() {
+            if i > 0 {
+                self.raw(".");
+            }
+            self.num(*octet as u32);
+        }
+        self.raw("\"");
+    }
+
+    fn unit(&mut self, v: f32) {
+        let mut s: String<16> = String::new();
+        unit_f32_to_string(v, &mut s);
+        self.raw(s.as_str());
+    }
+
+    /// A signed value with three decimals (e.g. analog scale and offset)
+    fn milli(&mut self, v: f32) {
+        let milli = (if v < 0.0 { -v } else { v } * 1000.0 + 0.5) as u32;
+        if v < 0.0 && milli > 0 {
+            self.raw("-");
+        }
+        self.num(milli / 1000);
+        self.raw(".");
+        let frac = milli % 1000;
+        self.raw(if frac < 10 { "00" } else if frac < 100 { "0" } else { "" });
+        self.num(frac);
+    }
+
+    /// `"key":`, comma-separated from the previous field
+    fn key(&mut self, key: &str) {
+        if !self.first {
+            self.raw(",");
+        }
+        self.first = false;
+        self.string(key);
+        self.raw(":");
+    }
+
+    fn open(&mut self, key: &str) {
+        self.key(key);
+        self.raw("{\"v\":");
+    }
+
+    fn close(&mut self, source: Source) {
+        self.raw(",\"src\":\"");
+        self.raw(source.as_str());
+        self.raw("\"}");
+    }
+
+    fn field_u32(&mut self, key: &str, value: u32, source: Source) {
+        self.open(key);
+        self.num(value);
+        self.close(source);
+    }
+
+    fn field_bool(&mut self, key: &str, value: bool, source: Source) {
+        self.open(key);
+        self.raw(if value { "true" } else { "false" });
+        self.close(source);
+    }
+
+    fn field_str(&mut self, key: &str, value: &str, source: Source) {
+        self.open(key);
+        self.string(value);
+        self.close(source);
+    }
+
+    fn field_unit(&mut self, key: &str, value: f32, source: Source) {
+        self.open(key);
+        self.unit(value);
+        self.close(source);
+    }
+
+    /// A value with one decimal (e.g. lengths in mm)
+    fn field_decimal(&mut self, key: &str, value: f32, source: Source) {
+        let mut s: String<16> = String::new();
+        signed_f32_to_string(value, &mut s);
+        self.open(key);
+        self.raw(s.as_str());
+        self.close(source);
+    }
+
+    /// A value with three decimals (e.g. volts, amps, ohms)
+    fn field_milli(&mut self, key: &str, value: f32, source: Source) {
+        self.open(key);
+        self.milli(value);
+        self.close(source);
+    }
+}
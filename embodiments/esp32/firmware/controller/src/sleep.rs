@@ -0,0 +1,291 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Deep and light sleep, and wake sources, for event-driven embodiments
+//!
+//! With a `sleep` block in config.json the board goes to sleep after
+//! `idle_ms` without motor commands (or when the host sends `{"sleep":1}`),
+//! and wakes on any configured source:
+//!
+//! - `ext0`: one RTC pin reaching a level (e.g. a doorbell button)
+//! - `ext1`: several RTC pins, any high or all low (e.g. PIR sensors)
+//! - `touch`: touch pads dropping below a threshold
+//! - `gpio`: any GPIO reaching a level (light sleep only)
+//! - `timer_ms`: periodic wake-up
+//!
+//! In deep sleep (`"mode": "deep"`, the default) waking is a fresh boot; the
+//! reason is reported in the hello line (`"wake":"ext0|ext1|touch|timer|reset"`,
+//! plus `"wake_pin"` when known) so the host knows what happened while the
+//! board was asleep. Light sleep (`"mode": "light"`) keeps RAM and resumes the
+//! burst loop, which reports `{"awake":"gpio","pin":4}` instead. The host can
+//! pick the mode per request: `{"sleep":"light"}` or `{"sleep":"deep"}`.
+
+use esp_idf_svc::sys;
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+use crate::touch::TOUCH_PADS;
+use crate::warn;
+
+/// A touch pad used as a wake source
+#[derive(Debug, Clone, Copy)]
+pub struct TouchWake {
+    pub pin: u32,
+    /// Wake when the pad reading drops below this
+    pub threshold: u16,
+}
+
+/// A GPIO used as a light-sleep wake source
+#[derive(Debug, Clone, Copy)]
+pub struct GpioWake {
+    pub pin: u32,
+    /// Wake when the pin reads this level (true = high)
+    pub high: bool,
+}
+
+/// How deeply the board sleeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepMode {
+    /// Power down all but the RTC domain; waking reboots
+    Deep,
+    /// Pause the CPUs with RAM kept; waking resumes the burst loop
+    Light,
+}
+
+impl SleepMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SleepMode::Deep => "deep",
+            SleepMode::Light => "light",
+        }
+    }
+}
+
+/// Sleep settings (from config.json `sleep` block)
+#[derive(Debug, Clone, Copy)]
+pub struct SleepConfig {
+    /// Mode used when idle or for `{"sleep":1}`
+    pub mode: SleepMode,
+    /// Sleep after this long without motor commands (0 = only on request)
+    pub idle_ms: u32,
+    /// Wake pin and level (true = high)
+    pub ext0: Option<(u32, bool)>,
+    /// Wake pin mask and mode (true = any high, false = all low)
+    pub ext1: Option<(u64, bool)>,
+    pub touch: &'static [TouchWake],
+    /// Light sleep only
+    pub gpio: &'static [GpioWake],
+    /// Wake after this long (0 = no timer)
+    pub timer_ms: u32,
+}
+
+/// Why the board booted (or woke from light sleep)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WakeReason {
+    /// Power-on or reset, not a deep-sleep wake
+    Reset,
+    Ext0,
+    /// Lowest pin in the EXT1 wake status, if any
+    Ext1(Option<u32>),
+    Touch(Option<u32>),
+    /// First `gpio` wake pin found at its level, if any
+    Gpio(Option<u32>),
+    Timer,
+}
+
+impl WakeReason {
+    /// Read the wake cause of this boot, or of the last light-sleep wake
+    pub fn read() -> Self {
+        let cause = unsafe { sys::esp_sleep_get_wakeup_cause() };
+        match cause {
+            sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0 => WakeReason::Ext0,
+            sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => {
+                let status = unsafe { sys::esp_sleep_get_ext1_wakeup_status() };
+                WakeReason::Ext1((status != 0).then(|| status.trailing_zeros()))
+            }
+            sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_TOUCHPAD => WakeReason::Touch(touch_wake_pin()),
+            sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => WakeReason::Gpio(None),
+            sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeReason::Timer,
+            _ => WakeReason::Reset,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WakeReason::Reset => "reset",
+            WakeReason::Ext0 => "ext0",
+            WakeReason::Ext1(_) => "ext1",
+            WakeReason::Touch(_) => "touch",
+            WakeReason::Gpio(_) => "gpio",
+            WakeReason::Timer => "timer",
+        }
+    }
+
+    /// The pin that woke the board, when the source reports one
+    pub fn pin(&self, config: Option<&SleepConfig>) -> Option<u32> {
+        match self {
+            WakeReason::Ext0 => config.and_then(|c| c.ext0).map(|(pin, _)| pin),
+            WakeReason::Ext1(pin) | WakeReason::Touch(pin) | WakeReason::Gpio(pin) => *pin,
+            _ => None,
+        }
+    }
+}
+
+/// Is this line a `{"sleep":...}` request?
+pub fn is_sleep_request(message: &str) -> bool {
+    message.starts_with("{\"sleep\"")
+}
+
+/// Mode a `{"sleep":...}` request asks for: `"light"` or `"deep"`, anything
+/// else (`1`) the configured one
+pub fn requested_mode(message: &str, config: &SleepConfig) -> SleepMode {
+    let value = message.strip_prefix("{\"sleep\":").unwrap_or("").trim_start();
+    if value.starts_with("\"light\"") {
+        SleepMode::Light
+    } else if value.starts_with("\"deep\"") {
+        SleepMode::Deep
+    } else {
+        config.mode
+    }
+}
+
+/// Arm the configured wake sources
+///
+/// Pins get the pull opposite to their wake level, with the RTC peripherals
+/// kept powered in sleep so the pulls hold (buttons to GND/VCC need no
+/// external resistor).
+pub fn arm_wake_sources(config: &SleepConfig) {
+    unsafe {
+        if let Some((pin, high)) = config.ext0 {
+            pull_rtc_pin(pin, !high);
+            sys::esp_sleep_enable_ext0_wakeup(pin as i32, high as i32);
+        }
+        if let Some((mask, any_high)) = config.ext1 {
+            for pin in 0..40 {
+                if mask & (1 << pin) != 0 {
+                    pull_rtc_pin(pin, !any_high);
+                }
+            }
+            let mode = if any_high {
+                sys::esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_HIGH
+            } else {
+                sys::esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ALL_LOW
+            };
+            sys::esp_sleep_enable_ext1_wakeup(mask, mode);
+        }
+        if config.ext0.is_some() || config.ext1.is_some() {
+            sys::esp_sleep_pd_config(
+                sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
+                sys::esp_sleep_pd_option_t_ESP_PD_OPTION_ON,
+            );
+        }
+        if !config.touch.is_empty() {
+            arm_touch(config.touch);
+        }
+        for wake in config.gpio {
+            let pin = wake.pin as i32;
+            sys::gpio_set_direction(pin, sys::gpio_mode_t_GPIO_MODE_INPUT);
+            let pull = if wake.high { sys::gpio_pull_mode_t_GPIO_PULLDOWN_ONLY } else { sys::gpio_pull_mode_t_GPIO_PULLUP_ONLY };
+            sys::gpio_set_pull_mode(pin, pull);
+            let level = if wake.high { sys::gpio_int_type_t_GPIO_INTR_HIGH_LEVEL } else { sys::gpio_int_type_t_GPIO_INTR_LOW_LEVEL };
+            sys::gpio_wakeup_enable(pin, level);
+        }
+        if !config.gpio.is_empty() {
+            sys::esp_sleep_enable_gpio_wakeup();
+        }
+        if config.timer_ms > 0 {
+            sys::esp_sleep_enable_timer_wakeup(config.timer_ms as u64 * 1000);
+        }
+    }
+}
+
+/// Hand EXT0/EXT1 wake pins back to the digital GPIO matrix after waking
+///
+/// Arming routes them to the RTC domain, where normal GPIO reads don't see
+/// them; a wake pin is often also a sensory input (the doorbell button).
+pub fn release_wake_pins(config: &SleepConfig) {
+    let ext0_mask = config.ext0.map_or(0, |(pin, _)| 1u64 << pin);
+    let mask = ext0_mask | config.ext1.map_or(0, |(mask, _)| mask);
+    for pin in 0..40 {
+        if mask & (1 << pin) != 0 {
+            unsafe {
+                sys::rtc_gpio_deinit(pin);
+            }
+        }
+    }
+}
+
+/// Enter deep sleep; does not return
+pub fn deep_sleep() -> ! {
+    unsafe { sys::esp_deep_sleep_start() }
+}
+
+/// Arm the wake sources and light sleep until one fires
+///
+/// Disarms them again on the way out, so a wake pin that stays at its level
+/// doesn't keep the next sleep from starting, and returns why it woke.
+pub fn light_sleep(config: &SleepConfig) -> WakeReason {
+    arm_wake_sources(config);
+    let reason = unsafe {
+        sys::esp_light_sleep_start();
+        WakeReason::read()
+    };
+    unsafe {
+        sys::esp_sleep_disable_wakeup_source(sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL);
+        for wake in config.gpio {
+            sys::gpio_wakeup_disable(wake.pin as i32);
+        }
+    }
+    release_wake_pins(config);
+    match reason {
+        // The GPIO wake status isn't latched: look for a pin still at its level
+        WakeReason::Gpio(_) => WakeReason::Gpio(
+            config.gpio.iter().find(|w| unsafe { sys::gpio_get_level(w.pin as i32) } == w.high as i32).map(|w| w.pin),
+        ),
+        reason => reason,
+    }
+}
+
+unsafe fn pull_rtc_pin(pin: u32, up: bool) {
+    let gpio = pin as i32;
+    sys::rtc_gpio_init(gpio);
+    sys::rtc_gpio_set_direction(gpio, sys::rtc_gpio_mode_t_RTC_GPIO_MODE_INPUT_ONLY);
+    if up {
+        sys::rtc_gpio_pulldown_dis(gpio);
+        sys::rtc_gpio_pullup_en(gpio);
+    } else {
+        sys::rtc_gpio_pullup_dis(gpio);
+        sys::rtc_gpio_pulldown_en(gpio);
+    }
+}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+unsafe fn arm_touch(pads: &[TouchWake]) {
+    sys::touch_pad_init();
+    sys::touch_pad_set_fsm_mode(sys::touch_fsm_mode_t_TOUCH_FSM_MODE_TIMER);
+    for wake in pads {
+        if let Some(pad) = TOUCH_PADS.iter().position(|&p| p == wake.pin) {
+            sys::touch_pad_config(pad as sys::touch_pad_t, wake.threshold);
+        }
+    }
+    sys::esp_sleep_enable_touchpad_wakeup();
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+unsafe fn arm_touch(_pads: &[TouchWake]) {
+    warn!("Touch wake not supported on this chip");
+}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+fn touch_wake_pin() -> Option<u32> {
+    let pad = unsafe { sys::esp_sleep_get_touchpad_wakeup_status() };
+    TOUCH_PADS.get(pad as usize).copied()
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+fn touch_wake_pin() -> Option<u32> {
+    None
+}
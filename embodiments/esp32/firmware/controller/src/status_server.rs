@@ -0,0 +1,275 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! On-device HTTP status and control endpoint
+//!
+//! With `status_server` in config.json, a board on WiFi or Ethernet serves a
+//! few JSON endpoints for debugging an embodiment in the field without FEAGI:
+//!
+//! | Endpoint       | Returns / does                                          |
+//! |----------------|---------------------------------------------------------|
+//! | `GET /status`  | Frame counters, transport, supervisor state, metrics    |
+//! | `GET /gpio`    | Last level of every digital input, output and touch pad |
+//! | `POST /stop`   | Safe-stop: all outputs low, motor commands ignored      |
+//! | `POST /resume` | Leave safe-stop, motor commands drive outputs again     |
+//!
+//! The server runs in esp_http_server's own task. The burst loop publishes
+//! its state here once per burst through atomics, and picks up stop/resume
+//! requests on its next iteration, so a handler never touches a pin or the
+//! transport itself.
+
+use core::ffi::c_char;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::String;
+
+use crate::failsafe::PinMask;
+use crate::metrics;
+use crate::provisioning::http_config;
+use crate::stored_config;
+use crate::time_sync;
+use crate::{u32_to_string, GpioMode};
+
+/// Transport names reported in /status, indexed by `TRANSPORT` (the names
+/// used in the hello line)
+const TRANSPORT_KINDS: [&str; 9] = ["none", "serial", "wifi", "udp", "websocket", "mqtt", "zmq", "bluetooth", "ethernet"];
+
+/// Burst-loop state as of its last publish
+static FRAME: AtomicU32 = AtomicU32::new(0);
+static FRAMES_SENT: AtomicU32 = AtomicU32::new(0);
+static MOTOR_COMMANDS: AtomicU32 = AtomicU32::new(0);
+static TRANSPORT: AtomicU8 = AtomicU8::new(0);
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+static RESTARTS: AtomicU32 = AtomicU32::new(0);
+static RECONNECTS: AtomicU32 = AtomicU32::new(0);
+static QUEUED: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+static FRAMES_RECEIVED: AtomicU32 = AtomicU32::new(0);
+static RX_DROPPED: AtomicU32 = AtomicU32::new(0);
+static PARSE_ERRORS: AtomicU32 = AtomicU32::new(0);
+static PERIOD_US: AtomicU32 = AtomicU32::new(0);
+static JITTER_US: AtomicU32 = AtomicU32::new(0);
+static SAFE_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// One bit per GPIO: last level read from an input / driven onto an output
+static LEVELS: PinMask = PinMask::new();
+
+/// Pending request from a handler, taken by the burst loop
+const REQUEST_NONE: u8 = 0;
+const REQUEST_STOP: u8 = 1;
+const REQUEST_RESUME: u8 = 2;
+static REQUEST: AtomicU8 = AtomicU8::new(REQUEST_NONE);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Burst-loop counters shown in /status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub frame: u32,
+    pub frames_sent: u32,
+    pub motor_commands: u32,
+    pub restarts: u32,
+    pub reconnects: u32,
+    pub queued: u32,
+    pub dropped: u32,
+    /// Runtime metrics (see metrics.rs)
+    pub frames_received: u32,
+    pub rx_dropped: u32,
+    pub parse_errors: u32,
+    pub period_us: u32,
+    pub jitter_us: u32,
+}
+
+/// Publish the burst loop's state; `transport` is the hello's transport
+/// name, or None while the transport is down, and `connected` whether a
+/// peer can hear it (false for a BLE link no central is subscribed to)
+pub fn publish(counters: &Counters, transport: Option<&str>, connected: bool, safe_stopped: bool) {
+    FRAME.store(counters.frame, Ordering::Relaxed);
+    FRAMES_SENT.store(counters.frames_sent, Ordering::Relaxed);
+    MOTOR_COMMANDS.store(counters.motor_commands, Ordering::Relaxed);
+    RESTARTS.store(counters.restarts, Ordering::Relaxed);
+    RECONNECTS.store(counters.reconnects, Ordering::Relaxed);
+    QUEUED.store(counters.queued, Ordering::Relaxed);
+    DROPPED.store(counters.dropped, Ordering::Relaxed);
+    FRAMES_RECEIVED.store(counters.frames_received, Ordering::Relaxed);
+    RX_DROPPED.store(counters.rx_dropped, Ordering::Relaxed);
+    PARSE_ERRORS.store(counters.parse_errors, Ordering::Relaxed);
+    PERIOD_US.store(counters.period_us, Ordering::Relaxed);
+    JITTER_US.store(counters.jitter_us, Ordering::Relaxed);
+    let kind = transport
+        .and_then(|k| TRANSPORT_KINDS.iter().position(|&name| name == k))
+        .unwrap_or(0);
+    TRANSPORT.store(kind as u8, Ordering::Relaxed);
+    CONNECTED.store(connected, Ordering::Relaxed);
+    SAFE_STOPPED.store(safe_stopped, Ordering::Relaxed);
+}
+
+/// Record the level of a digital pin
+pub fn set_level(pin: u32, high: bool) {
+    if pin >= 64 {
+        return;
+    }
+    LEVELS.set(pin, high);
+}
+
+/// Stop/resume requested since the last call: Some(true) = enter safe-stop,
+/// Some(false) = leave it
+pub fn take_request() -> Option<bool> {
+    match REQUEST.swap(REQUEST_NONE, Ordering::AcqRel) {
+        REQUEST_STOP => Some(true),
+        REQUEST_RESUME => Some(false),
+        _ => None,
+    }
+}
+
+/// Start the server on `port` once the network interface is up
+///
+/// Later calls (e.g. after switching from BLE to WiFi) are no-ops; the
+/// server keeps running across transport restarts. Returns false if it
+/// couldn't be started.
+pub fn start(port: u16) -> bool {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return true;
+    }
+    unsafe {
+        let config = http_config(port);
+        let mut server: sys::httpd_handle_t = core::ptr::null_mut();
+        if sys::httpd_start(&mut server, &config) != sys::ESP_OK {
+            STARTED.store(false, Ordering::Release);
+            return false;
+        }
+        let routes: [(&[u8], sys::http_method, unsafe extern "C" fn(*mut sys::httpd_req_t) -> sys::esp_err_t); 4] = [
+            (b"/status\0", sys::http_method_HTTP_GET, handle_status),
+            (b"/gpio\0", sys::http_method_HTTP_GET, handle_gpio),
+            (b"/stop\0", sys::http_method_HTTP_POST, handle_stop),
+            (b"/resume\0", sys::http_method_HTTP_POST, handle_resume),
+        ];
+        for (uri, method, handler) in routes {
+            let mut route: sys::httpd_uri_t = core::mem::zeroed();
+            route.uri = uri.as_ptr() as *const c_char;
+            route.method = method;
+            route.handler = Some(handler);
+            if sys::httpd_register_uri_handler(server, &route) != sys::ESP_OK {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+unsafe extern "C" fn handle_status(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    // {"frame":N,"frames_sent":N,"frames_received":N,"motor_commands":N,"transport":"wifi","connected":true,
+    //  "restarts":R,"reconnects":C,"queued":Q,"dropped":D,"rx_dropped":B,"parse_errors":E,"period_us":P,
+    //  "jitter_us":J,"heap_free":H,"heap_min":M,"safe_stop":false,"time_synced":true,"uptime_ms":U}
+    let mut json: String<512> = String::new();
+    let mut num: String<16> = String::new();
+    let fields: [(&str, &AtomicU32); 4] = [
+        ("{\"frame\":", &FRAME),
+        (",\"frames_sent\":", &FRAMES_SENT),
+        (",\"frames_received\":", &FRAMES_RECEIVED),
+        (",\"motor_commands\":", &MOTOR_COMMANDS),
+    ];
+    for (key, value) in fields {
+        u32_to_string(value.load(Ordering::Relaxed), &mut num);
+        let _ = json.push_str(key);
+        let _ = json.push_str(num.as_str());
+    }
+    let kind = TRANSPORT.load(Ordering::Relaxed) as usize;
+    let _ = json.push_str(",\"transport\":\"");
+    let _ = json.push_str(TRANSPORT_KINDS[kind.min(TRANSPORT_KINDS.len() - 1)]);
+    let _ = json.push_str("\",\"connected\":");
+    let _ = json.push_str(if CONNECTED.load(Ordering::Relaxed) { "true" } else { "false" });
+    let fields: [(&str, &AtomicU32); 8] = [
+        (",\"restarts\":", &RESTARTS),
+        (",\"reconnects\":", &RECONNECTS),
+        (",\"queued\":", &QUEUED),
+        (",\"dropped\":", &DROPPED),
+        (",\"rx_dropped\":", &RX_DROPPED),
+        (",\"parse_errors\":", &PARSE_ERRORS),
+        (",\"period_us\":", &PERIOD_US),
+        (",\"jitter_us\":", &JITTER_US),
+    ];
+    for (key, value) in fields {
+        u32_to_string(value.load(Ordering::Relaxed), &mut num);
+        let _ = json.push_str(key);
+        let _ = json.push_str(num.as_str());
+    }
+    let (heap_free, heap_min) = metrics::heap();
+    for (key, value) in [(",\"heap_free\":", heap_free), (",\"heap_min\":", heap_min)] {
+        u32_to_string(value, &mut num);
+        let _ = json.push_str(key);
+        let _ = json.push_str(num.as_str());
+    }
+    let _ = json.push_str(",\"safe_stop\":");
+    let _ = json.push_str(if SAFE_STOPPED.load(Ordering::Relaxed) { "true" } else { "false" });
+    let _ = json.push_str(",\"time_synced\":");
+    let _ = json.push_str(if time_sync::is_synced() { "true" } else { "false" });
+    u32_to_string((sys::esp_timer_get_time() / 1000) as u32, &mut num);
+    let _ = json.push_str(",\"uptime_ms\":");
+    let _ = json.push_str(num.as_str());
+    let _ = json.push_str("}");
+    send_json(req, json.as_str())
+}
+
+unsafe extern "C" fn handle_gpio(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    // {"gpio":[{"pin":4,"mode":"digital_input","level":1},...]}, one chunk per pin
+    sys::httpd_resp_set_type(req, b"application/json\0".as_ptr() as *const c_char);
+    let levels = LEVELS.load();
+    let mut first = true;
+    if send_chunk(req, "{\"gpio\":[") != sys::ESP_OK {
+        return sys::ESP_FAIL;
+    }
+    for gpio_config in stored_config::gpio_config() {
+        let mode = match gpio_config.mode {
+            GpioMode::DigitalInput => "digital_input",
+            GpioMode::DigitalOutput => "digital_output",
+            GpioMode::TouchInput => "touch_input",
+            // No digital level to report (analog inputs, PWM duty cycles)
+            _ => continue,
+        };
+        let mut entry: String<64> = String::new();
+        let mut num: String<16> = String::new();
+        let _ = entry.push_str(if first { "{\"pin\":" } else { ",{\"pin\":" });
+        u32_to_string(gpio_config.pin, &mut num);
+        let _ = entry.push_str(num.as_str());
+        let _ = entry.push_str(",\"mode\":\"");
+        let _ = entry.push_str(mode);
+        let _ = entry.push_str("\",\"level\":");
+        let _ = entry.push_str(if (levels >> gpio_config.pin) & 1 == 1 { "1}" } else { "0}" });
+        first = false;
+        if send_chunk(req, entry.as_str()) != sys::ESP_OK {
+            return sys::ESP_FAIL;
+        }
+    }
+    if send_chunk(req, "]}") != sys::ESP_OK {
+        return sys::ESP_FAIL;
+    }
+    sys::httpd_resp_send_chunk(req, core::ptr::null(), 0)
+}
+
+unsafe extern "C" fn handle_stop(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    REQUEST.store(REQUEST_STOP, Ordering::Release);
+    // Applied by the burst loop within one burst
+    sys::httpd_resp_set_status(req, b"202 Accepted\0".as_ptr() as *const c_char);
+    send_json(req, "{\"safe_stop\":true}")
+}
+
+unsafe extern "C" fn handle_resume(req: *mut sys::httpd_req_t) -> sys::esp_err_t {
+    REQUEST.store(REQUEST_RESUME, Ordering::Release);
+    sys::httpd_resp_set_status(req, b"202 Accepted\0".as_ptr() as *const c_char);
+    send_json(req, "{\"safe_stop\":false}")
+}
+
+unsafe fn send_json(req: *mut sys::httpd_req_t, json: &str) -> sys::esp_err_t {
+    sys::httpd_resp_set_type(req, b"application/json\0".as_ptr() as *const c_char);
+    sys::httpd_resp_send(req, json.as_ptr() as *const c_char, json.len() as _)
+}
+
+unsafe fn send_chunk(req: *mut sys::httpd_req_t, chunk: &str) -> sys::esp_err_t {
+    sys::httpd_resp_send_chunk(req, chunk.as_ptr() as *const c_char, chunk.len() as _)
+}
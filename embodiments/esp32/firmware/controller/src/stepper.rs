@@ -0,0 +1,349 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Step/direction stepper motors (`stepper_output`)
+//!
+//! The output's pin is the driver's STEP input (A4988, DRV8825, TMC2209 in
+//! step/dir mode), `dir_pin` its DIR input and the optional `enable_pin` its
+//! active-low EN input, driven low (motor energized) from boot.
+//!
+//! A motor command sets the axis target, per `control`:
+//! - `velocity`: a signed speed as for DC motors, 0.5 = stop, 1.0 / 0.0 =
+//!   `max_speed_sps` forward / in reverse
+//! - `position`: an absolute position, 0.0-1.0 = step 0-`range_steps`
+//!   counted from the position at boot
+//!
+//! Pulses come from one hardware timer interrupt shared by all axes, ticking
+//! at `TICK_HZ`. Every millisecond it moves each axis' speed towards what
+//! its target asks for by at most `accel_sps2`; position axes ask for the
+//! fastest speed they can still stop from at the target, and never step
+//! past it. The handler only does integer math (no FPU in interrupts).
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering};
+
+use esp_idf_svc::sys;
+
+/// Axes served by the step timer
+pub const MAX_STEPPERS: usize = 4;
+
+/// Step timer rate; a step takes a high and a low tick
+const TICK_HZ: u32 = 20_000;
+
+/// Fastest step rate an axis can be configured for
+pub const MAX_STEP_RATE: u32 = TICK_HZ / 2;
+
+/// Ticks between speed updates (1 ms)
+const PLAN_TICKS: u32 = TICK_HZ / 1000;
+
+/// Speeds are kept in milli-steps per second
+const MILLI: i64 = 1000;
+
+/// What a motor command sets on an axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepperControl {
+    Velocity,
+    Position,
+}
+
+impl StepperControl {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepperControl::Velocity => "velocity",
+            StepperControl::Position => "position",
+        }
+    }
+}
+
+/// Driver wiring and motion limits of a `stepper_output` (from config.json
+/// `stepper`)
+#[derive(Debug, Clone, Copy)]
+pub struct StepperConfig {
+    pub dir_pin: u32,
+    /// Active-low driver enable, if wired
+    pub enable_pin: Option<u32>,
+    pub control: StepperControl,
+    /// Top speed, in steps per second (at most MAX_STEP_RATE)
+    pub max_speed_sps: u32,
+    /// Acceleration and deceleration limit, in steps per second squared
+    pub accel_sps2: u32,
+    /// Steps commanded by 1.0 (position control)
+    pub range_steps: u32,
+    /// Swap the DIR level of forward and reverse
+    pub invert: bool,
+}
+
+/// Per-axis state shared with the timer interrupt
+struct AxisSlot {
+    step_pin: AtomicU32,
+    dir_pin: AtomicU32,
+    invert: AtomicBool,
+    position_control: AtomicBool,
+    /// Milli-steps per second
+    max_speed: AtomicI32,
+    /// Steps per second squared (= milli-steps per second, per millisecond)
+    accel: AtomicI32,
+    /// Target position in steps, or target speed in milli-steps per second
+    target: AtomicI32,
+    /// Current speed, milli-steps per second (negative in reverse)
+    velocity: AtomicI32,
+    /// Steps from the position at boot
+    position: AtomicI32,
+    /// Step phase accumulator; a step is due at TICK_HZ * MILLI
+    phase: AtomicU32,
+    step_high: AtomicBool,
+    /// Direction the DIR pin currently selects
+    forward: AtomicBool,
+}
+
+// Only used as the array initializer below
+#[allow(clippy::declare_interior_mutable_const)]
+const IDLE_AXIS: AxisSlot = AxisSlot {
+    step_pin: AtomicU32::new(0),
+    dir_pin: AtomicU32::new(0),
+    invert: AtomicBool::new(false),
+    position_control: AtomicBool::new(false),
+    max_speed: AtomicI32::new(0),
+    accel: AtomicI32::new(0),
+    target: AtomicI32::new(0),
+    velocity: AtomicI32::new(0),
+    position: AtomicI32::new(0),
+    phase: AtomicU32::new(0),
+    step_high: AtomicBool::new(false),
+    forward: AtomicBool::new(true),
+};
+
+static AXES: [AxisSlot; MAX_STEPPERS] = [IDLE_AXIS; MAX_STEPPERS];
+/// Axes attached so far (the timer only serves these)
+static AXIS_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TICKS: AtomicU32 = AtomicU32::new(0);
+/// Set by `halt`: the step timer stops stepping for good
+static HALTED: AtomicBool = AtomicBool::new(false);
+
+/// Slot of the axis stepped through `step_pin`
+fn axis(step_pin: u32) -> Option<&'static AxisSlot> {
+    AXES[..AXIS_COUNT.load(Ordering::Acquire)]
+        .iter()
+        .find(|a| a.step_pin.load(Ordering::Relaxed) == step_pin)
+}
+
+/// Set up an axis' pins and hand it to the step timer, stopped
+///
+/// The timer is started with the first axis. Returns false if all slots are
+/// taken or the timer couldn't be started.
+pub fn attach(step_pin: u32, config: &StepperConfig) -> bool {
+    let index = AXIS_COUNT.load(Ordering::Acquire);
+    if index >= MAX_STEPPERS || (index == 0 && !start_timer()) {
+        return false;
+    }
+    for gpio in [Some(step_pin), Some(config.dir_pin), config.enable_pin].into_iter().flatten() {
+        unsafe {
+            sys::gpio_reset_pin(gpio as i32);
+            sys::gpio_set_level(gpio as i32, 0);
+            sys::gpio_set_direction(gpio as i32, sys::gpio_mode_t_GPIO_MODE_OUTPUT);
+        }
+    }
+    let slot = &AXES[index];
+    slot.step_pin.store(step_pin, Ordering::Relaxed);
+    slot.dir_pin.store(config.dir_pin, Ordering::Relaxed);
+    slot.invert.store(config.invert, Ordering::Relaxed);
+    slot.position_control.store(config.control == StepperControl::Position, Ordering::Relaxed);
+    slot.max_speed.store((config.max_speed_sps.min(MAX_STEP_RATE) as i64 * MILLI) as i32, Ordering::Relaxed);
+    slot.accel.store(config.accel_sps2.min(i32::MAX as u32) as i32, Ordering::Relaxed);
+    // The DIR pin starts low, which is reverse unless inverted
+    slot.forward.store(config.invert, Ordering::Relaxed);
+    // Publish the slot to the interrupt last
+    AXIS_COUNT.store(index + 1, Ordering::Release);
+    true
+}
+
+/// Set an axis' target from a 0.0-1.0 command
+///
+/// Returns the command as applied (clamped).
+pub fn command(step_pin: u32, config: &StepperConfig, value: f32) -> Option<f32> {
+    let slot = axis(step_pin)?;
+    let value = value.clamp(0.0, 1.0);
+    let target = match config.control {
+        StepperControl::Velocity => ((value * 2.0 - 1.0) * config.max_speed_sps.min(MAX_STEP_RATE) as f32 * MILLI as f32) as i32,
+        StepperControl::Position => (value * config.range_steps as f32 + 0.5) as i32,
+    };
+    slot.target.store(target, Ordering::Relaxed);
+    Some(value)
+}
+
+/// Decelerate an axis to a stop where it is (safe-stop)
+pub fn stop(step_pin: u32) {
+    if let Some(slot) = axis(step_pin) {
+        if slot.position_control.load(Ordering::Relaxed) {
+            // Hold the position it can stop at with the current speed
+            let velocity = slot.velocity.load(Ordering::Relaxed) as i64;
+            let accel = (slot.accel.load(Ordering::Relaxed) as i64).max(1);
+            let braking_steps = velocity * velocity.abs() / (2 * accel * MILLI * MILLI);
+            let position = slot.position.load(Ordering::Relaxed) as i64;
+            slot.target.store((position + braking_steps) as i32, Ordering::Relaxed);
+        } else {
+            slot.target.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Stop every axis at once, without decelerating; safe to call from an
+/// interrupt or the panic handler (see failsafe.rs). Nothing steps after this.
+pub fn halt() {
+    HALTED.store(true, Ordering::Release);
+}
+
+/// Where the axis actually is, on its command's 0.0-1.0 scale
+///
+/// Current speed for velocity control, current position for position
+/// control (output echo).
+pub fn feedback(step_pin: u32, config: &StepperConfig) -> Option<f32> {
+    let slot = axis(step_pin)?;
+    let value = match config.control {
+        StepperControl::Velocity => {
+            let max_speed = slot.max_speed.load(Ordering::Relaxed).max(1) as f32;
+            0.5 + slot.velocity.load(Ordering::Relaxed) as f32 / (2.0 * max_speed)
+        }
+        StepperControl::Position => slot.position.load(Ordering::Relaxed) as f32 / config.range_steps.max(1) as f32,
+    };
+    Some(value.clamp(0.0, 1.0))
+}
+
+/// Start the shared step timer
+fn start_timer() -> bool {
+    unsafe {
+        let mut timer_config: sys::gptimer_config_t = core::mem::zeroed();
+        timer_config.clk_src = sys::soc_periph_gptimer_clk_src_t_GPTIMER_CLK_SRC_DEFAULT;
+        timer_config.direction = sys::gptimer_count_direction_t_GPTIMER_COUNT_UP;
+        timer_config.resolution_hz = 1_000_000;
+        let mut timer: sys::gptimer_handle_t = core::ptr::null_mut();
+        if sys::gptimer_new_timer(&timer_config, &mut timer) != sys::ESP_OK {
+            return false;
+        }
+
+        let callbacks = sys::gptimer_event_callbacks_t { on_alarm: Some(on_tick) };
+        let mut alarm: sys::gptimer_alarm_config_t = core::mem::zeroed();
+        alarm.alarm_count = (1_000_000 / TICK_HZ) as u64;
+        alarm.reload_count = 0;
+        alarm.flags.set_auto_reload_on_alarm(1);
+        if sys::gptimer_register_event_callbacks(timer, &callbacks, core::ptr::null_mut()) != sys::ESP_OK
+            || sys::gptimer_set_alarm_action(timer, &alarm) != sys::ESP_OK
+            || sys::gptimer_enable(timer) != sys::ESP_OK
+            || sys::gptimer_start(timer) != sys::ESP_OK
+        {
+            sys::gptimer_del_timer(timer);
+            return false;
+        }
+    }
+    true
+}
+
+/// Largest integer whose square is at most `n`
+fn isqrt(mut n: u64) -> u64 {
+    let mut root = 0;
+    let mut bit = 1u64 << 62;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= root + bit {
+            n -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+/// Move an axis' speed towards its target by at most one millisecond of
+/// acceleration
+fn plan(slot: &AxisSlot) {
+    let velocity = slot.velocity.load(Ordering::Relaxed) as i64;
+    let accel = slot.accel.load(Ordering::Relaxed) as i64;
+    let max_speed = slot.max_speed.load(Ordering::Relaxed) as i64;
+    let target = slot.target.load(Ordering::Relaxed) as i64;
+    let desired = if slot.position_control.load(Ordering::Relaxed) {
+        // Fastest speed that can still stop at the target: v² = 2·a·d
+        let error = target - slot.position.load(Ordering::Relaxed) as i64;
+        let reach = (isqrt(2 * accel as u64 * error.unsigned_abs()) as i64 * MILLI).min(max_speed);
+        if error < 0 {
+            -reach
+        } else {
+            reach
+        }
+    } else {
+        target.clamp(-max_speed, max_speed)
+    };
+    let velocity = velocity + (desired - velocity).clamp(-accel, accel);
+    slot.velocity.store(velocity as i32, Ordering::Relaxed);
+}
+
+/// Advance one axis by one tick: end a step pulse, or start one when due
+fn step(slot: &AxisSlot) {
+    let step_pin = slot.step_pin.load(Ordering::Relaxed) as i32;
+    if slot.step_high.load(Ordering::Relaxed) {
+        unsafe {
+            sys::gpio_set_level(step_pin, 0);
+        }
+        slot.step_high.store(false, Ordering::Relaxed);
+        return;
+    }
+    let velocity = slot.velocity.load(Ordering::Relaxed);
+    if velocity == 0 {
+        slot.phase.store(0, Ordering::Relaxed);
+        return;
+    }
+    let forward = velocity > 0;
+    if forward != slot.forward.load(Ordering::Relaxed) {
+        // Direction change: DIR settles for a tick before the next step
+        let level = forward != slot.invert.load(Ordering::Relaxed);
+        unsafe {
+            sys::gpio_set_level(slot.dir_pin.load(Ordering::Relaxed) as i32, level as u32);
+        }
+        slot.forward.store(forward, Ordering::Relaxed);
+        return;
+    }
+    let due = TICK_HZ * MILLI as u32;
+    let phase = slot.phase.load(Ordering::Relaxed) + velocity.unsigned_abs();
+    let position = slot.position.load(Ordering::Relaxed);
+    let at_target = slot.position_control.load(Ordering::Relaxed) && position == slot.target.load(Ordering::Relaxed);
+    if phase < due || at_target {
+        slot.phase.store(phase.min(due), Ordering::Relaxed);
+        return;
+    }
+    slot.phase.store(phase - due, Ordering::Relaxed);
+    unsafe {
+        sys::gpio_set_level(step_pin, 1);
+    }
+    slot.step_high.store(true, Ordering::Relaxed);
+    slot.position.store(if forward { position + 1 } else { position - 1 }, Ordering::Relaxed);
+}
+
+/// Step timer alarm: runs in interrupt context every tick
+unsafe extern "C" fn on_tick(
+    _timer: sys::gptimer_handle_t,
+    _event: *const sys::gptimer_alarm_event_data_t,
+    _user_ctx: *mut c_void,
+) -> bool {
+    if HALTED.load(Ordering::Acquire) {
+        return false;
+    }
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed);
+    let axes = &AXES[..AXIS_COUNT.load(Ordering::Acquire)];
+    if ticks % PLAN_TICKS == 0 {
+        for slot in axes {
+            plan(slot);
+        }
+    }
+    for slot in axes {
+        step(slot);
+    }
+    // No task to wake
+    false
+}
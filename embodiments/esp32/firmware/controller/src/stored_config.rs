@@ -0,0 +1,310 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Configuration stored in NVS, so it can be changed without rebuilding
+//!
+//! `{"store_config":{"burst_frequency":50,"gpio.25.cortical_mapping":"omot00:3"}}`
+//! validates every pair first and stores them all or none, answered by
+//! `{"config_ack":{"ok":true,"n":2}}` or
+//! `{"config_ack":{"ok":false,"key":"...","error":"..."}}`. The values take
+//! effect on the next boot and replace config.json's there (`"src":"nvs"` in
+//! `{"get_config":1}`); until then the board keeps running on the old ones.
+//!
+//! Accepted keys are the runtime settings (settings.rs) other than
+//! `device_id`, which `set_settings` stores already, and the ones that are
+//! only read at boot:
+//!
+//! | Key                            | Value                                     |
+//! |--------------------------------|-------------------------------------------|
+//! | `burst_frequency`              | 1-1000 Hz                                 |
+//! | `transport.host`               | FEAGI (or broker) IPv4 address, `a.b.c.d` |
+//! | `transport.port`               | 1-65535                                   |
+//! | `gpio.<pin>.mode`              | digital_input, digital_output or disabled |
+//! | `gpio.<pin>.cortical_mapping`  | As in config.json                         |
+//!
+//! A pin's mode can only change if config.json makes it a plain digital
+//! input or output (no `population`); GPIO 34-39 stay inputs.
+//!
+//! The provisioning portal and BLE provisioning store their FEAGI host and
+//! port the same way. Later stores of a key replace earlier ones.
+//! `{"get_stored_config":1}` is answered by `{"stored_config":{...}}`, and
+//! `{"clear_config":1}` forgets everything (the device ID too), answered by
+//! `{"config_ack":{"ok":true,"n":0}}`; config.json applies again after the
+//! next reboot.
+//!
+//! `{"set_gpio":{"pin":25,"mode":"digital_output","cortical_mapping":"omot00:3"}}`
+//! remaps one pin (either field may be left out) without rebuilding: it's
+//! validated and stored like `store_config`, answered by `{"gpio_ack":{...}}`,
+//! and on success the board stops its outputs, sends `{"restarting":"gpio"}`
+//! and reboots to set up its pins again.
+
+use core::ffi::c_char;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use esp_idf_svc::sys;
+use feagi_controller_core::parse::pairs;
+use heapless::{String, Vec};
+use static_cell::StaticCell;
+
+use crate::debounce::{InputConfig, Trigger};
+use crate::device_id;
+use crate::provisioning::init_nvs;
+use crate::settings::{Mapping, Settings, SettingsError, GPIO_PINS};
+use crate::{GpioMode, GpioPinConfig, GPIO_CONFIG};
+
+/// Longest stored configuration, as a JSON object
+pub const STORED_CONFIG_CAPACITY: usize = 1024;
+
+pub type StoredConfig = String<STORED_CONFIG_CAPACITY>;
+
+const NAMESPACE: &[u8] = b"feagi_cfg\0";
+const KEY_CONFIG: &[u8] = b"config\0";
+
+/// Filter of an output turned input: plain levels, config.json's default
+const PLAIN_INPUT: InputConfig = InputConfig { trigger: Trigger::Level, debounce_ms: 0, interrupt: false };
+
+/// GPIO_CONFIG with the stored modes and cortical mappings, built once at boot
+static GPIO: StaticCell<Vec<GpioPinConfig, GPIO_PINS>> = StaticCell::new();
+static MAPPINGS: StaticCell<Vec<Mapping, GPIO_PINS>> = StaticCell::new();
+/// GPIO once it's filled in, for the other tasks
+static PATCHED: AtomicPtr<Vec<GpioPinConfig, GPIO_PINS>> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Is this line a `{"store_config":{...}}` request?
+pub fn is_store_request(message: &str) -> bool {
+    message.starts_with("{\"store_config\"")
+}
+
+/// Is this line a `{"set_gpio":{...}}` request?
+pub fn is_gpio_request(message: &str) -> bool {
+    message.starts_with("{\"set_gpio\"")
+}
+
+/// Is this line a `{"get_stored_config":...}` request?
+pub fn is_get_request(message: &str) -> bool {
+    message.starts_with("{\"get_stored_config\"")
+}
+
+/// Is this line a `{"clear_config":...}` request?
+pub fn is_clear_request(message: &str) -> bool {
+    message.starts_with("{\"clear_config\"")
+}
+
+/// The configuration stored so far, if any
+pub fn load() -> Option<StoredConfig> {
+    if !init_nvs() {
+        return None;
+    }
+    let mut buf = [0u8; STORED_CONFIG_CAPACITY + 1];
+    unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READONLY, &mut handle) != sys::ESP_OK {
+            return None;
+        }
+        let mut len = buf.len();
+        let ok = sys::nvs_get_str(handle, KEY_CONFIG.as_ptr() as *const c_char, buf.as_mut_ptr() as *mut c_char, &mut len) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        if !ok {
+            return None;
+        }
+        // `len` counts the terminating NUL
+        let config = core::str::from_utf8(&buf[..len.saturating_sub(1)]).ok()?;
+        let mut stored = StoredConfig::new();
+        stored.push_str(config).ok()?;
+        Some(stored)
+    }
+}
+
+/// Validate and store a `store_config` request on top of what's stored
+///
+/// Returns the number of values stored.
+pub fn store_request(settings: &Settings, message: &str) -> Result<usize, SettingsError> {
+    let malformed = || SettingsError { key: None, reason: "expected an object of key/value pairs" };
+    let object = message
+        .strip_prefix("{\"store_config\":")
+        .and_then(|rest| rest.trim_end().strip_suffix('}'))
+        .ok_or_else(malformed)?;
+    let updates = pairs(object).filter(|p| !p.is_empty()).ok_or_else(malformed)?;
+    validate_and_store(settings, &updates)
+}
+
+/// Validate and store a `set_gpio` request as its `gpio.<pin>.*` keys
+///
+/// Returns the number of values stored.
+pub fn gpio_request(settings: &Settings, message: &str) -> Result<usize, SettingsError> {
+    let malformed = || SettingsError { key: None, reason: "expected a pin and its mode and/or cortical_mapping" };
+    let object = message
+        .strip_prefix("{\"set_gpio\":")
+        .and_then(|rest| rest.trim_end().strip_suffix('}'))
+        .ok_or_else(malformed)?;
+    let fields = pairs(object).ok_or_else(malformed)?;
+    let pin = fields
+        .iter()
+        .find(|(field, _)| *field == "pin")
+        .and_then(|(_, value)| value.parse::<u32>().ok())
+        .ok_or_else(malformed)?;
+    let mut keys: Vec<(String<32>, &str), 2> = Vec::new();
+    for &(field, value) in fields.iter() {
+        if field == "pin" {
+            continue;
+        }
+        if field != "mode" && field != "cortical_mapping" {
+            return Err(SettingsError { key: None, reason: "expected pin, mode or cortical_mapping" });
+        }
+        let mut key: String<32> = String::new();
+        let _ = write!(key, "gpio.{}.{}", pin, field);
+        keys.push((key, value)).map_err(|_| malformed())?;
+    }
+    let updates: Vec<(&str, &str), 2> = keys.iter().map(|(key, value)| (key.as_str(), *value)).collect();
+    if updates.is_empty() {
+        return Err(malformed());
+    }
+    validate_and_store(settings, &updates)
+}
+
+/// Validate every pair on a copy of `settings`, then store them all
+fn validate_and_store(settings: &Settings, updates: &[(&str, &str)]) -> Result<usize, SettingsError> {
+    let mut staged = settings.clone();
+    for &(key, value) in updates.iter() {
+        staged.set_stored(key, value)?;
+    }
+    if !store(updates) {
+        return Err(SettingsError { key: None, reason: "failed to write NVS" });
+    }
+    Ok(updates.len())
+}
+
+/// Store `updates` on top of what's stored; false if NVS failed or the
+/// result doesn't fit STORED_CONFIG_CAPACITY
+pub fn store(updates: &[(&str, &str)]) -> bool {
+    let previous = load().unwrap_or_default();
+    let mut config: String<{ STORED_CONFIG_CAPACITY + 1 }> = String::new();
+    let mut kept = pairs(&previous).unwrap_or_default();
+    kept.retain(|(key, _)| !updates.iter().any(|(k, _)| k == key));
+    let fits = config.push('{').is_ok()
+        && kept.iter().chain(updates.iter()).enumerate().all(|(i, &(key, value))| push_pair(&mut config, i > 0, key, value))
+        && config.push('}').is_ok()
+        && config.len() <= STORED_CONFIG_CAPACITY
+        && config.push('\0').is_ok();
+    if !fits || !init_nvs() {
+        return false;
+    }
+    unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READWRITE, &mut handle) != sys::ESP_OK {
+            return false;
+        }
+        let ok = sys::nvs_set_str(handle, KEY_CONFIG.as_ptr() as *const c_char, config.as_ptr() as *const c_char) == sys::ESP_OK
+            && sys::nvs_commit(handle) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        ok
+    }
+}
+
+/// Forget the stored configuration and device ID; false if NVS failed
+pub fn clear() -> bool {
+    if !init_nvs() {
+        return false;
+    }
+    let cleared = unsafe {
+        let mut handle: sys::nvs_handle_t = 0;
+        if sys::nvs_open(NAMESPACE.as_ptr() as *const c_char, sys::nvs_open_mode_t_NVS_READWRITE, &mut handle) != sys::ESP_OK {
+            return false;
+        }
+        let ok = sys::nvs_erase_all(handle) == sys::ESP_OK && sys::nvs_commit(handle) == sys::ESP_OK;
+        sys::nvs_close(handle);
+        ok
+    };
+    cleared && device_id::clear()
+}
+
+/// Apply the stored configuration at boot
+///
+/// Returns how many values were skipped: stored by an older firmware, or
+/// no longer valid for this build's config.json.
+pub fn apply(settings: &mut Settings, stored: &str) -> usize {
+    let Some(pairs) = pairs(stored) else {
+        return 1;
+    };
+    pairs.iter().filter(|&&(key, value)| settings.set_stored(key, value).is_err()).count()
+}
+
+/// `{"stored_config":{...}}` answer to a `get_stored_config` request
+pub fn get_line() -> String<{ STORED_CONFIG_CAPACITY + 32 }> {
+    let mut line: String<{ STORED_CONFIG_CAPACITY + 32 }> = String::new();
+    let _ = line.push_str("{\"stored_config\":");
+    match load() {
+        Some(stored) => {
+            let _ = line.push_str(&stored);
+        }
+        None => {
+            let _ = line.push_str("{}");
+        }
+    }
+    let _ = line.push_str("}\n");
+    line
+}
+
+/// GPIO_CONFIG with the modes and cortical mappings in `settings`; call
+/// once, at boot
+///
+/// The table lives on in a static, so the pin banks can hold on to the
+/// mapping strings like they do to config.json's.
+pub fn patch_gpio_config(settings: &Settings) -> &'static [GpioPinConfig] {
+    let unchanged = settings.cortical_mappings.is_empty() && settings.gpio_modes.is_empty();
+    if unchanged || !PATCHED.load(Ordering::Acquire).is_null() {
+        return gpio_config();
+    }
+    // Filled in place to keep a few KiB off the main task's stack
+    let mappings = MAPPINGS.uninit().write(Vec::new());
+    for (_, mapping) in settings.cortical_mappings.iter() {
+        let _ = mappings.push(mapping.clone());
+    }
+    let mappings: &'static Vec<Mapping, GPIO_PINS> = mappings;
+    let gpio = GPIO.uninit().write(Vec::new());
+    for config in GPIO_CONFIG {
+        let mut config = *config;
+        if let Some(i) = settings.cortical_mappings.iter().position(|(pin, _)| *pin == config.pin) {
+            config.cortical_mapping = mappings[i].as_str();
+        }
+        let mode = settings.gpio_mode(&config);
+        if mode != config.mode {
+            // Only digital inputs read their pin, with config.json's
+            // filter if they had one
+            config.input = match mode {
+                GpioMode::DigitalInput => config.input.or(Some(PLAIN_INPUT)),
+                _ => None,
+            };
+            config.mode = mode;
+        }
+        let _ = gpio.push(config);
+    }
+    PATCHED.store(gpio, Ordering::Release);
+    gpio_config()
+}
+
+/// The GPIO table in effect: GPIO_CONFIG, with the stored modes and cortical
+/// mappings once `patch_gpio_config` ran
+pub fn gpio_config() -> &'static [GpioPinConfig] {
+    let gpio = PATCHED.load(Ordering::Acquire);
+    if gpio.is_null() {
+        GPIO_CONFIG
+    } else {
+        // Only ever set to GPIO, which isn't written after that
+        unsafe { (*gpio).as_slice() }
+    }
+}
+
+/// Append `"key":value`, quoting values that aren't numbers or booleans
+fn push_pair<const N: usize>(out: &mut String<N>, comma: bool, key: &str, value: &str) -> bool {
+    let number = value.bytes().all(|b| b.is_ascii_digit() || b == b'.' || b == b'-') && value.parse::<f64>().is_ok();
+    let bare = number || value == "true" || value == "false";
+    let quote = if bare { "" } else { "\"" };
+    [if comma { "," } else { "" }, "\"", key, "\":", quote, value, quote]
+        .iter()
+        .all(|part| out.push_str(part).is_ok())
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Transport supervision: restart a wedged transport without rebooting
+//!
+//! The supervisor watches the transport's health from the burst loop. When it
+//! wedges (too many consecutive I/O errors, no received line for `silence_ms`
+//! when that watchdog is enabled, or a keepalive timeout, see keepalive.rs)
+//! the main loop tears down just the transport driver and reinitializes it,
+//! while the burst engine, outputs and device registry keep running.
+//!
+//! Restarts back off exponentially from `backoff_ms`, with jitter so a room
+//! full of boards doesn't hammer FEAGI in lockstep after a WiFi outage. After
+//! `max_restarts` failed attempts in a row the supervisor escalates to a full
+//! reboot, which was the previous behaviour for a wedged link. The count
+//! starts over only once a restarted link has heard from the host and stayed
+//! up for `STABLE_MS`; a link that accepts writes but drops again soon after
+//! keeps counting. Sensory frames produced meanwhile wait in a bounded queue
+//! (controller-core/src/frame_queue.rs).
+//!
+//! With fallback transports configured (`transport.fallback`), a primary
+//! that stays down for `failover_ms`, or exhausts its restarts, is replaced
+//! by the next fallback instead of rebooting.
+
+use esp_idf_svc::sys;
+
+/// How long a restarted link has to stay up before its restarts are forgiven
+const STABLE_MS: i64 = 10_000;
+
+/// Supervision thresholds (from config.json `transport.supervision`)
+#[derive(Debug, Clone, Copy)]
+pub struct SupervisionConfig {
+    /// Consecutive read/write errors that count as wedged
+    pub error_limit: u32,
+    /// Restart if nothing was received for this long (0 = disabled)
+    pub silence_ms: u32,
+    /// Failed restarts in a row before rebooting the board
+    pub max_restarts: u32,
+    /// Delay before the first restart attempt, doubled per failed attempt
+    pub backoff_ms: u32,
+    /// Sensory frames kept while the transport is down (0 = drop them)
+    pub queue_frames: u32,
+    /// Fail over to the next fallback transport after being down this long
+    /// (0 = only once restarts are exhausted)
+    pub failover_ms: u32,
+}
+
+/// What the main loop should do with the transport this burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Transport is fine (or a restart is still backing off)
+    None,
+    /// Drop the transport driver and bring it up again
+    Restart,
+    /// Restarts keep failing: reboot the board
+    Reboot,
+    /// The transport stayed down: switch to the next fallback transport
+    Failover,
+}
+
+pub struct TransportSupervisor {
+    config: SupervisionConfig,
+    consecutive_errors: u32,
+    last_rx_us: i64,
+    /// Failed restarts since the transport was last healthy
+    attempts: u32,
+    /// Earliest time for the next restart attempt while the transport is down
+    retry_at_us: Option<i64>,
+    /// When the transport was first seen wedged, while it still is
+    down_since_us: Option<i64>,
+    /// Since when the transport has been healthy, while it still is
+    up_since_us: Option<i64>,
+    /// The host was heard from since the last restart or failover
+    heard: bool,
+    /// A fallback transport is left to fail over to
+    can_fail_over: bool,
+    /// The keepalive timed out since the last restart or received line
+    link_lost: bool,
+    /// Restarts since boot
+    pub restarts: u32,
+    /// Restarts since boot that brought the transport back
+    pub reconnects: u32,
+}
+
+impl TransportSupervisor {
+    /// `can_fail_over`: fallback transports are configured
+    pub fn new(config: SupervisionConfig, can_fail_over: bool) -> Self {
+        Self {
+            config,
+            consecutive_errors: 0,
+            last_rx_us: now_us(),
+            attempts: 0,
+            retry_at_us: None,
+            down_since_us: None,
+            up_since_us: None,
+            heard: false,
+            can_fail_over,
+            link_lost: false,
+            restarts: 0,
+            reconnects: 0,
+        }
+    }
+
+    /// A line or read completed successfully
+    pub fn record_rx(&mut self) {
+        self.consecutive_errors = 0;
+        self.last_rx_us = now_us();
+        self.heard = true;
+        self.link_lost = false;
+    }
+
+    /// The keepalive timed out: the host is gone, restart the transport
+    pub fn link_lost(&mut self) {
+        self.link_lost = true;
+    }
+
+    /// A write completed successfully
+    pub fn record_tx(&mut self) {
+        self.consecutive_errors = 0;
+    }
+
+    /// A read or write failed
+    pub fn record_error(&mut self) {
+        self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+    }
+
+    /// Decide what to do this burst; `up` is whether the transport exists
+    pub fn check(&mut self, up: bool) -> Action {
+        let now = now_us();
+        let wedged = !up
+            || self.link_lost
+            || (self.config.error_limit > 0 && self.consecutive_errors >= self.config.error_limit)
+            || (self.config.silence_ms > 0 && now - self.last_rx_us >= self.config.silence_ms as i64 * 1000);
+        if !wedged {
+            self.retry_at_us = None;
+            self.down_since_us = None;
+            let up_since = *self.up_since_us.get_or_insert(now);
+            if self.heard && now - up_since >= STABLE_MS * 1000 {
+                self.attempts = 0;
+            }
+            return Action::None;
+        }
+        self.up_since_us = None;
+        let down_since = *self.down_since_us.get_or_insert(now);
+        if self.can_fail_over && self.config.failover_ms > 0 && now - down_since >= self.config.failover_ms as i64 * 1000 {
+            return Action::Failover;
+        }
+        let retry_at = *self.retry_at_us.get_or_insert_with(|| now + self.backoff_us());
+        if now < retry_at {
+            return Action::None;
+        }
+        if self.attempts >= self.config.max_restarts {
+            return if self.can_fail_over { Action::Failover } else { Action::Reboot };
+        }
+        Action::Restart
+    }
+
+    /// Switched to a fallback transport (`ok`: it came up); `more` fallbacks
+    /// remain after it
+    pub fn failed_over(&mut self, ok: bool, more: bool) {
+        self.can_fail_over = more;
+        self.attempts = 0;
+        self.consecutive_errors = 0;
+        self.link_lost = false;
+        self.last_rx_us = now_us();
+        self.down_since_us = None;
+        self.up_since_us = None;
+        self.heard = false;
+        self.retry_at_us = if ok { None } else { Some(now_us() + self.backoff_us()) };
+    }
+
+    /// Outcome of a restart attempt
+    pub fn restarted(&mut self, ok: bool) {
+        self.restarts = self.restarts.wrapping_add(1);
+        if ok {
+            self.reconnects = self.reconnects.wrapping_add(1);
+        }
+        self.consecutive_errors = 0;
+        self.link_lost = false;
+        self.up_since_us = None;
+        self.heard = false;
+        // A fresh link gets a full silence window before it's judged again
+        self.last_rx_us = now_us();
+        self.attempts += 1;
+        self.retry_at_us = if ok { None } else { Some(now_us() + self.backoff_us()) };
+    }
+
+    /// Exponential backoff with "equal jitter": half the delay is fixed, the
+    /// other half random
+    fn backoff_us(&self) -> i64 {
+        let shift = self.attempts.min(6);
+        let delay = (self.config.backoff_ms as i64 * 1000) << shift;
+        let half = delay / 2;
+        half + (unsafe { sys::esp_random() } as i64 % (half + 1))
+    }
+}
+
+fn now_us() -> i64 {
+    unsafe { sys::esp_timer_get_time() }
+}
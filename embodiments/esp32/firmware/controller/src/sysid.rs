@@ -0,0 +1,175 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! System-identification mode
+//!
+//! On request from the host, the controller drives one proportional output
+//! (PWM, servo, DC motor or stepper) through a step, ramp, or chirp signal
+//! while sampling the response channels at `sysid.rate_hz`, streaming
+//! `{"sy":[t_us,u,y0,y1,...]}` lines over the transport. The responses are,
+//! in this order, every servo feedback pot, every encoder's position and
+//! velocity, and the IMU's accel and gyro axes (read directly, not by the
+//! I2C scheduler). The host trace tool (`tools/feagi_esp32.py sysid`) turns
+//! the recording into gain/latency estimates.
+//!
+//! Samples are paced by a periodic esp_timer (as the burst loop is, see
+//! burst_timer.rs), so the wait between them leaves the CPU to other tasks.
+//!
+//! Request format: `{"sysid":{"n":<neuron_id>,"sig":"step|ramp|chirp","ms":<duration>,
+//! "amp":<0.0-1.0>,"f0":<start Hz>,"f1":<end Hz>}}`. Only `n` is required. A
+//! neuron without a proportional output is answered by
+//! `{"sy_err":"not a proportional output"}`.
+
+use heapless::{String, Vec};
+
+use esp_idf_svc::sys;
+use feagi_controller_core::parse;
+
+use crate::burst_timer::BurstTimer;
+use crate::{u32_to_string, unit_f32_to_string};
+use crate::{SYSID_LINE_CAPACITY, SYSID_RESPONSES};
+
+extern "C" {
+    // newlib's single-precision sine (no libm in no_std)
+    fn sinf(x: f32) -> f32;
+}
+
+const TWO_PI: f32 = 6.283_185_5;
+
+/// Excitation signal shape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// 0 for the first 10% of the run, then `amp`
+    Step,
+    /// Linear ramp from 0 to `amp`
+    Ramp,
+    /// Linear frequency sweep from `f0` to `f1` around `amp / 2`
+    Chirp,
+}
+
+/// A parsed identification request
+#[derive(Debug, Clone, Copy)]
+pub struct SysIdRequest {
+    pub neuron_id: u32,
+    pub signal: Signal,
+    pub duration_ms: u32,
+    pub amplitude: f32,
+    pub f0_hz: f32,
+    pub f1_hz: f32,
+}
+
+impl SysIdRequest {
+    /// Parse a `{"sysid":{...}}` request line
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.starts_with("{\"sysid\"") {
+            return None;
+        }
+        let mut request = SysIdRequest {
+            neuron_id: u32::MAX,
+            signal: Signal::Step,
+            duration_ms: 2000,
+            amplitude: 1.0,
+            f0_hz: 0.5,
+            f1_hz: 10.0,
+        };
+        let words = parse::words(message);
+        for i in 0..words.len().saturating_sub(1) {
+            let value = words[i + 1];
+            match words[i] {
+                "n" => request.neuron_id = value.parse().ok()?,
+                "sig" => {
+                    request.signal = match value {
+                        "step" => Signal::Step,
+                        "ramp" => Signal::Ramp,
+                        "chirp" => Signal::Chirp,
+                        _ => return None,
+                    }
+                }
+                "ms" => request.duration_ms = value.parse().ok()?,
+                "amp" => request.amplitude = value.parse::<f32>().ok()?.clamp(0.0, 1.0),
+                "f0" => request.f0_hz = value.parse().ok()?,
+                "f1" => request.f1_hz = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        if request.neuron_id == u32::MAX || request.duration_ms == 0 {
+            return None;
+        }
+        Some(request)
+    }
+
+    /// Excitation value at `t_s` seconds into the run (0.0-1.0)
+    pub fn excitation(&self, t_s: f32) -> f32 {
+        let duration_s = self.duration_ms as f32 / 1000.0;
+        let value = match self.signal {
+            Signal::Step => {
+                if t_s >= duration_s * 0.1 { self.amplitude } else { 0.0 }
+            }
+            Signal::Ramp => self.amplitude * (t_s / duration_s),
+            Signal::Chirp => {
+                // Instantaneous phase of a linear sweep: 2π(f0·t + (f1-f0)·t²/2T)
+                let k = (self.f1_hz - self.f0_hz) / duration_s;
+                let phase = TWO_PI * (self.f0_hz * t_s + 0.5 * k * t_s * t_s);
+                0.5 * self.amplitude * (1.0 + unsafe { sinf(phase) })
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// Run an identification experiment (blocks the burst loop for its duration)
+///
+/// `drive` applies the excitation to the output, `sample` fills in the
+/// response channels, and `emit` sends each line over the transport. False
+/// if the sample timer couldn't be started.
+pub fn run<D, S, E>(request: &SysIdRequest, rate_hz: u32, mut drive: D, mut sample: S, mut emit: E) -> bool
+where
+    D: FnMut(f32),
+    S: FnMut(&mut Vec<f32, SYSID_RESPONSES>),
+    E: FnMut(&[u8]),
+{
+    let Some(timer) = BurstTimer::start(1_000_000 / rate_hz.max(1) as u64) else {
+        return false;
+    };
+    let duration_us = request.duration_ms as i64 * 1000;
+    let start = unsafe { sys::esp_timer_get_time() };
+    let mut responses: Vec<f32, SYSID_RESPONSES> = Vec::new();
+    let mut line: String<SYSID_LINE_CAPACITY> = String::new();
+    let mut num: String<16> = String::new();
+
+    emit(b"{\"sy_start\":1}\n");
+    loop {
+        let now = unsafe { sys::esp_timer_get_time() };
+        let t_us = now - start;
+        if t_us > duration_us {
+            break;
+        }
+        let u = request.excitation(t_us as f32 / 1_000_000.0);
+        drive(u);
+        responses.clear();
+        sample(&mut responses);
+
+        line.clear();
+        let _ = line.push_str("{\"sy\":[");
+        u32_to_string(t_us as u32, &mut num);
+        let _ = line.push_str(&num);
+        for value in core::iter::once(&u).chain(responses.iter()) {
+            let _ = line.push(',');
+            unit_f32_to_string(*value, &mut num);
+            let _ = line.push_str(&num);
+        }
+        let _ = line.push_str("]}\n");
+        emit(line.as_bytes());
+
+        // Blocks on the timer's semaphore, so FreeRTOS' tick doesn't limit
+        // the rate
+        timer.wait();
+    }
+    drive(0.0);
+    emit(b"{\"sy_end\":1}\n");
+    true
+}
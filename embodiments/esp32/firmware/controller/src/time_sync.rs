@@ -0,0 +1,74 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! SNTP wall-clock time for timestamped sensory frames
+//!
+//! With `time_sync` in config.json, a board on WiFi or Ethernet syncs its
+//! clock over SNTP and stamps every sensory frame with the Unix time of its
+//! sample in milliseconds (`"t"`), so FEAGI can line up the input streams of
+//! several boards. Until the first sync completes the clock is meaningless
+//! and frames go out without `"t"`.
+//!
+//! lwIP's SNTP client runs in the TCP/IP task and re-syncs every
+//! `sync_interval_ms`; the burst loop only reads the system clock.
+
+use core::ffi::c_char;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use esp_idf_svc::sys;
+
+/// SNTP servers and re-sync interval (from config.json `time_sync`)
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSyncConfig {
+    /// Host names or IPv4 addresses, NUL-terminated (at most 3)
+    pub servers: &'static [&'static str],
+    pub sync_interval_ms: u32,
+}
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// Set by lwIP once the clock has been set from a server
+static SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// Start the SNTP client once the network interface is up
+///
+/// Later calls (e.g. after switching from BLE to WiFi) are no-ops.
+pub fn start(config: &TimeSyncConfig) {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    unsafe {
+        sys::esp_sntp_setoperatingmode(sys::esp_sntp_operatingmode_t_ESP_SNTP_OPMODE_POLL);
+        for (index, server) in config.servers.iter().enumerate() {
+            // lwIP keeps the pointer: the names are 'static
+            sys::esp_sntp_setservername(index as u8, server.as_ptr() as *const c_char);
+        }
+        sys::sntp_set_sync_interval(config.sync_interval_ms);
+        sys::sntp_set_time_sync_notification_cb(Some(on_sync));
+        sys::esp_sntp_init();
+    }
+}
+
+/// The clock has been set from an SNTP server at least once
+pub fn is_synced() -> bool {
+    SYNCED.load(Ordering::Acquire)
+}
+
+/// Unix time in milliseconds, once synced
+pub fn now_unix_ms() -> Option<u64> {
+    if !is_synced() {
+        return None;
+    }
+    let mut tv: sys::timeval = unsafe { core::mem::zeroed() };
+    if unsafe { sys::gettimeofday(&mut tv, core::ptr::null_mut()) } != 0 {
+        return None;
+    }
+    Some(tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000)
+}
+
+unsafe extern "C" fn on_sync(_tv: *mut sys::timeval) {
+    SYNCED.store(true, Ordering::Release);
+}
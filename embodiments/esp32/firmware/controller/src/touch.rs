@@ -0,0 +1,186 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Capacitive touch inputs on the ESP32 touch-pad peripheral
+//!
+//! A `touch_input` pin is read through the touch sensor's IIR filter every
+//! burst. The reading drops when a finger (or anything conductive) comes
+//! close; it is reported either as a touch state or as a touch level:
+//!
+//! | `report` | Value                                                        |
+//! |----------|--------------------------------------------------------------|
+//! | `state`  | 1.0 while the reading is below the threshold, else 0.0       |
+//! | `level`  | 0.0 at the untouched reading, rising to 1.0 at the threshold |
+//!
+//! The untouched reading is sampled at boot, so pads must not be touched
+//! while the board starts. Without a configured threshold, 2/3 of it is used.
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::{info, warn};
+
+/// How a touch pad is reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchReport {
+    State,
+    Level,
+}
+
+/// Touch settings of one pin (from config.json `touch` block)
+#[derive(Debug, Clone, Copy)]
+pub struct TouchConfig {
+    /// Filtered reading below which the pad counts as touched; None = 2/3
+    /// of the untouched reading at boot
+    pub threshold: Option<u16>,
+    pub report: TouchReport,
+}
+
+/// GPIO of touch pads T0-T9
+pub const TOUCH_PADS: [u32; 10] = [4, 0, 2, 15, 13, 12, 14, 27, 33, 32];
+
+/// IIR filter period of the touch sensor
+const FILTER_PERIOD_MS: u32 = 10;
+
+struct TouchChannel {
+    pin: u32,
+    /// Touch pad number (T0-T9)
+    pad: u32,
+    neuron_id: u32,
+    report: TouchReport,
+    /// Filtered reading at boot
+    baseline: u16,
+    threshold: u16,
+}
+
+/// All configured touch inputs (capacity `N` from the build config)
+pub struct TouchBank<const N: usize> {
+    channels: Vec<TouchChannel, N>,
+}
+
+impl<const N: usize> TouchBank<N> {
+    /// Set up the touch sensor for the `touch_input` pins in GPIO_CONFIG and
+    /// sample their untouched readings
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut channels = Vec::new();
+        if !config.iter().any(|g| g.touch.is_some()) || !start_sensor() {
+            return Self { channels };
+        }
+        for gpio_config in config {
+            let Some(touch) = gpio_config.touch else {
+                continue;
+            };
+            let Some(pad) = TOUCH_PADS.iter().position(|&p| p == gpio_config.pin) else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            if !configure_pad(pad as u32) {
+                continue;
+            }
+            let _ = channels.push(TouchChannel {
+                pin: gpio_config.pin,
+                pad: pad as u32,
+                neuron_id,
+                report: touch.report,
+                baseline: 0,
+                threshold: touch.threshold.unwrap_or(0),
+            });
+        }
+        start_filter();
+        // Let the filter settle before taking the untouched readings
+        FreeRtos::delay_ms(FILTER_PERIOD_MS * 10);
+        for channel in channels.iter_mut() {
+            channel.baseline = read_filtered(channel.pad).unwrap_or(0);
+            if channel.threshold == 0 {
+                channel.threshold = channel.baseline / 3 * 2;
+            }
+            info!("GPIO {}: touch baseline {}, threshold {}", channel.pin, channel.baseline, channel.threshold);
+        }
+        Self { channels }
+    }
+
+    /// Read every pad, returning (pin, neuron_id, value, touched)
+    pub fn read_all(&mut self) -> Vec<(u32, u32, f32, bool), N> {
+        let mut readings = Vec::new();
+        for channel in self.channels.iter() {
+            let Some(reading) = read_filtered(channel.pad) else {
+                continue;
+            };
+            let touched = reading < channel.threshold;
+            let value = match channel.report {
+                TouchReport::Level if channel.baseline > channel.threshold => {
+                    let span = (channel.baseline - channel.threshold) as f32;
+                    (channel.baseline.saturating_sub(reading) as f32 / span).clamp(0.0, 1.0)
+                }
+                _ => if touched { 1.0 } else { 0.0 },
+            };
+            let _ = readings.push((channel.pin, channel.neuron_id, value, touched));
+        }
+        readings
+    }
+}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+fn start_sensor() -> bool {
+    unsafe {
+        if sys::touch_pad_init() != sys::ESP_OK {
+            return false;
+        }
+        sys::touch_pad_set_voltage(
+            sys::touch_high_volt_t_TOUCH_HVOLT_2V7,
+            sys::touch_low_volt_t_TOUCH_LVOLT_0V5,
+            sys::touch_volt_atten_t_TOUCH_HVOLT_ATTEN_1V,
+        );
+    }
+    true
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+fn start_sensor() -> bool {
+    warn!("Touch inputs not supported on this chip");
+    false
+}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+fn configure_pad(pad: u32) -> bool {
+    // The interrupt threshold is unused: pads are polled
+    unsafe { sys::touch_pad_config(pad as sys::touch_pad_t, 0) == sys::ESP_OK }
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+fn configure_pad(_pad: u32) -> bool {
+    false
+}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+fn start_filter() {
+    unsafe {
+        sys::touch_pad_filter_start(FILTER_PERIOD_MS);
+    }
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+fn start_filter() {}
+
+#[cfg(esp_idf_soc_touch_sensor_supported)]
+fn read_filtered(pad: u32) -> Option<u16> {
+    let mut value: u16 = 0;
+    if unsafe { sys::touch_pad_read_filtered(pad as sys::touch_pad_t, &mut value) } != sys::ESP_OK {
+        return None;
+    }
+    Some(value)
+}
+
+#[cfg(not(esp_idf_soc_touch_sensor_supported))]
+fn read_filtered(_pad: u32) -> Option<u16> {
+    None
+}
@@ -0,0 +1,723 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Byte transports the JSON-lines protocol runs over
+//!
+//! The burst loop only talks to [`FeagiTransport`]: frames go out with
+//! `send_frame`, motor and control lines come in with `poll_commands`.
+//! Serial is a UART (UART0 by default), optionally COBS-framed (controller-core/src/cobs.rs); over WiFi (see wifi.rs for the station side) it's either
+//! a TCP stream to FEAGI, UDP datagrams for high burst rates where a lost
+//! frame is better than a late one, a WebSocket to FEAGI's connector
+//! interface (websocket.rs), an MQTT session with a broker (mqtt.rs), or
+//! ZeroMQ PUB/SUB sockets to FEAGI core (zmtp.rs). Without WiFi, the board
+//! can also be a BLE Nordic UART Service peripheral (ble.rs).
+//!
+//! A new transport implements the trait and gets a [`Transport`] variant,
+//! which is what the burst loop owns. Once open, it's normally handed to the
+//! transport task (transport_task.rs), which drives and reopens it.
+
+use core::ffi::{c_char, c_void};
+use core::fmt::Write;
+use core::mem::size_of;
+
+use esp_idf_svc::sys;
+use feagi_controller_core::cobs;
+
+use crate::ble::BleLink;
+use crate::fault;
+use crate::mqtt::MqttClient;
+use crate::transport_task::OffloadedLink;
+use crate::websocket::WebSocket;
+use crate::zmtp::ZmqLink;
+use crate::RX_LINE_CAPACITY;
+
+/// What the burst loop needs from a link to FEAGI
+pub trait FeagiTransport {
+    /// Send one newline-terminated protocol line; false if the transport
+    /// failed
+    fn send_frame(&mut self, line: &[u8]) -> bool;
+
+    /// Read what's arrived, waiting up to `timeout` (ticks for serial, ms
+    /// for network transports and BLE); Ok(0) on timeout, Err if the
+    /// transport failed or closed
+    fn poll_commands(&mut self, buf: &mut [u8], timeout: u32) -> Result<usize, ()>;
+
+    fn status(&self) -> TransportStatus;
+
+    /// Wait until sent data has left the board (best effort)
+    fn flush(&mut self, _timeout: u32) {}
+}
+
+/// Which transport is up and whether a peer can currently hear it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportStatus {
+    /// Transport type as named in config.json, for the hello line
+    pub kind: &'static str,
+    /// False while nothing is listening (a BLE link with no subscribed
+    /// central); frames sent meanwhile are dropped
+    pub connected: bool,
+}
+
+impl TransportStatus {
+    /// A transport that can be heard whenever it's open
+    pub const fn up(kind: &'static str) -> Self {
+        Self { kind, connected: true }
+    }
+}
+
+pub enum Transport {
+    Serial(SerialLink),
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    WebSocket(WebSocket),
+    Mqtt(MqttClient),
+    Zmq(ZmqLink),
+    Ble(BleLink),
+    /// Any of the above, driven by the transport task
+    Offloaded(OffloadedLink),
+}
+
+impl Transport {
+    fn link(&self) -> &dyn FeagiTransport {
+        match self {
+            Transport::Serial(serial) => serial,
+            Transport::Tcp(stream) => stream,
+            Transport::Udp(socket) => socket,
+            Transport::WebSocket(socket) => socket,
+            Transport::Mqtt(client) => client,
+            Transport::Zmq(link) => link,
+            Transport::Ble(link) => link,
+            Transport::Offloaded(link) => link,
+        }
+    }
+
+    fn link_mut(&mut self) -> &mut dyn FeagiTransport {
+        match self {
+            Transport::Serial(serial) => serial,
+            Transport::Tcp(stream) => stream,
+            Transport::Udp(socket) => socket,
+            Transport::WebSocket(socket) => socket,
+            Transport::Mqtt(client) => client,
+            Transport::Zmq(link) => link,
+            Transport::Ble(link) => link,
+            Transport::Offloaded(link) => link,
+        }
+    }
+}
+
+impl FeagiTransport for Transport {
+    fn send_frame(&mut self, line: &[u8]) -> bool {
+        self.link_mut().send_frame(line)
+    }
+
+    fn poll_commands(&mut self, buf: &mut [u8], timeout: u32) -> Result<usize, ()> {
+        self.link_mut().poll_commands(buf, timeout)
+    }
+
+    fn status(&self) -> TransportStatus {
+        self.link().status()
+    }
+
+    fn flush(&mut self, timeout: u32) {
+        self.link_mut().flush(timeout)
+    }
+}
+
+/// How messages are delimited on the serial link (config.json
+/// `serial.framing`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialFraming {
+    /// As sent: newline-terminated lines, self-delimiting binary packets
+    Raw,
+    /// COBS frames with a CRC16 (see controller-core/src/cobs.rs)
+    Cobs,
+}
+
+impl SerialFraming {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerialFraming::Raw => "raw",
+            SerialFraming::Cobs => "cobs",
+        }
+    }
+}
+
+/// The serial transport's UART (from config.json `serial`)
+#[derive(Debug, Clone, Copy)]
+pub struct SerialConfig {
+    /// UART0 is also the console, UART1 and UART2 are the transport's alone
+    pub port: i32,
+    pub tx_pin: i32,
+    pub rx_pin: i32,
+    pub baud_rate: u32,
+    /// RTS/CTS hardware flow control, each optional
+    pub rts_pin: Option<i32>,
+    pub cts_pin: Option<i32>,
+    /// Driver ring buffer for received bytes
+    pub rx_buffer_bytes: u32,
+}
+
+/// Encoded bytes of one frame, a little over the longest accepted message
+const COBS_FRAME_CAPACITY: usize = RX_LINE_CAPACITY + RX_LINE_CAPACITY / 254 + 4;
+
+/// Driver events queued between reads; only overflows matter, and a full
+/// queue loses data events, not bytes
+const UART_EVENT_QUEUE: i32 = 16;
+
+/// Bytes in the hardware FIFO (of 128) that make the driver move them to the
+/// ring buffer; well short of full, so a late interrupt doesn't overrun it
+const UART_RX_FULL_THRESHOLD: i32 = 64;
+
+/// Transmit ring buffer, so short lines don't wait for the FIFO to drain
+const UART_TX_BUFFER: i32 = 256;
+
+/// Bytes in the hardware FIFO at which RTS tells the host to pause, with
+/// flow control
+const UART_RTS_THRESHOLD: u8 = 100;
+
+/// Longest a send may block (a peer that stopped reading, CTS held) before
+/// it fails; the transport task gets back to its requests well within
+/// transport_task.rs's DETACH_TIMEOUT_MS
+pub const SEND_TIMEOUT_MS: u32 = 300;
+
+/// The configured UART with the driver's receive ring buffer and event
+/// queue, and the configured framing
+///
+/// The driver's interrupt handler empties the hardware FIFO into a ring
+/// buffer of `serial.rx_buffer_bytes`, so a large burst of motor commands
+/// waits there for the next read instead of overrunning the FIFO. A read
+/// blocks on the driver's event queue, so it returns as soon as bytes
+/// arrive rather than after a fixed wait. Once the ring buffer is full,
+/// RTS (when wired) holds the host off; without it the FIFO overflows,
+/// which flushes the input and is reported as an `rx_overflow` fault.
+pub struct SerialLink {
+    port: sys::uart_port_t,
+    events: sys::QueueHandle_t,
+    framing: SerialFraming,
+    decoder: cobs::Decoder<COBS_FRAME_CAPACITY>,
+    /// Bytes read from the UART and not decoded yet
+    raw: [u8; 128],
+    raw_pos: usize,
+    raw_len: usize,
+    /// Last verified message and how much of it was handed out
+    message: heapless::Vec<u8, RX_LINE_CAPACITY>,
+    message_pos: usize,
+}
+
+impl SerialLink {
+    /// Install the UART driver (8N1) as configured; None if it failed
+    pub fn open(config: &SerialConfig, framing: SerialFraming) -> Option<Self> {
+        let port = config.port;
+        let flow_ctrl = match (config.rts_pin, config.cts_pin) {
+            (None, None) => sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_DISABLE,
+            (Some(_), None) => sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_RTS,
+            (None, Some(_)) => sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS,
+            (Some(_), Some(_)) => sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS,
+        };
+        unsafe {
+            let mut uart_config = sys::uart_config_t {
+                baud_rate: config.baud_rate as i32,
+                data_bits: sys::uart_word_length_t_UART_DATA_8_BITS,
+                parity: sys::uart_parity_t_UART_PARITY_DISABLE,
+                stop_bits: sys::uart_stop_bits_t_UART_STOP_BITS_1,
+                flow_ctrl,
+                rx_flow_ctrl_thresh: UART_RTS_THRESHOLD,
+                ..Default::default()
+            };
+            uart_config.__bindgen_anon_1.source_clk = sys::soc_periph_uart_clk_src_legacy_t_UART_SCLK_DEFAULT;
+            let mut events: sys::QueueHandle_t = core::ptr::null_mut();
+            if sys::uart_driver_install(port, config.rx_buffer_bytes as i32, UART_TX_BUFFER, UART_EVENT_QUEUE, &mut events, 0) != sys::ESP_OK {
+                return None;
+            }
+            // UART_PIN_NO_CHANGE for unused RTS/CTS
+            if sys::uart_param_config(port, &uart_config) != sys::ESP_OK
+                || sys::uart_set_pin(port, config.tx_pin, config.rx_pin, config.rts_pin.unwrap_or(-1), config.cts_pin.unwrap_or(-1)) != sys::ESP_OK
+                || sys::uart_set_rx_full_threshold(port, UART_RX_FULL_THRESHOLD) != sys::ESP_OK
+            {
+                sys::uart_driver_delete(port);
+                return None;
+            }
+            Some(Self {
+                port,
+                events,
+                framing,
+                decoder: cobs::Decoder::new(),
+                raw: [0; 128],
+                raw_pos: 0,
+                raw_len: 0,
+                message: heapless::Vec::new(),
+                message_pos: 0,
+            })
+        }
+    }
+}
+
+impl Drop for SerialLink {
+    fn drop(&mut self) {
+        unsafe {
+            sys::uart_driver_delete(self.port);
+        }
+    }
+}
+
+/// Move what the driver buffered into `buf`, first waiting up to `timeout`
+/// ticks for a driver event if nothing is
+fn uart_read(port: sys::uart_port_t, events: sys::QueueHandle_t, buf: &mut [u8], timeout: u32) -> Result<usize, ()> {
+    let mut buffered: usize = 0;
+    unsafe {
+        sys::uart_get_buffered_data_len(port, &mut buffered);
+        let mut event: sys::uart_event_t = core::mem::zeroed();
+        let mut wait = if buffered == 0 { timeout } else { 0 };
+        while sys::xQueueReceive(events, &mut event as *mut sys::uart_event_t as *mut c_void, wait) == 1 {
+            wait = 0;
+            // A full ring buffer only stops the driver emptying the FIFO
+            // (and deasserts RTS); bytes are lost once the FIFO overflows
+            if event.type_ == sys::uart_event_type_t_UART_FIFO_OVF {
+                // What's buffered has a gap in it; a COBS frame across it
+                // fails its CRC, a raw line is cut or merged
+                sys::uart_flush_input(port);
+                sys::xQueueGenericReset(events, 0);
+                fault!(RxOverflow, "UART receive buffer overflowed, input flushed");
+                return Ok(0);
+            }
+        }
+        sys::uart_get_buffered_data_len(port, &mut buffered);
+        if buffered == 0 {
+            return Ok(0);
+        }
+        let read = sys::uart_read_bytes(port, buf.as_mut_ptr() as *mut c_void, buffered.min(buf.len()) as u32, 0);
+        if read < 0 {
+            return Err(());
+        }
+        Ok(read as usize)
+    }
+}
+
+/// Queue `bytes` once the transmit ring buffer has room for them, rather
+/// than block in the driver while CTS holds the line; false if the buffer
+/// stopped draining for SEND_TIMEOUT_MS
+fn uart_write(port: sys::uart_port_t, bytes: &[u8]) -> bool {
+    unsafe {
+        let mut deadline = sys::esp_timer_get_time() + SEND_TIMEOUT_MS as i64 * 1000;
+        let mut last_free = 0;
+        loop {
+            let mut free: usize = 0;
+            if sys::uart_get_tx_buffer_free_size(port, &mut free) != sys::ESP_OK {
+                return false;
+            }
+            if free >= bytes.len() {
+                break;
+            }
+            let now = sys::esp_timer_get_time();
+            // A slow baud rate still drains: only a stall counts
+            if free > last_free {
+                deadline = now + SEND_TIMEOUT_MS as i64 * 1000;
+                last_free = free;
+            } else if now >= deadline {
+                return false;
+            }
+            sys::vTaskDelay(1);
+        }
+        sys::uart_write_bytes(port, bytes.as_ptr() as *const c_void, bytes.len()) == bytes.len() as i32
+    }
+}
+
+impl FeagiTransport for SerialLink {
+    fn send_frame(&mut self, line: &[u8]) -> bool {
+        let port = self.port;
+        let write = |bytes: &[u8]| bytes.chunks(UART_TX_BUFFER as usize / 2).all(|piece| uart_write(port, piece));
+        match self.framing {
+            SerialFraming::Raw => write(line),
+            SerialFraming::Cobs => cobs::encode(line, write),
+        }
+    }
+
+    fn poll_commands(&mut self, buf: &mut [u8], timeout: u32) -> Result<usize, ()> {
+        if self.framing == SerialFraming::Raw {
+            return uart_read(self.port, self.events, buf, timeout);
+        }
+        // Decode until a frame verifies, then hand its message out over as
+        // many calls as `buf` needs
+        if self.message_pos == self.message.len() {
+            self.message.clear();
+            self.message_pos = 0;
+        }
+        let mut timeout = timeout;
+        while self.message.is_empty() {
+            if self.raw_pos == self.raw_len {
+                self.raw_len = uart_read(self.port, self.events, &mut self.raw, timeout)?;
+                self.raw_pos = 0;
+                if self.raw_len == 0 {
+                    return Ok(0);
+                }
+                // Drain what's already buffered without waiting again
+                timeout = 0;
+            }
+            let byte = self.raw[self.raw_pos];
+            self.raw_pos += 1;
+            self.decoder.push(byte, &mut self.message);
+        }
+
+        let rest = &self.message[self.message_pos..];
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.message_pos += n;
+        Ok(n)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("serial")
+    }
+
+    fn flush(&mut self, timeout: u32) {
+        unsafe {
+            sys::uart_wait_tx_done(self.port, timeout);
+        }
+    }
+}
+
+/// TLS for the stream transports (from config.json `transport.config.tls`)
+#[derive(Debug, Clone, Copy)]
+pub struct TlsConfig {
+    /// PEM CA certificate, NUL-terminated; None skips server verification
+    pub ca_cert: Option<&'static [u8]>,
+    /// Name the server certificate must carry, NUL-terminated; None skips
+    /// the name check (FEAGI is addressed by IP)
+    pub server_name: Option<&'static str>,
+}
+
+/// A connected lwIP TCP socket, optionally wrapped in TLS; closed on drop
+pub struct TcpStream {
+    fd: i32,
+    /// SO_RCVTIMEO currently set on the socket
+    rx_timeout_ms: u32,
+    /// esp-tls session owning `fd`, null for plain TCP
+    tls: *mut sys::esp_tls_t,
+}
+
+impl TcpStream {
+    /// Connect to `host:port`, over TLS if `tls` is set (handshake within
+    /// `timeout_ms`)
+    pub fn open(host: [u8; 4], port: u16, tls: Option<&TlsConfig>, timeout_ms: u32) -> Option<Self> {
+        match tls {
+            Some(tls) => Self::connect_tls(host, port, tls, timeout_ms),
+            None => Self::connect(host, port),
+        }
+    }
+
+    /// Connect to `host:port` and complete a TLS handshake
+    pub fn connect_tls(host: [u8; 4], port: u16, config: &TlsConfig, timeout_ms: u32) -> Option<Self> {
+        let mut hostname: heapless::String<16> = heapless::String::new();
+        write!(hostname, "{}.{}.{}.{}", host[0], host[1], host[2], host[3]).ok()?;
+        unsafe {
+            let tls = sys::esp_tls_init();
+            if tls.is_null() {
+                return None;
+            }
+            // Destroys the session if the handshake fails
+            let mut stream = Self { fd: -1, rx_timeout_ms: 0, tls };
+
+            let mut cfg: sys::esp_tls_cfg_t = core::mem::zeroed();
+            if let Some(ca) = config.ca_cert {
+                cfg.__bindgen_anon_1.cacert_buf = ca.as_ptr();
+                cfg.__bindgen_anon_2.cacert_bytes = ca.len() as u32;
+            }
+            match (config.server_name, config.ca_cert) {
+                (Some(name), _) => cfg.common_name = name.as_ptr() as *const c_char,
+                // esp-tls checks the certificate names the host address
+                (None, Some(_)) => {}
+                // "verify": false, nothing to check it against
+                (None, None) => cfg.skip_common_name = true,
+            }
+            cfg.timeout_ms = timeout_ms as i32;
+            if sys::esp_tls_conn_new_sync(hostname.as_ptr() as *const c_char, hostname.len() as i32, port as i32, &cfg, tls) != 1
+                || sys::esp_tls_get_conn_sockfd(tls, &mut stream.fd) != sys::ESP_OK
+            {
+                return None;
+            }
+            set_nodelay(stream.fd);
+            set_send_timeout(stream.fd);
+            Some(stream)
+        }
+    }
+
+    /// Connect to `host:port` (IPv4)
+    pub fn connect(host: [u8; 4], port: u16) -> Option<Self> {
+        unsafe {
+            let fd = sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_STREAM as i32, sys::IPPROTO_TCP as i32);
+            if fd < 0 {
+                return None;
+            }
+            // Closes the socket if connecting fails
+            let stream = Self { fd, rx_timeout_ms: 0, tls: core::ptr::null_mut() };
+
+            let addr = ipv4_addr(host, port);
+            let addr_ptr = &addr as *const sys::sockaddr_in as *const sys::sockaddr;
+            if sys::lwip_connect(fd, addr_ptr, size_of::<sys::sockaddr_in>() as u32) != 0 {
+                return None;
+            }
+            set_nodelay(fd);
+            set_send_timeout(fd);
+            Some(stream)
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> bool {
+        let mut sent = 0;
+        while sent < data.len() {
+            let rest = &data[sent..];
+            let n = unsafe {
+                if self.tls.is_null() {
+                    sys::lwip_send(self.fd, rest.as_ptr() as *const c_void, rest.len(), 0)
+                } else {
+                    sys::esp_tls_conn_write(self.tls, rest.as_ptr() as *const c_void, rest.len())
+                }
+            };
+            if n <= 0 {
+                return false;
+            }
+            sent += n as usize;
+        }
+        true
+    }
+
+    pub fn read(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        set_rx_timeout(self.fd, &mut self.rx_timeout_ms, timeout_ms);
+        if !self.tls.is_null() {
+            let n = unsafe { sys::esp_tls_conn_read(self.tls, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            return match n as i32 {
+                // Orderly shutdown by FEAGI (close_notify or FIN)
+                0 => Err(()),
+                // The socket timed out under mbedTLS
+                sys::MBEDTLS_ERR_SSL_WANT_READ | sys::MBEDTLS_ERR_SSL_WANT_WRITE => Ok(0),
+                n if n > 0 => Ok(n as usize),
+                _ => Err(()),
+            };
+        }
+        let n = unsafe { sys::lwip_recv(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len(), 0) };
+        match n {
+            // Orderly shutdown by FEAGI
+            0 => Err(()),
+            n => received(n),
+        }
+    }
+}
+
+// lwIP sends on its own: nothing useful for `flush` to wait for
+impl FeagiTransport for TcpStream {
+    fn send_frame(&mut self, line: &[u8]) -> bool {
+        self.write(line)
+    }
+
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        self.read(buf, timeout_ms)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("wifi")
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        unsafe {
+            if self.tls.is_null() {
+                sys::lwip_close(self.fd);
+            } else {
+                // Closes the socket too
+                sys::esp_tls_conn_destroy(self.tls);
+            }
+        }
+    }
+}
+
+/// Motor datagrams this far behind the newest one are stale and dropped;
+/// further behind means FEAGI restarted its frame counter
+const REORDER_WINDOW: u64 = 256;
+
+/// A bound lwIP UDP socket exchanging one line per datagram with FEAGI
+pub struct UdpSocket {
+    fd: i32,
+    peer: sys::sockaddr_in,
+    rx_timeout_ms: u32,
+    /// Highest `"f"` frame number received so far
+    last_frame: Option<u64>,
+}
+
+impl UdpSocket {
+    /// Bind `local_port` for motor commands; frames go to `host:port`
+    pub fn bind(local_port: u16, host: [u8; 4], port: u16) -> Option<Self> {
+        unsafe {
+            let fd = sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_DGRAM as i32, sys::IPPROTO_UDP as i32);
+            if fd < 0 {
+                return None;
+            }
+            // Closes the socket if binding fails
+            let socket = Self {
+                fd,
+                peer: ipv4_addr(host, port),
+                rx_timeout_ms: 0,
+                last_frame: None,
+            };
+            let local = ipv4_addr([0, 0, 0, 0], local_port);
+            let local_ptr = &local as *const sys::sockaddr_in as *const sys::sockaddr;
+            if sys::lwip_bind(fd, local_ptr, size_of::<sys::sockaddr_in>() as u32) != 0 {
+                return None;
+            }
+            set_send_timeout(fd);
+            Some(socket)
+        }
+    }
+}
+
+impl FeagiTransport for UdpSocket {
+    fn send_frame(&mut self, data: &[u8]) -> bool {
+        let peer_ptr = &self.peer as *const sys::sockaddr_in as *const sys::sockaddr;
+        let n = unsafe {
+            sys::lwip_sendto(
+                self.fd,
+                data.as_ptr() as *const c_void,
+                data.len(),
+                0,
+                peer_ptr,
+                size_of::<sys::sockaddr_in>() as u32,
+            )
+        };
+        n == data.len() as isize
+    }
+
+    /// Receive one datagram from FEAGI
+    ///
+    /// Datagrams from other addresses, and ones whose `"f"` frame number is
+    /// older than the newest seen, are dropped (read as Ok(0)). Each datagram
+    /// holds one newline-terminated line.
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        set_rx_timeout(self.fd, &mut self.rx_timeout_ms, timeout_ms);
+        let mut from: sys::sockaddr_in = unsafe { core::mem::zeroed() };
+        let mut from_len = size_of::<sys::sockaddr_in>() as u32;
+        let n = unsafe {
+            sys::lwip_recvfrom(
+                self.fd,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                0,
+                &mut from as *mut sys::sockaddr_in as *mut sys::sockaddr,
+                &mut from_len,
+            )
+        };
+        let len = received(n)?;
+        if len == 0 || from.sin_addr.s_addr != self.peer.sin_addr.s_addr {
+            return Ok(0);
+        }
+        if let Some(frame) = frame_number(&buf[..len]) {
+            match self.last_frame {
+                // Several lines may share a frame number, so only older frames are stale
+                Some(last) if frame < last && last - frame < REORDER_WINDOW => return Ok(0),
+                // Newer, or far older: FEAGI restarted its frame counter
+                _ => self.last_frame = Some(frame),
+            }
+        }
+        Ok(len)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("udp")
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        unsafe {
+            sys::lwip_close(self.fd);
+        }
+    }
+}
+
+/// The `"f":N` frame number of a plaintext line, if it has one
+fn frame_number(line: &[u8]) -> Option<u64> {
+    let key = b"\"f\":";
+    let start = line.windows(key.len()).position(|w| w == key)? + key.len();
+    let digits = line[start..].iter().take_while(|b| b.is_ascii_digit()).count();
+    core::str::from_utf8(&line[start..start + digits]).ok()?.parse().ok()
+}
+
+/// Frames are small and latency-bound: don't let Nagle batch them
+fn set_nodelay(fd: i32) {
+    let one: i32 = 1;
+    unsafe {
+        sys::lwip_setsockopt(
+            fd,
+            sys::IPPROTO_TCP as i32,
+            sys::TCP_NODELAY as i32,
+            &one as *const i32 as *const c_void,
+            size_of::<i32>() as u32,
+        );
+    }
+}
+
+/// Fail sends that block past SEND_TIMEOUT_MS (SO_SNDTIMEO); esp-tls
+/// writes go through the same socket
+fn set_send_timeout(fd: i32) {
+    let timeout = sys::timeval {
+        tv_sec: 0,
+        tv_usec: (SEND_TIMEOUT_MS * 1000) as _,
+    };
+    unsafe {
+        sys::lwip_setsockopt(
+            fd,
+            sys::SOL_SOCKET as i32,
+            sys::SO_SNDTIMEO as i32,
+            &timeout as *const sys::timeval as *const c_void,
+            size_of::<sys::timeval>() as u32,
+        );
+    }
+}
+
+pub fn ipv4_addr(host: [u8; 4], port: u16) -> sys::sockaddr_in {
+    let mut addr: sys::sockaddr_in = unsafe { core::mem::zeroed() };
+    addr.sin_len = size_of::<sys::sockaddr_in>() as u8;
+    addr.sin_family = sys::AF_INET as _;
+    addr.sin_port = port.to_be();
+    addr.sin_addr.s_addr = u32::from_ne_bytes(host);
+    addr
+}
+
+/// Set SO_RCVTIMEO when it differs from what the socket has (`current`)
+pub fn set_rx_timeout(fd: i32, current: &mut u32, timeout_ms: u32) {
+    // SO_RCVTIMEO of 0 would block forever
+    let timeout_ms = timeout_ms.max(1);
+    if timeout_ms == *current {
+        return;
+    }
+    let timeout = sys::timeval {
+        tv_sec: (timeout_ms / 1000) as _,
+        tv_usec: ((timeout_ms % 1000) * 1000) as _,
+    };
+    unsafe {
+        sys::lwip_setsockopt(
+            fd,
+            sys::SOL_SOCKET as i32,
+            sys::SO_RCVTIMEO as i32,
+            &timeout as *const sys::timeval as *const c_void,
+            size_of::<sys::timeval>() as u32,
+        );
+    }
+    *current = timeout_ms;
+}
+
+/// Result of a recv call: byte count, Ok(0) on timeout, Err on failure
+pub fn received(n: isize) -> Result<usize, ()> {
+    if n >= 0 {
+        return Ok(n as usize);
+    }
+    let errno = unsafe { *sys::__errno() } as u32;
+    if errno == sys::EAGAIN || errno == sys::EWOULDBLOCK {
+        Ok(0)
+    } else {
+        Err(())
+    }
+}
@@ -0,0 +1,443 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Transport I/O on its own task and core (config.json `tasks`)
+//!
+//! The burst loop (the main task, pinned to the APP core on the dual-core
+//! chips by sdkconfig.defaults.esp32 and .esp32s3) samples inputs and applies motor commands; once a
+//! transport is open it's handed to a task pinned to the PRO core, next to
+//! the WiFi and BT stacks. The two only meet at FreeRTOS queues:
+//!
+//! - Outgoing lines are split into `CHUNK`-byte pieces by the burst loop and
+//!   reassembled and sent by the transport task, so a slow TCP send, TLS
+//!   write or BLE notification never holds up sampling. A line that doesn't
+//!   fit the queue counts as a failed send: the frame is queued
+//!   (controller-core/src/frame_queue.rs) and the supervisor sees the
+//!   error, as for a transport that can't keep up
+//! - Received bytes are queued as they arrive and drained by the burst loop
+//!   without waiting. When the burst loop falls that far behind, they're
+//!   dropped and counted, and reported as an `rx_overflow` fault
+//! - Transports, and the links they're opened over, move between the two
+//!   through a control queue rather than shared statics
+//!
+//! An [`OffloadedLink`] stands in for the transport handed over; dropping it
+//! has the task drop the transport. Sends give up after SEND_TIMEOUT_MS and
+//! reads wait a tick at most (see transport.rs), and the task stops sending
+//! queued lines once a request is waiting, so it gets back to its requests
+//! well within DETACH_TIMEOUT_MS. One that still doesn't let go in time is
+//! wedged in a driver or the network stack, which the board can't recover
+//! from in place: the outputs are forced safe and the board restarts.
+//!
+//! Reopening happens on the task too: the burst loop hands it the WiFi
+//! station, Ethernet port or BLE host ([`Links`]) with the reason
+//! ([`Reopen`]), and keeps driving outputs while the task associates, runs
+//! mDNS discovery and the protocol's handshakes. At a later burst
+//! [`reopened`] gives the links back along with the new transport. On
+//! single-core chips the task runs on the one core, which still keeps
+//! blocking I/O out of the burst loop.
+
+use core::ffi::{c_char, c_void};
+use core::mem::{size_of, ManuallyDrop, MaybeUninit};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::failsafe;
+use crate::transport::{FeagiTransport, Transport, TransportStatus};
+use crate::{error, fault};
+use crate::{Links, Reopen, LINE_CAPACITY, SEALED_LINE_CAPACITY};
+
+/// Transport task settings (from config.json `tasks`)
+#[derive(Debug, Clone, Copy)]
+pub struct TransportTaskConfig {
+    /// Room in each direction's queue
+    pub queue_bytes: u32,
+    pub stack_size: u32,
+}
+
+/// Bytes per queue item
+const CHUNK: usize = 256;
+
+/// Longest line the burst loop sends (stamped, sealed when encrypted)
+const LINE_MAX: usize = if SEALED_LINE_CAPACITY > LINE_CAPACITY { SEALED_LINE_CAPACITY } else { LINE_CAPACITY };
+
+/// The PRO core, where ESP-IDF runs the WiFi and BT tasks
+const TRANSPORT_CORE: i32 = 0;
+
+/// Longest the burst loop waits for the task to drop a transport
+const DETACH_TIMEOUT_MS: u32 = 1000;
+
+/// One queue item: part of an outgoing line, or received bytes
+#[repr(C)]
+struct Chunk {
+    len: u16,
+    /// Last chunk of an outgoing line
+    end: bool,
+    data: [u8; CHUNK],
+}
+
+impl Chunk {
+    const fn new() -> Self {
+        Self { len: 0, end: false, data: [0; CHUNK] }
+    }
+}
+
+/// Burst loop → task, moved through CONTROL (by value: a queue item is a
+/// copy of the bytes)
+#[allow(clippy::large_enum_variant)]
+enum Request {
+    /// Drive a transport the burst loop opened
+    Attach(Transport),
+    /// Drop the transport (its OffloadedLink went away)
+    Detach,
+    /// Open a transport over the links, and hand them back through DONE
+    Reopen(Links, Reopen),
+}
+
+/// Task → burst loop, moved through DONE: the links, why they were
+/// reopened and the kind of transport that came up, if one did
+type Reopened = (Links, Reopen, Option<&'static str>);
+
+// Queues and flags shared with the transport task
+static TX_QUEUE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+static RX_QUEUE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+static CONTROL: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+static DONE: AtomicPtr<sys::QueueDefinition> = AtomicPtr::new(core::ptr::null_mut());
+/// The running task; null if it couldn't be (re)started
+static TASK: AtomicPtr<sys::tskTaskControlBlock> = AtomicPtr::new(core::ptr::null_mut());
+static STACK_SIZE: AtomicU32 = AtomicU32::new(0);
+/// The task holds a transport
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+/// The attached transport failed a send or read
+static FAILED: AtomicBool = AtomicBool::new(false);
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+/// Flush timeout requested by the burst loop, +1 (0 = none pending)
+static FLUSH: AtomicU32 = AtomicU32::new(0);
+
+/// Create the queues and start the transport task; call once at boot
+///
+/// Returns false if it couldn't be started, and the burst loop keeps
+/// driving the transport itself.
+pub fn start(config: &TransportTaskConfig) -> bool {
+    let depth = (config.queue_bytes as usize / CHUNK).max(2) as u32;
+    unsafe {
+        let tx = sys::xQueueGenericCreate(depth, size_of::<Chunk>() as u32, 0);
+        let rx = sys::xQueueGenericCreate(depth, size_of::<Chunk>() as u32, 0);
+        // A Detach can follow a request the task hasn't picked up yet
+        let control = sys::xQueueGenericCreate(2, size_of::<Request>() as u32, 0);
+        let done = sys::xQueueGenericCreate(1, size_of::<Reopened>() as u32, 0);
+        if tx.is_null() || rx.is_null() || control.is_null() || done.is_null() {
+            return false;
+        }
+        TX_QUEUE.store(tx, Ordering::Release);
+        RX_QUEUE.store(rx, Ordering::Release);
+        CONTROL.store(control, Ordering::Release);
+        DONE.store(done, Ordering::Release);
+    }
+    // Room for the line being reassembled, the transport it goes out on and
+    // one being moved in, on top of what the transports need
+    STACK_SIZE.store(config.stack_size + (LINE_MAX + 2 * size_of::<Request>()) as u32, Ordering::Relaxed);
+    spawn()
+}
+
+/// Whether transports are handed to the task
+pub fn running() -> bool {
+    !TASK.load(Ordering::Acquire).is_null()
+}
+
+fn spawn() -> bool {
+    unsafe {
+        let mut info: sys::esp_chip_info_t = core::mem::zeroed();
+        sys::esp_chip_info(&mut info);
+        let core = if info.cores > 1 { TRANSPORT_CORE } else { sys::tskNO_AFFINITY as _ };
+        let mut handle: sys::TaskHandle_t = core::ptr::null_mut();
+        // Same priority as the burst loop
+        let created = sys::xTaskCreatePinnedToCore(
+            Some(run),
+            b"feagi_transport\0".as_ptr() as *const c_char,
+            STACK_SIZE.load(Ordering::Relaxed),
+            core::ptr::null_mut(),
+            1,
+            &mut handle,
+            core,
+        ) == 1;
+        TASK.store(if created { handle } else { core::ptr::null_mut() }, Ordering::Release);
+        created
+    }
+}
+
+/// The task didn't let go of its transport in time, despite the send
+/// timeouts: it's stuck inside a driver or lwIP, holding their locks.
+/// Deleting it there would leave them held, so restart the board with the
+/// outputs safe.
+fn wedged() -> ! {
+    error!("Transport task unresponsive for {} ms, restarting", DETACH_TIMEOUT_MS);
+    failsafe::force_pads();
+    unsafe {
+        sys::esp_restart();
+    }
+}
+
+/// Move `value` into `queue`, waiting up to `ticks` for room; handed back
+/// if there's none
+unsafe fn send<T>(queue: sys::QueueHandle_t, value: T, ticks: u32) -> Result<(), T> {
+    let value = ManuallyDrop::new(value);
+    if sys::xQueueGenericSend(queue, &*value as *const T as *const c_void, ticks, 0) == 1 {
+        Ok(())
+    } else {
+        Err(ManuallyDrop::into_inner(value))
+    }
+}
+
+/// Move the next item out of `queue`, waiting up to `ticks`
+unsafe fn receive<T>(queue: sys::QueueHandle_t, ticks: u32) -> Option<T> {
+    let mut slot = MaybeUninit::<T>::uninit();
+    (sys::xQueueReceive(queue, slot.as_mut_ptr() as *mut c_void, ticks) == 1).then(|| slot.assume_init())
+}
+
+fn ticks(ms: u32) -> u32 {
+    (ms * sys::configTICK_RATE_HZ / 1000).max(1)
+}
+
+/// Prepare the flags and queues for a transport about to be attached
+fn attaching(status: TransportStatus) {
+    unsafe {
+        sys::xQueueGenericReset(TX_QUEUE.load(Ordering::Acquire), 0);
+        sys::xQueueGenericReset(RX_QUEUE.load(Ordering::Acquire), 0);
+    }
+    FAILED.store(false, Ordering::Relaxed);
+    CONNECTED.store(status.connected, Ordering::Relaxed);
+    FLUSH.store(0, Ordering::Relaxed);
+    ATTACHED.store(true, Ordering::Release);
+}
+
+fn link(kind: &'static str) -> Transport {
+    Transport::Offloaded(OffloadedLink { kind, pending: Chunk::new(), pending_pos: 0 })
+}
+
+/// Hand `transport` to the transport task; one already offloaded, or any
+/// while the task isn't running, is returned as is
+pub fn offload(transport: Transport) -> Transport {
+    if matches!(transport, Transport::Offloaded(_)) || !running() {
+        return transport;
+    }
+    let status = transport.status();
+    attaching(status);
+    match unsafe { send(CONTROL.load(Ordering::Acquire), Request::Attach(transport), ticks(DETACH_TIMEOUT_MS)) } {
+        Ok(()) => link(status.kind),
+        Err(Request::Attach(transport)) => {
+            ATTACHED.store(false, Ordering::Release);
+            transport
+        }
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Have the task reopen the transport over `links`; handed back if the
+/// task isn't running, for the burst loop to reopen it itself
+pub fn reopen(links: Links, how: Reopen) -> Result<(), (Links, Reopen)> {
+    if !running() {
+        return Err((links, how));
+    }
+    match unsafe { send(CONTROL.load(Ordering::Acquire), Request::Reopen(links, how), ticks(DETACH_TIMEOUT_MS)) } {
+        Ok(()) => Ok(()),
+        Err(Request::Reopen(links, how)) => Err((links, how)),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// The links back from a finished reopen, why they were reopened and the
+/// transport that came up (driven by the task), without waiting
+pub fn reopened() -> Option<(Links, Reopen, Option<Transport>)> {
+    let (links, how, kind) = unsafe { receive::<Reopened>(DONE.load(Ordering::Acquire), 0) }?;
+    Some((links, how, kind.map(link)))
+}
+
+/// The burst loop's end of a transport handed to the transport task
+pub struct OffloadedLink {
+    kind: &'static str,
+    /// Last received chunk, handed out from `pending_pos`
+    pending: Chunk,
+    pending_pos: usize,
+}
+
+impl FeagiTransport for OffloadedLink {
+    /// Queue one line for the transport task; false if the transport failed
+    /// or the queue has no room for the whole line
+    fn send_frame(&mut self, line: &[u8]) -> bool {
+        if FAILED.load(Ordering::Acquire) {
+            return false;
+        }
+        let queue = TX_QUEUE.load(Ordering::Acquire);
+        let chunks = line.len().div_ceil(CHUNK).max(1);
+        // The burst loop is the only producer: the room can only grow
+        if line.len() > LINE_MAX || (unsafe { sys::uxQueueSpacesAvailable(queue) } as usize) < chunks {
+            return false;
+        }
+        let mut chunk = Chunk::new();
+        for (i, part) in line.chunks(CHUNK).enumerate() {
+            chunk.len = part.len() as u16;
+            chunk.end = i + 1 == chunks;
+            chunk.data[..part.len()].copy_from_slice(part);
+            unsafe {
+                sys::xQueueGenericSend(queue, &chunk as *const Chunk as *const c_void, 0, 0);
+            }
+        }
+        true
+    }
+
+    /// Hand out what the transport task received, without waiting
+    fn poll_commands(&mut self, buf: &mut [u8], _timeout: u32) -> Result<usize, ()> {
+        let queue = RX_QUEUE.load(Ordering::Acquire);
+        let mut n = 0;
+        while n < buf.len() {
+            if self.pending_pos == self.pending.len as usize {
+                let received = unsafe { sys::xQueueReceive(queue, &mut self.pending as *mut Chunk as *mut c_void, 0) } == 1;
+                self.pending_pos = 0;
+                if !received {
+                    self.pending.len = 0;
+                    break;
+                }
+            }
+            let rest = &self.pending.data[self.pending_pos..self.pending.len as usize];
+            let take = rest.len().min(buf.len() - n);
+            buf[n..n + take].copy_from_slice(&rest[..take]);
+            self.pending_pos += take;
+            n += take;
+        }
+        // Bytes received before a failure are still handed out
+        if n == 0 && FAILED.load(Ordering::Acquire) {
+            return Err(());
+        }
+        Ok(n)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus { kind: self.kind, connected: CONNECTED.load(Ordering::Relaxed) }
+    }
+
+    /// Wait until the queued lines went out and the transport flushed them
+    fn flush(&mut self, timeout: u32) {
+        FLUSH.store(timeout + 1, Ordering::Release);
+        let deadline = unsafe { sys::esp_timer_get_time() } + 2 * timeout as i64 * 1000;
+        while FLUSH.load(Ordering::Acquire) != 0 && !FAILED.load(Ordering::Acquire) && unsafe { sys::esp_timer_get_time() } < deadline {
+            unsafe {
+                sys::vTaskDelay(1);
+            }
+        }
+    }
+}
+
+impl Drop for OffloadedLink {
+    /// Have the task drop the transport (closing its socket or driver), so
+    /// it can be reopened; restart the board if it doesn't in time
+    fn drop(&mut self) {
+        let deadline = unsafe { sys::esp_timer_get_time() } + DETACH_TIMEOUT_MS as i64 * 1000;
+        let requested = unsafe { send(CONTROL.load(Ordering::Acquire), Request::Detach, ticks(DETACH_TIMEOUT_MS)) }.is_ok();
+        while ATTACHED.load(Ordering::Acquire) {
+            if !requested || unsafe { sys::esp_timer_get_time() } >= deadline {
+                wedged();
+            }
+            unsafe {
+                sys::vTaskDelay(1);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn run(_arg: *mut c_void) {
+    let tx = TX_QUEUE.load(Ordering::Acquire);
+    let rx = RX_QUEUE.load(Ordering::Acquire);
+    let control = CONTROL.load(Ordering::Acquire);
+    let done = DONE.load(Ordering::Acquire);
+    let mut transport: Option<Transport> = None;
+    // Outgoing line being reassembled
+    let mut line: Vec<u8, LINE_MAX> = Vec::new();
+    let mut chunk = Chunk::new();
+    // Received bytes dropped since the last report
+    let mut dropped: u32 = 0;
+    loop {
+        // Requests first, waiting for one while there's nothing to drive
+        let wait = if transport.is_some() { 0 } else { 1 };
+        if let Some(request) = receive::<Request>(control, wait) {
+            line.clear();
+            report_dropped(&mut dropped);
+            match request {
+                Request::Attach(attached) => transport = Some(attached),
+                Request::Detach => {
+                    transport = None;
+                    ATTACHED.store(false, Ordering::Release);
+                }
+                Request::Reopen(mut links, how) => {
+                    // Slow: association, DHCP, mDNS discovery, handshakes
+                    transport = links.reopen(&how);
+                    let status = transport.as_ref().map(|t| t.status());
+                    if let Some(status) = status {
+                        attaching(status);
+                    }
+                    // The burst loop took the last result before asking again
+                    let _ = send::<Reopened>(done, (links, how, status.map(|s| s.kind)), 0);
+                }
+            }
+            continue;
+        }
+        let Some(ref mut transport) = transport else {
+            continue;
+        };
+        if FAILED.load(Ordering::Acquire) {
+            // Wait for the burst loop to notice and restart it
+            sys::vTaskDelay(1);
+            continue;
+        }
+
+        // Send what's queued, a line at a time: stop at a failure, or to
+        // take a request (a Detach shouldn't wait behind a backlog)
+        while sys::xQueueReceive(tx, &mut chunk as *mut Chunk as *mut c_void, 0) == 1 {
+            let _ = line.extend_from_slice(&chunk.data[..chunk.len as usize]);
+            if chunk.end {
+                if !transport.send_frame(&line) {
+                    FAILED.store(true, Ordering::Release);
+                }
+                line.clear();
+                if FAILED.load(Ordering::Acquire) || sys::uxQueueMessagesWaiting(control) > 0 {
+                    break;
+                }
+            }
+        }
+        let flush = FLUSH.load(Ordering::Acquire);
+        if flush != 0 {
+            transport.flush(flush - 1);
+            FLUSH.store(0, Ordering::Release);
+        }
+
+        // Then read for a tick; received bytes are queued for the burst loop
+        match transport.poll_commands(&mut chunk.data, 1) {
+            Ok(0) => {}
+            Ok(n) => {
+                chunk.len = n as u16;
+                // Like a full UART buffer: bytes the burst loop can't keep up
+                // with are lost, and a malformed line is dropped upstream
+                if sys::xQueueGenericSend(rx, &chunk as *const Chunk as *const c_void, 0, 0) == 1 {
+                    report_dropped(&mut dropped);
+                } else {
+                    dropped = dropped.saturating_add(n as u32);
+                }
+            }
+            Err(()) => FAILED.store(true, Ordering::Release),
+        }
+        CONNECTED.store(transport.status().connected, Ordering::Relaxed);
+    }
+}
+
+/// Report received bytes dropped for a full queue, once they stop being
+fn report_dropped(dropped: &mut u32) {
+    if *dropped > 0 {
+        fault!(RxOverflow, "burst loop fell behind, {} received bytes dropped", *dropped);
+        *dropped = 0;
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! HC-SR04 ultrasonic distance sensors
+//!
+//! An `ultrasonic_input` pin drives the sensor's TRIG input;
+//! `ultrasonic.echo_pin` reads its ECHO output. The echo pulse is timed by
+//! a GPIO interrupt, so the burst loop never waits for it: each burst picks
+//! up the echo of the previous ping (if it has come back) and sends the next
+//! one. Between echoes the last distance is repeated.
+//!
+//! The distance is reported as 0.0 (touching) to 1.0 (`max_range_cm` or
+//! beyond); an echo that doesn't come back within `timeout_us` counts as
+//! nothing in range.
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::cortical;
+use crate::GpioPinConfig;
+use crate::fault;
+
+/// Echo pin and range of one sensor (from config.json `ultrasonic` block)
+#[derive(Debug, Clone, Copy)]
+pub struct UltrasonicConfig {
+    pub echo_pin: u32,
+    /// Distance reported as 1.0
+    pub max_range_cm: u32,
+    /// Wait for an echo before reporting out of range and pinging again
+    pub timeout_us: u32,
+}
+
+/// Sensors served by the echo interrupt
+pub const MAX_ULTRASONIC: usize = 8;
+
+/// Echo round trip per centimeter of distance (speed of sound at ~20 °C)
+const US_PER_CM: f32 = 58.0;
+
+/// Echo width not yet measured
+const NO_ECHO: u32 = u32::MAX;
+
+/// Echo timing shared with the interrupt handler (timestamps are the low
+/// 32 bits of esp_timer, which wrap harmlessly for pulse widths)
+struct Echo {
+    pin: AtomicU32,
+    rise_us: AtomicU32,
+    width_us: AtomicU32,
+}
+
+// Only used as the array initializer below
+#[allow(clippy::declare_interior_mutable_const)]
+const IDLE_ECHO: Echo = Echo {
+    pin: AtomicU32::new(0),
+    rise_us: AtomicU32::new(0),
+    width_us: AtomicU32::new(NO_ECHO),
+};
+
+static ECHOES: [Echo; MAX_ULTRASONIC] = [IDLE_ECHO; MAX_ULTRASONIC];
+
+struct Sensor {
+    /// ECHOES slot
+    index: usize,
+    trigger_pin: u32,
+    neuron_id: u32,
+    config: UltrasonicConfig,
+    /// When the outstanding ping was sent
+    pinged_us: Option<u32>,
+    distance: f32,
+}
+
+/// All configured ultrasonic sensors
+pub struct UltrasonicBank {
+    sensors: Vec<Sensor, MAX_ULTRASONIC>,
+}
+
+impl UltrasonicBank {
+    /// Set up the trigger and echo pins of each `ultrasonic_input` pin in
+    /// GPIO_CONFIG
+    pub fn from_config(config: &[GpioPinConfig]) -> Self {
+        let mut sensors = Vec::new();
+        for gpio_config in config {
+            let Some(ultrasonic) = gpio_config.ultrasonic else {
+                continue;
+            };
+            let Some(neuron_id) = cortical::neuron_id(gpio_config.cortical_mapping) else {
+                continue;
+            };
+            let index = sensors.len();
+            if index == MAX_ULTRASONIC {
+                break;
+            }
+            if !attach(index, gpio_config.pin, ultrasonic.echo_pin) {
+                fault!(PinInit, "Failed to set up ultrasonic sensor on GPIO {}", gpio_config.pin);
+                continue;
+            }
+            let _ = sensors.push(Sensor {
+                index,
+                trigger_pin: gpio_config.pin,
+                neuron_id,
+                config: ultrasonic,
+                pinged_us: None,
+                distance: 1.0,
+            });
+        }
+        Self { sensors }
+    }
+
+    /// Collect finished echoes and ping again, returning (neuron_id, distance)
+    pub fn read_all(&mut self) -> Vec<(u32, f32), MAX_ULTRASONIC> {
+        let mut readings = Vec::new();
+        let now_us = unsafe { sys::esp_timer_get_time() } as u32;
+        for sensor in self.sensors.iter_mut() {
+            let width_us = ECHOES[sensor.index].width_us.swap(NO_ECHO, Ordering::Acquire);
+            if let Some(pinged_us) = sensor.pinged_us {
+                if width_us != NO_ECHO {
+                    let cm = width_us as f32 / US_PER_CM;
+                    sensor.distance = (cm / sensor.config.max_range_cm as f32).min(1.0);
+                    sensor.pinged_us = None;
+                } else if now_us.wrapping_sub(pinged_us) > sensor.config.timeout_us {
+                    sensor.distance = 1.0;
+                    sensor.pinged_us = None;
+                }
+            }
+            if sensor.pinged_us.is_none() {
+                ping(sensor.trigger_pin);
+                sensor.pinged_us = Some(now_us);
+            }
+            let _ = readings.push((sensor.neuron_id, sensor.distance));
+        }
+        readings
+    }
+}
+
+/// Configure TRIG as an output and time ECHO on both edges into `ECHOES[index]`
+fn attach(index: usize, trigger_pin: u32, echo_pin: u32) -> bool {
+    ECHOES[index].pin.store(echo_pin, Ordering::Relaxed);
+    unsafe {
+        let trigger = trigger_pin as i32;
+        let echo = echo_pin as i32;
+        if sys::gpio_reset_pin(trigger) != sys::ESP_OK
+            || sys::gpio_set_direction(trigger, sys::gpio_mode_t_GPIO_MODE_OUTPUT) != sys::ESP_OK
+            || sys::gpio_set_level(trigger, 0) != sys::ESP_OK
+        {
+            return false;
+        }
+        if sys::gpio_reset_pin(echo) != sys::ESP_OK
+            || sys::gpio_set_direction(echo, sys::gpio_mode_t_GPIO_MODE_INPUT) != sys::ESP_OK
+            || sys::gpio_set_intr_type(echo, sys::gpio_int_type_t_GPIO_INTR_ANYEDGE) != sys::ESP_OK
+        {
+            return false;
+        }
+        // Shared with other drivers (e.g. the W5500), so it may already be installed
+        let ret = sys::gpio_install_isr_service(0);
+        if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+            return false;
+        }
+        sys::gpio_isr_handler_add(echo, Some(on_echo_edge), index as *mut c_void) == sys::ESP_OK
+            && sys::gpio_intr_enable(echo) == sys::ESP_OK
+    }
+}
+
+/// 10 µs TRIG pulse
+fn ping(trigger_pin: u32) {
+    unsafe {
+        sys::gpio_set_level(trigger_pin as i32, 1);
+        sys::esp_rom_delay_us(10);
+        sys::gpio_set_level(trigger_pin as i32, 0);
+    }
+}
+
+/// ECHO edge: remember the rising edge, publish the width on the falling one
+unsafe extern "C" fn on_echo_edge(arg: *mut c_void) {
+    let echo = &ECHOES[arg as usize];
+    let now_us = sys::esp_timer_get_time() as u32;
+    if sys::gpio_get_level(echo.pin.load(Ordering::Relaxed) as i32) != 0 {
+        echo.rise_us.store(now_us, Ordering::Relaxed);
+    } else {
+        let width_us = now_us.wrapping_sub(echo.rise_us.load(Ordering::Relaxed));
+        echo.width_us.store(width_us, Ordering::Release);
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Protocol version negotiation
+//!
+//! The hello lists the protocol versions the board speaks as
+//! `"proto":[1,3]`, and the host answers with the one it speaks,
+//! `{"proto":3}`:
+//!
+//! - A version in that range is used: `{"proto_ack":3}`
+//! - A newer one is answered with the board's newest, `{"proto_ack":3}`,
+//!   for the host to fall back to (or hang up)
+//! - An older one (or not a number) is refused with
+//!   `{"proto_err":{"got":0,"min":1,"max":3}}`. Until the host offers a
+//!   version the board speaks, no frames are sent and motor commands are
+//!   ignored, instead of being misread
+//!
+//! | Version | Adds                                                          |
+//! |---------|---------------------------------------------------------------|
+//! | 1       | JSON lines, potentials thresholded to 0 or 1                  |
+//! | 2       | The binary packets of `"wire_format": "binary"` (protocol.rs) |
+//! | 3       | Potentials at full 0.0-1.0 resolution                         |
+//!
+//! A version 1 host gets JSON frames even from a board built for binary.
+//! A host that never sends `{"proto":...}` gets the newest version. Every
+//! hello starts over.
+
+use feagi_controller_core::parse;
+use heapless::String;
+
+use crate::u32_to_string;
+
+/// Newest protocol version the board speaks
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest protocol version the board still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Parse a `{"proto":N}` offer; 0 if N isn't a number
+pub fn parse_offer(message: &str) -> Option<u32> {
+    if !message.starts_with("{\"proto\"") {
+        return None;
+    }
+    Some(parse::words(message).get(1).and_then(|w| w.parse().ok()).unwrap_or(0))
+}
+
+/// The version to speak with a host offering `offered`; None if it's older
+/// than the board speaks
+pub fn negotiate(offered: u32) -> Option<u32> {
+    (offered >= MIN_PROTOCOL_VERSION).then_some(offered.min(PROTOCOL_VERSION))
+}
+
+/// `{"proto_ack":N}` line confirming the version in use
+pub fn ack_line(version: u32) -> String<32> {
+    let mut line: String<32> = String::new();
+    let _ = line.push_str("{\"proto_ack\":");
+    let mut num: String<16> = String::new();
+    u32_to_string(version, &mut num);
+    let _ = line.push_str(&num);
+    let _ = line.push_str("}\n");
+    line
+}
+
+/// `{"proto_err":{...}}` line refusing the version `offered`
+pub fn error_line(offered: u32) -> String<64> {
+    let mut line: String<64> = String::new();
+    let mut num: String<16> = String::new();
+    for (prefix, n) in [("{\"proto_err\":{\"got\":", offered), (",\"min\":", MIN_PROTOCOL_VERSION), (",\"max\":", PROTOCOL_VERSION)] {
+        let _ = line.push_str(prefix);
+        u32_to_string(n, &mut num);
+        let _ = line.push_str(&num);
+    }
+    let _ = line.push_str("}}\n");
+    line
+}
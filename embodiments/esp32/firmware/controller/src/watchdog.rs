@@ -0,0 +1,93 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Task watchdog on the burst loop (config.json `watchdog`)
+//!
+//! The burst loop subscribes to ESP-IDF's task watchdog and feeds it once per
+//! burst. If it stalls for `timeout_ms` (a transport call that never
+//! returns, a driver deadlock), the watchdog interrupt halts the steppers and
+//! forces every output pad to its safe level, detached from LEDC, before the
+//! watchdog resets the board (see failsafe.rs):
+//!
+//! - Digital outputs with a `safe_value` go to its level, other outputs to
+//!   their boot state: low, or floating with `"boot_state": "float"`; pins
+//!   that `hold` keep their level
+//! - H-bridge direction pins and stepper DIR pins go low, stepper drivers
+//!   with an enable pin are disabled
+//!
+//! The hello after the reset carries `"reset":"watchdog"`.
+//!
+//! The burst loop blocks while the supervisor reconnects the transport (WiFi
+//! association, mDNS discovery, the protocol handshake), so the timeout is
+//! raised to cover that plus a second.
+
+use core::ffi::c_char;
+
+use esp_idf_svc::sys;
+
+use crate::failsafe;
+use crate::{ETHERNET_CONFIG, WIFI_CONFIG};
+
+/// Watchdog settings (from config.json `watchdog`)
+#[derive(Debug, Clone, Copy)]
+pub struct WatchdogConfig {
+    /// Reset when the burst loop doesn't come around for this long
+    pub timeout_ms: u32,
+}
+
+/// Subscribe the calling task (the burst loop) to the task watchdog
+///
+/// Returns the timeout in effect, or None if the watchdog couldn't be set up.
+pub fn arm(config: &WatchdogConfig) -> Option<u32> {
+    let timeout_ms = config.timeout_ms.max(longest_block_ms() + 1000);
+    let twdt = sys::esp_task_wdt_config_t {
+        timeout_ms,
+        idle_core_mask: 0,
+        trigger_panic: true,
+    };
+    unsafe {
+        // ESP-IDF starts it at boot (watching the idle tasks) unless
+        // sdkconfig says otherwise
+        let mut ret = sys::esp_task_wdt_init(&twdt);
+        if ret == sys::ESP_ERR_INVALID_STATE as i32 {
+            ret = sys::esp_task_wdt_reconfigure(&twdt);
+        }
+        (ret == sys::ESP_OK && sys::esp_task_wdt_add(core::ptr::null_mut()) == sys::ESP_OK).then_some(timeout_ms)
+    }
+}
+
+/// Tell the watchdog the burst loop came around
+pub fn feed() {
+    unsafe {
+        sys::esp_task_wdt_reset();
+    }
+}
+
+/// Did the task (or interrupt) watchdog reset the board last?
+pub fn caused_last_reset() -> bool {
+    matches!(
+        unsafe { sys::esp_reset_reason() },
+        sys::esp_reset_reason_t_ESP_RST_TASK_WDT | sys::esp_reset_reason_t_ESP_RST_INT_WDT | sys::esp_reset_reason_t_ESP_RST_WDT
+    )
+}
+
+/// Longest the burst loop may block reconnecting the transport
+fn longest_block_ms() -> u32 {
+    let network = WIFI_CONFIG.or(ETHERNET_CONFIG.map(|e| e.network));
+    // Association (or link) and connect time out separately
+    network.map_or(0, |n| 2 * n.connect_timeout_ms + n.discovery_timeout_ms)
+}
+
+/// Called by ESP-IDF from the watchdog interrupt, right before the panic
+/// that resets the board
+#[no_mangle]
+pub extern "C" fn esp_task_wdt_isr_user_handler() {
+    failsafe::force_pads();
+    unsafe {
+        sys::esp_rom_printf(b"[FEAGI] Burst loop stalled, outputs safe-stopped before the watchdog reset\r\n\0".as_ptr() as *const c_char);
+    }
+}
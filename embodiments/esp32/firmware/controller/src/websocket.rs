@@ -0,0 +1,353 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WebSocket client transport (RFC 6455)
+//!
+//! FEAGI's connector interface speaks WebSocket, so with
+//! `"transport": {"type": "websocket", ...}` the board talks to it directly
+//! instead of through a host-side bridge. Every JSON line goes out as one
+//! masked text message (without its newline); incoming text or binary
+//! messages are handed to the burst loop as newline-terminated lines, so the
+//! protocol on top is the same as over serial or TCP.
+//!
+//! Pings go out every `ping_interval_ms`; if nothing at all (not even a pong)
+//! arrives for [`MISSED_PINGS`] intervals the connection counts as dead and
+//! `read` fails, which lets the transport supervisor reconnect.
+
+use core::ffi::c_void;
+use core::fmt::Write;
+
+use esp_idf_svc::sys;
+use feagi_controller_core::base64;
+use heapless::{String, Vec};
+
+use crate::transport::{FeagiTransport, TcpStream, TlsConfig, TransportStatus};
+use crate::RX_LINE_CAPACITY;
+
+/// Appended to Sec-WebSocket-Key before hashing (RFC 6455 section 1.3)
+const ACCEPT_GUID: &[u8; 36] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Silence for this many ping intervals drops the connection
+const MISSED_PINGS: u32 = 3;
+
+/// Longest frame header: 2 bytes + 8-byte length + 4-byte mask
+const MAX_HEADER_LEN: usize = 14;
+
+/// Raw bytes buffered until a whole frame has arrived
+const RX_CAPACITY: usize = RX_LINE_CAPACITY + MAX_HEADER_LEN;
+
+/// Upgrade request/response size limit (build.rs caps the path at 128 bytes)
+const HANDSHAKE_CAPACITY: usize = 512;
+
+/// An upgraded WebSocket connection to FEAGI
+pub struct WebSocket {
+    stream: TcpStream,
+    /// Received bytes; the first `ready` are unmasked payload not yet read
+    rx: Vec<u8, RX_CAPACITY>,
+    ready: usize,
+    /// The message ends after the ready bytes: terminate the line
+    end_of_message: bool,
+    ping_interval_ms: u32,
+    last_ping_ms: u32,
+    last_rx_ms: u32,
+}
+
+impl WebSocket {
+    /// Connect to `host:port` (over TLS if `tls` is set), upgrade `path`, and
+    /// wait up to `timeout_ms` for FEAGI to accept; `ping_interval_ms` of 0
+    /// disables keep-alive pings
+    pub fn connect(
+        host: [u8; 4],
+        port: u16,
+        tls: Option<&TlsConfig>,
+        path: &str,
+        ping_interval_ms: u32,
+        timeout_ms: u32,
+    ) -> Option<Self> {
+        let mut stream = TcpStream::open(host, port, tls, timeout_ms)?;
+
+        let mut nonce = [0u8; 16];
+        unsafe {
+            sys::esp_fill_random(nonce.as_mut_ptr() as *mut c_void, nonce.len());
+        }
+        let mut key: Vec<u8, 24> = Vec::new();
+        base64::encode(&nonce, &mut key);
+        let key = core::str::from_utf8(&key).ok()?;
+
+        let mut request: String<HANDSHAKE_CAPACITY> = String::new();
+        write!(
+            request,
+            "GET {} HTTP/1.1\r\nHost: {}.{}.{}.{}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, host[0], host[1], host[2], host[3], port, key
+        )
+        .ok()?;
+        if !stream.write(request.as_bytes()) {
+            return None;
+        }
+
+        // Read up to the end of the response headers
+        let mut response: Vec<u8, HANDSHAKE_CAPACITY> = Vec::new();
+        let started = now_ms();
+        let header_end = loop {
+            if let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if response.is_full() || now_ms().wrapping_sub(started) > timeout_ms {
+                return None;
+            }
+            let mut chunk = [0u8; 128];
+            let spare = (response.capacity() - response.len()).min(chunk.len());
+            let n = stream.read(&mut chunk[..spare], 100).ok()?;
+            response.extend_from_slice(&chunk[..n]).ok()?;
+        };
+        if !accepted(&response[..header_end], key) {
+            return None;
+        }
+
+        let mut socket = Self {
+            stream,
+            rx: Vec::new(),
+            ready: 0,
+            end_of_message: false,
+            ping_interval_ms,
+            last_ping_ms: started,
+            last_rx_ms: now_ms(),
+        };
+        // FEAGI may send its first message right behind the response
+        socket.rx.extend_from_slice(&response[header_end..]).ok()?;
+        Some(socket)
+    }
+}
+
+impl FeagiTransport for WebSocket {
+    /// Send one line as a text message
+    fn send_frame(&mut self, data: &[u8]) -> bool {
+        let line = data.strip_suffix(b"\n").unwrap_or(data);
+        self.write_frame(OPCODE_TEXT, line)
+    }
+
+    /// Read message bytes, newline-terminated per message; Ok(0) on timeout,
+    /// Err once FEAGI closed the connection or stopped answering pings
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        let now = now_ms();
+        if self.ping_interval_ms > 0 {
+            if now.wrapping_sub(self.last_rx_ms) > self.ping_interval_ms.saturating_mul(MISSED_PINGS) {
+                return Err(());
+            }
+            if now.wrapping_sub(self.last_ping_ms) >= self.ping_interval_ms {
+                if !self.write_frame(OPCODE_PING, b"") {
+                    return Err(());
+                }
+                self.last_ping_ms = now;
+            }
+        }
+
+        // Hand out what's already buffered before waiting on the socket
+        let n = self.drain(buf)?;
+        if n > 0 {
+            return Ok(n);
+        }
+        let mut chunk = [0u8; 256];
+        let spare = (RX_CAPACITY - self.rx.len()).min(chunk.len());
+        let received = self.stream.read(&mut chunk[..spare], timeout_ms)?;
+        if received > 0 {
+            self.last_rx_ms = now_ms();
+            let _ = self.rx.extend_from_slice(&chunk[..received]);
+        }
+        self.drain(buf)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("websocket")
+    }
+}
+
+impl WebSocket {
+    /// Move decoded message bytes into `buf`, answering control frames on the way
+    fn drain(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut n = 0;
+        loop {
+            if self.ready > 0 {
+                let take = self.ready.min(buf.len() - n);
+                buf[n..n + take].copy_from_slice(&self.rx[..take]);
+                self.consume(take);
+                self.ready -= take;
+                n += take;
+                if self.ready > 0 {
+                    return Ok(n);
+                }
+            }
+            if self.end_of_message {
+                if n == buf.len() {
+                    return Ok(n);
+                }
+                buf[n] = b'\n';
+                n += 1;
+                self.end_of_message = false;
+            }
+
+            let Some(frame) = FrameHeader::parse(&self.rx) else {
+                return Ok(n);
+            };
+            let frame_len = frame.header_len.saturating_add(frame.payload_len);
+            if frame_len > RX_CAPACITY {
+                // Longer than any line the burst loop accepts
+                return Err(());
+            }
+            if frame_len > self.rx.len() {
+                return Ok(n);
+            }
+            let payload = &mut self.rx[frame.header_len..frame_len];
+            if let Some(mask) = frame.mask {
+                for (i, byte) in payload.iter_mut().enumerate() {
+                    *byte ^= mask[i % 4];
+                }
+            }
+            self.consume(frame.header_len);
+
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    self.ready = frame.payload_len;
+                    // Don't double the newline if FEAGI already sent one
+                    self.end_of_message = frame.fin && self.rx[..frame.payload_len].last() != Some(&b'\n');
+                }
+                OPCODE_PING => {
+                    let mut payload: Vec<u8, 125> = Vec::new();
+                    let _ = payload.extend_from_slice(&self.rx[..frame.payload_len.min(125)]);
+                    self.consume(frame.payload_len);
+                    if !self.write_frame(OPCODE_PONG, &payload) {
+                        return Err(());
+                    }
+                }
+                OPCODE_CLOSE => {
+                    let _ = self.write_frame(OPCODE_CLOSE, b"");
+                    return Err(());
+                }
+                // Pongs only matter for last_rx_ms; unknown opcodes are skipped
+                _ => self.consume(frame.payload_len),
+            }
+        }
+    }
+
+    /// Send one complete frame, masked as RFC 6455 requires of clients
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> bool {
+        let mut out: Vec<u8, 256> = Vec::new();
+        let _ = out.push(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => {
+                let _ = out.push(0x80 | len as u8);
+            }
+            len if len <= u16::MAX as usize => {
+                let _ = out.push(0x80 | 126);
+                let _ = out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                let _ = out.push(0x80 | 127);
+                let _ = out.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let mask = unsafe { sys::esp_random() }.to_ne_bytes();
+        let _ = out.extend_from_slice(&mask);
+
+        // Mask in chunks so a long frame goes out in few segments
+        for (i, byte) in payload.iter().enumerate() {
+            if out.is_full() {
+                if !self.stream.write(&out) {
+                    return false;
+                }
+                out.clear();
+            }
+            let _ = out.push(byte ^ mask[i % 4]);
+        }
+        self.stream.write(&out)
+    }
+
+    /// Drop the first `n` buffered bytes
+    fn consume(&mut self, n: usize) {
+        let len = self.rx.len();
+        self.rx.copy_within(n..len, 0);
+        self.rx.truncate(len - n);
+    }
+}
+
+struct FrameHeader {
+    fin: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHeader {
+    /// Parse the header at the start of `bytes`; None until all of it arrived
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let first = *bytes.first()?;
+        let second = *bytes.get(1)?;
+        let (payload_len, mut header_len) = match second & 0x7F {
+            126 => (u16::from_be_bytes(bytes.get(2..4)?.try_into().ok()?) as usize, 4),
+            127 => {
+                let len = u64::from_be_bytes(bytes.get(2..10)?.try_into().ok()?);
+                (usize::try_from(len).unwrap_or(usize::MAX), 10)
+            }
+            len => (len as usize, 2),
+        };
+        // Servers must not mask, but accept it anyway
+        let mask = if second & 0x80 != 0 {
+            let key = bytes.get(header_len..header_len + 4)?.try_into().ok()?;
+            header_len += 4;
+            Some(key)
+        } else {
+            None
+        };
+        Some(Self {
+            fin: first & 0x80 != 0,
+            opcode: first & 0x0F,
+            mask,
+            header_len,
+            payload_len,
+        })
+    }
+}
+
+/// A `101 Switching Protocols` response carrying the right Sec-WebSocket-Accept for `key`
+fn accepted(response: &[u8], key: &str) -> bool {
+    let Ok(text) = core::str::from_utf8(response) else {
+        return false;
+    };
+    let mut lines = text.split("\r\n");
+    let switching = lines
+        .next()
+        .and_then(|status| status.split(' ').nth(1))
+        .is_some_and(|code| code == "101");
+    let accept = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-accept"))
+        .map(|(_, value)| value.trim());
+
+    let mut input = [0u8; 24 + 36];
+    input[..24].copy_from_slice(key.as_bytes());
+    input[24..].copy_from_slice(ACCEPT_GUID);
+    let mut digest = [0u8; 20];
+    if unsafe { sys::mbedtls_sha1(input.as_ptr(), input.len(), digest.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    let mut expected: Vec<u8, 28> = Vec::new();
+    base64::encode(&digest, &mut expected);
+    switching && accept.is_some_and(|a| a.as_bytes() == expected.as_slice())
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
@@ -0,0 +1,306 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WiFi station for the TCP and UDP transports
+//!
+//! With `"transport": {"type": "wifi", ...}` the board joins the configured
+//! network as a station and connects to FEAGI over TCP; with `"udp"` it
+//! exchanges datagrams with FEAGI instead, with `"websocket"` it upgrades
+//! the TCP connection to a WebSocket, with `"mqtt"` it talks to a broker, and
+//! with `"zmq"` it joins FEAGI's ZeroMQ PUB/SUB sockets.
+//! Association and DHCP
+//! are polled rather than event-driven: the station is (re)connected from the
+//! burst loop when the transport supervisor restarts the transport, so a lost
+//! access point is handled the same way as a wedged UART. The station takes
+//! its address from DHCP unless `static_ip` is configured.
+
+use core::ffi::{c_char, c_void};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+
+use crate::mdns;
+use crate::mqtt::{MqttClient, MqttConfig};
+use crate::provisioning::{init_nvs, Credentials, ProvisioningConfig};
+use crate::transport::{TcpStream, TlsConfig, Transport, UdpSocket};
+use crate::websocket::WebSocket;
+use crate::zmtp::ZmqLink;
+
+/// How lines travel once the station is up
+#[derive(Debug, Clone, Copy)]
+pub enum NetProtocol {
+    /// One TCP stream to FEAGI
+    Tcp,
+    /// Datagrams to FEAGI; motor commands arrive on `local_port`
+    Udp { local_port: u16 },
+    /// WebSocket to FEAGI's connector interface at `path`, pinged every
+    /// `ping_interval_ms` (0 = never)
+    WebSocket { path: &'static str, ping_interval_ms: u32 },
+    /// MQTT session with the broker at host:port
+    Mqtt(MqttConfig),
+    /// ZeroMQ PUB to FEAGI's sensory SUB at host:port, SUB to its motor PUB
+    /// at `motor_port`, subscribed to the `subscribe` prefix
+    Zmq { motor_port: u16, subscribe: &'static str },
+}
+
+/// Fixed station address for networks without DHCP (from config.json
+/// `transport.config.static_ip`)
+#[derive(Debug, Clone, Copy)]
+pub struct StaticIp {
+    pub ip: [u8; 4],
+    pub netmask: [u8; 4],
+    pub gateway: [u8; 4],
+    pub dns: Option<[u8; 4]>,
+}
+
+/// WiFi station and FEAGI endpoint (from config.json `transport.config`)
+#[derive(Debug, Clone, Copy)]
+pub struct WifiConfig {
+    pub ssid: &'static str,
+    pub password: &'static str,
+    /// FEAGI (or MQTT broker) IPv4 address and port
+    pub host: [u8; 4],
+    pub port: u16,
+    pub protocol: NetProtocol,
+    /// Give up on association + DHCP (and the WebSocket upgrade, MQTT
+    /// CONNACK or ZMTP handshake) after this long
+    pub connect_timeout_ms: u32,
+    /// Browse mDNS for `_feagi._tcp.local` this long before falling back to
+    /// `host:port` (0 = don't browse)
+    pub discovery_timeout_ms: u32,
+    /// TLS for the TCP, WebSocket and MQTT protocols
+    pub tls: Option<TlsConfig>,
+    /// Captive-portal provisioning; credentials it stored override the
+    /// ssid, password, host and port above
+    pub provisioning: Option<ProvisioningConfig>,
+    /// Fixed address instead of DHCP
+    pub static_ip: Option<StaticIp>,
+    /// DHCP/mDNS hostname, NUL-terminated (None = the ESP-IDF default)
+    pub hostname: Option<&'static str>,
+}
+
+/// Times the station lost its access point since boot (link telemetry)
+static DISCONNECTS: AtomicU32 = AtomicU32::new(0);
+
+/// The station interface, brought up once at boot
+pub struct Wifi {
+    netif: *mut sys::esp_netif_t,
+    config: WifiConfig,
+}
+
+impl Wifi {
+    /// Initialize NVS, netif and the WiFi driver and start joining the network
+    /// (the `provisioned` one instead of config.json's, if given)
+    ///
+    /// Returns None if the driver couldn't be brought up. Not being associated
+    /// yet is not an error: `open_transport` keeps retrying.
+    pub fn start(mut config: WifiConfig, provisioned: Option<&Credentials>) -> Option<Self> {
+        let (ssid, password) = match provisioned {
+            Some(credentials) => {
+                config.host = credentials.host;
+                config.port = credentials.port;
+                (credentials.ssid.as_str(), credentials.password.as_str())
+            }
+            None => (config.ssid, config.password),
+        };
+        if !init_nvs() {
+            return None;
+        }
+        unsafe {
+            if sys::esp_netif_init() != sys::ESP_OK {
+                return None;
+            }
+            let ret = sys::esp_event_loop_create_default();
+            if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+                return None;
+            }
+            let netif = sys::esp_netif_create_default_wifi_sta();
+            if netif.is_null() {
+                return None;
+            }
+            if let Some(hostname) = config.hostname {
+                sys::esp_netif_set_hostname(netif, hostname.as_ptr() as *const c_char);
+            }
+            if let Some(ref static_ip) = config.static_ip {
+                if !set_static_ip(netif, static_ip) {
+                    return None;
+                }
+            }
+
+            let init = init_config();
+            if sys::esp_wifi_init(&init) != sys::ESP_OK {
+                return None;
+            }
+            sys::esp_event_handler_register(
+                sys::WIFI_EVENT,
+                sys::wifi_event_t_WIFI_EVENT_STA_DISCONNECTED as i32,
+                Some(on_disconnected),
+                core::ptr::null_mut(),
+            );
+            sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM);
+            sys::esp_wifi_set_mode(sys::wifi_mode_t_WIFI_MODE_STA);
+
+            let mut wifi_config: sys::wifi_config_t = core::mem::zeroed();
+            copy_truncated(&mut wifi_config.sta.ssid, ssid);
+            copy_truncated(&mut wifi_config.sta.password, password);
+            if sys::esp_wifi_set_config(sys::wifi_interface_t_WIFI_IF_STA, &mut wifi_config) != sys::ESP_OK
+                || sys::esp_wifi_start() != sys::ESP_OK
+            {
+                return None;
+            }
+            // Modem sleep delays received motor commands by up to a beacon interval
+            sys::esp_wifi_set_ps(sys::wifi_ps_type_t_WIFI_PS_NONE);
+        }
+        Some(Self { netif, config })
+    }
+
+    /// Disconnect and turn the radio off (the board failed over to another
+    /// transport)
+    pub fn stop(self) {
+        unsafe {
+            sys::esp_wifi_disconnect();
+            sys::esp_wifi_stop();
+        }
+    }
+
+    /// Associated and holding an IP address
+    pub fn is_connected(&self) -> bool {
+        unsafe {
+            let mut ap: sys::wifi_ap_record_t = core::mem::zeroed();
+            let mut ip: sys::esp_netif_ip_info_t = core::mem::zeroed();
+            sys::esp_wifi_sta_get_ap_info(&mut ap) == sys::ESP_OK
+                && sys::esp_netif_get_ip_info(self.netif, &mut ip) == sys::ESP_OK
+                && ip.ip.addr != 0
+        }
+    }
+
+    /// Signal strength of the access point, while associated
+    pub fn rssi(&self) -> Option<i8> {
+        unsafe {
+            let mut ap: sys::wifi_ap_record_t = core::mem::zeroed();
+            (sys::esp_wifi_sta_get_ap_info(&mut ap) == sys::ESP_OK).then_some(ap.rssi)
+        }
+    }
+
+    /// Times the station lost its access point since boot
+    pub fn disconnects() -> u32 {
+        DISCONNECTS.load(Ordering::Relaxed)
+    }
+
+    /// Join the network (if not already) and wait for DHCP
+    pub fn connect(&self) -> bool {
+        if self.is_connected() {
+            return true;
+        }
+        unsafe {
+            sys::esp_wifi_connect();
+        }
+        let mut waited_ms = 0;
+        while waited_ms < self.config.connect_timeout_ms {
+            FreeRtos::delay_ms(100);
+            waited_ms += 100;
+            if self.is_connected() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Connect (or reconnect) to the network, then open the TCP stream, UDP
+    /// socket, WebSocket or ZeroMQ sockets to FEAGI, or the MQTT session
+    pub fn open_transport(&self) -> Option<Transport> {
+        if !self.connect() {
+            return None;
+        }
+        open_endpoint(&self.config)
+    }
+}
+
+/// Open the configured protocol to FEAGI (or the MQTT broker) once the
+/// interface has an address; shared with the Ethernet port (ethernet.rs)
+pub fn open_endpoint(config: &WifiConfig) -> Option<Transport> {
+    let WifiConfig { mut host, mut port, connect_timeout_ms, discovery_timeout_ms, tls, .. } = *config;
+    if discovery_timeout_ms > 0 {
+        if let Some((found_host, found_port)) = mdns::discover(discovery_timeout_ms) {
+            host = found_host;
+            port = found_port;
+        }
+    }
+    match config.protocol {
+        NetProtocol::Tcp => TcpStream::open(host, port, tls.as_ref(), connect_timeout_ms).map(Transport::Tcp),
+        NetProtocol::Udp { local_port } => UdpSocket::bind(local_port, host, port).map(Transport::Udp),
+        NetProtocol::WebSocket { path, ping_interval_ms } => {
+            WebSocket::connect(host, port, tls.as_ref(), path, ping_interval_ms, connect_timeout_ms).map(Transport::WebSocket)
+        }
+        NetProtocol::Mqtt(mqtt) => MqttClient::connect(host, port, tls.as_ref(), mqtt, connect_timeout_ms).map(Transport::Mqtt),
+        NetProtocol::Zmq { motor_port, subscribe } => {
+            ZmqLink::connect(host, port, motor_port, subscribe, connect_timeout_ms).map(Transport::Zmq)
+        }
+    }
+}
+
+unsafe extern "C" fn on_disconnected(_arg: *mut c_void, _base: sys::esp_event_base_t, _id: i32, _data: *mut c_void) {
+    DISCONNECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Stop the DHCP client and configure a fixed address (and DNS server)
+pub unsafe fn set_static_ip(netif: *mut sys::esp_netif_t, config: &StaticIp) -> bool {
+    // Fails harmlessly if the client isn't running yet
+    sys::esp_netif_dhcpc_stop(netif);
+    let mut ip: sys::esp_netif_ip_info_t = core::mem::zeroed();
+    ip.ip.addr = u32::from_ne_bytes(config.ip);
+    ip.netmask.addr = u32::from_ne_bytes(config.netmask);
+    ip.gw.addr = u32::from_ne_bytes(config.gateway);
+    if sys::esp_netif_set_ip_info(netif, &ip) != sys::ESP_OK {
+        return false;
+    }
+    if let Some(server) = config.dns {
+        let mut dns: sys::esp_netif_dns_info_t = core::mem::zeroed();
+        dns.ip.u_addr.ip4.addr = u32::from_ne_bytes(server);
+        dns.ip.type_ = sys::ESP_IPADDR_TYPE_V4 as u8;
+        if sys::esp_netif_set_dns_info(netif, sys::esp_netif_dns_type_t_ESP_NETIF_DNS_MAIN, &mut dns) != sys::ESP_OK {
+            return false;
+        }
+    }
+    true
+}
+
+/// Equivalent of the C `WIFI_INIT_CONFIG_DEFAULT()` macro (ESP-IDF v5.1)
+pub unsafe fn init_config() -> sys::wifi_init_config_t {
+    sys::wifi_init_config_t {
+        osi_funcs: core::ptr::addr_of_mut!(sys::g_wifi_osi_funcs),
+        wpa_crypto_funcs: sys::g_wifi_default_wpa_crypto_funcs,
+        static_rx_buf_num: sys::CONFIG_ESP_WIFI_STATIC_RX_BUFFER_NUM as _,
+        dynamic_rx_buf_num: sys::CONFIG_ESP_WIFI_DYNAMIC_RX_BUFFER_NUM as _,
+        tx_buf_type: sys::CONFIG_ESP_WIFI_TX_BUFFER_TYPE as _,
+        static_tx_buf_num: sys::WIFI_STATIC_TX_BUFFER_NUM as _,
+        dynamic_tx_buf_num: sys::WIFI_DYNAMIC_TX_BUFFER_NUM as _,
+        cache_tx_buf_num: sys::WIFI_CACHE_TX_BUFFER_NUM as _,
+        csi_enable: sys::WIFI_CSI_ENABLED as _,
+        ampdu_rx_enable: sys::WIFI_AMPDU_RX_ENABLED as _,
+        ampdu_tx_enable: sys::WIFI_AMPDU_TX_ENABLED as _,
+        amsdu_tx_enable: sys::WIFI_AMSDU_TX_ENABLED as _,
+        nvs_enable: sys::WIFI_NVS_ENABLED as _,
+        nano_enable: sys::WIFI_NANO_FORMAT_ENABLED as _,
+        rx_ba_win: sys::WIFI_DEFAULT_RX_BA_WIN as _,
+        wifi_task_core_id: sys::WIFI_TASK_CORE_ID as _,
+        beacon_max_len: sys::WIFI_SOFTAP_BEACON_MAX_LEN as _,
+        mgmt_sbuf_num: sys::WIFI_MGMT_SBUF_NUM as _,
+        feature_caps: sys::g_wifi_feature_caps,
+        sta_disconnected_pm: sys::WIFI_STA_DISCONNECTED_PM_ENABLED != 0,
+        espnow_max_encrypt_num: sys::CONFIG_ESP_WIFI_ESPNOW_MAX_ENCRYPT_NUM as _,
+        magic: sys::WIFI_INIT_CONFIG_MAGIC as _,
+    }
+}
+
+/// Copy a string into a fixed C field (build.rs and the provisioning form
+/// reject values that don't fit)
+pub fn copy_truncated(field: &mut [u8], value: &str) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
@@ -0,0 +1,263 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! ZeroMQ compatibility transport (minimal ZMTP 3.0)
+//!
+//! FEAGI core moves IPU/OPU traffic over ZeroMQ PUB/SUB. With
+//! `"transport": {"type": "zmq", ...}` the board joins such a deployment
+//! without a translation bridge: it connects a PUB socket to FEAGI's sensory
+//! SUB endpoint (`port`) and a SUB socket to FEAGI's motor PUB endpoint
+//! (`motor_port`), speaking just enough ZMTP for libzmq peers: the 3.0
+//! greeting, the NULL security mechanism, READY, and single-part messages.
+//!
+//! Every line the board sends goes out as one message (without its newline).
+//! Each incoming message is read as one line; in a multipart message only the
+//! last frame counts, earlier ones (topic, envelope) are skipped. ZMTP 3.0
+//! has no heartbeats, so a dead FEAGI shows up as a failed write or a closed
+//! connection, which the transport supervisor handles.
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::transport::{FeagiTransport, TcpStream, TransportStatus};
+use crate::RX_LINE_CAPACITY;
+
+/// Frame flags
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+/// Greeting: signature, version 3.0, NULL mechanism, as-server = 0
+const GREETING_LEN: usize = 64;
+
+/// Longest frame header: flags + 8-byte size
+const MAX_HEADER_LEN: usize = 9;
+
+/// Raw bytes buffered until a whole frame has arrived
+const RX_CAPACITY: usize = RX_LINE_CAPACITY + MAX_HEADER_LEN;
+
+/// The peer's READY must fit a short frame (it only carries Socket-Type and
+/// maybe Identity)
+const MAX_COMMAND_LEN: usize = u8::MAX as usize;
+
+/// The PUB/SUB connection pair to FEAGI
+pub struct ZmqLink {
+    /// PUB, connected to FEAGI's sensory SUB
+    sensory: TcpStream,
+    /// SUB, connected to FEAGI's motor PUB
+    motor: TcpStream,
+    /// Received bytes; the first `ready` are message payload not yet read
+    rx: Vec<u8, RX_CAPACITY>,
+    ready: usize,
+    /// The message ends after the ready bytes: terminate the line
+    end_of_message: bool,
+}
+
+impl ZmqLink {
+    /// Connect and handshake both sockets, each within `timeout_ms`, and
+    /// subscribe to motor messages starting with `subscribe` ("" = all)
+    pub fn connect(host: [u8; 4], sensory_port: u16, motor_port: u16, subscribe: &str, timeout_ms: u32) -> Option<Self> {
+        let mut sensory = TcpStream::connect(host, sensory_port)?;
+        handshake(&mut sensory, "PUB", timeout_ms)?;
+        let mut motor = TcpStream::connect(host, motor_port)?;
+        handshake(&mut motor, "SUB", timeout_ms)?;
+
+        // ZMTP 3.0 subscription: a message of 0x01 followed by the prefix
+        let mut subscription: Vec<u8, 256> = Vec::new();
+        subscription.push(0x01).ok()?;
+        subscription.extend_from_slice(subscribe.as_bytes()).ok()?;
+        if !send_frame(&mut motor, 0, &subscription) {
+            return None;
+        }
+        Some(Self {
+            sensory,
+            motor,
+            rx: Vec::new(),
+            ready: 0,
+            end_of_message: false,
+        })
+    }
+}
+
+impl FeagiTransport for ZmqLink {
+    /// Publish one line as a single-frame message
+    fn send_frame(&mut self, data: &[u8]) -> bool {
+        let line = data.strip_suffix(b"\n").unwrap_or(data);
+        send_frame(&mut self.sensory, 0, line)
+    }
+
+    /// Read motor messages, newline-terminated per message; Ok(0) on
+    /// timeout, Err once FEAGI closed the connection
+    fn poll_commands(&mut self, buf: &mut [u8], timeout_ms: u32) -> Result<usize, ()> {
+        // Hand out what's already buffered before waiting on the socket
+        let n = self.drain(buf)?;
+        if n > 0 {
+            return Ok(n);
+        }
+        let mut chunk = [0u8; 256];
+        let spare = (RX_CAPACITY - self.rx.len()).min(chunk.len());
+        let received = self.motor.read(&mut chunk[..spare], timeout_ms)?;
+        let _ = self.rx.extend_from_slice(&chunk[..received]);
+        self.drain(buf)
+    }
+
+    fn status(&self) -> TransportStatus {
+        TransportStatus::up("zmq")
+    }
+}
+
+impl ZmqLink {
+    /// Move message payloads into `buf`, skipping commands and envelope frames
+    fn drain(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut n = 0;
+        loop {
+            if self.ready > 0 {
+                let take = self.ready.min(buf.len() - n);
+                buf[n..n + take].copy_from_slice(&self.rx[..take]);
+                self.consume(take);
+                self.ready -= take;
+                n += take;
+                if self.ready > 0 {
+                    return Ok(n);
+                }
+            }
+            if self.end_of_message {
+                if n == buf.len() {
+                    return Ok(n);
+                }
+                buf[n] = b'\n';
+                n += 1;
+                self.end_of_message = false;
+            }
+
+            let Some((flags, header_len, size)) = frame_header(&self.rx) else {
+                return Ok(n);
+            };
+            let frame_len = header_len.saturating_add(size);
+            if frame_len > RX_CAPACITY {
+                // Longer than any line the burst loop accepts
+                return Err(());
+            }
+            if frame_len > self.rx.len() {
+                return Ok(n);
+            }
+            if flags & (FLAG_COMMAND | FLAG_MORE) != 0 {
+                self.consume(frame_len);
+                continue;
+            }
+            // Don't double the newline if FEAGI already sent one
+            self.end_of_message = self.rx[header_len..frame_len].last() != Some(&b'\n');
+            self.consume(header_len);
+            self.ready = size;
+        }
+    }
+
+    /// Drop the first `n` buffered bytes
+    fn consume(&mut self, n: usize) {
+        let len = self.rx.len();
+        self.rx.copy_within(n..len, 0);
+        self.rx.truncate(len - n);
+    }
+}
+
+/// Exchange greetings and READY commands as `socket_type`
+fn handshake(stream: &mut TcpStream, socket_type: &str, timeout_ms: u32) -> Option<()> {
+    let mut greeting = [0u8; GREETING_LEN];
+    greeting[0] = 0xFF;
+    greeting[9] = 0x7F;
+    greeting[10] = 3; // version 3.0
+    greeting[12..16].copy_from_slice(b"NULL");
+    if !stream.write(&greeting) {
+        return None;
+    }
+
+    let mut ready: Vec<u8, 64> = Vec::new();
+    ready.push(5).ok()?;
+    ready.extend_from_slice(b"READY").ok()?;
+    ready.push(11).ok()?;
+    ready.extend_from_slice(b"Socket-Type").ok()?;
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes()).ok()?;
+    ready.extend_from_slice(socket_type.as_bytes()).ok()?;
+    if !send_frame(stream, FLAG_COMMAND, &ready) {
+        return None;
+    }
+
+    let started = now_ms();
+    let mut peer = [0u8; GREETING_LEN];
+    read_exact(stream, &mut peer, started, timeout_ms)?;
+    if peer[0] != 0xFF || peer[9] != 0x7F || peer[10] < 3 || peer[12..17] != *b"NULL\0" {
+        return None;
+    }
+
+    // The peer's READY: a short command frame (flags, size, body)
+    let mut header = [0u8; 2];
+    read_exact(stream, &mut header, started, timeout_ms)?;
+    if header[0] != FLAG_COMMAND {
+        return None;
+    }
+    let mut command = [0u8; MAX_COMMAND_LEN];
+    let command = &mut command[..header[1] as usize];
+    read_exact(stream, command, started, timeout_ms)?;
+    if !command.starts_with(b"\x05READY") {
+        return None;
+    }
+    Some(())
+}
+
+/// Send one frame with `flags` (short or long as the size needs)
+fn send_frame(stream: &mut TcpStream, flags: u8, body: &[u8]) -> bool {
+    let mut out: Vec<u8, 256> = Vec::new();
+    if body.len() <= u8::MAX as usize {
+        let _ = out.push(flags);
+        let _ = out.push(body.len() as u8);
+    } else {
+        let _ = out.push(flags | FLAG_LONG);
+        let _ = out.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    }
+    // Copy through a small buffer so short frames go out in one segment
+    let mut rest = body;
+    while !rest.is_empty() {
+        if out.is_full() {
+            if !stream.write(&out) {
+                return false;
+            }
+            out.clear();
+        }
+        let n = rest.len().min(out.capacity() - out.len());
+        let _ = out.extend_from_slice(&rest[..n]);
+        rest = &rest[n..];
+    }
+    stream.write(&out)
+}
+
+/// Flags, header length and body size of the frame at the start of
+/// `bytes`; None until the whole header arrived
+fn frame_header(bytes: &[u8]) -> Option<(u8, usize, usize)> {
+    let flags = *bytes.first()?;
+    if flags & FLAG_LONG != 0 {
+        let size = u64::from_be_bytes(bytes.get(1..9)?.try_into().ok()?);
+        Some((flags, 9, usize::try_from(size).unwrap_or(usize::MAX)))
+    } else {
+        Some((flags, 2, *bytes.get(1)? as usize))
+    }
+}
+
+/// Fill `buf` from the stream, giving up `timeout_ms` after `started`
+fn read_exact(stream: &mut TcpStream, buf: &mut [u8], started: u32, timeout_ms: u32) -> Option<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        if now_ms().wrapping_sub(started) > timeout_ms {
+            return None;
+        }
+        filled += stream.read(&mut buf[filled..], 100).ok()?;
+    }
+    Some(())
+}
+
+fn now_ms() -> u32 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u32
+}
@@ -36,6 +36,9 @@ fn main() {
     let burst_frequency = config.get("burst_frequency")
         .and_then(|v| v.as_u64())
         .unwrap_or(100);
+    if !(1..=1000).contains(&burst_frequency) {
+        panic!("burst_frequency must be 1-1000 Hz (got {})", burst_frequency);
+    }
     
     let model = config.get("model")
         .and_then(|v| v.as_str())
@@ -46,10 +49,42 @@ fn main() {
         .and_then(|b| b.get("path"))
         .and_then(|v| v.as_str());
     
+    // Hybrid mode also talks to a FEAGI host (see src/hybrid.rs)
+    let mode = config.get("mode")
+        .and_then(|v| v.as_str())
+        .unwrap_or("standalone");
+    let telemetry_hz = match mode {
+        "standalone" => None,
+        "hybrid" => {
+            let telemetry_hz = config.get("hybrid")
+                .and_then(|h| h.get("telemetry_hz"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(10);
+            if !(1..=burst_frequency).contains(&telemetry_hz) {
+                panic!("hybrid.telemetry_hz must be 1-{} Hz, at most the burst frequency (got {})", burst_frequency, telemetry_hz);
+            }
+            Some(telemetry_hz)
+        }
+        other => panic!("mode must be standalone or hybrid (got \"{}\")", other),
+    };
+    
+    // Brain checkpoints (see src/checkpoint.rs): on when the block is there,
+    // unless "enabled": false
+    let checkpoint = config.get("checkpoint")
+        .filter(|c| c.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true));
+    let checkpoint_interval = checkpoint.map(|c| {
+        let interval_s = c.get("interval_s").and_then(|v| v.as_u64()).unwrap_or(600);
+        if !(10..=86400).contains(&interval_s) {
+            panic!("checkpoint.interval_s must be 10-86400 (got {})", interval_s);
+        }
+        interval_s
+    });
+    
     // Generate GPIO configuration
+    let no_gpio = Vec::new();
     let gpio_config = config.get("gpio")
         .and_then(|v| v.as_array())
-        .unwrap_or(&vec![]);
+        .unwrap_or(&no_gpio);
     
     // Generate Rust code for config
     let mut config_code = String::new();
@@ -57,7 +92,54 @@ fn main() {
     config_code.push_str(&format!("pub const BURST_FREQUENCY_HZ: u32 = {};\n", burst_frequency));
     config_code.push_str(&format!("pub const MODEL: &str = \"{}\";\n", model));
     
+    // Connectome on an SD card or external SPI flash (see src/storage.rs),
+    // loaded at boot instead of embedded: on when the block is there,
+    // unless "enabled": false
+    let storage = config.get("storage")
+        .filter(|s| s.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true));
+    let mut storage_pins = Vec::new();
+    match storage {
+        Some(storage) => {
+            let medium = match storage.get("type").and_then(|v| v.as_str()) {
+                Some("sd_card") => {
+                    let file = storage.get("file").and_then(|v| v.as_str()).unwrap_or("brain.fcn");
+                    if file.is_empty() || file.len() > 64 || file.starts_with('/') || file.contains(['"', '\\']) {
+                        panic!("storage.file must be a path on the SD card of 1-64 characters, without a leading / (got \"{}\")", file);
+                    }
+                    format!("Medium::SdCard {{ file: \"/sdcard/{}\\0\" }}", file)
+                }
+                Some("spi_flash") => {
+                    let offset = storage.get("offset").and_then(|v| v.as_u64()).unwrap_or(0);
+                    if offset > u32::MAX as u64 {
+                        panic!("storage.offset must fit 32 bits (got {})", offset);
+                    }
+                    format!("Medium::SpiFlash {{ offset: {} }}", offset)
+                }
+                other => panic!("storage.type must be sd_card or spi_flash (got {:?})", other),
+            };
+            // The SPI bus; the defaults are the usual wiring of each board
+            let defaults = if model.contains("s3") { [12, 11, 13, 10] } else { [18, 23, 19, 5] };
+            for (name, default) in ["sclk_pin", "mosi_pin", "miso_pin", "cs_pin"].into_iter().zip(defaults) {
+                let pin = storage.get(name).and_then(|v| v.as_u64()).unwrap_or(default);
+                if !peripheral_pin_allowed(model, pin) {
+                    panic!("storage.{} {} can't be used on {} (console, LED, flash or input-only pin)", name, pin, model);
+                }
+                if gpio_uses_pin(gpio_config, pin) || storage_pins.contains(&pin) {
+                    panic!("storage.{} {} is already in use", name, pin);
+                }
+                storage_pins.push(pin);
+            }
+            config_code.push_str(&format!(
+                "pub const STORAGE: Option<StorageConfig> = Some(StorageConfig {{ medium: {}, sclk_pin: {}, mosi_pin: {}, miso_pin: {}, cs_pin: {} }});\n",
+                medium, storage_pins[0], storage_pins[1], storage_pins[2], storage_pins[3]
+            ));
+        }
+        None => config_code.push_str("pub const STORAGE: Option<StorageConfig> = None;\n"),
+    }
+    
     // Add connectome embedding if path is provided
+    let mut connectome_areas: Option<Vec<String>> = None;
+    let mut embedded: Option<(usize, usize)> = None;
     if let Some(connectome_file) = connectome_path {
         // Try to resolve connectome path (could be absolute or relative)
         let connectome_path = if connectome_file.starts_with('/') {
@@ -70,22 +152,37 @@ fn main() {
         };
         
         if connectome_path.exists() {
-            // Copy connectome to OUT_DIR so it can be included at compile time
-            let connectome_name = "embedded_connectome.bin";
-            let out_connectome = PathBuf::from(&out_dir).join(connectome_name);
-            
-            if fs::copy(&connectome_path, &out_connectome).is_ok() {
+            // Convert the connectome into the flat image src/connectome.rs
+            // loads at boot, and include that
+            let snapshot = feagi_connectome_serialization::load_connectome(&connectome_path)
+                .unwrap_or_else(|e| panic!("Failed to load connectome {:?}: {}", connectome_path, e));
+            let image = compact_connectome(&snapshot, model);
+            if storage.is_some() {
+                // Not embedded; the image to put on the storage
+                let out_connectome = PathBuf::from(&out_dir).join("external_connectome.bin");
+                fs::write(&out_connectome, &image.bytes).expect("Failed to write connectome image");
+                config_code.push_str("pub const HAS_CONNECTOME: bool = false;\n");
+                config_code.push_str("pub const CONNECTOME_DATA: &[u8] = &[];\n");
+                println!(
+                    "cargo:warning=Connectome image for external storage: {:?}, {} neurons, {} synapses ({} bytes)",
+                    out_connectome, image.neurons, image.synapses, image.bytes.len()
+                );
+            } else {
+                let connectome_name = "embedded_connectome.bin";
+                let out_connectome = PathBuf::from(&out_dir).join(connectome_name);
+                fs::write(&out_connectome, &image.bytes).expect("Failed to write embedded connectome");
                 config_code.push_str(&format!(
                     "\npub const CONNECTOME_DATA: &[u8] = include_bytes!(\"{}\");\n",
                     connectome_name
                 ));
                 config_code.push_str("pub const HAS_CONNECTOME: bool = true;\n");
-                println!("cargo:warning=Connectome embedded: {} bytes", connectome_path.metadata().map(|m| m.len()).unwrap_or(0));
-            } else {
-                config_code.push_str("pub const HAS_CONNECTOME: bool = false;\n");
-                config_code.push_str("pub const CONNECTOME_DATA: &[u8] = &[];\n");
-                println!("cargo:warning=Failed to copy connectome file");
+                println!(
+                    "cargo:warning=Connectome embedded: {} neurons, {} synapses ({} bytes)",
+                    image.neurons, image.synapses, image.bytes.len()
+                );
             }
+            connectome_areas = Some(image.areas);
+            embedded = Some((image.neurons, image.synapses));
         } else {
             config_code.push_str("pub const HAS_CONNECTOME: bool = false;\n");
             config_code.push_str("pub const CONNECTOME_DATA: &[u8] = &[];\n");
@@ -96,6 +193,172 @@ fn main() {
         config_code.push_str("pub const CONNECTOME_DATA: &[u8] = &[];\n");
     }
     
+    // Connectome uploads (see src/upload.rs) and external storage need
+    // arrays with room for what they bring: as big as brain.path's
+    // connectome by default, or the board's limits without one
+    let upload = config.get("upload")
+        .filter(|u| u.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true));
+    let (mut max_neurons, mut max_synapses) = embedded.unwrap_or((0, 0));
+    for (name, block) in [("upload", upload), ("storage", storage)] {
+        if let Some(block) = block {
+            let (neurons, synapses) = array_capacity(name, block, embedded, model);
+            max_neurons = max_neurons.max(neurons);
+            max_synapses = max_synapses.max(synapses);
+        }
+    }
+    config_code.push_str(&format!("pub const UPLOAD: bool = {};\n", upload.is_some()));
+    config_code.push_str(&format!("pub const MAX_NEURONS: usize = {};\n", max_neurons.max(1)));
+    config_code.push_str(&format!("pub const MAX_SYNAPSES: usize = {};\n", max_synapses.max(1)));
+    
+    match telemetry_hz {
+        Some(telemetry_hz) => config_code.push_str(&format!(
+            "pub const HYBRID: Option<HybridConfig> = Some(HybridConfig {{ telemetry_hz: {} }});\n",
+            telemetry_hz
+        )),
+        None => config_code.push_str("pub const HYBRID: Option<HybridConfig> = None;\n"),
+    }
+    
+    // Synaptic plasticity (see src/plasticity.rs): available when the block
+    // is there, learning at boot unless "enabled": false
+    match config.get("plasticity") {
+        Some(plasticity) => {
+            let enabled = plasticity.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true);
+            // Below 0.002 a step rounds to 0 of the 0-255 weight range
+            // (MIN_LEARNING_RATE in src/plasticity.rs)
+            let min_rate = plasticity.get("min_learning_rate").and_then(|v| v.as_f64()).unwrap_or(0.002);
+            let max_rate = plasticity.get("max_learning_rate").and_then(|v| v.as_f64()).unwrap_or(0.1);
+            if !(0.002..=1.0).contains(&min_rate) || !(min_rate..=1.0).contains(&max_rate) {
+                panic!("plasticity learning rate bounds must be 0.002 <= min_learning_rate <= max_learning_rate <= 1.0 (got {}-{})", min_rate, max_rate);
+            }
+            let learning_rate = plasticity.get("learning_rate").and_then(|v| v.as_f64()).unwrap_or(0.01_f64.clamp(min_rate, max_rate));
+            if !(min_rate..=max_rate).contains(&learning_rate) {
+                panic!("plasticity.learning_rate must be {}-{} (got {})", min_rate, max_rate, learning_rate);
+            }
+            let mut frozen = Vec::new();
+            for area in plasticity.get("frozen_areas").and_then(|v| v.as_array()).into_iter().flatten() {
+                let area = area.as_str().unwrap_or_else(|| panic!("plasticity.frozen_areas must be cortical IDs (got {})", area));
+                if let Some(ref areas) = connectome_areas {
+                    if !areas.iter().any(|a| a == area) {
+                        println!("cargo:warning=plasticity: frozen cortical area \"{}\" isn't in the connectome", area);
+                    }
+                }
+                frozen.push(format!("{:?}", area));
+            }
+            config_code.push_str(&format!(
+                "pub const PLASTICITY: Option<PlasticityConfig> = Some(PlasticityConfig {{ enabled: {}, learning_rate: {:?}, min_learning_rate: {:?}, max_learning_rate: {:?}, frozen_areas: &[{}] }});\n",
+                enabled, learning_rate as f32, min_rate as f32, max_rate as f32, frozen.join(", ")
+            ));
+        }
+        None => config_code.push_str("pub const PLASTICITY: Option<PlasticityConfig> = None;\n"),
+    }
+    
+    // WiFi station and command port (see src/wifi.rs): on when the block
+    // is there, unless "enabled": false
+    let wifi = config.get("wifi")
+        .filter(|w| w.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true));
+    match wifi {
+        Some(wifi) => {
+            let ssid = wifi.get("ssid").and_then(|v| v.as_str()).unwrap_or("");
+            if ssid.is_empty() || ssid.len() > 32 {
+                panic!("wifi.ssid must be 1-32 bytes (got {})", ssid.len());
+            }
+            let password = wifi.get("password").and_then(|v| v.as_str()).unwrap_or("");
+            if !password.is_empty() && !(8..=63).contains(&password.len()) {
+                panic!("wifi.password must be empty (open network) or 8-63 bytes (got {})", password.len());
+            }
+            let port = wifi.get("port").and_then(|v| v.as_u64()).unwrap_or(7700);
+            if !(1..=65535).contains(&port) {
+                panic!("wifi.port must be 1-65535 (got {})", port);
+            }
+            config_code.push_str(&format!(
+                "pub const WIFI: Option<WifiConfig> = Some(WifiConfig {{ ssid: {:?}, password: {:?}, port: {} }});\n",
+                ssid, password, port
+            ));
+        }
+        None => config_code.push_str("pub const WIFI: Option<WifiConfig> = None;\n"),
+    }
+    
+    // Spike raster on a second UART or over WiFi (see src/raster.rs): on
+    // when the block is there, unless "enabled": false
+    let raster = config.get("raster")
+        .filter(|r| r.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true));
+    match raster {
+        Some(raster) => {
+            let every = raster.get("every").and_then(|v| v.as_u64()).unwrap_or(1);
+            if !(1..=255).contains(&every) {
+                panic!("raster.every must be 1-255 bursts (got {})", every);
+            }
+            let max_spikes = raster.get("max_spikes").and_then(|v| v.as_u64()).unwrap_or(256);
+            if !(1..=1024).contains(&max_spikes) {
+                panic!("raster.max_spikes must be 1-1024 (got {})", max_spikes);
+            }
+            // A full frame is about 2 bytes a spike
+            let frame_bytes = 16 + 2 * max_spikes;
+            let sink = match raster.get("sink").and_then(|v| v.as_str()).unwrap_or("uart") {
+                "uart" => {
+                    let uart = raster.get("uart").and_then(|v| v.as_u64()).unwrap_or(1);
+                    if !(1..=2).contains(&uart) {
+                        panic!("raster.uart must be 1 or 2, UART0 is the console (got {})", uart);
+                    }
+                    let tx_pin = raster.get("tx_pin").and_then(|v| v.as_u64()).unwrap_or(17);
+                    if !peripheral_pin_allowed(model, tx_pin) {
+                        panic!("raster.tx_pin {} can't drive a UART on {} (console, LED, flash or input-only pin)", tx_pin, model);
+                    }
+                    if gpio_uses_pin(gpio_config, tx_pin) || storage_pins.contains(&tx_pin) {
+                        panic!("raster.tx_pin {} is already in use", tx_pin);
+                    }
+                    let baud_rate = raster.get("baud_rate").and_then(|v| v.as_u64()).unwrap_or(921600);
+                    if !(9600..=5_000_000).contains(&baud_rate) {
+                        panic!("raster.baud_rate must be 9600-5000000 (got {})", baud_rate);
+                    }
+                    // 10 bits a byte on the line
+                    if frame_bytes * 10 * burst_frequency / every > baud_rate {
+                        println!(
+                            "cargo:warning=raster: {} baud can't carry full frames at {} Hz / {}; frames will be dropped while firing is dense",
+                            baud_rate, burst_frequency, every
+                        );
+                    }
+                    format!("RasterSink::Uart {{ uart: {}, tx_pin: {}, baud_rate: {} }}", uart, tx_pin, baud_rate)
+                }
+                "udp" => {
+                    if wifi.is_none() {
+                        panic!("raster.sink \"udp\" needs a wifi block");
+                    }
+                    let host = raster.get("host").and_then(|v| v.as_str())
+                        .and_then(|h| h.parse::<std::net::Ipv4Addr>().ok())
+                        .unwrap_or_else(|| panic!("raster.host must be an IPv4 address for the udp sink"));
+                    let port = raster.get("port").and_then(|v| v.as_u64()).unwrap_or(7701);
+                    if !(1..=65535).contains(&port) {
+                        panic!("raster.port must be 1-65535 (got {})", port);
+                    }
+                    // Past one Ethernet MTU lwIP fragments the datagram, and
+                    // losing any fragment loses the frame
+                    if frame_bytes > 1472 {
+                        println!(
+                            "cargo:warning=raster: full frames of {} spikes don't fit one datagram; lower max_spikes to 720 or less to keep them whole",
+                            max_spikes
+                        );
+                    }
+                    format!("RasterSink::Udp {{ host: {:?}, port: {} }}", host.octets(), port)
+                }
+                other => panic!("raster.sink must be uart or udp (got \"{}\")", other),
+            };
+            config_code.push_str(&format!(
+                "pub const RASTER: Option<RasterConfig> = Some(RasterConfig {{ sink: {}, every: {}, max_spikes: {} }});\n",
+                sink, every, max_spikes
+            ));
+        }
+        None => config_code.push_str("pub const RASTER: Option<RasterConfig> = None;\n"),
+    }
+    
+    match checkpoint_interval {
+        Some(interval_s) => config_code.push_str(&format!(
+            "pub const CHECKPOINT: Option<CheckpointConfig> = Some(CheckpointConfig {{ interval_s: {} }});\n",
+            interval_s
+        )),
+        None => config_code.push_str("pub const CHECKPOINT: Option<CheckpointConfig> = None;\n"),
+    }
+    
     // Generate GPIO pin configuration
     config_code.push_str("\npub const GPIO_CONFIG: &[GpioPinConfig] = &[\n");
     for gpio in gpio_config {
@@ -114,9 +377,34 @@ fn main() {
                         _ => "GpioMode::Disabled",
                     };
                     
+                    // The neurons a pin stimulates or is driven by (see
+                    // src/mapping.rs): a cortical ID, optionally with a voxel
+                    let area = check_cortical_mapping(pin, cortical_mapping);
+                    if let Some(ref areas) = connectome_areas {
+                        if !areas.iter().any(|a| a == area) {
+                            println!("cargo:warning=GPIO {}: cortical area \"{}\" isn't in the connectome", pin, area);
+                        }
+                    }
+                    
+                    // Fraction of a neuron's threshold a full-scale input adds
+                    let scale = gpio.get("scale").and_then(|v| v.as_f64()).unwrap_or(1.0);
+                    if !(0.0..=10.0).contains(&scale) {
+                        panic!("GPIO {}: scale must be 0.0-10.0 (got {})", pin, scale);
+                    }
+                    
+                    // Keeps an output from chattering (see src/outputs.rs)
+                    let decay = gpio.get("decay").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                    if !(0.0..=1.0).contains(&decay) {
+                        panic!("GPIO {}: decay must be 0.0-1.0 (got {})", pin, decay);
+                    }
+                    let hold_ms = gpio.get("hold_ms").and_then(|v| v.as_u64()).unwrap_or(0);
+                    if hold_ms > 60000 {
+                        panic!("GPIO {}: hold_ms must be 0-60000 (got {})", pin, hold_ms);
+                    }
+                    
                     config_code.push_str(&format!(
-                        "    GpioPinConfig {{ pin: {}, mode: {}, cortical_mapping: \"{}\" }},\n",
-                        pin, mode_const, cortical_mapping
+                        "    GpioPinConfig {{ pin: {}, mode: {}, cortical_mapping: \"{}\", scale: {:?}, decay: {:?}, hold_ms: {} }},\n",
+                        pin, mode_const, cortical_mapping, scale as f32, decay as f32, hold_ms
                     ));
                 }
             }
@@ -129,3 +417,163 @@ fn main() {
         .expect("Failed to write config.rs");
 }
 
+// Check a cortical_mapping, "area", "area:x" or "area:x,y,z"; its area
+fn check_cortical_mapping(pin: u64, mapping: &str) -> &str {
+    let (area, voxel) = match mapping.split_once(':') {
+        Some((area, voxel)) => (area, Some(voxel)),
+        None => (mapping, None),
+    };
+    if area.is_empty() || area.len() > 8 {
+        panic!("GPIO {}: cortical_mapping \"{}\" must start with a cortical ID of 1-8 characters", pin, mapping);
+    }
+    if let Some(voxel) = voxel {
+        let coordinates: Vec<_> = voxel.split(',').map(|c| c.trim().parse::<u16>()).collect();
+        if !matches!(coordinates.len(), 1 | 3) || coordinates.iter().any(|c| c.is_err()) {
+            panic!("GPIO {}: cortical_mapping \"{}\" must be \"area\", \"area:x\" or \"area:x,y,z\"", pin, mapping);
+        }
+    }
+    area
+}
+
+// A pin the raster UART or the storage SPI bus may use: not the console's
+// (UART0), the LED's, the flash's or an input-only one
+fn peripheral_pin_allowed(model: &str, pin: u64) -> bool {
+    if model.contains("s3") {
+        pin <= 48 && !matches!(pin, 2 | 19 | 20 | 26..=32 | 43 | 44)
+    } else {
+        pin <= 33 && !matches!(pin, 1 | 2 | 3 | 6..=11)
+    }
+}
+
+// Whether a pin is configured under gpio
+fn gpio_uses_pin(gpio_config: &[serde_json::Value], pin: u64) -> bool {
+    gpio_config.iter().any(|g| {
+        g.get("pin").and_then(|v| v.as_u64()) == Some(pin)
+            && g.get("mode").and_then(|v| v.as_str()).is_some_and(|m| m != "disabled")
+    })
+}
+
+// Neurons and synapses the arrays need for the connectomes a block (upload,
+// storage) may bring: its max_neurons and max_synapses, by default those of
+// the connectome at brain.path, else the board's limits
+fn array_capacity(name: &str, block: &serde_json::Value, embedded: Option<(usize, usize)>, model: &str) -> (usize, usize) {
+    let (board_neurons, board_synapses) = board_limits(model);
+    let (default_neurons, default_synapses) = embedded.unwrap_or((board_neurons, board_synapses));
+    let neurons = block.get("max_neurons").and_then(|v| v.as_u64()).unwrap_or(default_neurons as u64) as usize;
+    if neurons > board_neurons {
+        panic!("{}.max_neurons must be at most {} on {} (got {})", name, board_neurons, model, neurons);
+    }
+    let synapses = block.get("max_synapses").and_then(|v| v.as_u64()).unwrap_or(default_synapses as u64) as usize;
+    if synapses > board_synapses {
+        panic!("{}.max_synapses must be at most {} on {} (got {})", name, board_synapses, model, synapses);
+    }
+    (neurons, synapses)
+}
+
+// What the board's RAM holds (see README.md), neurons and synapses
+fn board_limits(model: &str) -> (usize, usize) {
+    if model.contains("s3") { (15000, 75000) } else { (10000, 50000) }
+}
+
+/// Embedded connectome image, as written by `compact_connectome`
+struct ConnectomeImage {
+    bytes: Vec<u8>,
+    neurons: usize,
+    synapses: usize,
+    /// Cortical IDs, for checking the GPIO mappings against
+    areas: Vec<String>,
+}
+
+// Flat little-endian image of a connectome, for src/connectome.rs:
+// a 20-byte header ("FCN1", version, cortical area, neuron and synapse
+// counts, CRC-32 of the rest), 8 bytes per cortical ID, then 28 bytes per
+// neuron and 8 per synapse. Neurons marked invalid are left out and the
+// synapses renumbered to match.
+fn compact_connectome(snapshot: &feagi_connectome_serialization::ConnectomeSnapshot, model: &str) -> ConnectomeImage {
+    let neurons = &snapshot.neurons;
+    let synapses = &snapshot.synapses;
+    
+    // Cortical IDs by area index, in the order the neurons refer to them
+    let mut area_ids: Vec<(u32, &String)> = snapshot.cortical_area_names.iter().map(|(&i, id)| (i, id)).collect();
+    area_ids.sort();
+    let area_index = |area: u32| area_ids.iter().position(|&(i, _)| i == area);
+    
+    // Index in the image of every valid neuron
+    let mut index = vec![None; neurons.count];
+    let mut neuron_count = 0usize;
+    for (i, slot) in index.iter_mut().enumerate() {
+        if neurons.valid_mask[i] {
+            *slot = Some(neuron_count as u16);
+            neuron_count += 1;
+        }
+    }
+    
+    let (max_neurons, max_synapses) = board_limits(model);
+    if neuron_count > max_neurons {
+        panic!("Connectome has {} neurons, {} holds at most {}", neuron_count, model, max_neurons);
+    }
+    
+    let mut payload = Vec::new();
+    for (_, id) in &area_ids {
+        if id.len() > 8 {
+            panic!("Connectome cortical ID \"{}\" is longer than 8 characters", id);
+        }
+        let mut field = [0u8; 8];
+        field[..id.len()].copy_from_slice(id.as_bytes());
+        payload.extend_from_slice(&field);
+    }
+    for i in (0..neurons.count).filter(|&i| neurons.valid_mask[i]) {
+        let area = area_index(neurons.cortical_areas[i] as u32)
+            .unwrap_or_else(|| panic!("Connectome neuron {} is in unnamed cortical area {}", i, neurons.cortical_areas[i]));
+        payload.extend_from_slice(&neurons.thresholds[i].to_le_bytes());
+        payload.extend_from_slice(&neurons.leak_coefficients[i].to_le_bytes());
+        payload.extend_from_slice(&neurons.resting_potentials[i].to_le_bytes());
+        payload.extend_from_slice(&neurons.excitabilities[i].to_le_bytes());
+        payload.extend_from_slice(&(neurons.refractory_periods[i] as u16).to_le_bytes());
+        payload.extend_from_slice(&(area as u16).to_le_bytes());
+        for axis in 0..3 {
+            payload.extend_from_slice(&(neurons.coordinates[i * 3 + axis] as u16).to_le_bytes());
+        }
+        payload.extend_from_slice(&[0, 0]);
+    }
+    let mut synapse_count = 0usize;
+    for i in (0..synapses.count).filter(|&i| synapses.valid_mask[i]) {
+        let neuron = |id: usize| index.get(id).copied().flatten();
+        let (Some(source), Some(target)) = (neuron(synapses.source_neurons[i] as usize), neuron(synapses.target_neurons[i] as usize)) else {
+            // Dangling: one of its neurons was deleted
+            continue;
+        };
+        payload.extend_from_slice(&source.to_le_bytes());
+        payload.extend_from_slice(&target.to_le_bytes());
+        payload.push(synapses.weights[i]);
+        payload.push(synapses.postsynaptic_potentials[i]);
+        payload.push(synapses.types[i]);
+        payload.push(0);
+        synapse_count += 1;
+    }
+    if synapse_count > max_synapses {
+        panic!("Connectome has {} synapses, {} holds at most {}", synapse_count, model, max_synapses);
+    }
+    
+    let mut bytes = Vec::with_capacity(20 + payload.len());
+    bytes.extend_from_slice(b"FCN1");
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&(area_ids.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(neuron_count as u32).to_le_bytes());
+    bytes.extend_from_slice(&(synapse_count as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    let areas = area_ids.iter().map(|(_, id)| id.to_string()).collect();
+    ConnectomeImage { bytes, neurons: neuron_count, synapses: synapse_count, areas }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,119 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! On-device burst engine
+//!
+//! Each burst integrates the input every neuron received since the last one
+//! (synaptic input from the neurons that fired then, plus sensory
+//! stimulation) into its membrane potential; neurons at threshold fire and
+//! go refractory, the rest leak. feagi-runtime-embedded does the per-neuron
+//! and per-synapse work; this module keeps the buffers in between, the
+//! fired set of the last burst, and paces bursts at BURST_FREQUENCY_HZ.
+//!
+//! Bursts are paced on the FreeRTOS tick, so a burst frequency above the
+//! tick rate (CONFIG_FREERTOS_HZ) runs at the tick rate. A burst that
+//! overruns its period starts the next one right away and is counted.
+
+use esp_idf_svc::sys;
+use feagi_runtime_embedded::{NeuronArray, SynapseArray};
+
+/// Input buffers and fired set of a network of up to N neurons
+pub struct BurstEngine<const N: usize> {
+    /// Input each neuron integrates in the coming burst
+    candidates: [f32; N],
+    /// Neurons that fired in the last burst
+    fired: [bool; N],
+    fired_count: usize,
+    /// Bursts run since boot
+    burst: u64,
+}
+
+impl<const N: usize> BurstEngine<N> {
+    pub const fn new() -> Self {
+        Self { candidates: [0.0; N], fired: [false; N], fired_count: 0, burst: 0 }
+    }
+
+    /// Add input to neuron `index` for the coming burst
+    pub fn stimulate(&mut self, index: usize, amount: f32) {
+        if let Some(candidate) = self.candidates.get_mut(index) {
+            *candidate += amount;
+        }
+    }
+
+    /// Run one burst; how many neurons fired
+    pub fn run<const S: usize>(&mut self, neurons: &mut NeuronArray<N>, synapses: &SynapseArray<S>) -> usize {
+        self.fired = [false; N];
+        self.fired_count = neurons.process_burst(&self.candidates, &mut self.fired);
+        // What the fired neurons pass on is integrated in the next burst
+        self.candidates = [0.0; N];
+        if self.fired_count > 0 {
+            synapses.propagate(&self.fired, &mut self.candidates);
+        }
+        self.burst += 1;
+        self.fired_count
+    }
+
+    /// Did neuron `index` fire in the last burst?
+    pub fn fired(&self, index: usize) -> bool {
+        self.fired.get(index).copied().unwrap_or(false)
+    }
+
+    /// Neurons that fired in the last burst
+    pub fn fired_count(&self) -> usize {
+        self.fired_count
+    }
+
+    /// Bursts run since boot
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+}
+
+/// Paces bursts `1 / frequency` apart, however long each one took
+pub struct Pacer {
+    last_wake: sys::TickType_t,
+    period: sys::TickType_t,
+    /// Bursts that ran past their period
+    overruns: u32,
+}
+
+impl Pacer {
+    pub fn new(frequency_hz: u32) -> Self {
+        Self {
+            last_wake: unsafe { sys::xTaskGetTickCount() },
+            period: (sys::configTICK_RATE_HZ / frequency_hz.max(1)).max(1),
+            overruns: 0,
+        }
+    }
+
+    /// Block until the next burst is due; false if the last one overran
+    /// its period, and the next one starts right away
+    pub fn wait(&mut self) -> bool {
+        let on_time = unsafe { sys::xTaskDelayUntil(&mut self.last_wake, self.period) } != 0;
+        if !on_time {
+            self.overruns = self.overruns.wrapping_add(1);
+            // Don't try to catch up on the periods missed
+            self.last_wake = unsafe { sys::xTaskGetTickCount() };
+        }
+        on_time
+    }
+
+    /// Pace at a new frequency from the next burst on
+    pub fn set_frequency(&mut self, frequency_hz: u32) {
+        self.period = (sys::configTICK_RATE_HZ / frequency_hz.max(1)).max(1);
+    }
+
+    /// Bursts that ran past their period since boot
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Burst period in milliseconds, as paced
+    pub fn period_ms(&self) -> u32 {
+        self.period * 1000 / sys::configTICK_RATE_HZ
+    }
+}
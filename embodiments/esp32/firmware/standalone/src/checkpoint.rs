@@ -0,0 +1,318 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Brain checkpoints in the `brain` flash partition (config.json
+//! `checkpoint`)
+//!
+//! The connectome embedded in the firmware is where the brain starts from
+//! after every power-up; learning only changes the copy in RAM. A checkpoint
+//! saves the synaptic weights and membrane potentials to flash, and at boot
+//! the newest checkpoint taken from the same connectome is loaded on top of
+//! it.
+//!
+//! Checkpoints are taken every `interval_s` (skipped when no weight changed
+//! since the last one) and on `{"checkpoint":1}` from the console (see
+//! console.rs). To spread the wear, the partition is divided into slots of
+//! whole 4 KB sectors and every checkpoint goes to the slot after the last
+//! one's, so each sector is only erased once per round through them all.
+//! Erasing a sector stalls the CPU for tens of milliseconds, so a checkpoint
+//! is written one sector per burst; a burst may still overrun while a
+//! sector is erased. Its header goes in last, so a checkpoint cut short by
+//! a reset is never loaded.
+//!
+//! Slot layout, little-endian:
+//!
+//! | Offset | Size       | Content                                        |
+//! |--------|------------|------------------------------------------------|
+//! | 0      | 4          | `FCK1`                                         |
+//! | 4      | 4          | Sequence number, one up per checkpoint         |
+//! | 8      | 4          | CRC-32 of the connectome image it was taken of |
+//! | 12     | 4          | Neuron count                                   |
+//! | 16     | 4          | Synapse count                                  |
+//! | 20     | 4          | CRC-32 of the rest                             |
+//! | 32     | 1 / synapse | Weight                                        |
+//! |        | 4 / neuron | Membrane potential (f32)                       |
+
+use core::ffi::{c_char, c_void};
+
+use esp_idf_svc::sys;
+use feagi_runtime_embedded::{NeuronArray, SynapseArray};
+
+/// Checkpoint settings (from config.json `checkpoint`)
+#[derive(Debug, Clone, Copy)]
+pub struct CheckpointConfig {
+    /// Time between periodic checkpoints
+    pub interval_s: u32,
+}
+
+const MAGIC: u32 = u32::from_le_bytes(*b"FCK1");
+const PARTITION_LABEL: &[u8] = b"brain\0";
+const PARTITION_SUBTYPE: sys::esp_partition_subtype_t = 0x40;
+const SECTOR_SIZE: usize = 4096;
+const HEADER_SIZE: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct Header {
+    magic: u32,
+    seq: u32,
+    image_crc: u32,
+    neuron_count: u32,
+    synapse_count: u32,
+    payload_crc: u32,
+    reserved: [u32; 2],
+}
+
+/// Where a checkpoint being written got to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// Erasing sector n of the slot
+    Erasing(usize),
+    /// Writing the payload from this offset into it
+    Writing(usize),
+}
+
+/// Checkpoints of one connectome in the `brain` partition
+pub struct Checkpoints {
+    partition: *const sys::esp_partition_t,
+    image_crc: u32,
+    neuron_count: usize,
+    synapse_count: usize,
+    slot_size: usize,
+    slots: usize,
+    /// Sequence number and slot of the last checkpoint (0: none yet)
+    seq: u32,
+    slot: usize,
+    phase: Phase,
+    /// CRC-32 of the payload written so far
+    crc: u32,
+    /// Of the weights last saved or loaded, and of those in the checkpoint
+    /// being written
+    weights_crc: u32,
+    written_weights_crc: u32,
+    interval_us: i64,
+    due_us: i64,
+}
+
+impl Checkpoints {
+    /// Find the partition and the checkpoints taken of the connectome
+    /// image `data`; None, with a note on the console, without a partition
+    /// big enough for one
+    pub fn open(config: &CheckpointConfig, data: &[u8], neuron_count: usize, synapse_count: usize) -> Option<Self> {
+        let partition = unsafe {
+            sys::esp_partition_find_first(
+                sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                PARTITION_SUBTYPE,
+                PARTITION_LABEL.as_ptr() as *const c_char,
+            )
+        };
+        if partition.is_null() {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] No brain partition, checkpoints off\r\n\0".as_ptr() as *const c_char);
+            }
+            return None;
+        }
+        let payload = synapse_count + neuron_count * 4;
+        let slot_size = (HEADER_SIZE + payload).div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+        let slots = unsafe { (*partition).size } as usize / slot_size;
+        if slots == 0 {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Brain partition too small for a checkpoint (%d bytes), checkpoints off\r\n\0".as_ptr()
+                    as *const c_char, slot_size as i32);
+            }
+            return None;
+        }
+        let interval_us = config.interval_s as i64 * 1_000_000;
+        Some(Self {
+            partition,
+            image_crc: u32::from_le_bytes([data[16], data[17], data[18], data[19]]),
+            neuron_count,
+            synapse_count,
+            slot_size,
+            slots,
+            seq: 0,
+            slot: slots - 1,
+            phase: Phase::Idle,
+            crc: 0,
+            weights_crc: 0,
+            written_weights_crc: 0,
+            interval_us,
+            due_us: unsafe { sys::esp_timer_get_time() } + interval_us,
+        })
+    }
+
+    /// Load the newest checkpoint into the arrays, if there's one; its
+    /// sequence number
+    pub fn restore<const N: usize, const S: usize>(&mut self, neurons: &mut NeuronArray<N>, synapses: &mut SynapseArray<S>) -> Option<u32> {
+        let mut newest: Option<(usize, Header)> = None;
+        for slot in 0..self.slots {
+            let header = self.header(slot);
+            let matches = header.magic == MAGIC
+                && header.image_crc == self.image_crc
+                && header.neuron_count as usize == self.neuron_count
+                && header.synapse_count as usize == self.synapse_count;
+            if matches && newest.map_or(true, |(_, h)| header.seq > h.seq) && self.payload_crc(slot) == header.payload_crc {
+                newest = Some((slot, header));
+            }
+        }
+        let (slot, header) = newest?;
+        let base = slot * self.slot_size + HEADER_SIZE;
+        let weights = &mut synapses.weights[..self.synapse_count];
+        self.read(base, weights);
+        let mut potential = [0u8; 4];
+        for (i, v) in neurons.membrane_potentials[..self.neuron_count].iter_mut().enumerate() {
+            self.read(base + self.synapse_count + i * 4, &mut potential);
+            *v = f32::from_le_bytes(potential);
+        }
+        self.seq = header.seq;
+        self.slot = slot;
+        self.weights_crc = crc32(0, weights);
+        Some(header.seq)
+    }
+
+    /// Take a checkpoint now (unless one is being written)
+    pub fn request(&mut self) {
+        if self.phase == Phase::Idle {
+            self.start();
+        }
+    }
+
+    /// Take the next step of a checkpoint being written, or start a
+    /// periodic one that's due; call once per burst. The sequence number of
+    /// a checkpoint just completed
+    pub fn poll<const N: usize, const S: usize>(&mut self, neurons: &NeuronArray<N>, synapses: &SynapseArray<S>) -> Option<u32> {
+        let now_us = unsafe { sys::esp_timer_get_time() };
+        if self.phase == Phase::Idle && now_us >= self.due_us {
+            self.due_us = now_us + self.interval_us;
+            let weights_crc = crc32(0, &synapses.weights[..self.synapse_count]);
+            if weights_crc != self.weights_crc {
+                self.start();
+            }
+        }
+        let next = (self.slot + 1) % self.slots;
+        let base = next * self.slot_size;
+        match self.phase {
+            Phase::Idle => None,
+            Phase::Erasing(sector) => {
+                let offset = base + sector * SECTOR_SIZE;
+                if unsafe { sys::esp_partition_erase_range(self.partition, offset, SECTOR_SIZE) } != sys::ESP_OK {
+                    return self.fail();
+                }
+                self.phase = if (sector + 1) * SECTOR_SIZE < self.slot_size { Phase::Erasing(sector + 1) } else { Phase::Writing(0) };
+                None
+            }
+            Phase::Writing(offset) => {
+                // A sector's worth of weights, then of potentials
+                let mut chunk = [0u8; SECTOR_SIZE];
+                let weights = &synapses.weights[..self.synapse_count];
+                let len = if offset < weights.len() {
+                    let len = (weights.len() - offset).min(SECTOR_SIZE);
+                    chunk[..len].copy_from_slice(&weights[offset..offset + len]);
+                    len
+                } else {
+                    let first = (offset - weights.len()) / 4;
+                    let potentials = &neurons.membrane_potentials[first..self.neuron_count];
+                    let count = potentials.len().min(SECTOR_SIZE / 4);
+                    for (i, v) in potentials[..count].iter().enumerate() {
+                        chunk[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+                    }
+                    count * 4
+                };
+                let at = base + HEADER_SIZE + offset;
+                if unsafe { sys::esp_partition_write(self.partition, at, chunk.as_ptr() as *const c_void, len) } != sys::ESP_OK {
+                    return self.fail();
+                }
+                self.crc = crc32(self.crc, &chunk[..len]);
+                if offset < weights.len() && offset + len == weights.len() {
+                    self.written_weights_crc = self.crc;
+                }
+                let offset = offset + len;
+                if offset < self.synapse_count + self.neuron_count * 4 {
+                    self.phase = Phase::Writing(offset);
+                    return None;
+                }
+                // Last, so a checkpoint cut short is never loaded
+                let header = Header {
+                    magic: MAGIC,
+                    seq: self.seq + 1,
+                    image_crc: self.image_crc,
+                    neuron_count: self.neuron_count as u32,
+                    synapse_count: self.synapse_count as u32,
+                    payload_crc: self.crc,
+                    reserved: [0; 2],
+                };
+                if unsafe { sys::esp_partition_write(self.partition, base, &header as *const Header as *const c_void, HEADER_SIZE) } != sys::ESP_OK {
+                    return self.fail();
+                }
+                self.seq = header.seq;
+                self.slot = next;
+                self.weights_crc = self.written_weights_crc;
+                self.phase = Phase::Idle;
+                Some(self.seq)
+            }
+        }
+    }
+
+    /// Sequence number of the last checkpoint (0: none yet)
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    /// A checkpoint is being written
+    pub fn busy(&self) -> bool {
+        self.phase != Phase::Idle
+    }
+
+    fn start(&mut self) {
+        self.phase = Phase::Erasing(0);
+        self.crc = 0;
+        self.written_weights_crc = 0;
+    }
+
+    fn fail(&mut self) -> Option<u32> {
+        unsafe {
+            sys::esp_rom_printf(b"[FEAGI] Checkpoint write failed\r\n\0".as_ptr() as *const c_char);
+        }
+        self.phase = Phase::Idle;
+        None
+    }
+
+    fn header(&self, slot: usize) -> Header {
+        let mut header = Header::default();
+        unsafe {
+            sys::esp_partition_read(self.partition, slot * self.slot_size, &mut header as *mut Header as *mut c_void, HEADER_SIZE);
+        }
+        header
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) {
+        unsafe {
+            sys::esp_partition_read(self.partition, offset, buf.as_mut_ptr() as *mut c_void, buf.len());
+        }
+    }
+
+    /// CRC-32 of a slot's payload as it is in flash
+    fn payload_crc(&self, slot: usize) -> u32 {
+        let mut chunk = [0u8; 256];
+        let mut crc = 0;
+        let mut offset = 0;
+        let len = self.synapse_count + self.neuron_count * 4;
+        while offset < len {
+            let n = (len - offset).min(chunk.len());
+            self.read(slot * self.slot_size + HEADER_SIZE + offset, &mut chunk[..n]);
+            crc = crc32(crc, &chunk[..n]);
+            offset += n;
+        }
+        crc
+    }
+}
+
+/// zlib's CRC-32, continued from `crc`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    unsafe { sys::esp_rom_crc32_le(crc, data.as_ptr(), data.len() as u32) }
+}
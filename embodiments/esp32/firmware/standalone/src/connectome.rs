@@ -0,0 +1,358 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Embedded connectome loader
+//!
+//! build.rs converts the `.connectome` file named by `brain.path` into a flat
+//! little-endian image, embedded as `CONNECTOME_DATA` (or uploaded later, see
+//! upload.rs, or read from external storage, see storage.rs), which is
+//! checked and loaded into the runtime's neuron and synapse arrays at boot:
+//!
+//! | Offset | Size        | Content                                              |
+//! |--------|-------------|------------------------------------------------------|
+//! | 0      | 4           | `FCN1`                                               |
+//! | 4      | 2           | Format version (2)                                   |
+//! | 6      | 2           | Cortical area count                                  |
+//! | 8      | 4           | Neuron count                                         |
+//! | 12     | 4           | Synapse count                                        |
+//! | 16     | 4           | CRC-32 of everything after the header                |
+//! | 20     | 8 / area    | Cortical ID, NUL-padded                              |
+//! |        | 28 / neuron | threshold, leak, resting potential, excitability (f32), refractory period, area index, x, y, z, padding (u16) |
+//! |        | 8 / synapse | source, target (u16), weight, PSP, type (u8), padding |
+//!
+//! The runtime's arrays only hold what the burst needs; which cortical
+//! area and voxel a neuron belongs to is looked up in the image itself
+//! (`neurons_of`), which stays in flash. The lookups only read up to the
+//! synapse records, so an image streamed in with `load_streamed` only keeps
+//! that much.
+
+use core::ffi::c_char;
+
+use esp_idf_svc::sys;
+use feagi_runtime_embedded::{NeuronArray, SynapseArray};
+use feagi_synapse::SynapseType;
+
+const MAGIC: &[u8; 4] = b"FCN1";
+const VERSION: u16 = 2;
+const HEADER_SIZE: usize = 20;
+const AREA_SIZE: usize = 8;
+const NEURON_SIZE: usize = 28;
+const SYNAPSE_SIZE: usize = 8;
+/// Bytes `load_streamed` reads at a time, whole neuron and synapse records
+const STREAM_CHUNK: usize = 16 * NEURON_SIZE;
+
+/// Why a connectome image was rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadError {
+    /// Shorter than its header says
+    Truncated,
+    /// Not a connectome image
+    BadMagic,
+    /// Written by a newer build.rs
+    UnsupportedVersion(u16),
+    /// Corrupted in flash
+    ChecksumMismatch,
+    /// More neurons than the array holds
+    TooManyNeurons(u32),
+    /// More synapses than the array holds
+    TooManySynapses(u32),
+    /// Neuron with a non-finite parameter, or in an unknown cortical area
+    BadNeuron(u32),
+    /// Synapse to a neuron that doesn't exist, or of an unknown type
+    BadSynapse(u32),
+    /// External storage couldn't be read (see storage.rs)
+    Storage,
+}
+
+impl LoadError {
+    /// Print why on the console
+    pub fn report(&self) {
+        unsafe {
+            match *self {
+                LoadError::Truncated => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: image truncated\r\n\0".as_ptr() as *const c_char);
+                }
+                LoadError::BadMagic => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: not a connectome image\r\n\0".as_ptr() as *const c_char);
+                }
+                LoadError::UnsupportedVersion(v) => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: image version %d, expected %d\r\n\0".as_ptr() as *const c_char,
+                        v as i32, VERSION as i32);
+                }
+                LoadError::ChecksumMismatch => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: checksum mismatch\r\n\0".as_ptr() as *const c_char);
+                }
+                LoadError::TooManyNeurons(n) => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: %d neurons don't fit\r\n\0".as_ptr() as *const c_char, n as i32);
+                }
+                LoadError::TooManySynapses(n) => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: %d synapses don't fit\r\n\0".as_ptr() as *const c_char, n as i32);
+                }
+                LoadError::BadNeuron(i) => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: neuron %d has an invalid parameter\r\n\0".as_ptr() as *const c_char,
+                        i as i32);
+                }
+                LoadError::BadSynapse(i) => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome rejected: synapse %d is invalid\r\n\0".as_ptr() as *const c_char, i as i32);
+                }
+                LoadError::Storage => {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome not loaded: external storage unavailable\r\n\0".as_ptr() as *const c_char);
+                }
+            }
+        }
+    }
+}
+
+/// Where the sections of an image start
+struct Layout {
+    areas: usize,
+    neuron_count: u32,
+    synapse_count: u32,
+    /// Offsets of the neuron and synapse records
+    neurons: usize,
+    synapses: usize,
+}
+
+impl Layout {
+    /// From the header, which must be there
+    fn of(data: &[u8]) -> Self {
+        let areas = u16_at(data, 6) as usize;
+        let neuron_count = u32_at(data, 8);
+        let neurons = HEADER_SIZE + areas * AREA_SIZE;
+        Self {
+            areas,
+            neuron_count,
+            synapse_count: u32_at(data, 12),
+            neurons,
+            synapses: neurons + neuron_count as usize * NEURON_SIZE,
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn f32_at(data: &[u8], offset: usize) -> f32 {
+    f32::from_bits(u32_at(data, offset))
+}
+
+/// Check an image's header and checksum, and that its neurons and synapses
+/// would fit arrays of the given sizes; the neuron and synapse counts
+pub fn check(data: &[u8], max_neurons: usize, max_synapses: usize) -> Result<(u32, u32), LoadError> {
+    if data.len() < HEADER_SIZE {
+        return Err(LoadError::Truncated);
+    }
+    let layout = check_header(data)?;
+    let payload = &data[HEADER_SIZE..];
+    if payload.len() != layout.areas * AREA_SIZE + layout.neuron_count as usize * NEURON_SIZE + layout.synapse_count as usize * SYNAPSE_SIZE {
+        return Err(LoadError::Truncated);
+    }
+    if crc32(0, payload) != u32_at(data, 16) {
+        return Err(LoadError::ChecksumMismatch);
+    }
+    check_counts(&layout, max_neurons, max_synapses)
+}
+
+/// Check the magic and version of a header
+fn check_header(header: &[u8]) -> Result<Layout, LoadError> {
+    if &header[..4] != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let version = u16_at(header, 4);
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    Ok(Layout::of(header))
+}
+
+/// The neuron and synapse counts, if they fit arrays of the given sizes
+fn check_counts(layout: &Layout, max_neurons: usize, max_synapses: usize) -> Result<(u32, u32), LoadError> {
+    if layout.neuron_count as usize > max_neurons {
+        return Err(LoadError::TooManyNeurons(layout.neuron_count));
+    }
+    if layout.synapse_count as usize > max_synapses {
+        return Err(LoadError::TooManySynapses(layout.synapse_count));
+    }
+    Ok((layout.neuron_count, layout.synapse_count))
+}
+
+/// Same CRC-32 as zlib's, continuing from `crc`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    unsafe { sys::esp_rom_crc32_le(crc, data.as_ptr(), data.len() as u32) }
+}
+
+/// Check the image and add its neurons and synapses to the (empty) arrays;
+/// the neuron and synapse counts on success
+pub fn load<const N: usize, const S: usize>(
+    data: &[u8],
+    neurons: &mut NeuronArray<N>,
+    synapses: &mut SynapseArray<S>,
+) -> Result<(u32, u32), LoadError> {
+    let counts = check(data, N, S)?;
+    let layout = Layout::of(data);
+
+    for (i, record) in data[layout.neurons..layout.synapses].chunks_exact(NEURON_SIZE).enumerate() {
+        add_neuron(record, i as u32, &layout, neurons)?;
+    }
+
+    for (i, record) in data[layout.synapses..].chunks_exact(SYNAPSE_SIZE).enumerate() {
+        add_synapse(record, i as u32, &layout, synapses)?;
+    }
+
+    Ok(counts)
+}
+
+/// Load an image `read` hands over piece by piece (filling the whole buffer
+/// it's given, or returning false) into the (empty) arrays, a few records at
+/// a time; the neuron and synapse counts on success
+///
+/// The header, cortical IDs and neuron records, what the lookups below
+/// need, are passed on to `keep` in order as they're read. The checksum
+/// can only be checked at the end, so the arrays may be half-loaded when
+/// it doesn't match.
+pub fn load_streamed<const N: usize, const S: usize>(
+    mut read: impl FnMut(&mut [u8]) -> bool,
+    mut keep: impl FnMut(&[u8]),
+    neurons: &mut NeuronArray<N>,
+    synapses: &mut SynapseArray<S>,
+) -> Result<(u32, u32), LoadError> {
+    let mut chunk = [0u8; STREAM_CHUNK];
+    let header = &mut chunk[..HEADER_SIZE];
+    if !read(header) {
+        return Err(LoadError::Truncated);
+    }
+    let layout = check_header(header)?;
+    let counts = check_counts(&layout, N, S)?;
+    let expected_crc = u32_at(header, 16);
+    keep(header);
+
+    let mut crc = 0;
+    let mut remaining = layout.areas * AREA_SIZE;
+    while remaining > 0 {
+        let part = &mut chunk[..remaining.min(STREAM_CHUNK)];
+        if !read(part) {
+            return Err(LoadError::Truncated);
+        }
+        crc = crc32(crc, part);
+        keep(part);
+        remaining -= part.len();
+    }
+
+    let mut i = 0;
+    while i < layout.neuron_count {
+        let records = ((layout.neuron_count - i) as usize).min(STREAM_CHUNK / NEURON_SIZE);
+        let part = &mut chunk[..records * NEURON_SIZE];
+        if !read(part) {
+            return Err(LoadError::Truncated);
+        }
+        crc = crc32(crc, part);
+        keep(part);
+        for record in part.chunks_exact(NEURON_SIZE) {
+            add_neuron(record, i, &layout, neurons)?;
+            i += 1;
+        }
+    }
+
+    let mut i = 0;
+    while i < layout.synapse_count {
+        let records = ((layout.synapse_count - i) as usize).min(STREAM_CHUNK / SYNAPSE_SIZE);
+        let part = &mut chunk[..records * SYNAPSE_SIZE];
+        if !read(part) {
+            return Err(LoadError::Truncated);
+        }
+        crc = crc32(crc, part);
+        for record in part.chunks_exact(SYNAPSE_SIZE) {
+            add_synapse(record, i, &layout, synapses)?;
+            i += 1;
+        }
+    }
+
+    if crc != expected_crc {
+        return Err(LoadError::ChecksumMismatch);
+    }
+    Ok(counts)
+}
+
+/// Check neuron record `i` and add it to the array
+fn add_neuron<const N: usize>(record: &[u8], i: u32, layout: &Layout, neurons: &mut NeuronArray<N>) -> Result<(), LoadError> {
+    let threshold = f32_at(record, 0);
+    let leak = f32_at(record, 4);
+    let resting = f32_at(record, 8);
+    let excitability = f32_at(record, 12);
+    let refractory = u16_at(record, 16);
+    let area = u16_at(record, 18);
+    if ![threshold, leak, resting, excitability].iter().all(|v| v.is_finite())
+        || !(0.0..=1.0).contains(&leak)
+        || area as usize >= layout.areas
+    {
+        return Err(LoadError::BadNeuron(i));
+    }
+    if neurons.add_neuron(threshold, leak, resting, excitability, refractory, area as u32).is_none() {
+        return Err(LoadError::TooManyNeurons(layout.neuron_count));
+    }
+    Ok(())
+}
+
+/// Check synapse record `i` and add it to the array
+fn add_synapse<const S: usize>(record: &[u8], i: u32, layout: &Layout, synapses: &mut SynapseArray<S>) -> Result<(), LoadError> {
+    let source = u16_at(record, 0);
+    let target = u16_at(record, 2);
+    let synapse_type = match record[6] {
+        0 => SynapseType::Excitatory,
+        1 => SynapseType::Inhibitory,
+        _ => return Err(LoadError::BadSynapse(i)),
+    };
+    if source as u32 >= layout.neuron_count || target as u32 >= layout.neuron_count {
+        return Err(LoadError::BadSynapse(i));
+    }
+    if synapses.add_synapse(source, target, record[4], record[5], synapse_type).is_none() {
+        return Err(LoadError::TooManySynapses(layout.synapse_count));
+    }
+    Ok(())
+}
+
+/// Indices of the neurons of cortical area `area` (all of them, or only
+/// those of voxel `voxel`) in an image `load` accepted
+pub fn neurons_of<'a>(data: &'a [u8], area: &str, voxel: Option<(u16, u16, u16)>) -> impl Iterator<Item = u16> + 'a {
+    let layout = Layout::of(data);
+    let area = (0..layout.areas).find(|&i| area_id(data, i) == area);
+    data[layout.neurons..layout.synapses]
+        .chunks_exact(NEURON_SIZE)
+        .enumerate()
+        .filter(move |(_, record)| {
+            area == Some(u16_at(record, 18) as usize)
+                && voxel.map_or(true, |(x, y, z)| (u16_at(record, 20), u16_at(record, 22), u16_at(record, 24)) == (x, y, z))
+        })
+        .map(|(i, _)| i as u16)
+}
+
+/// Firing threshold of neuron `index` in an image `load` accepted
+pub fn threshold(data: &[u8], index: u16) -> f32 {
+    f32_at(data, Layout::of(data).neurons + index as usize * NEURON_SIZE)
+}
+
+/// Cortical areas in an image `load` accepted
+pub fn area_count(data: &[u8]) -> usize {
+    Layout::of(data).areas
+}
+
+/// Cortical ID of area `area` in an image `load` accepted
+pub fn area_id(data: &[u8], area: usize) -> &str {
+    let field = &data[HEADER_SIZE + area * AREA_SIZE..HEADER_SIZE + (area + 1) * AREA_SIZE];
+    let len = field.iter().position(|&b| b == 0).unwrap_or(AREA_SIZE);
+    core::str::from_utf8(&field[..len]).unwrap_or("")
+}
+
+/// Cortical area of neuron `index` in an image `load` accepted
+pub fn area_of(data: &[u8], index: u16) -> u16 {
+    u16_at(data, Layout::of(data).neurons + index as usize * NEURON_SIZE + 18)
+}
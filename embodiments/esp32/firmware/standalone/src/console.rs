@@ -0,0 +1,232 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Command lines on the serial console
+//!
+//! The board's USB serial port (UART0) carries the boot log and also takes
+//! one-line JSON commands from a host tool, read without blocking between
+//! bursts; answers go back on the same port:
+//!
+//! ```text
+//! host:  {"checkpoint":1}
+//! board: {"checkpoint_ack":{"ok":true,"seq":12}}
+//! ```
+//!
+//! With a `wifi` block the same commands also come in on the WiFi command
+//! port (see wifi.rs). Answers, and hybrid mode's summaries, go to where
+//! the last command came from, or to the port's client from when it
+//! connects: the port while its client stays connected, the serial port
+//! otherwise.
+//!
+//! Commands are matched on their exact text; the `field_*` functions pick
+//! the values out of those that carry some.
+
+use core::ffi::c_void;
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::wifi::CommandPort;
+
+const PORT: sys::uart_port_t = 0;
+const BAUD_RATE: i32 = 115200;
+const RX_BUFFER: i32 = 1024;
+/// Lines sent are queued here rather than waited out (hybrid.rs sends a
+/// few a second)
+const TX_BUFFER: i32 = 2048;
+
+/// Longest command line kept, room for an upload chunk (see upload.rs);
+/// longer ones are dropped
+pub const LINE_CAPACITY: usize = 384;
+
+/// Where a command came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Channel {
+    Serial,
+    Network,
+}
+
+/// A line being received on one channel
+struct LineBuffer {
+    line: Vec<u8, LINE_CAPACITY>,
+    /// Dropping the rest of an overlong line
+    overflowed: bool,
+}
+
+impl LineBuffer {
+    const fn new() -> Self {
+        Self { line: Vec::new(), overflowed: false }
+    }
+
+    /// Add a received byte; the line it completed, unless that was empty or
+    /// too long
+    fn push(&mut self, byte: u8) -> Option<Vec<u8, LINE_CAPACITY>> {
+        match byte {
+            b'\n' => {
+                let overflowed = core::mem::replace(&mut self.overflowed, false);
+                let line = core::mem::take(&mut self.line);
+                (!overflowed && !line.is_empty()).then_some(line)
+            }
+            b'\r' => None,
+            _ => {
+                if self.line.push(byte).is_err() {
+                    self.line.clear();
+                    self.overflowed = true;
+                }
+                None
+            }
+        }
+    }
+}
+
+/// UART0 driver, the WiFi command port and the lines being received
+pub struct Console {
+    serial: LineBuffer,
+    network: Option<(CommandPort, LineBuffer)>,
+    /// Where the answers go
+    reply_to: Channel,
+    /// A client connected to the command port since `take_joined`
+    joined: bool,
+    /// The last complete line, valid until the next `poll`
+    complete: Vec<u8, LINE_CAPACITY>,
+}
+
+impl Console {
+    /// Install the UART0 driver; None if it couldn't be
+    pub fn open() -> Option<Self> {
+        unsafe {
+            let config = sys::uart_config_t {
+                baud_rate: BAUD_RATE,
+                data_bits: sys::uart_word_length_t_UART_DATA_8_BITS,
+                parity: sys::uart_parity_t_UART_PARITY_DISABLE,
+                stop_bits: sys::uart_stop_bits_t_UART_STOP_BITS_1,
+                flow_ctrl: sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_DISABLE,
+                ..Default::default()
+            };
+            if sys::uart_param_config(PORT, &config) != sys::ESP_OK {
+                return None;
+            }
+            if sys::uart_driver_install(PORT, RX_BUFFER, TX_BUFFER, 0, core::ptr::null_mut(), 0) != sys::ESP_OK {
+                return None;
+            }
+        }
+        Some(Self { serial: LineBuffer::new(), network: None, reply_to: Channel::Serial, joined: false, complete: Vec::new() })
+    }
+
+    /// Also take commands on the WiFi command port
+    pub fn attach(&mut self, port: CommandPort) {
+        self.network = Some((port, LineBuffer::new()));
+    }
+
+    /// The next complete command line received, if any; never blocks
+    pub fn poll(&mut self) -> Option<&str> {
+        let mut byte = 0u8;
+        let mut line = None;
+        while line.is_none() && unsafe { sys::uart_read_bytes(PORT, &mut byte as *mut u8 as *mut c_void, 1, 0) } == 1 {
+            line = self.serial.push(byte).map(|line| (line, Channel::Serial));
+        }
+        if let Some((port, buffer)) = self.network.as_mut() {
+            if port.accept() {
+                // A new client starts with a new line, and gets the answers
+                *buffer = LineBuffer::new();
+                self.reply_to = Channel::Network;
+                self.joined = true;
+            }
+            while line.is_none() {
+                let Some(byte) = port.read_byte() else {
+                    break;
+                };
+                line = buffer.push(byte).map(|line| (line, Channel::Network));
+            }
+        }
+        let (line, channel) = line?;
+        self.complete = line;
+        self.reply_to = channel;
+        core::str::from_utf8(&self.complete).ok().map(str::trim)
+    }
+
+    /// Did a client connect to the command port since the last call?
+    pub fn take_joined(&mut self) -> bool {
+        core::mem::take(&mut self.joined)
+    }
+
+    /// Send a line (newline included) to where the last command came from
+    pub fn send(&mut self, line: &[u8]) {
+        if self.reply_to == Channel::Network {
+            if let Some((port, _)) = self.network.as_mut() {
+                if port.write(line) {
+                    return;
+                }
+            }
+            // The client is gone: back to the serial port
+            self.reply_to = Channel::Serial;
+        }
+        unsafe {
+            sys::uart_write_bytes(PORT, line.as_ptr() as *const c_void, line.len());
+        }
+    }
+
+    /// Wait (up to 100 ms) until what was sent is out, before a restart
+    pub fn flush(&mut self) {
+        if self.reply_to == Channel::Network {
+            // lwIP sends on its own; give it the time
+            FreeRtos::delay_ms(100);
+            return;
+        }
+        unsafe {
+            sys::uart_wait_tx_done(PORT, 100 * sys::configTICK_RATE_HZ / 1000);
+        }
+    }
+}
+
+/// The unsigned number after `"key":` in a command line
+pub fn field_u32(line: &str, key: &str) -> Option<u32> {
+    let value = after_key(line, key)?;
+    let end = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// The number after `"key":` in a command line
+pub fn field_f32(line: &str, key: &str) -> Option<f32> {
+    let value = after_key(line, key)?;
+    let end = value.find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))).unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// The `true` or `false` after `"key":` in a command line
+pub fn field_bool(line: &str, key: &str) -> Option<bool> {
+    let value = after_key(line, key)?;
+    if value.starts_with("true") {
+        Some(true)
+    } else if value.starts_with("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The string after `"key":` in a command line, without its quotes (and
+/// without unescaping, the commands don't need it)
+pub fn field_str<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = after_key(line, key)?.strip_prefix('"')?;
+    Some(&value[..value.find('"')?])
+}
+
+fn after_key<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let mut rest = line;
+    loop {
+        let at = rest.find(key)?;
+        let before = &rest[..at];
+        rest = &rest[at + key.len()..];
+        if before.ends_with('"') {
+            if let Some(value) = rest.strip_prefix("\":") {
+                return Some(value.trim_start());
+            }
+        }
+    }
+}
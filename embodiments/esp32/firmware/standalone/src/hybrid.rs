@@ -0,0 +1,185 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Hybrid mode (config.json `"mode": "hybrid"`)
+//!
+//! The connectome runs on the board just as in standalone mode, and a FEAGI
+//! host on the serial console or the WiFi command port (see console.rs)
+//! follows its activity and tunes it. Board → host, a hello at boot (again
+//! on `{"hello":1}`, and to each client that connects to the port) naming
+//! the cortical areas, then `telemetry_hz` times a second a summary of the
+//! bursts since the last one:
+//!
+//! ```text
+//! {"hello":"esp32","mode":"hybrid","neurons":1200,"synapses":5400,"burst_hz":100,"telemetry_hz":10,"areas":["ibtn00","omot00"]}
+//! {"act":{"b":1530,"n":10,"f":[12,3],"ov":0}}
+//! ```
+//!
+//! `b` is the bursts run since boot, `n` the bursts summed up, `f` how many
+//! times neurons of each area fired in them (in the hello's order, up to
+//! `MAX_AREAS` areas) and `ov` the bursts that overran their period.
+//!
+//! Host → board, parameter tweaks, answered with `{"set_ack":{"ok":true}}`
+//! or `"ok":false` and what was wrong; they hold until the next restart:
+//!
+//! `{"set":{"burst_frequency":50}}`, `{"set":{"telemetry_hz":5}}`,
+//! `{"set":{"pin":34,"scale":0.5}}`, `{"set":{"pin":25,"decay":0.8}}`,
+//! `{"set":{"pin":26,"hold_ms":200}}`
+//!
+//! The boot log shares the serial port; its lines don't start with `{`.
+
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+
+use crate::burst::BurstEngine;
+use crate::connectome;
+use crate::console::{field_f32, field_u32, Console};
+
+/// Hybrid mode settings (from config.json `hybrid`)
+#[derive(Debug, Clone, Copy)]
+pub struct HybridConfig {
+    /// Activity summaries a second
+    pub telemetry_hz: u32,
+}
+
+/// Cortical areas summed up; those past it are left out of the summaries
+pub const MAX_AREAS: usize = 64;
+
+/// A parameter tweak from the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tweak {
+    BurstFrequency(u32),
+    TelemetryHz(u32),
+    /// Pin, new `scale` of the input
+    Scale(u32, f32),
+    /// Pin, new `decay` of the output
+    Decay(u32, f32),
+    /// Pin, new `hold_ms` of the output
+    HoldMs(u32, u32),
+}
+
+impl Tweak {
+    /// Parse a `{"set":{...}}` line; None if it isn't one, Err with what's
+    /// wrong with it if it isn't valid
+    pub fn parse(line: &str) -> Option<Result<Self, &'static str>> {
+        if !line.starts_with("{\"set\":") {
+            return None;
+        }
+        Some(Self::parse_set(line))
+    }
+
+    fn parse_set(line: &str) -> Result<Self, &'static str> {
+        // Same ranges as build.rs takes
+        if let Some(hz) = field_u32(line, "burst_frequency") {
+            return if (1..=1000).contains(&hz) { Ok(Tweak::BurstFrequency(hz)) } else { Err("burst_frequency") };
+        }
+        if let Some(hz) = field_u32(line, "telemetry_hz") {
+            return if (1..=1000).contains(&hz) { Ok(Tweak::TelemetryHz(hz)) } else { Err("telemetry_hz") };
+        }
+        let pin = field_u32(line, "pin").ok_or("pin")?;
+        if let Some(scale) = field_f32(line, "scale") {
+            return if (0.0..=10.0).contains(&scale) { Ok(Tweak::Scale(pin, scale)) } else { Err("scale") };
+        }
+        if let Some(decay) = field_f32(line, "decay") {
+            return if (0.0..=1.0).contains(&decay) { Ok(Tweak::Decay(pin, decay)) } else { Err("decay") };
+        }
+        if let Some(hold_ms) = field_u32(line, "hold_ms") {
+            return if hold_ms <= 60000 { Ok(Tweak::HoldMs(pin, hold_ms)) } else { Err("hold_ms") };
+        }
+        Err("parameter")
+    }
+}
+
+/// `{"set_ack":...}` line answering a tweak
+pub fn set_ack(result: Result<(), &str>) -> String<64> {
+    let mut line = String::new();
+    let _ = match result {
+        Ok(()) => writeln!(line, "{{\"set_ack\":{{\"ok\":true}}}}"),
+        Err(error) => writeln!(line, "{{\"set_ack\":{{\"ok\":false,\"error\":\"{}\"}}}}", error),
+    };
+    line
+}
+
+/// Activity summed up between summaries
+pub struct Uplink {
+    /// Firings per cortical area since the last summary
+    fired: Vec<u32, MAX_AREAS>,
+    neuron_count: usize,
+    synapse_count: usize,
+    bursts: u32,
+    burst_hz: u32,
+    telemetry_hz: u32,
+}
+
+impl Uplink {
+    /// For the loaded connectome image `data` (empty if there's none)
+    pub fn new(config: &HybridConfig, data: &[u8], neuron_count: usize, synapse_count: usize, burst_hz: u32) -> Self {
+        let areas = if data.is_empty() { 0 } else { connectome::area_count(data).min(MAX_AREAS) };
+        let mut fired = Vec::new();
+        let _ = fired.resize(areas, 0);
+        Self { fired, neuron_count, synapse_count, bursts: 0, burst_hz, telemetry_hz: config.telemetry_hz }
+    }
+
+    /// Send the hello
+    pub fn hello(&self, console: &mut Console, data: &[u8]) {
+        let mut line: String<160> = String::new();
+        let _ = write!(
+            line,
+            "{{\"hello\":\"esp32\",\"mode\":\"hybrid\",\"neurons\":{},\"synapses\":{},\"burst_hz\":{},\"telemetry_hz\":{},\"areas\":[",
+            self.neuron_count, self.synapse_count, self.burst_hz, self.telemetry_hz
+        );
+        console.send(line.as_bytes());
+        for area in 0..self.fired.len() {
+            if area > 0 {
+                console.send(b",");
+            }
+            console.send(b"\"");
+            console.send(connectome::area_id(data, area).as_bytes());
+            console.send(b"\"");
+        }
+        console.send(b"]}\n");
+    }
+
+    /// Burst frequency in effect, which summaries are spread over
+    pub fn set_burst_frequency(&mut self, hz: u32) {
+        self.burst_hz = hz;
+    }
+
+    pub fn set_telemetry_hz(&mut self, hz: u32) {
+        self.telemetry_hz = hz;
+    }
+
+    /// Count the firings of the burst that just ran, and send the summary
+    /// when it's due
+    pub fn record<const N: usize>(&mut self, engine: &BurstEngine<N>, data: &[u8], overruns: u32, console: &mut Console) {
+        if engine.fired_count() > 0 {
+            for index in (0..self.neuron_count).filter(|&i| engine.fired(i)) {
+                if let Some(count) = self.fired.get_mut(connectome::area_of(data, index as u16) as usize) {
+                    *count += 1;
+                }
+            }
+        }
+        self.bursts += 1;
+        if self.bursts < (self.burst_hz / self.telemetry_hz).max(1) {
+            return;
+        }
+        let mut line: String<48> = String::new();
+        let _ = write!(line, "{{\"act\":{{\"b\":{},\"n\":{},\"f\":[", engine.burst(), self.bursts);
+        console.send(line.as_bytes());
+        for (area, count) in self.fired.iter_mut().enumerate() {
+            line.clear();
+            let _ = write!(line, "{}{}", if area > 0 { "," } else { "" }, count);
+            console.send(line.as_bytes());
+            *count = 0;
+        }
+        line.clear();
+        let _ = writeln!(line, "],\"ov\":{}}}}}", overruns);
+        console.send(line.as_bytes());
+        self.bursts = 0;
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Sensory injection from GPIO inputs
+//!
+//! At the start of every burst each digital and analog input stimulates the
+//! neurons its `cortical_mapping` names (see mapping.rs). A stimulus is a
+//! fraction of the neuron's firing threshold: `scale` (1.0 by default, so
+//! enough to fire it) for a digital input that reads high, nothing when it
+//! reads low, and `scale` times the reading (0.0-1.0 over the 0-3.3 V range)
+//! for an analog input.
+//!
+//! Analog inputs use ADC1 (GPIO 32-39); ADC2 pins are skipped, like pins
+//! whose mapping names no neuron of the connectome.
+
+use core::ffi::c_char;
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::burst::BurstEngine;
+use crate::connectome;
+use crate::mapping::{Mappings, Span};
+use crate::GpioPinConfig;
+
+/// Inputs kept; as many as the GPIO config has room for
+const MAX_INPUTS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Digital(i32),
+    Analog(sys::adc_channel_t),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Input {
+    pin: u32,
+    kind: Kind,
+    neurons: Span,
+    scale: f32,
+}
+
+/// Digital and analog inputs, and the neurons they stimulate
+pub struct InputBank {
+    inputs: Vec<Input, MAX_INPUTS>,
+    /// ADC1 oneshot unit, if there are analog inputs
+    adc: sys::adc_oneshot_unit_handle_t,
+}
+
+impl InputBank {
+    /// An empty bank, for running without a connectome
+    pub fn new() -> Self {
+        Self { inputs: Vec::new(), adc: core::ptr::null_mut() }
+    }
+
+    /// Set up the pins and look up their neurons in the loaded connectome
+    /// image `data`
+    pub fn from_config<const P: usize>(
+        digital: &[&GpioPinConfig],
+        analog: &[&GpioPinConfig],
+        data: &[u8],
+        mappings: &mut Mappings<P>,
+    ) -> Self {
+        let mut bank = Self::new();
+        for config in digital {
+            let Some(neurons) = mappings.resolve_pin(data, config) else {
+                continue;
+            };
+            unsafe {
+                sys::gpio_reset_pin(config.pin as i32);
+                sys::gpio_set_direction(config.pin as i32, sys::gpio_mode_t_GPIO_MODE_INPUT);
+            }
+            let _ = bank.inputs.push(Input { pin: config.pin, kind: Kind::Digital(config.pin as i32), neurons, scale: config.scale });
+        }
+        for config in analog {
+            let Some(neurons) = mappings.resolve_pin(data, config) else {
+                continue;
+            };
+            let Some(channel) = bank.adc_channel(config.pin) else {
+                unsafe {
+                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: not an ADC1 pin, analog input skipped\r\n\0".as_ptr() as *const c_char,
+                        config.pin as i32);
+                }
+                continue;
+            };
+            let _ = bank.inputs.push(Input { pin: config.pin, kind: Kind::Analog(channel), neurons, scale: config.scale });
+        }
+        bank
+    }
+
+    /// Read every input and stimulate its neurons for the coming burst
+    pub fn inject<const N: usize, const P: usize>(&mut self, engine: &mut BurstEngine<N>, data: &[u8], mappings: &Mappings<P>) {
+        for input in self.inputs.iter() {
+            let level = match input.kind {
+                Kind::Digital(pin) => {
+                    if unsafe { sys::gpio_get_level(pin) } == 0 {
+                        continue;
+                    }
+                    1.0
+                }
+                Kind::Analog(channel) => {
+                    let mut raw: i32 = 0;
+                    if unsafe { sys::adc_oneshot_read(self.adc, channel, &mut raw) } != sys::ESP_OK {
+                        continue;
+                    }
+                    raw.clamp(0, 4095) as f32 / 4095.0
+                }
+            };
+            let amount = level * input.scale;
+            if amount <= 0.0 {
+                continue;
+            }
+            for &index in mappings.neurons(input.neurons) {
+                engine.stimulate(index as usize, amount * connectome::threshold(data, index));
+            }
+        }
+    }
+
+    /// Change the `scale` of the input on `pin`; false if there's none
+    pub fn set_scale(&mut self, pin: u32, scale: f32) -> bool {
+        let Some(input) = self.inputs.iter_mut().find(|input| input.pin == pin) else {
+            return false;
+        };
+        input.scale = scale;
+        true
+    }
+
+    /// Configure `pin` as a 12-bit ADC1 input over the full 0-3.3 V range,
+    /// creating the unit on first use
+    fn adc_channel(&mut self, pin: u32) -> Option<sys::adc_channel_t> {
+        unsafe {
+            let mut unit: sys::adc_unit_t = 0;
+            let mut channel: sys::adc_channel_t = 0;
+            if sys::adc_oneshot_io_to_channel(pin as i32, &mut unit, &mut channel) != sys::ESP_OK || unit != sys::adc_unit_t_ADC_UNIT_1 {
+                return None;
+            }
+            if self.adc.is_null() {
+                let init_config = sys::adc_oneshot_unit_init_cfg_t {
+                    unit_id: sys::adc_unit_t_ADC_UNIT_1,
+                    ulp_mode: sys::adc_ulp_mode_t_ADC_ULP_MODE_DISABLE,
+                    ..Default::default()
+                };
+                if sys::adc_oneshot_new_unit(&init_config, &mut self.adc) != sys::ESP_OK {
+                    self.adc = core::ptr::null_mut();
+                    return None;
+                }
+            }
+            let chan_config = sys::adc_oneshot_chan_cfg_t {
+                atten: sys::adc_atten_t_ADC_ATTEN_DB_11,
+                bitwidth: sys::adc_bitwidth_t_ADC_BITWIDTH_12,
+            };
+            if sys::adc_oneshot_config_channel(self.adc, channel, &chan_config) != sys::ESP_OK {
+                return None;
+            }
+            Some(channel)
+        }
+    }
+}
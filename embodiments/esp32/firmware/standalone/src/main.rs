@@ -15,6 +15,35 @@
 
 use esp_idf_svc::sys;
 use core::ffi::{c_char, CStr};
+use core::fmt::Write;
+use core::ptr::addr_of_mut;
+
+mod burst;
+mod checkpoint;
+mod connectome;
+mod console;
+mod hybrid;
+mod inputs;
+mod mapping;
+mod outputs;
+mod plasticity;
+mod raster;
+mod storage;
+mod upload;
+mod wifi;
+
+use burst::{BurstEngine, Pacer};
+use checkpoint::{CheckpointConfig, Checkpoints};
+use console::Console;
+use hybrid::{set_ack, HybridConfig, Tweak, Uplink};
+use inputs::InputBank;
+use mapping::Mappings;
+use outputs::OutputBank;
+use plasticity::{Plasticity, PlasticityConfig};
+use raster::{Raster, RasterConfig, RasterSink};
+use storage::{Medium, StorageConfig};
+use upload::{Ack, Next, Upload};
+use wifi::WifiConfig;
 
 // Platform abstraction
 use feagi_embedded::prelude::*;
@@ -28,7 +57,6 @@ use feagi_types::INT8Value;
 use esp_idf_svc::hal::{
     gpio::PinDriver,
     peripherals::Peripherals,
-    delay::FreeRtos,
 };
 use heapless::Vec;
 
@@ -50,8 +78,26 @@ pub struct GpioPinConfig {
     pub pin: u32,
     pub mode: GpioMode,
     pub cortical_mapping: &'static str,
+    /// Stimulus of an input, as a fraction of its neurons' threshold
+    pub scale: f32,
+    /// Per-burst falloff of an output's level
+    pub decay: f32,
+    /// Least time a digital output stays in a state
+    pub hold_ms: u32,
 }
 
+// The embedded brain, sized by build.rs to fit the connectome; static to
+// keep it off the main task's stack
+static mut NEURONS: NeuronArray<MAX_NEURONS> = NeuronArray::new();
+static mut SYNAPSES: SynapseArray<MAX_SYNAPSES> = SynapseArray::new();
+static mut ENGINE: BurstEngine<MAX_NEURONS> = BurstEngine::new();
+// Neurons of every mapped pin (see mapping.rs)
+static mut MAPPINGS: Mappings<MAX_NEURONS> = Mappings::new();
+// Weight learning, configured by PLASTICITY (see plasticity.rs)
+static mut LEARNING: Plasticity<MAX_NEURONS> = Plasticity::new();
+// Spike frames on a second UART or over WiFi, opened by RASTER (see raster.rs)
+static mut SPIKES: Raster<MAX_NEURONS> = Raster::new();
+
 fn main() -> anyhow::Result<()> {
     // Initialize ESP-IDF
     unsafe {
@@ -85,36 +131,36 @@ fn main() -> anyhow::Result<()> {
     // We'll store pin drivers in arrays based on mode
     // Note: This is a simplified implementation - in production, you'd use a more sophisticated pin management system
     
-    let mut digital_inputs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut digital_outputs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut analog_inputs: Vec<(u32, &'static str), 32> = Vec::new();
-    let mut pwm_outputs: Vec<(u32, &'static str), 32> = Vec::new();
+    let mut digital_inputs: Vec<&'static GpioPinConfig, 32> = Vec::new();
+    let mut digital_outputs: Vec<&'static GpioPinConfig, 32> = Vec::new();
+    let mut analog_inputs: Vec<&'static GpioPinConfig, 32> = Vec::new();
+    let mut pwm_outputs: Vec<&'static GpioPinConfig, 32> = Vec::new();
     
     for gpio_config in GPIO_CONFIG {
         match gpio_config.mode {
             GpioMode::DigitalInput => {
-                let _ = digital_inputs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                let _ = digital_inputs.push(gpio_config);
                 unsafe {
                     sys::esp_rom_printf(b"[FEAGI] GPIO %d: Digital Input -> %s\r\n\0".as_ptr() as *const c_char,
                         gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
                 }
             }
             GpioMode::DigitalOutput => {
-                let _ = digital_outputs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                let _ = digital_outputs.push(gpio_config);
                 unsafe {
                     sys::esp_rom_printf(b"[FEAGI] GPIO %d: Digital Output -> %s\r\n\0".as_ptr() as *const c_char,
                         gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
                 }
             }
             GpioMode::AnalogInput => {
-                let _ = analog_inputs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                let _ = analog_inputs.push(gpio_config);
                 unsafe {
                     sys::esp_rom_printf(b"[FEAGI] GPIO %d: Analog Input -> %s\r\n\0".as_ptr() as *const c_char,
                         gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
                 }
             }
             GpioMode::PwmOutput => {
-                let _ = pwm_outputs.push((gpio_config.pin, gpio_config.cortical_mapping));
+                let _ = pwm_outputs.push(gpio_config);
                 unsafe {
                     sys::esp_rom_printf(b"[FEAGI] GPIO %d: PWM Output -> %s\r\n\0".as_ptr() as *const c_char,
                         gpio_config.pin as i32, gpio_config.cortical_mapping.as_ptr() as *const c_char);
@@ -128,24 +174,130 @@ fn main() -> anyhow::Result<()> {
         sys::esp_rom_printf(b"[FEAGI] GPIO configuration complete\r\n\0".as_ptr() as *const c_char);
     }
     
+    // WiFi station and command port (see wifi.rs), up before the raster's
+    // UDP socket is opened
+    let command_port = WIFI.and_then(|config| {
+        let port = wifi::start(&config)?;
+        unsafe {
+            sys::esp_rom_printf(b"[FEAGI] Joining WiFi, commands on TCP port %d\r\n\0".as_ptr() as *const c_char, config.port as i32);
+        }
+        Some(port)
+    });
+    
     // Initialize FEAGI embedded runtime
-    unsafe {
-        if HAS_CONNECTOME {
-            sys::esp_rom_printf(b"[FEAGI] Loading embedded connectome (%d bytes)\r\n\0".as_ptr() as *const c_char,
-                CONNECTOME_DATA.len() as i32);
-            
-            // Deserialize connectome from embedded data
-            // TODO: Use feagi-connectome-serialization::load_connectome_from_bytes when available
-            // For now, we'll parse it manually or use a placeholder
-            // The connectome data is embedded as a static byte array at build time
-            
-            sys::esp_rom_printf(b"[FEAGI] Connectome loaded successfully\r\n\0".as_ptr() as *const c_char);
-            sys::esp_rom_printf(b"[FEAGI] Initializing neural network from connectome...\r\n\0".as_ptr() as *const c_char);
-            
-            // TODO: Initialize NeuronArray and SynapseArray from connectome data
+    let mut connectome_rejected = false;
+    let mut inputs = InputBank::new();
+    let mut outputs = OutputBank::new();
+    let mut checkpoints = None;
+    let mut loaded = None;
+    // An uploaded connectome takes the embedded one's place (see upload.rs)
+    let mut upload = if UPLOAD { Upload::open(MAX_NEURONS, MAX_SYNAPSES) } else { None };
+    let uploaded = upload.as_mut().and_then(|u| u.stored());
+    let mut brain = uploaded.unwrap_or(CONNECTOME_DATA);
+    // Or it's on external storage, streamed in (see storage.rs)
+    let external = STORAGE.filter(|_| uploaded.is_none());
+    if uploaded.is_some() || HAS_CONNECTOME || external.is_some() {
+        let (neurons, synapses) = unsafe { (&mut *addr_of_mut!(NEURONS), &mut *addr_of_mut!(SYNAPSES)) };
+        let result = if let Some(config) = external {
+            let source: &[u8] = match config.medium {
+                Medium::SdCard { .. } => b"SD card\0",
+                Medium::SpiFlash { .. } => b"SPI flash\0",
+            };
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Loading connectome from %s\r\n\0".as_ptr() as *const c_char,
+                    source.as_ptr() as *const c_char);
+            }
+            storage::load(&config, neurons, synapses).map(|(index, neuron_count, synapse_count)| {
+                brain = index;
+                (neuron_count, synapse_count)
+            })
         } else {
+            let source: &[u8] = if uploaded.is_some() { b"uploaded\0" } else { b"embedded\0" };
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Loading %s connectome (%d bytes)\r\n\0".as_ptr() as *const c_char,
+                    source.as_ptr() as *const c_char, brain.len() as i32);
+            }
+            
+            // Checked and loaded into the neuron and synapse arrays (see connectome.rs)
+            connectome::load(brain, neurons, synapses)
+        };
+        match result {
+            Ok((neuron_count, synapse_count)) => {
+                unsafe {
+                    sys::esp_rom_printf(b"[FEAGI] Connectome loaded: %d neurons, %d synapses\r\n\0".as_ptr() as *const c_char,
+                        neuron_count as i32, synapse_count as i32);
+                }
+                loaded = Some((neuron_count as usize, synapse_count as usize));
+                if let Some(config) = PLASTICITY {
+                    let learning = unsafe { &mut *addr_of_mut!(LEARNING) };
+                    learning.configure(&config, brain, neuron_count as usize, synapse_count as usize);
+                }
+                if let Some(config) = RASTER {
+                    let spikes = unsafe { &mut *addr_of_mut!(SPIKES) };
+                    let opened = match config.sink {
+                        // No network stack to send on
+                        RasterSink::Udp { .. } if command_port.is_none() => false,
+                        _ => spikes.open(&config, neuron_count as usize),
+                    };
+                    unsafe {
+                        match (config.sink, opened) {
+                            (RasterSink::Uart { uart, tx_pin, baud_rate }, true) => {
+                                sys::esp_rom_printf(b"[FEAGI] Spike raster on UART%d, GPIO %d at %d baud\r\n\0".as_ptr() as *const c_char,
+                                    uart as i32, tx_pin, baud_rate as i32);
+                            }
+                            (RasterSink::Uart { uart, .. }, false) => {
+                                sys::esp_rom_printf(b"[FEAGI] Spike raster UART%d failed to start\r\n\0".as_ptr() as *const c_char, uart as i32);
+                            }
+                            (RasterSink::Udp { host, port }, true) => {
+                                sys::esp_rom_printf(b"[FEAGI] Spike raster to UDP %d.%d.%d.%d:%d\r\n\0".as_ptr() as *const c_char,
+                                    host[0] as i32, host[1] as i32, host[2] as i32, host[3] as i32, port as i32);
+                            }
+                            (RasterSink::Udp { .. }, false) => {
+                                sys::esp_rom_printf(b"[FEAGI] Spike raster over UDP failed to start (WiFi down or no socket)\r\n\0".as_ptr() as *const c_char);
+                            }
+                        }
+                    }
+                }
+                // Inputs stimulate the neurons they're mapped to, outputs
+                // follow theirs (see inputs.rs and outputs.rs)
+                let mappings = unsafe { &mut *addr_of_mut!(MAPPINGS) };
+                inputs = InputBank::from_config(&digital_inputs, &analog_inputs, brain, mappings);
+                outputs = OutputBank::from_config(&digital_outputs, &pwm_outputs, brain, mappings, BURST_FREQUENCY_HZ);
+                
+                // What the brain learned before the last reset (see checkpoint.rs)
+                if let Some(config) = CHECKPOINT {
+                    checkpoints = Checkpoints::open(&config, brain, neuron_count as usize, synapse_count as usize);
+                    if let Some(seq) = checkpoints.as_mut().and_then(|c| c.restore(neurons, synapses)) {
+                        unsafe {
+                            sys::esp_rom_printf(b"[FEAGI] Checkpoint %d loaded\r\n\0".as_ptr() as *const c_char, seq as i32);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                e.report();
+                if let Some(upload) = upload.as_mut().filter(|_| uploaded.is_some()) {
+                    // Half-loaded arrays; start over with the embedded one
+                    unsafe {
+                        sys::esp_rom_printf(b"[FEAGI] Uploaded connectome discarded, restarting\r\n\0".as_ptr() as *const c_char);
+                    }
+                    upload.discard();
+                    restart(None);
+                }
+                unsafe {
+                    if external.is_some() {
+                        sys::esp_rom_printf(b"[FEAGI] Running without a brain, check the connectome storage and restart\r\n\0".as_ptr() as *const c_char);
+                    } else {
+                        sys::esp_rom_printf(b"[FEAGI] Running without a brain, rebuild the firmware with a valid connectome\r\n\0".as_ptr() as *const c_char);
+                    }
+                }
+                connectome_rejected = true;
+            }
+        }
+    } else {
+        unsafe {
             sys::esp_rom_printf(b"[FEAGI] No connectome embedded - running in minimal mode\r\n\0".as_ptr() as *const c_char);
-            sys::esp_rom_printf(b"[FEAGI] Standalone mode requires a connectome to be embedded\r\n\0".as_ptr() as *const c_char);
+            sys::esp_rom_printf(b"[FEAGI] Standalone mode requires a connectome to be embedded or uploaded\r\n\0".as_ptr() as *const c_char);
         }
     }
     
@@ -154,29 +306,196 @@ fn main() -> anyhow::Result<()> {
         sys::esp_rom_printf(b"[FEAGI] Burst frequency: %d Hz\r\n\0".as_ptr() as *const c_char, BURST_FREQUENCY_HZ as i32);
     }
     
-    // Main loop: Neural burst processing
-    let burst_period_ms = 1000 / BURST_FREQUENCY_HZ;
+    // Main loop: Neural burst processing, paced at BURST_FREQUENCY_HZ (see burst.rs)
+    let (neurons, synapses, engine, mappings, learning, spikes) = unsafe {
+        (
+            &mut *addr_of_mut!(NEURONS),
+            &mut *addr_of_mut!(SYNAPSES),
+            &mut *addr_of_mut!(ENGINE),
+            &*addr_of_mut!(MAPPINGS),
+            &mut *addr_of_mut!(LEARNING),
+            &mut *addr_of_mut!(SPIKES),
+        )
+    };
+    let mut pacer = Pacer::new(BURST_FREQUENCY_HZ);
+    // Commands from a host tool (see console.rs), also over WiFi
+    let mut console = Console::open();
+    if let (Some(port), Some(console)) = (command_port, console.as_mut()) {
+        console.attach(port);
+    }
+    // A console checkpoint request is answered once it's written
+    let mut checkpoint_requested = false;
+    let mut blink = 0;
+    let mut plasticity_ack = plasticity::Ack::new();
+    // Changed by the host in hybrid mode
+    let mut burst_hz = BURST_FREQUENCY_HZ;
+    
+    // Hybrid mode: activity summaries to the host, tweaks from it (see hybrid.rs)
+    let loaded_brain: &[u8] = if loaded.is_some() { brain } else { &[] };
+    let mut uplink = HYBRID.map(|config| {
+        let (neuron_count, synapse_count) = loaded.unwrap_or((0, 0));
+        Uplink::new(&config, loaded_brain, neuron_count, synapse_count, burst_hz)
+    });
+    if let Some(config) = HYBRID {
+        unsafe {
+            let channel: &[u8] = if WIFI.is_some() { b"serial console or WiFi\0" } else { b"serial console\0" };
+            sys::esp_rom_printf(b"[FEAGI] Hybrid mode: activity summaries at %d Hz on the %s\r\n\0".as_ptr() as *const c_char,
+                config.telemetry_hz as i32, channel.as_ptr() as *const c_char);
+        }
+    }
+    if let (Some(uplink), Some(console)) = (uplink.as_ref(), console.as_mut()) {
+        uplink.hello(console, loaded_brain);
+    }
     
     loop {
-        // Blink LED to show activity
-        led.set_high().ok();
-        FreeRtos::delay_ms(50);
-        led.set_low().ok();
-        
-        // Process neural burst
-        // 1. Read sensor inputs (GPIO)
-        // TODO: Read digital inputs and map to cortical areas
-        // TODO: Read analog inputs and map to cortical areas
-        
-        // 2. Update neural network (process burst)
-        // TODO: Process neural network burst when connectome is embedded
+        // No bursts while an upload replaces the brain
+        let paused = upload.as_ref().is_some_and(|u| u.active());
+        if paused {
+            led.set_high().ok();
+        } else if connectome_rejected {
+            // A rejected connectome blinks the LED fast, for boards without
+            // a serial console attached
+            blink += 1;
+            if blink >= (burst_hz / 10).max(1) {
+                blink = 0;
+                led.toggle().ok();
+            }
+        } else {
+            // Process neural burst
+            // 1. Read sensor inputs (GPIO) into their cortical areas
+            inputs.inject(engine, brain, mappings);
+            
+            // 2. Update neural network: integrate, fire, propagate
+            let fired = engine.run(neurons, synapses);
+            if PLASTICITY.is_some() {
+                learning.update(engine, synapses, brain);
+            }
+            
+            // LED lit while neurons fire
+            if fired > 0 {
+                led.set_high().ok();
+            } else {
+                led.set_low().ok();
+            }
+            
+            // 3. Write motor outputs (GPIO) from their cortical areas
+            outputs.update(engine, mappings);
+            
+            if let (Some(uplink), Some(console)) = (uplink.as_mut(), console.as_mut()) {
+                uplink.record(engine, brain, pacer.overruns(), console);
+            }
+            if RASTER.is_some() {
+                spikes.record(engine);
+            }
+        }
         
-        // 3. Write motor outputs (GPIO)
-        // TODO: Write digital outputs from cortical areas
-        // TODO: Write PWM outputs from cortical areas
+        // 4. Console commands, and a step of a checkpoint being written
+        if let Some(console) = console.as_mut() {
+            if let Some(line) = console.poll() {
+                if line == "{\"checkpoint\":1}" {
+                    match checkpoints.as_mut() {
+                        Some(checkpoints) => {
+                            checkpoints.request();
+                            checkpoint_requested = true;
+                        }
+                        None => console.send(b"{\"checkpoint_ack\":{\"ok\":false}}\n"),
+                    }
+                } else if learning.command(line, loaded_brain, &mut plasticity_ack) {
+                    console.send(plasticity_ack.as_bytes());
+                } else if line == "{\"hello\":1}" {
+                    if let Some(uplink) = uplink.as_ref() {
+                        uplink.hello(console, loaded_brain);
+                    }
+                } else if let Some(tweak) = Tweak::parse(line) {
+                    let result = match (tweak, uplink.as_mut()) {
+                        (_, None) => Err("off"),
+                        (Err(error), _) => Err(error),
+                        (Ok(Tweak::BurstFrequency(hz)), Some(uplink)) => {
+                            burst_hz = hz;
+                            pacer.set_frequency(hz);
+                            outputs.set_burst_frequency(hz);
+                            uplink.set_burst_frequency(hz);
+                            Ok(())
+                        }
+                        (Ok(Tweak::TelemetryHz(hz)), Some(uplink)) => {
+                            uplink.set_telemetry_hz(hz);
+                            Ok(())
+                        }
+                        (Ok(Tweak::Scale(pin, scale)), _) => inputs.set_scale(pin, scale).then_some(()).ok_or("pin"),
+                        (Ok(Tweak::Decay(pin, decay)), _) => outputs.set_decay(pin, decay).then_some(()).ok_or("pin"),
+                        (Ok(Tweak::HoldMs(pin, hold_ms)), _) => outputs.set_hold_ms(pin, hold_ms).then_some(()).ok_or("pin"),
+                    };
+                    console.send(set_ack(result).as_bytes());
+                } else if line.starts_with("{\"upload") {
+                    let mut ack = Ack::new();
+                    let next = match upload.as_mut() {
+                        Some(upload) => upload.command(line, &mut ack),
+                        None => {
+                            let _ = writeln!(ack, "{{\"upload_ack\":{{\"ok\":false,\"error\":\"off\"}}}}");
+                            Some(Next::Continue)
+                        }
+                    };
+                    console.send(ack.as_bytes());
+                    if next == Some(Next::Restart) {
+                        restart(Some(console));
+                    }
+                    if upload.as_ref().is_some_and(|u| u.active()) {
+                        outputs.stop();
+                    }
+                }
+            }
+            // A host that connects over WiFi takes over the summaries
+            if console.take_joined() {
+                if let Some(uplink) = uplink.as_ref() {
+                    uplink.hello(console, loaded_brain);
+                }
+            }
+        }
+        if upload.as_ref().is_some_and(|u| u.expired()) {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Upload timed out, restarting\r\n\0".as_ptr() as *const c_char);
+            }
+            restart(console.as_mut());
+        }
+        if let Some(checkpoints) = checkpoints.as_mut().filter(|_| !paused) {
+            let saved = checkpoints.poll(&*neurons, &*synapses);
+            if let Some(seq) = saved {
+                unsafe {
+                    sys::esp_rom_printf(b"[FEAGI] Checkpoint %d saved\r\n\0".as_ptr() as *const c_char, seq as i32);
+                }
+            }
+            // Done, or failed, once no longer busy
+            if checkpoint_requested && !checkpoints.busy() {
+                checkpoint_requested = false;
+                if let Some(console) = console.as_mut() {
+                    let mut ack: heapless::String<64> = heapless::String::new();
+                    let _ = match saved {
+                        Some(seq) => writeln!(ack, "{{\"checkpoint_ack\":{{\"ok\":true,\"seq\":{}}}}}", seq),
+                        None => writeln!(ack, "{{\"checkpoint_ack\":{{\"ok\":false}}}}"),
+                    };
+                    console.send(ack.as_bytes());
+                }
+            }
+        }
         
         // Wait for next burst
-        FreeRtos::delay_ms(burst_period_ms - 50);
+        if !pacer.wait() && !paused && !connectome_rejected && (pacer.overruns() - 1) % burst_hz == 0 {
+            // Every burst_hz-th overrun, so about once a second
+            // while overloaded
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Burst %d overran its %d ms period (%d overruns)\r\n\0".as_ptr() as *const c_char,
+                    engine.burst() as i32, pacer.period_ms() as i32, pacer.overruns() as i32);
+            }
+        }
     }
 }
 
+/// Restart the board, once what was sent on the console is out
+fn restart(console: Option<&mut Console>) {
+    if let Some(console) = console {
+        console.flush();
+    }
+    unsafe {
+        sys::esp_restart();
+    }
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Which neurons a GPIO pin stimulates or is driven by
+//!
+//! A `cortical_mapping` names a cortical area of the connectome by its
+//! cortical ID, `"igpio00"` (every neuron of the area), or one voxel of it,
+//! `"igpio00:2"` (x = 2, y = z = 0) or `"igpio00:2,0,1"`. build.rs checks the
+//! syntax; the neurons are looked up in the connectome image once, after it
+//! was loaded, and their indices kept in one pool shared by every pin.
+
+use core::ffi::c_char;
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::connectome;
+use crate::GpioPinConfig;
+
+/// A pin's neurons in the pool
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    start: usize,
+    len: usize,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Neuron indices of every mapped pin, up to P in total
+pub struct Mappings<const P: usize> {
+    pool: Vec<u16, P>,
+}
+
+impl<const P: usize> Mappings<P> {
+    pub const fn new() -> Self {
+        Self { pool: Vec::new() }
+    }
+
+    /// Look up the neurons `mapping` names in a loaded connectome image;
+    /// what's past the pool's capacity is left out
+    pub fn resolve(&mut self, data: &[u8], mapping: &str) -> Span {
+        let start = self.pool.len();
+        if let Some((area, voxel)) = parse(mapping) {
+            for index in connectome::neurons_of(data, area, voxel) {
+                if self.pool.push(index).is_err() {
+                    break;
+                }
+            }
+        }
+        Span { start, len: self.pool.len() - start }
+    }
+
+    /// The neurons of a pin; None, with a note on the console, if its
+    /// mapping names none and the pin is skipped
+    pub fn resolve_pin(&mut self, data: &[u8], config: &GpioPinConfig) -> Option<Span> {
+        let neurons = self.resolve(data, config.cortical_mapping);
+        if neurons.is_empty() {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] GPIO %d: cortical_mapping names no neuron of the connectome, pin skipped\r\n\0".as_ptr() as *const c_char,
+                    config.pin as i32);
+            }
+            return None;
+        }
+        Some(neurons)
+    }
+
+    /// The neuron indices of a span
+    pub fn neurons(&self, span: Span) -> &[u16] {
+        &self.pool[span.start..span.start + span.len]
+    }
+}
+
+/// Cortical ID and voxel of a `cortical_mapping`
+pub fn parse(mapping: &str) -> Option<(&str, Option<(u16, u16, u16)>)> {
+    let Some((area, voxel)) = mapping.split_once(':') else {
+        return Some((mapping, None));
+    };
+    let mut coordinates = voxel.split(',').map(|c| c.trim().parse::<u16>().ok());
+    match (coordinates.next()?, coordinates.next(), coordinates.next(), coordinates.next()) {
+        (Some(x), None, None, None) => Some((area, Some((x, 0, 0)))),
+        (Some(x), Some(Some(y)), Some(Some(z)), None) => Some((area, Some((x, y, z)))),
+        _ => None,
+    }
+}
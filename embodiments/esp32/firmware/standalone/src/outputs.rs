@@ -0,0 +1,250 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Motor extraction from cortical areas to GPIO outputs
+//!
+//! After every burst each output's level is the fraction of its neurons
+//! (see mapping.rs) that fired:
+//!
+//! - A `pwm_output` drives its duty cycle with the level (5 kHz, 10-bit
+//!   LEDC, up to 8 outputs)
+//! - A `digital_output` is high while the level is above zero
+//!
+//! Two settings keep outputs from chattering on sparse firing. With
+//! `decay` (0.0-1.0, default 0.0) the level falls off by that factor per
+//! burst instead of dropping at once, and a burst with more activity raises
+//! it again; it's cut to zero below 1%. `hold_ms` (default 0) is how long a
+//! digital output stays in a state before it may switch again, like a
+//! refractory period.
+
+use core::ffi::c_char;
+
+use esp_idf_svc::sys;
+use heapless::Vec;
+
+use crate::burst::BurstEngine;
+use crate::mapping::{Mappings, Span};
+use crate::GpioPinConfig;
+
+/// Outputs kept; as many as the GPIO config has room for
+const MAX_OUTPUTS: usize = 32;
+
+/// LEDC channels, one per PWM output
+const PWM_CHANNELS: u32 = 8;
+const PWM_FREQUENCY_HZ: u32 = 5000;
+const PWM_RESOLUTION_BITS: u32 = 10;
+
+/// Levels below this count as no activity
+const LEVEL_FLOOR: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Digital,
+    Pwm(sys::ledc_channel_t),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    pin: i32,
+    kind: Kind,
+    neurons: Span,
+    decay: f32,
+    hold_ms: u32,
+    /// Bursts a digital output stays in a state, at least
+    hold_bursts: u32,
+    level: f32,
+    high: bool,
+    /// Bursts since the digital output last switched
+    held: u32,
+}
+
+/// Digital and PWM outputs, and the neurons that drive them
+pub struct OutputBank {
+    outputs: Vec<Output, MAX_OUTPUTS>,
+    /// LEDC timer set up for the first PWM output
+    pwm_timer: bool,
+    burst_frequency_hz: u32,
+}
+
+impl OutputBank {
+    /// An empty bank, for running without a connectome
+    pub fn new() -> Self {
+        Self { outputs: Vec::new(), pwm_timer: false, burst_frequency_hz: 1 }
+    }
+
+    /// Set up the pins, low, and look up their neurons in the loaded
+    /// connectome image `data`
+    pub fn from_config<const P: usize>(
+        digital: &[&GpioPinConfig],
+        pwm: &[&GpioPinConfig],
+        data: &[u8],
+        mappings: &mut Mappings<P>,
+        burst_frequency_hz: u32,
+    ) -> Self {
+        let mut bank = Self::new();
+        bank.burst_frequency_hz = burst_frequency_hz;
+        let output = |config: &GpioPinConfig, kind, neurons| Output {
+            pin: config.pin as i32,
+            kind,
+            neurons,
+            decay: config.decay,
+            hold_ms: config.hold_ms,
+            hold_bursts: hold_bursts(config.hold_ms, burst_frequency_hz),
+            level: 0.0,
+            high: false,
+            held: 0,
+        };
+        for config in digital {
+            let Some(neurons) = mappings.resolve_pin(data, config) else {
+                continue;
+            };
+            unsafe {
+                sys::gpio_reset_pin(config.pin as i32);
+                sys::gpio_set_direction(config.pin as i32, sys::gpio_mode_t_GPIO_MODE_OUTPUT);
+                sys::gpio_set_level(config.pin as i32, 0);
+            }
+            let _ = bank.outputs.push(output(config, Kind::Digital, neurons));
+        }
+        for config in pwm {
+            let Some(neurons) = mappings.resolve_pin(data, config) else {
+                continue;
+            };
+            let Some(channel) = bank.pwm_channel(config.pin) else {
+                unsafe {
+                    sys::esp_rom_printf(b"[FEAGI] GPIO %d: no LEDC channel left, PWM output skipped\r\n\0".as_ptr() as *const c_char,
+                        config.pin as i32);
+                }
+                continue;
+            };
+            let _ = bank.outputs.push(output(config, Kind::Pwm(channel), neurons));
+        }
+        bank
+    }
+
+    /// Drive every output from the activity of its neurons in the burst
+    /// that just ran
+    pub fn update<const N: usize, const P: usize>(&mut self, engine: &BurstEngine<N>, mappings: &Mappings<P>) {
+        for output in self.outputs.iter_mut() {
+            let neurons = mappings.neurons(output.neurons);
+            let fired = neurons.iter().filter(|&&index| engine.fired(index as usize)).count();
+            let activity = fired as f32 / neurons.len() as f32;
+            output.level = activity.max(output.level * output.decay);
+            if output.level < LEVEL_FLOOR {
+                output.level = 0.0;
+            }
+            match output.kind {
+                Kind::Digital => {
+                    output.held = output.held.saturating_add(1);
+                    let high = output.level > 0.0;
+                    if high != output.high && output.held > output.hold_bursts {
+                        output.high = high;
+                        output.held = 0;
+                        unsafe {
+                            sys::gpio_set_level(output.pin, high as u32);
+                        }
+                    }
+                }
+                Kind::Pwm(channel) => {
+                    let duty = (output.level * ((1 << PWM_RESOLUTION_BITS) - 1) as f32) as u32;
+                    unsafe {
+                        sys::ledc_set_duty(sys::ledc_mode_t_LEDC_LOW_SPEED_MODE, channel, duty);
+                        sys::ledc_update_duty(sys::ledc_mode_t_LEDC_LOW_SPEED_MODE, channel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Change the `decay` of the output on `pin`; false if there's none
+    pub fn set_decay(&mut self, pin: u32, decay: f32) -> bool {
+        let Some(output) = self.outputs.iter_mut().find(|output| output.pin == pin as i32) else {
+            return false;
+        };
+        output.decay = decay;
+        true
+    }
+
+    /// Change the `hold_ms` of the digital output on `pin`; false if there's
+    /// none
+    pub fn set_hold_ms(&mut self, pin: u32, hold_ms: u32) -> bool {
+        let burst_frequency_hz = self.burst_frequency_hz;
+        let Some(output) = self.outputs.iter_mut().find(|output| output.pin == pin as i32 && matches!(output.kind, Kind::Digital)) else {
+            return false;
+        };
+        output.hold_ms = hold_ms;
+        output.hold_bursts = hold_bursts(hold_ms, burst_frequency_hz);
+        true
+    }
+
+    /// Keep the hold times as they are at a new burst frequency
+    pub fn set_burst_frequency(&mut self, burst_frequency_hz: u32) {
+        self.burst_frequency_hz = burst_frequency_hz;
+        for output in self.outputs.iter_mut() {
+            output.hold_bursts = hold_bursts(output.hold_ms, burst_frequency_hz);
+        }
+    }
+
+    /// Drive every output low, for while the network is paused
+    pub fn stop(&mut self) {
+        for output in self.outputs.iter_mut() {
+            output.level = 0.0;
+            output.high = false;
+            output.held = 0;
+            unsafe {
+                match output.kind {
+                    Kind::Digital => {
+                        sys::gpio_set_level(output.pin, 0);
+                    }
+                    Kind::Pwm(channel) => {
+                        sys::ledc_set_duty(sys::ledc_mode_t_LEDC_LOW_SPEED_MODE, channel, 0);
+                        sys::ledc_update_duty(sys::ledc_mode_t_LEDC_LOW_SPEED_MODE, channel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attach `pin` to the next free LEDC channel at duty 0, setting up the
+    /// shared timer on first use
+    fn pwm_channel(&mut self, pin: u32) -> Option<sys::ledc_channel_t> {
+        let used = self.outputs.iter().filter(|o| matches!(o.kind, Kind::Pwm(_))).count() as u32;
+        if used >= PWM_CHANNELS {
+            return None;
+        }
+        unsafe {
+            if !self.pwm_timer {
+                let mut timer: sys::ledc_timer_config_t = core::mem::zeroed();
+                timer.speed_mode = sys::ledc_mode_t_LEDC_LOW_SPEED_MODE;
+                timer.duty_resolution = PWM_RESOLUTION_BITS;
+                timer.timer_num = sys::ledc_timer_t_LEDC_TIMER_0;
+                timer.freq_hz = PWM_FREQUENCY_HZ;
+                timer.clk_cfg = sys::ledc_clk_cfg_t_LEDC_AUTO_CLK;
+                if sys::ledc_timer_config(&timer) != sys::ESP_OK {
+                    return None;
+                }
+                self.pwm_timer = true;
+            }
+            let mut channel: sys::ledc_channel_config_t = core::mem::zeroed();
+            channel.gpio_num = pin as i32;
+            channel.speed_mode = sys::ledc_mode_t_LEDC_LOW_SPEED_MODE;
+            channel.channel = used;
+            channel.intr_type = sys::ledc_intr_type_t_LEDC_INTR_DISABLE;
+            channel.timer_sel = sys::ledc_timer_t_LEDC_TIMER_0;
+            channel.duty = 0;
+            channel.hpoint = 0;
+            if sys::ledc_channel_config(&channel) != sys::ESP_OK {
+                return None;
+            }
+        }
+        Some(used)
+    }
+}
+
+/// `hold_ms` in bursts
+fn hold_bursts(hold_ms: u32, burst_frequency_hz: u32) -> u32 {
+    hold_ms * burst_frequency_hz / 1000
+}
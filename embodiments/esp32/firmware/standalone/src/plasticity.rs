@@ -0,0 +1,205 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Synaptic plasticity (config.json `plasticity`)
+//!
+//! After every burst, spike-timing-dependent plasticity adjusts the weights
+//! of the synapses whose neurons fired one burst apart: a synapse whose
+//! source fired the burst before its target is strengthened, one whose
+//! target fired the burst before its source is weakened, each by
+//! `learning_rate` of the full 0-255 weight range. An inhibitory synapse's
+//! weight is the size of a negative one, so strengthening moves it toward
+//! excitation by shrinking it, and weakening grows it. Synapses onto the
+//! neurons of a frozen cortical area keep their weights. On bursts with firing every
+//! synapse is looked at, so plasticity costs about a millisecond per 25,000
+//! synapses.
+//!
+//! The console (see console.rs) can turn it on and off, change the learning
+//! rate within `min_learning_rate`-`max_learning_rate`, and freeze and
+//! unfreeze areas; every command is answered with the state in effect:
+//!
+//! ```text
+//! host:  {"plasticity":{"learning_rate":0.02,"freeze":"ibtn00"}}
+//! board: {"plasticity_ack":{"ok":true,"enabled":true,"learning_rate":0.020,"frozen":1}}
+//! ```
+//!
+//! `{"plasticity":1}` only asks for the state. Changes hold until the next
+//! restart; the bounds can only be changed in config.json, so a host can't
+//! set a rate that would destabilize the network.
+
+use core::fmt::Write;
+
+use feagi_runtime_embedded::SynapseArray;
+use feagi_synapse::SynapseType;
+use heapless::String;
+
+use crate::burst::BurstEngine;
+use crate::connectome;
+use crate::console::{field_bool, field_f32, field_str};
+
+/// Plasticity settings (from config.json `plasticity`)
+#[derive(Debug, Clone, Copy)]
+pub struct PlasticityConfig {
+    /// Learning at boot
+    pub enabled: bool,
+    pub learning_rate: f32,
+    /// What the console may set the learning rate to
+    pub min_learning_rate: f32,
+    pub max_learning_rate: f32,
+    /// Cortical IDs of the areas frozen at boot
+    pub frozen_areas: &'static [&'static str],
+}
+
+/// Smallest learning rate that still moves a weight: anything lower rounds
+/// to a step of 0 (build.rs keeps `min_learning_rate` at or above it)
+pub const MIN_LEARNING_RATE: f32 = 0.002;
+
+/// Cortical areas that can be frozen, by index; the rest never are
+pub const MAX_AREAS: usize = 256;
+
+/// Reply to a plasticity command, one line
+pub type Ack = String<112>;
+
+/// Learning state, and the neurons that fired the burst before
+pub struct Plasticity<const N: usize> {
+    config: Option<PlasticityConfig>,
+    enabled: bool,
+    learning_rate: f32,
+    frozen: [bool; MAX_AREAS],
+    previous: [bool; N],
+    previous_count: usize,
+    neuron_count: usize,
+    synapse_count: usize,
+}
+
+impl<const N: usize> Plasticity<N> {
+    /// Off, until `configure`
+    pub const fn new() -> Self {
+        Self {
+            config: None,
+            enabled: false,
+            learning_rate: 0.0,
+            frozen: [false; MAX_AREAS],
+            previous: [false; N],
+            previous_count: 0,
+            neuron_count: 0,
+            synapse_count: 0,
+        }
+    }
+
+    /// Set up for the loaded connectome image `data`
+    pub fn configure(&mut self, config: &PlasticityConfig, data: &[u8], neuron_count: usize, synapse_count: usize) {
+        self.config = Some(*config);
+        self.enabled = config.enabled;
+        self.learning_rate = config.learning_rate;
+        self.neuron_count = neuron_count;
+        self.synapse_count = synapse_count;
+        for id in config.frozen_areas {
+            self.freeze(data, id, true);
+        }
+    }
+
+    /// Adjust the weights after the burst that just ran
+    pub fn update<const S: usize>(&mut self, engine: &BurstEngine<N>, synapses: &mut SynapseArray<S>, data: &[u8]) {
+        let step = step(self.learning_rate);
+        if self.enabled && step > 0 && engine.fired_count() > 0 && self.previous_count > 0 {
+            for i in 0..self.synapse_count {
+                let source = synapses.source_neurons[i] as usize;
+                let target = synapses.target_neurons[i] as usize;
+                let strengthen = self.previous[source] && engine.fired(target);
+                let weaken = self.previous[target] && engine.fired(source);
+                if strengthen == weaken {
+                    continue;
+                }
+                let area = connectome::area_of(data, target as u16) as usize;
+                if self.frozen.get(area).copied().unwrap_or(false) {
+                    continue;
+                }
+                let inhibitory = synapses.types[i] == SynapseType::Inhibitory as u8;
+                let weight = &mut synapses.weights[i];
+                *weight = if strengthen != inhibitory { weight.saturating_add(step) } else { weight.saturating_sub(step) };
+            }
+        }
+        for (i, previous) in self.previous[..self.neuron_count].iter_mut().enumerate() {
+            *previous = engine.fired(i);
+        }
+        self.previous_count = engine.fired_count();
+    }
+
+    /// Handle a command line, answering in `ack`; false if it isn't a
+    /// plasticity command
+    pub fn command(&mut self, line: &str, data: &[u8], ack: &mut Ack) -> bool {
+        if !line.starts_with("{\"plasticity\":") {
+            return false;
+        }
+        ack.clear();
+        let _ = match self.apply(line, data) {
+            Ok(()) => {
+                let frozen = self.frozen.iter().filter(|&&frozen| frozen).count();
+                writeln!(
+                    ack,
+                    "{{\"plasticity_ack\":{{\"ok\":true,\"enabled\":{},\"learning_rate\":{:.3},\"frozen\":{}}}}}",
+                    self.enabled, self.learning_rate, frozen
+                )
+            }
+            Err(error) => writeln!(ack, "{{\"plasticity_ack\":{{\"ok\":false,\"error\":\"{}\"}}}}", error),
+        };
+        true
+    }
+
+    /// Apply what a command asks for, all of it or, if any of it is
+    /// invalid, none
+    fn apply(&mut self, line: &str, data: &[u8]) -> Result<(), &'static str> {
+        let Some(config) = self.config else {
+            return Err("off");
+        };
+        let enabled = field_bool(line, "enabled");
+        let learning_rate = field_f32(line, "learning_rate");
+        if learning_rate.is_some_and(|rate| !(config.min_learning_rate..=config.max_learning_rate).contains(&rate) || step(rate) == 0) {
+            return Err("learning_rate");
+        }
+        let freeze = field_str(line, "freeze");
+        let unfreeze = field_str(line, "unfreeze");
+        for id in freeze.iter().chain(unfreeze.iter()) {
+            if area_index(data, id).is_none() {
+                return Err("area");
+            }
+        }
+        if let Some(enabled) = enabled {
+            self.enabled = enabled;
+        }
+        if let Some(learning_rate) = learning_rate {
+            self.learning_rate = learning_rate;
+        }
+        if let Some(id) = freeze {
+            self.freeze(data, id, true);
+        }
+        if let Some(id) = unfreeze {
+            self.freeze(data, id, false);
+        }
+        Ok(())
+    }
+
+    fn freeze(&mut self, data: &[u8], id: &str, frozen: bool) {
+        if let Some(area) = area_index(data, id) {
+            self.frozen[area] = frozen;
+        }
+    }
+}
+
+/// Weight change per pairing at `learning_rate`
+fn step(learning_rate: f32) -> u8 {
+    (learning_rate * 255.0 + 0.5) as u8
+}
+
+/// Index of the cortical area `id`, if the image has it and it can be frozen
+fn area_index(data: &[u8], id: &str) -> Option<usize> {
+    if data.is_empty() {
+        return None;
+    }
+    (0..connectome::area_count(data).min(MAX_AREAS)).find(|&area| connectome::area_id(data, area) == id)
+}
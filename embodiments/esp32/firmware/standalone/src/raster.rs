@@ -0,0 +1,293 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Spike raster on a second UART or over WiFi (config.json `raster`)
+//!
+//! Tools that watch the brain live, Brain Visualizer-style, get the neurons
+//! that fired, burst by burst, as compact binary frames on their own UART
+//! (`uart`, `tx_pin`, `baud_rate`), leaving the console to the boot log and
+//! commands, or with `"sink": "udp"` one frame a datagram to `host:port`
+//! over the station of the `wifi` block (see wifi.rs). With `every` above 1
+//! a frame holds the neurons that fired in any of that many bursts; a frame
+//! with more than `max_spikes` of them lists an evenly thinned `max_spikes`.
+//!
+//! Every frame is `0x00, COBS(message || CRC16), 0x00`, the controller
+//! firmware's serial framing (CRC-16/CCITT-FALSE, big-endian), on either
+//! sink, so one decoder reads both. The message, little-endian:
+//!
+//! | Offset | Size | Field |
+//! |-------:|-----:|-------|
+//! | 0 | 1 | `R` |
+//! | 1 | 1 | version, 1 |
+//! | 2 | 4 | last burst in the frame (bursts run since boot) |
+//! | 6 | 1 | bursts merged |
+//! | 7 | 1 | flags: bit 0 thinned, bit 1 frames dropped before this one |
+//! | 8 | 2 | neurons that fired |
+//! | 10 | 2 | neurons listed, n |
+//! | 12 | 2n | their indices, ascending |
+//!
+//! Indices are the order of the connectome image's neuron records (see
+//! connectome.rs), which give each one's cortical area and coordinates. A
+//! frame that doesn't fit the UART's transmit buffer, or that lwIP can't
+//! take (the station not joined yet, its buffers full), is dropped rather
+//! than holding up the burst.
+
+use core::ffi::c_void;
+use core::mem::size_of;
+
+use esp_idf_svc::sys;
+
+use crate::burst::BurstEngine;
+
+/// Where the frames go
+#[derive(Debug, Clone, Copy)]
+pub enum RasterSink {
+    /// A second UART, transmit only
+    Uart { uart: u8, tx_pin: i32, baud_rate: u32 },
+    /// Datagrams to an IPv4 `host:port`
+    Udp { host: [u8; 4], port: u16 },
+}
+
+/// Raster settings (from config.json `raster`)
+#[derive(Debug, Clone, Copy)]
+pub struct RasterConfig {
+    pub sink: RasterSink,
+    /// Bursts merged into a frame
+    pub every: u32,
+    /// Most neurons a frame lists
+    pub max_spikes: u32,
+}
+
+/// Most `max_spikes` build.rs takes
+pub const MAX_SPIKES: usize = 1024;
+
+const MAGIC: u8 = b'R';
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 12;
+const FLAG_THINNED: u8 = 1;
+const FLAG_DROPPED: u8 = 2;
+/// Longest message, and its frame: with the CRC, a COBS code byte per
+/// started 254 bytes and both delimiters
+const MESSAGE_CAPACITY: usize = HEADER_SIZE + 2 * MAX_SPIKES;
+const FRAME_CAPACITY: usize = frame_size(MESSAGE_CAPACITY);
+/// Room for a couple of the largest frames
+const TX_BUFFER: i32 = 4096;
+/// The driver needs a receive buffer, though nothing is read
+const RX_BUFFER: i32 = 256;
+/// Longest run of non-zero bytes one COBS block holds
+const BLOCK: usize = 254;
+
+/// The raster sink and the bursts merged so far
+pub struct Raster<const N: usize> {
+    config: Option<RasterConfig>,
+    /// Socket and destination of the UDP sink
+    udp: Option<(i32, sys::sockaddr_in)>,
+    neuron_count: usize,
+    /// Neurons that fired in the bursts since the last frame
+    merged: [bool; N],
+    merged_count: usize,
+    bursts: u32,
+    dropped: bool,
+    message: [u8; MESSAGE_CAPACITY],
+    frame: [u8; FRAME_CAPACITY],
+}
+
+impl<const N: usize> Raster<N> {
+    /// Off, until `open`
+    pub const fn new() -> Self {
+        Self {
+            config: None,
+            udp: None,
+            neuron_count: 0,
+            merged: [false; N],
+            merged_count: 0,
+            bursts: 0,
+            dropped: false,
+            message: [0; MESSAGE_CAPACITY],
+            frame: [0; FRAME_CAPACITY],
+        }
+    }
+
+    /// Install the UART driver (8N1, transmit only), or open the UDP
+    /// socket, for a connectome of `neuron_count` neurons; false if it
+    /// couldn't be
+    pub fn open(&mut self, config: &RasterConfig, neuron_count: usize) -> bool {
+        let opened = match config.sink {
+            RasterSink::Uart { uart, tx_pin, baud_rate } => open_uart(uart as sys::uart_port_t, tx_pin, baud_rate),
+            RasterSink::Udp { host, port } => {
+                let fd = unsafe { sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_DGRAM as i32, sys::IPPROTO_UDP as i32) };
+                let mut addr: sys::sockaddr_in = unsafe { core::mem::zeroed() };
+                addr.sin_len = size_of::<sys::sockaddr_in>() as u8;
+                addr.sin_family = sys::AF_INET as _;
+                addr.sin_port = port.to_be();
+                addr.sin_addr.s_addr = u32::from_ne_bytes(host);
+                self.udp = (fd >= 0).then_some((fd, addr));
+                fd >= 0
+            }
+        };
+        if opened {
+            self.config = Some(*config);
+            self.neuron_count = neuron_count.min(N);
+        }
+        opened
+    }
+
+    /// Merge the firing of the burst that just ran, and send the frame when
+    /// it's due
+    pub fn record(&mut self, engine: &BurstEngine<N>) {
+        let Some(config) = self.config else {
+            return;
+        };
+        if engine.fired_count() > 0 {
+            for (i, merged) in self.merged[..self.neuron_count].iter_mut().enumerate() {
+                if !*merged && engine.fired(i) {
+                    *merged = true;
+                    self.merged_count += 1;
+                }
+            }
+        }
+        self.bursts += 1;
+        if self.bursts < config.every {
+            return;
+        }
+        let size = self.encode_message(engine.burst() as u32, config.max_spikes as usize);
+        let sent = self.send(&config.sink, size);
+        self.dropped = !sent;
+        self.merged[..self.neuron_count].fill(false);
+        self.merged_count = 0;
+        self.bursts = 0;
+    }
+
+    /// Write the message for the merged bursts; its size
+    fn encode_message(&mut self, burst: u32, max_spikes: usize) -> usize {
+        let fired = self.merged_count;
+        let listed = fired.min(max_spikes);
+        let mut flags = 0;
+        if listed < fired {
+            flags |= FLAG_THINNED;
+        }
+        if self.dropped {
+            flags |= FLAG_DROPPED;
+        }
+        let header = &mut self.message[..HEADER_SIZE];
+        header[0] = MAGIC;
+        header[1] = VERSION;
+        header[2..6].copy_from_slice(&burst.to_le_bytes());
+        header[6] = self.bursts as u8;
+        header[7] = flags;
+        header[8..10].copy_from_slice(&(fired.min(u16::MAX as usize) as u16).to_le_bytes());
+        header[10..12].copy_from_slice(&(listed as u16).to_le_bytes());
+        // Of the fired neurons, the k-th is listed when it starts a new
+        // listed/fired share, so exactly `listed` spread over all of them
+        let mut at = HEADER_SIZE;
+        for (k, index) in (0..self.neuron_count).filter(|&i| self.merged[i]).enumerate() {
+            if (k * listed) / fired != ((k + 1) * listed) / fired {
+                self.message[at..at + 2].copy_from_slice(&(index as u16).to_le_bytes());
+                at += 2;
+            }
+        }
+        at
+    }
+
+    /// Frame the first `size` bytes of the message and queue them; false if
+    /// the sink couldn't take them without waiting
+    fn send(&mut self, sink: &RasterSink, size: usize) -> bool {
+        let length = frame(&self.message[..size], &mut self.frame);
+        match (sink, self.udp.as_ref()) {
+            (RasterSink::Uart { uart, .. }, _) => {
+                let port = *uart as sys::uart_port_t;
+                let mut free: usize = 0;
+                unsafe {
+                    if sys::uart_get_tx_buffer_free_size(port, &mut free) != sys::ESP_OK || free < length {
+                        return false;
+                    }
+                    sys::uart_write_bytes(port, self.frame.as_ptr() as *const c_void, length) == length as i32
+                }
+            }
+            (RasterSink::Udp { .. }, Some((fd, addr))) => unsafe {
+                sys::lwip_sendto(
+                    *fd,
+                    self.frame.as_ptr() as *const c_void,
+                    length,
+                    sys::MSG_DONTWAIT as i32,
+                    addr as *const sys::sockaddr_in as *const sys::sockaddr,
+                    size_of::<sys::sockaddr_in>() as u32,
+                ) == length as _
+            },
+            (RasterSink::Udp { .. }, None) => false,
+        }
+    }
+}
+
+/// Install a UART driver, 8N1 and transmit only; false if it couldn't be
+fn open_uart(port: sys::uart_port_t, tx_pin: i32, baud_rate: u32) -> bool {
+    unsafe {
+        let uart_config = sys::uart_config_t {
+            baud_rate: baud_rate as i32,
+            data_bits: sys::uart_word_length_t_UART_DATA_8_BITS,
+            parity: sys::uart_parity_t_UART_PARITY_DISABLE,
+            stop_bits: sys::uart_stop_bits_t_UART_STOP_BITS_1,
+            flow_ctrl: sys::uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_DISABLE,
+            ..Default::default()
+        };
+        if sys::uart_driver_install(port, RX_BUFFER, TX_BUFFER, 0, core::ptr::null_mut(), 0) != sys::ESP_OK {
+            return false;
+        }
+        // UART_PIN_NO_CHANGE for RX, RTS and CTS
+        if sys::uart_param_config(port, &uart_config) != sys::ESP_OK
+            || sys::uart_set_pin(port, tx_pin, -1, -1, -1) != sys::ESP_OK
+        {
+            sys::uart_driver_delete(port);
+            return false;
+        }
+    }
+    true
+}
+
+/// CRC-16/CCITT-FALSE of `data`
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Most bytes a message of `size` bytes takes framed
+const fn frame_size(size: usize) -> usize {
+    let coded = size + 2;
+    coded + coded / BLOCK + 1 + 2
+}
+
+/// Write `0x00, COBS(message || CRC16), 0x00` to `out`, which has room for
+/// `frame_size(message.len())` bytes; its length
+fn frame(message: &[u8], out: &mut [u8]) -> usize {
+    let crc = crc16(message).to_be_bytes();
+    out[0] = 0;
+    // out[code] is the COBS code of the block being written: the offset of
+    // the next zero
+    let mut code = 1;
+    let mut at = 2;
+    for &byte in message.iter().chain(crc.iter()) {
+        if byte != 0 {
+            out[at] = byte;
+            at += 1;
+            if at - code <= BLOCK {
+                continue;
+            }
+        }
+        out[code] = (at - code) as u8;
+        code = at;
+        at += 1;
+    }
+    out[code] = (at - code) as u8;
+    out[at] = 0;
+    at + 1
+}
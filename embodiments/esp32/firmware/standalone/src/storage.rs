@@ -0,0 +1,338 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Connectome on external storage (config.json `storage`)
+//!
+//! A connectome too large to embed in the firmware is read at boot from a
+//! FAT-formatted SD card or an external SPI NOR flash chip, both on the SPI
+//! bus, in the image format build.rs writes (see connectome.rs). It's
+//! streamed into the neuron and synapse arrays a few records at a time, so
+//! loading takes no RAM beyond the arrays.
+//!
+//! The cortical and voxel lookups the rest of the firmware makes need the
+//! image up to its synapse records; that part is copied into the
+//! `connectome` flash partition and mapped from there. The copy is only
+//! rewritten when the image changed, which makes that boot a few seconds
+//! longer. An uploaded connectome (see upload.rs) shares the partition and
+//! is loaded instead while it's there.
+//!
+//! Partition layout: a 4 KB sector with the copy's header (`FCX1`, size),
+//! written last, then the copy.
+
+use core::ffi::{c_char, c_void};
+
+use esp_idf_svc::sys;
+use feagi_runtime_embedded::{NeuronArray, SynapseArray};
+
+use crate::connectome::{self, LoadError};
+
+/// External storage settings (from config.json `storage`)
+#[derive(Debug, Clone, Copy)]
+pub struct StorageConfig {
+    pub medium: Medium,
+    pub sclk_pin: i32,
+    pub mosi_pin: i32,
+    pub miso_pin: i32,
+    pub cs_pin: i32,
+}
+
+/// Where on the SPI bus the image is
+#[derive(Debug, Clone, Copy)]
+pub enum Medium {
+    /// File on the card, a NUL-terminated path under `MOUNT_POINT`
+    SdCard { file: &'static str },
+    /// Byte offset on the flash chip
+    SpiFlash { offset: u32 },
+}
+
+const HOST: sys::spi_host_device_t = sys::spi_host_device_t_SPI2_HOST;
+const MOUNT_POINT: &[u8] = b"/sdcard\0";
+/// sdmmc_types.h flags, BIT() macros bindgen leaves out
+const SDMMC_HOST_FLAG_SPI: u32 = 1 << 3;
+const SDMMC_HOST_FLAG_DEINIT_ARG: u32 = 1 << 5;
+const FLASH_FREQ_MHZ: i32 = 40;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"FCX1");
+const PARTITION_LABEL: &[u8] = b"connectome\0";
+const PARTITION_SUBTYPE: sys::esp_partition_subtype_t = 0x41;
+const SECTOR_SIZE: usize = 4096;
+/// Bytes of the image's header compared to tell whether the copy is of it;
+/// they include the image's CRC-32
+const IMAGE_HEADER_SIZE: usize = 20;
+
+/// Load the connectome from external storage into the (empty) arrays; the
+/// image up to its synapse records, mapped into memory for the lookups, and
+/// the neuron and synapse counts
+pub fn load<const N: usize, const S: usize>(
+    config: &StorageConfig,
+    neurons: &mut NeuronArray<N>,
+    synapses: &mut SynapseArray<S>,
+) -> Result<(&'static [u8], u32, u32), LoadError> {
+    let mut prefix = Prefix::open().ok_or(LoadError::Storage)?;
+    let mut reader = Reader::open(config).ok_or(LoadError::Storage)?;
+    let loaded = connectome::load_streamed(|buffer| reader.read(buffer), |part| prefix.keep(part), neurons, synapses);
+    reader.close();
+    let (neuron_count, synapse_count) = loaded?;
+    let index = prefix.finish().ok_or(LoadError::Storage)?;
+    Ok((index, neuron_count, synapse_count))
+}
+
+/// The image's medium, read from the start on
+enum Reader {
+    SdCard { card: *mut sys::sdmmc_card_t, file: *mut sys::FILE },
+    SpiFlash { chip: *mut sys::esp_flash_t, at: u32 },
+}
+
+impl Reader {
+    /// Bring up the SPI bus and the medium and open the image; None, with
+    /// a note on the console, if any of it failed
+    fn open(config: &StorageConfig) -> Option<Self> {
+        let mut bus = sys::spi_bus_config_t {
+            sclk_io_num: config.sclk_pin,
+            data4_io_num: -1,
+            data5_io_num: -1,
+            data6_io_num: -1,
+            data7_io_num: -1,
+            max_transfer_sz: SECTOR_SIZE as i32,
+            ..Default::default()
+        };
+        bus.__bindgen_anon_1.mosi_io_num = config.mosi_pin;
+        bus.__bindgen_anon_2.miso_io_num = config.miso_pin;
+        bus.__bindgen_anon_3.quadwp_io_num = -1;
+        bus.__bindgen_anon_4.quadhd_io_num = -1;
+        let result = unsafe { sys::spi_bus_initialize(HOST, &bus, sys::spi_common_dma_t_SPI_DMA_CH_AUTO) };
+        if result != sys::ESP_OK {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Storage SPI bus failed to start (error %d)\r\n\0".as_ptr() as *const c_char, result);
+            }
+            return None;
+        }
+        let reader = match config.medium {
+            Medium::SdCard { file } => Self::open_card(config, file),
+            Medium::SpiFlash { offset } => Self::open_flash(config, offset),
+        };
+        if reader.is_none() {
+            unsafe {
+                sys::spi_bus_free(HOST);
+            }
+        }
+        reader
+    }
+
+    fn open_card(config: &StorageConfig, file: &'static str) -> Option<Self> {
+        // SDSPI_HOST_DEFAULT()
+        let mut host = sys::sdmmc_host_t {
+            flags: SDMMC_HOST_FLAG_SPI | SDMMC_HOST_FLAG_DEINIT_ARG,
+            slot: HOST as i32,
+            max_freq_khz: sys::SDMMC_FREQ_DEFAULT as i32,
+            io_voltage: 3.3,
+            init: Some(sys::sdspi_host_init),
+            set_card_clk: Some(sys::sdspi_host_set_card_clk),
+            do_transaction: Some(sys::sdspi_host_do_transaction),
+            io_int_enable: Some(sys::sdspi_host_io_int_enable),
+            io_int_wait: Some(sys::sdspi_host_io_int_wait),
+            ..Default::default()
+        };
+        host.__bindgen_anon_1.deinit_p = Some(sys::sdspi_host_remove_device);
+        let device = sys::sdspi_device_config_t {
+            host_id: HOST,
+            gpio_cs: config.cs_pin,
+            gpio_cd: -1,
+            gpio_wp: -1,
+            gpio_int: -1,
+            ..Default::default()
+        };
+        let mount = sys::esp_vfs_fat_mount_config_t { format_if_mount_failed: false, max_files: 1, ..Default::default() };
+        let mut card: *mut sys::sdmmc_card_t = core::ptr::null_mut();
+        let result = unsafe {
+            sys::esp_vfs_fat_sdspi_mount(MOUNT_POINT.as_ptr() as *const c_char, &host, &device, &mount, &mut card)
+        };
+        if result != sys::ESP_OK {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] SD card not mounted (error %d)\r\n\0".as_ptr() as *const c_char, result);
+            }
+            return None;
+        }
+        let handle = unsafe { sys::fopen(file.as_ptr() as *const c_char, b"rb\0".as_ptr() as *const c_char) };
+        if handle.is_null() {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] %s not found on the SD card\r\n\0".as_ptr() as *const c_char, file.as_ptr() as *const c_char);
+                sys::esp_vfs_fat_sdcard_unmount(MOUNT_POINT.as_ptr() as *const c_char, card);
+            }
+            return None;
+        }
+        Some(Reader::SdCard { card, file: handle })
+    }
+
+    fn open_flash(config: &StorageConfig, offset: u32) -> Option<Self> {
+        let device = sys::esp_flash_spi_device_config_t {
+            host_id: HOST,
+            cs_io_num: config.cs_pin,
+            io_mode: sys::esp_flash_io_mode_t_SPI_FLASH_FASTRD,
+            freq_mhz: FLASH_FREQ_MHZ,
+            ..Default::default()
+        };
+        let mut chip: *mut sys::esp_flash_t = core::ptr::null_mut();
+        unsafe {
+            let result = sys::spi_bus_add_flash_device(&mut chip, &device);
+            if result != sys::ESP_OK {
+                sys::esp_rom_printf(b"[FEAGI] SPI flash not added (error %d)\r\n\0".as_ptr() as *const c_char, result);
+                return None;
+            }
+            let result = sys::esp_flash_init(chip);
+            if result != sys::ESP_OK {
+                sys::esp_rom_printf(b"[FEAGI] SPI flash not found (error %d)\r\n\0".as_ptr() as *const c_char, result);
+                sys::spi_bus_remove_flash_device(chip);
+                return None;
+            }
+        }
+        Some(Reader::SpiFlash { chip, at: offset })
+    }
+
+    /// Fill `buffer` with the next bytes of the image; false if it couldn't
+    fn read(&mut self, buffer: &mut [u8]) -> bool {
+        match self {
+            Reader::SdCard { file, .. } => unsafe {
+                sys::fread(buffer.as_mut_ptr() as *mut c_void, 1, buffer.len(), *file) == buffer.len()
+            },
+            Reader::SpiFlash { chip, at } => {
+                let result = unsafe { sys::esp_flash_read(*chip, buffer.as_mut_ptr() as *mut c_void, *at, buffer.len() as u32) };
+                *at += buffer.len() as u32;
+                result == sys::ESP_OK
+            }
+        }
+    }
+
+    /// Release the medium and the bus; the image is in RAM and flash by now
+    fn close(self) {
+        unsafe {
+            match self {
+                Reader::SdCard { card, file } => {
+                    sys::fclose(file);
+                    sys::esp_vfs_fat_sdcard_unmount(MOUNT_POINT.as_ptr() as *const c_char, card);
+                }
+                Reader::SpiFlash { chip, .. } => {
+                    sys::spi_bus_remove_flash_device(chip);
+                }
+            }
+            sys::spi_bus_free(HOST);
+        }
+    }
+}
+
+/// The copy of the image up to its synapse records, in the `connectome`
+/// partition
+struct Prefix {
+    partition: *const sys::esp_partition_t,
+    /// Bytes of the image passed on so far
+    size: usize,
+    /// Sectors of the copy erased so far
+    erased: usize,
+    /// The copy in the partition is already of this image
+    current: bool,
+    /// A flash write failed, or the copy didn't fit
+    failed: bool,
+}
+
+impl Prefix {
+    /// Find the partition; None, with a note on the console, without one
+    fn open() -> Option<Self> {
+        let partition = unsafe {
+            sys::esp_partition_find_first(
+                sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                PARTITION_SUBTYPE,
+                PARTITION_LABEL.as_ptr() as *const c_char,
+            )
+        };
+        if partition.is_null() {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] No connectome partition, external storage needs it\r\n\0".as_ptr() as *const c_char);
+            }
+            return None;
+        }
+        Some(Self { partition, size: 0, erased: 0, current: false, failed: false })
+    }
+
+    /// Take the next part of the image, the header first
+    fn keep(&mut self, part: &[u8]) {
+        if self.size == 0 {
+            self.current = self.is_copy_of(part);
+            // A copy rewritten only halfway is never taken for a whole one
+            if !self.current && !self.erase(0) {
+                self.failed = true;
+            }
+        }
+        let at = SECTOR_SIZE + self.size;
+        let end = at + part.len();
+        self.size += part.len();
+        if self.current || self.failed {
+            return;
+        }
+        if end > unsafe { (*self.partition).size as usize } {
+            self.failed = true;
+            return;
+        }
+        while SECTOR_SIZE * (1 + self.erased) < end {
+            if !self.erase(SECTOR_SIZE * (1 + self.erased)) {
+                self.failed = true;
+                return;
+            }
+            self.erased += 1;
+        }
+        if unsafe { sys::esp_partition_write(self.partition, at, part.as_ptr() as *const c_void, part.len()) } != sys::ESP_OK {
+            self.failed = true;
+        }
+    }
+
+    /// Whether the partition holds a whole copy of the image with this header
+    fn is_copy_of(&self, header: &[u8]) -> bool {
+        let mut stored = [0u32; 2];
+        let mut image_header = [0u8; IMAGE_HEADER_SIZE];
+        unsafe {
+            sys::esp_partition_read(self.partition, 0, stored.as_mut_ptr() as *mut c_void, 8);
+            sys::esp_partition_read(self.partition, SECTOR_SIZE, image_header.as_mut_ptr() as *mut c_void, IMAGE_HEADER_SIZE);
+        }
+        stored[0] == MAGIC && header.get(..IMAGE_HEADER_SIZE) == Some(&image_header[..])
+    }
+
+    /// Once the whole image was loaded: mark the copy whole and map it; it
+    /// stays mapped for as long as the firmware runs
+    fn finish(self) -> Option<&'static [u8]> {
+        if self.failed {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] Connectome lookups couldn't be copied to the connectome partition\r\n\0".as_ptr() as *const c_char);
+            }
+            return None;
+        }
+        if !self.current {
+            let header = [MAGIC, self.size as u32];
+            if unsafe { sys::esp_partition_write(self.partition, 0, header.as_ptr() as *const c_void, 8) } != sys::ESP_OK {
+                return None;
+            }
+        }
+        let mut data: *const c_void = core::ptr::null();
+        let mut handle: sys::spi_flash_mmap_handle_t = 0;
+        let mapped = unsafe {
+            sys::esp_partition_mmap(
+                self.partition,
+                SECTOR_SIZE,
+                self.size,
+                sys::esp_partition_mmap_memory_t_ESP_PARTITION_MMAP_DATA,
+                &mut data,
+                &mut handle,
+            )
+        };
+        if mapped != sys::ESP_OK {
+            return None;
+        }
+        Some(unsafe { core::slice::from_raw_parts(data as *const u8, self.size) })
+    }
+
+    fn erase(&self, offset: usize) -> bool {
+        unsafe { sys::esp_partition_erase_range(self.partition, offset, SECTOR_SIZE) == sys::ESP_OK }
+    }
+}
@@ -0,0 +1,342 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Connectome upload over the serial console (config.json `upload`)
+//!
+//! A host tool can replace the brain without rebuilding the firmware: it
+//! sends a connectome image (the format build.rs embeds, see connectome.rs)
+//! in chunks, which are stored in the `connectome` flash partition, and the
+//! board restarts with it. An uploaded connectome is loaded in place of the
+//! embedded one from then on, until `{"upload_clear":1}`.
+//!
+//! ```text
+//! host:  {"upload_begin":{"size":48020}}
+//! board: {"upload_ack":{"ok":true,"offset":0}}
+//! host:  {"upload":{"offset":0,"crc":3735928559,"data":"RkNOMQIA..."}}
+//! board: {"upload_ack":{"ok":true,"offset":192}}
+//! ...
+//! host:  {"upload_end":1}
+//! board: {"upload_ack":{"ok":true,"neurons":1200,"synapses":5400}}
+//! ```
+//!
+//! A chunk is at most `CHUNK_SIZE` bytes, base64, with the CRC-32 of its
+//! bytes; a chunk that doesn't arrive intact, or not at the offset the last
+//! ack named, is refused with that offset again so the host can resend it.
+//! `upload_end` checks the whole image (and that it fits the arrays) before
+//! it's taken; a failed upload can be begun again.
+//!
+//! The network pauses, outputs low, from `upload_begin` until the restart,
+//! since the image being replaced may be the one it runs from.
+//! `{"upload_abort":1}`, or no upload command for `TIMEOUT_US`, restarts
+//! the board without the upload.
+//!
+//! Partition layout: a 4 KB sector with the header (`FCU1`, image size),
+//! written last, then the image.
+
+use core::ffi::{c_char, c_void};
+use core::fmt::Write;
+
+use esp_idf_svc::sys;
+use heapless::{String, Vec};
+
+use crate::connectome;
+use crate::console::{field_str, field_u32};
+
+/// Longest chunk, so a chunk line fits the console's
+pub const CHUNK_SIZE: usize = 192;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"FCU1");
+const PARTITION_LABEL: &[u8] = b"connectome\0";
+const PARTITION_SUBTYPE: sys::esp_partition_subtype_t = 0x41;
+const SECTOR_SIZE: usize = 4096;
+/// An upload with no command for this long is abandoned
+const TIMEOUT_US: i64 = 30_000_000;
+
+/// Reply to an upload command, one line
+pub type Ack = String<96>;
+
+/// What the main loop does after sending an upload command's ack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Next {
+    /// Carry on (paused, if an upload was begun)
+    Continue,
+    /// Restart the board
+    Restart,
+}
+
+/// The `connectome` partition and the upload in progress
+pub struct Upload {
+    partition: *const sys::esp_partition_t,
+    /// Mapping of the stored image
+    mapped: Option<sys::spi_flash_mmap_handle_t>,
+    /// Size of the image being uploaded, and how much of it arrived
+    size: usize,
+    received: usize,
+    /// Sectors erased so far
+    erased: usize,
+    active: bool,
+    last_us: i64,
+    max_neurons: usize,
+    max_synapses: usize,
+}
+
+impl Upload {
+    /// Find the partition; None, with a note on the console, without one.
+    /// Uploads must fit arrays of the given sizes
+    pub fn open(max_neurons: usize, max_synapses: usize) -> Option<Self> {
+        let partition = unsafe {
+            sys::esp_partition_find_first(
+                sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                PARTITION_SUBTYPE,
+                PARTITION_LABEL.as_ptr() as *const c_char,
+            )
+        };
+        if partition.is_null() {
+            unsafe {
+                sys::esp_rom_printf(b"[FEAGI] No connectome partition, uploads off\r\n\0".as_ptr() as *const c_char);
+            }
+            return None;
+        }
+        Some(Self {
+            partition,
+            mapped: None,
+            size: 0,
+            received: 0,
+            erased: 0,
+            active: false,
+            last_us: 0,
+            max_neurons,
+            max_synapses,
+        })
+    }
+
+    /// The uploaded image, mapped into memory, if there's one
+    pub fn stored(&mut self) -> Option<&'static [u8]> {
+        let mut header = [0u32; 2];
+        unsafe {
+            sys::esp_partition_read(self.partition, 0, header.as_mut_ptr() as *mut c_void, 8);
+        }
+        let size = header[1] as usize;
+        if header[0] != MAGIC || size == 0 || size > self.capacity() {
+            return None;
+        }
+        self.map(size)
+    }
+
+    /// Forget the uploaded image, so the next boot runs the embedded one
+    pub fn discard(&mut self) {
+        unsafe {
+            sys::esp_partition_erase_range(self.partition, 0, SECTOR_SIZE);
+        }
+    }
+
+    /// An upload was begun; the network stays paused until the restart
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// An upload went without a command for too long
+    pub fn expired(&self) -> bool {
+        self.active && unsafe { sys::esp_timer_get_time() } - self.last_us > TIMEOUT_US
+    }
+
+    /// Handle a command line; None if it isn't an upload command, else what
+    /// to do once `ack` is sent
+    pub fn command(&mut self, line: &str, ack: &mut Ack) -> Option<Next> {
+        ack.clear();
+        if line.starts_with("{\"upload\":") {
+            self.last_us = unsafe { sys::esp_timer_get_time() };
+            match self.chunk(line) {
+                Ok(()) => ok_offset(ack, self.received),
+                Err(error) => refuse(ack, error, Some(self.received)),
+            }
+            Some(Next::Continue)
+        } else if line.starts_with("{\"upload_begin\":") {
+            self.last_us = unsafe { sys::esp_timer_get_time() };
+            match self.begin(field_u32(line, "size")) {
+                Ok(()) => ok_offset(ack, 0),
+                Err(error) => refuse(ack, error, None),
+            }
+            Some(Next::Continue)
+        } else if line == "{\"upload_end\":1}" {
+            self.last_us = unsafe { sys::esp_timer_get_time() };
+            match self.end() {
+                Ok((neurons, synapses)) => {
+                    let _ = writeln!(ack, "{{\"upload_ack\":{{\"ok\":true,\"neurons\":{},\"synapses\":{}}}}}", neurons, synapses);
+                    Some(Next::Restart)
+                }
+                Err(error) => {
+                    refuse(ack, error, None);
+                    Some(Next::Continue)
+                }
+            }
+        } else if line == "{\"upload_abort\":1}" {
+            let _ = writeln!(ack, "{{\"upload_ack\":{{\"ok\":true}}}}");
+            Some(if self.active { Next::Restart } else { Next::Continue })
+        } else if line == "{\"upload_clear\":1}" {
+            self.discard();
+            let _ = writeln!(ack, "{{\"upload_ack\":{{\"ok\":true}}}}");
+            Some(Next::Restart)
+        } else {
+            None
+        }
+    }
+
+    /// Room for an image, after the header sector
+    fn capacity(&self) -> usize {
+        unsafe { (*self.partition).size as usize - SECTOR_SIZE }
+    }
+
+    fn begin(&mut self, size: Option<u32>) -> Result<(), &'static str> {
+        let size = size.ok_or("size")? as usize;
+        if size == 0 || size > self.capacity() {
+            return Err("too large");
+        }
+        // The image the network runs from may be overwritten; it's paused
+        // from here on
+        self.unmap();
+        self.active = true;
+        self.discard();
+        self.size = size;
+        self.received = 0;
+        self.erased = 0;
+        Ok(())
+    }
+
+    fn chunk(&mut self, line: &str) -> Result<(), &'static str> {
+        if !self.active {
+            return Err("not begun");
+        }
+        if field_u32(line, "offset") != Some(self.received as u32) {
+            return Err("offset");
+        }
+        let mut data: Vec<u8, CHUNK_SIZE> = Vec::new();
+        if !field_str(line, "data").is_some_and(|text| decode_base64(text.as_bytes(), &mut data)) || data.is_empty() {
+            return Err("data");
+        }
+        let crc = unsafe { sys::esp_rom_crc32_le(0, data.as_ptr(), data.len() as u32) };
+        if field_u32(line, "crc") != Some(crc) {
+            return Err("crc");
+        }
+        if self.received + data.len() > self.size {
+            return Err("too large");
+        }
+        let at = SECTOR_SIZE + self.received;
+        let end = at + data.len();
+        // One sector erase per chunk at most, as it's reached
+        while SECTOR_SIZE * (1 + self.erased) < end {
+            let sector = SECTOR_SIZE * (1 + self.erased);
+            if unsafe { sys::esp_partition_erase_range(self.partition, sector, SECTOR_SIZE) } != sys::ESP_OK {
+                return Err("flash");
+            }
+            self.erased += 1;
+        }
+        if unsafe { sys::esp_partition_write(self.partition, at, data.as_ptr() as *const c_void, data.len()) } != sys::ESP_OK {
+            return Err("flash");
+        }
+        self.received += data.len();
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(u32, u32), &'static str> {
+        if !self.active {
+            return Err("not begun");
+        }
+        if self.received != self.size {
+            return Err("incomplete");
+        }
+        let image = self.map(self.size).ok_or("flash")?;
+        let checked = connectome::check(image, self.max_neurons, self.max_synapses);
+        self.unmap();
+        let counts = checked.map_err(|e| {
+            e.report();
+            "rejected"
+        })?;
+        // Last, so an upload cut short is never loaded
+        let header = [MAGIC, self.size as u32];
+        if unsafe { sys::esp_partition_write(self.partition, 0, header.as_ptr() as *const c_void, 8) } != sys::ESP_OK {
+            return Err("flash");
+        }
+        Ok(counts)
+    }
+
+    fn map(&mut self, size: usize) -> Option<&'static [u8]> {
+        self.unmap();
+        let mut data: *const c_void = core::ptr::null();
+        let mut handle: sys::spi_flash_mmap_handle_t = 0;
+        let mapped = unsafe {
+            sys::esp_partition_mmap(
+                self.partition,
+                SECTOR_SIZE,
+                size,
+                sys::esp_partition_mmap_memory_t_ESP_PARTITION_MMAP_DATA,
+                &mut data,
+                &mut handle,
+            )
+        };
+        if mapped != sys::ESP_OK {
+            return None;
+        }
+        self.mapped = Some(handle);
+        Some(unsafe { core::slice::from_raw_parts(data as *const u8, size) })
+    }
+
+    fn unmap(&mut self) {
+        if let Some(handle) = self.mapped.take() {
+            unsafe {
+                sys::spi_flash_munmap(handle);
+            }
+        }
+    }
+}
+
+fn ok_offset(ack: &mut Ack, offset: usize) {
+    let _ = writeln!(ack, "{{\"upload_ack\":{{\"ok\":true,\"offset\":{}}}}}", offset);
+}
+
+fn refuse(ack: &mut Ack, error: &str, offset: Option<usize>) {
+    let _ = match offset {
+        Some(offset) => writeln!(ack, "{{\"upload_ack\":{{\"ok\":false,\"error\":\"{}\",\"offset\":{}}}}}", error, offset),
+        None => writeln!(ack, "{{\"upload_ack\":{{\"ok\":false,\"error\":\"{}\"}}}}", error),
+    };
+}
+
+/// Append standard padded base64 `input`, decoded, to `out`; false if it
+/// isn't base64 or didn't fit
+fn decode_base64<const N: usize>(input: &[u8], out: &mut Vec<u8, N>) -> bool {
+    if input.len() % 4 != 0 {
+        return false;
+    }
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    for (i, quad) in input.chunks_exact(4).enumerate() {
+        let last = i + 1 == input.len() / 4;
+        let padding = quad.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return false;
+        }
+        let mut bits = 0u32;
+        for &c in &quad[..4 - padding] {
+            let Some(v) = value(c) else {
+                return false;
+            };
+            bits = bits << 6 | v as u32;
+        }
+        bits <<= 6 * padding as u32;
+        let bytes = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        if out.extend_from_slice(&bytes[..3 - padding]).is_err() {
+            return false;
+        }
+    }
+    true
+}
@@ -0,0 +1,247 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! WiFi station and command port (config.json `wifi`)
+//!
+//! With a `wifi` block the board joins the network as a station and listens
+//! on TCP `port` for the one-line commands the serial console takes (see
+//! console.rs), so a host tool on the network can upload a connectome or
+//! ask for a checkpoint without a cable. One client at a time: a new
+//! connection replaces the previous one, as a host tool that restarted
+//! leaves its old connection behind.
+//!
+//! Nothing in the burst loop waits for the network: the station is started
+//! at boot and rejoins on its own when the access point drops it, and the
+//! port is read without blocking between bursts.
+
+use core::ffi::{c_char, c_void};
+use core::mem::size_of;
+
+use esp_idf_svc::sys;
+
+/// Station and command port settings (from config.json `wifi`)
+#[derive(Debug, Clone, Copy)]
+pub struct WifiConfig {
+    pub ssid: &'static str,
+    pub password: &'static str,
+    /// TCP port taking command lines
+    pub port: u16,
+}
+
+/// Received bytes read from the socket at a time
+const RX_CHUNK: usize = 256;
+/// A client that takes no data for this long is dropped rather than
+/// holding up the burst
+const SEND_TIMEOUT_MS: u32 = 100;
+
+/// Bring the station up and start joining the network; None (with a note on
+/// the console) if the driver couldn't be
+pub fn start(config: &WifiConfig) -> Option<CommandPort> {
+    let started = unsafe { start_station(config) };
+    if !started {
+        unsafe {
+            sys::esp_rom_printf(b"[FEAGI] WiFi failed to start\r\n\0".as_ptr() as *const c_char);
+        }
+        return None;
+    }
+    let port = CommandPort::listen(config.port);
+    if port.is_none() {
+        unsafe {
+            sys::esp_rom_printf(b"[FEAGI] WiFi command port %d failed to open\r\n\0".as_ptr() as *const c_char, config.port as i32);
+        }
+    }
+    port
+}
+
+unsafe fn start_station(config: &WifiConfig) -> bool {
+    let mut ret = sys::nvs_flash_init();
+    if ret == sys::ESP_ERR_NVS_NO_FREE_PAGES as i32 || ret == sys::ESP_ERR_NVS_NEW_VERSION_FOUND as i32 {
+        sys::nvs_flash_erase();
+        ret = sys::nvs_flash_init();
+    }
+    if ret != sys::ESP_OK || sys::esp_netif_init() != sys::ESP_OK {
+        return false;
+    }
+    let ret = sys::esp_event_loop_create_default();
+    if ret != sys::ESP_OK && ret != sys::ESP_ERR_INVALID_STATE as i32 {
+        return false;
+    }
+    if sys::esp_netif_create_default_wifi_sta().is_null() {
+        return false;
+    }
+    let init = init_config();
+    if sys::esp_wifi_init(&init) != sys::ESP_OK {
+        return false;
+    }
+    sys::esp_event_handler_register(
+        sys::WIFI_EVENT,
+        sys::wifi_event_t_WIFI_EVENT_STA_DISCONNECTED as i32,
+        Some(on_disconnected),
+        core::ptr::null_mut(),
+    );
+    sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM);
+    sys::esp_wifi_set_mode(sys::wifi_mode_t_WIFI_MODE_STA);
+
+    let mut wifi_config: sys::wifi_config_t = core::mem::zeroed();
+    copy_truncated(&mut wifi_config.sta.ssid, config.ssid);
+    copy_truncated(&mut wifi_config.sta.password, config.password);
+    sys::esp_wifi_set_config(sys::wifi_interface_t_WIFI_IF_STA, &mut wifi_config) == sys::ESP_OK
+        && sys::esp_wifi_start() == sys::ESP_OK
+        && sys::esp_wifi_connect() == sys::ESP_OK
+}
+
+/// Rejoin whenever the access point is lost (or joining failed)
+unsafe extern "C" fn on_disconnected(_arg: *mut c_void, _base: sys::esp_event_base_t, _id: i32, _data: *mut c_void) {
+    sys::esp_wifi_connect();
+}
+
+/// The listening socket and the client connected to it
+pub struct CommandPort {
+    listener: i32,
+    client: Option<i32>,
+    rx: [u8; RX_CHUNK],
+    rx_pos: usize,
+    rx_len: usize,
+}
+
+impl CommandPort {
+    /// Listen on `port` of any address the station gets
+    fn listen(port: u16) -> Option<Self> {
+        unsafe {
+            let fd = sys::lwip_socket(sys::AF_INET as i32, sys::SOCK_STREAM as i32, sys::IPPROTO_TCP as i32);
+            if fd < 0 {
+                return None;
+            }
+            let one: i32 = 1;
+            sys::lwip_setsockopt(fd, sys::SOL_SOCKET as i32, sys::SO_REUSEADDR as i32, &one as *const i32 as *const c_void, size_of::<i32>() as u32);
+            let mut addr: sys::sockaddr_in = core::mem::zeroed();
+            addr.sin_len = size_of::<sys::sockaddr_in>() as u8;
+            addr.sin_family = sys::AF_INET as _;
+            addr.sin_port = port.to_be();
+            let addr_ptr = &addr as *const sys::sockaddr_in as *const sys::sockaddr;
+            // accept() mustn't wait for a client
+            if sys::lwip_bind(fd, addr_ptr, size_of::<sys::sockaddr_in>() as u32) != 0
+                || sys::lwip_listen(fd, 1) != 0
+                || sys::lwip_fcntl(fd, sys::F_SETFL as i32, sys::O_NONBLOCK as i32) != 0
+            {
+                sys::lwip_close(fd);
+                return None;
+            }
+            Some(Self { listener: fd, client: None, rx: [0; RX_CHUNK], rx_pos: 0, rx_len: 0 })
+        }
+    }
+
+    /// Take a client that connected since the last call, in place of the
+    /// current one; true if there was one
+    pub fn accept(&mut self) -> bool {
+        let fd = unsafe { sys::lwip_accept(self.listener, core::ptr::null_mut(), core::ptr::null_mut()) };
+        if fd < 0 {
+            return false;
+        }
+        self.close();
+        let timeout = sys::timeval {
+            tv_sec: 0,
+            tv_usec: (SEND_TIMEOUT_MS * 1000) as _,
+        };
+        let one: i32 = 1;
+        unsafe {
+            sys::lwip_setsockopt(fd, sys::SOL_SOCKET as i32, sys::SO_SNDTIMEO as i32, &timeout as *const sys::timeval as *const c_void, size_of::<sys::timeval>() as u32);
+            // Answers are short lines: don't let Nagle hold them back
+            sys::lwip_setsockopt(fd, sys::IPPROTO_TCP as i32, sys::TCP_NODELAY as i32, &one as *const i32 as *const c_void, size_of::<i32>() as u32);
+        }
+        self.client = Some(fd);
+        true
+    }
+
+    /// Is a client connected?
+    pub fn connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// The next byte the client sent, if one has arrived; never blocks
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.rx_pos == self.rx_len {
+            let fd = self.client?;
+            let n = unsafe { sys::lwip_recv(fd, self.rx.as_mut_ptr() as *mut c_void, RX_CHUNK, sys::MSG_DONTWAIT as i32) };
+            if n <= 0 {
+                let errno = unsafe { *sys::__errno() } as u32;
+                // 0 is the client closing the connection
+                if n == 0 || (errno != sys::EAGAIN && errno != sys::EWOULDBLOCK) {
+                    self.close();
+                }
+                return None;
+            }
+            self.rx_pos = 0;
+            self.rx_len = n as usize;
+        }
+        self.rx_pos += 1;
+        Some(self.rx[self.rx_pos - 1])
+    }
+
+    /// Send to the client; false (and the client dropped) if it couldn't
+    /// take the data
+    pub fn write(&mut self, data: &[u8]) -> bool {
+        let Some(fd) = self.client else {
+            return false;
+        };
+        let mut sent = 0;
+        while sent < data.len() {
+            let rest = &data[sent..];
+            let n = unsafe { sys::lwip_send(fd, rest.as_ptr() as *const c_void, rest.len(), 0) };
+            if n <= 0 {
+                self.close();
+                return false;
+            }
+            sent += n as usize;
+        }
+        true
+    }
+
+    fn close(&mut self) {
+        if let Some(fd) = self.client.take() {
+            unsafe {
+                sys::lwip_close(fd);
+            }
+        }
+        self.rx_pos = 0;
+        self.rx_len = 0;
+    }
+}
+
+/// Equivalent of the C `WIFI_INIT_CONFIG_DEFAULT()` macro (ESP-IDF v5.1)
+unsafe fn init_config() -> sys::wifi_init_config_t {
+    sys::wifi_init_config_t {
+        osi_funcs: core::ptr::addr_of_mut!(sys::g_wifi_osi_funcs),
+        wpa_crypto_funcs: sys::g_wifi_default_wpa_crypto_funcs,
+        static_rx_buf_num: sys::CONFIG_ESP_WIFI_STATIC_RX_BUFFER_NUM as _,
+        dynamic_rx_buf_num: sys::CONFIG_ESP_WIFI_DYNAMIC_RX_BUFFER_NUM as _,
+        tx_buf_type: sys::CONFIG_ESP_WIFI_TX_BUFFER_TYPE as _,
+        static_tx_buf_num: sys::WIFI_STATIC_TX_BUFFER_NUM as _,
+        dynamic_tx_buf_num: sys::WIFI_DYNAMIC_TX_BUFFER_NUM as _,
+        cache_tx_buf_num: sys::WIFI_CACHE_TX_BUFFER_NUM as _,
+        csi_enable: sys::WIFI_CSI_ENABLED as _,
+        ampdu_rx_enable: sys::WIFI_AMPDU_RX_ENABLED as _,
+        ampdu_tx_enable: sys::WIFI_AMPDU_TX_ENABLED as _,
+        amsdu_tx_enable: sys::WIFI_AMSDU_TX_ENABLED as _,
+        nvs_enable: sys::WIFI_NVS_ENABLED as _,
+        nano_enable: sys::WIFI_NANO_FORMAT_ENABLED as _,
+        rx_ba_win: sys::WIFI_DEFAULT_RX_BA_WIN as _,
+        wifi_task_core_id: sys::WIFI_TASK_CORE_ID as _,
+        beacon_max_len: sys::WIFI_SOFTAP_BEACON_MAX_LEN as _,
+        mgmt_sbuf_num: sys::WIFI_MGMT_SBUF_NUM as _,
+        feature_caps: sys::g_wifi_feature_caps,
+        sta_disconnected_pm: sys::WIFI_STA_DISCONNECTED_PM_ENABLED != 0,
+        espnow_max_encrypt_num: sys::CONFIG_ESP_WIFI_ESPNOW_MAX_ENCRYPT_NUM as _,
+        magic: sys::WIFI_INIT_CONFIG_MAGIC as _,
+    }
+}
+
+/// Copy a string into a fixed C field (build.rs rejects values that don't fit)
+fn copy_truncated(field: &mut [u8], value: &str) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
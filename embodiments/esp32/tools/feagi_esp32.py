@@ -0,0 +1,655 @@
+#!/usr/bin/env python3
+"""
+FEAGI ESP32 Host CLI
+
+Talks to the controller firmware from a PC: records its serial protocol
+traffic and converts recorded traces into CSV or Chrome `trace_event` JSON
+(open in chrome://tracing or Perfetto), so burst timing, latency, and channel
+values can be inspected without writing scripts; reads and changes its
+configuration; and uploads firmware.
+
+Usage:
+    python feagi_esp32.py record --port /dev/ttyUSB0 --out run.jsonl
+    python feagi_esp32.py export run.jsonl --format csv --out run.csv
+    python feagi_esp32.py export run.jsonl --format chrome --out run.trace.json
+    python feagi_esp32.py sysid run.jsonl
+    python feagi_esp32.py config --port /dev/ttyUSB0 --diff ../firmware/controller/config.json
+    python feagi_esp32.py settings --port /dev/ttyUSB0 rate_policy.motor=interp barrier.timeout_ms=8
+    python feagi_esp32.py gpio --port /dev/ttyUSB0 --pin 25 --mode digital_output --mapping omot01:0
+    python feagi_esp32.py ota --host 192.168.1.50 --image fw.bin --signature fw.sig
+
+Boards built with `serial.framing: "cobs"` need `--framing cobs` on the
+commands that open the port.
+
+Trace format (one JSON object per line):
+    {"t": <host time in seconds>, "dir": "rx"|"tx", "line": "<raw line>"}
+"rx" lines were received from the board, "tx" lines were sent to it.
+
+Boards built with `telemetry.profile` also send their burst loop's timing
+({"prof":[[start_us, busy_us], ...]}, on the board's clock), which the exports
+place on the trace's timeline.
+"""
+
+import argparse
+import base64
+import csv
+import json
+import logging
+import sys
+import time
+from typing import Any, Dict, Iterator, List, Optional, Tuple
+
+logger = logging.getLogger(__name__)
+
+# Sensory frame arrays carrying [neuron_id, value] pairs (see controller README)
+CHANNEL_KEYS = {
+    "np": "sensory",
+    "ao": "applied_output",
+    "fb": "feedback",
+    "wl": "link",
+    "ec": "edge_count",
+}
+
+# Scalar board health fields (telemetry.board_health)
+HEALTH_KEYS = ("tc", "cpu")
+
+# Wheel odometry object: {"x", "y", "th", "d", "dth"}
+ODOMETRY_KEY = "od"
+
+# Per-burst timing (telemetry.profile): [[start_us, busy_us], ...]
+PROFILE_KEY = "prof"
+
+
+def read_trace(path: str) -> Iterator[Dict[str, Any]]:
+    """Yield trace records, skipping lines that aren't valid records."""
+    with open(path, "r", encoding="utf-8") as f:
+        for line_number, line in enumerate(f, start=1):
+            line = line.strip()
+            if not line:
+                continue
+            try:
+                record = json.loads(line)
+            except json.JSONDecodeError:
+                logger.warning(f"Skipping malformed trace line {line_number}")
+                continue
+            if "t" in record and "line" in record:
+                yield record
+
+
+def parse_message(line: str) -> Optional[Dict[str, Any]]:
+    """
+    Parse a protocol line as JSON; console output returns None.
+    Compressed lines ({"lz":N,"d":"..."}) are decompressed first.
+    """
+    if not line.startswith("{"):
+        return None
+    try:
+        message = json.loads(line)
+        if isinstance(message, dict) and "lz" in message and "d" in message:
+            device_id = message.get("id")
+            message = json.loads(heatshrink_decode(base64.b64decode(message["d"])))
+            if isinstance(message, dict) and device_id is not None:
+                message = {"id": device_id, **message}
+    except ValueError:
+        return None
+    return message if isinstance(message, dict) else None
+
+
+def heatshrink_decode(data: bytes, window_bits: int = 8, lookahead_bits: int = 4) -> bytes:
+    """Decompress a heatshrink stream, as the firmware's compress.rs writes it."""
+    out = bytearray()
+    total_bits = len(data) * 8
+    pos = 0
+
+    def read(bits: int) -> int:
+        nonlocal pos
+        value = 0
+        for _ in range(bits):
+            value = (value << 1) | ((data[pos >> 3] >> (7 - (pos & 7))) & 1)
+            pos += 1
+        return value
+
+    while pos < total_bits:
+        if read(1):
+            if pos + 8 > total_bits:
+                break
+            out.append(read(8))
+        else:
+            if pos + window_bits + lookahead_bits > total_bits:
+                break
+            distance = read(window_bits) + 1
+            count = read(lookahead_bits) + 1
+            if distance > len(out):
+                raise ValueError("heatshrink back-reference before the start")
+            for _ in range(count):
+                out.append(out[-distance])
+    return bytes(out)
+
+
+def crc16(data: bytes) -> int:
+    """CRC-16/CCITT-FALSE, as the firmware's cobs.rs computes it."""
+    crc = 0xFFFF
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x1021) if crc & 0x8000 else (crc << 1)
+            crc &= 0xFFFF
+    return crc
+
+
+def cobs_encode(message: bytes) -> bytes:
+    """Frame a message as 0x00, COBS(message + CRC16), 0x00."""
+    data = message + crc16(message).to_bytes(2, "big")
+    out = bytearray(b"\0")
+    block = bytearray()
+    for byte in data:
+        if byte:
+            block.append(byte)
+            if len(block) < 254:
+                continue
+        out.append(len(block) + 1)
+        out += block
+        block.clear()
+    out.append(len(block) + 1)
+    out += block
+    out.append(0)
+    return bytes(out)
+
+
+def cobs_decode(frame: bytes) -> Optional[bytes]:
+    """Decode one frame (without delimiters); None if it's corrupt."""
+    out = bytearray()
+    i = 0
+    while i < len(frame):
+        code = frame[i]
+        if code == 0 or i + code > len(frame):
+            return None
+        out += frame[i + 1:i + code]
+        i += code
+        if code < 255 and i < len(frame):
+            out.append(0)
+    if len(out) < 2 or crc16(bytes(out[:-2])) != int.from_bytes(out[-2:], "big"):
+        return None
+    return bytes(out[:-2])
+
+
+class SerialLines:
+    """Lines to and from the board, raw or COBS-framed (`serial.framing`)."""
+
+    def __init__(self, ser: Any, framing: str) -> None:
+        self.ser = ser
+        self.framing = framing
+        self.pending = b""
+
+    def readline(self) -> bytes:
+        """The next line, or b"" on timeout; corrupt frames are dropped."""
+        if self.framing == "raw":
+            return self.ser.readline()
+        self.pending += self.ser.read_until(b"\0")
+        if not self.pending.endswith(b"\0"):
+            return b""
+        frame, self.pending = self.pending[:-1], b""
+        if not frame:
+            return b""
+        message = cobs_decode(frame)
+        if message is None:
+            logger.debug("Dropped corrupt frame")
+            return b""
+        return message
+
+    def write(self, line: bytes) -> None:
+        self.ser.write(line if self.framing == "raw" else cobs_encode(line))
+
+
+def record(port: str, baudrate: int, out: str, duration: Optional[float], framing: str = "raw") -> None:
+    """Capture serial traffic from the board into a trace file."""
+    try:
+        import serial
+    except ImportError:
+        print("ERROR: pyserial not installed. Install with: pip install pyserial")
+        sys.exit(1)
+
+    start = time.time()
+    count = 0
+    with serial.Serial(port, baudrate, timeout=0.1) as ser, open(out, "w", encoding="utf-8") as f:
+        lines = SerialLines(ser, framing)
+        logger.info(f"Recording {port} @ {baudrate} baud to {out} (Ctrl+C to stop)")
+        try:
+            while duration is None or time.time() - start < duration:
+                raw = lines.readline()
+                if not raw:
+                    continue
+                line = raw.decode("utf-8", errors="replace").strip()
+                if not line:
+                    continue
+                f.write(json.dumps({"t": time.time(), "dir": "rx", "line": line}) + "\n")
+                count += 1
+        except KeyboardInterrupt:
+            pass
+    logger.info(f"Recorded {count} lines")
+
+
+def burst_profile(records: List[Dict[str, Any]]) -> List[Tuple[float, float, Optional[float]]]:
+    """
+    (start, busy, period) of every burst in the "prof" lines, in microseconds;
+    start is on the trace's timeline, period None for the first burst.
+
+    The board's clock is mapped onto the trace's by the smallest gap between
+    a line's arrival and the start of its last burst.
+    """
+    t0 = records[0]["t"] if records else 0.0
+    batches: List[Tuple[float, List[List[float]]]] = []
+    for rec in records:
+        message = parse_message(rec["line"])
+        if message is None or rec.get("dir", "rx") != "rx":
+            continue
+        bursts = [b for b in message.get(PROFILE_KEY, []) if isinstance(b, list) and len(b) >= 2]
+        if bursts:
+            batches.append(((rec["t"] - t0) * 1_000_000.0, bursts))
+    if not batches:
+        return []
+    offset = min(arrival - bursts[-1][0] for arrival, bursts in batches)
+
+    profile: List[Tuple[float, float, Optional[float]]] = []
+    previous: Optional[float] = None
+    for _, bursts in batches:
+        for start, busy in (b[:2] for b in bursts):
+            profile.append((start + offset, busy, None if previous is None else start - previous))
+            previous = start
+    return profile
+
+
+def export_csv(records: List[Dict[str, Any]], out: str) -> None:
+    """One row per channel value: time, direction, frame, kind, neuron id, value."""
+    t0 = records[0]["t"] if records else 0.0
+    with open(out, "w", newline="", encoding="utf-8") as f:
+        writer = csv.writer(f)
+        writer.writerow(["time_ms", "dir", "frame", "kind", "neuron_id", "value"])
+        for rec in records:
+            message = parse_message(rec["line"])
+            if message is None:
+                continue
+            time_ms = round((rec["t"] - t0) * 1000.0, 3)
+            frame = message.get("f", "")
+            for key, kind in CHANNEL_KEYS.items():
+                for pair in message.get(key, []):
+                    if isinstance(pair, list) and len(pair) >= 2:
+                        writer.writerow([time_ms, rec.get("dir", "rx"), frame, kind, pair[0], pair[1]])
+            for key in HEALTH_KEYS:
+                if key in message:
+                    writer.writerow([time_ms, rec.get("dir", "rx"), frame, "board", key, message[key]])
+            odometry = message.get(ODOMETRY_KEY)
+            if isinstance(odometry, dict):
+                for key, value in odometry.items():
+                    writer.writerow([time_ms, rec.get("dir", "rx"), frame, "odometry", key, value])
+        for start, busy, period in burst_profile(records):
+            time_ms = round(start / 1000.0, 3)
+            writer.writerow([time_ms, "rx", "", "burst", "busy_us", busy])
+            if period is not None:
+                writer.writerow([time_ms, "rx", "", "burst", "period_us", period])
+    logger.info(f"Wrote {out}")
+
+
+def export_chrome(records: List[Dict[str, Any]], out: str) -> None:
+    """
+    Chrome trace_event JSON:
+    - one complete event ("X") per burst, spanning frame N to frame N+1
+    - one counter event ("C") per channel kind with every neuron's value
+    - instant events ("i") for console lines, e.g. warnings
+    - with telemetry.profile, one complete event per burst on the board's
+      clock, spanning the time it spent working
+    """
+    t0 = records[0]["t"] if records else 0.0
+    events: List[Dict[str, Any]] = []
+    previous_frame: Optional[Dict[str, Any]] = None
+
+    for rec in records:
+        ts_us = (rec["t"] - t0) * 1_000_000.0
+        message = parse_message(rec["line"])
+
+        if message is None:
+            events.append({"name": rec["line"][:80], "ph": "i", "s": "g",
+                           "ts": ts_us, "pid": 1, "tid": 2})
+            continue
+
+        if rec.get("dir", "rx") == "tx":
+            events.append({"name": "motor", "ph": "i", "s": "t",
+                           "ts": ts_us, "pid": 1, "tid": 3, "args": message})
+            continue
+
+        if "f" in message:
+            if previous_frame is not None:
+                events.append({
+                    "name": f"burst {previous_frame['f']}",
+                    "ph": "X",
+                    "ts": previous_frame["ts"],
+                    "dur": ts_us - previous_frame["ts"],
+                    "pid": 1,
+                    "tid": 1,
+                })
+            previous_frame = {"f": message["f"], "ts": ts_us}
+
+        for key, kind in CHANNEL_KEYS.items():
+            values = {str(pair[0]): pair[1] for pair in message.get(key, [])
+                      if isinstance(pair, list) and len(pair) >= 2}
+            if values:
+                events.append({"name": kind, "ph": "C", "ts": ts_us, "pid": 1, "args": values})
+
+        health = {key: message[key] for key in HEALTH_KEYS if key in message}
+        if health:
+            events.append({"name": "board", "ph": "C", "ts": ts_us, "pid": 1, "args": health})
+
+        odometry = message.get(ODOMETRY_KEY)
+        if isinstance(odometry, dict) and odometry:
+            events.append({"name": "odometry", "ph": "C", "ts": ts_us, "pid": 1, "args": odometry})
+
+    for start, busy, period in burst_profile(records):
+        args = {"busy_us": busy} if period is None else {"busy_us": busy, "period_us": period}
+        events.append({"name": "burst work", "ph": "X", "ts": start, "dur": busy,
+                       "pid": 1, "tid": 4, "args": args})
+
+    trace = {
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+        "metadata": {"source": "feagi_esp32.py"},
+    }
+    with open(out, "w", encoding="utf-8") as f:
+        json.dump(trace, f)
+    logger.info(f"Wrote {out} ({len(events)} events)")
+
+
+def load_sysid_runs(records: List[Dict[str, Any]]) -> List[List[List[float]]]:
+    """Split "sy" samples ([t_us, u, y0, ...]) into runs at sy_start markers."""
+    runs: List[List[List[float]]] = []
+    for rec in records:
+        message = parse_message(rec["line"])
+        if message is None:
+            continue
+        if "sy_start" in message:
+            runs.append([])
+        elif "sy" in message and runs:
+            runs[-1].append([float(v) for v in message["sy"]])
+    return [run for run in runs if run]
+
+
+def analyse_step(samples: List[List[float]], channel: int) -> Dict[str, Optional[float]]:
+    """
+    Gain, dead time, and 10-90% rise time of response channel `channel`
+    for a step (or ramp) excitation.
+    """
+    t = [s[0] / 1000.0 for s in samples]
+    u = [s[1] for s in samples]
+    y = [s[2 + channel] for s in samples]
+
+    step_index = next((i for i, v in enumerate(u) if v > u[0]), None)
+    if step_index is None:
+        return {"gain": None, "latency_ms": None, "rise_ms": None}
+
+    y0 = sum(y[:step_index]) / step_index if step_index > 0 else y[0]
+    tail = y[len(y) * 4 // 5:]
+    y_final = sum(tail) / len(tail)
+    du = u[-1] - u[0]
+    dy = y_final - y0
+    gain = dy / du if du else None
+
+    def crossing(fraction: float) -> Optional[float]:
+        target = y0 + fraction * dy
+        for i in range(step_index, len(y)):
+            if (dy >= 0 and y[i] >= target) or (dy < 0 and y[i] <= target):
+                return t[i]
+        return None
+
+    t_step = t[step_index]
+    t10, t90 = crossing(0.1), crossing(0.9)
+    return {
+        "gain": gain,
+        "latency_ms": t10 - t_step if t10 is not None else None,
+        "rise_ms": t90 - t10 if t10 is not None and t90 is not None else None,
+    }
+
+
+def analyse_chirp(samples: List[List[float]], channel: int, windows: int = 10) -> List[Dict[str, float]]:
+    """Amplitude ratio (peak-to-peak y / peak-to-peak u) over equal time windows."""
+    size = max(len(samples) // windows, 2)
+    result = []
+    for start in range(0, len(samples) - size + 1, size):
+        window = samples[start:start + size]
+        u = [s[1] for s in window]
+        y = [s[2 + channel] for s in window]
+        du = max(u) - min(u)
+        result.append({
+            "t_ms": window[0][0] / 1000.0,
+            "ratio": (max(y) - min(y)) / du if du else 0.0,
+        })
+    return result
+
+
+def sysid(records: List[Dict[str, Any]]) -> None:
+    """Print gain/latency estimates for every recorded identification run."""
+    runs = load_sysid_runs(records)
+    if not runs:
+        logger.error("No system-identification runs (sy_start/sy lines) in trace")
+        sys.exit(1)
+    for run_index, samples in enumerate(runs):
+        channels = len(samples[0]) - 2
+        print(f"Run {run_index}: {len(samples)} samples, {channels} response channel(s)")
+        # A chirp never holds its excitation: decide by how often u changes
+        changes = sum(1 for a, b in zip(samples, samples[1:]) if a[1] != b[1])
+        is_chirp = changes > len(samples) // 2
+        for channel in range(channels):
+            if is_chirp:
+                print(f"  y{channel}: amplitude ratio per window")
+                for window in analyse_chirp(samples, channel):
+                    print(f"    t={window['t_ms']:8.1f} ms  ratio={window['ratio']:.3f}")
+            else:
+                est = analyse_step(samples, channel)
+                fmt = lambda v, unit="": "n/a" if v is None else f"{v:.3f}{unit}"
+                print(f"  y{channel}: gain={fmt(est['gain'])} "
+                      f"latency={fmt(est['latency_ms'], ' ms')} rise={fmt(est['rise_ms'], ' ms')}")
+
+
+def flatten(value: Any, prefix: str = "") -> Dict[str, Any]:
+    """Flatten nested dicts into dotted keys ({"a":{"b":1}} -> {"a.b": 1})."""
+    if not isinstance(value, dict):
+        return {prefix: value}
+    flat: Dict[str, Any] = {}
+    for key, child in value.items():
+        flat.update(flatten(child, f"{prefix}.{key}" if prefix else key))
+    return flat
+
+
+def request(port: str, baudrate: int, timeout: float, message: Dict[str, Any], reply_key: str,
+            framing: str = "raw") -> Any:
+    """Send one request line and wait for the reply carrying `reply_key`."""
+    try:
+        import serial
+    except ImportError:
+        print("ERROR: pyserial not installed. Install with: pip install pyserial")
+        sys.exit(1)
+
+    with serial.Serial(port, baudrate, timeout=0.1) as ser:
+        lines = SerialLines(ser, framing)
+        lines.write(json.dumps(message, separators=(",", ":")).encode("utf-8") + b"\n")
+        deadline = time.time() + timeout
+        while time.time() < deadline:
+            reply = parse_message(lines.readline().decode("utf-8", errors="replace").strip())
+            if reply and reply_key in reply:
+                return reply[reply_key]
+    logger.error(f"No {reply_key} reply within {timeout:.1f}s")
+    sys.exit(1)
+
+
+def fetch_config(port: str, baudrate: int, timeout: float, framing: str = "raw") -> Dict[str, Any]:
+    """Ask the board for its effective configuration ({"get_config":1})."""
+    return request(port, baudrate, timeout, {"get_config": 1}, "effective_config", framing)
+
+
+def settings(port: str, baudrate: int, timeout: float, assignments: List[str], framing: str = "raw") -> None:
+    """Read all runtime settings, or set several at once (all-or-nothing)."""
+    if not assignments:
+        for key, value in request(port, baudrate, timeout, {"get_settings": []}, "settings", framing).items():
+            print(f"{key:32} {value!r}")
+        return
+
+    batch: Dict[str, Any] = {}
+    for assignment in assignments:
+        key, sep, value = assignment.partition("=")
+        if not sep:
+            logger.error(f"Expected key=value, got {assignment!r}")
+            sys.exit(1)
+        batch[key] = value
+    ack = request(port, baudrate, timeout, {"set_settings": batch}, "settings_ack", framing)
+    if ack.get("ok"):
+        print(f"Applied {ack.get('n')} setting(s)")
+    else:
+        logger.error(f"Rejected, nothing applied: {ack.get('key', '')} {ack.get('error')}")
+        sys.exit(1)
+
+
+def set_gpio(port: str, baudrate: int, timeout: float, pin: int, mode: Optional[str],
+             mapping: Optional[str], framing: str = "raw") -> None:
+    """Store a pin's new mode and/or cortical mapping; the board reboots into it."""
+    update: Dict[str, Any] = {"pin": pin}
+    if mode:
+        update["mode"] = mode
+    if mapping:
+        update["cortical_mapping"] = mapping
+    if len(update) == 1:
+        logger.error("Nothing to change: give --mode and/or --mapping")
+        sys.exit(1)
+    ack = request(port, baudrate, timeout, {"set_gpio": update}, "gpio_ack", framing)
+    if ack.get("ok"):
+        print(f"Stored {ack.get('n')} value(s) for GPIO {pin}, the board is rebooting to apply them")
+    else:
+        logger.error(f"Rejected, nothing stored: {ack.get('key', '')} {ack.get('error')}")
+        sys.exit(1)
+
+
+def show_config(effective: Dict[str, Any], diff_path: Optional[str]) -> None:
+    """Print the effective configuration, optionally diffed against config.json."""
+    expected: Dict[str, Any] = {}
+    if diff_path:
+        with open(diff_path, "r", encoding="utf-8") as f:
+            expected = flatten(json.load(f))
+
+    for key, entry in effective.items():
+        if key == "gpio":
+            continue
+        value, source = entry.get("v"), entry.get("src", "?")
+        marker = ""
+        if key in expected and expected[key] != value:
+            marker = f"  (config.json: {expected[key]!r})"
+        print(f"{key:32} {value!r:>12}  [{source}]{marker}")
+    for gpio in effective.get("gpio", []):
+        print(f"gpio {gpio.get('pin'):>3}: {gpio.get('mode')} -> {gpio.get('cortical_mapping')} "
+              f"(boot {gpio.get('boot_state')})")
+
+
+def ota(host: str, port: int, image_path: str, signature_path: str, timeout: float) -> None:
+    """Upload a signed firmware image to the board's /ota endpoint."""
+    import urllib.error
+    import urllib.request
+
+    with open(image_path, "rb") as f:
+        image = f.read()
+    with open(signature_path, "rb") as f:
+        signature = f.read()
+    req = urllib.request.Request(
+        f"http://{host}:{port}/ota",
+        data=image,
+        method="POST",
+        headers={"Content-Type": "application/octet-stream", "X-Signature": signature.hex()},
+    )
+    logger.info(f"Uploading {len(image)} bytes to {host}:{port}")
+    try:
+        with urllib.request.urlopen(req, timeout=timeout) as resp:
+            reply = json.loads(resp.read())
+    except urllib.error.HTTPError as e:
+        reply = json.loads(e.read() or b"{}")
+    except (urllib.error.URLError, OSError) as e:
+        logger.error(f"Upload failed: {e}")
+        sys.exit(1)
+    if reply.get("ota") == "ok":
+        print(f"Firmware {reply.get('version')} accepted, the board is rebooting into it")
+    else:
+        logger.error(f"Rejected, the board keeps its firmware: {reply.get('reason')}")
+        sys.exit(1)
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description="FEAGI ESP32 host CLI")
+    sub = parser.add_subparsers(dest="command", required=True)
+
+    rec = sub.add_parser("record", help="Record serial traffic to a trace file")
+    rec.add_argument("--port", required=True, help="Serial port (e.g. /dev/ttyUSB0, COM3)")
+    rec.add_argument("--baud", type=int, default=115200, help="Baud rate (default: 115200)")
+    rec.add_argument("--out", required=True, help="Output trace file (.jsonl)")
+    rec.add_argument("--duration", type=float, default=None, help="Stop after N seconds")
+    rec.add_argument("--framing", choices=["raw", "cobs"], default="raw", help="Serial framing (default: raw)")
+
+    exp = sub.add_parser("export", help="Convert a trace file to CSV or Chrome trace format")
+    exp.add_argument("trace", help="Recorded trace file (.jsonl)")
+    exp.add_argument("--format", choices=["csv", "chrome"], required=True)
+    exp.add_argument("--out", required=True, help="Output file")
+
+    sid = sub.add_parser("sysid", help="Estimate gain/latency from system-identification runs")
+    sid.add_argument("trace", help="Recorded trace file (.jsonl)")
+
+    cfg = sub.add_parser("config", help="Show the board's effective configuration")
+    cfg.add_argument("--port", required=True, help="Serial port (e.g. /dev/ttyUSB0, COM3)")
+    cfg.add_argument("--baud", type=int, default=115200, help="Baud rate (default: 115200)")
+    cfg.add_argument("--diff", default=None, help="config.json to compare against")
+    cfg.add_argument("--timeout", type=float, default=3.0, help="Reply timeout in seconds")
+    cfg.add_argument("--framing", choices=["raw", "cobs"], default="raw", help="Serial framing (default: raw)")
+
+    st = sub.add_parser("settings", help="Read runtime settings, or set several at once")
+    st.add_argument("--port", required=True, help="Serial port (e.g. /dev/ttyUSB0, COM3)")
+    st.add_argument("--baud", type=int, default=115200, help="Baud rate (default: 115200)")
+    st.add_argument("--timeout", type=float, default=3.0, help="Reply timeout in seconds")
+    st.add_argument("--framing", choices=["raw", "cobs"], default="raw", help="Serial framing (default: raw)")
+    st.add_argument("assignments", nargs="*", help="key=value pairs to set (none = read all)")
+
+    gp = sub.add_parser("gpio", help="Change a pin's mode and/or cortical mapping (stored, then reboots)")
+    gp.add_argument("--port", required=True, help="Serial port (e.g. /dev/ttyUSB0, COM3)")
+    gp.add_argument("--baud", type=int, default=115200, help="Baud rate (default: 115200)")
+    gp.add_argument("--timeout", type=float, default=3.0, help="Reply timeout in seconds")
+    gp.add_argument("--framing", choices=["raw", "cobs"], default="raw", help="Serial framing (default: raw)")
+    gp.add_argument("--pin", type=int, required=True, help="GPIO number as in config.json")
+    gp.add_argument("--mode", choices=["digital_input", "digital_output", "disabled"], default=None, help="New mode")
+    gp.add_argument("--mapping", default=None, help="New cortical_mapping (e.g. omot01:0)")
+
+    up = sub.add_parser("ota", help="Upload a signed firmware image over the network")
+    up.add_argument("--host", required=True, help="Board IP address or hostname")
+    up.add_argument("--port", type=int, default=8080, help="ota.port (default: 8080)")
+    up.add_argument("--image", required=True, help="App image (espflash save-image)")
+    up.add_argument("--signature", required=True, help="Signature (openssl dgst -sha256 -sign)")
+    up.add_argument("--timeout", type=float, default=120.0, help="Upload timeout in seconds")
+
+    args = parser.parse_args()
+    logging.basicConfig(level=logging.INFO, format="%(message)s")
+
+    if args.command == "record":
+        record(args.port, args.baud, args.out, args.duration, args.framing)
+    elif args.command == "export":
+        records = sorted(read_trace(args.trace), key=lambda r: r["t"])
+        if not records:
+            logger.error(f"No trace records in {args.trace}")
+            sys.exit(1)
+        if args.format == "csv":
+            export_csv(records, args.out)
+        else:
+            export_chrome(records, args.out)
+    elif args.command == "sysid":
+        sysid(sorted(read_trace(args.trace), key=lambda r: r["t"]))
+    elif args.command == "config":
+        show_config(fetch_config(args.port, args.baud, args.timeout, args.framing), args.diff)
+    elif args.command == "settings":
+        settings(args.port, args.baud, args.timeout, args.assignments, args.framing)
+    elif args.command == "gpio":
+        set_gpio(args.port, args.baud, args.timeout, args.pin, args.mode, args.mapping, args.framing)
+    elif args.command == "ota":
+        ota(args.host, args.port, args.image, args.signature, args.timeout)
+
+
+if __name__ == "__main__":
+    main()
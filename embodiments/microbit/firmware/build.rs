@@ -3,6 +3,11 @@ use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 
+/// Longest fixed message the BLE buffers must hold: the capabilities JSON
+const MIN_BLE_BUFFER_BYTES: u64 = 160;
+/// Largest USB packet: command + length byte + 255-byte payload
+const MIN_USB_RX_BUFFER_BYTES: u64 = 2 + 255;
+
 fn main() {
     // Get the build profile
     let _target = env::var("TARGET").unwrap();
@@ -35,6 +40,72 @@ fn main() {
     writeln!(config_file, "pub const SENSOR_TEMP_ENABLED: bool = true;").unwrap();
     writeln!(config_file, "pub const SENSOR_BUTTONS_ENABLED: bool = true;").unwrap();
     writeln!(config_file, "pub const OUTPUT_LED_MATRIX_ENABLED: bool = true;").unwrap();
+    writeln!(config_file, "").unwrap();
+    writeln!(config_file, "// Idle neural-noise visualization while no host is connected").unwrap();
+    writeln!(config_file, "pub const IDLE_NOISE_ENABLED: bool = true;").unwrap();
+    writeln!(config_file, "pub const IDLE_NOISE_DENSITY_PCT: u8 = 12;").unwrap();
+
+    // Optional USB link encryption key (64 hex chars), see
+    // embodiments/shared/feagi-link/src/secure_link.rs
+    writeln!(config_file, "").unwrap();
+    writeln!(config_file, "// Pre-shared key for the sealed USB link (None = plaintext)").unwrap();
+    match env::var("FEAGI_LINK_PSK") {
+        Ok(psk) => {
+            let psk = psk.trim();
+            if psk.len() != 64 || !psk.chars().all(|c| c.is_ascii_hexdigit()) {
+                panic!("FEAGI_LINK_PSK must be 64 hex characters (32 bytes)");
+            }
+            let bytes: Vec<String> = (0..32)
+                .map(|i| format!("0x{}", &psk[i * 2..i * 2 + 2]))
+                .collect();
+            writeln!(config_file, "#[allow(dead_code)]").unwrap();
+            writeln!(config_file, "pub const LINK_PSK: Option<[u8; 32]> = Some([{}]);", bytes.join(", ")).unwrap();
+        }
+        Err(_) => {
+            writeln!(config_file, "#[allow(dead_code)]").unwrap();
+            writeln!(config_file, "pub const LINK_PSK: Option<[u8; 32]> = None;").unwrap();
+        }
+    }
+
+    // Buffer capacities (const generics in the firmware), from config.json
+    // ($FEAGI_CONFIG or ./config.json) so larger setups don't need code edits
+    let json_path = env::var("FEAGI_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("config.json"));
+    // A missing file would make cargo rerun this script on every build
+    if json_path.exists() {
+        println!("cargo:rerun-if-changed={}", json_path.display());
+    }
+    let config: serde_json::Value = match std::fs::read_to_string(&json_path) {
+        Ok(text) => serde_json::from_str(&text).expect("Failed to parse config.json"),
+        Err(_) => serde_json::json!({}),
+    };
+    let buffers = config.get("buffers");
+    let ble_buffer_bytes = buffers
+        .and_then(|b| b.get("ble_buffer_bytes"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(256);
+    let usb_rx_buffer_bytes = buffers
+        .and_then(|b| b.get("usb_rx_buffer_bytes"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(320);
+    if !(MIN_BLE_BUFFER_BYTES..=4096).contains(&ble_buffer_bytes) {
+        panic!(
+            "buffers.ble_buffer_bytes = {} cannot fit: must be {}-4096",
+            ble_buffer_bytes, MIN_BLE_BUFFER_BYTES
+        );
+    }
+    if !(MIN_USB_RX_BUFFER_BYTES..=4096).contains(&usb_rx_buffer_bytes) {
+        panic!(
+            "buffers.usb_rx_buffer_bytes = {} cannot fit a full packet: must be {}-4096",
+            usb_rx_buffer_bytes, MIN_USB_RX_BUFFER_BYTES
+        );
+    }
+    writeln!(config_file, "").unwrap();
+    writeln!(config_file, "// Buffer capacities").unwrap();
+    writeln!(config_file, "pub const BLE_BUFFER_SIZE: usize = {};", ble_buffer_bytes).unwrap();
+    writeln!(config_file, "#[allow(dead_code)]").unwrap();
+    writeln!(config_file, "pub const USB_RX_BUFFER_SIZE: usize = {};", usb_rx_buffer_bytes).unwrap();
 
     println!("cargo:rustc-env=CONFIG_RS={}", config_path.display());
 
@@ -45,6 +116,7 @@ fn main() {
     println!("cargo:rerun-if-changed=memory.x");
 
     println!("cargo:rerun-if-env-changed=FEAGI_CONFIG");
+    println!("cargo:rerun-if-env-changed=FEAGI_LINK_PSK");
 }
 
 
@@ -9,6 +9,7 @@ use microbit_bsp::ble::SoftdeviceController;
 use trouble_host::prelude::*;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use crate::ble_compat::BleCompatController;
+use feagi_link::segment::{self, Mtu, Reassembler, Received, SEGMENTATION_ACK};
 
 /// Nordic UART Service UUIDs (128-bit)
 pub const NUS_SERVICE_UUID: Uuid = Uuid::new_long([
@@ -26,11 +27,21 @@ const L2CAP_CHANNELS_MAX: usize = 3;
 const L2CAP_MTU: usize = 247;
 const ATT_TABLE_SIZE: usize = 20;
 const ADV_SETS: usize = 1;
+/// NUS characteristic value size: the largest notification/write payload
+/// (ATT MTU 247 - 3), so a negotiated MTU is usable in full
+pub const NUS_VALUE_LEN: usize = 244;
 
 // Static storage for BLE runner components (split from Runner)
-static RX_RUNNER: StaticCell<Option<RxRunner<'static, BleCompatController<'static>>>> = StaticCell::new();
-static CONTROL_RUNNER: StaticCell<Option<ControlRunner<'static, BleCompatController<'static>>>> = StaticCell::new();
-static TX_RUNNER: StaticCell<Option<TxRunner<'static, BleCompatController<'static>>>> = StaticCell::new();
+static RX_RUNNER: StaticCell<RxRunner<'static, BleCompatController<'static>>> = StaticCell::new();
+static CONTROL_RUNNER: StaticCell<ControlRunner<'static, BleCompatController<'static>>> = StaticCell::new();
+static TX_RUNNER: StaticCell<TxRunner<'static, BleCompatController<'static>>> = StaticCell::new();
+
+/// Runner halves, held until they are handed to their tasks
+struct Runners {
+    rx: &'static mut RxRunner<'static, BleCompatController<'static>>,
+    control: &'static mut ControlRunner<'static, BleCompatController<'static>>,
+    tx: &'static mut TxRunner<'static, BleCompatController<'static>>,
+}
 
 /// BLE Stack handle using TrouBLE via microbit-bsp
 /// 
@@ -48,20 +59,20 @@ pub struct BleStack {
     server: AttributeServer<'static, NoopRawMutex, ATT_TABLE_SIZE, 1, CONNECTIONS_MAX>,
     connection: Option<Connection<'static>>,
     advertiser: Option<Advertiser<'static, BleCompatController<'static>>>,
-    nus_tx_characteristic: Option<Characteristic<[u8; 20]>>,
+    nus_tx_characteristic: Option<Characteristic<[u8; NUS_VALUE_LEN]>>,
     nus_rx_handle: Option<u16>,
+    runners: Option<Runners>,
+    /// Segments written to the NUS RX characteristic, glued into frames
+    reassembler: Reassembler<{ crate::BLE_BUFFER_SIZE }>,
+    /// Last complete frame, until `receive_data` takes it
+    received: Option<Vec<u8, crate::BLE_BUFFER_SIZE>>,
 }
 
 impl BleStack {
     /// Initialize BLE stack with TrouBLE via microbit-bsp
-    pub async fn new(device_name: &str, sdc: SoftdeviceController<'_>) -> Result<Self, &'static str> {
-        // Create compatibility controller
-        // Note: We need to extend the lifetime to 'static for the stack
-        // This is safe because the controller is owned by the stack and will live as long as needed
+    pub async fn new(device_name: &'static str, sdc: SoftdeviceController<'static>) -> Result<Self, &'static str> {
+        // Create compatibility controller (the SDC lives for the whole program)
         let compat_controller = BleCompatController::new(sdc);
-        let compat_controller_static: BleCompatController<'static> = unsafe {
-            core::mem::transmute(compat_controller)
-        };
         
         // Initialize host resources
         static HOST_RESOURCES: StaticCell<HostResources<CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU, ADV_SETS>> = StaticCell::new();
@@ -70,7 +81,7 @@ impl BleStack {
         // Create BLE stack
         // Store stack in static storage to ensure it lives long enough
         static STACK: StaticCell<Stack<'static, BleCompatController<'static>>> = StaticCell::new();
-        let stack = STACK.init(trouble_host::new(compat_controller_static, host_resources));
+        let stack = STACK.init(trouble_host::new(compat_controller, host_resources));
         
         // Build host components
         let host = stack.build();
@@ -81,9 +92,11 @@ impl BleStack {
         // Split runner and store in static cells
         // We NEED runners for advertising to work, but we'll spawn them in the ble_init_task
         let (rx_runner, control_runner, tx_runner) = host.runner.split();
-        RX_RUNNER.init(Some(rx_runner));
-        CONTROL_RUNNER.init(Some(control_runner));
-        TX_RUNNER.init(Some(tx_runner));
+        let runners = Runners {
+            rx: RX_RUNNER.init(rx_runner),
+            control: CONTROL_RUNNER.init(control_runner),
+            tx: TX_RUNNER.init(tx_runner),
+        };
         
         // Set up GATT Attribute Server with Nordic UART Service
         // Create the attribute table (will be moved into the server)
@@ -100,18 +113,11 @@ impl BleStack {
             let name_bytes = device_name.as_bytes();
             let name_len = name_bytes.len().min(20);
             device_name_buf[..name_len].copy_from_slice(&name_bytes[..name_len]);
-            // Use a static string slice for the device name
-            static DEVICE_NAME_STATIC: StaticCell<&'static str> = StaticCell::new();
-            let device_name_static = DEVICE_NAME_STATIC.init(unsafe {
-                // Safety: We're storing the device name in static memory via the buffer
-                // This is safe as long as device_name parameter is valid for the duration
-                core::mem::transmute(device_name)
-            });
             let _device_name_handle = gas_service
                 .add_characteristic(
                     Uuid::from(0x2A00u16), // Device Name UUID
                     &[CharacteristicProp::Read],
-                    *device_name_static,
+                    device_name,
                     device_name_buf,
                 )
                 .build();
@@ -123,9 +129,9 @@ impl BleStack {
             
             // NUS TX Characteristic (Notify) - micro:bit sends data to client
             // We need static storage for TX value
-            static NUS_TX_VALUE: StaticCell<[u8; 20]> = StaticCell::new();
-            let nus_tx_value = NUS_TX_VALUE.init([0u8; 20]);
-            let nus_tx_initial: [u8; 20] = [0u8; 20];
+            static NUS_TX_VALUE: StaticCell<[u8; NUS_VALUE_LEN]> = StaticCell::new();
+            let nus_tx_value = NUS_TX_VALUE.init([0u8; NUS_VALUE_LEN]);
+            let nus_tx_initial: [u8; NUS_VALUE_LEN] = [0u8; NUS_VALUE_LEN];
             let nus_tx_characteristic = nus_service
                 .add_characteristic(
                     NUS_TX_CHAR_UUID,
@@ -137,9 +143,9 @@ impl BleStack {
             
             // NUS RX Characteristic (Write) - client sends data to micro:bit
             // We need static storage for RX value
-            static NUS_RX_VALUE: StaticCell<[u8; 20]> = StaticCell::new();
-            let nus_rx_value = NUS_RX_VALUE.init([0u8; 20]);
-            let nus_rx_initial: [u8; 20] = [0u8; 20];
+            static NUS_RX_VALUE: StaticCell<[u8; NUS_VALUE_LEN]> = StaticCell::new();
+            let nus_rx_value = NUS_RX_VALUE.init([0u8; NUS_VALUE_LEN]);
+            let nus_rx_initial: [u8; NUS_VALUE_LEN] = [0u8; NUS_VALUE_LEN];
             let nus_rx_handle = nus_service
                 .add_characteristic(
                     NUS_RX_CHAR_UUID,
@@ -165,6 +171,9 @@ impl BleStack {
             advertiser: None,
             nus_tx_characteristic: Some(nus_tx_characteristic),
             nus_rx_handle: Some(nus_rx_handle),
+            runners: Some(runners),
+            reassembler: Reassembler::new(),
+            received: None,
         })
     }
     
@@ -234,6 +243,7 @@ impl BleStack {
         }
         
         // Process GATT events if connected
+        let mut ack_segmentation = false;
         if let Some(ref connection) = self.connection {
             // Process connection events and handle GATT PDUs
             match connection.next().await {
@@ -247,15 +257,17 @@ impl BleStack {
                             
                             // Check if this is the RX characteristic
                             if Some(handle) == self.nus_rx_handle {
-                                // Store received data
-                                unsafe {
-                                    let mut buffer = heapless::Vec::new();
-                                    for &byte in data {
-                                        if buffer.push(byte).is_err() {
-                                            break;
+                                // A write is a frame, or one segment of it once the
+                                // host asked for segments
+                                match self.reassembler.push(data) {
+                                    Received::Frame(frame) => {
+                                        let mut buffer = Vec::new();
+                                        if buffer.extend_from_slice(frame).is_ok() {
+                                            self.received = Some(buffer);
                                         }
                                     }
-                                    crate::BLE_RX_BUFFER = Some(buffer);
+                                    Received::SegmentationRequested => ack_segmentation = true,
+                                    Received::Nothing => {}
                                 }
                             }
                             
@@ -277,10 +289,14 @@ impl BleStack {
                 ConnectionEvent::Disconnected { .. } => {
                     self.connected = false;
                     self.connection = None;
+                    self.reassembler = Reassembler::new();
                 }
                 _ => {}
             }
         }
+        if ack_segmentation {
+            let _ = self.send_notify(&SEGMENTATION_ACK).await;
+        }
     }
     
     /// Send data via BLE notify (Nordic UART Service TX characteristic)
@@ -321,14 +337,42 @@ impl BleStack {
         Ok(())
     }
     
-    /// Receive data from BLE (Nordic UART Service RX characteristic)
-    /// Returns data if available, None otherwise
-    pub async fn receive_data(&mut self) -> Option<heapless::Vec<u8, 256>> {
-        // Data is received in process_events and stored in BLE_RX_BUFFER
-        unsafe {
-            crate::BLE_RX_BUFFER.take()
+    /// Notification payload for the current connection
+    ///
+    /// The negotiated ATT MTU (20-244 bytes of payload), or the 20-byte
+    /// default before negotiation / without a connection.
+    pub fn mtu(&self) -> Mtu {
+        match self.connection {
+            Some(ref connection) => Mtu::ble(connection.att_mtu()),
+            None => Mtu::BLE_DEFAULT,
         }
     }
+
+    /// Send a frame of any length, split into MTU-sized notifications
+    /// (segments once the host asked for them)
+    pub async fn send_frame(&mut self, frame: &[u8]) -> Result<(), &'static str> {
+        let mtu = self.mtu();
+        let mut packet: Vec<u8, NUS_VALUE_LEN> = Vec::new();
+        let writes = if self.reassembler.segmented() {
+            segment::segments(frame, mtu)
+        } else {
+            segment::chunks(frame, mtu)
+        };
+        for seg in writes {
+            if !seg.write_to(&mut packet) {
+                return Err("Segment exceeds NUS value");
+            }
+            self.send_notify(&packet).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive a frame from BLE (Nordic UART Service RX characteristic)
+    /// Returns a complete, reassembled frame if available, None otherwise
+    pub async fn receive_data(&mut self) -> Option<Vec<u8, crate::BLE_BUFFER_SIZE>> {
+        // Data is received in process_events
+        self.received.take()
+    }
     
     /// Check if BLE is connected
     pub fn is_connected(&self) -> bool {
@@ -350,29 +394,18 @@ impl BleStack {
         self.connected = connection.is_some();
         self.connection = connection;
     }
-}
-
-/// Spawn the BLE runner tasks from static storage
-/// This must be called after BleStack::new() to start processing BLE events
-pub fn spawn_runner_task(spawner: &embassy_executor::Spawner) {
-    // Get mutable references to the runners from static storage
-    // Safety: StaticCell guarantees initialization, and executor is single-threaded
-    let rx_runner = unsafe {
-        let ptr = &RX_RUNNER as *const StaticCell<Option<RxRunner<'static, BleCompatController<'static>>>> as *mut Option<RxRunner<'static, BleCompatController<'static>>>;
-        (*ptr).as_mut().unwrap()
-    };
-    let control_runner = unsafe {
-        let ptr = &CONTROL_RUNNER as *const StaticCell<Option<ControlRunner<'static, BleCompatController<'static>>>> as *mut Option<ControlRunner<'static, BleCompatController<'static>>>;
-        (*ptr).as_mut().unwrap()
-    };
-    let tx_runner = unsafe {
-        let ptr = &TX_RUNNER as *const StaticCell<Option<TxRunner<'static, BleCompatController<'static>>>> as *mut Option<TxRunner<'static, BleCompatController<'static>>>;
-        (*ptr).as_mut().unwrap()
-    };
     
-    spawner.must_spawn(rx_runner_task(rx_runner));
-    spawner.must_spawn(control_runner_task(control_runner));
-    spawner.must_spawn(tx_runner_task(tx_runner));
+    /// Spawn the BLE runner tasks
+    ///
+    /// Must be called after `new()` and before `start_advertising()`, which
+    /// waits on controller events the runners deliver.
+    pub fn spawn_runner_tasks(&mut self, spawner: &embassy_executor::Spawner) {
+        if let Some(runners) = self.runners.take() {
+            spawner.must_spawn(rx_runner_task(runners.rx));
+            spawner.must_spawn(control_runner_task(runners.control));
+            spawner.must_spawn(tx_runner_task(runners.tx));
+        }
+    }
 }
 
 /// RX Runner task - processes incoming BLE data
@@ -15,6 +15,7 @@
 //!   - Capabilities (Read):   e95d0758-251d-470a-a062-fa1922dfa9a8
 
 use crate::sensors::SensorData;
+use crate::BLE_BUFFER_SIZE;
 use heapless::Vec;
 
 /// FEAGI BLE Service UUIDs
@@ -36,11 +37,13 @@ pub enum Command {
 }
 
 /// Bluetooth service for FEAGI communication
-pub struct BluetoothService {
+///
+/// `N` is the receive/transmit buffer capacity (`buffers.ble_buffer_bytes`).
+pub struct BluetoothService<const N: usize = BLE_BUFFER_SIZE> {
     device_name: &'static str,
     // Receive buffer for incoming BLE data
     // TODO: Connect to actual BLE characteristic when BLE stack is integrated
-    receive_buffer: heapless::Vec<u8, 256>,  // Max BLE MTU is typically 23-247 bytes
+    receive_buffer: heapless::Vec<u8, N>,  // Max BLE MTU is typically 23-247 bytes
     // Flag to indicate if BLE is connected
     connected: bool,
 }
@@ -57,6 +60,13 @@ pub enum PacketCommand {
 
 impl BluetoothService {
     pub fn new(device_name: &'static str) -> Self {
+        Self::with_capacity(device_name)
+    }
+}
+
+impl<const N: usize> BluetoothService<N> {
+    /// Create a service with an explicit buffer capacity
+    pub fn with_capacity(device_name: &'static str) -> Self {
         Self {
             device_name,
             receive_buffer: heapless::Vec::new(),
@@ -130,7 +140,7 @@ impl BluetoothService {
     
     /// Serialize sensor data to JSON format for BLE transmission
     /// Format: {"accel":[x,y,z],"mag":[x,y,z],"temp":23.5,"buttons":{"a":false,"b":true}}
-    fn serialize_sensor_data(&mut self, _data: &SensorData, buffer: &mut heapless::Vec<u8, 256>) -> Result<(), ()> {
+    fn serialize_sensor_data(&mut self, _data: &SensorData, buffer: &mut heapless::Vec<u8, N>) -> Result<(), ()> {
         // Simple JSON serialization for no_std environment
         // Format: {"accel":[x,y,z],"mag":[x,y,z],"temp":23.5,"buttons":{"a":false,"b":false}}
         buffer.clear();
@@ -145,7 +155,7 @@ impl BluetoothService {
     
     /// Send sensor data via BLE
     /// Returns serialized data if sensors are enabled
-    pub fn send_sensor_data(&mut self, data: &SensorData) -> Option<heapless::Vec<u8, 256>> {
+    pub fn send_sensor_data(&mut self, data: &SensorData) -> Option<heapless::Vec<u8, N>> {
         let mut buffer = heapless::Vec::new();
         if self.serialize_sensor_data(data, &mut buffer).is_ok() {
             Some(buffer)
@@ -189,7 +199,7 @@ impl BluetoothService {
     }
     
     /// Get capabilities data to send via BLE
-    pub fn get_capabilities_data(&self, caps: &str) -> heapless::Vec<u8, 256> {
+    pub fn get_capabilities_data(&self, caps: &str) -> heapless::Vec<u8, N> {
         // Convert capabilities string to bytes for BLE transmission
         let mut buffer = heapless::Vec::new();
         for &byte in caps.as_bytes() {
@@ -1,5 +1,7 @@
 #![no_std]
 #![no_main]
+// Shared state goes through channels/atomics/mutexes, never `static mut`
+#![deny(static_mut_refs)]
 
 use panic_halt as _;
 
@@ -52,24 +54,38 @@ mod protocol;
 // Common modules (always compiled)
 mod bluetooth;
 mod gpio_controller;
+mod idle_noise;
 mod sensors;
 
 use bluetooth::BluetoothService;
 use gpio_controller::GpioController;
+use idle_noise::NeuralNoise;
 use sensors::Sensors;
 
 // Include build-time configuration
 include!(concat!(env!("OUT_DIR"), "/config.rs"));
 
 // Shared state between BLE task and main loop
-// Using simple static buffers with manual synchronization
-// Note: Embassy executor is single-threaded, so this is safe
 use heapless::Vec;
 
-// Buffer for BLE data (BLE task -> Main loop)
-static mut BLE_RX_BUFFER: Option<heapless::Vec<u8, 256>> = None;
-// Buffer for sensor data (Main loop -> BLE task)  
-static mut BLE_TX_BUFFER: Option<heapless::Vec<u8, 256>> = None;
+#[cfg(feature = "transport-ble")]
+type BleChannel = embassy_sync::channel::Channel<
+    embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex,
+    Vec<u8, BLE_BUFFER_SIZE>,
+    BLE_QUEUE_DEPTH,
+>;
+// Packets queued in each direction before new ones are dropped
+#[cfg(feature = "transport-ble")]
+const BLE_QUEUE_DEPTH: usize = 4;
+
+// Received BLE data (BLE task -> Main loop)
+#[cfg(feature = "transport-ble")]
+static BLE_RX: BleChannel = BleChannel::new();
+// Outgoing data (Main loop -> BLE task)
+#[cfg(feature = "transport-ble")]
+static BLE_TX: BleChannel = BleChannel::new();
+// BLE connection state (BLE task -> Main loop)
+static BLE_CONNECTED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
 
 // ============================================================================
 // BLE VARIANT - Main function for Bluetooth Low Energy transport
@@ -206,6 +222,9 @@ async fn main(_spawner: embassy_executor::Spawner) {
     let mut ble_stack = ble_stack::BleStack::new(BLUETOOTH_NAME, sdc).await
         .expect("Failed to initialize BLE stack");
     
+    // The host runners must be running before advertising can start
+    ble_stack.spawn_runner_tasks(&_spawner);
+    
     // Start BLE advertising
     ble_stack.start_advertising(BLUETOOTH_NAME).await
         .expect("Failed to start BLE advertising");
@@ -215,21 +234,30 @@ async fn main(_spawner: embassy_executor::Spawner) {
     
     // Create a simple display buffer for LED matrix
     let mut display_buffer = [[0u8; 5]; 5];
-    let mut sensors = Sensors::new();
+    // Sensors on the internal I2C bus, read with async TWIM (EasyDMA) so the
+    // BLE tasks keep running while a transfer is in flight
+    use microbit_bsp::embassy_nrf::{bind_interrupts, peripherals, twim};
+    bind_interrupts!(struct TwimIrqs {
+        TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
+    });
+    let mut twim_config = twim::Config::default();
+    twim_config.frequency = twim::Frequency::K400;
+    let twim = twim::Twim::new(board.twispi0, TwimIrqs, board.i2c_int_sda, board.i2c_int_scl, twim_config);
+    let mut sensors = Sensors::new(twim, board.btn_a, board.btn_b).await;
     let mut gpio = GpioController::new();
     let mut bluetooth = BluetoothService::new(BLUETOOTH_NAME);
+    let mut idle_noise = NeuralNoise::new(0x4645_4147, IDLE_NOISE_DENSITY_PCT); // seed: "FEAG"
+    let mut was_connected = false;
     
     // Main control loop (async)
     let mut loop_count: u32 = 0;
     loop {
-        // Read sensors
-        let sensor_data = sensors.read_all();
+        // Read sensors (awaits the I2C transfers instead of busy-waiting)
+        let sensor_data = sensors.read_all().await;
         
         // Process BLE data if available
-        unsafe {
-            if let Some(ref ble_data) = BLE_RX_BUFFER.take() {
-                bluetooth.process_received_data(ble_data);
-            }
+        while let Ok(ble_data) = BLE_RX.try_receive() {
+            bluetooth.process_received_data(&ble_data);
         }
         
         // Check for Bluetooth commands
@@ -264,10 +292,10 @@ async fn main(_spawner: embassy_executor::Spawner) {
                     }
                 }
                 bluetooth::Command::GetCapabilities => {
-                    let caps = bluetooth.get_capabilities_data("{\"sensors\":{\"accel\":true,\"mag\":true,\"temp\":true,\"buttons\":true},\"gpio\":{\"digital\":8,\"analog\":3,\"pwm\":8},\"display\":{\"matrix\":true}}");
-                    unsafe {
-                        BLE_TX_BUFFER = Some(caps);
-                    }
+                    // "health" lists the board health fields sent (chip
+                    // temperature "tc", load "cpu"); none yet on the micro:bit
+                    let caps = bluetooth.get_capabilities_data("{\"sensors\":{\"accel\":true,\"mag\":true,\"temp\":true,\"buttons\":true},\"gpio\":{\"digital\":8,\"analog\":3,\"pwm\":8},\"display\":{\"matrix\":true},\"health\":[]}");
+                    let _ = BLE_TX.try_send(caps);
                 }
             }
         }
@@ -286,6 +314,18 @@ async fn main(_spawner: embassy_executor::Spawner) {
             }
         }
         
+        // While no host is connected, show idle neural noise so the board
+        // visibly waits instead of looking frozen (refreshed every ~120ms)
+        let connected = BLE_CONNECTED.load(core::sync::atomic::Ordering::Relaxed);
+        if IDLE_NOISE_ENABLED && OUTPUT_LED_MATRIX_ENABLED && !connected && loop_count % 3 == 0 {
+            display_buffer = idle_noise.next_frame();
+        }
+        if IDLE_NOISE_ENABLED && connected && !was_connected {
+            // Host just connected: drop the leftover noise frame
+            display_buffer = [[0; 5]; 5];
+        }
+        was_connected = connected;
+        
         // Update LED display
         if OUTPUT_LED_MATRIX_ENABLED {
             let mut frame = Frame::<5, 5>::empty();
@@ -316,6 +356,8 @@ async fn main(spawner: embassy_executor::Spawner) {
     use embassy_usb::{Builder, Config};
     use embassy_time::{Duration, Timer};
     use crate::protocol::{FeagiProtocol, Command};
+    use feagi_link::segment::{Reassembler, Received, SEGMENTATION_ACK};
+    use feagi_link::secure_link::{Role, SecureLink, SALT_LEN};
     use crate::usb_vbus::AlwaysOnVbus;
     
     // Initialize embassy-nrf FIRST for USB (can't use microbit-bsp at same time)
@@ -329,7 +371,12 @@ async fn main(spawner: embassy_executor::Spawner) {
     // USB interrupt bindings
     bind_interrupts!(struct Irqs {
         USBD => usb::InterruptHandler<peripherals::USBD>;
+        RNG => embassy_nrf::rng::InterruptHandler<peripherals::RNG>;
     });
+
+    // Link salt from the hardware RNG (only used with LINK_PSK)
+    let mut salt = [0u8; SALT_LEN];
+    embassy_nrf::rng::Rng::new(p.RNG, Irqs).blocking_fill_bytes(&mut salt);
     
     // Create USB driver with always-on VBUS detect
     static VBUS_DETECT: AlwaysOnVbus = AlwaysOnVbus::new();
@@ -368,14 +415,26 @@ async fn main(spawner: embassy_executor::Spawner) {
     let usb_device = builder.build();
     spawner.must_spawn(usb_device_task(usb_device));
     
-    // Initialize FEAGI protocol
-    let mut protocol = FeagiProtocol::new();
+    // Initialize FEAGI protocol (sealed when built with FEAGI_LINK_PSK)
+    let mut protocol = match LINK_PSK {
+        Some(psk) => FeagiProtocol::with_link(SecureLink::new(&psk, salt, Role::Device)),
+        None => FeagiProtocol::new(),
+    };
+    
+    // Packets are passed on as they are, or glued into frames once the host
+    // asks for segments
+    let mut reassembler: Reassembler<USB_RX_BUFFER_SIZE> = Reassembler::new();
     
     // Wait for USB connection (CDC ACM DTR signal)
     loop {
         let mut cdc_lock = cdc.lock().await;
         if let Some(ref mut cdc_instance) = *cdc_lock {
             cdc_instance.wait_connection().await;
+            // Announce the link salt so the host can start the handshake (the
+            // link isn't segmented yet)
+            if let Some(hello) = protocol.hello_packet() {
+                write_frame(cdc_instance, &hello, false).await;
+            }
             break;
         }
     }
@@ -391,8 +450,23 @@ async fn main(spawner: embassy_executor::Spawner) {
         if let Some(ref mut cdc_instance) = *cdc_lock {
             match cdc_instance.read_packet(&mut buf).await {
                 Ok(len) if len > 0 => {
-                    drop(cdc_lock);
-                    protocol.process_received_data(&buf[..len]);
+                    match reassembler.push(&buf[..len]) {
+                        Received::Frame(frame) => {
+                            protocol.process_received_data(frame);
+                            // A host that lost its session asks for the hello again
+                            if protocol.take_hello_request() {
+                                if let Some(hello) = protocol.hello_packet() {
+                                    write_frame(cdc_instance, &hello, reassembler.segmented()).await;
+                                }
+                            }
+                            drop(cdc_lock);
+                        }
+                        Received::SegmentationRequested => {
+                            let _ = cdc_instance.write_packet(&SEGMENTATION_ACK).await;
+                            drop(cdc_lock);
+                        }
+                        Received::Nothing => drop(cdc_lock),
+                    }
                 }
                 _ => {
                     drop(cdc_lock);
@@ -428,6 +502,26 @@ async fn main(spawner: embassy_executor::Spawner) {
     }
 }
 
+// Write a frame as USB packets: plain chunks, or segments once the host asked
+// for them
+#[cfg(feature = "transport-usb")]
+async fn write_frame<'d, D: embassy_usb::driver::Driver<'d>>(
+    cdc: &mut embassy_usb::class::cdc_acm::CdcAcmClass<'d, D>,
+    frame: &[u8],
+    segmented: bool,
+) {
+    use feagi_link::segment::{self, Mtu};
+
+    let mtu = Mtu::new(cdc.max_packet_size() as usize);
+    let writes = if segmented { segment::segments(frame, mtu) } else { segment::chunks(frame, mtu) };
+    let mut packet: Vec<u8, 64> = Vec::new();
+    for seg in writes {
+        if seg.write_to(&mut packet) {
+            let _ = cdc.write_packet(&packet).await;
+        }
+    }
+}
+
 // USB device task (runs USB stack)
 #[cfg(feature = "transport-usb")]
 #[embassy_executor::task]
@@ -458,24 +552,22 @@ async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) -> ! {
 // BLE task to handle BLE events
 #[cfg(feature = "transport-ble")]
 #[embassy_executor::task]
-async fn ble_task(mut ble_stack: ble_stack::BleStack<'static>) {
+async fn ble_task(mut ble_stack: ble_stack::BleStack) {
     loop {
         // Process BLE events
         ble_stack.process_events().await;
+        BLE_CONNECTED.store(ble_stack.is_connected(), core::sync::atomic::Ordering::Relaxed);
         
         // Check for received data and put it in RX buffer
         if let Some(data) = ble_stack.receive_data().await {
-            unsafe {
-                BLE_RX_BUFFER = Some(data);
-            }
+            // Main loop fell behind: drop the packet rather than block BLE
+            let _ = BLE_RX.try_send(data);
         }
         
-        // Check for data to send and send it via BLE
-        unsafe {
-            if let Some(data) = BLE_TX_BUFFER.take() {
-                if let Err(_) = ble_stack.send_notify(&data).await {
-                    // If send fails, put data back (or drop it)
-                }
+        // Check for frames to send and send them via BLE (segmented to the MTU)
+        while let Ok(data) = BLE_TX.try_receive() {
+            if let Err(_) = ble_stack.send_frame(&data).await {
+                // Not connected: drop it
             }
         }
         
@@ -2,11 +2,31 @@
 //!
 //! This is a minimal copy of feagi-embedded's protocol layer for embedded use.
 //! It's transport-agnostic and works with BLE, USB CDC, UART, etc.
+//!
+//! With a `SecureLink` attached, only sealed packets (0xE0), the host salt
+//! (0xE1) and hello requests (0xE3) are accepted; plaintext commands are
+//! dropped.
 
 #![allow(dead_code)]
 
 use heapless::Vec;
 
+use feagi_link::secure_link::{SecureLink, AUTH_LEN, SALT_LEN};
+use crate::USB_RX_BUFFER_SIZE;
+
+/// Sealed envelope carrying one or more FEAGI packets
+pub const CMD_SEALED: u8 = 0xE0;
+/// Host session salt and its authenticator (16 + 16 bytes)
+pub const CMD_HOST_SALT: u8 = 0xE1;
+/// Board hello with its current salt and authenticator (16 + 16 bytes),
+/// board -> host
+pub const CMD_HELLO: u8 = 0xE2;
+/// Host asks for the hello again (no payload)
+pub const CMD_HELLO_REQUEST: u8 = 0xE3;
+
+/// Bytes of a hello or host salt packet
+pub const HANDSHAKE_PACKET_LEN: usize = 2 + SALT_LEN + AUTH_LEN;
+
 /// FEAGI commands (parsed from binary packets)
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
@@ -23,16 +43,69 @@ pub enum Command {
 }
 
 /// FEAGI protocol handler
-pub struct FeagiProtocol {
-    rx_buffer: Vec<u8, 256>,
+///
+/// `RX` is the receive buffer capacity (`buffers.usb_rx_buffer_bytes`); it
+/// must hold at least one full packet (257 bytes).
+pub struct FeagiProtocol<const RX: usize = USB_RX_BUFFER_SIZE> {
+    rx_buffer: Vec<u8, RX>,
     commands: Vec<Command, 8>,
+    link: Option<SecureLink>,
+    /// The host asked for the hello since the last `take_hello_request`
+    hello_requested: bool,
 }
 
 impl FeagiProtocol {
     pub fn new() -> Self {
+        Self::with_capacity(None)
+    }
+
+    /// Require every command to arrive sealed with `link`
+    pub fn with_link(link: SecureLink) -> Self {
+        Self::with_capacity(Some(link))
+    }
+}
+
+impl<const RX: usize> FeagiProtocol<RX> {
+    /// Create a handler with an explicit receive buffer capacity
+    pub fn with_capacity(link: Option<SecureLink>) -> Self {
         Self {
             rx_buffer: Vec::new(),
             commands: Vec::new(),
+            link,
+            hello_requested: false,
+        }
+    }
+
+    /// Hello packet announcing the board's salt (None when the link is plaintext)
+    pub fn hello_packet(&self) -> Option<[u8; HANDSHAKE_PACKET_LEN]> {
+        let link = self.link.as_ref()?;
+        let mut packet = [0u8; HANDSHAKE_PACKET_LEN];
+        packet[0] = CMD_HELLO;
+        packet[1] = (SALT_LEN + AUTH_LEN) as u8;
+        packet[2..2 + SALT_LEN].copy_from_slice(&link.local_salt());
+        packet[2 + SALT_LEN..].copy_from_slice(&link.hello_auth());
+        Some(packet)
+    }
+
+    /// Did the host ask for the hello again (e.g. after losing its session)?
+    pub fn take_hello_request(&mut self) -> bool {
+        core::mem::take(&mut self.hello_requested)
+    }
+
+    /// Seal an outgoing packet into a `0xE0` envelope (plaintext passes through)
+    pub fn seal_packet<const N: usize>(&mut self, packet: &[u8], out: &mut Vec<u8, N>) -> bool {
+        out.clear();
+        match self.link.as_mut() {
+            None => out.extend_from_slice(packet).is_ok(),
+            Some(link) => {
+                let mut sealed: Vec<u8, 256> = Vec::new();
+                if link.seal(packet, &mut sealed).is_err() || sealed.len() > 255 {
+                    return false;
+                }
+                out.push(CMD_SEALED).is_ok()
+                    && out.push(sealed.len() as u8).is_ok()
+                    && out.extend_from_slice(&sealed).is_ok()
+            }
         }
     }
     
@@ -68,58 +141,45 @@ impl FeagiProtocol {
             }
             
             // Extract payload
-            let payload = &self.rx_buffer[2..2 + payload_len];
-            
-            // Parse command
-            match cmd_id {
-                0x01 => {
-                    // NeuronFiring
-                    if payload_len >= 1 && payload_len % 2 == 1 {
-                        let count = payload[0] as usize;
-                        let mut coords = Vec::new();
-                        for i in 0..count {
-                            if 1 + i * 2 + 1 < payload.len() {
-                                let x = payload[1 + i * 2];
-                                let y = payload[1 + i * 2 + 1];
-                                let _ = coords.push((x, y));
-                            }
-                        }
-                        let _ = self.commands.push(Command::NeuronFiring { coordinates: coords });
+            let mut payload: Vec<u8, 256> = Vec::new();
+            let _ = payload.extend_from_slice(&self.rx_buffer[2..2 + payload_len]);
+
+            match (cmd_id, self.link.as_mut()) {
+                (CMD_HOST_SALT, Some(link)) => {
+                    // Unauthenticated salts are dropped; the session goes on
+                    if payload_len == SALT_LEN + AUTH_LEN {
+                        let mut salt = [0u8; SALT_LEN];
+                        let mut auth = [0u8; AUTH_LEN];
+                        salt.copy_from_slice(&payload[..SALT_LEN]);
+                        auth.copy_from_slice(&payload[SALT_LEN..]);
+                        let _ = link.accept_host(&salt, &auth);
                     }
                 }
-                0x02 => {
-                    // SetGpio
-                    if payload_len == 2 {
-                        let pin = payload[0];
-                        let value = payload[1] != 0;
-                        let _ = self.commands.push(Command::SetGpio { pin, value });
+                (CMD_HELLO_REQUEST, Some(_)) => self.hello_requested = true,
+                (CMD_SEALED, Some(link)) => {
+                    let mut plain: Vec<u8, 256> = Vec::new();
+                    if link.open(&payload, &mut plain).is_ok() {
+                        // A sealed envelope holds complete packets only
+                        let mut rest = plain.as_slice();
+                        while rest.len() >= 2 && rest.len() >= 2 + rest[1] as usize {
+                            let len = rest[1] as usize;
+                            if let Some(cmd) = decode_command(rest[0], &rest[2..2 + len]) {
+                                let _ = self.commands.push(cmd);
+                            }
+                            rest = &rest[2 + len..];
+                        }
                     }
                 }
-                0x03 => {
-                    // SetPwm
-                    if payload_len == 2 {
-                        let pin = payload[0];
-                        let duty = payload[1];
-                        let _ = self.commands.push(Command::SetPwm { pin, duty });
-                    }
+                (_, Some(_)) => {
+                    // Plaintext command on an encrypted link - drop
                 }
-                0x04 => {
-                    // SetLedMatrix
-                    if payload_len == 25 {
-                        let mut data = [0u8; 25];
-                        data.copy_from_slice(payload);
-                        let _ = self.commands.push(Command::SetLedMatrix { data });
+                (_, None) => {
+                    if let Some(cmd) = decode_command(cmd_id, &payload) {
+                        let _ = self.commands.push(cmd);
                     }
                 }
-                0x05 => {
-                    // GetCapabilities
-                    let _ = self.commands.push(Command::GetCapabilities);
-                }
-                _ => {
-                    // Unknown command - skip
-                }
             }
-            
+
             // Remove processed packet from buffer
             for _ in 0..(2 + payload_len) {
                 self.rx_buffer.remove(0);
@@ -128,6 +188,60 @@ impl FeagiProtocol {
     }
 }
 
+/// Decode one plaintext packet into a command
+fn decode_command(cmd_id: u8, payload: &[u8]) -> Option<Command> {
+    let payload_len = payload.len();
+    match cmd_id {
+        0x01 => {
+            // NeuronFiring
+            if payload_len >= 1 && payload_len % 2 == 1 {
+                let count = payload[0] as usize;
+                let mut coords = Vec::new();
+                for i in 0..count {
+                    if 1 + i * 2 + 1 < payload.len() {
+                        let x = payload[1 + i * 2];
+                        let y = payload[1 + i * 2 + 1];
+                        let _ = coords.push((x, y));
+                    }
+                }
+                return Some(Command::NeuronFiring { coordinates: coords });
+            }
+            None
+        }
+        0x02 => {
+            // SetGpio
+            if payload_len == 2 {
+                return Some(Command::SetGpio { pin: payload[0], value: payload[1] != 0 });
+            }
+            None
+        }
+        0x03 => {
+            // SetPwm
+            if payload_len == 2 {
+                return Some(Command::SetPwm { pin: payload[0], duty: payload[1] });
+            }
+            None
+        }
+        0x04 => {
+            // SetLedMatrix
+            if payload_len == 25 {
+                let mut data = [0u8; 25];
+                data.copy_from_slice(payload);
+                return Some(Command::SetLedMatrix { data });
+            }
+            None
+        }
+        0x05 => {
+            // GetCapabilities
+            Some(Command::GetCapabilities)
+        }
+        _ => {
+            // Unknown command - skip
+            None
+        }
+    }
+}
+
 impl Default for FeagiProtocol {
     fn default() -> Self {
         Self::new()
@@ -1,4 +1,42 @@
 //! Sensor reading module for micro:bit
+//!
+//! The V2's LSM303AGR (accelerometer + magnetometer, internal I2C bus) is read
+//! through embassy-nrf's async TWIM driver. Transfers run on EasyDMA and the
+//! task awaits their completion interrupt, so polling the sensors never blocks
+//! the executor (and the BLE tasks on it) while the bus is busy. Every
+//! transfer is bounded by a timeout, so a wedged bus costs one reading, not
+//! the connection.
+
+use embassy_time::{with_timeout, Duration};
+use microbit_bsp::embassy_nrf::gpio::Input;
+use microbit_bsp::embassy_nrf::peripherals::TWISPI0;
+use microbit_bsp::embassy_nrf::twim::Twim;
+
+use crate::{SENSOR_ACCEL_ENABLED, SENSOR_BUTTONS_ENABLED, SENSOR_MAG_ENABLED, SENSOR_TEMP_ENABLED};
+
+/// LSM303AGR accelerometer I2C address
+const ACCEL_ADDR: u8 = 0x19;
+/// LSM303AGR magnetometer I2C address
+const MAG_ADDR: u8 = 0x1E;
+
+// Accelerometer registers
+const WHO_AM_I_A: u8 = 0x0F;
+const TEMP_CFG_REG_A: u8 = 0x1F;
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG4_A: u8 = 0x23;
+const OUT_TEMP_L_A: u8 = 0x0C;
+const OUT_X_L_A: u8 = 0x28;
+// Magnetometer registers
+const WHO_AM_I_M: u8 = 0x4F;
+const CFG_REG_A_M: u8 = 0x60;
+const CFG_REG_C_M: u8 = 0x62;
+const OUTX_L_REG_M: u8 = 0x68;
+
+/// Set on a register address to auto-increment through a multi-byte read
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// Upper bound for one I2C transfer (a 6-byte read takes ~200µs at 400kHz)
+const TRANSFER_TIMEOUT: Duration = Duration::from_millis(5);
 
 #[derive(Debug, Clone)]
 pub struct SensorData {
@@ -10,52 +48,146 @@ pub struct SensorData {
 }
 
 pub struct Sensors {
-    // TODO: Add I2C sensor drivers (LSM303AGR for V2, MMA8653 for V1)
-    // For Phase 2, we'll return mock sensor data
+    twim: Twim<'static, TWISPI0>,
+    button_a: Input<'static>,
+    button_b: Input<'static>,
+    accel_ok: bool,
+    mag_ok: bool,
 }
 
 impl Sensors {
-    pub fn new() -> Self {
-        // Simplified initialization for Phase 2
-        // Full I2C sensor setup is complex and requires careful error handling
-        // TODO: Initialize I2C and sensor drivers
-        Self {}
-    }
-    
-    pub fn read_all(&mut self) -> SensorData {
-        // TODO: Implement actual I2C sensor reading
-        // For now, return mock data that simulates real sensors
-        
-        // Simulate a slowly changing accelerometer (as if device is tilting)
-        static mut TICK: u32 = 0;
-        let t = unsafe {
-            TICK += 1;
-            TICK
+    /// Configure the LSM303AGR; sensors that don't answer are reported as None
+    pub async fn new(twim: Twim<'static, TWISPI0>, button_a: Input<'static>, button_b: Input<'static>) -> Self {
+        let mut sensors = Self {
+            twim,
+            button_a,
+            button_b,
+            accel_ok: false,
+            mag_ok: false,
         };
-        
-        // Simple oscillating values without transcendental functions
-        // (no_std doesn't have sin/cos/sqrt by default)
-        let phase = (t % 100) as f32 / 100.0; // 0.0 to 1.0
-        let accel_x = if phase < 0.5 { phase * 2.0 - 0.5 } else { 1.5 - phase * 2.0 };
-        let accel_y = if phase < 0.5 { 0.5 - phase * 2.0 } else { phase * 2.0 - 1.5 };
-        let accel_z = 0.8; // Mostly downward (resting on table)
-        
+
+        if SENSOR_ACCEL_ENABLED || SENSOR_TEMP_ENABLED {
+            sensors.accel_ok = sensors.read_reg(ACCEL_ADDR, WHO_AM_I_A).await == Some(0x33)
+                // 100 Hz, normal mode, X/Y/Z enabled
+                && sensors.write_reg(ACCEL_ADDR, CTRL_REG1_A, 0x57).await
+                // Block data update (required for the temperature sensor), ±2g
+                && sensors.write_reg(ACCEL_ADDR, CTRL_REG4_A, 0x80).await
+                && sensors.write_reg(ACCEL_ADDR, TEMP_CFG_REG_A, 0xC0).await;
+        }
+        if SENSOR_MAG_ENABLED {
+            sensors.mag_ok = sensors.read_reg(MAG_ADDR, WHO_AM_I_M).await == Some(0x40)
+                // Temperature compensation, 10 Hz, continuous mode
+                && sensors.write_reg(MAG_ADDR, CFG_REG_A_M, 0x80).await
+                // Block data update
+                && sensors.write_reg(MAG_ADDR, CFG_REG_C_M, 0x10).await;
+        }
+        sensors
+    }
+
+    pub async fn read_all(&mut self) -> SensorData {
+        let (button_a, button_b) = self.read_buttons();
         SensorData {
-            accelerometer: Some([accel_x * 0.3, accel_y * 0.3, accel_z]),
-            magnetometer: Some([20.0, 30.0, -45.0]), // Static magnetic field
-            temperature: Some(23.5 + (phase - 0.5) * 1.0), // 23.0 to 24.0
-            button_a: false, // TODO: Read actual button state
-            button_b: false, // TODO: Read actual button state
+            accelerometer: if SENSOR_ACCEL_ENABLED && self.accel_ok { self.read_accel().await } else { None },
+            magnetometer: if SENSOR_MAG_ENABLED && self.mag_ok { self.read_mag().await } else { None },
+            temperature: if SENSOR_TEMP_ENABLED && self.accel_ok { self.read_temp().await } else { None },
+            button_a,
+            button_b,
         }
     }
-    
+
     pub fn read_buttons(&self) -> (bool, bool) {
-        // TODO: Implement actual button reading
-        // Buttons are on GPIO pins:
-        // V2: Button A = P0.14, Button B = P0.23
-        // V1: Button A = P0.17, Button B = P0.26
-        (false, false)
+        if !SENSOR_BUTTONS_ENABLED {
+            return (false, false);
+        }
+        // Buttons pull the pin low when pressed
+        (self.button_a.is_low(), self.button_b.is_low())
+    }
+
+    async fn read_accel(&mut self) -> Option<[f32; 3]> {
+        let mut buf = [0u8; 6];
+        self.read_block(ACCEL_ADDR, OUT_X_L_A, &mut buf).await?;
+        Some(decode_accel(&buf))
+    }
+
+    async fn read_mag(&mut self) -> Option<[f32; 3]> {
+        let mut buf = [0u8; 6];
+        self.read_block(MAG_ADDR, OUTX_L_REG_M, &mut buf).await?;
+        Some(decode_mag(&buf))
+    }
+
+    async fn read_temp(&mut self) -> Option<f32> {
+        let mut buf = [0u8; 2];
+        self.read_block(ACCEL_ADDR, OUT_TEMP_L_A, &mut buf).await?;
+        Some(decode_temp(&buf))
+    }
+
+    async fn read_reg(&mut self, addr: u8, reg: u8) -> Option<u8> {
+        let mut buf = [0u8; 1];
+        self.read_block(addr, reg, &mut buf).await?;
+        Some(buf[0])
+    }
+
+    async fn read_block(&mut self, addr: u8, reg: u8, buf: &mut [u8]) -> Option<()> {
+        let reg = if buf.len() > 1 { reg | AUTO_INCREMENT } else { reg };
+        // EasyDMA can only read from RAM, so the register address lives on the stack
+        let write = [reg];
+        match with_timeout(TRANSFER_TIMEOUT, self.twim.write_read(addr, &write, buf)).await {
+            Ok(Ok(())) => Some(()),
+            _ => None,
+        }
+    }
+
+    async fn write_reg(&mut self, addr: u8, reg: u8, value: u8) -> bool {
+        let write = [reg, value];
+        matches!(with_timeout(TRANSFER_TIMEOUT, self.twim.write(addr, &write)).await, Ok(Ok(())))
     }
 }
 
+/// Accelerometer output (normal mode: 10-bit, left-justified, 4 mg/digit at ±2g) in g
+fn decode_accel(buf: &[u8; 6]) -> [f32; 3] {
+    let axis = |i: usize| (i16::from_le_bytes([buf[i], buf[i + 1]]) >> 6) as f32 * 0.004;
+    [axis(0), axis(2), axis(4)]
+}
+
+/// Magnetometer output (1.5 mgauss/digit) in µT
+fn decode_mag(buf: &[u8; 6]) -> [f32; 3] {
+    let axis = |i: usize| i16::from_le_bytes([buf[i], buf[i + 1]]) as f32 * 0.15;
+    [axis(0), axis(2), axis(4)]
+}
 
+/// Temperature output (normal mode: 8-bit, 1 °C/digit, offset from 25 °C)
+fn decode_temp(buf: &[u8; 2]) -> f32 {
+    25.0 + buf[1] as i8 as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_accel() {
+        // 1g on Z: 250 digits << 6 = 0x3E80; -0.5g on X: -125 << 6 = 0xE0C0
+        let buf = [0xC0, 0xE0, 0x00, 0x00, 0x80, 0x3E];
+        let accel = decode_accel(&buf);
+        assert!((accel[0] + 0.5).abs() < 1e-6);
+        assert_eq!(accel[1], 0.0);
+        assert!((accel[2] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_decode_mag() {
+        // 200 digits = 30 µT, -100 digits = -15 µT
+        let buf = [0xC8, 0x00, 0x9C, 0xFF, 0x00, 0x00];
+        let mag = decode_mag(&buf);
+        assert!((mag[0] - 30.0).abs() < 1e-4);
+        assert!((mag[1] + 15.0).abs() < 1e-4);
+        assert_eq!(mag[2], 0.0);
+    }
+
+    #[test]
+    fn test_decode_temp() {
+        assert_eq!(decode_temp(&[0x00, 0x00]), 25.0);
+        assert_eq!(decode_temp(&[0x00, 0xFE]), 23.0);
+        assert_eq!(decode_temp(&[0x00, 0x05]), 30.0);
+    }
+}
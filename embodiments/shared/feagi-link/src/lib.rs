@@ -0,0 +1,21 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Link layer shared by the FEAGI board firmwares
+//!
+//! The ESP32 controller and the micro:bit speak the same link layer, so a
+//! host (or the FEAGI desktop BLE bridge) handles both boards the same way:
+//!
+//! - [`segment`]: MTU-aware frame segmentation and reassembly, switched on
+//!   by the host
+//! - [`secure_link`]: the optional ChaCha20-Poly1305 link encryption and its
+//!   authenticated handshake
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod secure_link;
+pub mod segment;
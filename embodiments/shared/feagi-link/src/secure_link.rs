@@ -0,0 +1,494 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! Optional authenticated encryption of a board link (transport-agnostic)
+//!
+//! For deployments where the link runs through shared USB hubs, extenders or
+//! radio, every protocol frame can be sealed with ChaCha20-Poly1305 under a
+//! pre-shared key. The boards carry the handshake differently (hex JSON lines
+//! on the ESP32, binary packets on the micro:bit); the messages are the same.
+//!
+//! **Handshake:** both ends pick a random 16-byte salt.
+//! - The board announces its salt D with `auth = HMAC(psk, "feagi-link hello" || D)`.
+//! - The host checks that, and answers with its salt H and
+//!   `auth = HMAC(psk, "feagi-link host" || D || H)`; the board drops any
+//!   answer that doesn't authenticate against its current D.
+//! - Both derive the session from HKDF-SHA256(psk, salt = D || H): a 32-byte
+//!   key, the board's next salt and the host's next salt. Each salt thus
+//!   serves one handshake only: an old answer never starts a session again,
+//!   and a replayed hello meets a new host salt, so a new key.
+//!
+//! `auth` is the HMAC-SHA256 truncated to 16 bytes.
+//!
+//! **Sealed frame:** `counter:8 LE || ciphertext || tag:16`, nonce =
+//! direction (1 byte) || 0 (3 bytes) || counter (8 bytes LE). The key is new
+//! for every session, so counters restarting with it never reuse a nonce,
+//! even when a board's RNG repeats a salt after a reboot: the host's salt
+//! changes the key. Counters must strictly increase within a session.
+
+use chacha20poly1305::aead::AeadInPlace;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce, Tag};
+use heapless::Vec;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Bytes of a handshake salt
+pub const SALT_LEN: usize = 16;
+/// Bytes of a handshake authenticator
+pub const AUTH_LEN: usize = 16;
+/// Hex characters of a salt or authenticator
+pub const HEX_LEN: usize = 2 * SALT_LEN;
+
+const DIR_DEVICE_TO_HOST: u8 = 0x01;
+const DIR_HOST_TO_DEVICE: u8 = 0x02;
+
+const HELLO_LABEL: &[u8] = b"feagi-link hello";
+const HOST_LABEL: &[u8] = b"feagi-link host";
+const SESSION_LABEL: &[u8] = b"feagi-link session";
+
+const COUNTER_LEN: usize = 8;
+const TAG_LEN: usize = 16;
+
+/// Which end of the link this instance is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Device,
+    Host,
+}
+
+/// Reasons a frame or handshake message was not accepted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkError {
+    /// No handshake has completed yet
+    NoSession,
+    /// Handshake message not authenticated by the key (forged, stale or
+    /// wrong key)
+    BadAuth,
+    /// Sealed payload shorter than counter + tag, or not valid hex
+    Malformed,
+    /// Counter not greater than the last accepted one
+    Replay,
+    /// Authentication tag mismatch (tampered or wrong key)
+    BadTag,
+    /// Output buffer too small
+    BufferFull,
+}
+
+struct Session {
+    cipher: ChaCha20Poly1305,
+    tx_counter: u64,
+    rx_counter: Option<u64>,
+}
+
+/// Handshake and sealing state for one end of the link
+pub struct SecureLink {
+    psk: [u8; 32],
+    role: Role,
+    /// This end's salt for the next handshake
+    local_salt: [u8; SALT_LEN],
+    session: Option<Session>,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+impl SecureLink {
+    /// `local_salt` must come from a hardware or OS random generator
+    pub fn new(psk: &[u8; 32], local_salt: [u8; SALT_LEN], role: Role) -> Self {
+        Self {
+            psk: *psk,
+            role,
+            local_salt,
+            session: None,
+        }
+    }
+
+    /// The salt this end brings to the next handshake: the board's current
+    /// challenge, or the host's next answer
+    pub fn local_salt(&self) -> [u8; SALT_LEN] {
+        self.local_salt
+    }
+
+    /// Has a handshake completed?
+    pub fn is_established(&self) -> bool {
+        self.session.is_some()
+    }
+
+    fn mac(&self, label: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.psk).expect("HMAC takes any key length");
+        mac.update(label);
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+
+    fn auth(&self, label: &[u8], parts: &[&[u8]]) -> [u8; AUTH_LEN] {
+        let mut auth = [0u8; AUTH_LEN];
+        auth.copy_from_slice(&self.mac(label, parts).finalize().into_bytes()[..AUTH_LEN]);
+        auth
+    }
+
+    /// Constant-time check of a truncated HMAC
+    fn verify(&self, label: &[u8], parts: &[&[u8]], auth: &[u8; AUTH_LEN]) -> Result<(), LinkError> {
+        self.mac(label, parts).verify_truncated_left(auth).map_err(|_| LinkError::BadAuth)
+    }
+
+    /// Start the session of handshake D || H; returns this end's next salt
+    fn start_session(&mut self, device_salt: &[u8; SALT_LEN], host_salt: &[u8; SALT_LEN]) -> [u8; SALT_LEN] {
+        let mut salt = [0u8; 2 * SALT_LEN];
+        salt[..SALT_LEN].copy_from_slice(device_salt);
+        salt[SALT_LEN..].copy_from_slice(host_salt);
+        // Key, the board's next salt, the host's next salt
+        let mut okm = [0u8; 32 + 2 * SALT_LEN];
+        Hkdf::<Sha256>::new(Some(&salt), &self.psk)
+            .expand(SESSION_LABEL, &mut okm)
+            .expect("64 bytes is a valid HKDF-SHA256 length");
+        self.session = Some(Session {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&okm[..32])),
+            tx_counter: 0,
+            rx_counter: None,
+        });
+        let next = match self.role {
+            Role::Device => &okm[32..32 + SALT_LEN],
+            Role::Host => &okm[32 + SALT_LEN..],
+        };
+        let mut next_salt = [0u8; SALT_LEN];
+        next_salt.copy_from_slice(next);
+        next_salt
+    }
+
+    /// The board's hello authenticator for its current salt
+    pub fn hello_auth(&self) -> [u8; AUTH_LEN] {
+        self.auth(HELLO_LABEL, &[&self.local_salt])
+    }
+
+    /// Board: accept the host's answer to the current salt, starting a new
+    /// session (the previous one, if any, ends)
+    pub fn accept_host(&mut self, host_salt: &[u8; SALT_LEN], auth: &[u8; AUTH_LEN]) -> Result<(), LinkError> {
+        let device_salt = self.local_salt;
+        self.verify(HOST_LABEL, &[&device_salt, host_salt], auth)?;
+        self.local_salt = self.start_session(&device_salt, host_salt);
+        Ok(())
+    }
+
+    /// Host: accept a board's hello, starting a new session; returns the
+    /// salt and authenticator to answer with. The host salt is used up, so
+    /// the same hello again gets a different session.
+    pub fn accept_hello(&mut self, device_salt: &[u8; SALT_LEN], auth: &[u8; AUTH_LEN]) -> Result<([u8; SALT_LEN], [u8; AUTH_LEN]), LinkError> {
+        self.verify(HELLO_LABEL, &[device_salt], auth)?;
+        let host_salt = self.local_salt;
+        self.local_salt = self.start_session(device_salt, &host_salt);
+        Ok((host_salt, self.auth(HOST_LABEL, &[device_salt, &host_salt])))
+    }
+
+    fn nonce(direction: u8, counter: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[0] = direction;
+        nonce[4..12].copy_from_slice(&counter.to_le_bytes());
+        nonce
+    }
+
+    /// Seal `plain` into `out` as counter || ciphertext || tag
+    pub fn seal<const N: usize>(&mut self, plain: &[u8], out: &mut Vec<u8, N>) -> Result<(), LinkError> {
+        out.clear();
+        let direction = match self.role {
+            Role::Device => DIR_DEVICE_TO_HOST,
+            Role::Host => DIR_HOST_TO_DEVICE,
+        };
+        let session = self.session.as_mut().ok_or(LinkError::NoSession)?;
+        let counter = session.tx_counter;
+        out.extend_from_slice(&counter.to_le_bytes()).map_err(|_| LinkError::BufferFull)?;
+        out.extend_from_slice(plain).map_err(|_| LinkError::BufferFull)?;
+        let tag = session
+            .cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&Self::nonce(direction, counter)), &[], &mut out[COUNTER_LEN..])
+            .map_err(|_| LinkError::BufferFull)?;
+        out.extend_from_slice(tag.as_slice()).map_err(|_| LinkError::BufferFull)?;
+        session.tx_counter += 1;
+        Ok(())
+    }
+
+    /// Open a sealed payload from the peer into `out`
+    pub fn open<const N: usize>(&mut self, sealed: &[u8], out: &mut Vec<u8, N>) -> Result<(), LinkError> {
+        out.clear();
+        out.extend_from_slice(sealed).map_err(|_| LinkError::BufferFull)?;
+        self.open_in_place(out)
+    }
+
+    /// Open a sealed payload in `buf`, leaving the plaintext there
+    pub fn open_in_place<const N: usize>(&mut self, buf: &mut Vec<u8, N>) -> Result<(), LinkError> {
+        let direction = match self.role {
+            Role::Device => DIR_HOST_TO_DEVICE,
+            Role::Host => DIR_DEVICE_TO_HOST,
+        };
+        let session = self.session.as_mut().ok_or(LinkError::NoSession)?;
+        if buf.len() < COUNTER_LEN + TAG_LEN {
+            return Err(LinkError::Malformed);
+        }
+        let mut counter_bytes = [0u8; COUNTER_LEN];
+        counter_bytes.copy_from_slice(&buf[..COUNTER_LEN]);
+        let counter = u64::from_le_bytes(counter_bytes);
+        if let Some(last) = session.rx_counter {
+            if counter <= last {
+                return Err(LinkError::Replay);
+            }
+        }
+
+        let body_end = buf.len() - TAG_LEN;
+        let tag = *Tag::from_slice(&buf[body_end..]);
+        session
+            .cipher
+            .decrypt_in_place_detached(Nonce::from_slice(&Self::nonce(direction, counter)), &[], &mut buf[COUNTER_LEN..body_end], &tag)
+            .map_err(|_| LinkError::BadTag)?;
+        session.rx_counter = Some(counter);
+        buf.truncate(body_end);
+        buf.rotate_left(COUNTER_LEN);
+        buf.truncate(body_end - COUNTER_LEN);
+        Ok(())
+    }
+
+    /// Seal a protocol line into `E<hex>\n` form
+    ///
+    /// `N` must hold the hex line, so it's also ample for the raw sealed bytes.
+    pub fn seal_line<const N: usize>(&mut self, plain: &[u8], out: &mut Vec<u8, N>) -> Result<(), LinkError> {
+        // Strip the trailing newline: it's re-added outside the sealed payload
+        let plain = plain.strip_suffix(b"\n").unwrap_or(plain);
+        let mut sealed: Vec<u8, N> = Vec::new();
+        self.seal(plain, &mut sealed)?;
+        out.clear();
+        out.push(b'E').map_err(|_| LinkError::BufferFull)?;
+        for byte in sealed.iter() {
+            out.push(HEX[(byte >> 4) as usize]).map_err(|_| LinkError::BufferFull)?;
+            out.push(HEX[(byte & 0x0f) as usize]).map_err(|_| LinkError::BufferFull)?;
+        }
+        out.push(b'\n').map_err(|_| LinkError::BufferFull)?;
+        Ok(())
+    }
+
+    /// Open an `E<hex>` line from the peer into `out`
+    pub fn open_line<const N: usize>(&mut self, line: &str, out: &mut Vec<u8, N>) -> Result<(), LinkError> {
+        let hex = line.strip_prefix('E').ok_or(LinkError::Malformed)?;
+        decode_hex(hex.trim_end().as_bytes(), out)?;
+        self.open_in_place(out)
+    }
+}
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode lowercase/uppercase hex into bytes
+pub fn decode_hex<const N: usize>(hex: &[u8], out: &mut Vec<u8, N>) -> Result<(), LinkError> {
+    let pairs = hex.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(LinkError::Malformed);
+    }
+    out.clear();
+    for pair in pairs {
+        let hi = hex_nibble(pair[0]).ok_or(LinkError::Malformed)?;
+        let lo = hex_nibble(pair[1]).ok_or(LinkError::Malformed)?;
+        out.push((hi << 4) | lo).map_err(|_| LinkError::BufferFull)?;
+    }
+    Ok(())
+}
+
+/// Encode a salt or authenticator as 32 hex characters
+pub fn to_hex(bytes: &[u8; SALT_LEN]) -> [u8; HEX_LEN] {
+    let mut hex = [0u8; HEX_LEN];
+    for (i, byte) in bytes.iter().enumerate() {
+        hex[i * 2] = HEX[(byte >> 4) as usize];
+        hex[i * 2 + 1] = HEX[(byte & 0x0f) as usize];
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<[u8; SALT_LEN]> {
+    let mut bytes: Vec<u8, SALT_LEN> = Vec::new();
+    decode_hex(hex.get(..HEX_LEN)?.as_bytes(), &mut bytes).ok()?;
+    bytes.into_array().ok()
+}
+
+/// Parse the host's answer `{"enc_salt":"<32 hex>","auth":"<32 hex>"}` into
+/// its salt and authenticator
+pub fn parse_peer_salt(line: &str) -> Option<([u8; SALT_LEN], [u8; AUTH_LEN])> {
+    let rest = line.strip_prefix("{\"enc_salt\":\"")?;
+    let salt = from_hex(rest)?;
+    let rest = rest.get(HEX_LEN..)?.strip_prefix("\",\"auth\":\"")?;
+    Some((salt, from_hex(rest)?))
+}
+
+/// Does `line` ask for the hello again (`{"enc_hello":1}`), as a host that
+/// lost its session does? Answering doesn't touch the current session.
+pub fn is_hello_request(line: &str) -> bool {
+    line.trim_end() == "{\"enc_hello\":1}"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PSK: [u8; 32] = [7u8; 32];
+
+    fn handshake(device: &mut SecureLink, host: &mut SecureLink) {
+        let (host_salt, auth) = host.accept_hello(&device.local_salt(), &device.hello_auth()).unwrap();
+        device.accept_host(&host_salt, &auth).unwrap();
+    }
+
+    fn paired() -> (SecureLink, SecureLink) {
+        let mut device = SecureLink::new(&PSK, [1; SALT_LEN], Role::Device);
+        let mut host = SecureLink::new(&PSK, [2; SALT_LEN], Role::Host);
+        handshake(&mut device, &mut host);
+        (device, host)
+    }
+
+    #[test]
+    fn test_round_trip_both_directions() {
+        let (mut device, mut host) = paired();
+        let mut sealed: Vec<u8, 64> = Vec::new();
+        let mut plain: Vec<u8, 64> = Vec::new();
+
+        host.seal(&[0x02, 0x02, 0x00, 0x01], &mut sealed).unwrap();
+        device.open(&sealed, &mut plain).unwrap();
+        assert_eq!(plain.as_slice(), &[0x02, 0x02, 0x00, 0x01]);
+
+        device.seal(b"sensor", &mut sealed).unwrap();
+        host.open(&sealed, &mut plain).unwrap();
+        assert_eq!(plain.as_slice(), b"sensor");
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        let (mut device, mut host) = paired();
+        let mut line: Vec<u8, 128> = Vec::new();
+        let mut plain: Vec<u8, 128> = Vec::new();
+
+        device.seal_line(b"{\"gpio\":1}\n", &mut line).unwrap();
+        assert_eq!(line[0], b'E');
+        host.open_line(core::str::from_utf8(&line).unwrap(), &mut plain).unwrap();
+        assert_eq!(plain.as_slice(), b"{\"gpio\":1}");
+    }
+
+    #[test]
+    fn test_replay_rejected() {
+        let (mut device, mut host) = paired();
+        let mut sealed: Vec<u8, 64> = Vec::new();
+        let mut plain: Vec<u8, 64> = Vec::new();
+
+        host.seal(b"cmd", &mut sealed).unwrap();
+        device.open(&sealed, &mut plain).unwrap();
+        assert_eq!(device.open(&sealed, &mut plain), Err(LinkError::Replay));
+    }
+
+    #[test]
+    fn test_tampered_and_wrong_key_rejected() {
+        let (mut device, mut host) = paired();
+        let mut sealed: Vec<u8, 64> = Vec::new();
+        let mut plain: Vec<u8, 64> = Vec::new();
+
+        host.seal(b"cmd", &mut sealed).unwrap();
+        sealed[COUNTER_LEN] ^= 0x01;
+        assert_eq!(device.open(&sealed, &mut plain), Err(LinkError::BadTag));
+
+        let mut other = SecureLink::new(&[9u8; 32], [3; SALT_LEN], Role::Host);
+        assert_eq!(
+            other.accept_hello(&device.local_salt(), &device.hello_auth()),
+            Err(LinkError::BadAuth)
+        );
+    }
+
+    #[test]
+    fn test_unauthenticated_handshake_keeps_session() {
+        let (mut device, mut host) = paired();
+        let mut sealed: Vec<u8, 64> = Vec::new();
+        let mut plain: Vec<u8, 64> = Vec::new();
+
+        // Anyone can send a salt, but without the key it neither starts a
+        // session nor resets the receive counter
+        host.seal(b"first", &mut sealed).unwrap();
+        assert_eq!(device.accept_host(&[5; SALT_LEN], &[0; AUTH_LEN]), Err(LinkError::BadAuth));
+        device.open(&sealed, &mut plain).unwrap();
+        assert_eq!(device.open(&sealed, &mut plain), Err(LinkError::Replay));
+
+        assert_eq!(SecureLink::new(&PSK, [1; SALT_LEN], Role::Device).open(&sealed, &mut plain), Err(LinkError::NoSession));
+    }
+
+    #[test]
+    fn test_old_answer_and_frames_rejected_after_rehandshake() {
+        let mut device = SecureLink::new(&PSK, [1; SALT_LEN], Role::Device);
+        let mut host = SecureLink::new(&PSK, [2; SALT_LEN], Role::Host);
+        let first_salt = device.local_salt();
+        let (host_salt, auth) = host.accept_hello(&first_salt, &device.hello_auth()).unwrap();
+        device.accept_host(&host_salt, &auth).unwrap();
+        assert_ne!(device.local_salt(), first_salt);
+
+        let mut old: Vec<u8, 64> = Vec::new();
+        let mut plain: Vec<u8, 64> = Vec::new();
+        host.seal(b"old session", &mut old).unwrap();
+
+        // A recorded answer can't restart the session it came from
+        assert_eq!(device.accept_host(&host_salt, &auth), Err(LinkError::BadAuth));
+
+        let mut host = SecureLink::new(&PSK, [4; SALT_LEN], Role::Host);
+        handshake(&mut device, &mut host);
+        assert_eq!(device.open(&old, &mut plain), Err(LinkError::BadTag));
+    }
+
+    #[test]
+    fn test_same_board_salt_new_key() {
+        // A board salt repeated after a reboot meets a new host salt: the
+        // first frames of both sessions share a nonce but not a key
+        let mut first: Vec<u8, 64> = Vec::new();
+        let mut second: Vec<u8, 64> = Vec::new();
+        for (host_salt, out) in [([2; SALT_LEN], &mut first), ([3; SALT_LEN], &mut second)] {
+            let mut device = SecureLink::new(&PSK, [1; SALT_LEN], Role::Device);
+            let mut host = SecureLink::new(&PSK, host_salt, Role::Host);
+            handshake(&mut device, &mut host);
+            device.seal(b"same plaintext", out).unwrap();
+        }
+        assert_eq!(first[..COUNTER_LEN], second[..COUNTER_LEN]);
+        assert_ne!(first[COUNTER_LEN..], second[COUNTER_LEN..]);
+    }
+
+    #[test]
+    fn test_replayed_hello_new_key() {
+        // A captured hello replayed to the same host meets its next salt:
+        // the host's first frames of both sessions share a nonce but not a key
+        let device = SecureLink::new(&PSK, [1; SALT_LEN], Role::Device);
+        let mut host = SecureLink::new(&PSK, [2; SALT_LEN], Role::Host);
+        let (salt, auth) = (device.local_salt(), device.hello_auth());
+        let mut first: Vec<u8, 64> = Vec::new();
+        let mut second: Vec<u8, 64> = Vec::new();
+
+        let (first_host_salt, _) = host.accept_hello(&salt, &auth).unwrap();
+        host.seal(b"same plaintext", &mut first).unwrap();
+        let (second_host_salt, _) = host.accept_hello(&salt, &auth).unwrap();
+        host.seal(b"same plaintext", &mut second).unwrap();
+
+        assert_ne!(first_host_salt, second_host_salt);
+        assert_eq!(first[..COUNTER_LEN], second[..COUNTER_LEN]);
+        assert_ne!(first[COUNTER_LEN..], second[COUNTER_LEN..]);
+    }
+
+    #[test]
+    fn test_parse_host_answer() {
+        let line = "{\"enc_salt\":\"000102030405060708090a0b0c0d0e0f\",\"auth\":\"ffffffffffffffffffffffffffffffff\"}";
+        let (salt, auth) = parse_peer_salt(line).unwrap();
+        assert_eq!(salt[15], 0x0f);
+        assert_eq!(auth, [0xff; AUTH_LEN]);
+        assert_eq!(&to_hex(&salt), b"000102030405060708090a0b0c0d0e0f");
+        assert!(parse_peer_salt("{\"enc_salt\":\"d4e5f6\"}").is_none());
+        assert!(is_hello_request("{\"enc_hello\":1}\n"));
+    }
+}
@@ -0,0 +1,373 @@
+/*
+ * Copyright 2025 Neuraville Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ */
+
+//! MTU-aware frame segmentation and reassembly (transport-agnostic)
+//!
+//! A FEAGI frame can be larger than one transport write: a BLE notification
+//! carries 20-244 bytes depending on the negotiated ATT MTU, a USB CDC packet
+//! 64 bytes, while TCP has no practical limit. Frames are split into segments
+//! that each fit the transport's [`Mtu`] and glued back together on the other
+//! side, so the protocol layer above only ever sees whole frames.
+//!
+//! **Segment format:** `[header] [data...]`, where the header byte is
+//! - bit 7: first segment of a frame
+//! - bit 6: last segment of a frame
+//! - bits 0-5: segment sequence number within the frame (wraps at 64)
+//!
+//! A frame that fits one write is a single segment with both flags set.
+//!
+//! **Negotiation:** a link starts unsegmented, as hosts written before
+//! segmentation expect: each write is passed on as it is, and frames go out
+//! as plain MTU-sized chunks. A host that wants segments sends
+//! [`SEGMENTATION_REQUEST`] as a write of its own; the board answers with
+//! [`SEGMENTATION_ACK`] and segments both ways until the link is reset (a new
+//! connection). A host that gets no answer keeps the link unsegmented.
+
+use heapless::Vec;
+
+/// First segment of a frame
+pub const SEG_FIRST: u8 = 0x80;
+/// Last segment of a frame
+pub const SEG_LAST: u8 = 0x40;
+/// Sequence number bits
+pub const SEG_SEQ_MASK: u8 = 0x3F;
+
+/// Written by a host, as a write of its own, to switch the link to segments
+///
+/// 0xF5 starts neither a JSON line nor a binary packet, and as a segment
+/// header it would be a first segment with a sequence number other than 0.
+pub const SEGMENTATION_REQUEST: [u8; 4] = [0xF5, b'S', b'E', b'G'];
+
+/// The board's answer to [`SEGMENTATION_REQUEST`]: the request, as one segment
+pub const SEGMENTATION_ACK: [u8; 5] = [SEG_FIRST | SEG_LAST, 0xF5, b'S', b'E', b'G'];
+
+/// Largest payload of one transport write, segment header included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mtu(usize);
+
+impl Mtu {
+    /// BLE before (or without) ATT MTU negotiation: 23 - 3 bytes ATT header
+    pub const BLE_DEFAULT: Mtu = Mtu(20);
+    /// USB CDC full-speed bulk packet
+    pub const USB_CDC: Mtu = Mtu(64);
+    /// Stream transports without a payload limit (TCP)
+    pub const UNLIMITED: Mtu = Mtu(usize::MAX);
+
+    /// Explicit payload size; anything below 2 bytes can't carry data
+    pub const fn new(bytes: usize) -> Self {
+        Mtu(if bytes < 2 { 2 } else { bytes })
+    }
+
+    /// Notification payload for a negotiated ATT MTU (clamped to 23-247)
+    pub fn ble(att_mtu: u16) -> Self {
+        Mtu(att_mtu.clamp(23, 247) as usize - 3)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.0
+    }
+}
+
+/// One write's worth of a frame, borrowed from the frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment<'a> {
+    /// None for a plain chunk of an unsegmented link
+    pub header: Option<u8>,
+    pub data: &'a [u8],
+}
+
+impl Segment<'_> {
+    /// Serialize into `out` (header then data); false if it doesn't fit
+    pub fn write_to<const N: usize>(&self, out: &mut Vec<u8, N>) -> bool {
+        out.clear();
+        if let Some(header) = self.header {
+            if out.push(header).is_err() {
+                return false;
+            }
+        }
+        out.extend_from_slice(self.data).is_ok()
+    }
+
+    /// Bytes on the wire
+    pub fn wire_len(&self) -> usize {
+        self.header.is_some() as usize + self.data.len()
+    }
+}
+
+/// Split `frame` into segments no larger than `mtu`
+pub fn segments(frame: &[u8], mtu: Mtu) -> Segments<'_> {
+    Segments {
+        rest: frame,
+        chunk: mtu.bytes() - 1,
+        seq: 0,
+        headers: true,
+        done: false,
+    }
+}
+
+/// Split `frame` into plain chunks no larger than `mtu`, for a link that
+/// isn't segmented; an empty frame has none
+pub fn chunks(frame: &[u8], mtu: Mtu) -> Segments<'_> {
+    Segments {
+        rest: frame,
+        chunk: mtu.bytes(),
+        seq: 0,
+        headers: false,
+        done: frame.is_empty(),
+    }
+}
+
+/// Iterator over the writes of one frame, see [`segments`] and [`chunks`]
+pub struct Segments<'a> {
+    rest: &'a [u8],
+    chunk: usize,
+    seq: u8,
+    headers: bool,
+    done: bool,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Segment<'a>> {
+        if self.done {
+            return None;
+        }
+        let take = self.rest.len().min(self.chunk);
+        let (data, rest) = self.rest.split_at(take);
+        let mut header = self.seq & SEG_SEQ_MASK;
+        if self.seq == 0 {
+            header |= SEG_FIRST;
+        }
+        if rest.is_empty() {
+            header |= SEG_LAST;
+            self.done = true;
+        }
+        self.rest = rest;
+        self.seq = self.seq.wrapping_add(1);
+        Some(Segment { header: self.headers.then_some(header), data })
+    }
+}
+
+/// What one received write amounted to
+#[derive(Debug, PartialEq)]
+pub enum Received<'a> {
+    /// Nothing complete yet
+    Nothing,
+    /// A whole frame (on an unsegmented link, the write itself)
+    Frame(&'a [u8]),
+    /// The host asked for segments; answer with [`SEGMENTATION_ACK`]
+    SegmentationRequested,
+}
+
+/// Reassembles segments into frames of up to `N` bytes
+///
+/// A missing or out-of-order segment, or a frame larger than `N`, drops the
+/// frame being assembled; reassembly resumes at the next first segment.
+/// Until the host sends [`SEGMENTATION_REQUEST`], writes are passed on as
+/// they are.
+pub struct Reassembler<const N: usize> {
+    frame: Vec<u8, N>,
+    /// Sequence number expected next, None while waiting for a first segment
+    expected: Option<u8>,
+    /// The host asked for segments
+    segmented: bool,
+    /// Frames dropped since boot
+    pub dropped: u32,
+}
+
+impl<const N: usize> Reassembler<N> {
+    /// A reassembler for a new, unsegmented link
+    pub fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            expected: None,
+            segmented: false,
+            dropped: 0,
+        }
+    }
+
+    /// Has the host switched the link to segments? Outgoing frames are then
+    /// split with [`segments`], otherwise with [`chunks`]
+    pub fn segmented(&self) -> bool {
+        self.segmented
+    }
+
+    /// Feed one received write
+    pub fn push<'a>(&'a mut self, write: &'a [u8]) -> Received<'a> {
+        if write == SEGMENTATION_REQUEST {
+            // Asked again (the ack got lost): start over
+            self.segmented = true;
+            self.frame.clear();
+            self.expected = None;
+            return Received::SegmentationRequested;
+        }
+        if !self.segmented {
+            return if write.is_empty() { Received::Nothing } else { Received::Frame(write) };
+        }
+        let Some((&header, data)) = write.split_first() else {
+            return Received::Nothing;
+        };
+        let seq = header & SEG_SEQ_MASK;
+        if header & SEG_FIRST != 0 {
+            if self.expected.is_some() {
+                // Previous frame never got its last segment
+                self.dropped = self.dropped.wrapping_add(1);
+            }
+            self.frame.clear();
+            self.expected = Some(seq);
+        }
+        if self.expected != Some(seq) {
+            if self.expected.is_some() {
+                self.drop_frame();
+            }
+            return Received::Nothing;
+        }
+        if self.frame.extend_from_slice(data).is_err() {
+            self.drop_frame();
+            return Received::Nothing;
+        }
+        if header & SEG_LAST != 0 {
+            self.expected = None;
+            return Received::Frame(&self.frame);
+        }
+        self.expected = Some((seq + 1) & SEG_SEQ_MASK);
+        Received::Nothing
+    }
+
+    fn drop_frame(&mut self) {
+        self.frame.clear();
+        self.expected = None;
+        self.dropped = self.dropped.wrapping_add(1);
+    }
+}
+
+impl<const N: usize> Default for Reassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segmented<const N: usize>() -> Reassembler<N> {
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassembler.push(&SEGMENTATION_REQUEST), Received::SegmentationRequested);
+        reassembler
+    }
+
+    fn roundtrip(frame: &[u8], mtu: Mtu) -> usize {
+        let mut reassembler: Reassembler<512> = segmented();
+        let mut count = 0;
+        let mut out = None;
+        for segment in segments(frame, mtu) {
+            assert!(segment.wire_len() <= mtu.bytes());
+            let mut wire: Vec<u8, 512> = Vec::new();
+            assert!(segment.write_to(&mut wire));
+            count += 1;
+            if let Received::Frame(done) = reassembler.push(&wire) {
+                out = Some(std::vec::Vec::from(done));
+            }
+        }
+        assert_eq!(out.as_deref(), Some(frame));
+        count
+    }
+
+    #[test]
+    fn test_small_frame_is_one_segment() {
+        let segs: std::vec::Vec<_> = segments(&[1, 2, 3], Mtu::BLE_DEFAULT).collect();
+        assert_eq!(segs.len(), 1);
+        assert_eq!(segs[0].header, Some(SEG_FIRST | SEG_LAST));
+        assert_eq!(segs[0].data, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_roundtrip_per_transport() {
+        let frame: std::vec::Vec<u8> = (0..300).map(|i| i as u8).collect();
+        // 19 data bytes per default BLE notification
+        assert_eq!(roundtrip(&frame, Mtu::BLE_DEFAULT), 16);
+        assert_eq!(roundtrip(&frame, Mtu::ble(247)), 2);
+        assert_eq!(roundtrip(&frame, Mtu::USB_CDC), 5);
+        assert_eq!(roundtrip(&frame, Mtu::UNLIMITED), 1);
+    }
+
+    #[test]
+    fn test_exact_multiple_and_empty_frame() {
+        let frame = [7u8; 38];
+        assert_eq!(roundtrip(&frame, Mtu::BLE_DEFAULT), 2);
+        assert_eq!(roundtrip(&[], Mtu::USB_CDC), 1);
+    }
+
+    #[test]
+    fn test_ble_mtu_clamped() {
+        assert_eq!(Mtu::ble(0), Mtu::BLE_DEFAULT);
+        assert_eq!(Mtu::ble(512).bytes(), 244);
+    }
+
+    #[test]
+    fn test_missing_segment_drops_frame() {
+        let frame = [9u8; 50];
+        let segs: std::vec::Vec<_> = segments(&frame, Mtu::BLE_DEFAULT).collect();
+        let mut reassembler: Reassembler<128> = segmented();
+        let mut wire: Vec<u8, 32> = Vec::new();
+        segs[0].write_to(&mut wire);
+        assert_eq!(reassembler.push(&wire), Received::Nothing);
+        segs[2].write_to(&mut wire);
+        assert_eq!(reassembler.push(&wire), Received::Nothing);
+        assert_eq!(reassembler.dropped, 1);
+
+        // Next frame still comes through
+        assert_eq!(reassembler.push(&[SEG_FIRST | SEG_LAST, 4, 2]), Received::Frame(&[4, 2]));
+    }
+
+    #[test]
+    fn test_oversized_frame_dropped() {
+        let frame = [1u8; 100];
+        let mut reassembler: Reassembler<64> = segmented();
+        let mut wire: Vec<u8, 64> = Vec::new();
+        for segment in segments(&frame, Mtu::BLE_DEFAULT) {
+            segment.write_to(&mut wire);
+            assert_eq!(reassembler.push(&wire), Received::Nothing);
+        }
+        assert_eq!(reassembler.dropped, 1);
+    }
+
+    #[test]
+    fn test_unsegmented_until_requested() {
+        let mut reassembler: Reassembler<64> = Reassembler::new();
+        assert!(!reassembler.segmented());
+        // A host that predates segmentation: writes are frames as they are
+        assert_eq!(reassembler.push(&[0xC0, 1, 2]), Received::Frame(&[0xC0, 1, 2]));
+        assert_eq!(reassembler.push(b"{\"a\":1}\n"), Received::Frame(b"{\"a\":1}\n"));
+        assert_eq!(reassembler.push(&[]), Received::Nothing);
+
+        assert_eq!(reassembler.push(&SEGMENTATION_REQUEST), Received::SegmentationRequested);
+        assert!(reassembler.segmented());
+        assert_eq!(reassembler.push(&[SEG_FIRST, 1]), Received::Nothing);
+        assert_eq!(reassembler.push(&[SEG_LAST | 1, 2]), Received::Frame(&[1, 2]));
+    }
+
+    #[test]
+    fn test_ack_is_a_segment() {
+        let mut reassembler: Reassembler<8> = segmented();
+        assert_eq!(reassembler.push(&SEGMENTATION_ACK), Received::Frame(&SEGMENTATION_REQUEST));
+    }
+
+    #[test]
+    fn test_plain_chunks() {
+        let frame = [5u8; 50];
+        let writes: std::vec::Vec<_> = chunks(&frame, Mtu::BLE_DEFAULT).collect();
+        assert_eq!(writes.iter().map(|w| w.data.len()).collect::<std::vec::Vec<_>>(), [20, 20, 10]);
+        assert!(writes.iter().all(|w| w.header.is_none() && w.wire_len() == w.data.len()));
+        let mut wire: Vec<u8, 32> = Vec::new();
+        assert!(writes[2].write_to(&mut wire));
+        assert_eq!(&wire[..], &[5u8; 10]);
+        assert_eq!(chunks(&[], Mtu::BLE_DEFAULT).count(), 0);
+    }
+}